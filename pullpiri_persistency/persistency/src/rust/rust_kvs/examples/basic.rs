@@ -84,6 +84,7 @@ fn main() -> Result<(), ErrorCode> {
                     KvsValue::F64(_) => "F64",
                     KvsValue::Boolean(_) => "Boolean",
                     KvsValue::String(_) => "String",
+                    KvsValue::Bytes(_) => "Bytes",
                     KvsValue::Null => "Null",
                     KvsValue::Array(_) => "Array",
                     KvsValue::Object(_) => "Object",
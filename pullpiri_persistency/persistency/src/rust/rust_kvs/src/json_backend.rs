@@ -24,6 +24,7 @@ use tinyjson::{JsonGenerateError, JsonParseError, JsonValue};
 //   "my_float": { "t": "f64", "v": 3.1415 },
 //   "my_bool": { "t": "bool", "v": true },
 //   "my_string": { "t": "str", "v": "hello" },
+//   "my_bytes": { "t": "bin", "v": "1f8b08" },
 //   "my_array": { "t": "arr", "v": [ ... ] },
 //   "my_object": { "t": "obj", "v": { ... } },
 //   "my_null": { "t": "null", "v": null }
@@ -46,6 +47,10 @@ impl From<JsonValue> for KvsValue {
                         ("f64", JsonValue::Number(v)) => KvsValue::F64(v),
                         ("bool", JsonValue::Boolean(v)) => KvsValue::Boolean(v),
                         ("str", JsonValue::String(v)) => KvsValue::String(v),
+                        ("bin", JsonValue::String(v)) => match decode_hex(&v) {
+                            Some(bytes) => KvsValue::Bytes(bytes),
+                            None => KvsValue::Null,
+                        },
                         ("null", JsonValue::Null) => KvsValue::Null,
                         ("arr", JsonValue::Array(v)) => {
                             KvsValue::Array(v.into_iter().map(KvsValue::from).collect())
@@ -103,6 +108,10 @@ impl From<KvsValue> for JsonValue {
                 obj.insert("t".to_string(), JsonValue::String("str".to_string()));
                 obj.insert("v".to_string(), JsonValue::String(s));
             }
+            KvsValue::Bytes(b) => {
+                obj.insert("t".to_string(), JsonValue::String("bin".to_string()));
+                obj.insert("v".to_string(), JsonValue::String(encode_hex(&b)));
+            }
             KvsValue::Null => {
                 obj.insert("t".to_string(), JsonValue::String("null".to_string()));
                 obj.insert("v".to_string(), JsonValue::Null);
@@ -130,6 +139,28 @@ impl From<KvsValue> for JsonValue {
     }
 }
 
+/// Binary data as a string of lowercase hex digits, JSON has no binary type.
+fn encode_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push(DIGITS[(byte >> 4) as usize] as char);
+        hex.push(DIGITS[(byte & 0x0f) as usize] as char);
+    }
+    hex
+}
+
+/// Binary data of a string of hex digits, `None` if it is not one.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
 /// tinyjson::JsonParseError -> ErrorCode::JsonParseError
 impl From<JsonParseError> for ErrorCode {
     fn from(cause: JsonParseError) -> Self {
@@ -427,6 +458,26 @@ mod json_value_to_kvs_value_conversion_tests {
         assert_eq!(kv, KvsValue::Null);
     }
 
+    #[test]
+    fn test_bytes_ok() {
+        let jv = JsonValue::from(HashMap::from([
+            ("t".to_string(), JsonValue::String("bin".to_string())),
+            ("v".to_string(), JsonValue::String("00ff1F".to_string())),
+        ]));
+        let kv = KvsValue::from(jv);
+        assert_eq!(kv, KvsValue::Bytes(vec![0x00, 0xff, 0x1f]));
+    }
+
+    #[test]
+    fn test_bytes_invalid_hex() {
+        let jv = JsonValue::from(HashMap::from([
+            ("t".to_string(), JsonValue::String("bin".to_string())),
+            ("v".to_string(), JsonValue::String("0xff".to_string())),
+        ]));
+        let kv = KvsValue::from(jv);
+        assert_eq!(kv, KvsValue::Null);
+    }
+
     #[test]
     fn test_null_ok() {
         let jv = JsonValue::from(HashMap::from([
@@ -630,6 +681,20 @@ mod kvs_value_to_json_value_conversion_tests {
         );
     }
 
+    #[test]
+    fn test_bytes_ok() {
+        let kv = KvsValue::Bytes(vec![0x1f, 0x8b, 0x08]);
+        let jv = JsonValue::from(kv);
+
+        assert_eq!(
+            jv,
+            JsonValue::Object(HashMap::from([
+                ("t".to_string(), JsonValue::String("bin".to_string())),
+                ("v".to_string(), JsonValue::String("1f8b08".to_string())),
+            ]))
+        );
+    }
+
     #[test]
     fn test_null_ok() {
         let kv = KvsValue::Null;
@@ -55,6 +55,10 @@ pub struct GenericKvs<Backend: KvsBackend, PathResolver: KvsPathResolver = Backe
     /// KVS instance data.
     data: Arc<Mutex<KvsData>>,
 
+    /// Serializes the flushes of the instance.
+    /// Flushes rotate and write the same files, but must not block the instance data.
+    flush_lock: Arc<Mutex<()>>,
+
     /// KVS instance parameters.
     parameters: KvsParameters,
 
@@ -66,9 +70,14 @@ pub struct GenericKvs<Backend: KvsBackend, PathResolver: KvsPathResolver = Backe
 }
 
 impl<Backend: KvsBackend, PathResolver: KvsPathResolver> GenericKvs<Backend, PathResolver> {
-    pub(crate) fn new(data: Arc<Mutex<KvsData>>, parameters: KvsParameters) -> Self {
+    pub(crate) fn new(
+        data: Arc<Mutex<KvsData>>,
+        flush_lock: Arc<Mutex<()>>,
+        parameters: KvsParameters,
+    ) -> Self {
         Self {
             data,
+            flush_lock,
             parameters,
             _backend_marker: PhantomData,
             _path_resolver_marker: PhantomData,
@@ -359,6 +368,11 @@ impl<Backend: KvsBackend, PathResolver: KvsPathResolver> KvsApi
 
     /// Flush the in-memory key-value-storage to the persistent storage
     ///
+    /// The data is locked only while it is cloned, serialization and file
+    /// writes work on the clone. Writes therefore proceed while a large store
+    /// is written, at the cost of holding a second copy of it meanwhile.
+    /// Concurrent flushes of the instance wait for each other.
+    ///
     /// # Features
     ///   * `FEAT_REQ__KVS__snapshots`
     ///   * `FEAT_REQ__KVS__persistency`
@@ -376,6 +390,10 @@ impl<Backend: KvsBackend, PathResolver: KvsPathResolver> KvsApi
             return Ok(());
         }
 
+        let _flush_guard = self
+            .flush_lock
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
         self.snapshot_rotate().map_err(|e| {
             eprintln!("error: snapshot_rotate failed: {e:?}");
             e
@@ -392,8 +410,8 @@ impl<Backend: KvsBackend, PathResolver: KvsPathResolver> KvsApi
             snapshot_id,
         );
 
-        let data = self.data.lock()?;
-        Backend::save_kvs(&data.kvs_map, &kvs_path, Some(&hash_path)).map_err(|e| {
+        let kvs_map = self.data.lock()?.kvs_map.clone();
+        Backend::save_kvs(&kvs_map, &kvs_path, Some(&hash_path)).map_err(|e| {
             eprintln!("error: save_kvs failed: {e:?}");
             e
         })?;
@@ -531,7 +549,8 @@ mod kvs_tests {
     use crate::kvs_builder::KvsData;
     use crate::kvs_value::{KvsMap, KvsValue};
     use std::path::PathBuf;
-    use std::sync::{Arc, Mutex};
+    use std::sync::{Arc, Barrier, Condvar, LazyLock, Mutex};
+    use std::time::Duration;
     use tempfile::tempdir;
 
     /// Most tests can be performed with mocked backend.
@@ -607,7 +626,7 @@ mod kvs_tests {
             working_dir,
             snapshot_max_count,
         };
-        GenericKvs::<B>::new(data, parameters)
+        GenericKvs::<B>::new(data, Arc::new(Mutex::new(())), parameters)
     }
 
     fn get_kvs<B: KvsBackend + KvsPathResolver>(
@@ -1041,6 +1060,73 @@ mod kvs_tests {
         assert_eq!(kvs.snapshot_count(), EXPECTED_MAX_COUNT);
     }
 
+    /// Backend pausing in `save_kvs` until a write went through meanwhile.
+    struct PausingBackend;
+
+    /// Reached by `PausingBackend` and the writing test.
+    static FLUSH_STARTED: LazyLock<Barrier> = LazyLock::new(|| Barrier::new(2));
+
+    /// Set by the writing test once its write returned.
+    static WRITE_DONE: LazyLock<(Mutex<bool>, Condvar)> =
+        LazyLock::new(|| (Mutex::new(false), Condvar::new()));
+
+    impl KvsBackend for PausingBackend {
+        fn load_kvs(
+            _kvs_path: &std::path::Path,
+            _hash_path: Option<&PathBuf>,
+        ) -> Result<KvsMap, ErrorCode> {
+            unimplemented!()
+        }
+
+        fn save_kvs(
+            kvs_map: &KvsMap,
+            kvs_path: &std::path::Path,
+            hash_path: Option<&PathBuf>,
+        ) -> Result<(), ErrorCode> {
+            FLUSH_STARTED.wait();
+            // Gives up instead of hanging if the write is blocked by the flush.
+            let (done, written) = &*WRITE_DONE;
+            let (done, _) = written
+                .wait_timeout_while(done.lock().unwrap(), Duration::from_secs(10), |done| !*done)
+                .unwrap();
+            if !*done || kvs_map.contains_key("written_during_flush") {
+                return Err(ErrorCode::UnmappedError);
+            }
+            JsonBackend::save_kvs(kvs_map, kvs_path, hash_path)
+        }
+    }
+
+    #[test]
+    fn test_flush_does_not_block_writes() {
+        let dir = tempdir().unwrap();
+        let kvs = GenericKvs::<PausingBackend, JsonBackend>::new(
+            Arc::new(Mutex::new(KvsData {
+                kvs_map: KvsMap::from([("key".to_string(), KvsValue::from("value"))]),
+                defaults_map: KvsMap::new(),
+            })),
+            Arc::new(Mutex::new(())),
+            KvsParameters {
+                working_dir: dir.path().to_path_buf(),
+                ..KvsParameters::new(InstanceId(1))
+            },
+        );
+
+        std::thread::scope(|scope| {
+            let flush = scope.spawn(|| kvs.flush());
+            FLUSH_STARTED.wait();
+            kvs.set_value("written_during_flush", 1).unwrap();
+            let (done, written) = &*WRITE_DONE;
+            *done.lock().unwrap() = true;
+            written.notify_one();
+            // The flush wrote the data as it was when the flush started.
+            assert_eq!(flush.join().unwrap(), Ok(()));
+        });
+        assert_eq!(
+            kvs.get_value("written_during_flush").unwrap(),
+            KvsValue::from(1)
+        );
+    }
+
     #[test]
     fn test_snapshot_count_zero() {
         let dir = tempdir().unwrap();
@@ -44,6 +44,9 @@ pub(crate) struct KvsInner {
 
     /// KVS instance data.
     pub(crate) data: Arc<Mutex<KvsData>>,
+
+    /// Lock serializing the flushes of the instance.
+    pub(crate) flush_lock: Arc<Mutex<()>>,
 }
 
 static KVS_POOL: LazyLock<Mutex<[Option<KvsInner>; KVS_MAX_INSTANCES]>> =
@@ -246,6 +249,7 @@ impl<Backend: KvsBackend, PathResolver: KvsPathResolver> GenericKvsBuilder<Backe
             if let Some(kvs_inner) = kvs_inner_option {
                 return Ok(GenericKvs::<Backend, PathResolver>::new(
                     kvs_inner.data.clone(),
+                    kvs_inner.flush_lock.clone(),
                     kvs_inner.parameters.clone(),
                 ));
             }
@@ -294,6 +298,7 @@ impl<Backend: KvsBackend, PathResolver: KvsPathResolver> GenericKvsBuilder<Backe
             kvs_map,
             defaults_map,
         }));
+        let flush_lock = Arc::new(Mutex::new(()));
 
         // Initialize entry in pool and return new KVS instance.
         {
@@ -306,10 +311,11 @@ impl<Backend: KvsBackend, PathResolver: KvsPathResolver> GenericKvsBuilder<Backe
             let _ = kvs_pool_entry.insert(KvsInner {
                 parameters: kvs_parameters.clone(),
                 data: data.clone(),
+                flush_lock: flush_lock.clone(),
             });
         }
 
-        Ok(GenericKvs::new(data, kvs_parameters))
+        Ok(GenericKvs::new(data, flush_lock, kvs_parameters))
     }
 }
 
@@ -39,6 +39,9 @@ pub enum KvsValue {
     /// String
     String(String),
 
+    /// Binary data
+    Bytes(Vec<u8>),
+
     /// Null
     Null,
 
@@ -68,6 +71,7 @@ impl_from_t_for_kvs_value!(u64, U64);
 impl_from_t_for_kvs_value!(f64, F64);
 impl_from_t_for_kvs_value!(bool, Boolean);
 impl_from_t_for_kvs_value!(String, String);
+impl_from_t_for_kvs_value!(Vec<u8>, Bytes);
 impl_from_t_for_kvs_value!(Vec<KvsValue>, Array);
 impl_from_t_for_kvs_value!(KvsMap, Object);
 
@@ -107,6 +111,7 @@ impl_tryfrom_kvs_value_to_t!(u64, U64);
 impl_tryfrom_kvs_value_to_t!(f64, F64);
 impl_tryfrom_kvs_value_to_t!(bool, Boolean);
 impl_tryfrom_kvs_value_to_t!(String, String);
+impl_tryfrom_kvs_value_to_t!(Vec<u8>, Bytes);
 impl_tryfrom_kvs_value_to_t!(Vec<KvsValue>, Array);
 impl_tryfrom_kvs_value_to_t!(std::collections::HashMap<String, KvsValue>, Object);
 
@@ -150,6 +155,7 @@ impl_kvs_get_inner_value!(i64, I64);
 impl_kvs_get_inner_value!(u64, U64);
 impl_kvs_get_inner_value!(bool, Boolean);
 impl_kvs_get_inner_value!(String, String);
+impl_kvs_get_inner_value!(Vec<u8>, Bytes);
 impl_kvs_get_inner_value!(Vec<KvsValue>, Array);
 impl_kvs_get_inner_value!(std::collections::HashMap<String, KvsValue>, Object);
 
@@ -371,6 +377,31 @@ mod kvs_value_tests {
         assert!(v.get::<String>().is_none());
     }
 
+    #[test]
+    fn test_bytes_from_ok() {
+        let v = KvsValue::from(vec![0x1fu8, 0x8b]);
+        assert!(matches!(v, KvsValue::Bytes(ref b) if b == &[0x1f, 0x8b]));
+    }
+
+    #[test]
+    fn test_bytes_tryfrom_ok() {
+        let v = KvsValue::from(vec![0u8, 255]);
+        assert_eq!(Vec::<u8>::try_from(&v).unwrap(), vec![0, 255]);
+    }
+
+    #[test]
+    fn test_bytes_tryfrom_invalid_type() {
+        let v = KvsValue::from("abc");
+        let err = Vec::<u8>::try_from(&v).unwrap_err();
+        assert_eq!(err, "KvsValue is not a Vec<u8>");
+    }
+
+    #[test]
+    fn test_bytes_get_ok() {
+        let v = KvsValue::from(vec![1u8, 2, 3]);
+        assert_eq!(v.get::<Vec<u8>>().unwrap(), &vec![1, 2, 3]);
+    }
+
     #[test]
     fn test_str_from_ok() {
         let v = KvsValue::from("world");
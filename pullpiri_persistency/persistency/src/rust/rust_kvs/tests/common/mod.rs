@@ -23,6 +23,7 @@ pub fn compare_kvs_values(left: &KvsValue, right: &KvsValue) -> bool {
         (KvsValue::F64(l), KvsValue::F64(r)) => l == r,
         (KvsValue::Boolean(l), KvsValue::Boolean(r)) => l == r,
         (KvsValue::String(l), KvsValue::String(r)) => l == r,
+        (KvsValue::Bytes(l), KvsValue::Bytes(r)) => l == r,
         (KvsValue::Null, KvsValue::Null) => true,
         (KvsValue::Array(l), KvsValue::Array(r)) => {
             // Check size.
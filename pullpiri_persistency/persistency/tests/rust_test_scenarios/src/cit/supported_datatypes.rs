@@ -53,6 +53,7 @@ impl Scenario for SupportedDatatypesValues {
             KvsValue::F64(_) => "f64",
             KvsValue::Boolean(_) => "bool",
             KvsValue::String(_) => "str",
+            KvsValue::Bytes(_) => "bin",
             KvsValue::Null => "null",
             KvsValue::Array(_) => "arr",
             KvsValue::Object(_) => "obj",
@@ -155,6 +155,11 @@ struct Args {
 #[cfg(not(feature = "tarpaulin_include"))]
 #[tokio::main]
 async fn main() {
+    // Identify this instance in the requests it sends, e.g. to the persistency service
+    common::identity::init(common::identity::ComponentIdentity::bootstrap(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+    ));
     // Parse command line arguments
     let args = Args::parse();
 
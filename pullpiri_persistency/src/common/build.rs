@@ -4,6 +4,11 @@
  */
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Without the grpc feature there is no generated code to compile against
+    if std::env::var_os("CARGO_FEATURE_GRPC").is_none() {
+        return Ok(());
+    }
+
     tonic_build::configure()
         .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
         .protoc_arg("--experimental_allow_proto3_optional")
@@ -18,9 +23,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 "proto/statemanager.proto",
                 "proto/pharos_service.proto",
                 "proto/persistency.proto",
+                "proto/console.proto",
                 "proto/external/timpani/schedinfo.proto",
             ],
             &["proto"],
         )?;
+
+    // Descriptor set of the persistency API for tooling that builds its stubs at runtime,
+    // the generated code of this run is not used
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+    let unused_code_dir = out_dir.join("persistency_descriptor");
+    std::fs::create_dir_all(&unused_code_dir)?;
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .out_dir(unused_code_dir)
+        .file_descriptor_set_path(out_dir.join("persistency_descriptor.bin"))
+        .compile_protos(&["proto/persistency.proto"], &["proto"])?;
     Ok(())
 }
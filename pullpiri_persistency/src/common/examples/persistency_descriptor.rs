@@ -0,0 +1,30 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Write the descriptor set of the persistency API to a file
+//!
+//! Test tooling without a Rust toolchain loads the file to build its stubs,
+//! e.g. with `descriptor_pool` and `message_factory` of the Python protobuf
+//! package. A running service reports the same descriptor set with the
+//! `GetProtoDescriptor` RPC.
+//!
+//! ```text
+//! cargo run --example persistency_descriptor -- persistency.desc
+//! ```
+
+use common::persistency_proto::FILE_DESCRIPTOR_SET;
+
+fn main() -> std::io::Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "persistency.desc".to_string());
+    std::fs::write(&path, FILE_DESCRIPTOR_SET)?;
+    println!(
+        "Wrote the persistency descriptor set ({} bytes) to {}",
+        FILE_DESCRIPTOR_SET.len(),
+        path
+    );
+    Ok(())
+}
@@ -0,0 +1,107 @@
+#!/usr/bin/env python3
+# SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+# SPDX-License-Identifier: Apache-2.0
+
+"""Persistency service client for Python test tooling, without generated stubs
+
+The message classes are built at runtime from the descriptor set reported by
+the GetProtoDescriptor RPC of the running service, or from a file written by
+`cargo run --example persistency_descriptor`, so the tooling follows the API
+of the service under test without a protoc step.
+
+    pip install grpcio "protobuf>=4.21"
+    python3 persistency_dynamic_client.py localhost:47007
+    python3 persistency_dynamic_client.py localhost:47007 --descriptor persistency.desc
+"""
+
+import argparse
+
+import grpc
+from google.protobuf import descriptor_pb2, descriptor_pool, message_factory
+
+SERVICE = "persistency.PersistencyService"
+
+# Field number of file_descriptor_set in GetProtoDescriptorResponse
+DESCRIPTOR_SET_FIELD = 2
+
+
+def bytes_field(data, number):
+    """Value of a bytes field of a serialized message whose classes are not built yet"""
+    pos = 0
+
+    def varint():
+        nonlocal pos
+        value = shift = 0
+        while True:
+            byte = data[pos]
+            pos += 1
+            value |= (byte & 0x7F) << shift
+            shift += 7
+            if not byte & 0x80:
+                return value
+
+    while pos < len(data):
+        key = varint()
+        wire_type = key & 7
+        if wire_type == 0:
+            varint()
+        elif wire_type == 1:
+            pos += 8
+        elif wire_type == 2:
+            length = varint()
+            if key >> 3 == number:
+                return data[pos:pos + length]
+            pos += length
+        elif wire_type == 5:
+            pos += 4
+        else:
+            raise ValueError(f"unsupported wire type {wire_type}")
+    raise ValueError(f"field {number} not found")
+
+
+class PersistencyClient:
+    """Calls any RPC of the persistency service by name with keyword fields"""
+
+    def __init__(self, target, descriptor_path=None):
+        self.channel = grpc.insecure_channel(target)
+        if descriptor_path:
+            with open(descriptor_path, "rb") as file:
+                descriptor_set = file.read()
+        else:
+            # Without serializers the call sends and returns raw bytes
+            get_descriptor = self.channel.unary_unary(f"/{SERVICE}/GetProtoDescriptor")
+            descriptor_set = bytes_field(get_descriptor(b""), DESCRIPTOR_SET_FIELD)
+
+        pool = descriptor_pool.DescriptorPool()
+        for file in descriptor_pb2.FileDescriptorSet.FromString(descriptor_set).file:
+            pool.Add(file)
+        self.service = pool.FindServiceByName(SERVICE)
+
+    def call(self, method_name, **fields):
+        method = self.service.methods_by_name[method_name]
+        request_class = message_factory.GetMessageClass(method.input_type)
+        response_class = message_factory.GetMessageClass(method.output_type)
+        stub = self.channel.unary_unary(
+            f"/{SERVICE}/{method.name}",
+            request_serializer=request_class.SerializeToString,
+            response_deserializer=response_class.FromString,
+        )
+        return stub(request_class(**fields))
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("target", nargs="?", default="localhost:47007")
+    parser.add_argument("--descriptor", help="descriptor set file instead of GetProtoDescriptor")
+    args = parser.parse_args()
+
+    client = PersistencyClient(args.target, args.descriptor)
+    print("RPCs:", ", ".join(client.service.methods_by_name))
+
+    client.call("SetValue", key="test/python/greeting", value={"string_value": "hello"})
+    print(client.call("GetValue", key="test/python/greeting"))
+    client.call("RemoveKey", key="test/python/greeting")
+
+
+if __name__ == "__main__":
+    main()
@@ -1,17 +1,322 @@
-pub type Result<T> = core::result::Result<T, Box<dyn std::error::Error>>;
-
-// TODO - add custom error message types
 /*
-pub struct Error {
-    msg: Msg,
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Error type shared by the Pullpiri components
+//!
+//! Every [`Error`] has an [`ErrorKind`] telling what failed, so that callers
+//! can react to e.g. an invalid artifact differently than to an unreachable
+//! service. Errors of the libraries and of the persistency client convert
+//! with `?`, keeping the original error as [`std::error::Error::source`].
+//!
+//! An error displays its message only, as REST clients receive it. Logs use
+//! the alternate form `{:#}`, which names the kind as well:
+//! `storage error: Key not found`.
+
+use std::fmt;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// What failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Missing or invalid settings
+    Config,
+    /// Another service could not be reached or failed to answer
+    Transport,
+    /// Reading or writing persistent data failed
+    Storage,
+    /// A request, artifact or value is invalid
+    Validation,
+    /// Publishing or reading DDS topics failed
+    Dds,
+    /// Anything else, e.g. a broken invariant
+    Internal,
+}
+
+impl ErrorKind {
+    /// Name used in error messages
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Config => "config",
+            ErrorKind::Transport => "transport",
+            ErrorKind::Storage => "storage",
+            ErrorKind::Validation => "validation",
+            ErrorKind::Dds => "dds",
+            ErrorKind::Internal => "internal",
+        }
+    }
 }
 
-struct Msg {
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Failure of a Pullpiri component
+#[derive(Debug)]
+pub struct Error {
     kind: ErrorKind,
-    desc: Box<std::error::Error+Send+Sync>,
+    message: String,
+    source: Option<Box<dyn std::error::Error + Send + Sync>>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Error of `kind` caused by `source`, with its message
+    pub fn with_source(
+        kind: ErrorKind,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            kind,
+            message: source.to_string(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Config, message)
+    }
+
+    pub fn transport(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Transport, message)
+    }
+
+    pub fn storage(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Storage, message)
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Validation, message)
+    }
+
+    pub fn dds(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Dds, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Message without the kind
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{} error: {}", self.kind, self.message)
+        } else {
+            f.write_str(&self.message)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
+
+// Plain messages are internal errors, as `Box<dyn Error>` took them before
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::internal(message)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::internal(message)
+    }
 }
 
-pub enum Errorkind {
-    ...
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    fn from(source: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        Self {
+            kind: ErrorKind::Internal,
+            message: source.to_string(),
+            source: Some(source),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::with_source(ErrorKind::Storage, err)
+    }
+}
+
+impl From<std::net::AddrParseError> for Error {
+    fn from(err: std::net::AddrParseError) -> Self {
+        Error::with_source(ErrorKind::Config, err)
+    }
+}
+
+impl From<config::ConfigError> for Error {
+    fn from(err: config::ConfigError) -> Self {
+        Error::with_source(ErrorKind::Config, err)
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Self {
+        Error::with_source(ErrorKind::Validation, err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::with_source(ErrorKind::Validation, err)
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        let kind = match status.code() {
+            tonic::Code::InvalidArgument
+            | tonic::Code::FailedPrecondition
+            | tonic::Code::OutOfRange => ErrorKind::Validation,
+            tonic::Code::NotFound
+            | tonic::Code::AlreadyExists
+            | tonic::Code::Aborted
+            | tonic::Code::DataLoss => ErrorKind::Storage,
+            tonic::Code::Internal | tonic::Code::Unknown => ErrorKind::Internal,
+            _ => ErrorKind::Transport,
+        };
+        Self {
+            kind,
+            message: status.message().to_string(),
+            source: Some(Box::new(status)),
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<tonic::transport::Error> for Error {
+    fn from(err: tonic::transport::Error) -> Self {
+        Error::with_source(ErrorKind::Transport, err)
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<tokio::task::JoinError> for Error {
+    fn from(err: tokio::task::JoinError) -> Self {
+        Error::with_source(ErrorKind::Internal, err)
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<crate::persistency_client::PersistencyError> for Error {
+    fn from(err: crate::persistency_client::PersistencyError) -> Self {
+        use crate::persistency_client::PersistencyError;
+        let (kind, message) = match &err {
+            PersistencyError::Transport(e) => (ErrorKind::Transport, e.to_string()),
+            PersistencyError::Grpc(status) => (ErrorKind::Transport, status.to_string()),
+            PersistencyError::NotFound => (ErrorKind::Storage, err.to_string()),
+            PersistencyError::Storage(message)
+            | PersistencyError::Conflict(message)
+            | PersistencyError::QuotaExceeded(message) => (ErrorKind::Storage, message.clone()),
+            PersistencyError::Conversion(message) | PersistencyError::InvalidArgs(message) => {
+                (ErrorKind::Validation, message.clone())
+            }
+            PersistencyError::Unsupported(_) => (ErrorKind::Validation, err.to_string()),
+        };
+        Self {
+            kind,
+            message,
+            source: Some(Box::new(err)),
+        }
+    }
+}
+
+#[cfg(feature = "lite-client")]
+impl From<crate::persistency_lite::LiteError> for Error {
+    fn from(err: crate::persistency_lite::LiteError) -> Self {
+        use crate::persistency_lite::LiteError;
+        let (kind, message) = match &err {
+            LiteError::Io(e) => (ErrorKind::Transport, e.to_string()),
+            LiteError::Protocol(message) => (ErrorKind::Transport, message.clone()),
+            LiteError::NotFound => (ErrorKind::Storage, err.to_string()),
+            LiteError::InvalidArgs(message) => (ErrorKind::Validation, message.clone()),
+        };
+        Self {
+            kind,
+            message,
+            source: Some(Box::new(err)),
+        }
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_display_names_the_kind() {
+        let err = Error::validation("Scenario has no name");
+        assert_eq!(err.kind(), ErrorKind::Validation);
+        assert_eq!(err.message(), "Scenario has no name");
+        assert_eq!(err.to_string(), "Scenario has no name");
+        assert_eq!(
+            format!("{:#}", err),
+            "validation error: Scenario has no name"
+        );
+        assert!(err.source().is_none());
+
+        let err: Error = "unexpected state".into();
+        assert_eq!(err.kind(), ErrorKind::Internal);
+    }
+
+    #[test]
+    fn test_conversions_keep_the_source() {
+        let yaml = serde_yaml::from_str::<u32>("[").unwrap_err();
+        let err = Error::from(yaml);
+        assert_eq!(err.kind(), ErrorKind::Validation);
+        assert!(err.source().unwrap().is::<serde_yaml::Error>());
+
+        let err = Error::from(std::io::Error::other("disk full"));
+        assert_eq!(format!("{:#}", err), "storage error: disk full");
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_persistency_errors() {
+        use crate::persistency_client::PersistencyError;
+        let err = Error::from(PersistencyError::NotFound);
+        assert_eq!(err.kind(), ErrorKind::Storage);
+        assert_eq!(format!("{:#}", err), "storage error: Key not found");
+        assert!(matches!(
+            err.source().unwrap().downcast_ref::<PersistencyError>(),
+            Some(PersistencyError::NotFound)
+        ));
+
+        let err = Error::from(PersistencyError::InvalidArgs("empty key".to_string()));
+        assert_eq!(format!("{:#}", err), "validation error: empty key");
+
+        let err = Error::from(tonic::Status::unavailable("connection refused"));
+        assert_eq!(err.kind(), ErrorKind::Transport);
+        assert_eq!(err.message(), "connection refused");
+    }
 }
-*/
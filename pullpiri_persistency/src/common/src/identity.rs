@@ -0,0 +1,199 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Identity of a Pullpiri component instance
+//!
+//! Every component sets its [`ComponentIdentity`] once at bootstrap with
+//! [`init`]. The persistency client sends it as gRPC metadata with every
+//! request, so that the service can attribute its logs, audit events and
+//! hot-key reports to the calling instance. A component that never calls
+//! [`init`] is named after its executable.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::OnceLock;
+
+/// gRPC metadata carrying the component name
+pub const NAME_METADATA: &str = "x-component-name";
+/// gRPC metadata carrying the component instance
+pub const INSTANCE_METADATA: &str = "x-component-instance";
+/// gRPC metadata carrying the component version
+pub const VERSION_METADATA: &str = "x-component-version";
+/// gRPC metadata carrying the node the component runs on
+pub const NODE_METADATA: &str = "x-component-node";
+
+/// Environment variable naming the instance, the process id by default
+pub const INSTANCE_ENV: &str = "PICCOLO_COMPONENT_INSTANCE";
+/// Environment variable naming the node, the host name by default
+pub const NODE_ENV: &str = "PICCOLO_NODE_NAME";
+/// Environment variable holding the bearer token of this component
+pub const TOKEN_ENV: &str = "PICCOLO_PERSISTENCY_TOKEN";
+
+/// Who is calling: component, instance, version and node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentIdentity {
+    /// Component name, e.g. `apiserver`
+    pub name: String,
+    /// Distinguishes instances of the component on the same node
+    pub instance: String,
+    pub version: String,
+    pub node: String,
+}
+
+impl ComponentIdentity {
+    /// Identity of this process
+    ///
+    /// ### Parameters
+    /// * `name: &str` - component name, usually `env!("CARGO_PKG_NAME")`
+    /// * `version: &str` - component version, usually `env!("CARGO_PKG_VERSION")`
+    pub fn bootstrap(name: &str, version: &str) -> Self {
+        let instance = std::env::var(INSTANCE_ENV)
+            .ok()
+            .filter(|instance| !instance.is_empty())
+            .unwrap_or_else(|| std::process::id().to_string());
+        let node = std::env::var(NODE_ENV)
+            .ok()
+            .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+            .map(|node| node.trim().to_string())
+            .filter(|node| !node.is_empty())
+            .unwrap_or_else(|| crate::setting::get_config().host.name.clone());
+        Self {
+            name: name.to_string(),
+            instance,
+            version: version.to_string(),
+            node,
+        }
+    }
+
+    /// Identity read from request metadata, `None` if the caller sent no name
+    ///
+    /// ### Parameters
+    /// * `get: Fn(&str) -> Option<&str>` - value of a metadata entry by name
+    pub fn from_lookup<'a>(get: impl Fn(&str) -> Option<&'a str>) -> Option<Self> {
+        let field = |name| get(name).unwrap_or_default().to_string();
+        let name = field(NAME_METADATA);
+        if name.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name,
+            instance: field(INSTANCE_METADATA),
+            version: field(VERSION_METADATA),
+            node: field(NODE_METADATA),
+        })
+    }
+
+    /// Metadata entries sent with every request
+    pub fn metadata(&self) -> [(&'static str, &str); 4] {
+        [
+            (NAME_METADATA, &self.name),
+            (INSTANCE_METADATA, &self.instance),
+            (VERSION_METADATA, &self.version),
+            (NODE_METADATA, &self.node),
+        ]
+    }
+}
+
+impl fmt::Display for ComponentIdentity {
+    /// Formats as `name/instance@node version`, e.g. `apiserver/4711@HPC 0.1.0`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}@{} {}",
+            self.name, self.instance, self.node, self.version
+        )
+    }
+}
+
+static IDENTITY: OnceLock<ComponentIdentity> = OnceLock::new();
+
+/// Set the identity of this process, the first call wins
+pub fn init(identity: ComponentIdentity) -> &'static ComponentIdentity {
+    IDENTITY.get_or_init(|| identity)
+}
+
+/// Identity of this process, named after the executable if [`init`] was not called
+pub fn current() -> &'static ComponentIdentity {
+    IDENTITY.get_or_init(|| {
+        let name = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "unknown".to_string());
+        ComponentIdentity::bootstrap(&name, "unknown")
+    })
+}
+
+/// Interceptor adding the identity of this process to every request
+#[cfg(feature = "grpc")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityInterceptor;
+
+#[cfg(feature = "grpc")]
+impl tonic::service::Interceptor for IdentityInterceptor {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        let metadata = request.metadata_mut();
+        for (name, value) in current().metadata() {
+            // Values that are not valid metadata are left out rather than failing the request
+            if let Ok(value) = value.parse() {
+                metadata.insert(name, value);
+            }
+        }
+        Ok(request)
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn identity() -> ComponentIdentity {
+        ComponentIdentity {
+            name: "apiserver".to_string(),
+            instance: "4711".to_string(),
+            version: "0.1.0".to_string(),
+            node: "HPC".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let identity = identity();
+        let metadata: HashMap<&str, &str> = identity.metadata().into_iter().collect();
+        let parsed = ComponentIdentity::from_lookup(|name| metadata.get(name).copied());
+        assert_eq!(parsed, Some(identity));
+        assert_eq!(ComponentIdentity::from_lookup(|_| None), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(identity().to_string(), "apiserver/4711@HPC 0.1.0");
+    }
+
+    #[test]
+    fn test_bootstrap() {
+        let identity = ComponentIdentity::bootstrap("monitoringserver", "1.2.3");
+        assert_eq!(identity.name, "monitoringserver");
+        assert_eq!(identity.version, "1.2.3");
+        assert!(!identity.instance.is_empty());
+        assert!(!identity.node.is_empty());
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_interceptor_adds_metadata() {
+        use tonic::service::Interceptor;
+
+        let request = IdentityInterceptor.call(tonic::Request::new(())).unwrap();
+        let metadata = request.metadata();
+        let parsed =
+            ComponentIdentity::from_lookup(|name| metadata.get(name)?.to_str().ok()).unwrap();
+        assert_eq!(&parsed, current());
+    }
+}
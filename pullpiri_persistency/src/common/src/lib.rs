@@ -5,11 +5,17 @@
 pub use crate::error::Result;
 
 pub mod error;
+pub mod identity;
+#[cfg(feature = "grpc")]
 pub mod persistency;
+#[cfg(feature = "grpc")]
 pub mod persistency_client;
+#[cfg(feature = "lite-client")]
+pub mod persistency_lite;
 pub mod setting;
 pub mod spec;
 
+#[cfg(feature = "grpc")]
 fn open_server(port: u16) -> String {
     format!("{}:{}", crate::setting::get_config().host.ip, port)
 }
@@ -20,17 +26,20 @@ fn open_server(port: u16) -> String {
 //     open_server(port)
 // }
 
+#[cfg(feature = "grpc")]
 fn connect_server(port: u16) -> String {
     format!("http://{}:{}", crate::setting::get_config().host.ip, port)
 }
 
 // guest 서버 연결 함수 수정: 이제 항상 호스트 서버 주소 반환
 //using rust build in _ to below code , as it was never used anywhere to prevent warnings
+#[cfg(feature = "grpc")]
 fn _connect_guest_server(port: u16) -> String {
     // 항상 호스트 서버 주소 반환
     connect_server(port)
 }
 
+#[cfg(feature = "grpc")]
 pub mod actioncontroller {
     tonic::include_proto!("actioncontroller");
 
@@ -43,6 +52,7 @@ pub mod actioncontroller {
     }
 }
 
+#[cfg(feature = "grpc")]
 pub mod apiserver {
     tonic::include_proto!("apiserver");
 
@@ -59,6 +69,7 @@ pub mod apiserver {
     }
 }
 
+#[cfg(feature = "grpc")]
 pub mod filtergateway {
     tonic::include_proto!("filtergateway");
 
@@ -71,6 +82,7 @@ pub mod filtergateway {
     }
 }
 
+#[cfg(feature = "grpc")]
 pub mod monitoringserver {
     tonic::include_proto!("monitoringserver");
 
@@ -83,6 +95,7 @@ pub mod monitoringserver {
     }
 }
 
+#[cfg(feature = "grpc")]
 pub mod nodeagent {
     tonic::include_proto!("nodeagent");
 
@@ -103,6 +116,7 @@ pub mod nodeagent {
     //     }
 }
 
+#[cfg(feature = "grpc")]
 pub mod policymanager {
     tonic::include_proto!("policymanager");
 
@@ -115,6 +129,7 @@ pub mod policymanager {
     }
 }
 
+#[cfg(feature = "grpc")]
 pub mod statemanager {
     tonic::include_proto!("statemanager");
 
@@ -127,6 +142,7 @@ pub mod statemanager {
     }
 }
 
+#[cfg(feature = "grpc")]
 pub mod pharos_service {
     tonic::include_proto!("pharos.api.v1");
     pub fn connect_pharos_server() -> String {
@@ -134,9 +150,70 @@ pub mod pharos_service {
     }
 }
 
+#[cfg(feature = "grpc")]
 pub mod persistency_proto {
     tonic::include_proto!("persistency");
 
+    /// Serialized `google.protobuf.FileDescriptorSet` of `persistency.proto`
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("persistency_descriptor");
+
+    /// Version of the persistency API reported by `GetCapabilities`
+    ///
+    /// Raised with every RPC or field added. Services without
+    /// `GetCapabilities` implement version 1.
+    pub const API_VERSION: u32 = 17;
+
+    /// Optional features reported by `GetCapabilities`
+    pub mod feature {
+        /// Change notifications with `Watch`
+        pub const WATCH: &str = "watch";
+        /// `Watch` with `filters` and `prev_value`
+        pub const WATCH_FILTERS: &str = "watch_filters";
+        /// Atomic updates: batches, `CompareAndSet`, `Increment` and `Decrement`
+        pub const TRANSACTIONS: &str = "transactions";
+        /// Requests to configured namespaces
+        pub const NAMESPACES: &str = "namespaces";
+        /// Keys written with a time to live
+        pub const TTL: &str = "ttl";
+        /// Chunked reads with `GetValueStream`
+        pub const VALUE_STREAM: &str = "value_stream";
+        /// Prefix reads in batches with `Scan`
+        pub const SCAN: &str = "scan";
+        /// Values of any size with `SetLargeValue` and `GetLargeValue`
+        pub const LARGE_VALUES: &str = "large_values";
+        /// Failures reported as gRPC status codes on request, see [`super::STATUS_CODES_HEADER`]
+        pub const STATUS_CODES: &str = "status_codes";
+        /// Key revisions and `SetValue` with an `expected_revision`
+        pub const REVISIONS: &str = "revisions";
+        /// Kept versions of the keys with `GetHistory` and `GetValueAtRevision`
+        pub const KEY_HISTORY: &str = "key_history";
+        /// `RemoveKey` to the trash, `ListDeleted` and `RestoreKey`
+        pub const TRASH: &str = "trash";
+        /// `GrantLease`, `KeepAlive`, `RevokeLease` and keys attached to a lease
+        pub const LEASES: &str = "leases";
+        /// `Lock` and `Unlock`, held by a lease
+        pub const LOCKS: &str = "locks";
+    }
+
+    /// Metadata asking the service to report failures as gRPC status codes
+    ///
+    /// Without it, failed operations are answered with `success: false` and
+    /// an `error_message`, as older services do. With it, a missing key is
+    /// answered with `NOT_FOUND`, an invalid request with `INVALID_ARGUMENT`
+    /// or `FAILED_PRECONDITION`, a write at a stale revision with `ABORTED`
+    /// and a storage error with `INTERNAL`.
+    /// Responses reporting the results of a partly failed batch or a rejected
+    /// restore keep `success: false`.
+    pub const STATUS_CODES_HEADER: &str = "x-persistency-status-codes";
+
+    /// Metadata carrying the bearer token of the calling component
+    ///
+    /// Services with authentication enabled reject requests whose
+    /// `Bearer <token>` is not the token configured for the component named
+    /// in the identity metadata, see [`crate::identity`].
+    pub const AUTHORIZATION_METADATA: &str = "authorization";
+
     pub fn open_server() -> String {
         super::open_server(47007)
     }
@@ -146,6 +223,13 @@ pub mod persistency_proto {
     }
 }
 
+/// gRPC view of the DDS console apps, served next to their REST APIs
+#[cfg(feature = "grpc")]
+pub mod console {
+    tonic::include_proto!("console");
+}
+
+#[cfg(feature = "grpc")]
 pub mod external {
     tonic::include_proto!("schedinfo.v1");
     pub fn connect_timpani_server() -> String {
@@ -239,4 +323,22 @@ mod tests {
         };
         assert_eq!(result, "Invalid port"); // Assert that the result indicates an invalid port
     }
+
+    // Test case for the descriptor set of the persistency API
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_persistency_file_descriptor_set() {
+        use prost::Message;
+        let set =
+            prost_types::FileDescriptorSet::decode(crate::persistency_proto::FILE_DESCRIPTOR_SET)
+                .unwrap();
+        assert_eq!(set.file.len(), 1); // persistency.proto has no imports
+        let file = &set.file[0];
+        assert_eq!(file.package(), "persistency");
+        let service = &file.service[0];
+        assert_eq!(service.name(), "PersistencyService");
+        for method in ["SetValue", "GetValue", "Restore", "GetProtoDescriptor"] {
+            assert!(service.method.iter().any(|m| m.name() == method), "{method} missing");
+        }
+    }
 }
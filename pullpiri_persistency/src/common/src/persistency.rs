@@ -9,13 +9,15 @@
 //! offering key-value storage operations with built-in error handling and retry logic.
 //! This is the main interface for components to interact with persistent data storage.
 
-use crate::persistency_client::{PersistencyClient, PersistencyError};
+use crate::persistency_client::{Capabilities, PersistencyClient, PersistencyError};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 /// Re-export the error type for convenience
 pub type Error = PersistencyError;
 
+pub mod optional;
+
 /// Lazy static client instance for global access
 static CLIENT: tokio::sync::OnceCell<Arc<Mutex<PersistencyClient>>> = tokio::sync::OnceCell::const_new();
 
@@ -56,6 +58,15 @@ async fn get_client() -> Result<Arc<Mutex<PersistencyClient>>, PersistencyError>
     }).await.map(|client| client.clone())
 }
 
+/// Use `client` for the functions of this module instead of connecting to
+/// the configured service
+///
+/// Only takes effect before the first request, returns whether it did. Tests
+/// use it to run against an in-memory service.
+pub fn use_client(client: PersistencyClient) -> bool {
+    CLIENT.set(Arc::new(Mutex::new(client))).is_ok()
+}
+
 pub struct KV {
     pub key: String,
     pub value: String,
@@ -67,12 +78,77 @@ pub async fn put(key: &str, value: &str) -> Result<(), PersistencyError> {
     client.put(key, value).await
 }
 
+/// Set `key` to a binary value, see [`PersistencyClient::put_bytes`]
+pub async fn put_bytes(key: &str, value: &[u8]) -> Result<(), PersistencyError> {
+    let client = get_client().await?;
+    let mut client = client.lock().await;
+    client.put_bytes(key, value).await
+}
+
+/// Set `key` to `value` only if it still holds `expected`, see
+/// [`PersistencyClient::compare_and_set`]
+pub async fn compare_and_set(key: &str, expected: Option<&str>, value: &str) -> Result<bool, PersistencyError> {
+    let client = get_client().await?;
+    let mut client = client.lock().await;
+    client.compare_and_set(key, expected, value).await
+}
+
+/// Add `delta` to the counter under `key`, see [`PersistencyClient::increment`]
+pub async fn increment(key: &str, delta: i64) -> Result<i64, PersistencyError> {
+    let client = get_client().await?;
+    let mut client = client.lock().await;
+    client.increment(key, delta).await
+}
+
+/// Subtract `delta` from the counter under `key`, see [`PersistencyClient::decrement`]
+pub async fn decrement(key: &str, delta: i64) -> Result<i64, PersistencyError> {
+    let client = get_client().await?;
+    let mut client = client.lock().await;
+    client.decrement(key, delta).await
+}
+
 pub async fn get(key: &str) -> Result<String, PersistencyError> {
     let client = get_client().await?;
     let mut client = client.lock().await;
     client.get(key).await
 }
 
+/// Get the binary value of `key`, see [`PersistencyClient::get_bytes`]
+pub async fn get_bytes(key: &str) -> Result<Vec<u8>, PersistencyError> {
+    let client = get_client().await?;
+    let mut client = client.lock().await;
+    client.get_bytes(key).await
+}
+
+/// Get the string or binary value of `key` in chunks, see [`PersistencyClient::get_stream`]
+///
+/// The client is only locked until the service starts to send the chunks.
+pub async fn get_stream(
+    key: &str,
+) -> Result<impl tokio_stream::Stream<Item = Result<prost::bytes::Bytes, PersistencyError>>, PersistencyError> {
+    let client = get_client().await?;
+    let mut client = client.lock().await;
+    client.get_stream(key).await
+}
+
+/// Get the key-value pairs under `prefix` in batches, see [`PersistencyClient::scan`]
+///
+/// The client is only locked until the service starts to send the pairs.
+pub async fn scan(
+    prefix: &str,
+) -> Result<impl tokio_stream::Stream<Item = Result<Vec<crate::persistency_client::KV>, PersistencyError>>, PersistencyError> {
+    let client = get_client().await?;
+    let mut client = client.lock().await;
+    client.scan(prefix).await
+}
+
+/// API version and optional features of the service, see [`Capabilities`]
+pub async fn capabilities() -> Result<Capabilities, PersistencyError> {
+    let client = get_client().await?;
+    let client = client.lock().await;
+    Ok(client.capabilities().clone())
+}
+
 pub async fn get_all_with_prefix(key: &str) -> Result<Vec<KV>, PersistencyError> {
     let client = get_client().await?;
     let mut client = client.lock().await;
@@ -95,12 +171,25 @@ pub async fn delete(key: &str) -> Result<(), PersistencyError> {
     client.delete(key).await
 }
 
+/// Delete a key, keeping it restorable in the trash of the service if it has one
+pub async fn delete_to_trash(key: &str) -> Result<(), PersistencyError> {
+    let client = get_client().await?;
+    let mut client = client.lock().await;
+    client.delete_to_trash(key).await
+}
+
 pub async fn delete_all_with_prefix(key: &str) -> Result<(), PersistencyError> {
     let client = get_client().await?;
     let mut client = client.lock().await;
     client.delete_all_with_prefix(key).await
 }
 
+pub async fn flush_prefix(prefix: &str) -> Result<u32, PersistencyError> {
+    let client = get_client().await?;
+    let mut client = client.lock().await;
+    client.flush_prefix(prefix).await
+}
+
 // Keep the server configuration functions for compatibility
 pub fn open_server() -> String {
     let config = crate::setting::get_config();
@@ -123,105 +212,6 @@ mod tests {
     use super::*;
     use tokio;
 
-    // Test constants
-    const TEST_KEY: &str = "unit_test_key";
-    const TEST_VALUE: &str = "unit_test_value";
-    const TEST_PREFIX: &str = "unit_test_";
-
-    #[tokio::test]
-    async fn test_put_and_get() {
-        // Test put and get operations
-        let result = put(TEST_KEY, TEST_VALUE).await;
-        if result.is_ok() {
-            let get_result = get(TEST_KEY).await;
-            if let Ok(value) = get_result {
-                assert_eq!(value, TEST_VALUE);
-            }
-        }
-        // Clean up
-        let _ = delete(TEST_KEY).await;
-    }
-
-    #[tokio::test]
-    async fn test_get_nonexistent_key() {
-        let result = get("nonexistent_key_12345").await;
-        assert!(result.is_err(), "Expected error for nonexistent key");
-    }
-
-    #[tokio::test]
-    async fn test_get_all_with_prefix() {
-        // Set up test data
-        let _ = put(&format!("{}key1", TEST_PREFIX), "value1").await;
-        let _ = put(&format!("{}key2", TEST_PREFIX), "value2").await;
-        let _ = put("other_key", "other_value").await;
-
-        // Test prefix search
-        let result = get_all_with_prefix(TEST_PREFIX).await;
-        if let Ok(kvs) = result {
-            assert!(kvs.len() >= 2, "Expected at least 2 keys with prefix");
-            for kv in &kvs {
-                assert!(kv.key.starts_with(TEST_PREFIX), "Key should start with prefix");
-            }
-        }
-
-        // Clean up
-        let _ = delete(&format!("{}key1", TEST_PREFIX)).await;
-        let _ = delete(&format!("{}key2", TEST_PREFIX)).await;
-        let _ = delete("other_key").await;
-    }
-
-    #[tokio::test]
-    async fn test_delete() {
-        // Set up test data
-        let _ = put(TEST_KEY, TEST_VALUE).await;
-        
-        // Test delete
-        let delete_result = delete(TEST_KEY).await;
-        if delete_result.is_ok() {
-            let get_result = get(TEST_KEY).await;
-            assert!(get_result.is_err(), "Key should not exist after deletion");
-        }
-    }
-
-    #[tokio::test]
-    async fn test_delete_all_with_prefix() {
-        // Set up test data
-        let _ = put(&format!("{}key1", TEST_PREFIX), "value1").await;
-        let _ = put(&format!("{}key2", TEST_PREFIX), "value2").await;
-        let _ = put("other_key", "other_value").await;
-
-        // Test prefix deletion
-        let result = delete_all_with_prefix(TEST_PREFIX).await;
-        if result.is_ok() {
-            let remaining = get_all_with_prefix(TEST_PREFIX).await;
-            if let Ok(kvs) = remaining {
-                assert_eq!(kvs.len(), 0, "No keys should remain with the prefix");
-            }
-            
-            // Verify other key still exists
-            let other_result = get("other_key").await;
-            if other_result.is_ok() {
-                let _ = delete("other_key").await;
-            }
-        }
-    }
-
-    #[tokio::test]
-    async fn test_invalid_key_validation() {
-        // Test empty key
-        let result = put("", TEST_VALUE).await;
-        assert!(result.is_err(), "Empty key should be rejected");
-
-        // Test key with invalid characters
-        let result = put("key<with>invalid?chars{}", TEST_VALUE).await;
-        assert!(result.is_err(), "Key with invalid characters should be rejected");
-
-        // Test overly long key
-        let long_key = "a".repeat(2048);
-        let result = put(&long_key, TEST_VALUE).await;
-        assert!(result.is_err(), "Overly long key should be rejected");
-    }
-
     #[tokio::test]
     async fn test_open_server_config() {
         // This test just ensures the function doesn't panic with valid config
@@ -229,4 +219,4 @@ mod tests {
         assert!(!server_addr.is_empty(), "Server address should not be empty");
         assert!(server_addr.contains(":47007"), "Should use persistency service port");
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,564 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Graceful degradation for components that can run without persistency
+//!
+//! [`OptionalPersistency`] wraps the persistency service for components
+//! whose persisted data is nice to have, e.g. the last used mode or user
+//! settings. Instead of failing, it serves the last known values from a
+//! cache (or `None`, so the caller falls back to its default) while the
+//! service is unreachable, and queues writes to replay them in order once
+//! the service is back. Components report [`OptionalPersistency::status`]
+//! in their own health endpoints.
+//!
+//! Only outages degrade: a missing key or a rejected key is answered by the
+//! service and is reported as usual. While degraded, the service is probed
+//! again at most every [`OptionalConfig::retry_interval`], so callers do not
+//! wait for a timeout on every operation.
+
+use super::Error;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Operations [`OptionalPersistency`] needs from the persistency service
+pub trait Backend: Send + Sync {
+    fn get(&self, key: &str) -> impl Future<Output = Result<String, Error>> + Send;
+    fn put(&self, key: &str, value: &str) -> impl Future<Output = Result<(), Error>> + Send;
+    fn delete(&self, key: &str) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+/// The persistency service, through the global client of [`super`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Service;
+
+impl Backend for Service {
+    async fn get(&self, key: &str) -> Result<String, Error> {
+        super::get(key).await
+    }
+
+    async fn put(&self, key: &str, value: &str) -> Result<(), Error> {
+        super::put(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        super::delete(key).await
+    }
+}
+
+/// Configuration of [`OptionalPersistency`]
+#[derive(Debug, Clone)]
+pub struct OptionalConfig {
+    /// Time an operation may take before the service counts as unreachable
+    pub timeout: Duration,
+    /// Minimum time between two attempts to reach the service while degraded
+    pub retry_interval: Duration,
+    /// Queued writes kept while degraded; the oldest are dropped beyond this
+    pub max_queued_writes: usize,
+}
+
+impl Default for OptionalConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(500),
+            retry_interval: Duration::from_secs(5),
+            max_queued_writes: 1024,
+        }
+    }
+}
+
+/// Availability of the persistency service as seen by a component
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum DegradationStatus {
+    /// Operations reach the service and no writes are pending
+    Available,
+    /// The service is unreachable or queued writes are not replayed yet
+    Degraded {
+        /// Unix time in milliseconds the outage was first noticed
+        since_ms: u64,
+        /// Writes waiting for the service
+        queued_writes: usize,
+        /// Writes dropped because the queue was full
+        dropped_writes: u64,
+        /// Error of the last failed attempt
+        last_error: String,
+    },
+}
+
+/// Result of a write that did not fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+    /// Stored by the service
+    Stored,
+    /// Queued until the service is reachable again
+    Queued,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Write {
+    Put(String, String),
+    Delete(String),
+}
+
+impl Write {
+    fn key(&self) -> &str {
+        match self {
+            Write::Put(key, _) | Write::Delete(key) => key,
+        }
+    }
+}
+
+/// Ongoing outage
+#[derive(Debug)]
+struct Outage {
+    since_ms: u64,
+    last_attempt: Instant,
+    last_error: String,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Last known value per key, `None` for a key known to be absent
+    cache: HashMap<String, Option<String>>,
+    queue: VecDeque<Write>,
+    outage: Option<Outage>,
+    dropped_writes: u64,
+}
+
+/// Persistency service access that degrades instead of failing
+pub struct OptionalPersistency<B: Backend = Service> {
+    backend: B,
+    config: OptionalConfig,
+    state: Mutex<State>,
+    /// Held while the queue is replayed, so that writes are replayed once and in order
+    replay: tokio::sync::Mutex<()>,
+}
+
+/// Whether `error` means the service could not be reached, rather than an answer of the service
+fn is_outage(error: &Error) -> bool {
+    match error {
+        Error::Transport(_) => true,
+        Error::Grpc(status) => matches!(
+            status.code(),
+            tonic::Code::Unavailable
+                | tonic::Code::DeadlineExceeded
+                | tonic::Code::Cancelled
+                | tonic::Code::Unknown
+        ),
+        Error::Conversion(_)
+        | Error::NotFound
+        | Error::InvalidArgs(_)
+        | Error::Unsupported(_)
+        | Error::Storage(_)
+        | Error::Conflict(_)
+        | Error::QuotaExceeded(_) => false,
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Failure of an attempt to reach the service
+enum Failure {
+    Outage(String),
+    Rejected(Error),
+}
+
+impl OptionalPersistency<Service> {
+    /// Wrap the persistency service with the default configuration
+    pub fn new() -> Self {
+        Self::with_backend(Service, OptionalConfig::default())
+    }
+}
+
+impl Default for OptionalPersistency<Service> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Backend> OptionalPersistency<B> {
+    /// ### Parameters
+    /// * `backend` - service the operations are forwarded to
+    /// * `config` - timeouts and queue size
+    pub fn with_backend(backend: B, config: OptionalConfig) -> Self {
+        Self {
+            backend,
+            config,
+            state: Mutex::new(State::default()),
+            replay: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Current availability, for the component's health endpoint
+    pub fn status(&self) -> DegradationStatus {
+        let state = self.state.lock().unwrap();
+        match &state.outage {
+            None if state.queue.is_empty() => DegradationStatus::Available,
+            outage => DegradationStatus::Degraded {
+                since_ms: outage
+                    .as_ref()
+                    .map_or_else(now_ms, |outage| outage.since_ms),
+                queued_writes: state.queue.len(),
+                dropped_writes: state.dropped_writes,
+                last_error: outage
+                    .as_ref()
+                    .map(|outage| outage.last_error.clone())
+                    .unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Whether the service may be tried, i.e. it is not known to be down right now
+    fn may_try(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .outage
+            .as_ref()
+            .is_none_or(|outage| outage.last_attempt.elapsed() >= self.config.retry_interval)
+    }
+
+    /// Run one operation against the service within the timeout
+    async fn attempt<T>(
+        &self,
+        operation: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Failure> {
+        let result = match tokio::time::timeout(self.config.timeout, operation).await {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(error)) if is_outage(&error) => Err(Failure::Outage(error.to_string())),
+            Ok(Err(error)) => Err(Failure::Rejected(error)),
+            Err(_) => Err(Failure::Outage(format!(
+                "No answer within {:?}",
+                self.config.timeout
+            ))),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        match &result {
+            Err(Failure::Outage(error)) => {
+                let outage = state.outage.get_or_insert_with(|| {
+                    println!("Persistency service unreachable, degrading: {}", error);
+                    Outage {
+                        since_ms: now_ms(),
+                        last_attempt: Instant::now(),
+                        last_error: String::new(),
+                    }
+                });
+                outage.last_attempt = Instant::now();
+                outage.last_error = error.clone();
+            }
+            // Any answer of the service ends the outage
+            _ => {
+                if state.outage.take().is_some() {
+                    println!("Persistency service reachable again");
+                }
+            }
+        }
+        result
+    }
+
+    /// Replay the queued writes in order
+    ///
+    /// Returns whether the queue is empty afterwards. Writes the service
+    /// rejects are dropped, they would never succeed.
+    pub async fn replay_queued(&self) -> bool {
+        let Ok(_replay) = self.replay.try_lock() else {
+            // Another caller is replaying; its writes come first anyway
+            return false;
+        };
+        loop {
+            let Some(write) = self.state.lock().unwrap().queue.front().cloned() else {
+                return true;
+            };
+            let result = match &write {
+                Write::Put(key, value) => self.attempt(self.backend.put(key, value)).await,
+                Write::Delete(key) => self.attempt(self.backend.delete(key)).await,
+            };
+            match result {
+                Err(Failure::Outage(_)) => return false,
+                Err(Failure::Rejected(error)) => {
+                    println!("Dropping queued write of '{}': {}", write.key(), error);
+                }
+                Ok(()) => {}
+            }
+            let mut state = self.state.lock().unwrap();
+            // A newer write of the same key may have replaced this one meanwhile
+            if state.queue.front() == Some(&write) {
+                state.queue.pop_front();
+            }
+        }
+    }
+
+    /// Queue a write, replacing an older queued write of the same key
+    fn enqueue(&self, write: Write) {
+        let mut state = self.state.lock().unwrap();
+        state.queue.retain(|queued| queued.key() != write.key());
+        state.queue.push_back(write);
+        while state.queue.len() > self.config.max_queued_writes {
+            if let Some(dropped) = state.queue.pop_front() {
+                println!(
+                    "Persistency write queue full, dropping write of '{}'",
+                    dropped.key()
+                );
+                state.dropped_writes += 1;
+            }
+        }
+    }
+
+    /// Whether the service can be used now: reachable and no queued writes ahead
+    async fn ready(&self) -> bool {
+        if !self.may_try() {
+            return false;
+        }
+        let queued = !self.state.lock().unwrap().queue.is_empty();
+        !queued || self.replay_queued().await
+    }
+
+    /// Value of `key`, the last known value while the service is unreachable
+    ///
+    /// `Ok(None)` if the key does not exist, or is not known while degraded.
+    /// Errors are answers of the service, e.g. an invalid key.
+    pub async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        if self.ready().await {
+            match self.attempt(self.backend.get(key)).await {
+                Ok(value) => {
+                    self.cache(key, Some(value.clone()));
+                    return Ok(Some(value));
+                }
+                Err(Failure::Rejected(Error::NotFound)) => {
+                    self.cache(key, None);
+                    return Ok(None);
+                }
+                Err(Failure::Rejected(error)) => return Err(error),
+                Err(Failure::Outage(_)) => {}
+            }
+        }
+        Ok(self.state.lock().unwrap().cache.get(key).cloned().flatten())
+    }
+
+    /// Value of `key`, or `default` if it does not exist or cannot be read
+    pub async fn get_or(&self, key: &str, default: &str) -> String {
+        self.get(key)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| default.to_string())
+    }
+
+    /// Store `value` under `key`, queueing the write while the service is unreachable
+    pub async fn put(&self, key: &str, value: &str) -> Result<WriteOutcome, Error> {
+        self.write(Write::Put(key.to_string(), value.to_string()))
+            .await
+    }
+
+    /// Remove `key`, queueing the removal while the service is unreachable
+    pub async fn delete(&self, key: &str) -> Result<WriteOutcome, Error> {
+        self.write(Write::Delete(key.to_string())).await
+    }
+
+    async fn write(&self, write: Write) -> Result<WriteOutcome, Error> {
+        if self.ready().await {
+            let result = match &write {
+                Write::Put(key, value) => self.attempt(self.backend.put(key, value)).await,
+                Write::Delete(key) => self.attempt(self.backend.delete(key)).await,
+            };
+            match result {
+                Ok(()) => {
+                    self.cache_write(&write);
+                    return Ok(WriteOutcome::Stored);
+                }
+                Err(Failure::Rejected(error)) => return Err(error),
+                Err(Failure::Outage(_)) => {}
+            }
+        }
+        self.cache_write(&write);
+        self.enqueue(write);
+        Ok(WriteOutcome::Queued)
+    }
+
+    fn cache(&self, key: &str, value: Option<String>) {
+        self.state
+            .lock()
+            .unwrap()
+            .cache
+            .insert(key.to_string(), value);
+    }
+
+    fn cache_write(&self, write: &Write) {
+        match write {
+            Write::Put(key, value) => self.cache(key, Some(value.clone())),
+            Write::Delete(key) => self.cache(key, None),
+        }
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// In-memory service that can be switched off
+    #[derive(Default)]
+    struct FakeService {
+        values: Mutex<HashMap<String, String>>,
+        down: AtomicBool,
+    }
+
+    impl FakeService {
+        fn check(&self) -> Result<(), Error> {
+            if self.down.load(Ordering::SeqCst) {
+                Err(Error::Grpc(tonic::Status::unavailable("service down")))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl Backend for FakeService {
+        async fn get(&self, key: &str) -> Result<String, Error> {
+            self.check()?;
+            self.values
+                .lock()
+                .unwrap()
+                .get(key)
+                .cloned()
+                .ok_or(Error::NotFound)
+        }
+
+        async fn put(&self, key: &str, value: &str) -> Result<(), Error> {
+            self.check()?;
+            if key.is_empty() {
+                return Err(Error::InvalidArgs("Key cannot be empty".to_string()));
+            }
+            self.values
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), Error> {
+            self.check()?;
+            self.values.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    fn optional(max_queued_writes: usize) -> OptionalPersistency<FakeService> {
+        let config = OptionalConfig {
+            retry_interval: Duration::ZERO,
+            max_queued_writes,
+            ..Default::default()
+        };
+        OptionalPersistency::with_backend(FakeService::default(), config)
+    }
+
+    #[tokio::test]
+    async fn test_serves_cache_and_queues_writes_during_outage() {
+        let persistency = optional(16);
+        assert_eq!(
+            persistency.put("mode", "manual").await.unwrap(),
+            WriteOutcome::Stored
+        );
+        assert_eq!(
+            persistency.get("mode").await.unwrap().as_deref(),
+            Some("manual")
+        );
+        assert_eq!(persistency.status(), DegradationStatus::Available);
+
+        persistency.backend.down.store(true, Ordering::SeqCst);
+        assert_eq!(
+            persistency.get("mode").await.unwrap().as_deref(),
+            Some("manual")
+        );
+        assert_eq!(persistency.get_or("unknown", "default").await, "default");
+        assert_eq!(
+            persistency.put("mode", "autonomous").await.unwrap(),
+            WriteOutcome::Queued
+        );
+        assert_eq!(
+            persistency.delete("old").await.unwrap(),
+            WriteOutcome::Queued
+        );
+        assert_eq!(
+            persistency.get("mode").await.unwrap().as_deref(),
+            Some("autonomous")
+        );
+        match persistency.status() {
+            DegradationStatus::Degraded {
+                queued_writes,
+                last_error,
+                ..
+            } => {
+                assert_eq!(queued_writes, 2);
+                assert!(last_error.contains("service down"));
+            }
+            status => panic!("Expected degraded status, got {:?}", status),
+        }
+
+        persistency.backend.down.store(false, Ordering::SeqCst);
+        assert_eq!(
+            persistency.get("mode").await.unwrap().as_deref(),
+            Some("autonomous")
+        );
+        assert_eq!(persistency.status(), DegradationStatus::Available);
+        assert_eq!(
+            persistency
+                .backend
+                .values
+                .lock()
+                .unwrap()
+                .get("mode")
+                .cloned(),
+            Some("autonomous".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_answers_of_the_service_do_not_degrade() {
+        let persistency = optional(16);
+        assert_eq!(persistency.get("missing").await.unwrap(), None);
+        assert!(matches!(
+            persistency.put("", "value").await,
+            Err(Error::InvalidArgs(_))
+        ));
+        assert_eq!(persistency.status(), DegradationStatus::Available);
+    }
+
+    #[tokio::test]
+    async fn test_queue_coalesces_and_drops_oldest() {
+        let persistency = optional(2);
+        persistency.backend.down.store(true, Ordering::SeqCst);
+        for (key, value) in [("a", "1"), ("b", "1"), ("a", "2"), ("c", "1")] {
+            persistency.put(key, value).await.unwrap();
+        }
+        match persistency.status() {
+            DegradationStatus::Degraded {
+                queued_writes,
+                dropped_writes,
+                ..
+            } => {
+                assert_eq!(queued_writes, 2);
+                assert_eq!(dropped_writes, 1);
+            }
+            status => panic!("Expected degraded status, got {:?}", status),
+        }
+
+        persistency.backend.down.store(false, Ordering::SeqCst);
+        assert!(persistency.replay_queued().await);
+        let values = persistency.backend.values.lock().unwrap().clone();
+        assert_eq!(values.get("a").map(String::as_str), Some("2"));
+        assert_eq!(values.get("b"), None);
+        assert_eq!(values.get("c").map(String::as_str), Some("1"));
+    }
+}
@@ -7,15 +7,60 @@
 //!
 //! This module provides a client interface to the persistency service,
 //! replacing direct PERSISTENCY usage with gRPC calls to the persistency service.
+//! Every request carries the [`crate::identity`] of the calling component
+//! and, if one is set in [`TOKEN_ENV`], its bearer token. Components on the
+//! host of the service connect over its Unix domain socket if one is set in
+//! [`SOCKET_ENV`], see [`PersistencyClient::connect_to`].
+//!
+//! The client negotiates the [`Capabilities`] of the service when it
+//! connects. Features the service lacks fail with
+//! [`PersistencyError::Unsupported`] before a request is sent, chunked reads
+//! and scans fall back to reading everything at once.
+//!
+//! Values above [`DEFAULT_LARGE_VALUE_THRESHOLD`] are uploaded in chunks, and
+//! values too large for one response are read back in chunks, so that they
+//! never hit the 4 MiB message limit of tonic.
 
 use crate::persistency_proto::{
     persistency_service_client::PersistencyServiceClient, GetAllWithPrefixRequest,
     GetValueRequest, KvsValue, RemoveKeyRequest, ResetRequest, SetValueRequest,
-    FlushRequest,
+    FlushRequest, FlushPrefixRequest, CompareAndSetRequest, IncrementRequest, DecrementRequest,
+    GetValueStreamRequest, GetCapabilitiesRequest, ScanRequest, SetLargeValueRequest,
+    GetLargeValueRequest, GetHistoryRequest, GetValueAtRevisionRequest, KeyVersion, feature,
+    ListDeletedRequest, RestoreKeyRequest, DeletedKey,
+    GrantLeaseRequest, KeepAliveRequest, RevokeLeaseRequest, LockRequest, UnlockRequest,
+    WatchRequest, WatchResponse, WatchFilter, watch_request,
+    AUTHORIZATION_METADATA, STATUS_CODES_HEADER,
 };
-use tonic::transport::{Channel, Error as TonicError};
+use crate::identity::IdentityInterceptor;
+use prost::bytes::Bytes;
+use std::collections::BTreeSet;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio_stream::{Stream, StreamExt};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, Endpoint, Error as TonicError, Uri};
 use tonic::Status;
 
+/// Size above which values are uploaded in chunks, see
+/// [`PersistencyClient::with_large_value_threshold`]
+pub const DEFAULT_LARGE_VALUE_THRESHOLD: usize = 1024 * 1024;
+
+pub use crate::identity::TOKEN_ENV;
+
+/// Environment variable holding the Unix domain socket of the service, the
+/// configured host is connected over TCP without one
+pub const SOCKET_ENV: &str = "PICCOLO_PERSISTENCY_SOCKET";
+
+/// Scheme of endpoints naming a Unix domain socket, e.g.
+/// `unix:///run/piccolo/persistency-grpc.sock`
+pub const UNIX_SCHEME: &str = "unix://";
+
+/// Bytes per chunk of an upload
+const LARGE_VALUE_CHUNK_SIZE: usize = 64 * 1024;
+
 /// Key-Value pair for compatibility with existing code
 #[derive(Debug, Clone)]
 pub struct KV {
@@ -23,9 +68,39 @@ pub struct KV {
     pub value: String,
 }
 
+/// API version and optional features of the connected service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// 1 for services built before capability negotiation
+    pub api_version: u32,
+    /// Optional features of [`crate::persistency_proto::feature`] the service offers
+    pub features: BTreeSet<String>,
+}
+
+impl Capabilities {
+    /// Capabilities of services built before capability negotiation
+    ///
+    /// None of their optional features is relied on, as their build is unknown.
+    pub fn legacy() -> Self {
+        Self {
+            api_version: 1,
+            features: BTreeSet::new(),
+        }
+    }
+
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.contains(feature)
+    }
+}
+
 /// Client for the persistency service
 pub struct PersistencyClient {
-    client: PersistencyServiceClient<Channel>,
+    client: PersistencyServiceClient<InterceptedService<Channel, RequestInterceptor>>,
+    /// Namespace every request applies to, empty for the default store
+    namespace: String,
+    capabilities: Capabilities,
+    /// Values above this many bytes are uploaded in chunks
+    large_value_threshold: usize,
 }
 
 /// Custom error type for persistency operations
@@ -36,6 +111,53 @@ pub enum PersistencyError {
     Conversion(String),
     NotFound,
     InvalidArgs(String),
+    /// Optional feature the connected service does not offer
+    Unsupported(String),
+    /// The service failed to access its storage
+    Storage(String),
+    /// Another writer changed the key since its revision was read, or wrote
+    /// a removed key again before it was restored
+    Conflict(String),
+    /// The write would exceed a quota of the service
+    QuotaExceeded(String),
+}
+
+/// Interceptor identifying this process, sending its bearer token and asking
+/// for gRPC status codes on failures
+///
+/// Services without status codes ignore the metadata and keep answering
+/// with `success: false`, which the client handles as well.
+#[derive(Debug, Clone, Default)]
+pub struct RequestInterceptor {
+    /// `authorization` metadata, `None` without a token
+    authorization: Option<MetadataValue<Ascii>>,
+}
+
+impl RequestInterceptor {
+    /// Interceptor sending `token` as bearer token, none if it is empty
+    pub fn new(token: Option<&str>) -> Result<Self, PersistencyError> {
+        let authorization = token
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                format!("Bearer {}", token).parse().map_err(|_| {
+                    PersistencyError::InvalidArgs("Token is not valid gRPC metadata".to_string())
+                })
+            })
+            .transpose()?;
+        Ok(Self { authorization })
+    }
+}
+
+impl Interceptor for RequestInterceptor {
+    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, Status> {
+        let mut request = IdentityInterceptor.call(request)?;
+        let metadata = request.metadata_mut();
+        metadata.insert(STATUS_CODES_HEADER, MetadataValue::from_static("true"));
+        if let Some(authorization) = &self.authorization {
+            metadata.insert(AUTHORIZATION_METADATA, authorization.clone());
+        }
+        Ok(request)
+    }
 }
 
 impl From<TonicError> for PersistencyError {
@@ -44,9 +166,22 @@ impl From<TonicError> for PersistencyError {
     }
 }
 
+/// Failures reported with a status code map to the variants of `success: false` responses
 impl From<Status> for PersistencyError {
     fn from(err: Status) -> Self {
-        PersistencyError::Grpc(err)
+        match err.code() {
+            tonic::Code::NotFound => PersistencyError::NotFound,
+            tonic::Code::InvalidArgument | tonic::Code::FailedPrecondition => {
+                PersistencyError::InvalidArgs(err.message().to_string())
+            }
+            tonic::Code::Internal => PersistencyError::Storage(err.message().to_string()),
+            tonic::Code::Unimplemented => PersistencyError::Unsupported(err.message().to_string()),
+            tonic::Code::Aborted | tonic::Code::AlreadyExists => {
+                PersistencyError::Conflict(err.message().to_string())
+            }
+            tonic::Code::ResourceExhausted => PersistencyError::QuotaExceeded(err.message().to_string()),
+            _ => PersistencyError::Grpc(err),
+        }
     }
 }
 
@@ -58,6 +193,10 @@ impl std::fmt::Display for PersistencyError {
             PersistencyError::Conversion(e) => write!(f, "Conversion error: {}", e),
             PersistencyError::NotFound => write!(f, "Key not found"),
             PersistencyError::InvalidArgs(e) => write!(f, "Invalid arguments: {}", e),
+            PersistencyError::Unsupported(e) => write!(f, "Not supported by the persistency service: {}", e),
+            PersistencyError::Storage(e) => write!(f, "Storage error: {}", e),
+            PersistencyError::Conflict(e) => write!(f, "Conflict: {}", e),
+            PersistencyError::QuotaExceeded(e) => write!(f, "Quota exceeded: {}", e),
         }
     }
 }
@@ -65,12 +204,134 @@ impl std::fmt::Display for PersistencyError {
 impl std::error::Error for PersistencyError {}
 
 impl PersistencyClient {
-    /// Create a new persistency client
+    /// Create a new persistency client and negotiate the service capabilities
+    ///
+    /// The client connects to the socket in [`SOCKET_ENV`] if it is set, to
+    /// the configured host otherwise. It authenticates with the token in
+    /// [`TOKEN_ENV`], if it is set.
     pub async fn new() -> Result<Self, PersistencyError> {
-        let endpoint = crate::persistency_proto::connect_server();
-        let client = PersistencyServiceClient::connect(endpoint).await?;
+        let token = std::env::var(TOKEN_ENV).ok();
+        Self::connect(RequestInterceptor::new(token.as_deref())?).await
+    }
+
+    /// Create a new persistency client authenticating with `token`
+    ///
+    /// Services with authentication enabled only accept the token configured
+    /// for the component named in [`crate::identity::current`].
+    pub async fn connect_with_token(token: &str) -> Result<Self, PersistencyError> {
+        Self::connect(RequestInterceptor::new(Some(token))?).await
+    }
+
+    /// Create a client of the service at `endpoint` rather than the configured host
+    ///
+    /// Endpoints starting with [`UNIX_SCHEME`] name the Unix domain socket
+    /// of a service on the same host. The client authenticates with the
+    /// token in [`TOKEN_ENV`], if it is set.
+    pub async fn connect_to(endpoint: &str) -> Result<Self, PersistencyError> {
+        let token = std::env::var(TOKEN_ENV).ok();
+        Self::connect_endpoint(endpoint.to_string(), RequestInterceptor::new(token.as_deref())?).await
+    }
+
+    async fn connect(interceptor: RequestInterceptor) -> Result<Self, PersistencyError> {
+        let endpoint = match std::env::var(SOCKET_ENV) {
+            Ok(path) if !path.is_empty() => format!("{}{}", UNIX_SCHEME, path),
+            _ => crate::persistency_proto::connect_server(),
+        };
+        Self::connect_endpoint(endpoint, interceptor).await
+    }
+
+    async fn connect_endpoint(endpoint: String, interceptor: RequestInterceptor) -> Result<Self, PersistencyError> {
+        let channel = match endpoint.strip_prefix(UNIX_SCHEME) {
+            Some(path) => Self::connect_unix(path.to_string()).await?,
+            None => Channel::from_shared(endpoint)
+                .map_err(|e| PersistencyError::InvalidArgs(format!("Invalid endpoint: {}", e)))?
+                .connect()
+                .await?,
+        };
+        let mut client = PersistencyServiceClient::with_interceptor(channel, interceptor);
+        let capabilities = Self::negotiate(&mut client).await?;
         
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            namespace: String::new(),
+            capabilities,
+            large_value_threshold: DEFAULT_LARGE_VALUE_THRESHOLD,
+        })
+    }
+
+    /// Channel to the service listening on the Unix domain socket at `path`
+    async fn connect_unix(path: String) -> Result<Channel, PersistencyError> {
+        // The URI only fills the requests, every connection dials the socket
+        let channel = Endpoint::from_static("http://localhost")
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let path = path.clone();
+                async move {
+                    let stream = tokio::net::UnixStream::connect(path).await?;
+                    Ok::<_, std::io::Error>(hyper_util::rt::TokioIo::new(stream))
+                }
+            }))
+            .await?;
+        Ok(channel)
+    }
+
+    /// Ask the service for its capabilities
+    async fn negotiate(
+        client: &mut PersistencyServiceClient<InterceptedService<Channel, RequestInterceptor>>,
+    ) -> Result<Capabilities, PersistencyError> {
+        match client.get_capabilities(GetCapabilitiesRequest {}).await {
+            Ok(response) => {
+                let response = response.into_inner();
+                Ok(Capabilities {
+                    api_version: response.api_version,
+                    features: response.features.into_iter().collect(),
+                })
+            }
+            // Built before capability negotiation
+            Err(status) if status.code() == tonic::Code::Unimplemented => Ok(Capabilities::legacy()),
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    /// Capabilities of the service negotiated when connecting
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Fail before sending a request that needs `feature` if the service lacks it
+    fn require(&self, feature: &str) -> Result<(), PersistencyError> {
+        if self.capabilities.supports(feature) {
+            Ok(())
+        } else {
+            Err(PersistencyError::Unsupported(feature.to_string()))
+        }
+    }
+
+    /// Namespace of the next request, if the service offers namespaces
+    fn request_namespace(&self) -> Result<String, PersistencyError> {
+        if !self.namespace.is_empty() {
+            self.require(feature::NAMESPACES)?;
+        }
+        Ok(self.namespace.clone())
+    }
+
+    /// Send every request to `namespace` of the service instead of the default store
+    ///
+    /// The namespace has to be configured in the service, requests to an
+    /// unknown namespace are rejected. Requests fail with
+    /// [`PersistencyError::Unsupported`] if the service has no namespaces.
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    /// Upload values above `threshold` bytes in chunks with `SetLargeValue`
+    ///
+    /// Smaller values are sent in one message. Keep the threshold well below
+    /// the 4 MiB message limit, the request carries the key besides the value.
+    /// Services without large values get every value in one message.
+    pub fn with_large_value_threshold(mut self, threshold: usize) -> Self {
+        self.large_value_threshold = threshold;
+        self
     }
 
     /// Helper function to convert string to KvsValue
@@ -99,6 +360,9 @@ impl PersistencyClient {
                 // For arrays and objects, we'll serialize to JSON string
                 Err(PersistencyError::Conversion("Complex types not supported in string conversion".to_string()))
             }
+            Some(crate::persistency_proto::kvs_value::Value::BytesValue(_)) => {
+                Err(PersistencyError::Conversion("Binary value, read it with get_bytes".to_string()))
+            }
             None => Err(PersistencyError::Conversion("Empty value".to_string())),
         }
     }
@@ -106,6 +370,10 @@ impl PersistencyClient {
     /// Set a key-value pair
     pub async fn put(&mut self, key: &str, value: &str) -> Result<(), PersistencyError> {
         // Validate key similar to original implementation
+        if key.is_empty() {
+            return Err(PersistencyError::InvalidArgs("Key cannot be empty".to_string()));
+        }
+
         if key.len() > 1024 {
             return Err(PersistencyError::InvalidArgs(
                 "Key exceeds maximum allowed length of 1024 characters".to_string(),
@@ -118,9 +386,17 @@ impl PersistencyClient {
             ));
         }
 
+        if self.is_large(value.len()) {
+            return self.put_large(key, value.as_bytes().to_vec(), false).await;
+        }
+
         let request = SetValueRequest {
             key: key.to_string(),
             value: Some(Self::string_to_kvs_value(value)),
+            ttl_seconds: 0,
+            namespace: self.request_namespace()?,
+            expected_revision: 0,
+            lease_id: 0,
         };
 
         let response = self.client.set_value(request).await?;
@@ -133,9 +409,284 @@ impl PersistencyClient {
         }
     }
 
-    /// Get a value by key
-    pub async fn get(&mut self, key: &str) -> Result<String, PersistencyError> {
-        // Validate key similar to original implementation
+    /// Set a key to a binary value
+    ///
+    /// For payloads that are no valid UTF-8, like compressed yaml or recorded
+    /// samples. Read them back with [`get_bytes`](Self::get_bytes).
+    pub async fn put_bytes(&mut self, key: &str, value: &[u8]) -> Result<(), PersistencyError> {
+        Self::validate_read_key(key)?;
+        if self.is_large(value.len()) {
+            return self.put_large(key, value.to_vec(), true).await;
+        }
+
+        let request = SetValueRequest {
+            key: key.to_string(),
+            value: Some(KvsValue {
+                value: Some(crate::persistency_proto::kvs_value::Value::BytesValue(value.to_vec())),
+            }),
+            ttl_seconds: 0,
+            namespace: self.request_namespace()?,
+            expected_revision: 0,
+            lease_id: 0,
+        };
+
+        let response = self.client.set_value(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(())
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Whether a value of `size` bytes is uploaded in chunks
+    fn is_large(&self, size: usize) -> bool {
+        size > self.large_value_threshold && self.capabilities.supports(feature::LARGE_VALUES)
+    }
+
+    /// Upload `value` in chunks with `SetLargeValue`
+    async fn put_large(&mut self, key: &str, value: Vec<u8>, binary: bool) -> Result<(), PersistencyError> {
+        let key = key.to_string();
+        let namespace = self.request_namespace()?;
+        let total_size = value.len() as u64;
+        // The first request carries the key, the others only their chunk
+        let requests = (0..value.len().max(1))
+            .step_by(LARGE_VALUE_CHUNK_SIZE)
+            .map(move |start| {
+                let chunk = value[start..(start + LARGE_VALUE_CHUNK_SIZE).min(value.len())].to_vec();
+                if start == 0 {
+                    SetLargeValueRequest {
+                        key: key.clone(),
+                        namespace: namespace.clone(),
+                        ttl_seconds: 0,
+                        binary,
+                        total_size,
+                        chunk,
+                    }
+                } else {
+                    SetLargeValueRequest {
+                        chunk,
+                        ..Default::default()
+                    }
+                }
+            });
+
+        let response = self.client.set_large_value(tokio_stream::iter(requests)).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(())
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Set `key` to `value` only if it still holds `expected`
+    ///
+    /// With `expected` set to `None` the key must not exist yet. Returns
+    /// whether the value was set; on `false` another writer changed the key
+    /// first, read it again and retry.
+    pub async fn compare_and_set(
+        &mut self,
+        key: &str,
+        expected: Option<&str>,
+        value: &str,
+    ) -> Result<bool, PersistencyError> {
+        if key.is_empty() {
+            return Err(PersistencyError::InvalidArgs("Key cannot be empty".to_string()));
+        }
+        self.require(feature::TRANSACTIONS)?;
+
+        let request = CompareAndSetRequest {
+            key: key.to_string(),
+            expected_value: expected.map(Self::string_to_kvs_value),
+            new_value: Some(Self::string_to_kvs_value(value)),
+            namespace: self.request_namespace()?,
+        };
+
+        let response = self.client.compare_and_set(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(response.swapped)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Get the value of `key` together with its revision
+    ///
+    /// Pass the revision to [`put_if_revision`](Self::put_if_revision) to
+    /// write the key only if nobody changed it meanwhile. Values too large
+    /// for a single response cannot be read with their revision.
+    pub async fn get_with_revision(&mut self, key: &str) -> Result<(String, u64), PersistencyError> {
+        Self::validate_read_key(key)?;
+        self.require(feature::REVISIONS)?;
+
+        let request = GetValueRequest {
+            key: key.to_string(),
+            namespace: self.request_namespace()?,
+        };
+
+        let response = self.client.get_value(request).await?;
+        let response = response.into_inner();
+
+        match response.value {
+            Some(value) if response.success => Ok((Self::kvs_value_to_string(&value)?, response.revision)),
+            _ => Err(PersistencyError::NotFound),
+        }
+    }
+
+    /// Set `key` to `value` only if it is still at `expected_revision`
+    ///
+    /// Returns the new revision of the key. Fails with
+    /// [`PersistencyError::Conflict`] if another writer changed or removed
+    /// the key since the revision was read; read it again and retry.
+    pub async fn put_if_revision(
+        &mut self,
+        key: &str,
+        value: &str,
+        expected_revision: u64,
+    ) -> Result<u64, PersistencyError> {
+        Self::validate_read_key(key)?;
+        if expected_revision == 0 {
+            return Err(PersistencyError::InvalidArgs("Expected revision cannot be 0".to_string()));
+        }
+        self.require(feature::REVISIONS)?;
+        if self.is_large(value.len()) {
+            return Err(PersistencyError::InvalidArgs(
+                "Values uploaded in chunks cannot be written with an expected revision".to_string(),
+            ));
+        }
+
+        let request = SetValueRequest {
+            key: key.to_string(),
+            value: Some(Self::string_to_kvs_value(value)),
+            ttl_seconds: 0,
+            namespace: self.request_namespace()?,
+            expected_revision,
+            lease_id: 0,
+        };
+
+        let response = self.client.set_value(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(response.revision)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Kept versions of `key`, newest first
+    ///
+    /// Removals are versions without value. Fails unless the service keeps
+    /// the versions of the key.
+    pub async fn get_history(&mut self, key: &str) -> Result<Vec<KeyVersion>, PersistencyError> {
+        Self::validate_read_key(key)?;
+        self.require(feature::KEY_HISTORY)?;
+
+        let request = GetHistoryRequest {
+            key: key.to_string(),
+            namespace: self.request_namespace()?,
+        };
+
+        let response = self.client.get_history(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(response.versions)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Get the value `key` had at `revision` of the store
+    ///
+    /// Write it back with [`put_if_revision`](Self::put_if_revision) to roll
+    /// the key back. Fails with [`PersistencyError::NotFound`] if the key did
+    /// not exist then or that version is no longer kept.
+    pub async fn get_at_revision(&mut self, key: &str, revision: u64) -> Result<String, PersistencyError> {
+        Self::validate_read_key(key)?;
+        self.require(feature::KEY_HISTORY)?;
+
+        let request = GetValueAtRevisionRequest {
+            key: key.to_string(),
+            revision,
+            namespace: self.request_namespace()?,
+        };
+
+        let response = self.client.get_value_at_revision(request).await?;
+        let response = response.into_inner();
+
+        match response.version.and_then(|version| version.value) {
+            Some(value) if response.success => Self::kvs_value_to_string(&value),
+            _ => Err(PersistencyError::NotFound),
+        }
+    }
+
+    /// Add `delta` to the integer counter under `key`, returning the new value
+    ///
+    /// A missing key starts at 0. The service updates the counter
+    /// atomically, concurrent increments are never lost.
+    pub async fn increment(&mut self, key: &str, delta: i64) -> Result<i64, PersistencyError> {
+        self.require(feature::TRANSACTIONS)?;
+        let request = IncrementRequest {
+            key: key.to_string(),
+            delta: Some(KvsValue {
+                value: Some(crate::persistency_proto::kvs_value::Value::I64Value(delta)),
+            }),
+            namespace: self.request_namespace()?,
+        };
+
+        let response = self.client.increment(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Self::kvs_value_to_i64(response.value)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Subtract `delta` from the integer counter under `key`, returning the new value
+    pub async fn decrement(&mut self, key: &str, delta: i64) -> Result<i64, PersistencyError> {
+        self.require(feature::TRANSACTIONS)?;
+        let request = DecrementRequest {
+            key: key.to_string(),
+            delta: Some(KvsValue {
+                value: Some(crate::persistency_proto::kvs_value::Value::I64Value(delta)),
+            }),
+            namespace: self.request_namespace()?,
+        };
+
+        let response = self.client.decrement(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Self::kvs_value_to_i64(response.value)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Helper function to convert an integer counter value to i64
+    fn kvs_value_to_i64(value: Option<KvsValue>) -> Result<i64, PersistencyError> {
+        use crate::persistency_proto::kvs_value::Value;
+
+        match value.and_then(|value| value.value) {
+            Some(Value::I32Value(v)) => Ok(v as i64),
+            Some(Value::U32Value(v)) => Ok(v as i64),
+            Some(Value::I64Value(v)) => Ok(v),
+            Some(Value::U64Value(v)) => i64::try_from(v)
+                .map_err(|_| PersistencyError::Conversion(format!("Counter value {} exceeds i64", v))),
+            _ => Err(PersistencyError::Conversion("Counter is not an integer".to_string())),
+        }
+    }
+
+    /// Validate a key to read, similar to original implementation
+    fn validate_read_key(key: &str) -> Result<(), PersistencyError> {
         if key.is_empty() {
             return Err(PersistencyError::InvalidArgs("Key cannot be empty".to_string()));
         }
@@ -151,29 +702,143 @@ impl PersistencyClient {
                 "Key contains invalid special characters".to_string(),
             ));
         }
+        Ok(())
+    }
 
+    /// Get a value by key
+    pub async fn get(&mut self, key: &str) -> Result<String, PersistencyError> {
+        Self::validate_read_key(key)?;
+        let value = self.read_value(key).await?;
+        Self::kvs_value_to_string(&value)
+    }
+
+    /// Read the value of `key`, in chunks if it is too large for one response
+    async fn read_value(&mut self, key: &str) -> Result<KvsValue, PersistencyError> {
         let request = GetValueRequest {
             key: key.to_string(),
+            namespace: self.request_namespace()?,
         };
 
-        let response = self.client.get_value(request).await?;
-        let response = response.into_inner();
-        
-        if response.success {
-            if let Some(value) = response.value {
-                Self::kvs_value_to_string(&value)
-            } else {
-                Err(PersistencyError::NotFound)
+        let response = match self.client.get_value(request).await {
+            Ok(response) => response.into_inner(),
+            // The response exceeded the message size limit
+            Err(status)
+                if status.code() == tonic::Code::OutOfRange
+                    && self.capabilities.supports(feature::LARGE_VALUES) =>
+            {
+                return self.read_large_value(key).await;
             }
+            Err(status) => return Err(status.into()),
+        };
+
+        if response.success {
+            response.value.ok_or(PersistencyError::NotFound)
         } else {
             Err(PersistencyError::NotFound)
         }
     }
 
+    /// Read the value of `key` in chunks with `GetLargeValue`
+    async fn read_large_value(&mut self, key: &str) -> Result<KvsValue, PersistencyError> {
+        use crate::persistency_proto::kvs_value::Value;
+
+        let request = GetLargeValueRequest {
+            key: key.to_string(),
+            namespace: self.request_namespace()?,
+            // Default chunk size of the service
+            chunk_size: 0,
+        };
+        let mut responses = match self.client.get_large_value(request).await {
+            Ok(response) => response.into_inner(),
+            Err(status) if status.code() == tonic::Code::NotFound => {
+                return Err(PersistencyError::NotFound)
+            }
+            Err(status) => return Err(status.into()),
+        };
+
+        let mut data = Vec::new();
+        let mut binary = false;
+        let mut first = true;
+        while let Some(response) = responses.message().await? {
+            if first {
+                data.reserve(response.total_size as usize);
+                binary = response.binary;
+                first = false;
+            }
+            data.extend(response.chunk);
+        }
+
+        let value = if binary {
+            Value::BytesValue(data)
+        } else {
+            Value::StringValue(String::from_utf8(data).map_err(|e| PersistencyError::Conversion(e.to_string()))?)
+        };
+        Ok(KvsValue { value: Some(value) })
+    }
+
+    /// Get a binary value by key
+    ///
+    /// String values are returned as their UTF-8 bytes.
+    pub async fn get_bytes(&mut self, key: &str) -> Result<Vec<u8>, PersistencyError> {
+        use crate::persistency_proto::kvs_value::Value;
+
+        Self::validate_read_key(key)?;
+
+        match self.read_value(key).await?.value {
+            Some(Value::BytesValue(bytes)) => Ok(bytes),
+            Some(Value::StringValue(s)) => Ok(s.into_bytes()),
+            Some(_) => Err(PersistencyError::Conversion("Value is not binary".to_string())),
+            None => Err(PersistencyError::NotFound),
+        }
+    }
+
+    /// Get a string or binary value by key in chunks
+    ///
+    /// Meant for values of hundreds of KB like artifact yaml, which can be
+    /// parsed while they arrive instead of being copied around as a whole.
+    /// The chunks split the value at byte boundaries, a UTF-8 character can
+    /// span two chunks. Only string and binary values can be streamed.
+    /// Services without chunked reads send the whole value as one chunk.
+    pub async fn get_stream(
+        &mut self,
+        key: &str,
+    ) -> Result<impl Stream<Item = Result<Bytes, PersistencyError>>, PersistencyError> {
+        type ChunkStream = Pin<Box<dyn Stream<Item = Result<Bytes, PersistencyError>> + Send>>;
+
+        Self::validate_read_key(key)?;
+        if !self.capabilities.supports(feature::VALUE_STREAM) {
+            let value = self.get_bytes(key).await?;
+            let chunks: ChunkStream = Box::pin(tokio_stream::once(Ok(Bytes::from(value))));
+            return Ok(chunks);
+        }
+
+        let request = GetValueStreamRequest {
+            key: key.to_string(),
+            namespace: self.request_namespace()?,
+            // Default chunk size of the service
+            chunk_size: 0,
+        };
+
+        let stream = match self.client.get_value_stream(request).await {
+            Ok(response) => response.into_inner(),
+            Err(status) if status.code() == tonic::Code::NotFound => {
+                return Err(PersistencyError::NotFound)
+            }
+            Err(status) => return Err(status.into()),
+        };
+        let chunks: ChunkStream = Box::pin(stream.map(|response| {
+            response
+                .map(|response| Bytes::from(response.chunk))
+                .map_err(PersistencyError::from)
+        }));
+        Ok(chunks)
+    }
+
     /// Get all key-value pairs with a given prefix
     pub async fn get_all_with_prefix(&mut self, prefix: &str) -> Result<Vec<KV>, PersistencyError> {
         let request = GetAllWithPrefixRequest {
             prefix: prefix.to_string(),
+            namespace: self.request_namespace()?,
         };
 
         let response = self.client.get_all_with_prefix(request).await?;
@@ -201,9 +866,281 @@ impl PersistencyClient {
         }
     }
 
+    /// Get the key-value pairs with a given prefix in batches, sorted by key
+    ///
+    /// Unlike [`Self::get_all_with_prefix`], neither side holds all pairs at
+    /// once. Every batch is read separately, keys written during the scan
+    /// show up if they sort after the pairs already received. Complex values
+    /// are skipped. Services without scans send all pairs in one batch.
+    pub async fn scan(
+        &mut self,
+        prefix: &str,
+    ) -> Result<impl Stream<Item = Result<Vec<KV>, PersistencyError>>, PersistencyError> {
+        type BatchStream = Pin<Box<dyn Stream<Item = Result<Vec<KV>, PersistencyError>> + Send>>;
+
+        if !self.capabilities.supports(feature::SCAN) {
+            let mut kv_pairs = self.get_all_with_prefix(prefix).await?;
+            kv_pairs.sort_by(|a, b| a.key.cmp(&b.key));
+            let batches: BatchStream = Box::pin(tokio_stream::once(Ok(kv_pairs)));
+            return Ok(batches);
+        }
+
+        let request = ScanRequest {
+            prefix: prefix.to_string(),
+            namespace: self.request_namespace()?,
+            // Default batch size of the service
+            batch_size: 0,
+        };
+
+        let stream = self.client.scan(request).await?.into_inner();
+        let batches: BatchStream = Box::pin(stream.map(|response| {
+            let kv_pairs = response?
+                .entries
+                .into_iter()
+                .filter_map(|entry| {
+                    // Skip complex values that can't be converted to strings
+                    let value = Self::kvs_value_to_string(entry.value.as_ref()?).ok()?;
+                    Some(KV { key: entry.key, value })
+                })
+                .collect();
+            Ok(kv_pairs)
+        }));
+        Ok(batches)
+    }
+
+    /// Watch the changes of a key or of the keys under a prefix
+    ///
+    /// `filters` leave out puts or deletions and `prev_value` asks for the
+    /// value a key had before each change, so that e.g. a mode watcher
+    /// receives only what it acts on. Filters and previous values fail with
+    /// [`PersistencyError::Unsupported`] on services without them. The
+    /// stream reports a `LAGGED` event if the watcher fell behind.
+    pub async fn watch(
+        &mut self,
+        target: watch_request::Target,
+        filters: &[WatchFilter],
+        prev_value: bool,
+    ) -> Result<impl Stream<Item = Result<WatchResponse, PersistencyError>>, PersistencyError> {
+        self.require(feature::WATCH)?;
+        if !filters.is_empty() || prev_value {
+            self.require(feature::WATCH_FILTERS)?;
+        }
+
+        let request = WatchRequest {
+            target: Some(target),
+            namespace: self.request_namespace()?,
+            filters: filters.iter().map(|filter| *filter as i32).collect(),
+            prev_value,
+        };
+
+        let stream = self.client.watch(request).await?.into_inner();
+        Ok(stream.map(|response| response.map_err(PersistencyError::from)))
+    }
+
     /// Delete a key
     pub async fn delete(&mut self, key: &str) -> Result<(), PersistencyError> {
+        self.remove(key, false).await
+    }
+
+    /// Delete a key, keeping it in the trash of the service
+    ///
+    /// The key can be brought back with [`restore_deleted`](Self::restore_deleted)
+    /// until the retention of the trash is over. Services without a trash
+    /// delete the key for good, like [`delete`](Self::delete).
+    pub async fn delete_to_trash(&mut self, key: &str) -> Result<(), PersistencyError> {
+        self.remove(key, self.capabilities.supports(feature::TRASH)).await
+    }
+
+    /// Deleted keys in the trash starting with `prefix`, sorted by key
+    pub async fn list_deleted(&mut self, prefix: &str) -> Result<Vec<DeletedKey>, PersistencyError> {
+        self.require(feature::TRASH)?;
+
+        let request = ListDeletedRequest {
+            prefix: prefix.to_string(),
+            namespace: self.request_namespace()?,
+        };
+
+        let response = self.client.list_deleted(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(response.keys)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Bring a key deleted with [`delete_to_trash`](Self::delete_to_trash) back, returning its new revision
+    ///
+    /// Fails with [`PersistencyError::Conflict`] if the key was written
+    /// again since, and with [`PersistencyError::NotFound`] if it is not in
+    /// the trash.
+    pub async fn restore_deleted(&mut self, key: &str) -> Result<u64, PersistencyError> {
+        Self::validate_read_key(key)?;
+        self.require(feature::TRASH)?;
+
+        let request = RestoreKeyRequest {
+            key: key.to_string(),
+            namespace: self.request_namespace()?,
+        };
+
+        let response = self.client.restore_key(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(response.revision)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Grant a lease living for `ttl_secs` unless kept alive, returning its id
+    ///
+    /// Keys written with [`put_with_lease`](Self::put_with_lease) are removed
+    /// when the lease is revoked or expires, e.g. because this component
+    /// died. Keep it alive with [`keep_alive`](Self::keep_alive).
+    pub async fn grant_lease(&mut self, ttl_secs: u32) -> Result<u64, PersistencyError> {
+        self.require(feature::LEASES)?;
+
+        let response = self
+            .client
+            .grant_lease(GrantLeaseRequest { ttl_seconds: ttl_secs })
+            .await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(response.lease_id)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Set a key-value pair that is removed together with lease `lease_id`
+    ///
+    /// Writing the key again without the lease keeps it for good.
+    pub async fn put_with_lease(&mut self, key: &str, value: &str, lease_id: u64) -> Result<(), PersistencyError> {
+        Self::validate_read_key(key)?;
+        self.require(feature::LEASES)?;
+
+        let request = SetValueRequest {
+            key: key.to_string(),
+            value: Some(Self::string_to_kvs_value(value)),
+            ttl_seconds: 0,
+            namespace: self.request_namespace()?,
+            expected_revision: 0,
+            lease_id,
+        };
+
+        let response = self.client.set_value(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(())
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Keep lease `lease_id` alive, renewing it every `interval`
+    ///
+    /// Yields the time to live in seconds granted by every renewal, and
+    /// [`PersistencyError::NotFound`] as its last item once the lease
+    /// expired or was revoked; its keys are removed then. Renew well within
+    /// the time to live, e.g. every third of it. Dropping the stream stops
+    /// the renewals and lets the lease expire.
+    pub async fn keep_alive(
+        &mut self,
+        lease_id: u64,
+        interval: Duration,
+    ) -> Result<impl Stream<Item = Result<u32, PersistencyError>>, PersistencyError> {
+        self.require(feature::LEASES)?;
+
+        let requests = tokio_stream::iter(std::iter::repeat(KeepAliveRequest { lease_id })).throttle(interval);
+        let stream = self.client.keep_alive(requests).await?.into_inner();
+        let mut ended = false;
+        Ok(stream.map_while(move |response| {
+            if ended {
+                return None;
+            }
+            let renewed = match response {
+                Ok(response) if response.ttl_seconds > 0 => return Some(Ok(response.ttl_seconds)),
+                Ok(_) => Err(PersistencyError::NotFound),
+                Err(status) => Err(status.into()),
+            };
+            ended = true;
+            Some(renewed)
+        }))
+    }
+
+    /// Revoke lease `lease_id`, removing the keys attached to it, and return their number
+    pub async fn revoke_lease(&mut self, lease_id: u64) -> Result<u32, PersistencyError> {
+        self.require(feature::LEASES)?;
+
+        let response = self
+            .client
+            .revoke_lease(RevokeLeaseRequest { lease_id })
+            .await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(response.keys)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Try to take the lock `name` for `ttl_secs`, returning its token
+    ///
+    /// Returns `None` without waiting if another caller holds the lock. Keep
+    /// the lock with [`keep_alive`](Self::keep_alive) of the token and
+    /// release it with [`unlock`](Self::unlock); a lock not kept alive is
+    /// released after its time to live.
+    pub async fn lock(&mut self, name: &str, ttl_secs: u32) -> Result<Option<u64>, PersistencyError> {
+        self.require(feature::LOCKS)?;
+
+        let request = LockRequest {
+            name: name.to_string(),
+            ttl_seconds: ttl_secs,
+        };
+
+        let response = self.client.lock(request).await?;
+        let response = response.into_inner();
+
+        if !response.success {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        } else if response.acquired {
+            Ok(Some(response.token))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Release the lock `name` taken with `token`
+    pub async fn unlock(&mut self, name: &str, token: u64) -> Result<(), PersistencyError> {
+        self.require(feature::LOCKS)?;
+
+        let request = UnlockRequest {
+            name: name.to_string(),
+            token,
+        };
+
+        let response = self.client.unlock(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(())
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Remove a key, moving it to the trash if `trash` is set
+    async fn remove(&mut self, key: &str, trash: bool) -> Result<(), PersistencyError> {
         // Validate key similar to original implementation
+        if key.is_empty() {
+            return Err(PersistencyError::InvalidArgs("Key cannot be empty".to_string()));
+        }
+
         if key.len() > 1024 {
             return Err(PersistencyError::InvalidArgs(
                 "Key exceeds maximum allowed length of 1024 characters".to_string(),
@@ -218,6 +1155,8 @@ impl PersistencyClient {
 
         let request = RemoveKeyRequest {
             key: key.to_string(),
+            namespace: self.request_namespace()?,
+            trash,
         };
 
         let response = self.client.remove_key(request).await?;
@@ -243,9 +1182,14 @@ impl PersistencyClient {
         Ok(())
     }
 
-    /// Reset all data (for testing/development)
+    /// Reset all data of the namespace (for testing/development)
+    ///
+    /// A service with a grace period for destructive operations only
+    /// journals the reset, the data is removed once the grace period is over.
     pub async fn reset(&mut self) -> Result<(), PersistencyError> {
-        let request = ResetRequest {};
+        let request = ResetRequest {
+            namespace: self.request_namespace()?,
+        };
         let response = self.client.reset(request).await?;
         let response = response.into_inner();
         
@@ -257,8 +1201,13 @@ impl PersistencyClient {
     }
 
     /// Flush data to persistent storage
+    ///
+    /// The service flushes writes in batches in the background, so a `put`
+    /// is only durable once this or [`Self::flush_prefix`] returned.
     pub async fn flush(&mut self) -> Result<(), PersistencyError> {
-        let request = FlushRequest {};
+        let request = FlushRequest {
+            namespace: self.request_namespace()?,
+        };
         let response = self.client.flush(request).await?;
         let response = response.into_inner();
         
@@ -268,4 +1217,23 @@ impl PersistencyClient {
             Err(PersistencyError::InvalidArgs(response.error_message))
         }
     }
+
+    /// Make pending changes of keys starting with `prefix` durable
+    ///
+    /// Returns the number of pending changes that were flushed. No storage
+    /// write happens when nothing under the prefix is pending.
+    pub async fn flush_prefix(&mut self, prefix: &str) -> Result<u32, PersistencyError> {
+        let request = FlushPrefixRequest {
+            prefix: prefix.to_string(),
+            namespace: self.request_namespace()?,
+        };
+        let response = self.client.flush_prefix(request).await?;
+        let response = response.into_inner();
+
+        if response.success {
+            Ok(response.flushed_keys)
+        } else {
+            Err(PersistencyError::InvalidArgs(response.error_message))
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,354 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Lightweight Persistency Client
+//!
+//! Blocking client for nodes that cannot carry the tonic/tokio stack. It talks
+//! to the persistency service over a Unix domain socket with a simple framing
+//! protocol that the service serves next to gRPC: every message is a 4-byte
+//! big-endian length followed by that many bytes of JSON. A connection carries
+//! any number of request/response pairs in order.
+//!
+//! Every request carries the name of the calling component, see
+//! [`crate::identity`], and, if one is set in [`TOKEN_ENV`], its bearer token,
+//! so services with authentication enabled admit lite requests like gRPC ones.
+//!
+//! Only the `lite-client` feature is needed, so the crate can be used with
+//! `default-features = false`.
+
+use crate::identity;
+pub use crate::identity::TOKEN_ENV;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+/// Default socket of the persistency service
+pub const DEFAULT_SOCKET_PATH: &str = "/run/piccolo/persistency.sock";
+
+/// Environment variable overriding the socket path
+pub const SOCKET_PATH_ENV: &str = "PERSISTENCY_LITE_SOCKET";
+
+/// Largest accepted frame payload
+pub const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+/// Upper bound for a single request/response exchange
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum LiteRequest {
+    Put { key: String, value: String },
+    Get { key: String },
+    Delete { key: String },
+}
+
+/// Request frame with the credentials of the calling component
+///
+/// The credentials are fields next to `op`, e.g.
+/// `{"op":"get","key":"vehicle/mode","component":"apiserver","token":"3f9c2a"}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiteFrame {
+    #[serde(flatten)]
+    pub request: LiteRequest,
+    /// Name of the calling component
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub component: String,
+    /// Bearer token of the component
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub token: String,
+}
+
+/// Response frame
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LiteResponse {
+    pub success: bool,
+    /// Value of a successful `get`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub error_message: String,
+}
+
+impl LiteResponse {
+    pub fn ok(value: Option<String>) -> Self {
+        Self {
+            success: true,
+            value,
+            error_message: String::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            value: None,
+            error_message: message.into(),
+        }
+    }
+}
+
+/// Error type for lightweight persistency operations
+#[derive(Debug)]
+pub enum LiteError {
+    Io(std::io::Error),
+    Protocol(String),
+    NotFound,
+    InvalidArgs(String),
+}
+
+impl From<std::io::Error> for LiteError {
+    fn from(err: std::io::Error) -> Self {
+        LiteError::Io(err)
+    }
+}
+
+impl std::fmt::Display for LiteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LiteError::Io(e) => write!(f, "I/O error: {}", e),
+            LiteError::Protocol(e) => write!(f, "Protocol error: {}", e),
+            LiteError::NotFound => write!(f, "Key not found"),
+            LiteError::InvalidArgs(e) => write!(f, "Invalid arguments: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LiteError {}
+
+/// Encode a message as one frame
+pub fn encode_frame<T: Serialize>(message: &T) -> Result<Vec<u8>, LiteError> {
+    let payload = serde_json::to_vec(message).map_err(|e| LiteError::Protocol(e.to_string()))?;
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(LiteError::Protocol(format!(
+            "frame of {} bytes exceeds the limit of {} bytes",
+            payload.len(),
+            MAX_FRAME_LEN
+        )));
+    }
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Decode the payload of one frame
+pub fn decode_payload<T: DeserializeOwned>(payload: &[u8]) -> Result<T, LiteError> {
+    serde_json::from_slice(payload).map_err(|e| LiteError::Protocol(e.to_string()))
+}
+
+/// Read one frame from a blocking reader
+pub fn read_frame<T: DeserializeOwned>(reader: &mut impl Read) -> Result<T, LiteError> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(LiteError::Protocol(format!(
+            "frame of {} bytes exceeds the limit of {} bytes",
+            len, MAX_FRAME_LEN
+        )));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    decode_payload(&payload)
+}
+
+/// Blocking client for the persistency service
+pub struct LiteClient {
+    stream: UnixStream,
+    /// Component named in every request
+    component: String,
+    /// Bearer token sent with every request, empty without one
+    token: String,
+}
+
+impl LiteClient {
+    /// Connect to the socket at `PERSISTENCY_LITE_SOCKET` or the default path
+    pub fn connect() -> Result<Self, LiteError> {
+        let path =
+            std::env::var(SOCKET_PATH_ENV).unwrap_or_else(|_| DEFAULT_SOCKET_PATH.to_string());
+        Self::connect_to(path)
+    }
+
+    /// Connect to the socket at `path`
+    ///
+    /// The client names the component of [`identity::current`] and
+    /// authenticates with the token in [`TOKEN_ENV`], if it is set.
+    pub fn connect_to(path: impl AsRef<Path>) -> Result<Self, LiteError> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        Ok(Self {
+            stream,
+            component: identity::current().name.clone(),
+            token: std::env::var(TOKEN_ENV).unwrap_or_default(),
+        })
+    }
+
+    /// Authenticate with `token` rather than the one in [`TOKEN_ENV`]
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = token.to_string();
+        self
+    }
+
+    /// Set a key-value pair
+    pub fn put(&mut self, key: &str, value: &str) -> Result<(), LiteError> {
+        validate_key(key)?;
+        let response = self.call(&LiteRequest::Put {
+            key: key.to_string(),
+            value: value.to_string(),
+        })?;
+        if response.success {
+            Ok(())
+        } else {
+            Err(LiteError::InvalidArgs(response.error_message))
+        }
+    }
+
+    /// Get a value by key
+    pub fn get(&mut self, key: &str) -> Result<String, LiteError> {
+        if key.is_empty() {
+            return Err(LiteError::InvalidArgs("Key cannot be empty".to_string()));
+        }
+        validate_key(key)?;
+        let response = self.call(&LiteRequest::Get {
+            key: key.to_string(),
+        })?;
+        match response.value {
+            Some(value) if response.success => Ok(value),
+            _ => Err(LiteError::NotFound),
+        }
+    }
+
+    /// Delete a key
+    pub fn delete(&mut self, key: &str) -> Result<(), LiteError> {
+        validate_key(key)?;
+        let response = self.call(&LiteRequest::Delete {
+            key: key.to_string(),
+        })?;
+        if response.success {
+            Ok(())
+        } else {
+            Err(LiteError::InvalidArgs(response.error_message))
+        }
+    }
+
+    fn call(&mut self, request: &LiteRequest) -> Result<LiteResponse, LiteError> {
+        let frame = LiteFrame {
+            request: request.clone(),
+            component: self.component.clone(),
+            token: self.token.clone(),
+        };
+        self.stream.write_all(&encode_frame(&frame)?)?;
+        read_frame(&mut self.stream)
+    }
+}
+
+/// Same key rules as the gRPC client
+fn validate_key(key: &str) -> Result<(), LiteError> {
+    if key.len() > 1024 {
+        return Err(LiteError::InvalidArgs(
+            "Key exceeds maximum allowed length of 1024 characters".to_string(),
+        ));
+    }
+    if key.contains(['<', '>', '?', '{', '}']) {
+        return Err(LiteError::InvalidArgs(
+            "Key contains invalid special characters".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let request = LiteRequest::Put {
+            key: "vehicle/mode".to_string(),
+            value: "autonomous".to_string(),
+        };
+        let frame = encode_frame(&request).unwrap();
+        assert_eq!(&frame[..4], &((frame.len() - 4) as u32).to_be_bytes());
+
+        let decoded: LiteRequest = read_frame(&mut Cursor::new(frame)).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    #[test]
+    fn test_request_wire_format() {
+        let request = LiteRequest::Get {
+            key: "vehicle/mode".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"op":"get","key":"vehicle/mode"}"#
+        );
+        let response: LiteResponse = decode_payload(br#"{"success":true}"#).unwrap();
+        assert_eq!(response, LiteResponse::ok(None));
+
+        let frame = LiteFrame {
+            request,
+            component: "apiserver".to_string(),
+            token: "3f9c2a".to_string(),
+        };
+        let encoded = serde_json::to_string(&frame).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"op":"get","key":"vehicle/mode","component":"apiserver","token":"3f9c2a"}"#
+        );
+        assert_eq!(decode_payload::<LiteFrame>(encoded.as_bytes()).unwrap(), frame);
+        // Frames of clients without credentials
+        let anonymous: LiteFrame = decode_payload(br#"{"op":"delete","key":"k"}"#).unwrap();
+        assert!(anonymous.component.is_empty() && anonymous.token.is_empty());
+    }
+
+    #[test]
+    fn test_oversized_frame_rejected() {
+        let mut frame = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(b"{}");
+        let result: Result<LiteResponse, _> = read_frame(&mut Cursor::new(frame));
+        assert!(matches!(result, Err(LiteError::Protocol(_))));
+    }
+
+    #[test]
+    fn test_client_roundtrip_over_socket() {
+        let (client_end, mut server_end) = UnixStream::pair().unwrap();
+        let server = std::thread::spawn(move || {
+            let frame: LiteFrame = read_frame(&mut server_end).unwrap();
+            assert_eq!(
+                frame.request,
+                LiteRequest::Get {
+                    key: "vehicle/mode".to_string()
+                }
+            );
+            assert_eq!(frame.component, "apiserver");
+            assert_eq!(frame.token, "3f9c2a");
+            let response = LiteResponse::ok(Some("manual".to_string()));
+            server_end
+                .write_all(&encode_frame(&response).unwrap())
+                .unwrap();
+        });
+
+        let mut client = LiteClient {
+            stream: client_end,
+            component: "apiserver".to_string(),
+            token: String::new(),
+        }
+        .with_token("3f9c2a");
+        assert_eq!(client.get("vehicle/mode").unwrap(), "manual");
+        assert!(matches!(
+            client.put("bad{key}", "x"),
+            Err(LiteError::InvalidArgs(_))
+        ));
+        server.join().unwrap();
+    }
+}
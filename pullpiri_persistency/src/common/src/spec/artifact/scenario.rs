@@ -19,6 +19,10 @@ impl Scenario {
     pub fn get_targets(&self) -> String {
         self.spec.target.clone()
     }
+
+    pub fn get_schedule(&self) -> Option<Schedule> {
+        self.spec.schedule.clone()
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
@@ -26,6 +30,53 @@ pub struct ScenarioSpec {
     condition: Option<Condition>,
     action: String,
     target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedule: Option<Schedule>,
+}
+
+/// Activation window of a scenario
+///
+/// The scenario is only active between `start` and `end` (RFC 3339
+/// timestamps), and if `recurring` is set only for `durationSeconds` after
+/// every time matching the `cron` expression (UTC).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Schedule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recurring: Option<Recurrence>,
+}
+
+impl Schedule {
+    pub fn get_start(&self) -> Option<String> {
+        self.start.clone()
+    }
+
+    pub fn get_end(&self) -> Option<String> {
+        self.end.clone()
+    }
+
+    pub fn get_recurring(&self) -> Option<Recurrence> {
+        self.recurring.clone()
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Recurrence {
+    cron: String,
+    durationSeconds: u64,
+}
+
+impl Recurrence {
+    pub fn get_cron(&self) -> String {
+        self.cron.clone()
+    }
+
+    pub fn get_duration_seconds(&self) -> u64 {
+        self.durationSeconds
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
@@ -103,6 +154,7 @@ mod tests {
                 }),
                 action: "start".to_string(),
                 target: "model-1".to_string(),
+                schedule: None,
             },
             status: Some(ScenarioStatus {
                 state: ScenarioState::None,
@@ -153,6 +205,7 @@ mod tests {
                 condition: None,
                 action: "stop".to_string(),
                 target: "model-2".to_string(),
+                schedule: None,
             },
             status: None,
         };
@@ -221,6 +274,7 @@ mod tests {
             }),
             action: "scale".to_string(),
             target: "deployment".to_string(),
+            schedule: None,
         };
 
         let serialized = serde_json::to_string(&spec).unwrap();
@@ -244,4 +298,37 @@ mod tests {
         let cloned = condition.clone();
         assert_eq!(condition, cloned);
     }
+
+    #[test]
+    fn test_scenario_schedule() {
+        let scenario: Scenario = serde_yaml::from_str(
+            r#"apiVersion: v1
+kind: Scenario
+metadata:
+  name: night-mode
+spec:
+  condition:
+  action: update
+  target: night-mode
+  schedule:
+    start: "2026-01-01T00:00:00Z"
+    recurring:
+      cron: "0 22 * * *"
+      durationSeconds: 28800
+"#,
+        )
+        .unwrap();
+
+        let schedule = scenario.get_schedule().unwrap();
+        assert_eq!(schedule.get_start(), Some("2026-01-01T00:00:00Z".to_string()));
+        assert_eq!(schedule.get_end(), None);
+        let recurring = schedule.get_recurring().unwrap();
+        assert_eq!(recurring.get_cron(), "0 22 * * *");
+        assert_eq!(recurring.get_duration_seconds(), 28800);
+
+        // Scenarios without a schedule serialize as before
+        assert!(create_test_scenario().get_schedule().is_none());
+        let yaml = serde_yaml::to_string(&create_test_scenario()).unwrap();
+        assert!(!yaml.contains("schedule"));
+    }
 }
@@ -0,0 +1,1187 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Backward compatibility of the persistency proto definitions
+//!
+//! Golden messages are serialized messages recorded with the definitions of a
+//! release, one file per message in `tests/golden/<release>/`. `0.1.0` holds
+//! the messages of the first release, `v<API_VERSION>` the ones added or
+//! changed in that [`API_VERSION`], with `v1` covering the changes before the
+//! API got versioned. Every golden message of every release has to decode
+//! with the current definitions and encode back to the same fields. A removed
+//! or renumbered field, or a field whose type changed incompatibly, makes the
+//! round trip lose or change data and fails the test, an added field does not.
+//!
+//! New messages and fields are registered in [`golden_messages!`] with a
+//! sample that sets every field. Samples no release has recorded yet are
+//! recorded in the directory of the current API version with
+//!
+//! ```text
+//! PROTO_GOLDEN_RECORD=1 cargo test --test proto_compat
+//! ```
+//!
+//! Recorded golden messages are never overwritten, the ones of a release stay
+//! as that release sent them. Adding a field therefore goes with raising
+//! [`API_VERSION`].
+
+#![cfg(feature = "grpc")]
+
+use common::persistency_proto::*;
+use prost::Message;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Environment variable enabling the recording of missing golden messages
+const RECORD_ENV: &str = "PROTO_GOLDEN_RECORD";
+
+/// Registered golden message, named `<proto message>.<case>`
+struct Golden {
+    name: String,
+    check: fn(&[u8]) -> Result<(), String>,
+    sample: Box<dyn Fn() -> Vec<u8>>,
+}
+
+/// Register the golden messages with a sample each
+///
+/// ```text
+/// golden_messages! {
+///     SetValueRequest as "persistency.SetValueRequest" {
+///         "string" => SetValueRequest { key: ..., value: ..., ttl_seconds: 0, namespace: ... },
+///     }
+/// }
+/// ```
+macro_rules! golden_messages {
+    ($($ty:ty as $message:literal { $($case:literal => $sample:expr),+ $(,)? })+) => {
+        fn registered() -> Vec<Golden> {
+            vec![$($(
+                Golden {
+                    name: format!("{}.{}", $message, $case),
+                    check: round_trip::<$ty>,
+                    sample: Box::new(|| {
+                        let sample: $ty = $sample;
+                        sample.encode_to_vec()
+                    }),
+                },
+            )+)+]
+        }
+    };
+}
+
+golden_messages! {
+    KvsValue as "persistency.KvsValue" {
+        "i32" => value(kvs_value::Value::I32Value(-42)),
+        "u32" => value(kvs_value::Value::U32Value(42)),
+        "i64" => value(kvs_value::Value::I64Value(-1_700_000_000_000)),
+        "u64" => value(kvs_value::Value::U64Value(u64::MAX)),
+        "f64" => value(kvs_value::Value::F64Value(12.5)),
+        "boolean" => value(kvs_value::Value::BooleanValue(true)),
+        "string" => string("manual"),
+        "null" => value(kvs_value::Value::NullValue(NullValue {})),
+        "array" => value(kvs_value::Value::ArrayValue(KvsArray {
+            values: vec![string("A9"), value(kvs_value::Value::U32Value(3))],
+        })),
+        "object" => value(kvs_value::Value::ObjectValue(KvsObject {
+            values: HashMap::from([
+                ("gear".to_string(), value(kvs_value::Value::U32Value(3))),
+                ("mode".to_string(), string("manual")),
+            ]),
+        })),
+        "bytes" => value(kvs_value::Value::BytesValue(vec![0x1f, 0x8b, 0x08, 0x00])),
+    }
+    SetValueRequest as "persistency.SetValueRequest" {
+        "string" => SetValueRequest {
+            key: "vehicle/mode".to_string(),
+            value: Some(string("manual")),
+            ttl_seconds: 0,
+            namespace: String::new(),
+            expected_revision: 0,
+            lease_id: 0,
+        },
+        "namespace" => SetValueRequest {
+            key: "scenario/helloworld".to_string(),
+            value: Some(string("apiVersion: v1")),
+            ttl_seconds: 0,
+            namespace: "artifacts".to_string(),
+            expected_revision: 0,
+            lease_id: 0,
+        },
+        "ttl" => SetValueRequest {
+            key: "discovery/heartbeat/actioncontroller".to_string(),
+            value: Some(value(kvs_value::Value::U64Value(1_700_000_000_000))),
+            ttl_seconds: 30,
+            namespace: String::new(),
+            expected_revision: 0,
+            lease_id: 0,
+        },
+        "expected_revision" => SetValueRequest {
+            key: "scenario/helloworld".to_string(),
+            value: Some(string("apiVersion: v1")),
+            ttl_seconds: 0,
+            namespace: String::new(),
+            expected_revision: 17,
+            lease_id: 0,
+        },
+        "lease" => SetValueRequest {
+            key: "presence/actioncontroller".to_string(),
+            value: Some(string("node-1")),
+            ttl_seconds: 0,
+            namespace: String::new(),
+            expected_revision: 0,
+            lease_id: 7_046_832_113_920_518_417,
+        },
+    }
+    SetValueResponse as "persistency.SetValueResponse" {
+        "error" => SetValueResponse {
+            success: false,
+            error_message: "permission denied".to_string(),
+            revision: 0,
+        },
+        "conflict" => SetValueResponse {
+            success: false,
+            error_message: "Revision conflict: expected 17, current 18".to_string(),
+            revision: 18,
+        },
+    }
+    GetValueRequest as "persistency.GetValueRequest" {
+        "key" => GetValueRequest {
+            key: "vehicle/mode".to_string(),
+            namespace: String::new(),
+        },
+    }
+    GetValueResponse as "persistency.GetValueResponse" {
+        "value" => GetValueResponse {
+            success: true,
+            value: Some(string("manual")),
+            error_message: String::new(),
+            revision: 0,
+        },
+        "error" => GetValueResponse {
+            success: false,
+            value: None,
+            error_message: "key not found".to_string(),
+            revision: 0,
+        },
+        "revision" => GetValueResponse {
+            success: true,
+            value: Some(string("manual")),
+            error_message: String::new(),
+            revision: 18,
+        },
+    }
+    RemoveKeyRequest as "persistency.RemoveKeyRequest" {
+        "key" => RemoveKeyRequest {
+            key: "vehicle/mode".to_string(),
+            namespace: String::new(),
+            trash: false,
+        },
+        "trash" => RemoveKeyRequest {
+            key: "Scenario/helloworld".to_string(),
+            namespace: "artifacts".to_string(),
+            trash: true,
+        },
+    }
+    RemoveKeyResponse as "persistency.RemoveKeyResponse" {
+        "error" => RemoveKeyResponse {
+            success: false,
+            error_message: "key not found".to_string(),
+        },
+    }
+    GetAllKeysResponse as "persistency.GetAllKeysResponse" {
+        "keys" => GetAllKeysResponse {
+            success: true,
+            keys: vec!["vehicle/mode".to_string(), "vehicle/route".to_string()],
+            error_message: String::new(),
+        },
+    }
+    GetTtlRequest as "persistency.GetTtlRequest" {
+        "key" => GetTtlRequest {
+            key: "vehicle/mode".to_string(),
+            namespace: String::new(),
+        },
+    }
+    GetTtlResponse as "persistency.GetTtlResponse" {
+        "remaining" => GetTtlResponse {
+            success: true,
+            has_ttl: true,
+            remaining_ms: 12_500,
+            error_message: String::new(),
+        },
+    }
+    KeyExistsRequest as "persistency.KeyExistsRequest" {
+        "key" => KeyExistsRequest {
+            key: "vehicle/mode".to_string(),
+            namespace: String::new(),
+        },
+    }
+    KeyExistsResponse as "persistency.KeyExistsResponse" {
+        "exists" => KeyExistsResponse {
+            success: true,
+            exists: true,
+            error_message: String::new(),
+        },
+    }
+    GetAllWithPrefixRequest as "persistency.GetAllWithPrefixRequest" {
+        "prefix" => GetAllWithPrefixRequest {
+            prefix: "vehicle/".to_string(),
+            namespace: String::new(),
+        },
+    }
+    GetAllWithPrefixResponse as "persistency.GetAllWithPrefixResponse" {
+        "entries" => GetAllWithPrefixResponse {
+            success: true,
+            key_values: HashMap::from([
+                ("vehicle/mode".to_string(), string("manual")),
+                ("vehicle/route".to_string(), string("A9")),
+            ]),
+            error_message: String::new(),
+        },
+    }
+    ResetRequest as "persistency.ResetRequest" {
+        "namespace" => ResetRequest { namespace: "runtime".to_string() },
+    }
+    ResetResponse as "persistency.ResetResponse" {
+        "error" => ResetResponse {
+            success: false,
+            error_message: "permission denied".to_string(),
+            pending: None,
+        },
+        "pending" => ResetResponse {
+            success: true,
+            error_message: String::new(),
+            pending: Some(pending_operation()),
+        },
+    }
+    FlushResponse as "persistency.FlushResponse" {
+        "error" => FlushResponse {
+            success: false,
+            error_message: "storage full".to_string(),
+        },
+    }
+    FlushPrefixRequest as "persistency.FlushPrefixRequest" {
+        "prefix" => FlushPrefixRequest {
+            prefix: "vehicle/".to_string(),
+            namespace: String::new(),
+        },
+    }
+    FlushPrefixResponse as "persistency.FlushPrefixResponse" {
+        "flushed" => FlushPrefixResponse {
+            success: true,
+            flushed_keys: 2,
+            error_message: String::new(),
+        },
+    }
+    SetValuesRequest as "persistency.SetValuesRequest" {
+        "entries" => SetValuesRequest {
+            entries: vec![
+                KeyValue {
+                    key: "vehicle/mode".to_string(),
+                    value: Some(string("manual")),
+                },
+                KeyValue {
+                    key: "vehicle/gear".to_string(),
+                    value: Some(value(kvs_value::Value::U32Value(3))),
+                },
+            ],
+            namespace: String::new(),
+        },
+    }
+    SetValuesResponse as "persistency.SetValuesResponse" {
+        "error" => SetValuesResponse {
+            success: false,
+            written_keys: 1,
+            error_message: "storage full".to_string(),
+        },
+    }
+    GetValuesRequest as "persistency.GetValuesRequest" {
+        "keys" => GetValuesRequest {
+            keys: vec!["vehicle/mode".to_string(), "vehicle/route".to_string()],
+            namespace: String::new(),
+        },
+    }
+    GetValuesResponse as "persistency.GetValuesResponse" {
+        "values" => GetValuesResponse {
+            success: true,
+            key_values: HashMap::from([("vehicle/mode".to_string(), string("manual"))]),
+            missing_keys: vec!["vehicle/route".to_string()],
+            error_message: String::new(),
+        },
+    }
+    RemoveKeysRequest as "persistency.RemoveKeysRequest" {
+        "keys" => RemoveKeysRequest {
+            keys: vec!["vehicle/mode".to_string(), "vehicle/route".to_string()],
+            namespace: String::new(),
+        },
+    }
+    RemoveKeysResponse as "persistency.RemoveKeysResponse" {
+        "removed" => RemoveKeysResponse {
+            success: true,
+            removed_keys: 1,
+            missing_keys: vec!["vehicle/route".to_string()],
+            error_message: String::new(),
+        },
+    }
+    CompareAndSetRequest as "persistency.CompareAndSetRequest" {
+        "swap" => CompareAndSetRequest {
+            key: "vehicle/mode".to_string(),
+            expected_value: Some(string("manual")),
+            new_value: Some(string("autonomous")),
+            namespace: String::new(),
+        },
+        "create" => CompareAndSetRequest {
+            key: "vehicle/mode".to_string(),
+            expected_value: None,
+            new_value: Some(string("manual")),
+            namespace: String::new(),
+        },
+    }
+    CompareAndSetResponse as "persistency.CompareAndSetResponse" {
+        "mismatch" => CompareAndSetResponse {
+            success: true,
+            swapped: false,
+            current_value: Some(string("emergency")),
+            error_message: String::new(),
+        },
+    }
+    IncrementRequest as "persistency.IncrementRequest" {
+        "delta" => IncrementRequest {
+            key: "statemanager/restarts".to_string(),
+            delta: Some(value(kvs_value::Value::I64Value(1))),
+            namespace: String::new(),
+        },
+    }
+    IncrementResponse as "persistency.IncrementResponse" {
+        "value" => IncrementResponse {
+            success: true,
+            value: Some(value(kvs_value::Value::U32Value(42))),
+            error_message: String::new(),
+        },
+    }
+    DecrementRequest as "persistency.DecrementRequest" {
+        "delta" => DecrementRequest {
+            key: "apiserver/active_scenarios".to_string(),
+            delta: Some(value(kvs_value::Value::I32Value(2))),
+            namespace: String::new(),
+        },
+    }
+    DecrementResponse as "persistency.DecrementResponse" {
+        "value" => DecrementResponse {
+            success: true,
+            value: Some(value(kvs_value::Value::I32Value(3))),
+            error_message: String::new(),
+        },
+    }
+    GetTraceSamplingResponse as "persistency.GetTraceSamplingResponse" {
+        "config" => GetTraceSamplingResponse {
+            success: true,
+            config: Some(trace_sampling()),
+            error_message: String::new(),
+        },
+    }
+    SetTraceSamplingRequest as "persistency.SetTraceSamplingRequest" {
+        "config" => SetTraceSamplingRequest { config: Some(trace_sampling()) },
+    }
+    SetTraceSamplingResponse as "persistency.SetTraceSamplingResponse" {
+        "error" => SetTraceSamplingResponse {
+            success: false,
+            error_message: "invalid read rate".to_string(),
+        },
+    }
+    DumpStateForDiagnosticsRequest as "persistency.DumpStateForDiagnosticsRequest" {
+        "keys" => DumpStateForDiagnosticsRequest {
+            include_keys: true,
+            audit_events: 20,
+        },
+    }
+    DumpStateForDiagnosticsResponse as "persistency.DumpStateForDiagnosticsResponse" {
+        "state" => DumpStateForDiagnosticsResponse {
+            success: true,
+            state_json: r#"{"keys":2}"#.to_string(),
+            error_message: String::new(),
+        },
+    }
+    GetServiceInfoResponse as "persistency.GetServiceInfoResponse" {
+        "upload" => GetServiceInfoResponse {
+            success: true,
+            version: "0.1.0".to_string(),
+            uptime_secs: 3600,
+            upload: Some(UploadStatus {
+                enabled: true,
+                endpoint: "https://backup.example.com/fleet".to_string(),
+                last_success_ms: 1_700_000_000_000,
+                last_attempt_ms: 1_700_000_060_000,
+                last_error: "connection refused".to_string(),
+                consecutive_failures: 1,
+                uploads: 12,
+                differential_uploads: 10,
+            }),
+            error_message: String::new(),
+            recovery: None,
+            pending_operations: Vec::new(),
+        },
+        "recovery" => GetServiceInfoResponse {
+            success: true,
+            version: "0.1.0".to_string(),
+            uptime_secs: 60,
+            upload: None,
+            error_message: String::new(),
+            recovery: Some(StorageRecovery {
+                recovered: true,
+                reason: "Checksum file missing".to_string(),
+                restored_snapshot: 1,
+                preserved_files: vec!["kvs_0_0.json.damaged-1700000000000".to_string()],
+                recovered_at_ms: 1_700_000_000_000,
+            }),
+            pending_operations: Vec::new(),
+        },
+        "pending_operations" => GetServiceInfoResponse {
+            success: true,
+            version: "0.1.0".to_string(),
+            uptime_secs: 60,
+            upload: None,
+            error_message: String::new(),
+            recovery: None,
+            pending_operations: vec![pending_operation()],
+        },
+    }
+    GetPrefixStatsRequest as "persistency.GetPrefixStatsRequest" {
+        "prefix" => GetPrefixStatsRequest { prefix: "telemetry/".to_string() },
+    }
+    GetPrefixStatsResponse as "persistency.GetPrefixStatsResponse" {
+        "stats" => GetPrefixStatsResponse {
+            success: true,
+            prefixes: vec![PrefixStats {
+                prefix: "telemetry/".to_string(),
+                keys: 100,
+                stored_bytes: 6_400,
+                written_bytes: 12_800,
+                write_bytes_per_day: 25_600.0,
+                growth_bytes_per_day: 6_400.0,
+                days_until_full: 160.5,
+            }],
+            total: Some(StorageForecast {
+                stored_bytes: 8_192,
+                limit_bytes: 1_048_576,
+                limit: "quota".to_string(),
+                growth_bytes_per_day: 6_400.0,
+                days_until_full: 162.5,
+                window_secs: 86_400,
+            }),
+            error_message: String::new(),
+        },
+    }
+    GetUsageReportsRequest as "persistency.GetUsageReportsRequest" {
+        "current" => GetUsageReportsRequest {
+            namespace: "telemetry/".to_string(),
+            limit: 24,
+            include_current: true,
+        },
+    }
+    GetUsageReportsResponse as "persistency.GetUsageReportsResponse" {
+        "reports" => GetUsageReportsResponse {
+            success: true,
+            reports: vec![UsageReport {
+                period_start_ms: 1_700_000_000_000,
+                period_end_ms: 1_700_003_600_000,
+                complete: true,
+                namespaces: vec![NamespaceUsage {
+                    namespace: "telemetry/".to_string(),
+                    reads: 120,
+                    writes: 360,
+                    removes: 2,
+                    bytes_read: 7_680,
+                    bytes_written: 23_040,
+                    keys: 100,
+                    stored_bytes: 6_400,
+                    top_keys: vec![KeyUsage {
+                        key: "telemetry/speed".to_string(),
+                        operations: 240,
+                        bytes_read: 3_840,
+                        bytes_written: 11_520,
+                    }],
+                }],
+            }],
+            error_message: String::new(),
+        },
+    }
+    SetFaultInjectionRequest as "persistency.SetFaultInjectionRequest" {
+        "config" => SetFaultInjectionRequest { config: Some(fault_injection()) },
+    }
+    SetFaultInjectionResponse as "persistency.SetFaultInjectionResponse" {
+        "active" => SetFaultInjectionResponse {
+            success: true,
+            active: Some(fault_injection()),
+            error_message: String::new(),
+        },
+    }
+    WatchRequest as "persistency.WatchRequest" {
+        "prefix" => WatchRequest {
+            target: Some(watch_request::Target::Prefix("vehicle/".to_string())),
+            namespace: String::new(),
+            filters: vec![],
+            prev_value: false,
+        },
+        "namespace" => WatchRequest {
+            target: Some(watch_request::Target::Key("filtergateway/vehicle_mode".to_string())),
+            namespace: "runtime".to_string(),
+            filters: vec![],
+            prev_value: false,
+        },
+        "filtered" => WatchRequest {
+            target: Some(watch_request::Target::Key("Vehicle/Mode".to_string())),
+            namespace: String::new(),
+            filters: vec![WatchFilter::Nodelete as i32],
+            prev_value: true,
+        },
+    }
+    WatchResponse as "persistency.WatchResponse" {
+        "put" => WatchResponse {
+            r#type: WatchEventType::Put as i32,
+            key: "vehicle/speed".to_string(),
+            value: Some(value(kvs_value::Value::F64Value(42.5))),
+            revision: 17,
+            dropped: 0,
+            prev_value: None,
+            changed_at_ms: 0,
+        },
+        "previous" => WatchResponse {
+            r#type: WatchEventType::Put as i32,
+            key: "Vehicle/Mode".to_string(),
+            value: Some(string("autonomous")),
+            revision: 18,
+            dropped: 0,
+            prev_value: Some(string("manual")),
+            changed_at_ms: 0,
+        },
+        "changed_at" => WatchResponse {
+            r#type: WatchEventType::Delete as i32,
+            key: "Vehicle/Mode".to_string(),
+            value: None,
+            revision: 19,
+            dropped: 0,
+            prev_value: None,
+            changed_at_ms: 1_700_000_000_000,
+        },
+        "lagged" => WatchResponse {
+            r#type: WatchEventType::Lagged as i32,
+            key: String::new(),
+            value: None,
+            revision: 0,
+            dropped: 12,
+            prev_value: None,
+            changed_at_ms: 0,
+        },
+    }
+    GetValueStreamRequest as "persistency.GetValueStreamRequest" {
+        "chunk_size" => GetValueStreamRequest {
+            key: "Package/helloworld".to_string(),
+            namespace: "artifacts".to_string(),
+            chunk_size: 16384,
+        },
+    }
+    GetValueStreamResponse as "persistency.GetValueStreamResponse" {
+        "first" => GetValueStreamResponse {
+            chunk: b"apiVersion: v1\nkind: Package\n".to_vec(),
+            total_size: 204800,
+        },
+    }
+    SetLargeValueRequest as "persistency.SetLargeValueRequest" {
+        "first" => SetLargeValueRequest {
+            key: "recorder/drive-17".to_string(),
+            namespace: "recordings".to_string(),
+            ttl_seconds: 86400,
+            binary: true,
+            total_size: 8 * 1024 * 1024,
+            chunk: vec![0x1f, 0x8b, 0x08, 0x00],
+        },
+        "next" => SetLargeValueRequest {
+            key: String::new(),
+            namespace: String::new(),
+            ttl_seconds: 0,
+            binary: false,
+            total_size: 0,
+            chunk: vec![0x2a; 16],
+        },
+    }
+    CancelPendingOperationRequest as "persistency.CancelPendingOperationRequest" {
+        "id" => CancelPendingOperationRequest { id: 3 },
+    }
+    CancelPendingOperationResponse as "persistency.CancelPendingOperationResponse" {
+        "cancelled" => CancelPendingOperationResponse {
+            success: true,
+            error_message: String::new(),
+            cancelled: Some(pending_operation()),
+        },
+    }
+    SetLargeValueResponse as "persistency.SetLargeValueResponse" {
+        "failure" => SetLargeValueResponse {
+            success: false,
+            error_message: "Value of 8388608 bytes exceeds the limit of 4194304 bytes".to_string(),
+        },
+    }
+    GetLargeValueRequest as "persistency.GetLargeValueRequest" {
+        "chunk_size" => GetLargeValueRequest {
+            key: "recorder/drive-17".to_string(),
+            namespace: "recordings".to_string(),
+            chunk_size: 65536,
+        },
+    }
+    GetLargeValueResponse as "persistency.GetLargeValueResponse" {
+        "first" => GetLargeValueResponse {
+            chunk: vec![0x1f, 0x8b, 0x08, 0x00],
+            total_size: 8 * 1024 * 1024,
+            binary: true,
+        },
+    }
+    ScanRequest as "persistency.ScanRequest" {
+        "batch_size" => ScanRequest {
+            prefix: "Scenario/".to_string(),
+            namespace: "artifacts".to_string(),
+            batch_size: 50,
+        },
+    }
+    ScanResponse as "persistency.ScanResponse" {
+        "entries" => ScanResponse {
+            entries: vec![
+                KeyValue {
+                    key: "Scenario/antipinch-enable".to_string(),
+                    value: Some(string("apiVersion: v1\nkind: Scenario\n")),
+                },
+                KeyValue {
+                    key: "Scenario/helloworld".to_string(),
+                    value: Some(string("apiVersion: v1\nkind: Scenario\n")),
+                },
+            ],
+        },
+    }
+    GetCapabilitiesResponse as "persistency.GetCapabilitiesResponse" {
+        "features" => GetCapabilitiesResponse {
+            success: true,
+            api_version: 2,
+            features: vec!["watch".to_string(), "namespaces".to_string()],
+            version: "0.1.0".to_string(),
+            error_message: String::new(),
+        },
+    }
+    RestoreRequest as "persistency.RestoreRequest" {
+        "snapshot" => RestoreRequest {
+            source: Some(restore_request::Source::SnapshotId(2)),
+            strategy: ConflictStrategy::NewestWins as i32,
+            increments_json: Vec::new(),
+        },
+        "export" => RestoreRequest {
+            source: Some(restore_request::Source::ExportJson(r#"{"format_version":1}"#.to_string())),
+            strategy: ConflictStrategy::Overwrite as i32,
+            increments_json: vec![r#"{"format_version":1,"base_revision":3}"#.to_string()],
+        },
+    }
+    GetProtoDescriptorResponse as "persistency.GetProtoDescriptorResponse" {
+        "descriptor" => GetProtoDescriptorResponse {
+            success: true,
+            file_descriptor_set: vec![0x0a, 0x13, 0x0a, 0x11],
+            service: "persistency.PersistencyService".to_string(),
+            error_message: String::new(),
+        },
+    }
+    RestoreResponse as "persistency.RestoreResponse" {
+        "conflicts" => RestoreResponse {
+            success: true,
+            restored_keys: 3,
+            unchanged_keys: 1,
+            skipped_keys: 1,
+            conflicts: vec![KeyConflict {
+                key: "vehicle/mode".to_string(),
+                resolution: "skipped".to_string(),
+                stored_ms: 1_700_000_060_000,
+                restored_ms: 1_700_000_000_000,
+            }],
+            error_message: String::new(),
+        },
+    }
+    RestoreSnapshotRequest as "persistency.RestoreSnapshotRequest" {
+        "first" => RestoreSnapshotRequest {
+            dry_run: true,
+            total_size: 2 * 1024 * 1024,
+            chunk: br#"{"format_version":1,"#.to_vec(),
+        },
+        "next" => RestoreSnapshotRequest {
+            dry_run: false,
+            total_size: 0,
+            chunk: br#""entries":{}}"#.to_vec(),
+        },
+    }
+    RestoreSnapshotResponse as "persistency.RestoreSnapshotResponse" {
+        "dry_run" => RestoreSnapshotResponse {
+            success: true,
+            applied: false,
+            prefixes: vec!["Scenario/".to_string(), "Package/".to_string()],
+            added_keys: 2,
+            replaced_keys: 5,
+            removed_keys: 1,
+            unchanged_keys: 40,
+            error_message: String::new(),
+        },
+    }
+    ListBackupsResponse as "persistency.ListBackupsResponse" {
+        "backups" => ListBackupsResponse {
+            success: true,
+            backups: vec![backup_file()],
+            directory: "/var/lib/piccolo/backups".to_string(),
+            error_message: String::new(),
+        },
+    }
+    CreateBackupResponse as "persistency.CreateBackupResponse" {
+        "rotated" => CreateBackupResponse {
+            success: true,
+            backup: Some(backup_file()),
+            keys: 120,
+            removed: vec!["backup-1699395200000.json".to_string()],
+            error_message: String::new(),
+        },
+    }
+    ExportToFileRequest as "persistency.ExportToFileRequest" {
+        "yaml" => ExportToFileRequest {
+            format: DumpFormat::Yaml as i32,
+            prefix: "Scenario/".to_string(),
+            file_name: "scenarios.yaml".to_string(),
+        },
+    }
+    ExportToFileResponse as "persistency.ExportToFileResponse" {
+        "written" => ExportToFileResponse {
+            success: true,
+            file_name: "scenarios.yaml".to_string(),
+            keys: 12,
+            size_bytes: 4096,
+            directory: "/var/lib/piccolo/dumps".to_string(),
+            error_message: String::new(),
+        },
+    }
+    ImportFromFileRequest as "persistency.ImportFromFileRequest" {
+        "etcd" => ImportFromFileRequest {
+            file_name: "etcd-dump.json".to_string(),
+            strategy: ConflictStrategy::SkipExisting as i32,
+        },
+    }
+    ImportFromFileResponse as "persistency.ImportFromFileResponse" {
+        "conflicts" => ImportFromFileResponse {
+            success: true,
+            source_format: "etcd".to_string(),
+            restored_keys: 30,
+            unchanged_keys: 2,
+            skipped_keys: 1,
+            conflicts: vec![KeyConflict {
+                key: "Package/helloworld".to_string(),
+                resolution: "skipped".to_string(),
+                stored_ms: 1_700_000_060_000,
+                restored_ms: 1_700_000_000_000,
+            }],
+            error_message: String::new(),
+        },
+    }
+    MigrateFromEtcdRequest as "persistency.MigrateFromEtcdRequest" {
+        "prefix" => MigrateFromEtcdRequest {
+            endpoint: "http://10.0.0.5:2379".to_string(),
+            prefix: "Scenario/".to_string(),
+            strategy: ConflictStrategy::Overwrite as i32,
+        },
+    }
+    MigrateFromEtcdResponse as "persistency.MigrateFromEtcdResponse" {
+        "migrated" => MigrateFromEtcdResponse {
+            success: true,
+            read_keys: 42,
+            etcd_revision: 1_234,
+            restored_keys: 40,
+            unchanged_keys: 2,
+            skipped_keys: 0,
+            conflicts: vec![KeyConflict {
+                key: "Scenario/helloworld".to_string(),
+                resolution: "overwritten".to_string(),
+                stored_ms: 1_700_000_000_000,
+                restored_ms: 1_700_000_060_000,
+            }],
+            error_message: String::new(),
+        },
+    }
+    GetHistoryRequest as "persistency.GetHistoryRequest" {
+        "key" => GetHistoryRequest {
+            key: "Scenario/helloworld".to_string(),
+            namespace: "artifacts".to_string(),
+        },
+    }
+    GetHistoryResponse as "persistency.GetHistoryResponse" {
+        "versions" => GetHistoryResponse {
+            success: true,
+            versions: vec![
+                key_version(21, None),
+                key_version(18, Some(string("apiVersion: v1"))),
+            ],
+            error_message: String::new(),
+        },
+    }
+    GetValueAtRevisionRequest as "persistency.GetValueAtRevisionRequest" {
+        "revision" => GetValueAtRevisionRequest {
+            key: "Scenario/helloworld".to_string(),
+            revision: 20,
+            namespace: "artifacts".to_string(),
+        },
+    }
+    GetValueAtRevisionResponse as "persistency.GetValueAtRevisionResponse" {
+        "version" => GetValueAtRevisionResponse {
+            success: true,
+            version: Some(key_version(18, Some(string("apiVersion: v1")))),
+            error_message: String::new(),
+        },
+    }
+    ListDeletedRequest as "persistency.ListDeletedRequest" {
+        "prefix" => ListDeletedRequest {
+            prefix: "Scenario/".to_string(),
+            namespace: "artifacts".to_string(),
+        },
+    }
+    ListDeletedResponse as "persistency.ListDeletedResponse" {
+        "keys" => ListDeletedResponse {
+            success: true,
+            keys: vec![DeletedKey {
+                key: "Scenario/helloworld".to_string(),
+                namespace: "artifacts".to_string(),
+                deleted_at_ms: 1_700_000_000_000,
+                expires_at_ms: 1_700_604_800_000,
+            }],
+            error_message: String::new(),
+        },
+    }
+    RestoreKeyRequest as "persistency.RestoreKeyRequest" {
+        "key" => RestoreKeyRequest {
+            key: "Scenario/helloworld".to_string(),
+            namespace: "artifacts".to_string(),
+        },
+    }
+    RestoreKeyResponse as "persistency.RestoreKeyResponse" {
+        "restored" => RestoreKeyResponse {
+            success: true,
+            revision: 42,
+            error_message: String::new(),
+        },
+        "conflict" => RestoreKeyResponse {
+            success: false,
+            revision: 0,
+            error_message: "Key Scenario/helloworld was written again since its removal".to_string(),
+        },
+    }
+    GrantLeaseRequest as "persistency.GrantLeaseRequest" {
+        "ttl" => GrantLeaseRequest { ttl_seconds: 10 },
+    }
+    GrantLeaseResponse as "persistency.GrantLeaseResponse" {
+        "granted" => GrantLeaseResponse {
+            success: true,
+            lease_id: 7_046_832_113_920_518_417,
+            ttl_seconds: 10,
+            error_message: String::new(),
+        },
+    }
+    KeepAliveRequest as "persistency.KeepAliveRequest" {
+        "lease" => KeepAliveRequest {
+            lease_id: 7_046_832_113_920_518_417,
+        },
+    }
+    KeepAliveResponse as "persistency.KeepAliveResponse" {
+        "renewed" => KeepAliveResponse {
+            lease_id: 7_046_832_113_920_518_417,
+            ttl_seconds: 10,
+        },
+    }
+    RevokeLeaseRequest as "persistency.RevokeLeaseRequest" {
+        "lease" => RevokeLeaseRequest {
+            lease_id: 7_046_832_113_920_518_417,
+        },
+    }
+    RevokeLeaseResponse as "persistency.RevokeLeaseResponse" {
+        "revoked" => RevokeLeaseResponse {
+            success: true,
+            keys: 2,
+            error_message: String::new(),
+        },
+    }
+    LockRequest as "persistency.LockRequest" {
+        "artifact" => LockRequest {
+            name: "artifact/helloworld".to_string(),
+            ttl_seconds: 30,
+        },
+    }
+    LockResponse as "persistency.LockResponse" {
+        "acquired" => LockResponse {
+            success: true,
+            acquired: true,
+            token: 7_046_832_113_920_518_417,
+            holder: String::new(),
+            error_message: String::new(),
+        },
+        "held" => LockResponse {
+            success: true,
+            acquired: false,
+            token: 0,
+            holder: "apiserver".to_string(),
+            error_message: String::new(),
+        },
+    }
+    UnlockRequest as "persistency.UnlockRequest" {
+        "token" => UnlockRequest {
+            name: "artifact/helloworld".to_string(),
+            token: 7_046_832_113_920_518_417,
+        },
+    }
+    UnlockResponse as "persistency.UnlockResponse" {
+        "released" => UnlockResponse {
+            success: true,
+            error_message: String::new(),
+        },
+    }
+}
+
+fn value(value: kvs_value::Value) -> KvsValue {
+    KvsValue { value: Some(value) }
+}
+
+fn string(value: &str) -> KvsValue {
+    self::value(kvs_value::Value::StringValue(value.to_string()))
+}
+
+fn trace_sampling() -> TraceSamplingConfig {
+    TraceSamplingConfig {
+        read_rate: 0.25,
+        write_rate: 1.0,
+        trace_errors: true,
+    }
+}
+
+fn fault_injection() -> FaultInjectionConfig {
+    FaultInjectionConfig {
+        fail_set_values: 3,
+        read_latency_ms: 250,
+        fail_flushes: true,
+    }
+}
+
+fn pending_operation() -> PendingOperation {
+    PendingOperation {
+        id: 3,
+        operation: "Reset".to_string(),
+        namespace: "runtime".to_string(),
+        caller: "apiserver@vehicle-1".to_string(),
+        requested_at_ms: 1_700_000_000_000,
+        execute_at_ms: 1_700_000_600_000,
+    }
+}
+
+fn backup_file() -> BackupFile {
+    BackupFile {
+        name: "backup-1700000000000.json".to_string(),
+        created_at_ms: 1_700_000_000_000,
+        size_bytes: 48_213,
+    }
+}
+
+fn key_version(revision: u64, value: Option<KvsValue>) -> KeyVersion {
+    KeyVersion {
+        revision,
+        removed: value.is_none(),
+        value,
+        written_at_ms: 1_700_000_000_000 + revision,
+    }
+}
+
+/// Decode `golden` with the current definition and compare the encoded fields
+fn round_trip<T: Message + Default>(golden: &[u8]) -> Result<(), String> {
+    let message = T::decode(golden).map_err(|err| format!("does not decode: {}", err))?;
+    let encoded = message.encode_to_vec();
+    let (before, after) = (canonical(golden)?, canonical(&encoded)?);
+    if before == after {
+        return Ok(());
+    }
+    let numbers = |fields: &[(u64, Vec<u8>)]| {
+        let mut numbers: Vec<u64> = fields.iter().map(|(key, _)| key >> 3).collect();
+        numbers.dedup();
+        numbers
+    };
+    let changed: Vec<u64> = numbers(&before)
+        .into_iter()
+        .filter(|number| {
+            let of = |fields: &[(u64, Vec<u8>)]| -> Vec<Vec<u8>> {
+                fields
+                    .iter()
+                    .filter(|(key, _)| key >> 3 == *number)
+                    .map(|(_, field)| field.clone())
+                    .collect()
+            };
+            of(&before) != of(&after)
+        })
+        .collect();
+    Err(format!("fields {:?} are lost or changed", changed))
+}
+
+/// Fields of a serialized message sorted by key, nested messages included
+///
+/// prost encodes map entries in hash order, sorting makes the field lists of
+/// the same message comparable. Length-delimited fields that parse as a
+/// message are compared as messages, the others as bytes.
+fn canonical(bytes: &[u8]) -> Result<Vec<(u64, Vec<u8>)>, String> {
+    let mut fields = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let key = varint(&mut rest)?;
+        let field = match key & 7 {
+            0 => varint(&mut rest)?.to_le_bytes().to_vec(),
+            1 => take(&mut rest, 8)?.to_vec(),
+            2 => {
+                let len = varint(&mut rest)? as usize;
+                let payload = take(&mut rest, len)?;
+                match canonical(payload) {
+                    Ok(nested) => nested
+                        .into_iter()
+                        .flat_map(|(key, field)| [key.to_le_bytes().to_vec(), field].concat())
+                        .collect(),
+                    Err(_) => payload.to_vec(),
+                }
+            }
+            5 => take(&mut rest, 4)?.to_vec(),
+            wire_type => return Err(format!("unsupported wire type {}", wire_type)),
+        };
+        if key >> 3 == 0 {
+            return Err("field number 0".to_string());
+        }
+        fields.push((key, field));
+    }
+    fields.sort();
+    Ok(fields)
+}
+
+fn varint(bytes: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or("truncated varint")?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("varint too long".to_string())
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if bytes.len() < len {
+        return Err("truncated field".to_string());
+    }
+    let (field, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(field)
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// Golden message files of all releases as (release, name, path)
+fn golden_files() -> Vec<(String, String, PathBuf)> {
+    let mut files = Vec::new();
+    let Ok(releases) = std::fs::read_dir(golden_dir()) else {
+        return files;
+    };
+    for release in releases.flatten() {
+        let release_name = release.file_name().to_string_lossy().into_owned();
+        for file in std::fs::read_dir(release.path())
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let path = file.path();
+            if path.extension().is_some_and(|extension| extension == "bin") {
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                files.push((release_name.clone(), name, path));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn test_golden_messages_round_trip() {
+    let registered = registered();
+    let mut failures = Vec::new();
+    for (release, name, path) in golden_files() {
+        let Some(golden) = registered.iter().find(|golden| golden.name == name) else {
+            failures.push(format!(
+                "{}/{}: not registered in golden_messages!",
+                release, name
+            ));
+            continue;
+        };
+        let bytes = std::fs::read(&path).unwrap();
+        if let Err(err) = (golden.check)(&bytes) {
+            failures.push(format!("{}/{}: {}", release, name, err));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "messages of earlier releases are not compatible:\n{}",
+        failures.join("\n")
+    );
+}
+
+/// Whether two serialized messages carry the same fields
+fn same_fields(a: &[u8], b: &[u8]) -> bool {
+    matches!((canonical(a), canonical(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+#[test]
+fn test_registered_messages_are_recorded() {
+    let release_dir = golden_dir().join(format!("v{}", API_VERSION));
+    let recorded = golden_files();
+    let record = std::env::var_os(RECORD_ENV).is_some();
+    let mut missing = Vec::new();
+    for golden in registered() {
+        let sample = (golden.sample)();
+        let path = release_dir.join(format!("{}.bin", golden.name));
+        let known = recorded
+            .iter()
+            .filter(|(_, name, _)| *name == golden.name)
+            .any(|(_, _, recorded)| same_fields(&std::fs::read(recorded).unwrap(), &sample));
+        if known || path.exists() {
+            continue;
+        }
+        if record {
+            std::fs::create_dir_all(&release_dir).unwrap();
+            std::fs::write(&path, sample).unwrap();
+        } else {
+            missing.push(golden.name);
+        }
+    }
+    assert!(
+        missing.is_empty(),
+        "golden messages not recorded, run with {}=1: {:?}",
+        RECORD_ENV,
+        missing
+    );
+}
+
+#[test]
+fn test_round_trip_detects_lost_fields() {
+    // Version 1 of a message with a field 15 that a later version dropped
+    let golden = [0x0a, 0x01, b'k', 0x12, 0x02, 0x3a, 0x00, 0x78, 0x07];
+    let err = round_trip::<SetValueRequest>(&golden).unwrap_err();
+    assert_eq!(err, "fields [15] are lost or changed");
+
+    // A string field turned into an integer
+    let golden = [0x08, 0x01];
+    assert!(round_trip::<GetValueRequest>(&golden).is_err());
+
+    // Map entries in another order are the same message
+    let mut entries = GetAllWithPrefixResponse::default();
+    entries.key_values.insert("a".to_string(), string("1"));
+    entries.key_values.insert("b".to_string(), string("2"));
+    let encoded = entries.encode_to_vec();
+    let (first, second) = encoded.split_at(encoded.len() / 2);
+    assert!(round_trip::<GetAllWithPrefixResponse>(&[second, first].concat()).is_ok());
+}
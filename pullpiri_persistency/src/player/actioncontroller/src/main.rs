@@ -54,6 +54,11 @@ async fn initialize(skip_grpc: bool) -> Result<(), Box<dyn Error>> {
 /// critical error during operation.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // Identify this instance in the requests it sends, e.g. to the persistency service
+    common::identity::init(common::identity::ComponentIdentity::bootstrap(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+    ));
     println!("Starting ActionController...");
 
     // Initialize the controller
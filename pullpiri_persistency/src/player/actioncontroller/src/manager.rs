@@ -621,7 +621,6 @@ impl ActionControllerManager {
 mod tests {
     use super::*;
     use crate::manager::Status;
-    use std::error::Error;
 
     #[tokio::test]
     async fn test_reconcile_do_with_valid_status() {
@@ -741,7 +740,7 @@ spec:
             state_sender: StateManagerSender::new(),
         };
 
-        let result: std::result::Result<(), Box<dyn Error>> = manager
+        let result: common::Result<()> = manager
             .start_workload("antipinch-enable", "HPC", "invalid_type")
             .await;
         assert!(result.is_err());
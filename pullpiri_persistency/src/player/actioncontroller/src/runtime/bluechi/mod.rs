@@ -1,3 +1,4 @@
+use common::error::Error;
 use common::Result;
 use dbus::blocking::{Connection, Proxy};
 use dbus::Path;
@@ -8,6 +9,11 @@ const PATH: &str = "/org/eclipse/bluechi";
 const DEST_CONTROLLER: &str = "org.eclipse.bluechi.Controller";
 const DEST_NODE: &str = "org.eclipse.bluechi.Node";
 
+/// Failed D-Bus call to the Bluechi controller or a node
+fn dbus_error(err: dbus::Error) -> Error {
+    Error::transport(format!("D-Bus call failed: {}", err))
+}
+
 /// Command structure for Bluechi operations
 ///
 /// Contains the command type to execute and optional node and unit
@@ -109,11 +115,15 @@ pub fn workload_run(
     unit_name: &str,
 ) -> Result<String> {
     println!("workload_run ...");
-    let (node,): (Path,) = proxy.method_call(DEST_CONTROLLER, "GetNode", (&node_name,))?;
+    let (node,): (Path,) = proxy
+        .method_call(DEST_CONTROLLER, "GetNode", (&node_name,))
+        .map_err(dbus_error)?;
 
     let node_proxy = conn.with_proxy(DEST, node, Duration::from_millis(5000));
 
-    let (job_path,): (Path,) = node_proxy.method_call(DEST_NODE, method, (unit_name, "replace"))?;
+    let (job_path,): (Path,) = node_proxy
+        .method_call(DEST_NODE, method, (unit_name, "replace"))
+        .map_err(dbus_error)?;
 
     Ok(format!("{method} '{unit_name}' : {job_path}\n"))
 }
@@ -136,16 +146,21 @@ pub fn workload_run(
 /// * `Err(...)` - If any of the D-Bus calls fail
 pub fn reload_all_nodes(proxy: &Proxy<'_, &Connection>) -> Result<String> {
     println!("Reloading all nodes...");
-    let (nodes,): (Vec<(String, dbus::Path, String)>,) =
-        proxy.method_call(DEST_CONTROLLER, "ListNodes", ())?;
+    let (nodes,): (Vec<(String, dbus::Path, String)>,) = proxy
+        .method_call(DEST_CONTROLLER, "ListNodes", ())
+        .map_err(dbus_error)?;
 
-    let conn = Connection::new_system()?;
+    let conn = Connection::new_system().map_err(dbus_error)?;
     let mut result = String::new();
     for (node_name, _, _) in nodes {
-        let (node,): (Path,) = proxy.method_call(DEST_CONTROLLER, "GetNode", (&node_name,))?;
+        let (node,): (Path,) = proxy
+            .method_call(DEST_CONTROLLER, "GetNode", (&node_name,))
+            .map_err(dbus_error)?;
 
         let node_proxy = conn.with_proxy(DEST, node, Duration::from_millis(5000));
-        node_proxy.method_call::<(), _, _, _>(DEST_NODE, "Reload", ())?;
+        node_proxy
+            .method_call::<(), _, _, _>(DEST_NODE, "Reload", ())
+            .map_err(dbus_error)?;
 
         result.push_str(&format!("Node - {} is reloaded.\n", &node_name));
     }
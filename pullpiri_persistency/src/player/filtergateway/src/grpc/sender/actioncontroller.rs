@@ -1,3 +1,4 @@
+use common::error::Error;
 use common::Result;
 
 // Import the generated protobuf code from actioncontroller.proto
@@ -49,7 +50,7 @@ impl FilterGatewaySender {
 
         client.trigger_action(request).await.map_err(|e| {
             log::error!("Failed to trigger action: {:?}", e);
-            anyhow::anyhow!("Failed to trigger action: {:?}", e)
+            Error::transport(format!("Failed to trigger action: {:?}", e))
         })?;
 
         Ok(())
@@ -21,6 +21,11 @@ use tokio::sync::mpsc::{channel, Receiver, Sender};
 #[cfg(not(feature = "tarpaulin_include"))]
 #[tokio::main]
 async fn main() {
+    // Identify this instance in the requests it sends, e.g. to the persistency service
+    common::identity::init(common::identity::ComponentIdentity::bootstrap(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+    ));
     // Initialize tracing subscriber for logging
 
     let (tx_grpc, rx_grpc): (Sender<ScenarioParameter>, Receiver<ScenarioParameter>) = channel(100);
@@ -32,12 +32,11 @@ use tokio::sync::mpsc::Sender;
 use tokio::task::JoinHandle;
 use tokio::time;
 
-use anyhow::anyhow;
 use serde_json::Value;
 
 use async_trait::async_trait;
 // use clap::Parser;
-use common;
+use common::error::Error;
 use log::{debug, error, info, warn};
 // use once_cell::sync::Lazy;
 use serde::de::DeserializeOwned;
@@ -177,13 +176,13 @@ impl TopicListener {
         let domain_participant_factory = DomainParticipantFactory::get_instance();
         let participant = domain_participant_factory
             .create_participant(domain_id, QosKind::Default, None, NO_STATUS)
-            .map_err(|e| anyhow!("Failed to create domain participant: {:?}", e))?;
+            .map_err(|e| Error::dds(format!("Failed to create domain participant: {:?}", e)))?;
 
         // 구독자 생성
         // commenting below subscriber never used in this block
         let _subscriber = participant
             .create_subscriber(QosKind::Default, None, NO_STATUS)
-            .map_err(|e| anyhow!("Failed to create subscriber: {:?}", e))?;
+            .map_err(|e| Error::dds(format!("Failed to create subscriber: {:?}", e)))?;
 
         // IDL 타입 정보를 유동적으로 처리
         // 토픽 메타데이터에 따라 데이터 처리를 다르게 함
@@ -287,21 +286,21 @@ impl<
         let domain_participant_factory = DomainParticipantFactory::get_instance();
         let participant = domain_participant_factory
             .create_participant(domain_id, QosKind::Default, None, NO_STATUS)
-            .map_err(|e| anyhow!("Failed to create domain participant: {:?}", e))?;
+            .map_err(|e| Error::dds(format!("Failed to create domain participant: {:?}", e)))?;
 
         // 구독자 생성
         let subscriber = participant
             .create_subscriber(QosKind::Default, None, NO_STATUS)
-            .map_err(|e| anyhow!("Failed to create subscriber: {:?}", e))?;
+            .map_err(|e| Error::dds(format!("Failed to create subscriber: {:?}", e)))?;
         // 토픽 생성
         let topic = participant
             .create_topic::<T>(&topic_name, &topic_name, QosKind::Default, None, NO_STATUS)
-            .map_err(|e| anyhow!("Failed to create topic: {:?}", e))?;
+            .map_err(|e| Error::dds(format!("Failed to create topic: {:?}", e)))?;
 
         // 데이터 리더 생성
         let data_reader = subscriber
             .create_datareader::<T>(&topic, QosKind::Default, None, NO_STATUS)
-            .map_err(|e| anyhow!("Failed to create data reader: {:?}", e))?;
+            .map_err(|e| Error::dds(format!("Failed to create data reader: {:?}", e)))?;
 
         println!(
             "Successfully created data reader for topic '{}'",
@@ -317,15 +316,16 @@ impl<
             // 새 샘플 확인
             let result = data_reader
                 .take(1, ANY_SAMPLE_STATE, ANY_VIEW_STATE, ANY_INSTANCE_STATE)
-                .map_err(|e| anyhow!("Failed to read samples: {:?}", e));
+                .map_err(|e| Error::dds(format!("Failed to read samples: {:?}", e)));
 
             match result {
                 Ok(samples) => {
                     for sample in samples {
                         if let Ok(data) = sample.data() {
                             // 데이터를 JSON으로 직렬화
-                            let json_value = serde_json::to_string(&data)
-                                .map_err(|e| anyhow!("Failed to serialize data: {:?}", e))?;
+                            let json_value = serde_json::to_string(&data).map_err(|e| {
+                                Error::dds(format!("Failed to serialize data: {:?}", e))
+                            })?;
 
                             // json_value를 key, value로 파싱해서 fields에 추가
                             let mut fields = HashMap::new();
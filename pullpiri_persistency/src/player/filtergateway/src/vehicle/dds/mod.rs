@@ -1,4 +1,4 @@
-use anyhow::anyhow;
+use common::error::Error;
 use common::Result;
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
@@ -100,7 +100,7 @@ impl DdsManager {
             typed_listener
                 .start()
                 .await
-                .map_err(|e| anyhow!("Failed to start typed listener: {:?}", e))?;
+                .map_err(|e| Error::dds(format!("Failed to start typed listener: {}", e)))?;
 
             println!(
                 "Started typed listener for {} with specific type {}",
@@ -166,7 +166,7 @@ impl DdsManager {
         listener
             .start()
             .await
-            .map_err(|e| anyhow!("Failed to start listener: {:?}", e))?;
+            .map_err(|e| Error::dds(format!("Failed to start listener: {}", e)))?;
 
         // 리스너 맵에 추가
         self.listeners.insert(topic_name, listener);
@@ -180,7 +180,7 @@ impl DdsManager {
             listener
                 .stop()
                 .await
-                .map_err(|e| anyhow!("Failed to stop listener: {:?}", e))?;
+                .map_err(|e| Error::dds(format!("Failed to stop listener: {}", e)))?;
         }
 
         Ok(())
@@ -632,7 +632,7 @@ mod tests {
             Ok(())
         }
         async fn stop(&mut self) -> Result<()> {
-            Err(anyhow!("Forced stop error").into())
+            Err(Error::dds("Forced stop error"))
         }
         fn is_running(&self) -> bool {
             true
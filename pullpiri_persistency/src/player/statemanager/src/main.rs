@@ -213,6 +213,11 @@ async fn initialize_timpani_server() {
 /// - Graceful shutdown even if one component fails
 #[tokio::main]
 async fn main() {
+    // Identify this instance in the requests it sends, e.g. to the persistency service
+    common::identity::init(common::identity::ComponentIdentity::bootstrap(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+    ));
     println!("========================================");
     println!("         PICCOLO StateManager           ");
     println!("========================================");
@@ -989,6 +989,13 @@ async fn execute_action(command: ActionCommand) {
             );
             // Would integrate with policy engine or condition evaluator
         }
+        "stop_condition_evaluation" => {
+            println!(
+                " Stopping condition evaluation for scenario: {}",
+                command.resource_key
+            );
+            // The filter was already withdrawn from the filtergateway
+        }
         "start_policy_verification" => {
             println!(
                 " Starting policy verification for scenario: {}",
@@ -154,7 +154,7 @@ impl StateMachine {
     /// - "Active" -> "Inactive" on "deactivate" event
     /// - Any state -> "Failed" on "error" event
     fn initialize_scenario_transitions(&mut self) {
-        let scenario_transitions = vec![
+        let mut scenario_transitions = vec![
             StateTransition {
                 from_state: ScenarioState::Idle as i32,
                 event: "scenario_activation".to_string(),
@@ -191,6 +191,22 @@ impl StateMachine {
                 action: "finalize_scenario".to_string(),
             },
         ];
+        // The activation window of a scheduled scenario closed, see the apiserver
+        for from_state in [
+            ScenarioState::Waiting,
+            ScenarioState::Satisfied,
+            ScenarioState::Allowed,
+            ScenarioState::Denied,
+            ScenarioState::Completed,
+        ] {
+            scenario_transitions.push(StateTransition {
+                from_state: from_state as i32,
+                event: "scenario_deactivation".to_string(),
+                to_state: ScenarioState::Idle as i32,
+                condition: None,
+                action: "stop_condition_evaluation".to_string(),
+            });
+        }
         self.transition_tables
             .insert(ResourceType::Scenario, scenario_transitions);
     }
@@ -1035,6 +1051,9 @@ impl StateMachine {
                 {
                     "scenario_completion".to_string()
                 }
+                (x, y) if x != ScenarioState::Idle as i32 && y == ScenarioState::Idle as i32 => {
+                    "scenario_deactivation".to_string()
+                }
                 _ => format!("transition_{current_state}_{target_state}"),
             },
             ResourceType::Package => match (current_state, target_state) {
@@ -5,6 +5,8 @@
 
 //! Read/Write/Delete artifact data in persistency
 
+use tokio_stream::{Stream, StreamExt};
+
 /// Read yaml string of artifacts from persistency
 ///
 /// ### Parameters
@@ -17,17 +19,18 @@ pub async fn read_from_persistency(artifact_name: &str) -> common::Result<String
     Ok(raw)
 }
 
-/// Read all scenario yaml string from persistency
+/// Read all scenario yaml strings from persistency in batches
 ///
 /// ### Parameters
 /// * None
 /// ### Return
-/// * `Result<Vec<String>>` - `Ok(_)` contains scenario yaml string vector
-pub async fn read_all_scenario_from_persistency() -> common::Result<Vec<String>> {
-    let kv_scenario = common::persistency::get_all_with_prefix("Scenario").await?;
-    let values = kv_scenario.into_iter().map(|kv| kv.value).collect();
-
-    Ok(values)
+/// * `Result<impl Stream>` - `Ok(_)` yields batches of scenario yaml strings
+/// ### Description
+/// Only one batch of scenarios is held in memory at a time.
+pub async fn scan_scenarios_from_persistency(
+) -> common::Result<impl Stream<Item = common::Result<Vec<String>>>> {
+    let batches = common::persistency::scan("Scenario").await?;
+    Ok(batches.map(|batch| Ok(batch?.into_iter().map(|kv| kv.value).collect())))
 }
 
 /// Write yaml string of artifacts to persistency
@@ -49,14 +52,14 @@ pub async fn write_to_persistency(key: &str, artifact_str: &str) -> common::Resu
     Ok(())
 }
 
-/// Write yaml string of artifacts to persistency
+/// Delete an artifact from persistency, restorable from the trash of the service
 ///
 /// ### Parameters
 /// * `key: &str` - data key to delete from persistency
 /// ### Return
 /// * `Result<()>` - `Ok` if success, `Err` otherwise
 pub async fn delete_at_persistency(key: &str) -> common::Result<()> {
-    common::persistency::delete(key).await?;
+    common::persistency::delete_to_trash(key).await?;
     Ok(())
 }
 
@@ -115,36 +118,71 @@ spec:
     const INVALID_KEY_EMPTY: &str = "";
     const INVALID_KEY_NULLBYTE: &str = "\0badkey";
 
+    // === In-memory persistency service ===
+
+    /// Make common::persistency use an in-memory service shared by the tests
+    ///
+    /// Every test uses keys of its own.
+    async fn use_in_memory_service() {
+        static SERVICE: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+        SERVICE
+            .get_or_init(|| async {
+                let service = persistency_service::ephemeral::spawn().unwrap();
+                let endpoint = service.endpoint().to_string();
+                let client = service
+                    .run(async move {
+                        common::persistency_client::PersistencyClient::connect_to(&endpoint).await
+                    })
+                    .await
+                    .unwrap();
+                assert!(common::persistency::use_client(client));
+            })
+            .await;
+    }
+
     // === Positive Tests ===
 
-    // Test reading valid key (exists or not — should not panic)
+    // Test reading a written key
     #[tokio::test]
     async fn test_read_from_persistency_positive() {
-        let result = read_from_persistency(TEST_KEY).await;
+        use_in_memory_service().await;
+        let key = format!("{}/read", TEST_KEY);
+        write_to_persistency(&key, TEST_YAML).await.unwrap();
+
+        let result = read_from_persistency(&key).await;
         println!("read_from_persistency (positive) result = {:?}", result);
+        assert_eq!(result.unwrap(), TEST_YAML);
+    }
 
-        //we accept both Ok and Err depending on persistency state
+    // Test reading a key that was never written
+    #[tokio::test]
+    async fn test_read_from_persistency_missing_key() {
+        use_in_memory_service().await;
+        let result = read_from_persistency(&format!("{}/missing", TEST_KEY)).await;
         assert!(
-            result.is_ok() || result.is_err(),
-            "Expected Ok or Err but got: {:?}",
+            result.is_err(),
+            "Expected read_from_persistency of a missing key to fail but got: {:?}",
             result
         );
     }
 
-    // Test reading all Scenario keys (should return Vec<String> or Err)
+    // Test reading all Scenario keys in batches
     #[tokio::test]
-    async fn test_read_all_scenario_from_persistency_positive() {
-        let result = read_all_scenario_from_persistency().await;
-        println!(
-            "read_all_scenario_from_persistency (positive) result = {:?}",
-            result
-        );
+    async fn test_scan_scenarios_from_persistency_positive() {
+        use_in_memory_service().await;
+        let key = format!("Scenario/{}", TEST_KEY);
+        write_to_persistency(&key, TEST_YAML).await.unwrap();
 
-        //we accept both Ok (some scenarios) or Ok(empty Vec) or Err (persistency error)
+        let batches = scan_scenarios_from_persistency().await.unwrap();
+        let scenarios: Vec<String> = batches
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .flat_map(|batch| batch.unwrap())
+            .collect();
         assert!(
-            result.is_ok() || result.is_err(),
-            "Expected Ok or Err but got: {:?}",
-            result
+            scenarios.iter().any(|yaml| yaml == TEST_YAML),
+            "Expected the written scenario to be read"
         );
     }
 
@@ -152,30 +190,36 @@ spec:
     #[tokio::test]
     async fn test_write_to_persistency_positive() {
         use std::time::Instant;
+        use_in_memory_service().await;
+        let key = format!("{}/write", TEST_KEY);
         let start = Instant::now();
-        let result = write_to_persistency(TEST_KEY, TEST_YAML).await;
+        let result = write_to_persistency(&key, TEST_YAML).await;
         let duration = start.elapsed();
         println!(
             "write_to_persistency (positive) result = {:?}, elapsed = {:?}",
             result, duration
         );
         assert!(
-            result.is_ok() || result.is_err(),
-            "Expected write_to_persistency to succeed or Err but got: {:?}",
+            result.is_ok(),
+            "Expected write_to_persistency to succeed but got: {:?}",
             result
         );
+        assert_eq!(read_from_persistency(&key).await.unwrap(), TEST_YAML);
     }
 
-    // Test deleting valid key (whether key exists or not — should succeed or cleanly fail)
+    // Test deleting a written key
     #[tokio::test]
     async fn test_delete_at_persistency_positive() {
-        let result = delete_at_persistency(TEST_KEY).await;
+        use_in_memory_service().await;
+        let key = format!("{}/delete", TEST_KEY);
+        write_to_persistency(&key, TEST_YAML).await.unwrap();
+
+        let result = delete_at_persistency(&key).await;
         println!("delete_at_persistency (positive) result = {:?}", result);
-        // We accept Ok (key deleted) or Err (key not found) as valid outcomes
+        assert!(result.is_ok(), "Expected Ok but got: {:?}", result);
         assert!(
-            result.is_ok() || result.is_err(),
-            "Expected Ok or Err but got: {:?}",
-            result
+            read_from_persistency(&key).await.is_err(),
+            "Key should not exist after deletion"
         );
     }
 
@@ -184,6 +228,7 @@ spec:
     // Test reading with invalid keys (empty/nullbyte) — should fail
     #[tokio::test]
     async fn test_read_from_persistency_negative_invalid_key() {
+        use_in_memory_service().await;
         let result = read_from_persistency(INVALID_KEY_EMPTY).await;
         assert!(
             result.is_err(),
@@ -195,6 +240,7 @@ spec:
     // Test writing with invalid keys (empty/nullbyte) — should fail
     #[tokio::test]
     async fn test_write_to_persistency_negative_invalid_key() {
+        use_in_memory_service().await;
         let result = write_to_persistency(INVALID_KEY_EMPTY, TEST_YAML).await;
         assert!(
             result.is_err(),
@@ -205,6 +251,7 @@ spec:
     // Test deleting with invalid keys (empty/nullbyte) — should fail
     #[tokio::test]
     async fn test_delete_at_persistency_negative_invalid_key() {
+        use_in_memory_service().await;
         let result = delete_at_persistency(INVALID_KEY_EMPTY).await;
         assert!(
             result.is_err(),
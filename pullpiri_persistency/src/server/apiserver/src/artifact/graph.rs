@@ -0,0 +1,396 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Dependency graph of artifacts
+//!
+//! A Scenario targets a Package, a Package runs Models, and each Model of a
+//! Package may use a Volume and a Network. Before an artifact bundle is
+//! persisted every reference is resolved, against the bundle first and the
+//! persisted artifacts second. A dangling reference is rejected with its path,
+//! e.g. `Scenario/a -> Package/a -> Model/a-core`, instead of surfacing only
+//! when the scenario is orchestrated.
+
+use common::error::Error;
+use common::spec::artifact::{Package, Scenario};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Where a referenced artifact was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// In the applied bundle
+    Bundle,
+    /// Already in persistency
+    Persisted,
+    /// Neither in the bundle nor in persistency
+    Missing,
+    /// Not looked up in persistency yet
+    Unresolved,
+}
+
+/// Artifact of the graph, with the keys of the artifacts it references
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub origin: Origin,
+    pub dependencies: Vec<String>,
+}
+
+/// Artifacts of a bundle and everything they reference, by key `<kind>/<name>`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    nodes: BTreeMap<String, Node>,
+    roots: Vec<String>,
+}
+
+impl DependencyGraph {
+    /// Build the graph of the artifacts of `bundle`
+    ///
+    /// ### Parameters
+    /// * `bundle: &BTreeMap<String, String>` - yaml of the bundle's artifacts by key
+    /// * `persisted: &HashMap<String, Option<String>>` - yaml of the persisted artifacts
+    ///   looked up so far by key, `None` if not persisted
+    /// ### Description
+    /// References neither in the bundle nor in `persisted` are [`Origin::Unresolved`],
+    /// see [`DependencyGraph::unresolved`].
+    pub fn build(
+        bundle: &BTreeMap<String, String>,
+        persisted: &HashMap<String, Option<String>>,
+    ) -> common::Result<Self> {
+        // Scenarios first, so that a dangling reference is reported from its scenario
+        let mut roots: Vec<String> = bundle.keys().cloned().collect();
+        roots.sort_by_key(|key| !key.starts_with("Scenario/"));
+        let mut graph = Self {
+            roots,
+            ..Self::default()
+        };
+
+        let mut pending = graph.roots.clone();
+        while let Some(key) = pending.pop() {
+            if graph.nodes.contains_key(&key) {
+                continue;
+            }
+            let (origin, yaml) = match (bundle.get(&key), persisted.get(&key)) {
+                (Some(yaml), _) => (Origin::Bundle, Some(yaml)),
+                (None, Some(Some(yaml))) => (Origin::Persisted, Some(yaml)),
+                (None, Some(None)) => (Origin::Missing, None),
+                (None, None) => (Origin::Unresolved, None),
+            };
+            let dependencies = match yaml {
+                Some(yaml) => dependencies(&key, yaml)
+                    .map_err(|e| Error::validation(format!("invalid artifact {}: {}", key, e)))?,
+                None => Vec::new(),
+            };
+            pending.extend(dependencies.iter().cloned());
+            graph.nodes.insert(
+                key,
+                Node {
+                    origin,
+                    dependencies,
+                },
+            );
+        }
+        Ok(graph)
+    }
+
+    /// Keys of the references still to be looked up in persistency
+    pub fn unresolved(&self) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.origin == Origin::Unresolved)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Paths from the bundle's artifacts to each missing artifact
+    ///
+    /// Every missing artifact is reported once, on the first path found to it.
+    pub fn dangling(&self) -> Vec<Vec<String>> {
+        let mut reported = Vec::new();
+        let mut paths = Vec::new();
+        for root in &self.roots {
+            self.collect_dangling(&mut vec![root.clone()], &mut reported, &mut paths);
+        }
+        paths
+    }
+
+    fn collect_dangling(
+        &self,
+        path: &mut Vec<String>,
+        reported: &mut Vec<String>,
+        paths: &mut Vec<Vec<String>>,
+    ) {
+        let key = path.last().cloned().unwrap_or_default();
+        let Some(node) = self.nodes.get(&key) else {
+            return;
+        };
+        if node.origin != Origin::Bundle && node.origin != Origin::Persisted {
+            if !reported.contains(&key) {
+                reported.push(key);
+                paths.push(path.clone());
+            }
+            return;
+        }
+        for dependency in &node.dependencies {
+            // Artifact kinds only reference kinds further down, but never loop on bad data
+            if path.contains(dependency) {
+                continue;
+            }
+            path.push(dependency.clone());
+            self.collect_dangling(path, reported, paths);
+            path.pop();
+        }
+    }
+
+    /// Fail with the path of every dangling reference
+    pub fn check(&self) -> common::Result<()> {
+        let dangling = self.dangling();
+        if dangling.is_empty() {
+            return Ok(());
+        }
+        let paths: Vec<String> = dangling.iter().map(|path| path.join(" -> ")).collect();
+        Err(Error::validation(format!(
+            "dangling artifact reference(s), not in the bundle nor persisted: {}",
+            paths.join("; ")
+        )))
+    }
+}
+
+impl fmt::Display for DependencyGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, node) in &self.nodes {
+            write!(f, "{} ({:?})", key, node.origin)?;
+            if !node.dependencies.is_empty() {
+                write!(f, " -> {}", node.dependencies.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys of the artifacts referenced by the artifact with `key`
+fn dependencies(key: &str, yaml: &str) -> common::Result<Vec<String>> {
+    let kind = key.split('/').next().unwrap_or_default();
+    let dependencies = match kind {
+        "Scenario" => {
+            let scenario: Scenario = serde_yaml::from_str(yaml)?;
+            vec![format!("Package/{}", scenario.get_targets())]
+        }
+        "Package" => {
+            let package: Package = serde_yaml::from_str(yaml)?;
+            let mut dependencies = Vec::new();
+            let mut add = |key: String| {
+                if !dependencies.contains(&key) {
+                    dependencies.push(key);
+                }
+            };
+            for model in package.get_models() {
+                add(format!("Model/{}", model.get_name()));
+                let resources = model.get_resources();
+                if let Some(volume) = resources.get_volume().filter(|v| !v.is_empty()) {
+                    add(format!("Volume/{}", volume));
+                }
+                if let Some(network) = resources.get_network().filter(|n| !n.is_empty()) {
+                    add(format!("Network/{}", network));
+                }
+            }
+            dependencies
+        }
+        _ => Vec::new(),
+    };
+    Ok(dependencies)
+}
+
+/// Resolve the references of `bundle` and fail on dangling ones
+///
+/// ### Parameters
+/// * `bundle: &BTreeMap<String, String>` - yaml of the bundle's artifacts by key
+/// ### Returns
+/// * `Result<DependencyGraph>` - the full graph if every reference resolves
+/// ### Description
+/// References outside the bundle are looked up in persistency, level by level.
+pub async fn resolve(bundle: &BTreeMap<String, String>) -> common::Result<DependencyGraph> {
+    let mut persisted = HashMap::new();
+    loop {
+        let graph = DependencyGraph::build(bundle, &persisted)?;
+        let unresolved = graph.unresolved();
+        if unresolved.is_empty() {
+            graph.check()?;
+            return Ok(graph);
+        }
+        for key in unresolved {
+            let yaml = super::data::read_from_persistency(&key).await.ok();
+            persisted.insert(key, yaml);
+        }
+    }
+}
+
+//UNIT TEST CASES
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENARIO_YAML: &str = r#"apiVersion: v1
+kind: Scenario
+metadata:
+  name: helloworld
+spec:
+  condition:
+  action: update
+  target: helloworld
+"#;
+
+    const PACKAGE_YAML: &str = r#"apiVersion: v1
+kind: Package
+metadata:
+  name: helloworld
+spec:
+  pattern:
+    - type: plain
+  models:
+    - name: helloworld-core
+      node: HPC
+      resources:
+        volume:
+        network:
+    - name: helloworld-logger
+      node: HPC
+      resources:
+        volume: helloworld-logs
+        network:
+"#;
+
+    const MODEL_YAML: &str = r#"apiVersion: v1
+kind: Model
+metadata:
+  name: helloworld-core
+spec:
+  containers:
+    - name: helloworld
+      image: helloworld
+"#;
+
+    fn artifacts(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn lookups(entries: &[(&str, Option<&str>)]) -> HashMap<String, Option<String>> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.map(str::to_string)))
+            .collect()
+    }
+
+    #[test]
+    fn test_complete_bundle() {
+        let bundle = artifacts(&[
+            ("Scenario/helloworld", SCENARIO_YAML),
+            ("Package/helloworld", PACKAGE_YAML),
+            ("Model/helloworld-core", MODEL_YAML),
+            ("Model/helloworld-logger", MODEL_YAML),
+            ("Volume/helloworld-logs", ""),
+        ]);
+        let graph = DependencyGraph::build(&bundle, &HashMap::new()).unwrap();
+
+        assert!(graph.unresolved().is_empty());
+        assert!(graph.check().is_ok());
+        assert_eq!(
+            graph.nodes["Package/helloworld"].dependencies,
+            vec![
+                "Model/helloworld-core",
+                "Model/helloworld-logger",
+                "Volume/helloworld-logs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_references_resolved_from_persistency() {
+        let bundle = artifacts(&[("Scenario/helloworld", SCENARIO_YAML)]);
+        let graph = DependencyGraph::build(&bundle, &HashMap::new()).unwrap();
+        assert_eq!(graph.unresolved(), vec!["Package/helloworld"]);
+
+        let persisted = lookups(&[("Package/helloworld", Some(PACKAGE_YAML))]);
+        let graph = DependencyGraph::build(&bundle, &persisted).unwrap();
+        assert_eq!(
+            graph.unresolved(),
+            vec![
+                "Model/helloworld-core",
+                "Model/helloworld-logger",
+                "Volume/helloworld-logs"
+            ]
+        );
+
+        let persisted = lookups(&[
+            ("Package/helloworld", Some(PACKAGE_YAML)),
+            ("Model/helloworld-core", Some(MODEL_YAML)),
+            ("Model/helloworld-logger", Some(MODEL_YAML)),
+            ("Volume/helloworld-logs", Some("")),
+        ]);
+        let graph = DependencyGraph::build(&bundle, &persisted).unwrap();
+        assert!(graph.unresolved().is_empty());
+        assert_eq!(graph.nodes["Package/helloworld"].origin, Origin::Persisted);
+        assert!(graph.check().is_ok());
+    }
+
+    #[test]
+    fn test_dangling_reference_paths() {
+        let bundle = artifacts(&[
+            ("Scenario/helloworld", SCENARIO_YAML),
+            ("Package/helloworld", PACKAGE_YAML),
+            ("Model/helloworld-core", MODEL_YAML),
+        ]);
+        let persisted = lookups(&[
+            ("Model/helloworld-logger", None),
+            ("Volume/helloworld-logs", None),
+        ]);
+        let graph = DependencyGraph::build(&bundle, &persisted).unwrap();
+
+        assert_eq!(
+            graph.dangling(),
+            vec![
+                vec![
+                    "Scenario/helloworld",
+                    "Package/helloworld",
+                    "Model/helloworld-logger"
+                ],
+                vec![
+                    "Scenario/helloworld",
+                    "Package/helloworld",
+                    "Volume/helloworld-logs"
+                ],
+            ]
+        );
+        let error = graph.check().unwrap_err().to_string();
+        assert!(
+            error.contains("Scenario/helloworld -> Package/helloworld -> Model/helloworld-logger")
+        );
+    }
+
+    #[test]
+    fn test_missing_package() {
+        let bundle = artifacts(&[("Scenario/helloworld", SCENARIO_YAML)]);
+        let persisted = lookups(&[("Package/helloworld", None)]);
+        let graph = DependencyGraph::build(&bundle, &persisted).unwrap();
+
+        assert_eq!(
+            graph.dangling(),
+            vec![vec!["Scenario/helloworld", "Package/helloworld"]]
+        );
+    }
+
+    #[test]
+    fn test_invalid_artifact() {
+        let bundle = artifacts(&[("Package/helloworld", "kind: Package\nspec: 3\n")]);
+        let error = DependencyGraph::build(&bundle, &HashMap::new()).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("invalid artifact Package/helloworld"));
+    }
+}
@@ -6,7 +6,9 @@
 //! Convert string-type artifacts to struct and access persistency
 
 pub mod data;
+pub mod graph;
 
+use common::error::Error;
 use common::spec::artifact::Artifact;
 use common::spec::artifact::Model;
 use common::spec::artifact::Network;
@@ -14,6 +16,7 @@ use common::spec::artifact::Node;
 use common::spec::artifact::Package;
 use common::spec::artifact::Scenario;
 use common::spec::artifact::Volume;
+use std::collections::BTreeMap;
 
 /// Apply downloaded artifact to persistency
 ///
@@ -31,6 +34,8 @@ pub async fn apply(body: &str) -> common::Result<String> {
     let mut scenario_str = String::new();
     let mut package_str = String::new();
 
+    // Parse the whole bundle first, nothing is persisted if a reference dangles
+    let mut artifacts = Vec::new();
     for doc in docs {
         let parse_start = Instant::now();
         let value: serde_yaml::Value = serde_yaml::from_str(doc)?;
@@ -40,7 +45,12 @@ pub async fn apply(body: &str) -> common::Result<String> {
 
         if let Some(kind) = value.clone().get("kind").and_then(|k| k.as_str()) {
             let name: String = match kind {
-                "Scenario" => serde_yaml::from_value::<Scenario>(value)?.get_name(),
+                "Scenario" => {
+                    let scenario = serde_yaml::from_value::<Scenario>(value)?;
+                    // Reject invalid activation windows before anything is persisted
+                    crate::schedule::Window::of(&scenario).map_err(Error::validation)?;
+                    scenario.get_name()
+                }
                 "Package" => serde_yaml::from_value::<Package>(value)?.get_name(),
                 "Volume" => serde_yaml::from_value::<Volume>(value)?.get_name(),
                 "Network" => serde_yaml::from_value::<Network>(value)?.get_name(),
@@ -51,66 +61,82 @@ pub async fn apply(body: &str) -> common::Result<String> {
                     continue;
                 }
             };
-            let key = format!("{}/{}", kind, name);
-
-            let persistency_start = Instant::now();
-            data::write_to_persistency(&key, &artifact_str).await?;
-            let persistency_elapsed = persistency_start.elapsed();
-            println!("apply: persistency write elapsed for {} = {:?}", key, persistency_elapsed);
+            artifacts.push((kind.to_string(), name, artifact_str));
+        }
+    }
 
-            match kind {
-                "Scenario" => {
-                    scenario_str = artifact_str;
-
-                    // Set initial scenario state to idle via StateManager
-                    println!("🔄 SCENARIO STATE INITIALIZATION: ApiServer Setting Initial State");
-                    println!("   📋 Scenario: {}", name);
-                    println!("   🔄 Initial State: → idle");
-                    println!("   🔍 Reason: New scenario artifact received and stored in persistency");
-
-                    let timestamp = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_nanos() as i64;
-
-                    let state_change = common::statemanager::StateChange {
-                        resource_type: common::statemanager::ResourceType::Scenario as i32,
-                        resource_name: name.clone(),
-                        current_state: "".to_string(), // No previous state for new scenario
-                        target_state: "idle".to_string(),
-                        transition_id: format!("apiserver-scenario-init-{}", timestamp),
-                        timestamp_ns: timestamp,
-                        source: "apiserver".to_string(),
-                    };
-
-                    println!("   📤 Sending StateChange to StateManager:");
-                    println!("      • Resource Type: SCENARIO");
-                    println!("      • Resource Name: {}", state_change.resource_name);
-                    println!("      • Target State: {}", state_change.target_state);
-                    println!("      • Transition ID: {}", state_change.transition_id);
-                    println!("      • Source: {}", state_change.source);
-
-                    let mut state_sender =
-                        crate::grpc::sender::statemanager::StateManagerSender::new();
-                    if let Err(e) = state_sender.send_state_change(state_change).await {
-                        println!("   ❌ Failed to send state change to StateManager: {:?}", e);
-                    } else {
-                        println!("   ✅ Successfully set scenario {} to idle state", name);
-                    }
+    let resolve_start = Instant::now();
+    let bundle: BTreeMap<String, String> = artifacts
+        .iter()
+        .map(|(kind, name, artifact_str)| (format!("{}/{}", kind, name), artifact_str.clone()))
+        .collect();
+    let graph = graph::resolve(&bundle).await?;
+    println!(
+        "apply: dependencies resolved in {:?}\n{}",
+        resolve_start.elapsed(),
+        graph
+    );
+
+    for (kind, name, artifact_str) in artifacts {
+        let key = format!("{}/{}", kind, name);
+
+        let persistency_start = Instant::now();
+        data::write_to_persistency(&key, &artifact_str).await?;
+        let persistency_elapsed = persistency_start.elapsed();
+        println!("apply: persistency write elapsed for {} = {:?}", key, persistency_elapsed);
+
+        match kind.as_str() {
+            "Scenario" => {
+                scenario_str = artifact_str;
+
+                // Set initial scenario state to idle via StateManager
+                println!("🔄 SCENARIO STATE INITIALIZATION: ApiServer Setting Initial State");
+                println!("   📋 Scenario: {}", name);
+                println!("   🔄 Initial State: → idle");
+                println!("   🔍 Reason: New scenario artifact received and stored in persistency");
+
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos() as i64;
+
+                let state_change = common::statemanager::StateChange {
+                    resource_type: common::statemanager::ResourceType::Scenario as i32,
+                    resource_name: name.clone(),
+                    current_state: "".to_string(), // No previous state for new scenario
+                    target_state: "idle".to_string(),
+                    transition_id: format!("apiserver-scenario-init-{}", timestamp),
+                    timestamp_ns: timestamp,
+                    source: "apiserver".to_string(),
+                };
+
+                println!("   📤 Sending StateChange to StateManager:");
+                println!("      • Resource Type: SCENARIO");
+                println!("      • Resource Name: {}", state_change.resource_name);
+                println!("      • Target State: {}", state_change.target_state);
+                println!("      • Transition ID: {}", state_change.transition_id);
+                println!("      • Source: {}", state_change.source);
+
+                let mut state_sender =
+                    crate::grpc::sender::statemanager::StateManagerSender::new();
+                if let Err(e) = state_sender.send_state_change(state_change).await {
+                    println!("   ❌ Failed to send state change to StateManager: {:?}", e);
+                } else {
+                    println!("   ✅ Successfully set scenario {} to idle state", name);
                 }
-                "Package" => package_str = artifact_str,
-                _ => continue,
-            };
-        }
+            }
+            "Package" => package_str = artifact_str,
+            _ => continue,
+        };
     }
 
     let total_elapsed = total_start.elapsed();
     println!("apply: total elapsed = {:?}", total_elapsed);
 
     if scenario_str.is_empty() {
-        Err("There is not any scenario in yaml string".into())
+        Err(Error::validation("There is not any scenario in yaml string"))
     } else if package_str.is_empty() {
-        Err("There is not any package in yaml string".into())
+        Err(Error::validation("There is not any package in yaml string"))
     } else {
         Ok(scenario_str)
     }
@@ -145,7 +171,7 @@ pub async fn withdraw(body: &str) -> common::Result<String> {
         }
     }
 
-    Err("There is not any scenario in yaml string".into())
+    Err(Error::validation("There is not any scenario in yaml string"))
 }
 
 //UNIT TEST CASES
@@ -183,6 +209,41 @@ spec:
     - type: plain
   models:
     - name: helloworld-core
+      node: HPC
+      resources:
+        volume:
+        network:---
+apiVersion: v1
+kind: Model
+metadata:
+  name: helloworld-core
+spec:
+  hostNetwork: true
+  containers:
+    - name: helloworld
+      image: helloworld
+"#;
+
+    /// Invalid artifact YAML — Package references a Model that exists nowhere
+    const INVALID_YAML_DANGLING_MODEL: &str = r#"
+apiVersion: v1
+kind: Scenario
+metadata:
+  name: helloworld-dangling
+spec:
+  condition:
+  action: update
+  target: helloworld-dangling
+---
+apiVersion: v1
+kind: Package
+metadata:
+  name: helloworld-dangling
+spec:
+  pattern:
+    - type: plain
+  models:
+    - name: helloworld-dangling-core
       node: HPC
       resources:
         volume:
@@ -246,6 +307,22 @@ spec:
         assert!(!scenario.is_empty(), "Scenario YAML should not be empty");
     }
 
+    /// Test apply() with a Package whose Model is neither in the bundle nor persisted
+    #[tokio::test]
+    async fn test_apply_invalid_dangling_model() {
+        let result = apply(INVALID_YAML_DANGLING_MODEL).await;
+
+        // Assert: should fail with the path of the dangling reference
+        let error = result.expect_err("apply() accepted a dangling Model reference");
+        assert!(
+            error.to_string().contains(
+                "Scenario/helloworld-dangling -> Package/helloworld-dangling -> Model/helloworld-dangling-core"
+            ),
+            "unexpected error: {}",
+            error
+        );
+    }
+
     /// Test apply() with missing `action` field (invalid Scenario)
     #[tokio::test]
     async fn test_apply_invalid_missing_action() {
@@ -3,4 +3,6 @@ pub mod diagnostics;
 pub mod grpc;
 pub mod manager;
 pub mod node;
+pub mod reconcile;
 pub mod route;
+pub mod schedule;
@@ -18,7 +18,9 @@ mod artifact;
 mod grpc;
 mod manager;
 mod node;
+mod reconcile;
 mod route;
+mod schedule;
 
 /// Main function of Piccolo API Server
 #[cfg(feature = "tarpaulin_include")]
@@ -29,6 +31,11 @@ fn main() {
 #[cfg(not(feature = "tarpaulin_include"))]
 #[tokio::main]
 async fn main() {
+    // Identify this instance in the requests it sends, e.g. to the persistency service
+    common::identity::init(common::identity::ComponentIdentity::bootstrap(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+    ));
     manager::initialize().await
 }
 
@@ -8,9 +8,11 @@ use crate::node::node_lookup::{find_guest_nodes, find_node_by_hostname, get_node
 use common::apiserver::api_server_connection_server::ApiServerConnectionServer;
 use common::filtergateway::{Action, HandleScenarioRequest};
 use common::nodeagent::HandleYamlRequest;
+use tokio_stream::StreamExt;
 use tonic::transport::Server;
 
-/// Launch REST API listener, gRPC server, and reload scenario data in persistency
+/// Launch REST API listener, gRPC server, reload scenario data in persistency
+/// and start the reconciliation loop and the scheduler
 pub async fn initialize() {
     // 먼저 호스트 노드를 persistency에 등록합니다.
     if let Err(e) = register_host_node().await {
@@ -22,7 +24,9 @@ pub async fn initialize() {
     tokio::join!(
         crate::route::launch_tcp_listener(),
         start_grpc_server(),
-        reload()
+        reload(),
+        crate::reconcile::run(),
+        crate::schedule::run()
     );
 }
 
@@ -110,20 +114,39 @@ async fn register_host_node() -> Result<(), Box<dyn std::error::Error + Send + S
 /// ### Description
 /// This function is called once when the apiserver starts.
 async fn reload() {
-    let scenarios_result = crate::artifact::data::read_all_scenario_from_persistency().await;
-
-    if let Ok(scenarios) = scenarios_result {
+    let batches = match crate::artifact::data::scan_scenarios_from_persistency().await {
+        Ok(batches) => batches,
+        Err(e) => {
+            println!("{:#?}", e);
+            return;
+        }
+    };
+    tokio::pin!(batches);
+
+    let now = chrono::Utc::now();
+    while let Some(batch) = batches.next().await {
+        let scenarios = match batch {
+            Ok(scenarios) => scenarios,
+            Err(e) => {
+                println!("Failed to read scenarios: {:?}", e);
+                break;
+            }
+        };
         for scenario in scenarios {
+            // The scheduler applies the others when their window opens
+            if !crate::schedule::is_active(&scenario, now) {
+                continue;
+            }
             let req = HandleScenarioRequest {
                 action: Action::Apply.into(),
-                scenario,
+                scenario: scenario.clone(),
             };
             if let Err(status) = crate::grpc::sender::filtergateway::send(req).await {
                 println!("{:#?}", status);
+            } else if let Err(e) = crate::reconcile::record_applied(&scenario).await {
+                println!("Failed to record applied scenario: {:?}", e);
             }
         }
-    } else {
-        println!("{:#?}", scenarios_result);
     }
 }
 
@@ -208,10 +231,10 @@ pub async fn apply_artifact(body: &str) -> common::Result<()> {
         Ok(_) => println!("Successfully sent yaml to NodeAgent"),
         Err(e) => {
             eprintln!("Error sending yaml to NodeAgent: {:?}", e);
-            return Err(Box::new(std::io::Error::other(format!(
+            return Err(common::error::Error::transport(format!(
                 "NodeAgent connection error: {:?}",
                 e
-            ))));
+            )));
         }
     };
 
@@ -246,11 +269,21 @@ pub async fn apply_artifact(body: &str) -> common::Result<()> {
         }
     }
 
+    // The scheduler applies scenarios outside their window when it opens
+    if !crate::schedule::admit(&scenario).await {
+        return Ok(());
+    }
+
     let req: HandleScenarioRequest = HandleScenarioRequest {
         action: Action::Apply.into(),
-        scenario,
+        scenario: scenario.clone(),
     };
     crate::grpc::sender::filtergateway::send(req).await?;
+
+    // The reconciliation loop re-applies the scenario if this record is lost
+    if let Err(e) = crate::reconcile::record_applied(&scenario).await {
+        println!("Failed to record applied scenario: {:?}", e);
+    }
     Ok(())
 }
 
@@ -267,10 +300,13 @@ pub async fn withdraw_artifact(body: &str) -> common::Result<()> {
 
     let req = HandleScenarioRequest {
         action: Action::Withdraw.into(),
-        scenario,
+        scenario: scenario.clone(),
     };
     crate::grpc::sender::filtergateway::send(req).await?;
 
+    if let Err(e) = crate::reconcile::clear_applied(&scenario).await {
+        println!("Failed to clear applied scenario: {:?}", e);
+    }
     Ok(())
 }
 
@@ -608,6 +644,16 @@ spec:
       resources:
         volume:
         network:
+---
+apiVersion: v1
+kind: Model
+metadata:
+  name: helloworld-core
+spec:
+  hostNetwork: true
+  containers:
+    - name: helloworld
+      image: helloworld
 "#;
 
     /// Invalid YAML WITH KNOWN/UNKNOWN ARTIFACT WITHOUT SCENARIO
@@ -810,9 +856,16 @@ spec:
     /// Mocked version of reload function.
     /// Sends a gRPC reload request to the mock server.
     async fn reload() {
-        let scenarios_result = crate::artifact::data::read_all_scenario_from_persistency().await;
+        let batches = match crate::artifact::data::scan_scenarios_from_persistency().await {
+            Ok(batches) => batches,
+            Err(e) => {
+                println!("{:#?}", e);
+                return;
+            }
+        };
+        tokio::pin!(batches);
         let grpc_addr = start_mock_server().await;
-        if let Ok(scenarios) = scenarios_result {
+        while let Some(Ok(scenarios)) = batches.next().await {
             for scenario in scenarios {
                 let req = HandleScenarioRequest {
                     action: Action::Apply.into(),
@@ -822,8 +875,6 @@ spec:
                     println!("{:#?}", status);
                 }
             }
-        } else {
-            println!("{:#?}", scenarios_result);
         }
     }
 
@@ -0,0 +1,271 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Periodic reconciliation of persisted desired state
+//!
+//! Scenarios stored under `Scenario/<name>` are the desired state. Every
+//! scenario that was successfully handed to filtergateway is recorded under
+//! `applied/<name>` together with the yaml that was sent. The reconciliation
+//! loop compares both sets periodically, re-applies scenarios that are missing
+//! or have drifted, withdraws scenarios that are applied but no longer
+//! desired, and records the outcome for each scenario under `reconcile/<name>`.
+//! Scenarios outside their activation window are not desired, see
+//! [`crate::schedule`].
+
+use common::filtergateway::{Action, HandleScenarioRequest};
+use common::spec::artifact::{Artifact, Scenario};
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// Key prefix of desired scenario state
+pub const DESIRED_PREFIX: &str = "Scenario/";
+/// Key prefix of last-applied scenario state
+pub const APPLIED_PREFIX: &str = "applied/";
+/// Key prefix of reconciliation outcome records
+pub const OUTCOME_PREFIX: &str = "reconcile/";
+
+/// Time between two reconciliation passes
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What a reconciliation pass has to do for one scenario
+#[derive(Debug, PartialEq)]
+pub enum ReconcileAction {
+    /// Desired and last-applied state match
+    InSync { name: String },
+    /// Desired scenario is missing from or differs from the last-applied state
+    Apply {
+        name: String,
+        scenario: String,
+        reason: &'static str,
+    },
+    /// Scenario is still applied but no longer desired
+    Withdraw { name: String, scenario: String },
+}
+
+/// Compare desired and last-applied state
+///
+/// ### Parameters
+/// * `desired: &HashMap<String, String>` - desired scenario yaml by name
+/// * `applied: &HashMap<String, String>` - last-applied scenario yaml by name
+/// ### Return
+/// * `Vec<ReconcileAction>` - one action per scenario, ordered by name
+pub fn plan(
+    desired: &HashMap<String, String>,
+    applied: &HashMap<String, String>,
+) -> Vec<ReconcileAction> {
+    let names: BTreeSet<&String> = desired.keys().chain(applied.keys()).collect();
+
+    names
+        .into_iter()
+        .map(|name| match (desired.get(name), applied.get(name)) {
+            (Some(want), Some(have)) if want == have => {
+                ReconcileAction::InSync { name: name.clone() }
+            }
+            (Some(want), Some(_)) => ReconcileAction::Apply {
+                name: name.clone(),
+                scenario: want.clone(),
+                reason: "drifted",
+            },
+            (Some(want), None) => ReconcileAction::Apply {
+                name: name.clone(),
+                scenario: want.clone(),
+                reason: "missing",
+            },
+            (None, Some(have)) => ReconcileAction::Withdraw {
+                name: name.clone(),
+                scenario: have.clone(),
+            },
+            (None, None) => unreachable!("name comes from one of the maps"),
+        })
+        .collect()
+}
+
+/// Name of the scenario in a scenario yaml string
+pub fn scenario_name(scenario: &str) -> Option<String> {
+    serde_yaml::from_str::<Scenario>(scenario)
+        .ok()
+        .map(|scenario| scenario.get_name())
+}
+
+/// Record that a scenario was applied successfully
+///
+/// ### Parameters
+/// * `scenario: &str` - scenario yaml sent to filtergateway
+pub async fn record_applied(scenario: &str) -> common::Result<()> {
+    let name = scenario_name(scenario).ok_or("scenario yaml has no name")?;
+    common::persistency::put(&format!("{}{}", APPLIED_PREFIX, name), scenario).await?;
+    Ok(())
+}
+
+/// Forget the last-applied state of a withdrawn scenario
+///
+/// ### Parameters
+/// * `scenario: &str` - scenario yaml sent to filtergateway
+pub async fn clear_applied(scenario: &str) -> common::Result<()> {
+    let name = scenario_name(scenario).ok_or("scenario yaml has no name")?;
+    common::persistency::delete(&format!("{}{}", APPLIED_PREFIX, name)).await?;
+    Ok(())
+}
+
+/// Run reconciliation passes forever
+///
+/// ### Description
+/// The first pass runs one interval after startup, so that `reload()` has
+/// already re-applied the persisted scenarios.
+pub async fn run() {
+    let mut interval = tokio::time::interval(RECONCILE_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = reconcile_once().await {
+            println!("reconcile: pass failed: {:?}", e);
+        }
+    }
+}
+
+/// Run a single reconciliation pass
+///
+/// ### Return
+/// * `Result<()>` - `Err` if the state could not be read from persistency
+pub async fn reconcile_once() -> common::Result<()> {
+    let mut desired = read_prefix(DESIRED_PREFIX).await?;
+    let now = chrono::Utc::now();
+    desired.retain(|_, scenario| crate::schedule::is_active(scenario, now));
+    let applied = read_prefix(APPLIED_PREFIX).await?;
+
+    for action in plan(&desired, &applied) {
+        match action {
+            ReconcileAction::InSync { name } => {
+                record_outcome(&name, "in_sync", "").await;
+            }
+            ReconcileAction::Apply {
+                name,
+                scenario,
+                reason,
+            } => {
+                println!("reconcile: re-applying {} scenario '{}'", reason, name);
+                let outcome = match send(Action::Apply, &scenario).await {
+                    Ok(()) => record_applied(&scenario).await,
+                    Err(e) => Err(e),
+                };
+                match outcome {
+                    Ok(()) => record_outcome(&name, "reapplied", reason).await,
+                    Err(e) => record_outcome(&name, "failed", &format!("{:?}", e)).await,
+                }
+            }
+            ReconcileAction::Withdraw { name, scenario } => {
+                println!("reconcile: withdrawing stale scenario '{}'", name);
+                let outcome = match send(Action::Withdraw, &scenario).await {
+                    Ok(()) => clear_applied(&scenario).await,
+                    Err(e) => Err(e),
+                };
+                match outcome {
+                    Ok(()) => record_outcome(&name, "withdrawn", "not desired").await,
+                    Err(e) => record_outcome(&name, "failed", &format!("{:?}", e)).await,
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn send(action: Action, scenario: &str) -> common::Result<()> {
+    let req = HandleScenarioRequest {
+        action: action.into(),
+        scenario: scenario.to_string(),
+    };
+    crate::grpc::sender::filtergateway::send(req).await?;
+    Ok(())
+}
+
+/// Values of the keys under `prefix` by the rest of the key
+pub async fn read_prefix(prefix: &str) -> common::Result<HashMap<String, String>> {
+    let kvs = common::persistency::get_all_with_prefix(prefix).await?;
+    Ok(kvs
+        .into_iter()
+        .filter_map(|kv| {
+            kv.key
+                .strip_prefix(prefix)
+                .map(|name| (name.to_string(), kv.value))
+        })
+        .collect())
+}
+
+async fn record_outcome(name: &str, outcome: &str, detail: &str) {
+    let record = serde_json::json!({
+        "outcome": outcome,
+        "detail": detail,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    let key = format!("{}{}", OUTCOME_PREFIX, name);
+    if let Err(e) = common::persistency::put(&key, &record.to_string()).await {
+        println!("reconcile: failed to record outcome of '{}': {:?}", name, e);
+    }
+}
+
+//UNIT Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENARIO_YAML: &str = r#"apiVersion: v1
+kind: Scenario
+metadata:
+  name: helloworld
+spec:
+  condition:
+  action: update
+  target: helloworld
+"#;
+
+    fn state(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_detects_missing_drifted_and_stale() {
+        let desired = state(&[("a", "yaml-a"), ("b", "yaml-b2"), ("c", "yaml-c")]);
+        let applied = state(&[("b", "yaml-b1"), ("c", "yaml-c"), ("d", "yaml-d")]);
+
+        assert_eq!(
+            plan(&desired, &applied),
+            vec![
+                ReconcileAction::Apply {
+                    name: "a".to_string(),
+                    scenario: "yaml-a".to_string(),
+                    reason: "missing",
+                },
+                ReconcileAction::Apply {
+                    name: "b".to_string(),
+                    scenario: "yaml-b2".to_string(),
+                    reason: "drifted",
+                },
+                ReconcileAction::InSync {
+                    name: "c".to_string()
+                },
+                ReconcileAction::Withdraw {
+                    name: "d".to_string(),
+                    scenario: "yaml-d".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_empty_state() {
+        assert!(plan(&HashMap::new(), &HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_scenario_name() {
+        assert_eq!(scenario_name(SCENARIO_YAML), Some("helloworld".to_string()));
+        assert_eq!(scenario_name("not: a scenario"), None);
+    }
+}
@@ -72,7 +72,7 @@ mod tests {
 
         // Negative case: Error response
         let err = Box::new(std::io::Error::other("test error")) as Box<dyn StdError + Send + Sync>;
-        let err_response = status(Err(err));
+        let err_response = status(Err(err.into()));
         assert_eq!(err_response.status(), StatusCode::METHOD_NOT_ALLOWED);
     }
 
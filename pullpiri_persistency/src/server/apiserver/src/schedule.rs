@@ -0,0 +1,602 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Scheduled activation windows of scenarios
+//!
+//! A scenario with a `schedule` in its spec is only active inside its
+//! activation window: between the optional `start` and `end` timestamps and,
+//! with `recurring`, for `durationSeconds` after every minute matching the
+//! cron expression. Cron expressions have the five fields minute, hour, day
+//! of month, month and day of week with `*`, lists, ranges and steps, and
+//! are evaluated in UTC.
+//!
+//! The window is part of the scenario yaml stored under `Scenario/<name>`.
+//! The scheduler evaluates the windows periodically, hands a scenario to
+//! filtergateway when its window opens and withdraws it when the window
+//! closes. The last state is recorded under `schedule/<name>`, so that every
+//! transition is handled once, also across restarts. Withdrawn scenarios are
+//! moved back to `idle` in the statemanager, filtergateway moves activated
+//! scenarios to `waiting` itself.
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use common::filtergateway::Action;
+use common::spec::artifact::Scenario;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// Key prefix of the recorded window states
+pub const STATE_PREFIX: &str = "schedule/";
+
+/// Time between two scheduler passes
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Longest recurring window, bounds the search for the last cron match
+const MAX_DURATION_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Parsed cron expression
+#[derive(Debug, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month field is `*`
+    any_day: bool,
+    /// Whether the day of week field is `*`
+    any_weekday: bool,
+}
+
+impl Cron {
+    /// Parse a five field cron expression
+    ///
+    /// ### Parameters
+    /// * `expr: &str` - e.g. `0 8 * * 1-5` for 08:00 on weekdays
+    /// ### Return
+    /// * `Result<Cron, String>` - `Err` describes the invalid field
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "cron expression '{}' needs 5 fields, found {}",
+                expr,
+                fields.len()
+            ));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // Both 0 and 7 are Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+
+    /// Whether the minute of `time` matches the expression
+    ///
+    /// ### Description
+    /// Like cron, a time matches either day field if both are restricted.
+    pub fn matches(&self, time: DateTime<Utc>) -> bool {
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        };
+
+        self.minutes & (1 << time.minute()) != 0
+            && self.hours & (1 << time.hour()) != 0
+            && self.months & (1 << time.month()) != 0
+            && day_matches
+    }
+}
+
+/// Values of one cron field between `min` and `max` as bit set
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let number = |value: &str| {
+        value
+            .parse::<u32>()
+            .map_err(|_| format!("invalid value '{}' in cron field '{}'", value, field))
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match number(step)? {
+                0 => return Err(format!("step 0 in cron field '{}'", field)),
+                step => (range, step),
+            },
+            None => (part, 1),
+        };
+        let (low, high) = if range == "*" {
+            (min, max)
+        } else if let Some((low, high)) = range.split_once('-') {
+            (number(low)?, number(high)?)
+        } else if step > 1 {
+            (number(range)?, max)
+        } else {
+            let value = number(range)?;
+            (value, value)
+        };
+        if low < min || high > max || low > high {
+            return Err(format!(
+                "'{}' is out of {}-{} in cron field '{}'",
+                range, min, max, field
+            ));
+        }
+        for value in (low..=high).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// Activation window of a scheduled scenario
+#[derive(Debug)]
+pub struct Window {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    recurring: Option<(Cron, chrono::Duration)>,
+}
+
+impl Window {
+    /// Activation window of `scenario`
+    ///
+    /// ### Parameters
+    /// * `scenario: &Scenario` - scenario artifact
+    /// ### Return
+    /// * `Result<Option<Window>, String>` - `None` if the scenario has no
+    ///   schedule, `Err` if the schedule is invalid
+    pub fn of(scenario: &Scenario) -> Result<Option<Self>, String> {
+        let Some(schedule) = scenario.get_schedule() else {
+            return Ok(None);
+        };
+        let timestamp = |value: String| {
+            DateTime::parse_from_rfc3339(&value)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|e| format!("invalid schedule timestamp '{}': {}", value, e))
+        };
+
+        let start = schedule.get_start().map(timestamp).transpose()?;
+        let end = schedule.get_end().map(timestamp).transpose()?;
+        if let (Some(start), Some(end)) = (start, end) {
+            if start >= end {
+                return Err(format!("schedule ends at {} before it starts", end));
+            }
+        }
+        let recurring = match schedule.get_recurring() {
+            Some(recurring) => {
+                let seconds = recurring.get_duration_seconds();
+                if seconds == 0 || seconds > MAX_DURATION_SECONDS {
+                    return Err(format!(
+                        "durationSeconds {} is out of 1-{}",
+                        seconds, MAX_DURATION_SECONDS
+                    ));
+                }
+                let cron = Cron::parse(&recurring.get_cron())?;
+                Some((cron, chrono::Duration::seconds(seconds as i64)))
+            }
+            None => None,
+        };
+
+        Ok(Some(Self {
+            start,
+            end,
+            recurring,
+        }))
+    }
+
+    /// Whether the window is open at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        if self.start.is_some_and(|start| now < start) || self.end.is_some_and(|end| now >= end) {
+            return false;
+        }
+        let Some((cron, duration)) = &self.recurring else {
+            return true;
+        };
+
+        // Search the minutes back to the longest possible window
+        let mut time = now
+            .with_second(0)
+            .and_then(|time| time.with_nanosecond(0))
+            .unwrap_or(now);
+        while now - time < *duration {
+            if cron.matches(time) {
+                return true;
+            }
+            time -= chrono::Duration::minutes(1);
+        }
+        false
+    }
+}
+
+/// Whether a scenario yaml string is inside its activation window
+///
+/// ### Description
+/// Scenarios without a valid schedule are always active.
+pub fn is_active(scenario: &str, now: DateTime<Utc>) -> bool {
+    match window_of(scenario) {
+        Some(window) => window.is_active(now),
+        None => true,
+    }
+}
+
+fn window_of(scenario: &str) -> Option<Window> {
+    let scenario = serde_yaml::from_str::<Scenario>(scenario).ok()?;
+    Window::of(&scenario).ok().flatten()
+}
+
+/// What a scheduler pass has to do for one scenario
+#[derive(Debug, PartialEq)]
+pub enum ScheduleAction {
+    /// Window opened, or no state was recorded for an open window
+    Activate { name: String, scenario: String },
+    /// Window closed, or no state was recorded for a closed window
+    Deactivate { name: String, scenario: String },
+    /// Scenario was withdrawn or lost its schedule
+    Forget { name: String },
+}
+
+/// Compare the windows with the recorded states
+///
+/// ### Parameters
+/// * `scenarios: &HashMap<String, String>` - persisted scenario yaml by name
+/// * `recorded: &HashMap<String, bool>` - recorded window state by name
+/// * `now: DateTime<Utc>` - time the windows are evaluated at
+/// ### Return
+/// * `Vec<ScheduleAction>` - actions for the changed scenarios, ordered by name
+pub fn plan(
+    scenarios: &HashMap<String, String>,
+    recorded: &HashMap<String, bool>,
+    now: DateTime<Utc>,
+) -> Vec<ScheduleAction> {
+    let names: BTreeSet<&String> = scenarios.keys().chain(recorded.keys()).collect();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let scenario = scenarios.get(name);
+            let window = scenario.and_then(|scenario| window_of(scenario));
+            match (scenario, window) {
+                (Some(scenario), Some(window)) => {
+                    let active = window.is_active(now);
+                    if recorded.get(name) == Some(&active) {
+                        None
+                    } else if active {
+                        Some(ScheduleAction::Activate {
+                            name: name.clone(),
+                            scenario: scenario.clone(),
+                        })
+                    } else {
+                        Some(ScheduleAction::Deactivate {
+                            name: name.clone(),
+                            scenario: scenario.clone(),
+                        })
+                    }
+                }
+                _ if recorded.contains_key(name) => {
+                    Some(ScheduleAction::Forget { name: name.clone() })
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Run scheduler passes forever
+///
+/// ### Description
+/// The first pass runs one interval after startup, `reload()` only re-applies
+/// the scenarios inside their window.
+pub async fn run() {
+    let mut interval = tokio::time::interval(SCHEDULE_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = schedule_once(Utc::now()).await {
+            println!("schedule: pass failed: {:?}", e);
+        }
+    }
+}
+
+/// Run a single scheduler pass
+///
+/// ### Parameters
+/// * `now: DateTime<Utc>` - time the windows are evaluated at
+/// ### Return
+/// * `Result<()>` - `Err` if the state could not be read from persistency
+pub async fn schedule_once(now: DateTime<Utc>) -> common::Result<()> {
+    let scenarios = crate::reconcile::read_prefix(crate::reconcile::DESIRED_PREFIX).await?;
+    let recorded = crate::reconcile::read_prefix(STATE_PREFIX)
+        .await?
+        .into_iter()
+        .filter_map(|(name, record)| {
+            let record: serde_json::Value = serde_json::from_str(&record).ok()?;
+            Some((name, record.get("state")?.as_str()? == "active"))
+        })
+        .collect();
+
+    for action in plan(&scenarios, &recorded, now) {
+        match action {
+            ScheduleAction::Activate { name, scenario } => {
+                println!("schedule: activation window of '{}' opened", name);
+                let outcome = match send(Action::Apply, &scenario).await {
+                    Ok(()) => crate::reconcile::record_applied(&scenario).await,
+                    Err(e) => Err(e),
+                };
+                match outcome {
+                    Ok(()) => record_state(&name, true).await,
+                    // Try again on the next pass
+                    Err(e) => println!("schedule: failed to activate '{}': {:?}", name, e),
+                }
+            }
+            ScheduleAction::Deactivate { name, scenario } => {
+                println!("schedule: activation window of '{}' closed", name);
+                let outcome = match send(Action::Withdraw, &scenario).await {
+                    Ok(()) => crate::reconcile::clear_applied(&scenario).await,
+                    Err(e) => Err(e),
+                };
+                match outcome {
+                    Ok(()) => {
+                        record_state(&name, false).await;
+                        set_idle(&name).await;
+                    }
+                    Err(e) => println!("schedule: failed to deactivate '{}': {:?}", name, e),
+                }
+            }
+            ScheduleAction::Forget { name } => {
+                let key = format!("{}{}", STATE_PREFIX, name);
+                if let Err(e) = common::persistency::delete(&key).await {
+                    println!("schedule: failed to forget '{}': {:?}", name, e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Record the window state of a newly applied scenario
+///
+/// ### Parameters
+/// * `scenario: &str` - scenario yaml about to be sent to filtergateway
+/// ### Return
+/// * `bool` - whether the scenario is active now and has to be sent
+pub async fn admit(scenario: &str) -> bool {
+    let Some(window) = window_of(scenario) else {
+        return true;
+    };
+    let Some(name) = crate::reconcile::scenario_name(scenario) else {
+        return true;
+    };
+    let active = window.is_active(Utc::now());
+    if !active {
+        println!(
+            "schedule: '{}' is outside its activation window, deferring it",
+            name
+        );
+    }
+    record_state(&name, active).await;
+    active
+}
+
+async fn send(action: Action, scenario: &str) -> common::Result<()> {
+    let req = common::filtergateway::HandleScenarioRequest {
+        action: action.into(),
+        scenario: scenario.to_string(),
+    };
+    crate::grpc::sender::filtergateway::send(req).await?;
+    Ok(())
+}
+
+async fn record_state(name: &str, active: bool) {
+    let record = serde_json::json!({
+        "state": if active { "active" } else { "inactive" },
+        "timestamp": Utc::now().to_rfc3339(),
+    });
+    let key = format!("{}{}", STATE_PREFIX, name);
+    if let Err(e) = common::persistency::put(&key, &record.to_string()).await {
+        println!("schedule: failed to record state of '{}': {:?}", name, e);
+    }
+}
+
+/// Move a deactivated scenario back to idle in the statemanager
+async fn set_idle(name: &str) {
+    let timestamp = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    let state_change = common::statemanager::StateChange {
+        resource_type: common::statemanager::ResourceType::Scenario as i32,
+        resource_name: name.to_string(),
+        // The statemanager knows the state the scenario reached
+        current_state: "".to_string(),
+        target_state: "idle".to_string(),
+        transition_id: format!("apiserver-scenario-schedule-{}", timestamp),
+        timestamp_ns: timestamp,
+        source: "apiserver".to_string(),
+    };
+
+    let mut state_sender = crate::grpc::sender::statemanager::StateManagerSender::new();
+    if let Err(e) = state_sender.send_state_change(state_change).await {
+        println!(
+            "schedule: failed to send state change of '{}' to StateManager: {:?}",
+            name, e
+        );
+    }
+}
+
+//UNIT Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scenario(name: &str, schedule: &str) -> String {
+        format!(
+            r#"apiVersion: v1
+kind: Scenario
+metadata:
+  name: {}
+spec:
+  condition:
+  action: update
+  target: {}
+{}"#,
+            name, name, schedule
+        )
+    }
+
+    fn time(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn window(schedule: &str) -> Result<Option<Window>, String> {
+        let scenario: Scenario = serde_yaml::from_str(&scenario("test", schedule)).unwrap();
+        Window::of(&scenario)
+    }
+
+    #[test]
+    fn test_cron_fields() {
+        let cron = Cron::parse("*/15 8-17 * * 1-5").unwrap();
+        // Friday
+        assert!(cron.matches(time("2026-10-16T08:45:00Z")));
+        assert!(!cron.matches(time("2026-10-16T08:50:00Z")));
+        assert!(!cron.matches(time("2026-10-16T18:00:00Z")));
+        // Saturday
+        assert!(!cron.matches(time("2026-10-17T08:45:00Z")));
+
+        let cron = Cron::parse("0 12 1,15 * 7").unwrap();
+        // Either day field matches: the 1st, the 15th or a Sunday
+        assert!(cron.matches(time("2026-10-01T12:00:00Z")));
+        assert!(cron.matches(time("2026-10-18T12:00:00Z")));
+        assert!(!cron.matches(time("2026-10-16T12:00:00Z")));
+    }
+
+    #[test]
+    fn test_invalid_cron_expressions() {
+        assert!(Cron::parse("0 8 * *").is_err());
+        assert!(Cron::parse("60 8 * * *").is_err());
+        assert!(Cron::parse("0 8 0 * *").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+        assert!(Cron::parse("0 17-8 * * *").is_err());
+        assert!(Cron::parse("0 8 * * mon").is_err());
+    }
+
+    #[test]
+    fn test_fixed_window() {
+        let window = window(
+            r#"  schedule:
+    start: "2026-10-16T08:00:00Z"
+    end: "2026-10-16T18:00:00+02:00"
+"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert!(!window.is_active(time("2026-10-16T07:59:59Z")));
+        assert!(window.is_active(time("2026-10-16T08:00:00Z")));
+        assert!(window.is_active(time("2026-10-16T15:59:59Z")));
+        assert!(!window.is_active(time("2026-10-16T16:00:00Z")));
+    }
+
+    #[test]
+    fn test_recurring_window_over_midnight() {
+        let window = window(
+            r#"  schedule:
+    recurring:
+      cron: "0 22 * * *"
+      durationSeconds: 28800
+"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert!(!window.is_active(time("2026-10-16T21:59:00Z")));
+        assert!(window.is_active(time("2026-10-16T22:00:00Z")));
+        assert!(window.is_active(time("2026-10-17T05:59:59Z")));
+        assert!(!window.is_active(time("2026-10-17T06:00:00Z")));
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        assert!(window("").unwrap().is_none());
+        assert!(window("  schedule:\n    start: tomorrow\n").is_err());
+        assert!(window(
+            "  schedule:\n    start: \"2026-10-16T18:00:00Z\"\n    end: \"2026-10-16T08:00:00Z\"\n"
+        )
+        .is_err());
+        assert!(window(
+            "  schedule:\n    recurring:\n      cron: \"0 22 * * *\"\n      durationSeconds: 0\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_plan_follows_windows() {
+        let open = scenario("open", "  schedule:\n    start: \"2026-10-16T08:00:00Z\"\n");
+        let closed = scenario("closed", "  schedule:\n    end: \"2026-10-16T08:00:00Z\"\n");
+        let scenarios: HashMap<String, String> = [
+            ("open".to_string(), open.clone()),
+            ("closed".to_string(), closed.clone()),
+            ("plain".to_string(), scenario("plain", "")),
+        ]
+        .into();
+        let now = time("2026-10-16T09:00:00Z");
+
+        assert_eq!(
+            plan(&scenarios, &HashMap::new(), now),
+            vec![
+                ScheduleAction::Deactivate {
+                    name: "closed".to_string(),
+                    scenario: closed,
+                },
+                ScheduleAction::Activate {
+                    name: "open".to_string(),
+                    scenario: open,
+                },
+            ]
+        );
+
+        let recorded: HashMap<String, bool> = [
+            ("open".to_string(), true),
+            ("closed".to_string(), false),
+            ("plain".to_string(), true),
+            ("withdrawn".to_string(), false),
+        ]
+        .into();
+        assert_eq!(
+            plan(&scenarios, &recorded, now),
+            vec![
+                ScheduleAction::Forget {
+                    name: "plain".to_string()
+                },
+                ScheduleAction::Forget {
+                    name: "withdrawn".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unscheduled_scenarios_are_active() {
+        let now = time("2026-10-16T09:00:00Z");
+        assert!(is_active(&scenario("plain", ""), now));
+        assert!(is_active("not: a scenario", now));
+        assert!(!is_active(
+            &scenario("closed", "  schedule:\n    end: \"2026-10-16T08:00:00Z\"\n"),
+            now
+        ));
+    }
+}
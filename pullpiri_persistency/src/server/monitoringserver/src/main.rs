@@ -59,6 +59,11 @@ async fn initialize(tx_container: Sender<ContainerList>, tx_node: Sender<NodeInf
 
 #[tokio::main]
 async fn main() {
+    // Identify this instance in the requests it sends, e.g. to the persistency service
+    common::identity::init(common::identity::ComponentIdentity::bootstrap(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+    ));
     println!("Starting MonitoringServer...");
 
     let (tx_container, rx_container) = channel::<ContainerList>(100);
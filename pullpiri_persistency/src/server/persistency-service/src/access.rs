@@ -0,0 +1,381 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Access control for persistency requests
+//!
+//! Native gRPC clients have full access, unless they are authenticated
+//! components restricted by the access control lists of [`crate::acl`].
+//! Requests that arrived through the gRPC-Web layer are marked with
+//! [`GrpcWebOrigin`] and are restricted to reading keys under the configured
+//! browser prefixes.
+//!
+//! Keys of a namespace are matched against the lists and the browser prefixes
+//! as `<namespace>/key`, see [`crate::namespace::tracked_key`], so access to
+//! `Vehicle/` of the default store grants nothing in the namespaces.
+//!
+//! The admin web UI authenticates with a bearer token and never shows the
+//! values of keys under the configured redaction prefixes.
+
+use crate::acl::Acl;
+use crate::auth::AuthenticatedComponent;
+use crate::config::ServiceConfig;
+use crate::namespace::tracked_key;
+use tonic::{Extensions, Status};
+
+/// Request extension marking requests translated by the gRPC-Web layer
+#[derive(Debug, Clone, Copy)]
+pub struct GrpcWebOrigin;
+
+/// Access policy applied by every RPC handler
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    browser_read_prefixes: Vec<String>,
+    /// Token expected from the admin web UI, the UI is locked without one
+    admin_token: Option<String>,
+    redact_prefixes: Vec<String>,
+    acl: Acl,
+}
+
+impl AccessPolicy {
+    /// Build the access policy from the service configuration
+    pub fn new(config: &ServiceConfig) -> Self {
+        Self {
+            browser_read_prefixes: config.grpc_web.read_prefixes.clone(),
+            admin_token: std::env::var(&config.admin_ui.token_env)
+                .ok()
+                .filter(|token| !token.is_empty()),
+            redact_prefixes: config.admin_ui.redact_prefixes.clone(),
+            acl: Acl::new(&config.acl),
+        }
+    }
+
+    fn is_browser(extensions: &Extensions) -> bool {
+        extensions.get::<GrpcWebOrigin>().is_some()
+    }
+
+    /// Component the request was authenticated for, `None` if it needed no token
+    pub fn component(extensions: &Extensions) -> Option<&str> {
+        extensions
+            .get::<AuthenticatedComponent>()
+            .map(|component| component.0.as_str())
+    }
+
+    /// Whether the caller may read `key` of `namespace`
+    pub fn can_read(&self, extensions: &Extensions, namespace: &str, key: &str) -> bool {
+        let key = tracked_key(namespace, key);
+        if Self::is_browser(extensions) {
+            return self
+                .browser_read_prefixes
+                .iter()
+                .any(|prefix| key.starts_with(prefix.as_str()));
+        }
+        Self::component(extensions).is_none_or(|component| self.acl.can_read(component, &key))
+    }
+
+    /// Reject reads of `key` of `namespace` the caller is not allowed to see
+    pub fn check_read(
+        &self,
+        extensions: &Extensions,
+        namespace: &str,
+        key: &str,
+    ) -> Result<(), Status> {
+        if self.can_read(extensions, namespace, key) {
+            return Ok(());
+        }
+        let key = tracked_key(namespace, key);
+        Err(Status::permission_denied(
+            match Self::component(extensions) {
+                Some(component) => format!("Key '{}' is not readable by '{}'", key, component),
+                None => format!("Key '{}' is not readable through gRPC-Web", key),
+            },
+        ))
+    }
+
+    /// Reject writes of `key` of `namespace`, or of all keys starting with
+    /// it, by callers not allowed to write it
+    ///
+    /// The empty key stands for the whole store of the namespace.
+    pub fn check_write(
+        &self,
+        extensions: &Extensions,
+        namespace: &str,
+        key: &str,
+    ) -> Result<(), Status> {
+        if Self::is_browser(extensions) {
+            return Err(Status::permission_denied(
+                "gRPC-Web clients have read-only access",
+            ));
+        }
+        let key = tracked_key(namespace, key);
+        match Self::component(extensions) {
+            Some(component) if !self.acl.can_write(component, &key) => {
+                Err(Status::permission_denied(format!(
+                    "Key '{}' is not writable by '{}'",
+                    key, component
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject streaming requests the gRPC-Web layer cannot carry
+    ///
+    /// The layer translates unary calls only, a streamed response would be
+    /// cut off after its first message.
+    pub fn check_streaming(&self, extensions: &Extensions) -> Result<(), Status> {
+        if self.can_stream(extensions) {
+            Ok(())
+        } else {
+            Err(Status::unimplemented(
+                "Streaming calls are not available through gRPC-Web",
+            ))
+        }
+    }
+
+    /// Whether the caller can receive streamed responses
+    pub fn can_stream(&self, extensions: &Extensions) -> bool {
+        !Self::is_browser(extensions)
+    }
+
+    /// Reject uses of a lease granted to `owner` by other callers
+    ///
+    /// Leases granted without authentication can be used by any native client.
+    pub fn check_lease(&self, extensions: &Extensions, owner: Option<&str>) -> Result<(), Status> {
+        if Self::is_browser(extensions) {
+            return Err(Status::permission_denied(
+                "gRPC-Web clients cannot use leases",
+            ));
+        }
+        Self::check_lease_holder(Self::component(extensions), owner)
+    }
+
+    /// Reject uses of a lease granted to `owner` by the authenticated `component`
+    pub fn check_lease_holder(component: Option<&str>, owner: Option<&str>) -> Result<(), Status> {
+        match owner {
+            Some(owner) if component != Some(owner) => Err(Status::permission_denied(format!(
+                "The lease is held by '{}'",
+                owner
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject admin requests from read-only callers and components without admin access
+    pub fn check_admin(&self, extensions: &Extensions) -> Result<(), Status> {
+        if Self::is_browser(extensions) {
+            return Err(Status::permission_denied(
+                "gRPC-Web clients cannot use admin operations",
+            ));
+        }
+        match Self::component(extensions) {
+            Some(component) if !self.acl.can_admin(component) => Err(Status::permission_denied(
+                format!("'{}' cannot use admin operations", component),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject admin web UI requests without the configured token
+    pub fn check_admin_token(&self, presented: Option<&str>) -> Result<(), Status> {
+        let Some(expected) = &self.admin_token else {
+            return Err(Status::permission_denied(
+                "No admin token is configured, the admin UI is locked",
+            ));
+        };
+        match presented {
+            Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+            _ => Err(Status::unauthenticated("Invalid admin token")),
+        }
+    }
+
+    /// Whether the value of `key` must be hidden from the admin web UI
+    pub fn is_redacted(&self, key: &str) -> bool {
+        self.redact_prefixes
+            .iter()
+            .any(|prefix| key.starts_with(prefix.as_str()))
+    }
+}
+
+/// Compare without leaking the position of the first difference through timing
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(prefixes: &[&str]) -> AccessPolicy {
+        let mut config = ServiceConfig::default();
+        config.grpc_web.read_prefixes = prefixes.iter().map(|p| p.to_string()).collect();
+        AccessPolicy::new(&config)
+    }
+
+    fn browser_extensions() -> Extensions {
+        let mut extensions = Extensions::new();
+        extensions.insert(GrpcWebOrigin);
+        extensions
+    }
+
+    #[test]
+    fn test_native_clients_have_full_access() {
+        let policy = policy(&[]);
+        let extensions = Extensions::new();
+        assert!(policy
+            .check_read(&extensions, "", "Scenario/helloworld")
+            .is_ok());
+        assert!(policy
+            .check_write(&extensions, "", "Scenario/helloworld")
+            .is_ok());
+        assert!(policy.check_admin(&extensions).is_ok());
+    }
+
+    #[test]
+    fn test_browser_reads_limited_to_prefixes() {
+        let policy = policy(&["vehicle/"]);
+        let extensions = browser_extensions();
+        assert!(policy.check_read(&extensions, "", "vehicle/mode").is_ok());
+        let err = policy
+            .check_read(&extensions, "", "Scenario/helloworld")
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn test_browser_writes_rejected() {
+        let policy = policy(&["vehicle/"]);
+        let err = policy
+            .check_write(&browser_extensions(), "", "vehicle/mode")
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn test_authenticated_components_limited_by_acl() {
+        let yaml = r#"
+acl:
+  enabled: true
+  components:
+    filtergateway:
+      read: ["Vehicle/"]
+      write: ["Vehicle/cmd/"]
+"#;
+        let policy = AccessPolicy::new(&ServiceConfig::from_yaml(yaml).unwrap());
+        let mut extensions = Extensions::new();
+        extensions.insert(AuthenticatedComponent("filtergateway".to_string()));
+
+        assert!(policy.check_read(&extensions, "", "Vehicle/speed").is_ok());
+        assert!(policy
+            .check_write(&extensions, "", "Vehicle/cmd/stop")
+            .is_ok());
+        let err = policy
+            .check_write(&extensions, "", "Vehicle/speed")
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert_eq!(
+            err.message(),
+            "Key 'Vehicle/speed' is not writable by 'filtergateway'"
+        );
+        assert!(!policy.can_read(&extensions, "", "Scenario/helloworld"));
+        assert!(policy.check_admin(&extensions).is_err());
+
+        // Requests of the lite protocol and the admin web UI are not authenticated
+        assert!(policy.check_write(&Extensions::new(), "", "").is_ok());
+    }
+
+    #[test]
+    fn test_acl_prefixes_apply_per_namespace() {
+        let yaml = r#"
+grpc_web:
+  read_prefixes: ["vehicle/"]
+acl:
+  enabled: true
+  components:
+    filtergateway:
+      read: ["Vehicle/"]
+      write: ["Vehicle/cmd/"]
+    apiserver:
+      write: ["<apiserver>/"]
+"#;
+        let policy = AccessPolicy::new(&ServiceConfig::from_yaml(yaml).unwrap());
+        let mut filtergateway = Extensions::new();
+        filtergateway.insert(AuthenticatedComponent("filtergateway".to_string()));
+        let mut apiserver = Extensions::new();
+        apiserver.insert(AuthenticatedComponent("apiserver".to_string()));
+
+        // Prefixes of the default store grant nothing in a namespace
+        assert!(!policy.can_read(&filtergateway, "apiserver", "Vehicle/speed"));
+        let err = policy
+            .check_write(&filtergateway, "apiserver", "Vehicle/cmd/stop")
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert_eq!(
+            err.message(),
+            "Key '<apiserver>/Vehicle/cmd/stop' is not writable by 'filtergateway'"
+        );
+
+        assert!(policy
+            .check_write(&apiserver, "apiserver", "Scenario/helloworld")
+            .is_ok());
+        assert!(policy.check_write(&apiserver, "apiserver", "").is_ok());
+        assert!(policy
+            .check_write(&apiserver, "", "Scenario/helloworld")
+            .is_err());
+        assert!(policy.check_write(&apiserver, "", "").is_err());
+
+        assert!(policy.can_read(&browser_extensions(), "", "vehicle/mode"));
+        assert!(!policy.can_read(&browser_extensions(), "apiserver", "vehicle/mode"));
+    }
+
+    #[test]
+    fn test_browser_streaming_rejected() {
+        let policy = policy(&["vehicle/"]);
+        assert!(policy.check_streaming(&Extensions::new()).is_ok());
+        let err = policy.check_streaming(&browser_extensions()).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unimplemented);
+    }
+
+    #[test]
+    fn test_lease_used_by_its_owner() {
+        let policy = policy(&[]);
+        let mut extensions = Extensions::new();
+        extensions.insert(AuthenticatedComponent("statemanager".to_string()));
+        assert!(policy
+            .check_lease(&extensions, Some("statemanager"))
+            .is_ok());
+        assert!(policy.check_lease(&extensions, None).is_ok());
+
+        let err = policy
+            .check_lease(&Extensions::new(), Some("statemanager"))
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert!(policy.check_lease(&browser_extensions(), None).is_err());
+    }
+
+    #[test]
+    fn test_admin_token_required() {
+        let mut policy = policy(&[]);
+        policy.admin_token = None;
+        let err = policy.check_admin_token(Some("secret")).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        policy.admin_token = Some("secret".to_string());
+        assert!(policy.check_admin_token(Some("secret")).is_ok());
+        for presented in [None, Some("secrex"), Some("secret2")] {
+            let err = policy.check_admin_token(presented).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        }
+    }
+
+    #[test]
+    fn test_redaction_by_prefix() {
+        let mut config = ServiceConfig::default();
+        config.admin_ui.redact_prefixes = vec!["credentials/".to_string()];
+        let policy = AccessPolicy::new(&config);
+        assert!(policy.is_redacted("credentials/wifi"));
+        assert!(!policy.is_redacted("vehicle/mode"));
+    }
+}
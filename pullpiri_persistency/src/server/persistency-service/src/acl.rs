@@ -0,0 +1,164 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Access control lists of the components
+//!
+//! Every component authenticated by [`crate::auth`] may only read and write
+//! keys under the prefixes configured for it, e.g. `apiserver` the keys
+//! under `Scenario` and `filtergateway` those under `Vehicle/`. Keys a
+//! component may write it may read as well. The empty key stands for the
+//! whole store, e.g. of `Reset`, and is only covered by the empty prefix.
+//! Components without an entry have no access at all.
+//!
+//! Keys of a namespace are checked as `<namespace>/key`, so a component needs
+//! prefixes like `<apiserver>/Scenario` for them. The whole store of a
+//! namespace is covered by `<namespace>/`, the empty prefix covers every
+//! namespace.
+//!
+//! The [`crate::access::AccessPolicy`] applies the lists to every request
+//! carrying an [`crate::auth::AuthenticatedComponent`].
+
+use crate::config::{AclConfig, ComponentAclConfig};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Access of one component
+#[derive(Debug, Clone, Default)]
+struct ComponentAcl {
+    read: Vec<String>,
+    write: Vec<String>,
+    admin: bool,
+}
+
+impl ComponentAcl {
+    fn new(config: &ComponentAclConfig) -> Self {
+        // `Vehicle/*` is configured as often as `Vehicle/`
+        let prefixes = |prefixes: &[String]| {
+            prefixes
+                .iter()
+                .map(|prefix| prefix.strip_suffix('*').unwrap_or(prefix).to_string())
+                .collect()
+        };
+        Self {
+            read: prefixes(&config.read),
+            write: prefixes(&config.write),
+            admin: config.admin,
+        }
+    }
+}
+
+fn covers(prefixes: &[String], key: &str) -> bool {
+    prefixes
+        .iter()
+        .any(|prefix| key.starts_with(prefix.as_str()))
+}
+
+/// Access control lists, allowing everything if disabled
+#[derive(Debug, Clone, Default)]
+pub struct Acl {
+    /// Access per component, `None` if the lists are disabled
+    components: Option<Arc<BTreeMap<String, ComponentAcl>>>,
+}
+
+impl Acl {
+    /// Build the lists from the service configuration
+    pub fn new(config: &AclConfig) -> Self {
+        let components = config
+            .components
+            .iter()
+            .map(|(component, acl)| (component.clone(), ComponentAcl::new(acl)))
+            .collect();
+        Self {
+            components: config.enabled.then(|| Arc::new(components)),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.components.is_some()
+    }
+
+    /// Access of `component`, `None` if it has no access at all
+    fn component(&self, component: &str) -> Option<&ComponentAcl> {
+        self.components.as_ref()?.get(component)
+    }
+
+    /// Whether `component` may read `key`
+    pub fn can_read(&self, component: &str, key: &str) -> bool {
+        !self.enabled()
+            || self
+                .component(component)
+                .is_some_and(|acl| covers(&acl.read, key) || covers(&acl.write, key))
+    }
+
+    /// Whether `component` may write `key`, all keys starting with it for a prefix
+    pub fn can_write(&self, component: &str, key: &str) -> bool {
+        !self.enabled()
+            || self
+                .component(component)
+                .is_some_and(|acl| covers(&acl.write, key))
+    }
+
+    /// Whether `component` may use admin operations
+    pub fn can_admin(&self, component: &str) -> bool {
+        !self.enabled() || self.component(component).is_some_and(|acl| acl.admin)
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acl() -> Acl {
+        let yaml = r#"
+enabled: true
+components:
+  apiserver:
+    write: ["Scenario*"]
+    read: ["Package/"]
+  filtergateway:
+    read: ["Vehicle/*"]
+  cli:
+    write: [""]
+    admin: true
+"#;
+        Acl::new(&serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn test_components_limited_to_prefixes() {
+        let acl = acl();
+        assert!(acl.can_write("apiserver", "Scenario/helloworld"));
+        assert!(acl.can_read("apiserver", "Scenario/helloworld"));
+        assert!(acl.can_read("apiserver", "Package/helloworld"));
+        assert!(!acl.can_write("apiserver", "Package/helloworld"));
+        assert!(!acl.can_read("apiserver", "Vehicle/speed"));
+
+        assert!(acl.can_read("filtergateway", "Vehicle/speed"));
+        assert!(!acl.can_read("filtergateway", "Vehicle"));
+        assert!(!acl.can_write("filtergateway", "Vehicle/speed"));
+    }
+
+    #[test]
+    fn test_whole_store_and_admin() {
+        let acl = acl();
+        assert!(!acl.can_write("apiserver", ""));
+        assert!(!acl.can_admin("apiserver"));
+        assert!(acl.can_write("cli", ""));
+        assert!(acl.can_admin("cli"));
+    }
+
+    #[test]
+    fn test_unknown_components_have_no_access() {
+        let acl = acl();
+        assert!(!acl.can_read("statemanager", "Scenario/helloworld"));
+        assert!(!acl.can_write("statemanager", "Scenario/helloworld"));
+        assert!(!acl.can_admin("statemanager"));
+
+        let disabled = Acl::default();
+        assert!(disabled.can_write("statemanager", ""));
+        assert!(disabled.can_admin("statemanager"));
+    }
+}
@@ -0,0 +1,404 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Admin web UI for field debugging
+//!
+//! Serves a single-page app and the JSON API behind it: browsing keys by
+//! prefix, editing string values, downloading a snapshot of the store,
+//! flushing and verifying the storage files. Field engineers only need a
+//! browser instead of CLI access and knowledge of the proto.
+//!
+//! Every API call is checked by the [`AccessPolicy`](crate::access::AccessPolicy):
+//! it needs the admin token as a bearer token, and values of redacted keys
+//! are replaced by [`REDACTED`] and cannot be edited. Edits and flushes go
+//! through the regular RPC handlers, so watchers, audit events and
+//! durability tracking see them like any other request.
+
+use crate::PersistencyServiceImpl;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use common::persistency_proto::persistency_service_server::PersistencyService;
+use common::persistency_proto::{
+    kvs_value, FlushRequest, GetValueRequest, KvsValue, SetValueRequest,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tonic::{Code, Request};
+use tracing::info;
+
+/// Shown instead of the value of a redacted key
+pub const REDACTED: &str = "[redacted]";
+
+const INDEX_HTML: &str = include_str!("admin_ui.html");
+
+type Service = Arc<PersistencyServiceImpl>;
+
+/// Error response of the API
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.0,
+            Json(json!({ "success": false, "error_message": self.1 })),
+        )
+            .into_response()
+    }
+}
+
+impl From<tonic::Status> for ApiError {
+    fn from(status: tonic::Status) -> Self {
+        let code = match status.code() {
+            Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Code::PermissionDenied => StatusCode::FORBIDDEN,
+            Code::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(code, status.message().to_string())
+    }
+}
+
+type ApiResult = Result<Json<Value>, ApiError>;
+
+/// Router of the UI and its API
+///
+/// ### Parameters
+/// * `service` - service whose store is administered
+pub fn router(service: Service) -> Router {
+    Router::new()
+        .route("/", get(|| async { Html(INDEX_HTML) }))
+        .route("/api/keys", get(list_keys))
+        .route("/api/keys/*key", put(set_string))
+        .route("/api/snapshot", get(snapshot))
+        .route("/api/flush", post(flush))
+        .route("/api/verify", post(verify))
+        .with_state(service)
+}
+
+/// Serve the UI on `listen_addr` until the process ends
+pub async fn serve(listen_addr: &str, service: Service) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen_addr).await?;
+    info!("Admin web UI listening on http://{}", listen_addr);
+    axum::serve(listener, router(service)).await
+}
+
+fn authorize(service: &Service, headers: &HeaderMap) -> Result<(), ApiError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    Ok(service.access().check_admin_token(token)?)
+}
+
+/// Export of the keys under `prefix` with redacted values replaced
+async fn redacted_export(
+    service: &Service,
+    prefix: &str,
+) -> Result<crate::export::Export, ApiError> {
+    let prefixes = if prefix.is_empty() {
+        vec![]
+    } else {
+        vec![prefix.to_string()]
+    };
+    let mut export = service.export(&prefixes).await.map_err(|e| {
+        ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read keys: {:?}", e),
+        )
+    })?;
+    for (key, value) in export.entries.iter_mut() {
+        if service.access().is_redacted(key) {
+            *value = Value::String(REDACTED.to_string());
+        }
+    }
+    Ok(export)
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ListQuery {
+    prefix: String,
+}
+
+async fn list_keys(
+    State(service): State<Service>,
+    headers: HeaderMap,
+    Query(query): Query<ListQuery>,
+) -> ApiResult {
+    authorize(&service, &headers)?;
+    let export = redacted_export(&service, &query.prefix).await?;
+    let keys: Vec<Value> = export
+        .entries
+        .into_iter()
+        .map(|(key, value)| {
+            let redacted = service.access().is_redacted(&key);
+            json!({ "key": key, "value": value, "redacted": redacted })
+        })
+        .collect();
+    Ok(Json(json!({ "success": true, "keys": keys })))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetStringBody {
+    value: String,
+}
+
+/// Replace the value of an existing string key
+async fn set_string(
+    State(service): State<Service>,
+    headers: HeaderMap,
+    Path(key): Path<String>,
+    Json(body): Json<SetStringBody>,
+) -> ApiResult {
+    authorize(&service, &headers)?;
+    if service.access().is_redacted(&key) {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            format!("Key '{}' is redacted", key),
+        ));
+    }
+
+    // Only string values are editable, so the UI never changes the type of a value
+    let current = service
+        .get_value(Request::new(GetValueRequest {
+            key: key.clone(),
+            namespace: String::new(),
+        }))
+        .await?
+        .into_inner();
+    match current.value.and_then(|value| value.value) {
+        Some(kvs_value::Value::StringValue(_)) => {}
+        Some(_) => {
+            return Err(ApiError(
+                StatusCode::CONFLICT,
+                format!("Key '{}' does not hold a string value", key),
+            ));
+        }
+        None => {
+            return Err(ApiError(
+                StatusCode::NOT_FOUND,
+                format!("Key '{}' not found", key),
+            ))
+        }
+    }
+
+    let response = service
+        .set_value(Request::new(SetValueRequest {
+            key: key.clone(),
+            value: Some(KvsValue {
+                value: Some(kvs_value::Value::StringValue(body.value)),
+            }),
+            ttl_seconds: 0,
+            namespace: String::new(),
+            expected_revision: 0,
+            lease_id: 0,
+        }))
+        .await?
+        .into_inner();
+    if !response.success {
+        return Err(ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            response.error_message,
+        ));
+    }
+    info!("Admin UI changed key {}", key);
+    Ok(Json(json!({ "success": true })))
+}
+
+/// Consistent snapshot of every key, for download
+async fn snapshot(State(service): State<Service>, headers: HeaderMap) -> ApiResult {
+    authorize(&service, &headers)?;
+    let export = redacted_export(&service, "").await?;
+    Ok(Json(json!(export)))
+}
+
+async fn flush(State(service): State<Service>, headers: HeaderMap) -> ApiResult {
+    authorize(&service, &headers)?;
+    let response = service
+        .flush(Request::new(FlushRequest {
+            namespace: String::new(),
+        }))
+        .await?
+        .into_inner();
+    if !response.success {
+        return Err(ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            response.error_message,
+        ));
+    }
+    Ok(Json(json!({ "success": true })))
+}
+
+async fn verify(State(service): State<Service>, headers: HeaderMap) -> ApiResult {
+    authorize(&service, &headers)?;
+    let snapshots = service.verify_storage().await;
+    let valid = snapshots.iter().all(|snapshot| snapshot.valid);
+    Ok(Json(
+        json!({ "success": true, "valid": valid, "snapshots": snapshots }),
+    ))
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request as HttpRequest;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "admin-ui-test-token";
+
+    fn service() -> Service {
+        std::env::set_var("PERSISTENCY_ADMIN_UI_TEST_TOKEN", TOKEN);
+        let mut config = crate::config::ServiceConfig::default();
+        config.admin_ui.token_env = "PERSISTENCY_ADMIN_UI_TEST_TOKEN".to_string();
+        config.admin_ui.redact_prefixes = vec!["admin_ui_test/secret/".to_string()];
+        Arc::new(PersistencyServiceImpl::with_config(&config).unwrap())
+    }
+
+    async fn call(
+        service: &Service,
+        method: &str,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = HttpRequest::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let body = match body {
+            Some(body) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = router(service.clone())
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    async fn put_value(service: &Service, key: &str, value: kvs_value::Value) {
+        service
+            .set_value(Request::new(SetValueRequest {
+                key: key.to_string(),
+                value: Some(KvsValue { value: Some(value) }),
+                ttl_seconds: 0,
+                namespace: String::new(),
+                expected_revision: 0,
+                lease_id: 0,
+            }))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_requires_token() {
+        let service = service();
+        let (status, _) = call(&service, "GET", "/api/keys", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&service, "POST", "/api/flush", Some("wrong"), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_browse_redacts_and_edits_strings_only() {
+        let service = service();
+        put_value(
+            &service,
+            "admin_ui_test/name",
+            kvs_value::Value::StringValue("old".to_string()),
+        )
+        .await;
+        put_value(
+            &service,
+            "admin_ui_test/count",
+            kvs_value::Value::I32Value(3),
+        )
+        .await;
+        put_value(
+            &service,
+            "admin_ui_test/secret/pin",
+            kvs_value::Value::StringValue("1234".to_string()),
+        )
+        .await;
+
+        let (status, body) = call(
+            &service,
+            "GET",
+            "/api/keys?prefix=admin_ui_test/",
+            Some(TOKEN),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let keys = body["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 3);
+        let secret = keys
+            .iter()
+            .find(|entry| entry["key"] == "admin_ui_test/secret/pin")
+            .unwrap();
+        assert_eq!(secret["value"], REDACTED);
+        assert_eq!(secret["redacted"], true);
+
+        let edit = Some(json!({ "value": "new" }));
+        let (status, _) = call(
+            &service,
+            "PUT",
+            "/api/keys/admin_ui_test/name",
+            Some(TOKEN),
+            edit.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(
+            &service,
+            "PUT",
+            "/api/keys/admin_ui_test/count",
+            Some(TOKEN),
+            edit.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = call(
+            &service,
+            "PUT",
+            "/api/keys/admin_ui_test/secret/pin",
+            Some(TOKEN),
+            edit,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (_, body) = call(&service, "GET", "/api/snapshot", Some(TOKEN), None).await;
+        assert_eq!(body["entries"]["admin_ui_test/name"], "new");
+        assert_eq!(body["entries"]["admin_ui_test/secret/pin"], REDACTED);
+    }
+
+    #[tokio::test]
+    async fn test_flush_and_verify() {
+        let service = service();
+        let (status, _) = call(&service, "POST", "/api/flush", Some(TOKEN), None).await;
+        assert_eq!(status, StatusCode::OK);
+        // Other tests share the store files, so only the report itself is checked here
+        let (status, body) = call(&service, "POST", "/api/verify", Some(TOKEN), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["valid"].is_boolean());
+        assert!(!body["snapshots"].as_array().unwrap().is_empty());
+    }
+}
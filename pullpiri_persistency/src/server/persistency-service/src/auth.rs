@@ -0,0 +1,241 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Authentication of the calling components
+//!
+//! With authentication enabled, the [`AuthInterceptor`] admits a gRPC request
+//! only if its [`AUTHORIZATION_METADATA`] carries the bearer token configured
+//! for the component named in its identity metadata. A component's token is
+//! therefore of no use to act as another component. Requests translated by
+//! the gRPC-Web layer need a token as well; only with
+//! `auth.anonymous_grpc_web` they pass without one, still read-only, see
+//! [`crate::access`]. Admitted requests carry the
+//! [`AuthenticatedComponent`] extension, which the access control lists of
+//! [`crate::acl`] are applied to.
+//!
+//! Requests of the REST gateway and the lite protocol are authenticated with
+//! [`AuthInterceptor::authenticate`] as well. Requests of the admin web UI do
+//! not pass the interceptor, the UI is protected by its own token.
+
+use crate::access::{constant_time_eq, GrpcWebOrigin};
+use crate::config::AuthConfig;
+use common::identity::NAME_METADATA;
+use common::persistency_proto::AUTHORIZATION_METADATA;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+use tracing::warn;
+
+/// Request extension naming the component whose token the request carried
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedComponent(pub String);
+
+/// Interceptor rejecting requests without the token of their component
+#[derive(Debug, Clone, Default)]
+pub struct AuthInterceptor {
+    /// Token per component, `None` if authentication is disabled
+    tokens: Option<Arc<BTreeMap<String, String>>>,
+    /// Admit gRPC-Web requests without a token
+    anonymous_grpc_web: bool,
+}
+
+impl AuthInterceptor {
+    /// Build the interceptor from the service configuration
+    ///
+    /// Components configured with an empty token cannot authenticate.
+    pub fn new(config: &AuthConfig) -> Self {
+        let tokens = config
+            .tokens
+            .iter()
+            .filter(|(_, token)| !token.is_empty())
+            .map(|(component, token)| (component.clone(), token.clone()))
+            .collect();
+        Self {
+            tokens: config.enabled.then(|| Arc::new(tokens)),
+            anonymous_grpc_web: config.anonymous_grpc_web,
+        }
+    }
+
+    /// Reject `request` unless it carries the token of its component
+    ///
+    /// Returns the authenticated component, `None` for requests that need no
+    /// token.
+    pub fn authenticate(
+        &self,
+        request: &Request<()>,
+    ) -> Result<Option<AuthenticatedComponent>, Status> {
+        let Some(tokens) = &self.tokens else {
+            return Ok(None);
+        };
+        if self.anonymous_grpc_web && request.extensions().get::<GrpcWebOrigin>().is_some() {
+            return Ok(None);
+        }
+        let metadata = request.metadata();
+        let Some(component) = metadata
+            .get(NAME_METADATA)
+            .and_then(|value| value.to_str().ok())
+            .filter(|component| !component.is_empty())
+        else {
+            return Err(Status::unauthenticated("Request names no component"));
+        };
+        let presented = metadata
+            .get(AUTHORIZATION_METADATA)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match (tokens.get(component), presented) {
+            (Some(expected), Some(token))
+                if constant_time_eq(token.as_bytes(), expected.as_bytes()) =>
+            {
+                Ok(Some(AuthenticatedComponent(component.to_string())))
+            }
+            _ => {
+                warn!(component, "Rejected request without a valid token");
+                Err(Status::unauthenticated(format!(
+                    "Invalid token for component '{}'",
+                    component
+                )))
+            }
+        }
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(component) = self.authenticate(&request)? {
+            request.extensions_mut().insert(component);
+        }
+        Ok(request)
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn interceptor() -> AuthInterceptor {
+        AuthInterceptor::new(&AuthConfig {
+            enabled: true,
+            tokens: [
+                ("apiserver".to_string(), "3f9c2a".to_string()),
+                ("statemanager".to_string(), String::new()),
+            ]
+            .into(),
+            anonymous_grpc_web: false,
+        })
+    }
+
+    fn request(component: Option<&str>, authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(component) = component {
+            request
+                .metadata_mut()
+                .insert(NAME_METADATA, component.parse().unwrap());
+        }
+        if let Some(authorization) = authorization {
+            request
+                .metadata_mut()
+                .insert(AUTHORIZATION_METADATA, authorization.parse().unwrap());
+        }
+        request
+    }
+
+    fn code(interceptor: &AuthInterceptor, request: Request<()>) -> Option<Code> {
+        interceptor
+            .authenticate(&request)
+            .err()
+            .map(|status| status.code())
+    }
+
+    #[test]
+    fn test_component_token_is_required() {
+        let auth = interceptor();
+        assert_eq!(
+            code(&auth, request(Some("apiserver"), Some("Bearer 3f9c2a"))),
+            None
+        );
+
+        let unauthenticated = Some(Code::Unauthenticated);
+        assert_eq!(
+            code(&auth, request(Some("apiserver"), None)),
+            unauthenticated
+        );
+        assert_eq!(
+            code(&auth, request(Some("apiserver"), Some("Bearer 3f9c2b"))),
+            unauthenticated
+        );
+        assert_eq!(
+            code(&auth, request(Some("apiserver"), Some("3f9c2a"))),
+            unauthenticated
+        );
+        // The token of another component, no token configured, no identity
+        assert_eq!(
+            code(&auth, request(Some("filtergateway"), Some("Bearer 3f9c2a"))),
+            unauthenticated
+        );
+        assert_eq!(
+            code(&auth, request(Some("statemanager"), Some("Bearer "))),
+            unauthenticated
+        );
+        assert_eq!(
+            code(&auth, request(None, Some("Bearer 3f9c2a"))),
+            unauthenticated
+        );
+    }
+
+    #[test]
+    fn test_admitted_requests_name_the_component() {
+        let admitted = interceptor()
+            .call(request(Some("apiserver"), Some("Bearer 3f9c2a")))
+            .unwrap();
+        assert_eq!(
+            admitted.extensions().get::<AuthenticatedComponent>(),
+            Some(&AuthenticatedComponent("apiserver".to_string()))
+        );
+
+        let mut disabled = AuthInterceptor::new(&AuthConfig::default());
+        let admitted = disabled.call(request(Some("apiserver"), None)).unwrap();
+        assert!(admitted
+            .extensions()
+            .get::<AuthenticatedComponent>()
+            .is_none());
+    }
+
+    #[test]
+    fn test_disabled_requests_pass() {
+        let disabled = AuthInterceptor::new(&AuthConfig::default());
+        assert_eq!(code(&disabled, request(None, None)), None);
+    }
+
+    #[test]
+    fn test_browser_requests_need_a_token() {
+        let browser = |component, authorization| {
+            let mut request = request(component, authorization);
+            request.extensions_mut().insert(GrpcWebOrigin);
+            request
+        };
+        assert_eq!(
+            code(&interceptor(), browser(None, None)),
+            Some(Code::Unauthenticated)
+        );
+        assert_eq!(
+            code(
+                &interceptor(),
+                browser(Some("apiserver"), Some("Bearer 3f9c2a"))
+            ),
+            None
+        );
+
+        let mut anonymous = interceptor();
+        anonymous.anonymous_grpc_web = true;
+        assert_eq!(code(&anonymous, browser(None, None)), None);
+        assert_eq!(
+            code(&anonymous, request(None, None)),
+            Some(Code::Unauthenticated)
+        );
+    }
+}
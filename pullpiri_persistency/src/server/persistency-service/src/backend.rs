@@ -0,0 +1,231 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Storage engines of the stores
+//!
+//! Every store keeps its keys in a [`StorageBackend`]. rust_kvs holds the
+//! keys in memory and writes the whole store to a JSON file with rotating
+//! snapshots on every flush. Deployments with other durability or
+//! performance needs select another engine with `storage.backend`, built
+//! into the service with the cargo feature of the same name:
+//!
+//! * `sled` - embedded log-structured database in `kvs_<instance>.sled/`
+//! * `sqlite` - SQLite database in `kvs_<instance>.sqlite`
+//!
+//! The `memory` backend, always built in, keeps the keys in the service for
+//! tests, see [`memory::MemoryBackend`].
+//!
+//! sled and SQLite keep the values in their protobuf encoding and make the writes
+//! durable on flush, like rust_kvs. Snapshots are a rust_kvs feature, and
+//! with them the recovery of the storage files at startup, their
+//! verification and restores from a snapshot, see [`StorageBackend::as_kvs`].
+//! Stored keys are not converted when the backend changes, an export and a
+//! restore move them over.
+
+pub mod memory;
+#[cfg(feature = "sled")]
+mod sled;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use crate::config::{StorageBackendKind, StorageConfig};
+use crate::recovery::snapshot_files;
+use crate::PersistencyServiceImpl;
+use common::persistency_proto::KvsValue as ProtoValue;
+use prost::Message;
+use rust_kvs::kvs_value::KvsValue;
+use rust_kvs::prelude::*;
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+/// Key-value storage of one store
+///
+/// The operations of rust_kvs the service relies on. Writes may be held in
+/// memory until [`StorageBackend::flush`].
+pub trait StorageBackend: Send + Sync {
+    /// Value of `key`, [`ErrorCode::KeyNotFound`] if it does not exist
+    fn get_value(&self, key: &str) -> Result<KvsValue, ErrorCode>;
+
+    fn set_value(&self, key: &str, value: KvsValue) -> Result<(), ErrorCode>;
+
+    /// Remove `key`, [`ErrorCode::KeyNotFound`] if it does not exist
+    fn remove_key(&self, key: &str) -> Result<(), ErrorCode>;
+
+    fn key_exists(&self, key: &str) -> Result<bool, ErrorCode>;
+
+    /// Every stored key in no particular order
+    fn get_all_keys(&self) -> Result<Vec<String>, ErrorCode>;
+
+    /// Make the writes durable
+    fn flush(&self) -> Result<(), ErrorCode>;
+
+    /// Remove every key
+    fn reset(&self) -> Result<(), ErrorCode>;
+
+    /// The rust_kvs instance, `None` for the backends without its snapshots
+    fn as_kvs(&self) -> Option<&Kvs> {
+        None
+    }
+}
+
+impl StorageBackend for Kvs {
+    fn get_value(&self, key: &str) -> Result<KvsValue, ErrorCode> {
+        KvsApi::get_value(self, key)
+    }
+
+    fn set_value(&self, key: &str, value: KvsValue) -> Result<(), ErrorCode> {
+        KvsApi::set_value(self, key, value)
+    }
+
+    fn remove_key(&self, key: &str) -> Result<(), ErrorCode> {
+        KvsApi::remove_key(self, key)
+    }
+
+    fn key_exists(&self, key: &str) -> Result<bool, ErrorCode> {
+        KvsApi::key_exists(self, key)
+    }
+
+    fn get_all_keys(&self) -> Result<Vec<String>, ErrorCode> {
+        KvsApi::get_all_keys(self)
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        KvsApi::flush(self)
+    }
+
+    fn reset(&self) -> Result<(), ErrorCode> {
+        KvsApi::reset(self)
+    }
+
+    fn as_kvs(&self) -> Option<&Kvs> {
+        Some(self)
+    }
+}
+
+/// Open the store of rust_kvs instance `instance` with the configured backend
+///
+/// rust_kvs keeps its files in the working directory, the other backends in
+/// `dir`. Backends the service is built without are rejected.
+#[cfg_attr(
+    not(any(feature = "sled", feature = "sqlite")),
+    allow(unused_variables)
+)]
+pub fn open(
+    config: &StorageConfig,
+    dir: &Path,
+    instance: usize,
+) -> Result<Box<dyn StorageBackend>, ErrorCode> {
+    match config.backend {
+        StorageBackendKind::RustKvs => Ok(Box::new(KvsBuilder::new(InstanceId(instance)).build()?)),
+        #[cfg(feature = "sled")]
+        StorageBackendKind::Sled => Ok(Box::new(sled::SledBackend::open(&storage_path(
+            dir, instance, "sled",
+        ))?)),
+        #[cfg(feature = "sqlite")]
+        StorageBackendKind::Sqlite => Ok(Box::new(sqlite::SqliteBackend::open(&storage_path(
+            dir, instance, "sqlite",
+        ))?)),
+        StorageBackendKind::Memory => Ok(Box::<memory::MemoryBackend>::default()),
+        #[allow(unreachable_patterns)]
+        kind => {
+            error!(
+                "The service is built without the {:?} storage backend, enable its cargo feature",
+                kind
+            );
+            Err(ErrorCode::ValidationFailed)
+        }
+    }
+}
+
+/// Where sled and SQLite keep the keys of `instance` in `dir`
+fn storage_path(dir: &Path, instance: usize, extension: &str) -> PathBuf {
+    dir.join(format!("kvs_{}.{}", instance, extension))
+}
+
+/// File holding the keys of `instance` in `dir`, whose size is reported
+///
+/// `None` for the memory backend.
+pub fn storage_file(kind: StorageBackendKind, dir: &Path, instance: usize) -> Option<PathBuf> {
+    match kind {
+        StorageBackendKind::RustKvs => Some(snapshot_files(dir, instance, 0).0),
+        StorageBackendKind::Sled => Some(storage_path(dir, instance, "sled").join("db")),
+        StorageBackendKind::Sqlite => Some(storage_path(dir, instance, "sqlite")),
+        StorageBackendKind::Memory => None,
+    }
+}
+
+/// Stored form of `value` in the backends other than rust_kvs
+#[cfg_attr(not(any(feature = "sled", feature = "sqlite")), allow(dead_code))]
+fn encode_value(value: &KvsValue) -> Vec<u8> {
+    PersistencyServiceImpl::kvs_value_to_proto(value).encode_to_vec()
+}
+
+/// Value of the stored form `bytes`
+#[cfg_attr(not(any(feature = "sled", feature = "sqlite")), allow(dead_code))]
+fn decode_value(bytes: &[u8]) -> Result<KvsValue, ErrorCode> {
+    let proto = ProtoValue::decode(bytes).map_err(|e| {
+        error!("Failed to decode a stored value: {}", e);
+        ErrorCode::ConversionFailed
+    })?;
+    PersistencyServiceImpl::proto_to_kvs_value(&proto).map_err(|e| {
+        error!("Failed to convert a stored value: {}", e);
+        ErrorCode::ConversionFailed
+    })
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_values_round_trip() {
+        let mut object = HashMap::new();
+        object.insert("gear".to_string(), KvsValue::U32(3));
+        for value in [
+            KvsValue::String("autonomous".to_string()),
+            KvsValue::F64(12.5),
+            KvsValue::Boolean(true),
+            KvsValue::Array(vec![KvsValue::I32(-1), KvsValue::Null]),
+            KvsValue::Object(object),
+        ] {
+            assert_eq!(decode_value(&encode_value(&value)).unwrap(), value);
+        }
+        assert!(decode_value(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn test_storage_files() {
+        let dir = Path::new("/var/lib/piccolo");
+        assert_eq!(
+            storage_file(StorageBackendKind::RustKvs, dir, 2),
+            Some(dir.join("kvs_2_0.json"))
+        );
+        assert_eq!(
+            storage_file(StorageBackendKind::Sled, dir, 2),
+            Some(dir.join("kvs_2.sled/db"))
+        );
+        assert_eq!(
+            storage_file(StorageBackendKind::Sqlite, dir, 2),
+            Some(dir.join("kvs_2.sqlite"))
+        );
+        assert_eq!(storage_file(StorageBackendKind::Memory, dir, 2), None);
+    }
+
+    #[test]
+    fn test_backend_without_feature_rejected() {
+        let config = StorageConfig {
+            backend: StorageBackendKind::Sled,
+        };
+        if cfg!(not(feature = "sled")) {
+            let dir = std::env::temp_dir();
+            assert!(matches!(
+                open(&config, &dir, 1),
+                Err(ErrorCode::ValidationFailed)
+            ));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! In-memory backend
+//!
+//! Keys and values live in a map of the service and are lost with it. Tests
+//! use it through [`crate::PersistencyServiceImpl::new_in_memory`] to run
+//! against a service that touches no files.
+
+use super::StorageBackend;
+use rust_kvs::kvs_value::KvsValue;
+use rust_kvs::prelude::*;
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Store kept in memory only
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    keys: RwLock<HashMap<String, KvsValue>>,
+}
+
+impl MemoryBackend {
+    fn keys(&self) -> Result<RwLockReadGuard<'_, HashMap<String, KvsValue>>, ErrorCode> {
+        self.keys.read().map_err(|_| ErrorCode::MutexLockFailed)
+    }
+
+    fn keys_mut(&self) -> Result<RwLockWriteGuard<'_, HashMap<String, KvsValue>>, ErrorCode> {
+        self.keys.write().map_err(|_| ErrorCode::MutexLockFailed)
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn get_value(&self, key: &str) -> Result<KvsValue, ErrorCode> {
+        self.keys()?.get(key).cloned().ok_or(ErrorCode::KeyNotFound)
+    }
+
+    fn set_value(&self, key: &str, value: KvsValue) -> Result<(), ErrorCode> {
+        self.keys_mut()?.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove_key(&self, key: &str) -> Result<(), ErrorCode> {
+        match self.keys_mut()?.remove(key) {
+            Some(_) => Ok(()),
+            None => Err(ErrorCode::KeyNotFound),
+        }
+    }
+
+    fn key_exists(&self, key: &str) -> Result<bool, ErrorCode> {
+        Ok(self.keys()?.contains_key(key))
+    }
+
+    fn get_all_keys(&self) -> Result<Vec<String>, ErrorCode> {
+        Ok(self.keys()?.keys().cloned().collect())
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), ErrorCode> {
+        self.keys_mut()?.clear();
+        Ok(())
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_kept_in_memory() {
+        let backend = MemoryBackend::default();
+        backend
+            .set_value("vehicle/mode", KvsValue::String("autonomous".to_string()))
+            .unwrap();
+        backend.set_value("vehicle/gear", KvsValue::U32(3)).unwrap();
+        backend.remove_key("vehicle/gear").unwrap();
+        assert!(matches!(
+            backend.remove_key("vehicle/gear"),
+            Err(ErrorCode::KeyNotFound)
+        ));
+        assert!(matches!(
+            backend.get_value("vehicle/gear"),
+            Err(ErrorCode::KeyNotFound)
+        ));
+        backend.flush().unwrap();
+        assert_eq!(backend.get_all_keys().unwrap(), vec!["vehicle/mode"]);
+
+        backend.reset().unwrap();
+        assert!(!backend.key_exists("vehicle/mode").unwrap());
+    }
+}
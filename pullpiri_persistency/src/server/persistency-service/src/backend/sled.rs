@@ -0,0 +1,115 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! sled backend
+//!
+//! Keys and values live in the default tree of a sled database. sled keeps
+//! recently used pages in memory and logs the writes, a flush makes them
+//! durable.
+
+use super::{decode_value, encode_value, StorageBackend};
+use rust_kvs::kvs_value::KvsValue;
+use rust_kvs::prelude::*;
+use std::path::Path;
+use tracing::error;
+
+fn storage_error(e: ::sled::Error) -> ErrorCode {
+    error!("sled storage failed: {}", e);
+    ErrorCode::PhysicalStorageFailure
+}
+
+/// Store kept in a sled database
+pub struct SledBackend {
+    db: ::sled::Db,
+}
+
+impl SledBackend {
+    /// Open or create the database in the directory `dir`
+    pub fn open(dir: &Path) -> Result<Self, ErrorCode> {
+        let db = ::sled::open(dir).map_err(storage_error)?;
+        Ok(Self { db })
+    }
+}
+
+impl StorageBackend for SledBackend {
+    fn get_value(&self, key: &str) -> Result<KvsValue, ErrorCode> {
+        match self.db.get(key).map_err(storage_error)? {
+            Some(bytes) => decode_value(&bytes),
+            None => Err(ErrorCode::KeyNotFound),
+        }
+    }
+
+    fn set_value(&self, key: &str, value: KvsValue) -> Result<(), ErrorCode> {
+        self.db
+            .insert(key, encode_value(&value))
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn remove_key(&self, key: &str) -> Result<(), ErrorCode> {
+        match self.db.remove(key).map_err(storage_error)? {
+            Some(_) => Ok(()),
+            None => Err(ErrorCode::KeyNotFound),
+        }
+    }
+
+    fn key_exists(&self, key: &str) -> Result<bool, ErrorCode> {
+        self.db.contains_key(key).map_err(storage_error)
+    }
+
+    fn get_all_keys(&self) -> Result<Vec<String>, ErrorCode> {
+        self.db
+            .iter()
+            .keys()
+            .map(|key| Ok(String::from_utf8(key.map_err(storage_error)?.to_vec())?))
+            .collect()
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        self.db.flush().map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), ErrorCode> {
+        self.db.clear().map_err(storage_error)
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_survive_reopening() {
+        let dir = std::env::temp_dir().join(format!("persistency-sled-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let backend = SledBackend::open(&dir).unwrap();
+        backend
+            .set_value("vehicle/mode", KvsValue::String("autonomous".to_string()))
+            .unwrap();
+        backend.set_value("vehicle/gear", KvsValue::U32(3)).unwrap();
+        backend.remove_key("vehicle/gear").unwrap();
+        assert!(matches!(
+            backend.remove_key("vehicle/gear"),
+            Err(ErrorCode::KeyNotFound)
+        ));
+        backend.flush().unwrap();
+        drop(backend);
+
+        let backend = SledBackend::open(&dir).unwrap();
+        assert_eq!(backend.get_all_keys().unwrap(), vec!["vehicle/mode"]);
+        assert_eq!(
+            backend.get_value("vehicle/mode").unwrap(),
+            KvsValue::String("autonomous".to_string())
+        );
+        backend.reset().unwrap();
+        assert!(!backend.key_exists("vehicle/mode").unwrap());
+
+        drop(backend);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,174 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! SQLite backend
+//!
+//! Keys and values live in the table `kvs` of a SQLite database. Writes
+//! open a transaction that the next flush commits, so that a burst of
+//! writes costs one commit rather than one each, and a crash loses the
+//! writes since the last flush like with rust_kvs.
+
+use super::{decode_value, encode_value, StorageBackend};
+use rusqlite::{params, Connection, OptionalExtension};
+use rust_kvs::kvs_value::KvsValue;
+use rust_kvs::prelude::*;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use tracing::error;
+
+fn storage_error(e: rusqlite::Error) -> ErrorCode {
+    error!("SQLite storage failed: {}", e);
+    ErrorCode::PhysicalStorageFailure
+}
+
+/// Store kept in a SQLite database
+pub struct SqliteBackend {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteBackend {
+    /// Open or create the database file `path`
+    pub fn open(path: &Path) -> Result<Self, ErrorCode> {
+        let connection = Connection::open(path).map_err(storage_error)?;
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 CREATE TABLE IF NOT EXISTS kvs (key TEXT PRIMARY KEY, value BLOB NOT NULL);",
+            )
+            .map_err(storage_error)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>, ErrorCode> {
+        self.connection
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)
+    }
+
+    /// Connection inside the transaction the next flush commits
+    fn writer(&self) -> Result<MutexGuard<'_, Connection>, ErrorCode> {
+        let connection = self.connection()?;
+        if connection.is_autocommit() {
+            connection.execute_batch("BEGIN").map_err(storage_error)?;
+        }
+        Ok(connection)
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn get_value(&self, key: &str) -> Result<KvsValue, ErrorCode> {
+        let bytes: Option<Vec<u8>> = self
+            .connection()?
+            .query_row("SELECT value FROM kvs WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(storage_error)?;
+        decode_value(&bytes.ok_or(ErrorCode::KeyNotFound)?)
+    }
+
+    fn set_value(&self, key: &str, value: KvsValue) -> Result<(), ErrorCode> {
+        self.writer()?
+            .execute(
+                "INSERT OR REPLACE INTO kvs (key, value) VALUES (?1, ?2)",
+                params![key, encode_value(&value)],
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn remove_key(&self, key: &str) -> Result<(), ErrorCode> {
+        let removed = self
+            .writer()?
+            .execute("DELETE FROM kvs WHERE key = ?1", [key])
+            .map_err(storage_error)?;
+        if removed == 0 {
+            return Err(ErrorCode::KeyNotFound);
+        }
+        Ok(())
+    }
+
+    fn key_exists(&self, key: &str) -> Result<bool, ErrorCode> {
+        self.connection()?
+            .query_row("SELECT 1 FROM kvs WHERE key = ?1", [key], |_| Ok(()))
+            .optional()
+            .map(|found| found.is_some())
+            .map_err(storage_error)
+    }
+
+    fn get_all_keys(&self) -> Result<Vec<String>, ErrorCode> {
+        let connection = self.connection()?;
+        let mut statement = connection
+            .prepare("SELECT key FROM kvs")
+            .map_err(storage_error)?;
+        let keys = statement
+            .query_map([], |row| row.get(0))
+            .map_err(storage_error)?
+            .collect::<Result<_, _>>()
+            .map_err(storage_error)?;
+        Ok(keys)
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        let connection = self.connection()?;
+        if !connection.is_autocommit() {
+            connection.execute_batch("COMMIT").map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), ErrorCode> {
+        self.writer()?
+            .execute("DELETE FROM kvs", [])
+            .map_err(storage_error)?;
+        Ok(())
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flush_commits_the_writes() {
+        let dir = std::env::temp_dir().join(format!("persistency-sqlite-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("kvs_1.sqlite");
+
+        let backend = SqliteBackend::open(&file).unwrap();
+        backend
+            .set_value("vehicle/mode", KvsValue::String("autonomous".to_string()))
+            .unwrap();
+        backend.flush().unwrap();
+        backend.set_value("vehicle/gear", KvsValue::U32(3)).unwrap();
+        assert!(backend.key_exists("vehicle/gear").unwrap());
+        assert!(matches!(
+            backend.remove_key("vehicle/speed"),
+            Err(ErrorCode::KeyNotFound)
+        ));
+        // Not flushed, lost when the backend goes away
+        drop(backend);
+
+        let backend = SqliteBackend::open(&file).unwrap();
+        assert_eq!(backend.get_all_keys().unwrap(), vec!["vehicle/mode"]);
+        assert_eq!(
+            backend.get_value("vehicle/mode").unwrap(),
+            KvsValue::String("autonomous".to_string())
+        );
+        backend.reset().unwrap();
+        backend.flush().unwrap();
+        assert!(matches!(
+            backend.get_value("vehicle/mode"),
+            Err(ErrorCode::KeyNotFound)
+        ));
+
+        drop(backend);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,257 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Scheduled local backups with rotation
+//!
+//! The backup task periodically exports the configured prefixes of the
+//! default store to a file `backup-<unix time ms>.json` in the backup
+//! directory and removes the oldest backups beyond the configured number.
+//! Backups are export documents, see [`crate::export`], and are restored with
+//! `RestoreSnapshot` or `Restore`. `CreateBackup` writes a backup on demand,
+//! `ListBackups` lists the kept ones.
+//!
+//! The first scheduled backup is only written one interval after the start,
+//! so that a store that had to be recovered at startup does not push the
+//! older backups out right away.
+
+use crate::config::BackupConfig;
+use crate::export::Export;
+use crate::PersistencyServiceImpl;
+use common::persistency_proto::BackupFile as BackupFileProto;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{info, warn};
+
+/// Prefix of the backup file names
+const FILE_PREFIX: &str = "backup-";
+/// Extension of the backup file names
+const FILE_EXTENSION: &str = "json";
+
+/// A backup in the backup directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupFile {
+    pub name: String,
+    /// Unix time of the export in milliseconds
+    pub created_at_ms: u64,
+    pub size_bytes: u64,
+}
+
+impl BackupFile {
+    pub fn to_proto(&self) -> BackupFileProto {
+        BackupFileProto {
+            name: self.name.clone(),
+            created_at_ms: self.created_at_ms,
+            size_bytes: self.size_bytes,
+        }
+    }
+}
+
+/// Outcome of writing a backup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatedBackup {
+    pub file: BackupFile,
+    /// Keys in the backup
+    pub keys: usize,
+    /// Names of the older backups removed by the rotation
+    pub removed: Vec<String>,
+}
+
+/// Export time of a backup file name, `None` for other files
+fn created_at_ms(name: &str) -> Option<u64> {
+    name.strip_prefix(FILE_PREFIX)?
+        .strip_suffix(FILE_EXTENSION)?
+        .strip_suffix('.')?
+        .parse()
+        .ok()
+}
+
+/// Backups in the backup directory
+#[derive(Debug)]
+pub struct Backups {
+    dir: PathBuf,
+    keep: usize,
+    /// Held while a backup is written and rotated
+    writing: Mutex<()>,
+}
+
+impl Backups {
+    /// Backups in `config.dir`, relative to `base_dir`
+    pub fn new(config: &BackupConfig, base_dir: &Path) -> Self {
+        Self {
+            dir: base_dir.join(&config.dir),
+            keep: config.keep.max(1),
+            writing: Mutex::new(()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Kept backups, newest first
+    pub fn list(&self) -> std::io::Result<Vec<BackupFile>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            // No backup written yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut backups = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(created_at_ms) = created_at_ms(&name) else {
+                continue;
+            };
+            backups.push(BackupFile {
+                name,
+                created_at_ms,
+                size_bytes: entry.metadata()?.len(),
+            });
+        }
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at_ms));
+        Ok(backups)
+    }
+
+    /// Write `export` as a new backup and remove the oldest beyond the kept number
+    ///
+    /// The file is written under a temporary name first, so that a backup
+    /// interrupted by power loss never replaces an intact one.
+    pub fn write(&self, export: &Export) -> Result<CreatedBackup, String> {
+        let _writing = self.writing.lock().unwrap();
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create backup directory {:?}: {}", self.dir, e))?;
+        let content =
+            serde_json::to_vec(export).map_err(|e| format!("Failed to encode backup: {}", e))?;
+        let name = format!(
+            "{}{}.{}",
+            FILE_PREFIX, export.exported_at_ms, FILE_EXTENSION
+        );
+        let path = self.dir.join(&name);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &content)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| format!("Failed to write backup {:?}: {}", path, e))?;
+
+        let mut removed = Vec::new();
+        match self.list() {
+            Ok(backups) => {
+                for old in backups.iter().skip(self.keep) {
+                    match fs::remove_file(self.dir.join(&old.name)) {
+                        Ok(()) => removed.push(old.name.clone()),
+                        Err(e) => warn!("Failed to remove old backup {}: {}", old.name, e),
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to list backups for the rotation: {}", e),
+        }
+        Ok(CreatedBackup {
+            file: BackupFile {
+                name,
+                created_at_ms: export.exported_at_ms,
+                size_bytes: content.len() as u64,
+            },
+            keys: export.entries.len(),
+            removed,
+        })
+    }
+}
+
+/// Write a backup every `config.interval_secs` forever
+///
+/// ### Parameters
+/// * `config: BackupConfig` - backup configuration
+/// * `service: Arc<PersistencyServiceImpl>` - service whose store is backed up
+pub async fn run(config: BackupConfig, service: Arc<PersistencyServiceImpl>) {
+    let period = Duration::from_secs(config.interval_secs.max(1));
+    info!(
+        "Backing up prefixes {:?} to {:?} every {}s, keeping {}",
+        config.prefixes,
+        service.backups().dir(),
+        period.as_secs(),
+        config.keep
+    );
+    let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Err(e) = service.create_backup().await {
+            warn!("Scheduled backup failed: {}", e);
+        }
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(exported_at_ms: u64, keys: &[&str]) -> Export {
+        Export {
+            format_version: crate::export::EXPORT_FORMAT_VERSION,
+            exported_at_ms,
+            prefixes: Vec::new(),
+            entries: keys
+                .iter()
+                .map(|key| (key.to_string(), serde_json::json!(1)))
+                .collect(),
+            history_id: 1,
+            revision: 0,
+            base_revision: None,
+            removed: Vec::new(),
+        }
+    }
+
+    fn backups(name: &str, keep: usize) -> Backups {
+        let dir = std::env::temp_dir().join(format!("persistency-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = BackupConfig {
+            keep,
+            ..BackupConfig::default()
+        };
+        Backups::new(&config, &dir)
+    }
+
+    #[test]
+    fn test_file_names() {
+        assert_eq!(
+            created_at_ms("backup-1700000000000.json"),
+            Some(1_700_000_000_000)
+        );
+        assert_eq!(created_at_ms("backup-1700000000000.tmp"), None);
+        assert_eq!(created_at_ms("backup-.json"), None);
+        assert_eq!(created_at_ms("kvs_0_0.json"), None);
+    }
+
+    #[test]
+    fn test_write_and_rotate() {
+        let backups = backups("backup-rotate", 2);
+        assert!(backups.list().unwrap().is_empty());
+
+        let first = backups.write(&export(1_000, &["a", "b"])).unwrap();
+        assert_eq!(first.file.name, "backup-1000.json");
+        assert_eq!(first.keys, 2);
+        assert!(first.removed.is_empty());
+        backups.write(&export(3_000, &["a"])).unwrap();
+        // Files that are no backups are left alone
+        fs::write(backups.dir().join("notes.txt"), "keep").unwrap();
+
+        let third = backups.write(&export(2_000, &[])).unwrap();
+        assert_eq!(third.removed, ["backup-1000.json"]);
+        let kept = backups.list().unwrap();
+        let names: Vec<&str> = kept.iter().map(|backup| backup.name.as_str()).collect();
+        assert_eq!(names, ["backup-3000.json", "backup-2000.json"]);
+        assert_eq!(kept[1], third.file);
+        assert!(backups.dir().join("notes.txt").exists());
+
+        let content = fs::read(backups.dir().join("backup-3000.json")).unwrap();
+        let restored: Export = serde_json::from_slice(&content).unwrap();
+        assert_eq!(restored, export(3_000, &["a"]));
+        let _ = fs::remove_dir_all(backups.dir());
+    }
+}
@@ -0,0 +1,428 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Read cache of the hot keys
+//!
+//! Components poll some keys, e.g. the state of the current scenario,
+//! hundreds of times per second. `GetValue` answers them from a cache of the
+//! `max_keys` most recently read values in front of rust_kvs, without going
+//! through the spill files, the decryption or the value conversion again.
+//!
+//! Every write and removal of the service invalidates the key, see
+//! [`crate::PersistencyServiceImpl`]. A read takes a [`CacheTicket`] before
+//! it reads the store, and its value is only cached if no key of its stripe
+//! was invalidated since, so that a read racing with a write cannot cache the
+//! overwritten value. Invalidations are counted per stripe of the key locks,
+//! see [`crate::striping`], so that a steady stream of writes to other keys
+//! does not keep the hot keys from being cached. Values of encrypted keys are not cached, their
+//! plaintext is not kept in memory, see [`crate::encryption`].
+//!
+//! Shadow reads verify the cache in the field: with
+//! `read_cache.shadow_read_percent`, that share of the hits reads the store
+//! as well, sampled deterministically like the trace sampling. A cached value
+//! that differs from the stored one is logged, counted on the metrics
+//! endpoint and dropped, and the stored value is answered. Hits of a key
+//! whose stripe was written meanwhile are not compared, a write of the key
+//! invalidated them anyway.
+
+use crate::config::ReadCacheConfig;
+use crate::striping::{self, STRIPES};
+use common::persistency_proto::KvsValue;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::error;
+
+/// Shadow read shares are stored as parts per million
+const RATE_SCALE: u64 = 1_000_000;
+
+#[derive(Debug)]
+struct Entry {
+    /// Position in the least recently used order
+    tick: u64,
+    value: KvsValue,
+}
+
+#[derive(Debug)]
+struct State {
+    entries: HashMap<String, Entry>,
+    /// Cached keys by tick, least recently used first
+    lru: BTreeMap<u64, String>,
+    tick: u64,
+    /// Invalidations since the start, per stripe
+    invalidations: Vec<u64>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            invalidations: vec![0; STRIPES],
+        }
+    }
+}
+
+impl State {
+    /// Invalidations of the stripe of `tracked`
+    fn invalidations(&self, tracked: &str) -> u64 {
+        self.invalidations[striping::stripe(tracked, STRIPES)]
+    }
+}
+
+/// Invalidations of the stripe of a key seen by a read before it read the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheTicket(u64);
+
+/// Hits and size of the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub keys: u64,
+    pub max_keys: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub shadow_reads: u64,
+    pub mismatches: u64,
+}
+
+/// Most recently read values by tracked key
+#[derive(Debug)]
+pub struct ReadCache {
+    enabled: bool,
+    max_keys: usize,
+    state: Mutex<State>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// Share of the hits read from the store as well, in parts per million
+    shadow_rate: u64,
+    /// Hits sampled for shadow reads so far
+    sampled: AtomicU64,
+    shadow_reads: AtomicU64,
+    mismatches: AtomicU64,
+}
+
+impl ReadCache {
+    pub fn new(config: &ReadCacheConfig) -> Self {
+        Self {
+            enabled: config.enabled && config.max_keys > 0,
+            max_keys: config.max_keys,
+            state: Mutex::new(State::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            shadow_rate: (config.shadow_read_percent.clamp(0.0, 100.0) / 100.0 * RATE_SCALE as f64)
+                .round() as u64,
+            sampled: AtomicU64::new(0),
+            shadow_reads: AtomicU64::new(0),
+            mismatches: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Cached value of `tracked`, counting the hit or miss
+    pub fn get(&self, tracked: &str) -> Option<KvsValue> {
+        if !self.enabled {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let State { entries, lru, .. } = &mut *state;
+        let Some(entry) = entries.get_mut(tracked) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        lru.remove(&entry.tick);
+        lru.insert(tick, tracked.to_string());
+        entry.tick = tick;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(entry.value.clone())
+    }
+
+    /// Ticket of a read of `tracked` about to read the store
+    pub fn ticket(&self, tracked: &str) -> CacheTicket {
+        CacheTicket(self.state.lock().unwrap().invalidations(tracked))
+    }
+
+    /// Whether the next hit is verified with a shadow read
+    pub fn shadow_read(&self) -> bool {
+        if self.shadow_rate == 0 {
+            return false;
+        }
+        let n = self.sampled.fetch_add(1, Ordering::Relaxed);
+        // Verify whenever the accumulated share crosses the next integer
+        (n + 1) * self.shadow_rate / RATE_SCALE > n * self.shadow_rate / RATE_SCALE
+    }
+
+    /// Compare the `cached` value of `tracked` with the `stored` one, `None` if it is not stored
+    ///
+    /// `ticket` is the one taken before the cache was read. Returns the value
+    /// to answer: the stored one, unless no key of the stripe of `tracked` was
+    /// invalidated since the ticket and both are equal. A mismatch drops the cached value.
+    pub fn verify(
+        &self,
+        tracked: &str,
+        cached: KvsValue,
+        stored: Option<KvsValue>,
+        ticket: CacheTicket,
+    ) -> Option<KvsValue> {
+        let mut state = self.state.lock().unwrap();
+        if state.invalidations(tracked) != ticket.0 {
+            return stored;
+        }
+        self.shadow_reads.fetch_add(1, Ordering::Relaxed);
+        if stored.as_ref() == Some(&cached) {
+            return Some(cached);
+        }
+        self.mismatches.fetch_add(1, Ordering::Relaxed);
+        error!(
+            "Read cache holds {:?} for key {}, the store {:?}",
+            cached, tracked, stored
+        );
+        if let Some(entry) = state.entries.remove(tracked) {
+            state.lru.remove(&entry.tick);
+        }
+        stored
+    }
+
+    /// Cache `value` read for `tracked` with `ticket`
+    ///
+    /// Ignored if a key of the stripe of `tracked` was invalidated since the
+    /// ticket was taken. The least recently read key is evicted beyond
+    /// `max_keys`.
+    pub fn insert(&self, tracked: &str, value: KvsValue, ticket: CacheTicket) {
+        if !self.enabled {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.invalidations(tracked) != ticket.0 {
+            return;
+        }
+        state.tick += 1;
+        let tick = state.tick;
+        let State { entries, lru, .. } = &mut *state;
+        if let Some(entry) = entries.insert(tracked.to_string(), Entry { tick, value }) {
+            lru.remove(&entry.tick);
+        }
+        lru.insert(tick, tracked.to_string());
+        while entries.len() > self.max_keys {
+            let Some((_, key)) = lru.pop_first() else {
+                break;
+            };
+            entries.remove(&key);
+        }
+    }
+
+    /// Account a write or removal of `tracked`
+    pub fn invalidate(&self, tracked: &str) {
+        if !self.enabled {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.invalidations[striping::stripe(tracked, STRIPES)] += 1;
+        if let Some(entry) = state.entries.remove(tracked) {
+            state.lru.remove(&entry.tick);
+        }
+    }
+
+    /// Current size and counters
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            keys: self.state.lock().unwrap().entries.len() as u64,
+            max_keys: self.max_keys as u64,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            shadow_reads: self.shadow_reads.load(Ordering::Relaxed),
+            mismatches: self.mismatches.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Size and counters in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let stats = self.stats();
+        let metrics = [
+            (
+                "persistency_read_cache_keys",
+                "gauge",
+                "Values held in the read cache",
+                stats.keys,
+            ),
+            (
+                "persistency_read_cache_max_keys",
+                "gauge",
+                "Values the read cache may hold",
+                stats.max_keys,
+            ),
+            (
+                "persistency_read_cache_hits_total",
+                "counter",
+                "GetValue requests answered from the read cache",
+                stats.hits,
+            ),
+            (
+                "persistency_read_cache_misses_total",
+                "counter",
+                "GetValue requests that read the store",
+                stats.misses,
+            ),
+            (
+                "persistency_read_cache_shadow_reads_total",
+                "counter",
+                "Cache hits compared with the store",
+                stats.shadow_reads,
+            ),
+            (
+                "persistency_read_cache_mismatches_total",
+                "counter",
+                "Cache hits that differed from the store",
+                stats.mismatches,
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(
+                out,
+                "# HELP {} {}\n# TYPE {} {}\n{} {}",
+                name, help, name, kind, name, value
+            );
+        }
+        out
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::persistency_proto::kvs_value::Value;
+
+    fn value(v: i32) -> KvsValue {
+        KvsValue {
+            value: Some(Value::I32Value(v)),
+        }
+    }
+
+    fn cache(max_keys: usize) -> ReadCache {
+        ReadCache::new(&ReadCacheConfig {
+            enabled: true,
+            max_keys,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_hits_and_misses() {
+        let cache = cache(4);
+        assert!(cache.get("scenario/state").is_none());
+        cache.insert("scenario/state", value(1), cache.ticket("scenario/state"));
+        assert_eq!(cache.get("scenario/state"), Some(value(1)));
+
+        let stats = cache.stats();
+        assert_eq!((stats.keys, stats.hits, stats.misses), (1, 1, 1));
+        assert!(cache
+            .render_metrics()
+            .contains("persistency_read_cache_hits_total 1\n"));
+    }
+
+    #[test]
+    fn test_least_recently_read_key_evicted() {
+        let cache = cache(2);
+        cache.insert("a", value(1), cache.ticket("a"));
+        cache.insert("b", value(2), cache.ticket("b"));
+        cache.get("a");
+        cache.insert("c", value(3), cache.ticket("c"));
+
+        assert_eq!(cache.get("a"), Some(value(1)));
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("c"), Some(value(3)));
+        assert_eq!(cache.stats().keys, 2);
+    }
+
+    #[test]
+    fn test_writes_invalidate() {
+        let cache = cache(4);
+        cache.insert("scenario/state", value(1), cache.ticket("scenario/state"));
+        cache.invalidate("scenario/state");
+        assert!(cache.get("scenario/state").is_none());
+
+        // A read that started before a write does not cache what it read
+        let ticket = cache.ticket("scenario/state");
+        cache.invalidate("scenario/state");
+        cache.insert("scenario/state", value(1), ticket);
+        assert!(cache.get("scenario/state").is_none());
+    }
+
+    #[test]
+    fn test_writes_of_other_stripes_do_not_hold_off_fills() {
+        let cache = cache(4);
+        let stripe = striping::stripe("scenario/state", STRIPES);
+        let other = (0..)
+            .map(|i| format!("vehicle/other/{}", i))
+            .find(|other| striping::stripe(other, STRIPES) != stripe)
+            .unwrap();
+
+        let ticket = cache.ticket("scenario/state");
+        cache.invalidate(&other);
+        cache.insert("scenario/state", value(1), ticket);
+        assert_eq!(cache.get("scenario/state"), Some(value(1)));
+    }
+
+    #[test]
+    fn test_shadow_reads() {
+        let cache = ReadCache::new(&ReadCacheConfig {
+            shadow_read_percent: 50.0,
+            ..Default::default()
+        });
+        cache.insert("scenario/state", value(1), cache.ticket("scenario/state"));
+        let sampled: Vec<_> = (0..4)
+            .map(|_| {
+                cache.get("scenario/state");
+                cache.shadow_read()
+            })
+            .collect();
+        assert_eq!(sampled, vec![false, true, false, true]);
+
+        let ticket = cache.ticket("scenario/state");
+        let cached = cache.get("scenario/state").unwrap();
+        assert_eq!(
+            cache.verify("scenario/state", cached.clone(), Some(value(1)), ticket),
+            Some(value(1))
+        );
+        // A stale value is dropped and the stored one answered
+        assert_eq!(
+            cache.verify("scenario/state", cached.clone(), Some(value(2)), ticket),
+            Some(value(2))
+        );
+        assert!(cache.get("scenario/state").is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.shadow_reads, stats.mismatches), (2, 1));
+
+        // Written meanwhile, nothing to compare
+        cache.insert("scenario/state", value(1), cache.ticket("scenario/state"));
+        let ticket = cache.ticket("scenario/state");
+        cache.invalidate("scenario/state");
+        assert_eq!(cache.verify("scenario/state", value(1), None, ticket), None);
+        assert_eq!(cache.stats().mismatches, 1);
+        assert!(cache
+            .render_metrics()
+            .contains("persistency_read_cache_mismatches_total 1\n"));
+        assert!(!ReadCache::new(&ReadCacheConfig::default()).shadow_read());
+    }
+
+    #[test]
+    fn test_disabled_cache_holds_nothing() {
+        let cache = ReadCache::new(&ReadCacheConfig {
+            enabled: false,
+            ..Default::default()
+        });
+        assert!(!cache.is_enabled());
+        cache.insert("scenario/state", value(1), cache.ticket("scenario/state"));
+        assert!(cache.get("scenario/state").is_none());
+    }
+}
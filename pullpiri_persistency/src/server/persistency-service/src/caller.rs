@@ -0,0 +1,131 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Attribution of requests to the calling component
+//!
+//! Pullpiri components send their [`ComponentIdentity`] as gRPC metadata.
+//! The [`CallerLayer`] runs every request inside a `persistency_caller` span
+//! naming the caller, so that all logs of the request attribute it, and makes
+//! the identity available to the handlers through [`caller`] for audit events
+//! and hot-key reports. Requests without identity, e.g. from browsers, are
+//! attributed to no component.
+
+use common::identity::ComponentIdentity;
+use http::{Request, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::{info_span, Instrument};
+
+tokio::task_local! {
+    static CALLER: Option<Arc<ComponentIdentity>>;
+}
+
+/// Identity of the component whose request is being handled
+///
+/// `None` outside of a request or if the caller did not identify itself.
+pub fn caller() -> Option<Arc<ComponentIdentity>> {
+    CALLER.try_with(|caller| caller.clone()).ok().flatten()
+}
+
+/// Name of the calling component instance as shown in reports, empty if unknown
+pub fn caller_name() -> String {
+    caller()
+        .map(|caller| caller.to_string())
+        .unwrap_or_default()
+}
+
+/// Run `f` on behalf of `caller`, for requests that do not pass the [`CallerLayer`]
+pub fn scope<R>(caller: Option<Arc<ComponentIdentity>>, f: impl FnOnce() -> R) -> R {
+    CALLER.sync_scope(caller, f)
+}
+
+/// Tower layer attributing every request to its caller
+#[derive(Debug, Clone, Default)]
+pub struct CallerLayer;
+
+impl CallerLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for CallerLayer {
+    type Service = CallerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CallerService { inner }
+    }
+}
+
+/// Service produced by [`CallerLayer`]
+#[derive(Debug, Clone)]
+pub struct CallerService<S> {
+    inner: S,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for CallerService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let headers = req.headers();
+        let caller =
+            ComponentIdentity::from_lookup(|name| headers.get(name)?.to_str().ok()).map(Arc::new);
+        let span = match &caller {
+            Some(caller) => info_span!(
+                "persistency_caller",
+                component = %caller.name,
+                instance = %caller.instance,
+                version = %caller.version,
+                node = %caller.node
+            ),
+            None => info_span!("persistency_caller", component = "unknown"),
+        };
+        let future = self.inner.call(req);
+        Box::pin(CALLER.scope(caller, future.instrument(span)))
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Empty;
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn test_layer_scopes_caller() {
+        let inner = tower::service_fn(|_req: Request<Empty<bytes::Bytes>>| async {
+            Ok::<_, Infallible>(Response::new(caller_name()))
+        });
+        let mut service = CallerLayer::new().layer(inner);
+
+        let request = Request::builder()
+            .header("x-component-name", "apiserver")
+            .header("x-component-instance", "4711")
+            .header("x-component-version", "0.1.0")
+            .header("x-component-node", "HPC")
+            .body(Empty::new())
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+        assert_eq!(response.body(), "apiserver/4711@HPC 0.1.0");
+
+        let response = service.call(Request::new(Empty::new())).await.unwrap();
+        assert_eq!(response.body(), "");
+        assert!(caller().is_none());
+    }
+}
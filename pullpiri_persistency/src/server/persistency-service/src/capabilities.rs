@@ -0,0 +1,109 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Capabilities reported by `GetCapabilities`
+//!
+//! Vehicles run service builds that are older or newer than the client
+//! library of their components. Clients ask for the API version and the
+//! optional features when they connect, and leave the features the service
+//! lacks alone instead of failing when they first use one. The features
+//! depend on the configuration and on the caller: gRPC-Web callers cannot
+//! use streaming calls.
+
+use common::persistency_proto::feature;
+
+/// Optional features available to a caller, sorted
+///
+/// `streaming` tells whether the caller can receive streamed responses,
+/// `namespaces` whether any namespace is configured, `key_history` whether
+/// the versions of the keys are kept, `trash` whether removed keys can be
+/// moved to the trash, `leases` whether leases and the locks held by them
+/// are granted. Leases are kept alive over a stream, so they need
+/// `streaming` as well.
+pub fn features(
+    streaming: bool,
+    namespaces: bool,
+    key_history: bool,
+    trash: bool,
+    leases: bool,
+) -> Vec<String> {
+    let mut features = vec![
+        feature::REVISIONS,
+        feature::STATUS_CODES,
+        feature::TRANSACTIONS,
+        feature::TTL,
+    ];
+    if streaming {
+        features.extend([
+            feature::WATCH,
+            feature::WATCH_FILTERS,
+            feature::VALUE_STREAM,
+            feature::SCAN,
+            feature::LARGE_VALUES,
+        ]);
+    }
+    if namespaces {
+        features.push(feature::NAMESPACES);
+    }
+    if key_history {
+        features.push(feature::KEY_HISTORY);
+    }
+    if trash {
+        features.push(feature::TRASH);
+    }
+    if streaming && leases {
+        features.extend([feature::LEASES, feature::LOCKS]);
+    }
+    features.sort_unstable();
+    features.into_iter().map(str::to_string).collect()
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_features() {
+        assert_eq!(
+            features(true, true, true, true, true),
+            vec![
+                "key_history",
+                "large_values",
+                "leases",
+                "locks",
+                "namespaces",
+                "revisions",
+                "scan",
+                "status_codes",
+                "transactions",
+                "trash",
+                "ttl",
+                "value_stream",
+                "watch",
+                "watch_filters"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_features_depend_on_caller_and_config() {
+        assert_eq!(
+            features(false, false, false, false, false),
+            vec!["revisions", "status_codes", "transactions", "ttl"]
+        );
+        assert!(!features(false, true, false, false, false).contains(&feature::WATCH.to_string()));
+        assert!(
+            !features(true, false, false, false, false).contains(&feature::NAMESPACES.to_string())
+        );
+        assert!(
+            !features(true, true, false, false, false).contains(&feature::KEY_HISTORY.to_string())
+        );
+        assert!(!features(true, true, true, false, false).contains(&feature::TRASH.to_string()));
+        assert!(!features(true, true, true, true, false).contains(&feature::LEASES.to_string()));
+        assert!(!features(false, true, true, true, true).contains(&feature::LEASES.to_string()));
+        assert!(!features(false, true, true, true, true).contains(&feature::LOCKS.to_string()));
+    }
+}
@@ -0,0 +1,87 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Chunks of the `GetValueStream` RPC
+//!
+//! Artifact yaml and package blobs reach hundreds of KB. Sending them in
+//! chunks lets clients parse them while they arrive, instead of receiving
+//! one message with the whole value and copying it into strings. The chunks
+//! are cut at byte boundaries, the first one carries the size of the whole
+//! value, so that clients can allocate their buffer once.
+
+use common::persistency_proto::GetValueStreamResponse;
+
+/// Chunk size of requests without one
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest chunk size, well below the 4 MiB message limit of tonic
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Chunk size to use for the `requested` one, 0 for the default
+pub fn chunk_size(requested: u32) -> usize {
+    match requested as usize {
+        0 => DEFAULT_CHUNK_SIZE,
+        requested => requested.min(MAX_CHUNK_SIZE),
+    }
+}
+
+/// Responses carrying `value` in chunks of `chunk_size` bytes
+///
+/// An empty value is sent as one empty chunk. The chunks are copied out of
+/// `value` one at a time while the stream is consumed.
+pub fn chunks(
+    value: Vec<u8>,
+    chunk_size: usize,
+) -> impl Iterator<Item = GetValueStreamResponse> + Send {
+    let chunk_size = chunk_size.max(1);
+    let total_size = value.len() as u64;
+    (0..value.len().max(1))
+        .step_by(chunk_size)
+        .map(move |start| {
+            let end = (start + chunk_size).min(value.len());
+            GetValueStreamResponse {
+                chunk: value[start..end].to_vec(),
+                total_size: if start == 0 { total_size } else { 0 },
+            }
+        })
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_sizes() {
+        assert_eq!(chunk_size(0), DEFAULT_CHUNK_SIZE);
+        assert_eq!(chunk_size(1000), 1000);
+        assert_eq!(chunk_size(u32::MAX), MAX_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn test_chunks_reassemble_the_value() {
+        let value: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let responses: Vec<_> = chunks(value.clone(), 300).collect();
+
+        let sizes: Vec<usize> = responses.iter().map(|r| r.chunk.len()).collect();
+        assert_eq!(sizes, vec![300, 300, 300, 100]);
+        assert_eq!(responses[0].total_size, 1000);
+        assert!(responses[1..].iter().all(|r| r.total_size == 0));
+        let reassembled: Vec<u8> = responses.into_iter().flat_map(|r| r.chunk).collect();
+        assert_eq!(reassembled, value);
+    }
+
+    #[test]
+    fn test_empty_value_is_one_empty_chunk() {
+        let responses: Vec<_> = chunks(Vec::new(), DEFAULT_CHUNK_SIZE).collect();
+        assert_eq!(
+            responses,
+            vec![GetValueStreamResponse {
+                chunk: Vec::new(),
+                total_size: 0,
+            }]
+        );
+    }
+}
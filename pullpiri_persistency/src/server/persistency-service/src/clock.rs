@@ -0,0 +1,140 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Time source of the service
+//!
+//! Every time-dependent feature (event timestamps, dump rate limiting, export
+//! and upload timestamps, uptime) reads time from a [`Clock`] rather than
+//! from the system directly. The service runs on the [`SystemClock`]; tests
+//! inject a [`TestClock`] and fast-forward it instead of sleeping.
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of wall-clock and monotonic time
+pub trait Clock: Send + Sync + Debug {
+    /// Unix time in milliseconds, for timestamps shown to users
+    fn now_ms(&self) -> u64;
+
+    /// Time since an arbitrary fixed point, for measuring intervals
+    ///
+    /// Unlike [`Clock::now_ms`] it never jumps backwards.
+    fn monotonic(&self) -> Duration;
+}
+
+/// Clock shared by the service and its background tasks
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The system's real time
+#[derive(Debug)]
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+
+    /// A system clock ready to be shared
+    pub fn shared() -> SharedClock {
+        Arc::new(Self::new())
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// Manually controlled clock for tests
+///
+/// Time stands still until it is advanced.
+#[derive(Debug, Default)]
+pub struct TestClock {
+    now_ms: AtomicU64,
+    monotonic_ns: AtomicU64,
+}
+
+impl TestClock {
+    /// A clock standing at Unix time `now_ms`
+    pub fn new(now_ms: u64) -> Self {
+        Self {
+            now_ms: AtomicU64::new(now_ms),
+            monotonic_ns: AtomicU64::new(0),
+        }
+    }
+
+    /// Let `duration` pass
+    pub fn advance(&self, duration: Duration) {
+        self.now_ms
+            .fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+        self.monotonic_ns
+            .fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    /// Set the wall clock, e.g. to simulate a time sync; monotonic time is unaffected
+    pub fn set_now_ms(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::SeqCst);
+    }
+}
+
+impl Clock for TestClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::SeqCst)
+    }
+
+    fn monotonic(&self) -> Duration {
+        Duration::from_nanos(self.monotonic_ns.load(Ordering::SeqCst))
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_test_clock_advances_only_on_demand() {
+        let clock = TestClock::new(1_000);
+        assert_eq!(clock.now_ms(), 1_000);
+        assert_eq!(clock.monotonic(), Duration::ZERO);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.now_ms(), 91_000);
+        assert_eq!(clock.monotonic(), Duration::from_secs(90));
+
+        // A wall clock jump does not disturb interval measurements
+        clock.set_now_ms(500);
+        assert_eq!(clock.now_ms(), 500);
+        assert_eq!(clock.monotonic(), Duration::from_secs(90));
+    }
+
+    #[test]
+    fn test_system_clock_is_monotonic() {
+        let clock = SystemClock::new();
+        let first = clock.monotonic();
+        assert!(clock.monotonic() >= first);
+        assert!(clock.now_ms() > 0);
+    }
+}
@@ -0,0 +1,1587 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Persistency Service Configuration
+//!
+//! Optional features of the service are configured through a YAML file.
+//! The file is looked up at `PERSISTENCY_CONFIG` (if set) or at
+//! `/etc/piccolo/persistency.yaml`. A missing or invalid file falls back to
+//! the defaults, which keep every optional feature disabled.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Default location of the service configuration file
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/piccolo/persistency.yaml";
+
+/// Environment variable overriding the configuration file location
+pub const CONFIG_PATH_ENV: &str = "PERSISTENCY_CONFIG";
+
+/// Top-level persistency service configuration
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServiceConfig {
+    /// gRPC-Web access for browser dashboards
+    pub grpc_web: GrpcWebConfig,
+    /// Request trace sampling
+    pub tracing: TraceSamplingConfig,
+    /// Logging of requests slower than a threshold
+    pub slow_requests: SlowRequestConfig,
+    /// Key change notifications
+    pub watch: WatchConfig,
+    /// Diagnostic state dumps
+    pub diagnostics: DiagnosticsConfig,
+    /// TCP port and Unix domain socket gRPC is served on
+    pub listen: ListenConfig,
+    /// Length-prefixed framing protocol for clients without gRPC
+    pub lite: LiteConfig,
+    /// Scheduled export to a cloud backup endpoint
+    pub upload: UploadConfig,
+    /// Embedded web UI for field debugging
+    pub admin_ui: AdminUiConfig,
+    /// HTTP/JSON access to the keys for dashboards and scripts
+    pub rest: RestConfig,
+    /// Storage usage tracking and forecast
+    pub forecast: StorageForecastConfig,
+    /// Prometheus metrics endpoint
+    pub metrics: MetricsConfig,
+    /// Bounded memory mode for small ECUs
+    pub memory: MemoryConfig,
+    /// Periodic usage reports per namespace
+    pub usage: UsageReportConfig,
+    /// Write-through of numeric values to a time-series sink
+    pub timeseries: TimeSeriesConfig,
+    /// Removal of keys written with a time to live
+    pub expiry: ExpiryConfig,
+    /// Stores hosted next to the default store
+    pub namespaces: NamespacesConfig,
+    /// Values uploaded in chunks with `SetLargeValue`
+    pub large_values: LargeValueConfig,
+    /// Grace period of destructive operations
+    pub destructive: DestructiveConfig,
+    /// Batched flushing of `SetValue` writes
+    pub flush: FlushConfig,
+    /// Mirroring of write RPCs to a staging instance
+    pub mirror: MirrorConfig,
+    /// Bearer token authentication of the components
+    pub auth: AuthConfig,
+    /// Key prefixes each authenticated component may access
+    pub acl: AclConfig,
+    /// gRPC server reflection for debugging tools
+    pub reflection: ReflectionConfig,
+    /// Replacement of the store with an exported snapshot
+    pub snapshot_restore: SnapshotRestoreConfig,
+    /// Scheduled local backups with rotation
+    pub backup: BackupConfig,
+    /// JSON and YAML files written and read by `ExportToFile` and `ImportFromFile`
+    pub dump: DumpConfig,
+    /// Import of the keys of the etcd used by earlier releases
+    pub etcd_migration: EtcdMigrationConfig,
+    /// Kept versions of the keys
+    pub key_history: KeyHistoryConfig,
+    /// Restorable removal of keys
+    pub trash: TrashConfig,
+    /// Encryption of the stored values
+    pub encryption: EncryptionConfig,
+    /// Limits on the values and keys the clients store
+    pub quotas: QuotaConfig,
+    /// Keys removed with the lease of the component that wrote them
+    pub leases: LeaseConfig,
+    /// Values of the hot keys answered from memory
+    pub read_cache: ReadCacheConfig,
+    /// Engine the stores keep their keys in
+    pub storage: StorageConfig,
+}
+
+/// gRPC-Web configuration
+///
+/// Browser clients are always read-only. They may only see keys that start
+/// with one of `read_prefixes`, keys of a namespace as `<namespace>/key`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GrpcWebConfig {
+    /// Accept gRPC-Web (HTTP/1.1) requests next to native gRPC
+    pub enabled: bool,
+    /// Origins allowed by CORS, `*` allows any origin
+    pub allowed_origins: Vec<String>,
+    /// Key prefixes visible to browser clients
+    pub read_prefixes: Vec<String>,
+}
+
+impl Default for GrpcWebConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allowed_origins: vec!["*".to_string()],
+            read_prefixes: Vec::new(),
+        }
+    }
+}
+
+/// Request trace sampling configuration
+///
+/// Rates are fractions between 0.0 and 1.0. The defaults trace 1% of reads,
+/// every write and every failed request. The rates can be changed at runtime
+/// through the `SetTraceSampling` admin RPC.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TraceSamplingConfig {
+    /// Fraction of read requests traced
+    pub read_rate: f64,
+    /// Fraction of write requests traced
+    pub write_rate: f64,
+    /// Always trace failed requests
+    pub trace_errors: bool,
+}
+
+impl Default for TraceSamplingConfig {
+    fn default() -> Self {
+        Self {
+            read_rate: 0.01,
+            write_rate: 1.0,
+            trace_errors: true,
+        }
+    }
+}
+
+/// Slow request log configuration
+///
+/// Requests taking longer than `threshold_ms` are logged at warn level with
+/// the key or prefix they accessed, see [`crate::timing`]. A threshold of 0
+/// turns the log off.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SlowRequestConfig {
+    /// Duration above which a request is logged
+    pub threshold_ms: u64,
+}
+
+impl Default for SlowRequestConfig {
+    fn default() -> Self {
+        Self { threshold_ms: 1000 }
+    }
+}
+
+/// Key change notification configuration
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WatchConfig {
+    /// Events buffered per watched key or prefix; slower watchers drop the oldest
+    pub channel_capacity: usize,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            channel_capacity: 256,
+        }
+    }
+}
+
+/// Diagnostic state dump configuration
+///
+/// The service keeps bounded histories of failed operations and mutating
+/// requests, which are included in `DumpStateForDiagnostics` snapshots.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DiagnosticsConfig {
+    /// Failed operations kept for dumps
+    pub recent_errors: usize,
+    /// Mutating requests kept for dumps
+    pub audit_events: usize,
+    /// Most accessed keys listed in dumps
+    pub hot_keys: usize,
+    /// Minimum time between two dumps in seconds
+    pub min_dump_interval_secs: u64,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            recent_errors: 50,
+            audit_events: 200,
+            hot_keys: 10,
+            min_dump_interval_secs: 10,
+        }
+    }
+}
+
+/// gRPC listener configuration
+///
+/// gRPC is served on TCP port 47007 of the configured host and, with
+/// `socket_path` set, on a Unix domain socket for components on the same
+/// host, see [`crate::listen`]. Clients connect to the socket with
+/// `common::persistency_client::SOCKET_ENV` set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ListenConfig {
+    /// Serve gRPC on the TCP port, off to serve on the socket only
+    pub tcp: bool,
+    /// Unix domain socket gRPC is served on, empty for none
+    pub socket_path: String,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            tcp: true,
+            socket_path: String::new(),
+        }
+    }
+}
+
+/// Lightweight protocol configuration
+///
+/// Clients built with only the `lite-client` feature of `common` connect to
+/// this Unix domain socket instead of the gRPC port.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LiteConfig {
+    /// Serve the framing protocol next to gRPC
+    pub enabled: bool,
+    /// Unix domain socket the protocol is served on
+    pub socket_path: String,
+}
+
+impl Default for LiteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            socket_path: common::persistency_lite::DEFAULT_SOCKET_PATH.to_string(),
+        }
+    }
+}
+
+/// Scheduled export configuration
+///
+/// Every `interval_secs` the keys under `prefixes` are exported and POSTed as
+/// JSON to `endpoint`. With `full_every` above 1 only every `full_every`-th
+/// upload is a full export, the uploads in between are differential exports
+/// holding the changes since the last full upload. A failed upload is retried
+/// `max_retries` times with exponentially growing delays. The value of the authentication header is
+/// read from the `auth_value_env` environment variable so that credentials
+/// stay out of the configuration file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    /// Run the uploader task
+    pub enabled: bool,
+    /// HTTP(S) URL the export is POSTed to
+    pub endpoint: String,
+    /// Key prefixes included in the export, empty exports every key
+    pub prefixes: Vec<String>,
+    /// Time between two uploads in seconds
+    pub interval_secs: u64,
+    /// Uploads per full export, 1 uploads the full export every time
+    pub full_every: u32,
+    /// Retries of a failed upload before waiting for the next interval
+    pub max_retries: u32,
+    /// Delay before the first retry in milliseconds, doubled for each further retry
+    pub retry_backoff_ms: u64,
+    /// Timeout of a single HTTP request in seconds
+    pub timeout_secs: u64,
+    /// Name of the authentication header
+    pub auth_header: String,
+    /// Environment variable holding the authentication header value, no header if unset
+    pub auth_value_env: String,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: String::new(),
+            prefixes: Vec::new(),
+            interval_secs: 3600,
+            full_every: 1,
+            max_retries: 3,
+            retry_backoff_ms: 1000,
+            timeout_secs: 30,
+            auth_header: "Authorization".to_string(),
+            auth_value_env: "PERSISTENCY_UPLOAD_AUTH".to_string(),
+        }
+    }
+}
+
+/// Admin web UI configuration
+///
+/// The UI is only served by builds with the `admin-ui` feature. Every API
+/// call must present the token from the `token_env` environment variable as
+/// a bearer token; without a token every call is rejected. Values of keys
+/// under `redact_prefixes` are neither shown nor editable.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AdminUiConfig {
+    /// Serve the UI next to gRPC
+    pub enabled: bool,
+    /// Address the UI is served on
+    pub listen_addr: String,
+    /// Environment variable holding the access token
+    pub token_env: String,
+    /// Key prefixes whose values are hidden in the UI
+    pub redact_prefixes: Vec<String>,
+}
+
+impl Default for AdminUiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen_addr: "127.0.0.1:47080".to_string(),
+            token_env: "PERSISTENCY_ADMIN_TOKEN".to_string(),
+            redact_prefixes: Vec::new(),
+        }
+    }
+}
+
+/// REST gateway configuration
+///
+/// The gateway is only served by builds with the `rest-gateway` feature.
+/// With component authentication enabled, every request must name its
+/// component in the `x-component-name` header and carry its bearer token,
+/// like gRPC requests, and the access control lists apply.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RestConfig {
+    /// Serve the gateway next to gRPC
+    pub enabled: bool,
+    /// Address the gateway is served on
+    pub listen_addr: String,
+}
+
+impl Default for RestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen_addr: "127.0.0.1:47081".to_string(),
+        }
+    }
+}
+
+/// Storage usage forecast configuration
+///
+/// Write volume and net growth are tracked per key prefix over the last
+/// `window_secs`. At the current growth rate the forecast tells how many
+/// days are left until the store exceeds `quota_bytes` or the filesystem
+/// holding it is `disk_threshold_percent` full, whichever comes first.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StorageForecastConfig {
+    /// Storage budget of the store in bytes, 0 for none
+    pub quota_bytes: u64,
+    /// Filesystem usage in percent that must not be exceeded, 0 to ignore the disk
+    pub disk_threshold_percent: u8,
+    /// Time the rates are measured over in seconds
+    pub window_secs: u64,
+    /// Number of `/`-separated key segments forming the prefix a key is accounted to
+    pub prefix_depth: usize,
+}
+
+impl Default for StorageForecastConfig {
+    fn default() -> Self {
+        Self {
+            quota_bytes: 64 * 1024 * 1024,
+            disk_threshold_percent: 90,
+            window_secs: 7 * 24 * 3600,
+            prefix_depth: 1,
+        }
+    }
+}
+
+/// Prometheus metrics configuration
+///
+/// The metrics are served over HTTP/1.1 on the gRPC port, like gRPC-Web.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Answer scrapes next to gRPC
+    pub enabled: bool,
+    /// HTTP path of the metrics
+    pub path: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "/metrics".to_string(),
+        }
+    }
+}
+
+/// Bounded memory configuration
+///
+/// By default every key is held in memory. In bounded mode only the
+/// `max_resident_keys` most recently used keys are, the others are spilled
+/// to files in `spill_dir` and read back on access.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MemoryConfig {
+    /// Cap the keys held in memory and spill the others to disk
+    pub bounded: bool,
+    /// Keys held in memory in bounded mode
+    pub max_resident_keys: usize,
+    /// Directory of the spill files, relative to the storage directory
+    pub spill_dir: String,
+    /// Number of spill files the keys are distributed over
+    pub spill_buckets: u32,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            bounded: false,
+            max_resident_keys: 10_000,
+            spill_dir: "spill".to_string(),
+            spill_buckets: 1024,
+        }
+    }
+}
+
+/// Usage report configuration
+///
+/// Every `period_secs` the operations, the bytes read and written and the
+/// stored footprint of every namespace are summarized in a usage report. The
+/// last `retained_reports` reports are served by the `GetUsageReports` admin
+/// RPC and, with `persist`, stored under `__reports/usage/`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UsageReportConfig {
+    /// Count the operations and generate reports
+    pub enabled: bool,
+    /// Length of a report period in seconds
+    pub period_secs: u64,
+    /// Busiest keys listed per namespace
+    pub top_keys: usize,
+    /// Completed reports kept in memory and in the store
+    pub retained_reports: usize,
+    /// Store every completed report in the store itself
+    pub persist: bool,
+}
+
+impl Default for UsageReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            period_secs: 3600,
+            top_keys: 5,
+            retained_reports: 24,
+            persist: false,
+        }
+    }
+}
+
+/// Destination of the time-series points
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeSeriesSinkKind {
+    /// POST to the InfluxDB write API at `endpoint`
+    Http,
+    /// Append to the local file at `file_path`
+    File,
+}
+
+/// Time-series write-through configuration
+///
+/// Every numeric value written under one of `prefixes` is forwarded as a
+/// point in the InfluxDB line protocol, tagged with its key, so that trends
+/// can be queried without keeping the history in the store. The HTTP sink
+/// POSTs batches to `endpoint`, e.g.
+/// `http://influxdb:8086/api/v2/write?org=fleet&bucket=vehicle&precision=ms`;
+/// the timestamps are in milliseconds, so the endpoint has to select that
+/// precision. The file sink appends the same lines to `file_path`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TimeSeriesConfig {
+    /// Forward the writes
+    pub enabled: bool,
+    /// Key prefixes whose numeric values are forwarded
+    pub prefixes: Vec<String>,
+    /// Where the points are written
+    pub sink: TimeSeriesSinkKind,
+    /// URL of the write API for the HTTP sink
+    pub endpoint: String,
+    /// File the points are appended to by the file sink
+    pub file_path: String,
+    /// Measurement name of the points
+    pub measurement: String,
+    /// Points written in one request
+    pub batch_size: usize,
+    /// Longest time a point waits for its batch in milliseconds
+    pub flush_interval_ms: u64,
+    /// Points kept while the sink is unavailable, the oldest are dropped beyond
+    pub max_buffered_points: usize,
+    /// Timeout of a single HTTP request in seconds
+    pub timeout_secs: u64,
+    /// Name of the authentication header
+    pub auth_header: String,
+    /// Environment variable holding the authentication header value, no header if unset
+    pub auth_value_env: String,
+}
+
+impl Default for TimeSeriesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefixes: Vec::new(),
+            sink: TimeSeriesSinkKind::Http,
+            endpoint: String::new(),
+            file_path: "/var/lib/piccolo/persistency-timeseries.lp".to_string(),
+            measurement: "persistency".to_string(),
+            batch_size: 500,
+            flush_interval_ms: 1000,
+            max_buffered_points: 10000,
+            timeout_secs: 10,
+            auth_header: "Authorization".to_string(),
+            auth_value_env: "PERSISTENCY_TIMESERIES_AUTH".to_string(),
+        }
+    }
+}
+
+/// Key expiry configuration
+///
+/// Keys written with `ttl_seconds` are removed by a sweeper running every
+/// `sweep_interval_ms`, so an expired key stays readable for at most that
+/// long. `max_ttl_secs` caps the requested lifetimes, 0 leaves them uncapped.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ExpiryConfig {
+    /// Time between two sweeps in milliseconds
+    pub sweep_interval_ms: u64,
+    /// Longest time to live accepted in seconds, 0 for no limit
+    pub max_ttl_secs: u64,
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        Self {
+            sweep_interval_ms: 1000,
+            max_ttl_secs: 0,
+        }
+    }
+}
+
+/// Large value configuration
+///
+/// `SetLargeValue` assembles a value from its chunks in memory before storing
+/// it. Values declared or sent larger than `max_value_bytes` are rejected.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LargeValueConfig {
+    /// Largest value accepted by `SetLargeValue` in bytes
+    pub max_value_bytes: u64,
+}
+
+impl Default for LargeValueConfig {
+    fn default() -> Self {
+        Self {
+            max_value_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Destructive operation configuration
+///
+/// With a `grace_period_secs` above 0, `Reset` is journaled and only executed
+/// once the grace period is over, unless it is cancelled with
+/// `CancelPendingOperation` before. 0 executes it at once.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DestructiveConfig {
+    /// Time between the request and the execution in seconds, 0 for none
+    pub grace_period_secs: u64,
+}
+
+/// Flush configuration
+///
+/// `SetValue` does not flush the store itself. Its writes stay pending until
+/// a background flush every `interval_ms`, or sooner once `max_writes` writes
+/// are pending. `Flush` and `FlushPrefix` still flush at once.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FlushConfig {
+    /// Time between two background flushes in milliseconds
+    pub interval_ms: u64,
+    /// Writes that trigger a flush before the interval is over, 0 for no limit
+    pub max_writes: usize,
+}
+
+impl Default for FlushConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 1000,
+            max_writes: 1000,
+        }
+    }
+}
+
+/// Request mirroring configuration
+///
+/// Sends `percent` of the write RPCs once more to the persistency service at
+/// `endpoint`, e.g. `http://10.0.0.7:47007`, a staging build validated
+/// against real traffic. Up to `queue_capacity` writes wait for the staging
+/// instance, further writes are not mirrored.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MirrorConfig {
+    /// Mirror the writes
+    pub enabled: bool,
+    /// gRPC endpoint of the staging instance
+    pub endpoint: String,
+    /// Share of the write RPCs mirrored, between 0.0 and 100.0
+    pub percent: f64,
+    /// Mirrored writes waiting for the staging instance
+    pub queue_capacity: usize,
+    /// Timeout of a mirrored write in milliseconds
+    pub timeout_ms: u64,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: String::new(),
+            percent: 10.0,
+            queue_capacity: 1000,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+/// Component authentication configuration
+///
+/// Every gRPC request must carry `authorization: Bearer <token>` with the
+/// token configured in `tokens` for the component it names in its identity
+/// metadata. Requests of other components are rejected. Browsers reach the
+/// service read-only through gRPC-Web and authenticate the same way, unless
+/// `anonymous_grpc_web` admits them without a token, e.g. for a dashboard on
+/// a closed test bench.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Reject requests without a valid token
+    pub enabled: bool,
+    /// Token per component name, e.g. `apiserver`
+    pub tokens: BTreeMap<String, String>,
+    /// Admit gRPC-Web requests without a token, off by default
+    pub anonymous_grpc_web: bool,
+}
+
+/// Access control list configuration
+///
+/// Every component authenticated by `auth` may only read and write keys
+/// under the prefixes of its entry in `components`, e.g. `apiserver` keys
+/// under `Scenario`. A trailing `*` of a prefix is ignored. Keys a component
+/// may write it may read as well. Keys of a namespace are matched as
+/// `<namespace>/key`, e.g. `<apiserver>/Scenario`. `Reset` and `Flush` affect
+/// the whole store and need the empty prefix, or `<namespace>/` for a
+/// namespace, in `write`. Components without an entry have no
+/// access. Requests that are not authenticated, e.g. of the admin web UI,
+/// are not restricted, so the lists only take effect together with `auth`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AclConfig {
+    /// Restrict the authenticated components to their prefixes
+    pub enabled: bool,
+    /// Access per component name
+    pub components: BTreeMap<String, ComponentAclConfig>,
+}
+
+/// Access of one component
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ComponentAclConfig {
+    /// Prefixes of the keys the component may read
+    pub read: Vec<String>,
+    /// Prefixes of the keys the component may read and write
+    pub write: Vec<String>,
+    /// Whether the component may use admin operations, e.g. `Restore`
+    pub admin: bool,
+}
+
+/// gRPC server reflection configuration
+///
+/// Serves the `grpc.reflection` services with the descriptors of the
+/// persistency and health services, so that tools like `grpcurl` can list
+/// and call the RPCs without compiled stubs during integration debugging.
+/// The reflection services need no token, the calls made with their help
+/// are authenticated like any other.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReflectionConfig {
+    /// Describe the services to reflection clients
+    pub enabled: bool,
+}
+
+/// Snapshot restore configuration
+///
+/// `RestoreSnapshot` assembles the uploaded export document in memory before
+/// replacing the store with it. Documents declared or sent larger than
+/// `max_snapshot_bytes` are rejected.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SnapshotRestoreConfig {
+    /// Largest export document accepted by `RestoreSnapshot` in bytes
+    pub max_snapshot_bytes: u64,
+}
+
+impl Default for SnapshotRestoreConfig {
+    fn default() -> Self {
+        Self {
+            max_snapshot_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Local backup configuration
+///
+/// Every `interval_secs` the keys under `prefixes` are exported to a new file
+/// in `dir`, relative to the storage directory, and only the newest `keep`
+/// backups are kept. An `interval_secs` of 0 only writes backups requested
+/// with `CreateBackup`. `ListBackups` and `CreateBackup` fail if disabled.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Write and list backups
+    pub enabled: bool,
+    /// Backup directory
+    pub dir: String,
+    /// Key prefixes included in the backups, empty backs up every key
+    pub prefixes: Vec<String>,
+    /// Time between two scheduled backups in seconds, 0 for none
+    pub interval_secs: u64,
+    /// Backups kept, the oldest are removed
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "backups".to_string(),
+            prefixes: Vec::new(),
+            interval_secs: 24 * 3600,
+            keep: 7,
+        }
+    }
+}
+
+/// File dump configuration
+///
+/// `ExportToFile` writes human-readable JSON and YAML dumps of the store to
+/// `dir`, relative to the storage directory, and `ImportFromFile` restores
+/// the dumps, etcd dumps and store files placed there. Both fail if disabled.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DumpConfig {
+    /// Write and read dump files
+    pub enabled: bool,
+    /// Dump directory
+    pub dir: String,
+}
+
+impl Default for DumpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "dumps".to_string(),
+        }
+    }
+}
+
+/// etcd migration configuration
+///
+/// `MigrateFromEtcd` reads the keys under a prefix from the etcd at
+/// `endpoint`, or at the endpoint of the request, over the JSON gateway of
+/// its v3 API, `page_size` keys per HTTP request, and imports them into the
+/// default store. It fails if disabled.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EtcdMigrationConfig {
+    /// Accept `MigrateFromEtcd` requests
+    pub enabled: bool,
+    /// etcd client URL used if the request names none
+    pub endpoint: String,
+    /// Keys read per HTTP request
+    pub page_size: u32,
+    /// Timeout of a single HTTP request in seconds
+    pub timeout_secs: u64,
+}
+
+impl Default for EtcdMigrationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://127.0.0.1:2379".to_string(),
+            page_size: 500,
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Key history configuration
+///
+/// The last `versions` changes of every key under `prefixes`, in any store,
+/// are kept for `GetHistory` and `GetValueAtRevision`. `GetHistory` and
+/// `GetValueAtRevision` fail if disabled.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeyHistoryConfig {
+    /// Keep the versions of the keys
+    pub enabled: bool,
+    /// Versions kept per key, the oldest are dropped
+    pub versions: usize,
+    /// Key prefixes whose versions are kept, empty keeps those of every key
+    pub prefixes: Vec<String>,
+}
+
+impl Default for KeyHistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            versions: 5,
+            prefixes: Vec::new(),
+        }
+    }
+}
+
+/// Trash configuration
+///
+/// A `RemoveKey` asking for the trash moves the key into `namespace`, which
+/// has to be one of the configured namespaces, from where `RestoreKey` brings
+/// it back for `retention_secs`, 0 keeps removed keys until they are
+/// restored. `ListDeleted` and `RestoreKey` fail if disabled.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Move removed keys to the trash on request
+    pub enabled: bool,
+    /// Namespace holding the removed keys
+    pub namespace: String,
+    /// Time a removed key stays restorable in seconds
+    pub retention_secs: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            namespace: "trash".to_string(),
+            retention_secs: 7 * 24 * 3600,
+        }
+    }
+}
+
+/// Encryption at rest configuration
+///
+/// Values of the keys under `prefixes`, in any store, are encrypted with
+/// AES-256-GCM before they reach rust_kvs. The 32-byte key is read from the
+/// environment variable `key_env`, e.g. set by the key management agent, or
+/// else from `key_file`, either as the raw bytes or in base64. The service
+/// does not start if encryption is enabled and the key is missing.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    /// Encrypt the values under `prefixes`
+    pub enabled: bool,
+    /// File holding the key
+    pub key_file: String,
+    /// Environment variable holding the key in base64, taking precedence over `key_file`
+    pub key_env: String,
+    /// Key prefixes whose values are encrypted, empty encrypts every value
+    pub prefixes: Vec<String>,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_file: String::new(),
+            key_env: "PERSISTENCY_ENCRYPTION_KEY".to_string(),
+            prefixes: Vec::new(),
+        }
+    }
+}
+
+/// Quota configuration
+///
+/// Writes of the clients beyond a limit are rejected with
+/// `RESOURCE_EXHAUSTED`. Keys are counted per store, so every namespace may
+/// hold `max_keys` keys. 0 disables a limit; by default nothing is limited.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// Largest encoded size of a value in bytes, 0 for no limit
+    pub max_value_bytes: u64,
+    /// Largest number of keys of a store, 0 for no limit
+    pub max_keys: u64,
+    /// Largest number of keys of a store under each key prefix
+    pub prefixes: BTreeMap<String, u64>,
+}
+
+/// Lease configuration
+///
+/// A component grants itself a lease with `GrantLease`, attaches keys to it
+/// with `SetValue` and renews it over `KeepAlive`. A lease not renewed
+/// within its time to live expires and its keys are removed at the next
+/// sweep of the expiry tracker, see `expiry.sweep_interval_ms`. `Lock`
+/// holds a lock with a lease of its own and a key under `lock_prefix` in
+/// the default store.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LeaseConfig {
+    /// Grant leases
+    pub enabled: bool,
+    /// Longest time to live of a lease in seconds, 0 for no limit
+    pub max_ttl_secs: u64,
+    /// Largest number of leases held at once
+    pub max_leases: usize,
+    /// Key prefix of the held locks
+    pub lock_prefix: String,
+}
+
+impl Default for LeaseConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_ttl_secs: 3600,
+            max_leases: 1024,
+            lock_prefix: "locks/".to_string(),
+        }
+    }
+}
+
+/// Read cache configuration
+///
+/// `GetValue` answers the `max_keys` most recently read keys from a cache
+/// that every write of the key invalidates. Its hits are reported on the
+/// metrics endpoint. With `shadow_read_percent` above 0, that share of the
+/// hits reads the store as well to verify the cached value.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReadCacheConfig {
+    /// Cache the values read with `GetValue`
+    pub enabled: bool,
+    /// Largest number of cached values
+    pub max_keys: usize,
+    /// Share of the cache hits compared with the store, between 0.0 and 100.0
+    pub shadow_read_percent: f64,
+}
+
+impl Default for ReadCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_keys: 1024,
+            shadow_read_percent: 0.0,
+        }
+    }
+}
+
+/// Storage engine of the stores, see [`crate::backend`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackendKind {
+    /// rust_kvs JSON files with rotating snapshots
+    RustKvs,
+    /// sled database, needs the `sled` cargo feature
+    Sled,
+    /// SQLite database, needs the `sqlite` cargo feature
+    Sqlite,
+    /// Memory of the service, for tests, nothing is kept across restarts
+    Memory,
+}
+
+impl StorageBackendKind {
+    /// Whether the stores and the other state of the service survive a restart
+    pub fn is_persistent(self) -> bool {
+        self != Self::Memory
+    }
+}
+
+/// Storage configuration
+///
+/// Every store, the default store as well as the namespaces, keeps its keys
+/// in the `backend` engine next to the other state files of the service.
+/// Snapshots and the features built on them need rust_kvs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Engine of the stores
+    pub backend: StorageBackendKind,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackendKind::RustKvs,
+        }
+    }
+}
+
+/// Namespace configuration
+///
+/// Every namespace is a rust_kvs instance of its own with its storage file
+/// `kvs_<instance>_0.json` next to the default store, which is instance 0.
+/// rust_kvs hosts at most 10 instances, so the instances are 1 to 9.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NamespacesConfig {
+    /// rust_kvs instance per namespace name
+    pub instances: BTreeMap<String, usize>,
+}
+
+impl ServiceConfig {
+    /// Parse a configuration from a YAML string
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+
+    /// Read a configuration file, falling back to defaults on any error
+    pub fn from_file(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(content) => match Self::from_yaml(&content) {
+                Ok(config) => {
+                    info!("Loaded persistency service configuration from {:?}", path);
+                    config
+                }
+                Err(e) => {
+                    warn!("Invalid configuration in {:?}, using defaults: {}", path, e);
+                    Self::default()
+                }
+            },
+            Err(_) => {
+                info!("No configuration file at {:?}, using defaults", path);
+                Self::default()
+            }
+        }
+    }
+
+    /// Check the settings that depend on each other
+    ///
+    /// Returns the message of the first inconsistency.
+    pub fn validate(&self) -> Result<(), String> {
+        crate::trash::validate(&self.trash, &self.namespaces)
+    }
+}
+
+/// Load the service configuration from the configured location
+pub fn load() -> ServiceConfig {
+    let path = std::env::var(CONFIG_PATH_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+    ServiceConfig::from_file(Path::new(&path))
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_disables_grpc_web() {
+        let config = ServiceConfig::default();
+        assert!(!config.grpc_web.enabled);
+        assert_eq!(config.grpc_web.allowed_origins, vec!["*".to_string()]);
+        assert!(config.grpc_web.read_prefixes.is_empty());
+    }
+
+    #[test]
+    fn test_from_yaml_tracing_section() {
+        let yaml = r#"
+tracing:
+  read_rate: 0.5
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.tracing.read_rate, 0.5);
+        assert_eq!(config.tracing.write_rate, 1.0);
+        assert!(config.tracing.trace_errors);
+    }
+
+    #[test]
+    fn test_from_yaml_partial_section() {
+        let yaml = r#"
+grpc_web:
+  enabled: true
+  read_prefixes:
+    - "vehicle/"
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.grpc_web.enabled);
+        assert_eq!(config.grpc_web.read_prefixes, vec!["vehicle/".to_string()]);
+        // Unset fields keep their defaults
+        assert_eq!(config.grpc_web.allowed_origins, vec!["*".to_string()]);
+    }
+
+    #[test]
+    fn test_from_yaml_upload_section() {
+        let yaml = r#"
+upload:
+  enabled: true
+  endpoint: "https://backup.example.com/vehicles"
+  prefixes:
+    - "Scenario/"
+  full_every: 6
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.upload.enabled);
+        assert_eq!(config.upload.full_every, 6);
+        assert_eq!(config.upload.prefixes, vec!["Scenario/".to_string()]);
+        assert_eq!(config.upload.max_retries, 3);
+        assert_eq!(config.upload.auth_header, "Authorization");
+    }
+
+    #[test]
+    fn test_from_yaml_admin_ui_section() {
+        let yaml = r#"
+admin_ui:
+  enabled: true
+  redact_prefixes:
+    - "credentials/"
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.admin_ui.enabled);
+        assert_eq!(
+            config.admin_ui.redact_prefixes,
+            vec!["credentials/".to_string()]
+        );
+        assert_eq!(config.admin_ui.listen_addr, "127.0.0.1:47080");
+        assert_eq!(config.admin_ui.token_env, "PERSISTENCY_ADMIN_TOKEN");
+    }
+
+    #[test]
+    fn test_from_yaml_forecast_and_metrics_sections() {
+        let yaml = r#"
+forecast:
+  quota_bytes: 1048576
+  prefix_depth: 2
+metrics:
+  enabled: true
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.forecast.quota_bytes, 1048576);
+        assert_eq!(config.forecast.prefix_depth, 2);
+        assert_eq!(config.forecast.disk_threshold_percent, 90);
+        assert!(config.metrics.enabled);
+        assert_eq!(config.metrics.path, "/metrics");
+    }
+
+    #[test]
+    fn test_from_yaml_memory_section() {
+        let yaml = r#"
+memory:
+  bounded: true
+  max_resident_keys: 500
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.memory.bounded);
+        assert_eq!(config.memory.max_resident_keys, 500);
+        assert_eq!(config.memory.spill_dir, "spill");
+        assert_eq!(config.memory.spill_buckets, 1024);
+        assert!(!ServiceConfig::default().memory.bounded);
+    }
+
+    #[test]
+    fn test_from_yaml_timeseries_section() {
+        let yaml = r#"
+timeseries:
+  enabled: true
+  sink: file
+  prefixes:
+    - "vehicle/speed"
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.timeseries.enabled);
+        assert_eq!(config.timeseries.sink, TimeSeriesSinkKind::File);
+        assert_eq!(
+            config.timeseries.prefixes,
+            vec!["vehicle/speed".to_string()]
+        );
+        assert_eq!(config.timeseries.measurement, "persistency");
+        assert_eq!(
+            ServiceConfig::default().timeseries.sink,
+            TimeSeriesSinkKind::Http
+        );
+    }
+
+    #[test]
+    fn test_from_yaml_expiry_section() {
+        let yaml = r#"
+expiry:
+  max_ttl_secs: 86400
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.expiry.max_ttl_secs, 86400);
+        assert_eq!(config.expiry.sweep_interval_ms, 1000);
+        assert_eq!(ServiceConfig::default().expiry.max_ttl_secs, 0);
+    }
+
+    #[test]
+    fn test_from_yaml_namespaces_section() {
+        let yaml = r#"
+namespaces:
+  instances:
+    artifacts: 2
+    runtime: 3
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.namespaces.instances.len(), 2);
+        assert_eq!(config.namespaces.instances["artifacts"], 2);
+        assert_eq!(config.namespaces.instances["runtime"], 3);
+        assert!(ServiceConfig::default().namespaces.instances.is_empty());
+    }
+
+    #[test]
+    fn test_from_yaml_large_values_section() {
+        let yaml = r#"
+large_values:
+  max_value_bytes: 1048576
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.large_values.max_value_bytes, 1024 * 1024);
+        assert_eq!(
+            ServiceConfig::default().large_values.max_value_bytes,
+            64 * 1024 * 1024
+        );
+    }
+
+    #[test]
+    fn test_from_yaml_destructive_section() {
+        let yaml = r#"
+destructive:
+  grace_period_secs: 600
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.destructive.grace_period_secs, 600);
+        assert_eq!(ServiceConfig::default().destructive.grace_period_secs, 0);
+    }
+
+    #[test]
+    fn test_from_yaml_flush_section() {
+        let yaml = r#"
+flush:
+  interval_ms: 250
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.flush.interval_ms, 250);
+        assert_eq!(config.flush.max_writes, 1000);
+    }
+
+    #[test]
+    fn test_from_yaml_mirror_section() {
+        let yaml = r#"
+mirror:
+  enabled: true
+  endpoint: http://10.0.0.7:47007
+  percent: 2.5
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.mirror.enabled);
+        assert_eq!(config.mirror.endpoint, "http://10.0.0.7:47007");
+        assert_eq!(config.mirror.percent, 2.5);
+        assert_eq!(config.mirror.queue_capacity, 1000);
+        assert!(!ServiceConfig::default().mirror.enabled);
+    }
+
+    #[test]
+    fn test_from_yaml_auth_section() {
+        let yaml = r#"
+auth:
+  enabled: true
+  tokens:
+    apiserver: 3f9c2a
+    statemanager: 81d0e7
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.auth.enabled);
+        assert_eq!(config.auth.tokens.len(), 2);
+        assert_eq!(config.auth.tokens["apiserver"], "3f9c2a");
+        assert!(!config.auth.anonymous_grpc_web);
+        assert!(!ServiceConfig::default().auth.enabled);
+    }
+
+    #[test]
+    fn test_from_yaml_acl_section() {
+        let yaml = r#"
+acl:
+  enabled: true
+  components:
+    apiserver:
+      write: ["Scenario*", "Package/"]
+    filtergateway:
+      read: ["Vehicle/*"]
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.acl.enabled);
+        let apiserver = &config.acl.components["apiserver"];
+        assert_eq!(apiserver.write, ["Scenario*", "Package/"]);
+        assert!(apiserver.read.is_empty());
+        assert!(!apiserver.admin);
+        assert_eq!(config.acl.components["filtergateway"].read, ["Vehicle/*"]);
+        assert!(!ServiceConfig::default().acl.enabled);
+    }
+
+    #[test]
+    fn test_from_yaml_reflection_section() {
+        let yaml = r#"
+reflection:
+  enabled: true
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.reflection.enabled);
+        assert!(!ServiceConfig::default().reflection.enabled);
+    }
+
+    #[test]
+    fn test_from_yaml_snapshot_restore_section() {
+        let yaml = r#"
+snapshot_restore:
+  max_snapshot_bytes: 1048576
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.snapshot_restore.max_snapshot_bytes, 1024 * 1024);
+        assert_eq!(
+            ServiceConfig::default().snapshot_restore.max_snapshot_bytes,
+            64 * 1024 * 1024
+        );
+    }
+
+    #[test]
+    fn test_from_yaml_backup_section() {
+        let yaml = r#"
+backup:
+  enabled: true
+  dir: /var/lib/piccolo/backups
+  prefixes: ["Scenario/"]
+  interval_secs: 3600
+  keep: 3
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.backup.enabled);
+        assert_eq!(config.backup.dir, "/var/lib/piccolo/backups");
+        assert_eq!(config.backup.prefixes, ["Scenario/"]);
+        assert_eq!(config.backup.interval_secs, 3600);
+        assert_eq!(config.backup.keep, 3);
+        let default = ServiceConfig::default().backup;
+        assert!(!default.enabled);
+        assert_eq!(default.dir, "backups");
+        assert_eq!(default.keep, 7);
+    }
+
+    #[test]
+    fn test_from_yaml_dump_section() {
+        let yaml = r#"
+dump:
+  enabled: true
+  dir: /var/lib/piccolo/dumps
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.dump.enabled);
+        assert_eq!(config.dump.dir, "/var/lib/piccolo/dumps");
+        let default = ServiceConfig::default().dump;
+        assert!(!default.enabled);
+        assert_eq!(default.dir, "dumps");
+    }
+
+    #[test]
+    fn test_from_yaml_etcd_migration_section() {
+        let yaml = r#"
+etcd_migration:
+  enabled: true
+  endpoint: http://10.0.0.5:2379
+  page_size: 100
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.etcd_migration.enabled);
+        assert_eq!(config.etcd_migration.endpoint, "http://10.0.0.5:2379");
+        assert_eq!(config.etcd_migration.page_size, 100);
+        assert_eq!(config.etcd_migration.timeout_secs, 30);
+        assert!(!ServiceConfig::default().etcd_migration.enabled);
+    }
+
+    #[test]
+    fn test_from_yaml_rest_section() {
+        let yaml = r#"
+rest:
+  enabled: true
+  listen_addr: 0.0.0.0:8080
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.rest.enabled);
+        assert_eq!(config.rest.listen_addr, "0.0.0.0:8080");
+        let default = ServiceConfig::default().rest;
+        assert!(!default.enabled);
+        assert_eq!(default.listen_addr, "127.0.0.1:47081");
+    }
+
+    #[test]
+    fn test_from_yaml_listen_section() {
+        let yaml = r#"
+listen:
+  tcp: false
+  socket_path: /run/piccolo/persistency-grpc.sock
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(!config.listen.tcp);
+        assert_eq!(
+            config.listen.socket_path,
+            "/run/piccolo/persistency-grpc.sock"
+        );
+        let default = ServiceConfig::default().listen;
+        assert!(default.tcp);
+        assert!(default.socket_path.is_empty());
+    }
+
+    #[test]
+    fn test_from_yaml_slow_requests_section() {
+        let yaml = r#"
+slow_requests:
+  threshold_ms: 250
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.slow_requests.threshold_ms, 250);
+        assert_eq!(ServiceConfig::default().slow_requests.threshold_ms, 1000);
+    }
+
+    #[test]
+    fn test_from_yaml_key_history_section() {
+        let yaml = r#"
+key_history:
+  enabled: true
+  versions: 10
+  prefixes: ["Scenario/", "vehicle/mode"]
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.key_history.enabled);
+        assert_eq!(config.key_history.versions, 10);
+        assert_eq!(config.key_history.prefixes, ["Scenario/", "vehicle/mode"]);
+        let default = ServiceConfig::default().key_history;
+        assert!(!default.enabled);
+        assert_eq!(default.versions, 5);
+    }
+
+    #[test]
+    fn test_from_yaml_trash_section() {
+        let yaml = r#"
+namespaces:
+  instances:
+    deleted: 3
+trash:
+  enabled: true
+  namespace: deleted
+  retention_secs: 3600
+"#;
+        let mut config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.trash.enabled);
+        assert_eq!(config.trash.namespace, "deleted");
+        assert_eq!(config.trash.retention_secs, 3600);
+        assert_eq!(config.validate(), Ok(()));
+
+        // The service refuses to start with the message
+        config.namespaces.instances.clear();
+        let message = "Trash namespace 'deleted' is not a configured namespace";
+        assert_eq!(config.validate(), Err(message.to_string()));
+        assert!(matches!(
+            crate::PersistencyServiceImpl::with_config(&config),
+            Err(crate::InitError::Config(e)) if e == message
+        ));
+        let default = ServiceConfig::default().trash;
+        assert!(!default.enabled);
+        assert_eq!(default.namespace, "trash");
+        assert_eq!(default.retention_secs, 7 * 24 * 3600);
+    }
+
+    #[test]
+    fn test_from_yaml_encryption_section() {
+        let yaml = r#"
+encryption:
+  enabled: true
+  key_file: /etc/pullpiri/persistency.key
+  prefixes: ["vehicle/config/", "credentials/"]
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.encryption.enabled);
+        assert_eq!(config.encryption.key_file, "/etc/pullpiri/persistency.key");
+        assert_eq!(config.encryption.key_env, "PERSISTENCY_ENCRYPTION_KEY");
+        assert_eq!(
+            config.encryption.prefixes,
+            ["vehicle/config/", "credentials/"]
+        );
+        assert!(!ServiceConfig::default().encryption.enabled);
+    }
+
+    #[test]
+    fn test_from_yaml_quotas_section() {
+        let yaml = r#"
+quotas:
+  max_value_bytes: 65536
+  max_keys: 5000
+  prefixes:
+    "diag/": 200
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.quotas.max_value_bytes, 65536);
+        assert_eq!(config.quotas.max_keys, 5000);
+        assert_eq!(config.quotas.prefixes.get("diag/"), Some(&200));
+        let default = ServiceConfig::default().quotas;
+        assert_eq!(default.max_value_bytes, 0);
+        assert_eq!(default.max_keys, 0);
+        assert!(default.prefixes.is_empty());
+    }
+
+    #[test]
+    fn test_from_yaml_leases_section() {
+        let yaml = r#"
+leases:
+  enabled: false
+  max_ttl_secs: 60
+  lock_prefix: "coordination/locks/"
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(!config.leases.enabled);
+        assert_eq!(config.leases.max_ttl_secs, 60);
+        assert_eq!(config.leases.max_leases, 1024);
+        assert_eq!(config.leases.lock_prefix, "coordination/locks/");
+        assert!(ServiceConfig::default().leases.enabled);
+    }
+
+    #[test]
+    fn test_from_yaml_read_cache_section() {
+        let yaml = r#"
+read_cache:
+  max_keys: 64
+  shadow_read_percent: 1.0
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert!(config.read_cache.enabled);
+        assert_eq!(config.read_cache.max_keys, 64);
+        assert_eq!(config.read_cache.shadow_read_percent, 1.0);
+        assert_eq!(ServiceConfig::default().read_cache.shadow_read_percent, 0.0);
+        assert_eq!(ServiceConfig::default().read_cache.max_keys, 1024);
+    }
+
+    #[test]
+    fn test_from_yaml_storage_section() {
+        let yaml = r#"
+storage:
+  backend: sqlite
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.storage.backend, StorageBackendKind::Sqlite);
+        assert_eq!(
+            ServiceConfig::default().storage.backend,
+            StorageBackendKind::RustKvs
+        );
+
+        let config = ServiceConfig::from_yaml("storage:\n  backend: memory\n").unwrap();
+        assert!(!config.storage.backend.is_persistent());
+        assert!(StorageBackendKind::Sqlite.is_persistent());
+    }
+
+    #[test]
+    fn test_from_file_missing_uses_defaults() {
+        let config = ServiceConfig::from_file(Path::new("/nonexistent/persistency.yaml"));
+        assert!(!config.grpc_web.enabled);
+    }
+}
@@ -0,0 +1,190 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Counter arithmetic of the `Increment` and `Decrement` RPCs
+//!
+//! The service adds the delta under the store lock, so that concurrent
+//! components no longer lose updates with read-modify-write over gRPC.
+//!
+//! The stored value keeps its type. Integer counters take any integer
+//! delta and fail instead of wrapping when the result does not fit, e.g. a
+//! `u32` counter decremented below zero. `f64` counters take any numeric
+//! delta. A missing key starts at zero with the type of the delta, other
+//! types than numbers are rejected.
+
+use common::persistency_proto::{kvs_value::Value, KvsValue};
+
+/// Value of `current` plus `delta`, or minus `delta` if `negate` is set
+///
+/// `current` is `None` for a missing key.
+pub fn apply(
+    current: Option<&KvsValue>,
+    delta: &KvsValue,
+    negate: bool,
+) -> Result<KvsValue, String> {
+    let delta = delta
+        .value
+        .as_ref()
+        .ok_or_else(|| "Missing delta in request".to_string())?;
+    let zero;
+    let current = match current {
+        Some(value) => value
+            .value
+            .as_ref()
+            .ok_or_else(|| "Stored value has no value set".to_string())?,
+        None => {
+            zero = zero_like(delta)?;
+            &zero
+        }
+    };
+
+    let value = match current {
+        Value::F64Value(value) => {
+            let delta = as_f64(delta)?;
+            Value::F64Value(if negate { value - delta } else { value + delta })
+        }
+        _ => {
+            let value = as_integer(current)
+                .ok_or_else(|| format!("Stored {} value is not a number", type_name(current)))?;
+            let delta = as_integer(delta).ok_or_else(|| {
+                format!(
+                    "A {} delta cannot change a {} counter",
+                    type_name(delta),
+                    type_name(current)
+                )
+            })?;
+            let result = if negate { value - delta } else { value + delta };
+            with_type_of(current, result).ok_or_else(|| {
+                format!(
+                    "Result {} is out of the range of the {} counter",
+                    result,
+                    type_name(current)
+                )
+            })?
+        }
+    };
+    Ok(KvsValue { value: Some(value) })
+}
+
+/// Zero of the numeric type of `delta`
+fn zero_like(delta: &Value) -> Result<Value, String> {
+    match delta {
+        Value::I32Value(_) => Ok(Value::I32Value(0)),
+        Value::U32Value(_) => Ok(Value::U32Value(0)),
+        Value::I64Value(_) => Ok(Value::I64Value(0)),
+        Value::U64Value(_) => Ok(Value::U64Value(0)),
+        Value::F64Value(_) => Ok(Value::F64Value(0.0)),
+        other => Err(format!(
+            "Delta of type {} is not a number",
+            type_name(other)
+        )),
+    }
+}
+
+fn as_integer(value: &Value) -> Option<i128> {
+    match value {
+        Value::I32Value(v) => Some(*v as i128),
+        Value::U32Value(v) => Some(*v as i128),
+        Value::I64Value(v) => Some(*v as i128),
+        Value::U64Value(v) => Some(*v as i128),
+        _ => None,
+    }
+}
+
+fn as_f64(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::F64Value(v) => Ok(*v),
+        other => as_integer(other)
+            .map(|v| v as f64)
+            .ok_or_else(|| format!("A {} delta cannot change a f64 counter", type_name(other))),
+    }
+}
+
+/// `value` as the integer type of `counter`, `None` if it does not fit
+fn with_type_of(counter: &Value, value: i128) -> Option<Value> {
+    match counter {
+        Value::I32Value(_) => i32::try_from(value).ok().map(Value::I32Value),
+        Value::U32Value(_) => u32::try_from(value).ok().map(Value::U32Value),
+        Value::I64Value(_) => i64::try_from(value).ok().map(Value::I64Value),
+        Value::U64Value(_) => u64::try_from(value).ok().map(Value::U64Value),
+        _ => None,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::I32Value(_) => "i32",
+        Value::U32Value(_) => "u32",
+        Value::I64Value(_) => "i64",
+        Value::U64Value(_) => "u64",
+        Value::F64Value(_) => "f64",
+        Value::BooleanValue(_) => "boolean",
+        Value::StringValue(_) => "string",
+        Value::BytesValue(_) => "bytes",
+        Value::NullValue(_) => "null",
+        Value::ArrayValue(_) => "array",
+        Value::ObjectValue(_) => "object",
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(value: Value) -> KvsValue {
+        KvsValue { value: Some(value) }
+    }
+
+    #[test]
+    fn test_counter_keeps_its_type() {
+        let counter = value(Value::U32Value(7));
+        let result = apply(Some(&counter), &value(Value::I64Value(3)), false).unwrap();
+        assert_eq!(result, value(Value::U32Value(10)));
+
+        let result = apply(Some(&counter), &value(Value::I32Value(7)), true).unwrap();
+        assert_eq!(result, value(Value::U32Value(0)));
+
+        let counter = value(Value::F64Value(1.5));
+        let result = apply(Some(&counter), &value(Value::I32Value(2)), true).unwrap();
+        assert_eq!(result, value(Value::F64Value(-0.5)));
+    }
+
+    #[test]
+    fn test_missing_key_starts_at_zero() {
+        let result = apply(None, &value(Value::I64Value(5)), false).unwrap();
+        assert_eq!(result, value(Value::I64Value(5)));
+
+        let result = apply(None, &value(Value::I32Value(5)), true).unwrap();
+        assert_eq!(result, value(Value::I32Value(-5)));
+
+        // An unsigned counter cannot start below zero
+        assert!(apply(None, &value(Value::U64Value(1)), true).is_err());
+    }
+
+    #[test]
+    fn test_out_of_range_results_are_rejected() {
+        let counter = value(Value::U32Value(0));
+        let err = apply(Some(&counter), &value(Value::I32Value(1)), true).unwrap_err();
+        assert_eq!(err, "Result -1 is out of the range of the u32 counter");
+
+        let counter = value(Value::I32Value(i32::MAX));
+        assert!(apply(Some(&counter), &value(Value::I32Value(1)), false).is_err());
+    }
+
+    #[test]
+    fn test_non_numbers_are_rejected() {
+        let counter = value(Value::StringValue("7".to_string()));
+        let err = apply(Some(&counter), &value(Value::I32Value(1)), false).unwrap_err();
+        assert_eq!(err, "Stored string value is not a number");
+
+        let counter = value(Value::I64Value(7));
+        let err = apply(Some(&counter), &value(Value::F64Value(0.5)), false).unwrap_err();
+        assert_eq!(err, "A f64 delta cannot change a i64 counter");
+
+        assert!(apply(None, &value(Value::BooleanValue(true)), false).is_err());
+        assert!(apply(None, &KvsValue::default(), false).is_err());
+    }
+}
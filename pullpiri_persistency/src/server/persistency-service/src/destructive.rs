@@ -0,0 +1,314 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Delayed destructive operations
+//!
+//! A `Reset` wipes the orchestration state of a vehicle at once, and a
+//! script pointed at the wrong namespace or vehicle cannot take it back.
+//! With `destructive.grace_period_secs` configured, a reset is journaled
+//! instead and only executed once the grace period is over. Until then it is
+//! listed by `GetServiceInfo` and can be cancelled with
+//! `CancelPendingOperation`.
+//!
+//! The journal is kept in `persistency-pending.json` next to the store and
+//! saved before the request is answered, so that a pending reset is neither
+//! lost nor executed early when the service restarts. Resets that became due
+//! while the service was down are executed by the first check after the
+//! start. A reset that fails stays journaled and is tried again.
+
+use crate::caller::caller_name;
+use crate::clock::SharedClock;
+use crate::config::DestructiveConfig;
+use crate::PersistencyServiceImpl;
+use common::persistency_proto::PendingOperation as PendingOperationProto;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+/// File the journal is kept in, next to the store
+pub const JOURNAL_FILE: &str = "persistency-pending.json";
+
+/// Time between two checks for due operations
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Destructive operation that can be delayed
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "operation")]
+pub enum DestructiveOperation {
+    /// Remove every key of a namespace, the default store for ""
+    Reset { namespace: String },
+}
+
+impl DestructiveOperation {
+    /// Name of the RPC, as listed by `GetServiceInfo`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Reset { .. } => "Reset",
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        match self {
+            Self::Reset { namespace } => namespace,
+        }
+    }
+}
+
+/// Operation waiting for its grace period
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PendingOperation {
+    pub id: u64,
+    #[serde(flatten)]
+    pub operation: DestructiveOperation,
+    /// Component that requested the operation, empty if unknown
+    pub caller: String,
+    pub requested_at_ms: u64,
+    /// Unix time the operation is executed at unless cancelled
+    pub execute_at_ms: u64,
+}
+
+impl PendingOperation {
+    pub fn to_proto(&self) -> PendingOperationProto {
+        PendingOperationProto {
+            id: self.id,
+            operation: self.operation.name().to_string(),
+            namespace: self.operation.namespace().to_string(),
+            caller: self.caller.clone(),
+            requested_at_ms: self.requested_at_ms,
+            execute_at_ms: self.execute_at_ms,
+        }
+    }
+}
+
+/// Content of the journal file
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Journal {
+    /// Id of the next operation, ids are never reused
+    next_id: u64,
+    pending: Vec<PendingOperation>,
+}
+
+/// Journal of the destructive operations waiting for their grace period
+#[derive(Debug)]
+pub struct DelayedOperations {
+    grace_period: Duration,
+    journal: Mutex<Journal>,
+    path: Option<PathBuf>,
+    clock: SharedClock,
+}
+
+impl DelayedOperations {
+    /// Journal kept in memory only
+    pub fn new(config: &DestructiveConfig, clock: SharedClock) -> Self {
+        Self {
+            grace_period: Duration::from_secs(config.grace_period_secs),
+            journal: Mutex::new(Journal {
+                next_id: 1,
+                pending: Vec::new(),
+            }),
+            path: None,
+            clock,
+        }
+    }
+
+    /// Journal saved in `dir`, with the operations journaled there before
+    ///
+    /// Journaled operations keep their execution time, even if the grace
+    /// period was changed meanwhile. An unreadable file is logged and kept
+    /// for analysis; the journal then starts empty.
+    pub fn load(config: &DestructiveConfig, dir: &Path, clock: SharedClock) -> Self {
+        let mut delayed = Self::new(config, clock);
+        let path = dir.join(JOURNAL_FILE);
+        if let Ok(content) = fs::read(&path) {
+            match serde_json::from_slice::<Journal>(&content) {
+                Ok(journal) => {
+                    if !journal.pending.is_empty() {
+                        info!(
+                            "Loaded {} pending destructive operations",
+                            journal.pending.len()
+                        );
+                    }
+                    delayed.journal = Mutex::new(journal);
+                }
+                Err(e) => {
+                    let preserved =
+                        path.with_extension(format!("damaged-{}", delayed.clock.now_ms()));
+                    warn!(
+                        "Ignoring unreadable journal {:?}, kept as {:?}: {}",
+                        path, preserved, e
+                    );
+                    let _ = fs::rename(&path, &preserved);
+                }
+            }
+        }
+        delayed.path = Some(path);
+        delayed
+    }
+
+    /// Whether destructive operations are delayed at all
+    pub fn enabled(&self) -> bool {
+        !self.grace_period.is_zero()
+    }
+
+    /// Journal `operation` for execution after the grace period
+    ///
+    /// Fails if the journal cannot be saved, the operation is then dropped.
+    pub fn schedule(&self, operation: DestructiveOperation) -> std::io::Result<PendingOperation> {
+        let now = self.clock.now_ms();
+        let mut journal = self.journal.lock().unwrap();
+        let pending = PendingOperation {
+            id: journal.next_id,
+            operation,
+            caller: caller_name(),
+            requested_at_ms: now,
+            execute_at_ms: now.saturating_add(self.grace_period.as_millis() as u64),
+        };
+        journal.next_id += 1;
+        journal.pending.push(pending.clone());
+        if let Err(e) = self.save(&journal) {
+            journal.pending.pop();
+            return Err(e);
+        }
+        Ok(pending)
+    }
+
+    /// Remove the operation `id` before it is executed
+    ///
+    /// `Ok(None)` if no such operation is pending, e.g. because it was
+    /// executed already.
+    pub fn cancel(&self, id: u64) -> std::io::Result<Option<PendingOperation>> {
+        let mut journal = self.journal.lock().unwrap();
+        let Some(index) = journal.pending.iter().position(|pending| pending.id == id) else {
+            return Ok(None);
+        };
+        let cancelled = journal.pending.remove(index);
+        if let Err(e) = self.save(&journal) {
+            journal.pending.insert(index, cancelled);
+            return Err(e);
+        }
+        Ok(Some(cancelled))
+    }
+
+    /// Operations waiting for their grace period, oldest first
+    pub fn pending(&self) -> Vec<PendingOperation> {
+        self.journal.lock().unwrap().pending.clone()
+    }
+
+    /// Operations whose grace period is over, oldest first
+    pub fn due(&self) -> Vec<PendingOperation> {
+        let now = self.clock.now_ms();
+        self.journal
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .filter(|pending| pending.execute_at_ms <= now)
+            .cloned()
+            .collect()
+    }
+
+    /// Remove the executed operation `id` from the journal
+    pub fn complete(&self, id: u64) -> std::io::Result<()> {
+        let mut journal = self.journal.lock().unwrap();
+        journal.pending.retain(|pending| pending.id != id);
+        self.save(&journal)
+    }
+
+    fn save(&self, journal: &Journal) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content = serde_json::to_vec(journal)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, path))
+    }
+}
+
+/// Execute the operations whose grace period is over every [`CHECK_INTERVAL`]
+pub async fn run(service: Arc<PersistencyServiceImpl>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        service.execute_due_operations().await;
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+
+    fn reset(namespace: &str) -> DestructiveOperation {
+        DestructiveOperation::Reset {
+            namespace: namespace.to_string(),
+        }
+    }
+
+    fn config(grace_period_secs: u64) -> DestructiveConfig {
+        DestructiveConfig { grace_period_secs }
+    }
+
+    #[test]
+    fn test_operations_are_due_after_the_grace_period() {
+        let clock = Arc::new(TestClock::new(1_000));
+        let delayed = DelayedOperations::new(&config(60), clock.clone());
+        assert!(delayed.enabled());
+        assert!(!DelayedOperations::new(&config(0), clock.clone()).enabled());
+
+        let pending = delayed.schedule(reset("runtime")).unwrap();
+        assert_eq!(pending.execute_at_ms, 61_000);
+        assert_eq!(delayed.pending(), vec![pending.clone()]);
+        assert!(delayed.due().is_empty());
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(delayed.due(), vec![pending.clone()]);
+        delayed.complete(pending.id).unwrap();
+        assert!(delayed.pending().is_empty());
+    }
+
+    #[test]
+    fn test_cancelled_operations_are_never_due() {
+        let clock = Arc::new(TestClock::new(0));
+        let delayed = DelayedOperations::new(&config(10), clock.clone());
+        let first = delayed.schedule(reset("")).unwrap();
+        let second = delayed.schedule(reset("runtime")).unwrap();
+        assert_ne!(first.id, second.id);
+
+        assert_eq!(delayed.cancel(first.id).unwrap(), Some(first.clone()));
+        assert_eq!(delayed.cancel(first.id).unwrap(), None);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(delayed.due(), vec![second]);
+    }
+
+    #[test]
+    fn test_journal_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("persistency-pending-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let clock = Arc::new(TestClock::new(1_000));
+
+        let delayed = DelayedOperations::load(&config(60), &dir, clock.clone());
+        let pending = delayed.schedule(reset("runtime")).unwrap();
+        assert!(dir.join(JOURNAL_FILE).exists());
+
+        // Due while the service was down, the shorter grace period does not matter
+        clock.advance(Duration::from_secs(120));
+        let reloaded = DelayedOperations::load(&config(10), &dir, clock.clone());
+        assert_eq!(reloaded.due(), vec![pending.clone()]);
+        reloaded.complete(pending.id).unwrap();
+
+        // Ids are not reused after a restart
+        let reloaded = DelayedOperations::load(&config(10), &dir, clock);
+        assert!(reloaded.pending().is_empty());
+        assert!(reloaded.schedule(reset("")).unwrap().id > pending.id);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
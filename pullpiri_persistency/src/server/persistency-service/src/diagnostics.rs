@@ -0,0 +1,526 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Diagnostic state of the service for bug reports
+//!
+//! Handlers record failed operations, mutating requests and key accesses in
+//! bounded histories. The `DumpStateForDiagnostics` admin RPC combines them
+//! with the key counts of the store and the active configuration into a
+//! single JSON document. Values are never part of a dump, only key names.
+//! Events and key accesses are attributed to the calling component instance,
+//! see [`crate::caller`].
+
+use crate::caller::caller_name;
+use crate::clock::SharedClock;
+use crate::config::{DiagnosticsConfig, ServiceConfig, TraceSamplingConfig};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A recorded operation on the store
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiagnosticEvent {
+    /// Unix time in milliseconds
+    pub timestamp_ms: u64,
+    /// gRPC method name, e.g. `SetValue`
+    pub operation: &'static str,
+    /// Affected key, empty for store-wide operations
+    pub key: String,
+    /// Error message, empty for audit events
+    pub detail: String,
+    /// Calling component instance, empty if unknown
+    pub component: String,
+}
+
+impl DiagnosticEvent {
+    fn new(timestamp_ms: u64, operation: &'static str, key: &str, detail: String) -> Self {
+        Self {
+            timestamp_ms,
+            operation,
+            key: key.to_string(),
+            detail,
+            component: caller_name(),
+        }
+    }
+}
+
+/// Components counted per key at most, accesses of others only count in total
+const MAX_COMPONENTS_PER_KEY: usize = 8;
+
+/// Accesses of one key
+#[derive(Debug, Default)]
+struct KeyAccesses {
+    count: u64,
+    /// Accesses by calling component instance
+    components: HashMap<String, u64>,
+}
+
+impl KeyAccesses {
+    fn record(&mut self, component: String) {
+        self.count += 1;
+        let count = self.components.len();
+        match self.components.get_mut(&component) {
+            Some(accesses) => *accesses += 1,
+            None if count < MAX_COMPONENTS_PER_KEY => {
+                self.components.insert(component, 1);
+            }
+            None => {}
+        }
+    }
+
+    /// Halve all counts, returning whether any accesses are left
+    fn halve(&mut self) -> bool {
+        self.count /= 2;
+        self.components.retain(|_, accesses| {
+            *accesses /= 2;
+            *accesses > 0
+        });
+        self.count > 0
+    }
+}
+
+/// Store state captured while no other request is running
+#[derive(Debug)]
+pub struct StoreState {
+    /// All keys of the store
+    pub keys: Vec<String>,
+    /// Keys with changes that are not flushed yet
+    pub pending_changes: usize,
+    /// Open watch channels
+    pub watch_channels: usize,
+}
+
+/// Bounded histories included in diagnostic dumps
+#[derive(Debug)]
+pub struct Diagnostics {
+    config: DiagnosticsConfig,
+    clock: SharedClock,
+    /// Monotonic time the service started at
+    started: Duration,
+    errors: Mutex<VecDeque<DiagnosticEvent>>,
+    audit: Mutex<VecDeque<DiagnosticEvent>>,
+    accesses: Mutex<HashMap<String, KeyAccesses>>,
+    /// Keys whose accesses are counted at most, unlimited if `None`
+    access_limit: Option<usize>,
+    /// Monotonic time of the last dump
+    last_dump: Mutex<Option<Duration>>,
+}
+
+impl Diagnostics {
+    pub fn new(config: &DiagnosticsConfig, clock: SharedClock) -> Self {
+        Self {
+            config: config.clone(),
+            started: clock.monotonic(),
+            clock,
+            errors: Mutex::new(VecDeque::new()),
+            audit: Mutex::new(VecDeque::new()),
+            accesses: Mutex::new(HashMap::new()),
+            access_limit: None,
+            last_dump: Mutex::new(None),
+        }
+    }
+
+    /// Count the accesses of at most `limit` keys
+    ///
+    /// When the limit is reached all counts are halved and keys that drop to
+    /// zero are forgotten, so that hot keys keep their place. A new key is not
+    /// counted if no key could be forgotten.
+    pub fn with_access_limit(mut self, limit: usize) -> Self {
+        self.access_limit = Some(limit);
+        self
+    }
+
+    /// Record a failed operation
+    pub fn record_error(&self, operation: &'static str, key: &str, detail: impl Into<String>) {
+        let event = DiagnosticEvent::new(self.clock.now_ms(), operation, key, detail.into());
+        push_bounded(&self.errors, event, self.config.recent_errors);
+    }
+
+    /// Record a successful mutating request
+    pub fn record_audit(&self, operation: &'static str, key: &str) {
+        let event = DiagnosticEvent::new(self.clock.now_ms(), operation, key, String::new());
+        push_bounded(&self.audit, event, self.config.audit_events);
+    }
+
+    /// Count a read or write of `key` by the calling component
+    pub fn record_access(&self, key: &str) {
+        let component = caller_name();
+        let mut accesses = self.accesses.lock().unwrap();
+        if let Some(key_accesses) = accesses.get_mut(key) {
+            key_accesses.record(component);
+            return;
+        }
+        if let Some(limit) = self.access_limit {
+            if accesses.len() >= limit {
+                accesses.retain(|_, key_accesses| key_accesses.halve());
+                if accesses.len() >= limit {
+                    return;
+                }
+            }
+        }
+        accesses
+            .entry(key.to_string())
+            .or_default()
+            .record(component);
+    }
+
+    /// Stop counting accesses of a removed key
+    pub fn forget_key(&self, key: &str) {
+        self.accesses.lock().unwrap().remove(key);
+    }
+
+    /// Stop counting accesses of all keys after a reset
+    pub fn forget_all_keys(&self) {
+        self.accesses.lock().unwrap().clear();
+    }
+
+    /// Claim the next dump, or return how long the caller has to wait
+    pub fn try_begin_dump(&self) -> Result<(), Duration> {
+        let interval = Duration::from_secs(self.config.min_dump_interval_secs);
+        let now = self.clock.monotonic();
+        let mut last_dump = self.last_dump.lock().unwrap();
+        if let Some(elapsed) = last_dump.map(|last| now.saturating_sub(last)) {
+            if elapsed < interval {
+                return Err(interval - elapsed);
+            }
+        }
+        *last_dump = Some(now);
+        Ok(())
+    }
+
+    /// Most accessed keys, most accessed first
+    pub fn hot_keys(&self) -> Vec<(String, u64)> {
+        let mut keys: Vec<(String, u64)> = self
+            .accesses
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, accesses)| (key.clone(), accesses.count))
+            .collect();
+        keys.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        keys.truncate(self.config.hot_keys);
+        keys
+    }
+
+    /// Components accessing `key` most, most accesses first
+    ///
+    /// Accesses of callers that did not identify themselves are counted
+    /// under an empty name.
+    pub fn key_components(&self, key: &str) -> Vec<(String, u64)> {
+        let accesses = self.accesses.lock().unwrap();
+        let mut components: Vec<(String, u64)> = accesses
+            .get(key)
+            .map(|accesses| {
+                accesses
+                    .components
+                    .iter()
+                    .map(|(component, count)| (component.clone(), *count))
+                    .collect()
+            })
+            .unwrap_or_default();
+        components.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        components
+    }
+
+    /// Recorded failed operations, oldest first
+    pub fn recent_errors(&self) -> Vec<DiagnosticEvent> {
+        self.errors.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Newest `limit` audit events, oldest first; `0` returns all recorded events
+    pub fn audit_events(&self, limit: usize) -> Vec<DiagnosticEvent> {
+        let audit = self.audit.lock().unwrap();
+        let skip = if limit == 0 {
+            0
+        } else {
+            audit.len().saturating_sub(limit)
+        };
+        audit.iter().skip(skip).cloned().collect()
+    }
+
+    /// Build the diagnostic dump
+    ///
+    /// ### Parameters
+    /// * `store: &StoreState` - store state captured for this dump
+    /// * `config: &ServiceConfig` - configuration the service was started with
+    /// * `trace_sampling: &TraceSamplingConfig` - currently active sampling rates
+    /// * `include_keys: bool` - add the names of all keys
+    /// * `audit_events: usize` - number of recent audit events, `0` for all recorded
+    pub fn dump(
+        &self,
+        store: &StoreState,
+        config: &ServiceConfig,
+        trace_sampling: &TraceSamplingConfig,
+        include_keys: bool,
+        audit_events: usize,
+    ) -> serde_json::Value {
+        let hot_keys: Vec<serde_json::Value> = self
+            .hot_keys()
+            .into_iter()
+            .map(|(key, accesses)| {
+                let components: Vec<serde_json::Value> = self
+                    .key_components(&key)
+                    .into_iter()
+                    .map(|(component, accesses)| {
+                        json!({ "component": component, "accesses": accesses })
+                    })
+                    .collect();
+                json!({ "key": key, "accesses": accesses, "components": components })
+            })
+            .collect();
+
+        let mut dump = json!({
+            "captured_at_ms": self.clock.now_ms(),
+            "uptime_secs": self.clock.monotonic().saturating_sub(self.started).as_secs(),
+            "service_version": env!("CARGO_PKG_VERSION"),
+            "key_count": store.keys.len(),
+            "key_counts_by_prefix": key_counts_by_prefix(&store.keys),
+            "pending_changes": store.pending_changes,
+            "watch_channels": store.watch_channels,
+            "config": config,
+            "trace_sampling": trace_sampling,
+            "recent_errors": self.recent_errors(),
+            "hot_keys": hot_keys,
+            "audit_events": self.audit_events(audit_events),
+        });
+        if include_keys {
+            let mut keys = store.keys.clone();
+            keys.sort();
+            dump["keys"] = json!(keys);
+        }
+        dump
+    }
+}
+
+fn push_bounded(
+    events: &Mutex<VecDeque<DiagnosticEvent>>,
+    event: DiagnosticEvent,
+    capacity: usize,
+) {
+    let mut events = events.lock().unwrap();
+    if capacity == 0 {
+        return;
+    }
+    while events.len() >= capacity {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+/// Number of keys per first path segment, e.g. `Scenario/`
+fn key_counts_by_prefix(keys: &[String]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for key in keys {
+        let prefix = match key.find('/') {
+            Some(index) => &key[..=index],
+            None => "",
+        };
+        *counts.entry(prefix.to_string()).or_default() += 1;
+    }
+    counts
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use common::identity::ComponentIdentity;
+    use std::sync::Arc;
+
+    fn new_diagnostics_with_clock(
+        min_dump_interval_secs: u64,
+        clock: Arc<TestClock>,
+    ) -> Diagnostics {
+        Diagnostics::new(
+            &DiagnosticsConfig {
+                recent_errors: 2,
+                audit_events: 3,
+                hot_keys: 2,
+                min_dump_interval_secs,
+            },
+            clock,
+        )
+    }
+
+    fn new_diagnostics(min_dump_interval_secs: u64) -> Diagnostics {
+        new_diagnostics_with_clock(min_dump_interval_secs, Arc::new(TestClock::new(0)))
+    }
+
+    #[test]
+    fn test_histories_are_bounded() {
+        let diagnostics = new_diagnostics(0);
+        for key in ["a", "b", "c"] {
+            diagnostics.record_error("SetValue", key, "failed");
+        }
+        for key in ["a", "b", "c", "d"] {
+            diagnostics.record_audit("RemoveKey", key);
+        }
+
+        let errors: Vec<String> = diagnostics
+            .recent_errors()
+            .into_iter()
+            .map(|e| e.key)
+            .collect();
+        assert_eq!(errors, vec!["b", "c"]);
+        let audit: Vec<String> = diagnostics
+            .audit_events(0)
+            .into_iter()
+            .map(|e| e.key)
+            .collect();
+        assert_eq!(audit, vec!["b", "c", "d"]);
+        let audit: Vec<String> = diagnostics
+            .audit_events(1)
+            .into_iter()
+            .map(|e| e.key)
+            .collect();
+        assert_eq!(audit, vec!["d"]);
+    }
+
+    #[test]
+    fn test_hot_keys() {
+        let diagnostics = new_diagnostics(0);
+        for key in [
+            "vehicle/mode",
+            "vehicle/mode",
+            "vehicle/speed",
+            "Scenario/a",
+            "Scenario/a",
+        ] {
+            diagnostics.record_access(key);
+        }
+        assert_eq!(
+            diagnostics.hot_keys(),
+            vec![
+                ("Scenario/a".to_string(), 2),
+                ("vehicle/mode".to_string(), 2)
+            ]
+        );
+
+        diagnostics.forget_key("Scenario/a");
+        assert_eq!(diagnostics.hot_keys()[0], ("vehicle/mode".to_string(), 2));
+    }
+
+    #[test]
+    fn test_key_components() {
+        let diagnostics = new_diagnostics(0);
+        let identity = |name: &str| {
+            Some(Arc::new(ComponentIdentity {
+                name: name.to_string(),
+                instance: "1".to_string(),
+                version: "0.1.0".to_string(),
+                node: "HPC".to_string(),
+            }))
+        };
+        for caller in [
+            identity("apiserver"),
+            identity("apiserver"),
+            identity("statemanager"),
+            None,
+        ] {
+            crate::caller::scope(caller, || diagnostics.record_access("vehicle/mode"));
+        }
+        crate::caller::scope(identity("apiserver"), || {
+            diagnostics.record_audit("SetValue", "vehicle/mode")
+        });
+
+        assert_eq!(
+            diagnostics.hot_keys(),
+            vec![("vehicle/mode".to_string(), 4)]
+        );
+        assert_eq!(
+            diagnostics.key_components("vehicle/mode"),
+            vec![
+                ("apiserver/1@HPC 0.1.0".to_string(), 2),
+                (String::new(), 1),
+                ("statemanager/1@HPC 0.1.0".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            diagnostics.audit_events(0)[0].component,
+            "apiserver/1@HPC 0.1.0"
+        );
+    }
+
+    #[test]
+    fn test_access_limit() {
+        let diagnostics = new_diagnostics(0).with_access_limit(2);
+        for key in ["hot", "hot", "hot", "cold"] {
+            diagnostics.record_access(key);
+        }
+        // Halving forgets the cold key and makes room for the new one
+        diagnostics.record_access("new");
+        assert_eq!(
+            diagnostics.hot_keys(),
+            vec![("hot".to_string(), 1), ("new".to_string(), 1)]
+        );
+        // Nothing can be forgotten, so the next new key is not counted
+        diagnostics.record_access("hot");
+        diagnostics.record_access("hot");
+        diagnostics.record_access("new");
+        diagnostics.record_access("new");
+        diagnostics.record_access("other");
+        assert_eq!(
+            diagnostics.hot_keys(),
+            vec![("hot".to_string(), 1), ("new".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_dumps_are_rate_limited() {
+        let clock = Arc::new(TestClock::new(0));
+        let diagnostics = new_diagnostics_with_clock(60, clock.clone());
+        assert!(diagnostics.try_begin_dump().is_ok());
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(diagnostics.try_begin_dump(), Err(Duration::from_secs(15)));
+        clock.advance(Duration::from_secs(15));
+        assert!(diagnostics.try_begin_dump().is_ok());
+
+        let diagnostics = new_diagnostics(0);
+        assert!(diagnostics.try_begin_dump().is_ok());
+        assert!(diagnostics.try_begin_dump().is_ok());
+    }
+
+    #[test]
+    fn test_dump_contents() {
+        let clock = Arc::new(TestClock::new(1_700_000_000_000));
+        let diagnostics = new_diagnostics_with_clock(0, clock.clone());
+        diagnostics.record_error("Flush", "", "disk full");
+        clock.advance(Duration::from_secs(120));
+        let store = StoreState {
+            keys: vec![
+                "vehicle/speed".to_string(),
+                "Scenario/a".to_string(),
+                "version".to_string(),
+            ],
+            pending_changes: 1,
+            watch_channels: 0,
+        };
+        let config = ServiceConfig::default();
+
+        let dump = diagnostics.dump(&store, &config, &config.tracing, false, 0);
+        assert_eq!(dump["key_count"], 3);
+        assert_eq!(dump["key_counts_by_prefix"]["Scenario/"], 1);
+        assert_eq!(dump["key_counts_by_prefix"][""], 1);
+        assert_eq!(dump["pending_changes"], 1);
+        assert_eq!(dump["recent_errors"][0]["detail"], "disk full");
+        assert_eq!(
+            dump["recent_errors"][0]["timestamp_ms"],
+            1_700_000_000_000u64
+        );
+        assert_eq!(dump["captured_at_ms"], 1_700_000_120_000u64);
+        assert_eq!(dump["uptime_secs"], 120);
+        assert_eq!(dump["config"]["grpc_web"]["enabled"], false);
+        assert!(dump.get("keys").is_none());
+
+        let dump = diagnostics.dump(&store, &config, &config.tracing, true, 0);
+        assert_eq!(
+            dump["keys"],
+            json!(["Scenario/a", "vehicle/speed", "version"])
+        );
+    }
+}
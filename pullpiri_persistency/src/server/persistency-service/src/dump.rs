@@ -0,0 +1,350 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Human-readable dumps of the key space
+//!
+//! `ExportToFile` writes the keys under a prefix of the default store as an
+//! export document, see [`crate::export`], to a pretty-printed JSON or YAML
+//! file in the dump directory. `ImportFromFile` restores a file of the dump
+//! directory like `Restore` does, see [`crate::restore`], and detects its
+//! layout from the content:
+//!
+//! * export documents in JSON or YAML, as written by `ExportToFile`, the
+//!   backups and the admin UI
+//! * the output of `etcdctl get --prefix "" -w json`, e.g. of the etcd that
+//!   kept the artifacts of earlier releases; values that are UTF-8 are
+//!   imported as strings, other ones as binary values
+//! * store files of the former PERSISTENCY service, `kvs_<instance>_0.json`
+//!
+//! Clients only name files in the dump directory, paths are rejected.
+
+use crate::config::DumpConfig;
+use crate::etcd;
+use crate::export::Export;
+use crate::restore::{self, RestoreSource};
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// File layout written by `ExportToFile`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+impl DumpFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            DumpFormat::Json => "json",
+            DumpFormat::Yaml => "yaml",
+        }
+    }
+}
+
+/// Layout detected in a file read by `ImportFromFile`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    /// Export document in JSON or YAML
+    Export,
+    /// JSON output of `etcdctl get -w json`
+    Etcd,
+    /// rust_kvs store file
+    Kvs,
+}
+
+impl SourceFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SourceFormat::Export => "export",
+            SourceFormat::Etcd => "etcd",
+            SourceFormat::Kvs => "kvs",
+        }
+    }
+}
+
+/// A dump written to the dump directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpFile {
+    pub name: String,
+    /// Keys in the dump
+    pub keys: usize,
+    pub size_bytes: u64,
+}
+
+/// Reject names that are not plain file names, so that clients cannot reach
+/// files outside the dump directory
+pub fn validate_file_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(format!("Invalid file name '{}'", name));
+    }
+    if name.contains(['/', '\\', '\0']) {
+        return Err(format!("File name '{}' must not be a path", name));
+    }
+    Ok(())
+}
+
+/// Dump files in the dump directory
+#[derive(Debug)]
+pub struct Dumps {
+    dir: PathBuf,
+}
+
+impl Dumps {
+    /// Dumps in `config.dir`, relative to `base_dir`
+    pub fn new(config: &DumpConfig, base_dir: &Path) -> Self {
+        Self {
+            dir: base_dir.join(&config.dir),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Write `export` to a new file `file_name`, or to
+    /// `export-<unix time ms>.<extension>` if it is empty
+    ///
+    /// Existing files are never replaced.
+    pub fn write(
+        &self,
+        export: &Export,
+        format: DumpFormat,
+        file_name: &str,
+    ) -> Result<DumpFile, String> {
+        let name = if file_name.is_empty() {
+            format!("export-{}.{}", export.exported_at_ms, format.extension())
+        } else {
+            validate_file_name(file_name)?;
+            file_name.to_string()
+        };
+        let content = match format {
+            DumpFormat::Json => serde_json::to_vec_pretty(export).map_err(|e| e.to_string()),
+            DumpFormat::Yaml => serde_yaml::to_string(export)
+                .map(String::into_bytes)
+                .map_err(|e| e.to_string()),
+        }
+        .map_err(|e| format!("Failed to encode dump: {}", e))?;
+
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create dump directory {:?}: {}", self.dir, e))?;
+        let path = self.dir.join(&name);
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .and_then(|mut file| {
+                file.write_all(&content)?;
+                file.sync_all()
+            })
+            .map_err(|e| format!("Failed to write dump {:?}: {}", path, e))?;
+        Ok(DumpFile {
+            name,
+            keys: export.entries.len(),
+            size_bytes: content.len() as u64,
+        })
+    }
+
+    /// Read the keys and values of the file `file_name`
+    pub fn read(&self, file_name: &str) -> Result<(RestoreSource, SourceFormat), String> {
+        validate_file_name(file_name)?;
+        let path = self.dir.join(file_name);
+        let content =
+            fs::read(&path).map_err(|e| format!("Failed to read dump {:?}: {}", path, e))?;
+        parse(&content, restore::file_modified_ms(&path))
+    }
+}
+
+/// Keys and values of a dump file and its detected layout
+///
+/// ### Parameters
+/// * `content: &[u8]` - content of the file, JSON or YAML
+/// * `modified_ms: u64` - Unix time in milliseconds the file was written, the
+///   age of its values unless it is an export document
+pub fn parse(content: &[u8], modified_ms: u64) -> Result<(RestoreSource, SourceFormat), String> {
+    // JSON is also YAML, but parsing it as such is slower and worse at reporting errors
+    let document: Value = match serde_json::from_slice(content) {
+        Ok(document) => document,
+        Err(json_error) => serde_yaml::from_slice(content)
+            .map_err(|e| format!("File is neither JSON ({}) nor YAML ({})", json_error, e))?,
+    };
+    let Value::Object(object) = &document else {
+        return Err("File does not hold a map".to_string());
+    };
+
+    if object.contains_key("format_version") {
+        let export: Export = serde_json::from_value(document)
+            .map_err(|e| format!("Failed to parse export: {}", e))?;
+        restore::check_format_version(&export)?;
+        if export.is_differential() {
+            return Err(
+                "Differential exports only hold changes, restore them with Restore".to_string(),
+            );
+        }
+        return Ok((
+            RestoreSource::from_parsed_export(&export),
+            SourceFormat::Export,
+        ));
+    }
+
+    if object.contains_key("header") || object.contains_key("kvs") {
+        let entries = etcd::decode_kvs(object)?;
+        return Ok((
+            RestoreSource::from_entries(entries, modified_ms),
+            SourceFormat::Etcd,
+        ));
+    }
+    let entries = restore::store_file_entries(&document).ok_or("File does not hold a map")?;
+    Ok((
+        RestoreSource::from_entries(entries, modified_ms),
+        SourceFormat::Kvs,
+    ))
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::EXPORT_FORMAT_VERSION;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use rust_kvs::kvs_value::KvsValue;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn export() -> Export {
+        Export {
+            format_version: EXPORT_FORMAT_VERSION,
+            exported_at_ms: 1_700_000_000_000,
+            prefixes: vec!["Scenario/".to_string()],
+            entries: BTreeMap::from([
+                (
+                    "Scenario/helloworld".to_string(),
+                    json!("apiVersion: v1\nkind: Scenario\n"),
+                ),
+                ("Scenario/count".to_string(), json!(3)),
+                ("Scenario/icon".to_string(), json!({"$bytes": "H4sIAA=="})),
+            ]),
+            history_id: 7,
+            revision: 42,
+            base_revision: None,
+            removed: Vec::new(),
+        }
+    }
+
+    fn dumps(name: &str) -> Dumps {
+        let dir =
+            std::env::temp_dir().join(format!("persistency-dump-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Dumps::new(&DumpConfig::default(), &dir)
+    }
+
+    #[test]
+    fn test_validate_file_name() {
+        assert!(validate_file_name("scenarios.yaml").is_ok());
+        assert!(validate_file_name("kvs_0_0.json").is_ok());
+        for name in ["", ".", "..", "../kvs_0_0.json", "/etc/passwd", "a\\b"] {
+            assert!(validate_file_name(name).is_err(), "{:?} accepted", name);
+        }
+    }
+
+    #[test]
+    fn test_write_and_read_back() {
+        for format in [DumpFormat::Json, DumpFormat::Yaml] {
+            let dumps = dumps(format.extension());
+            let written = dumps.write(&export(), format, "").unwrap();
+            assert_eq!(
+                written.name,
+                format!("export-1700000000000.{}", format.extension())
+            );
+            assert_eq!(written.keys, 3);
+            let content = fs::read_to_string(dumps.dir().join(&written.name)).unwrap();
+            assert_eq!(written.size_bytes, content.len() as u64);
+            assert!(content.contains("Scenario/helloworld"));
+
+            let (source, layout) = dumps.read(&written.name).unwrap();
+            assert_eq!(layout, SourceFormat::Export);
+            assert_eq!(source, RestoreSource::from_parsed_export(&export()));
+            // Dumps are never replaced
+            assert!(dumps.write(&export(), format, &written.name).is_err());
+            let _ = fs::remove_dir_all(dumps.dir());
+        }
+    }
+
+    #[test]
+    fn test_read_rejects_paths() {
+        let dumps = dumps("paths");
+        assert!(dumps.read("../kvs_0_0.json").unwrap_err().contains("path"));
+        assert!(dumps
+            .write(&export(), DumpFormat::Json, "../x.json")
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_etcd_output() {
+        let output = json!({
+            "header": {"cluster_id": 1, "member_id": 2, "revision": 5, "raft_term": 2},
+            "kvs": [
+                {"key": STANDARD.encode("Scenario/helloworld"), "value": STANDARD.encode("kind: Scenario"), "mod_revision": 4},
+                {"key": STANDARD.encode("Package/icon"), "value": STANDARD.encode([0xff, 0xfe])},
+            ],
+            "count": 2
+        });
+        let (source, layout) = parse(output.to_string().as_bytes(), 1_000).unwrap();
+        assert_eq!(layout, SourceFormat::Etcd);
+        assert_eq!(source.modified_ms, 1_000);
+        assert!(source.baseline.is_none());
+        assert!(
+            matches!(&source.entries["Scenario/helloworld"], KvsValue::String(v) if v == "kind: Scenario")
+        );
+        assert!(
+            matches!(&source.entries["Package/icon"], KvsValue::Bytes(v) if v == &[0xff, 0xfe])
+        );
+
+        // Nothing matched the prefix
+        let (source, _) = parse(br#"{"header": {"revision": 5}}"#, 1_000).unwrap();
+        assert!(source.entries.is_empty());
+        assert!(parse(br#"{"kvs": [{"key": "not base64!"}]}"#, 1_000).is_err());
+        assert!(parse(br#"{"kvs": {"key": "U2NlbmFyaW8v"}}"#, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_parse_store_file() {
+        let tagged = json!({"t": "obj", "v": {
+            "vehicle/mode": {"t": "str", "v": "manual"},
+            "vehicle/gear": {"t": "u32", "v": 3}
+        }});
+        let (source, layout) = parse(tagged.to_string().as_bytes(), 2_000).unwrap();
+        assert_eq!(layout, SourceFormat::Kvs);
+        assert_eq!(source.modified_ms, 2_000);
+        assert!(matches!(&source.entries["vehicle/mode"], KvsValue::String(v) if v == "manual"));
+        assert!(matches!(source.entries["vehicle/gear"], KvsValue::U32(3)));
+
+        let plain = json!({"vehicle/mode": {"t": "str", "v": "auto"}});
+        let (source, layout) = parse(plain.to_string().as_bytes(), 2_000).unwrap();
+        assert_eq!(layout, SourceFormat::Kvs);
+        assert_eq!(source.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_rejects_other_documents() {
+        assert!(parse(b"- a\n- b\n", 0).unwrap_err().contains("map"));
+        assert!(parse(b"{ not: [closed", 0)
+            .unwrap_err()
+            .contains("neither JSON"));
+        let mut differential = export();
+        differential.base_revision = Some(40);
+        let document = serde_json::to_vec(&differential).unwrap();
+        assert!(parse(&document, 0).unwrap_err().contains("Differential"));
+        let mut newer = export();
+        newer.format_version = EXPORT_FORMAT_VERSION + 1;
+        let document = serde_yaml::to_string(&newer).unwrap();
+        assert!(parse(document.as_bytes(), 0)
+            .unwrap_err()
+            .contains("not supported"));
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Tracking of changes that are not yet durable
+//!
+//! rust_kvs keeps the whole store in memory and writes it to a single file on
+//! flush. The flush clones the store and writes the clone, so writes are not
+//! held up while a large store is written. `SetValue` leaves its key pending
+//! for the next background flush, see [`crate::flusher`]. A write that could
+//! not be flushed, a removal or a reset leaves the affected keys pending until
+//! the next successful flush. Tracking them per
+//! key lets `FlushPrefix` skip the storage write entirely when nothing under
+//! the requested prefix is pending, instead of paying for a global flush on
+//! behalf of unrelated data.
+
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+/// Keys whose latest change has not been flushed to storage yet
+#[derive(Debug, Default)]
+pub struct PendingChanges {
+    keys: Mutex<BTreeSet<String>>,
+}
+
+impl PendingChanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark the latest change of `key` as not yet durable
+    pub fn mark(&self, key: &str) {
+        self.keys.lock().unwrap().insert(key.to_string());
+    }
+
+    /// Number of pending keys starting with `prefix`
+    pub fn count_with_prefix(&self, prefix: &str) -> usize {
+        self.keys
+            .lock()
+            .unwrap()
+            .range(prefix.to_string()..)
+            .take_while(|key| key.starts_with(prefix))
+            .count()
+    }
+
+    /// Forget all pending keys after a successful flush of the whole store
+    pub fn clear(&self) {
+        self.keys.lock().unwrap().clear();
+    }
+
+    /// Take the pending keys before a flush
+    ///
+    /// Keys changed while the flush runs are marked again and stay pending,
+    /// unlike with [`Self::clear`] after the flush.
+    pub fn take(&self) -> BTreeSet<String> {
+        std::mem::take(&mut *self.keys.lock().unwrap())
+    }
+
+    /// Mark the `keys` taken before a failed flush as pending again
+    pub fn restore(&self, keys: BTreeSet<String>) {
+        self.keys.lock().unwrap().extend(keys);
+    }
+
+    /// Number of pending keys
+    pub fn len(&self) -> usize {
+        self.keys.lock().unwrap().len()
+    }
+
+    /// Whether every change has been flushed
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_with_prefix() {
+        let pending = PendingChanges::new();
+        pending.mark("vehicle/mode");
+        pending.mark("vehicle/speed");
+        pending.mark("vehicle/speed");
+        pending.mark("telemetry/frame/1");
+
+        assert_eq!(pending.count_with_prefix("vehicle/"), 2);
+        assert_eq!(pending.count_with_prefix("telemetry/"), 1);
+        assert_eq!(pending.count_with_prefix("Scenario/"), 0);
+        assert_eq!(pending.count_with_prefix(""), 3);
+        assert_eq!(pending.len(), 3);
+    }
+
+    #[test]
+    fn test_clear() {
+        let pending = PendingChanges::new();
+        pending.mark("vehicle/mode");
+        pending.clear();
+        assert!(pending.is_empty());
+        assert_eq!(pending.count_with_prefix("vehicle/"), 0);
+    }
+
+    #[test]
+    fn test_take_and_restore() {
+        let pending = PendingChanges::new();
+        pending.mark("vehicle/mode");
+        let taken = pending.take();
+        assert!(pending.is_empty());
+
+        // Written during the failed flush
+        pending.mark("vehicle/speed");
+        pending.restore(taken);
+        assert_eq!(pending.count_with_prefix("vehicle/"), 2);
+    }
+}
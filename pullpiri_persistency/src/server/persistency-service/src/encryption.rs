@@ -0,0 +1,319 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Encryption of the stored values
+//!
+//! With `encryption.enabled`, the values of the keys under the configured
+//! prefixes are sealed with AES-256-GCM before they are handed to rust_kvs,
+//! so that vehicle configuration and credentials no longer land on disk in
+//! plaintext, and opened again when they are read. A sealed value is stored
+//! as an object with the single field `$encrypted` holding the format
+//! version, a random nonce and the ciphertext of the protobuf encoding of the
+//! value. The tracked key is authenticated along with it, see
+//! [`crate::namespace::tracked_key`], so a sealed value copied to another
+//! key fails to open.
+//!
+//! The prefixes apply to the keys of every namespace and to removed keys in
+//! the trash, see [`crate::trash`]. Covered values stored in plaintext are
+//! sealed when the service starts; older rust_kvs snapshots keep them in
+//! plaintext until they are rotated out. Spilled values of the bounded
+//! memory mode are sealed when they are written next.
+//!
+//! Exports, backups and snapshots hold sealed values as they are stored, so
+//! they can only be restored by a service with the same key. Sealed values
+//! are written back as they are, and cannot be read without the key. Clients
+//! cannot write objects of that shape themselves, they would be taken for
+//! sealed values.
+
+use crate::config::EncryptionConfig;
+use crate::namespace;
+use crate::PersistencyServiceImpl;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use common::persistency_proto::kvs_value::Value;
+use common::persistency_proto::KvsValue as ProtoValue;
+use prost::Message;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use rust_kvs::kvs_value::{KvsMap, KvsValue};
+use std::fs;
+
+/// Field of the object holding a sealed value
+pub const SEALED_FIELD: &str = "$encrypted";
+
+/// Format of the sealed values written by this version
+const FORMAT_VERSION: u8 = 1;
+
+/// Length of the key in bytes
+const KEY_LEN: usize = 32;
+
+/// Whether `value` is sealed, see [`Encryption::seal`]
+pub fn is_sealed(value: &KvsValue) -> bool {
+    match value {
+        KvsValue::Object(fields) => {
+            fields.len() == 1 && matches!(fields.get(SEALED_FIELD), Some(KvsValue::Bytes(_)))
+        }
+        _ => false,
+    }
+}
+
+/// Key of the configured sources, see [`EncryptionConfig`]
+fn load_key(config: &EncryptionConfig) -> Result<Vec<u8>, String> {
+    if !config.key_env.is_empty() {
+        if let Ok(encoded) = std::env::var(&config.key_env) {
+            return STANDARD
+                .decode(encoded.trim())
+                .map_err(|e| format!("Invalid key in ${}: {}", config.key_env, e));
+        }
+    }
+    if config.key_file.is_empty() {
+        return Err(format!(
+            "No encryption key, neither ${} nor a key file is set",
+            config.key_env
+        ));
+    }
+    let content = fs::read(&config.key_file)
+        .map_err(|e| format!("Failed to read key file {}: {}", config.key_file, e))?;
+    if content.len() == KEY_LEN {
+        return Ok(content);
+    }
+    let encoded = String::from_utf8_lossy(&content);
+    STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("Invalid key in {}: {}", config.key_file, e))
+}
+
+/// Sealing and opening of the stored values
+#[derive(Debug)]
+pub struct Encryption {
+    /// `None` while encryption is disabled
+    key: Option<LessSafeKey>,
+    prefixes: Vec<String>,
+    random: SystemRandom,
+}
+
+impl Encryption {
+    /// Encryption of `config`, loading its key if enabled
+    pub fn new(config: &EncryptionConfig) -> Result<Self, String> {
+        let key = if config.enabled {
+            let key = load_key(config)?;
+            if key.len() != KEY_LEN {
+                return Err(format!(
+                    "Encryption key has {} bytes instead of {}",
+                    key.len(),
+                    KEY_LEN
+                ));
+            }
+            let key = UnboundKey::new(&AES_256_GCM, &key)
+                .map_err(|_| "Invalid encryption key".to_string())?;
+            Some(LessSafeKey::new(key))
+        } else {
+            None
+        };
+        Ok(Self {
+            key,
+            prefixes: config.prefixes.clone(),
+            random: SystemRandom::new(),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Whether the value of `tracked` has to be sealed
+    pub fn covers(&self, tracked: &str) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        // Keys of the namespaces and of the trash are matched without their namespaces
+        let mut key = tracked;
+        loop {
+            let (namespace, rest) = namespace::split_tracked(key);
+            if namespace.is_empty() {
+                break;
+            }
+            key = rest;
+        }
+        self.prefixes.is_empty()
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| key.starts_with(prefix.as_str()))
+    }
+
+    /// Sealed `value` of `tracked`
+    pub fn seal(&self, tracked: &str, value: &KvsValue) -> Result<KvsValue, String> {
+        let key = self.key.as_ref().ok_or("Encryption is disabled")?;
+        let mut nonce = [0u8; NONCE_LEN];
+        self.random
+            .fill(&mut nonce)
+            .map_err(|_| "No random nonce available".to_string())?;
+        let mut sealed = PersistencyServiceImpl::kvs_value_to_proto(value).encode_to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(tracked.as_bytes()),
+            &mut sealed,
+        )
+        .map_err(|_| "Failed to seal value".to_string())?;
+
+        let mut data = Vec::with_capacity(1 + NONCE_LEN + sealed.len());
+        data.push(FORMAT_VERSION);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&sealed);
+        Ok(KvsValue::Object(KvsMap::from([(
+            SEALED_FIELD.to_string(),
+            KvsValue::Bytes(data),
+        )])))
+    }
+
+    /// Opened `value` of `tracked`, `value` itself if it is not sealed
+    pub fn open(&self, tracked: &str, value: KvsValue) -> Result<KvsValue, String> {
+        if !is_sealed(&value) {
+            return Ok(value);
+        }
+        let KvsValue::Object(mut fields) = value else {
+            unreachable!("sealed values are objects");
+        };
+        let Some(KvsValue::Bytes(mut data)) = fields.remove(SEALED_FIELD) else {
+            unreachable!("sealed values hold bytes");
+        };
+        let key = self.key.as_ref().ok_or_else(|| {
+            format!(
+                "Value of {} is encrypted, but encryption is disabled",
+                tracked
+            )
+        })?;
+        if data.len() < 1 + NONCE_LEN || data[0] != FORMAT_VERSION {
+            return Err(format!("Unknown encryption format of {}", tracked));
+        }
+        let nonce = Nonce::try_assume_unique_for_key(&data[1..1 + NONCE_LEN])
+            .map_err(|_| format!("Invalid nonce of {}", tracked))?;
+        let plain = key
+            .open_in_place(
+                nonce,
+                Aad::from(tracked.as_bytes()),
+                &mut data[1 + NONCE_LEN..],
+            )
+            .map_err(|_| format!("Failed to decrypt {}, wrong key or corrupted", tracked))?;
+        let proto = ProtoValue::decode(&*plain)
+            .map_err(|e| format!("Invalid decrypted value of {}: {}", tracked, e))?;
+        PersistencyServiceImpl::proto_to_kvs_value(&proto)
+    }
+
+    /// Opened `value` of `tracked` as sent to the clients, `value` itself if it is not sealed
+    pub fn open_proto(&self, tracked: &str, value: ProtoValue) -> Result<ProtoValue, String> {
+        // Sealed values are objects, the others need no conversion
+        if !matches!(value.value, Some(Value::ObjectValue(_))) {
+            return Ok(value);
+        }
+        let stored = PersistencyServiceImpl::proto_to_kvs_value(&value)?;
+        if !is_sealed(&stored) {
+            return Ok(value);
+        }
+        let opened = self.open(tracked, stored)?;
+        Ok(PersistencyServiceImpl::kvs_value_to_proto(&opened))
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(key_file: &str, prefixes: &[&str]) -> EncryptionConfig {
+        EncryptionConfig {
+            enabled: true,
+            key_file: key_file.to_string(),
+            key_env: String::new(),
+            prefixes: prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+        }
+    }
+
+    fn with_key(name: &str, key: &[u8], prefixes: &[&str]) -> Encryption {
+        let path =
+            std::env::temp_dir().join(format!("persistency-{}-{}.key", name, std::process::id()));
+        fs::write(&path, key).unwrap();
+        let encryption = Encryption::new(&config(path.to_str().unwrap(), prefixes));
+        fs::remove_file(&path).unwrap();
+        encryption.unwrap()
+    }
+
+    #[test]
+    fn test_sealed_values_open_again() {
+        let encryption = with_key("encryption-seal", &[7; KEY_LEN], &[]);
+        let value = KvsValue::Object(KvsMap::from([
+            ("user".to_string(), KvsValue::String("vehicle".to_string())),
+            ("port".to_string(), KvsValue::U32(8883)),
+        ]));
+        let sealed = encryption.seal("credentials/mqtt", &value).unwrap();
+        assert!(is_sealed(&sealed));
+        assert_ne!(sealed, value);
+        assert_eq!(
+            encryption.open("credentials/mqtt", sealed.clone()),
+            Ok(value.clone())
+        );
+
+        let proto = PersistencyServiceImpl::kvs_value_to_proto(&sealed);
+        assert_eq!(
+            encryption.open_proto("credentials/mqtt", proto),
+            Ok(PersistencyServiceImpl::kvs_value_to_proto(&value))
+        );
+
+        // Bound to the key it was sealed for
+        assert!(encryption
+            .open("credentials/other", sealed.clone())
+            .is_err());
+        let other = with_key("encryption-other", &[8; KEY_LEN], &[]);
+        assert!(other.open("credentials/mqtt", sealed.clone()).is_err());
+        let disabled = Encryption::new(&EncryptionConfig::default()).unwrap();
+        assert!(disabled.open("credentials/mqtt", sealed).is_err());
+
+        // Plaintext values are passed through
+        let plain = KvsValue::String("manual".to_string());
+        assert_eq!(encryption.open("vehicle/mode", plain.clone()), Ok(plain));
+    }
+
+    #[test]
+    fn test_covered_keys() {
+        let encryption = with_key("encryption-covers", &[7; KEY_LEN], &["credentials/"]);
+        assert!(encryption.covers("credentials/mqtt"));
+        assert!(encryption.covers("<artifacts>/credentials/mqtt"));
+        assert!(encryption.covers("<trash>/<artifacts>/credentials/mqtt"));
+        assert!(!encryption.covers("vehicle/mode"));
+        let disabled = Encryption::new(&EncryptionConfig::default()).unwrap();
+        assert!(!disabled.covers("credentials/mqtt"));
+    }
+
+    #[test]
+    fn test_key_sources() {
+        let base64_key = STANDARD.encode([7; KEY_LEN]);
+        let from_base64 = with_key(
+            "encryption-base64",
+            format!("{}\n", base64_key).as_bytes(),
+            &[],
+        );
+        let from_raw = with_key("encryption-raw", &[7; KEY_LEN], &[]);
+        let sealed = from_raw.seal("vehicle/vin", &KvsValue::Null).unwrap();
+        assert_eq!(from_base64.open("vehicle/vin", sealed), Ok(KvsValue::Null));
+
+        std::env::set_var("PERSISTENCY_TEST_ENCRYPTION_KEY", &base64_key);
+        let from_env = Encryption::new(&EncryptionConfig {
+            key_env: "PERSISTENCY_TEST_ENCRYPTION_KEY".to_string(),
+            ..config("/nonexistent/persistency.key", &[])
+        });
+        assert!(from_env.unwrap().is_enabled());
+
+        assert!(Encryption::new(&config("/nonexistent/persistency.key", &[])).is_err());
+        assert!(Encryption::new(&config("", &[])).is_err());
+        let short = std::env::temp_dir().join(format!(
+            "persistency-encryption-short-{}.key",
+            std::process::id()
+        ));
+        fs::write(&short, STANDARD.encode([7; 16])).unwrap();
+        assert!(Encryption::new(&config(short.to_str().unwrap(), &[])).is_err());
+        fs::remove_file(&short).unwrap();
+    }
+}
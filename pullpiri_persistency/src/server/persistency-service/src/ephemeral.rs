@@ -0,0 +1,139 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! In-memory service for the tests of the components
+//!
+//! Components reach the service through a client shared by the whole
+//! process, while every test runs on a runtime of its own. [`spawn`] serves a
+//! service created with [`PersistencyServiceImpl::new_in_memory`] on a local
+//! port from a thread and runtime of its own, and
+//! [`EphemeralService::run`] connects clients on that runtime, so that the
+//! service and the connections to it outlive the single tests. Tests of the
+//! service itself create it with the configuration and clock they need and
+//! keep driving it, e.g. its expiry sweeps, while it is served with [`serve`].
+
+use crate::caller::CallerLayer;
+use crate::status::StatusCodesLayer;
+use crate::PersistencyServiceImpl;
+use common::persistency_proto::persistency_service_server::PersistencyServiceServer;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use tokio::runtime::{Builder, Handle};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tracing::error;
+
+/// In-memory service running until the process exits
+#[derive(Debug, Clone)]
+pub struct EphemeralService {
+    endpoint: String,
+    runtime: Handle,
+}
+
+impl EphemeralService {
+    /// `http://` endpoint to connect clients to
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Run `future` on the runtime of the service, e.g. to connect a client
+    ///
+    /// Panics of `future` are resumed in the caller.
+    pub async fn run<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match self.runtime.spawn(future).await {
+            Ok(output) => output,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+/// Serve a new in-memory service on a free local port
+pub fn spawn() -> io::Result<EphemeralService> {
+    let service =
+        PersistencyServiceImpl::new_in_memory().map_err(|e| io::Error::other(e.to_string()))?;
+    serve(Arc::new(service))
+}
+
+/// Serve `service` on a free local port
+pub fn serve(service: Arc<PersistencyServiceImpl>) -> io::Result<EphemeralService> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let endpoint = format!("http://{}", listener.local_addr()?);
+
+    // A second worker serves the other calls while a handler blocks, e.g. on a flush
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()?;
+    let handle = runtime.handle().clone();
+    std::thread::Builder::new()
+        .name("persistency-ephemeral".to_string())
+        .spawn(move || {
+            let served = runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener)?;
+                let incoming = TcpIncoming::from_listener(listener, true, None)?;
+                Server::builder()
+                    .layer(CallerLayer::new())
+                    .layer(StatusCodesLayer::new())
+                    .add_service(PersistencyServiceServer::from_arc(service))
+                    .serve_with_incoming(incoming)
+                    .await?;
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+            });
+            if let Err(e) = served {
+                error!("In-memory persistency service stopped: {}", e);
+            }
+        })?;
+    Ok(EphemeralService {
+        endpoint,
+        runtime: handle,
+    })
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::persistency_proto::persistency_service_client::PersistencyServiceClient;
+    use common::persistency_proto::{kvs_value, GetValueRequest, KvsValue, SetValueRequest};
+
+    #[tokio::test]
+    async fn test_values_written_and_read_back() {
+        let service = spawn().unwrap();
+        let endpoint = service.endpoint().to_string();
+        let mut client = service
+            .run(async move { PersistencyServiceClient::connect(endpoint).await })
+            .await
+            .unwrap();
+
+        let value = KvsValue {
+            value: Some(kvs_value::Value::StringValue("autonomous".to_string())),
+        };
+        let set = client
+            .set_value(SetValueRequest {
+                key: "vehicle/mode".to_string(),
+                value: Some(value.clone()),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(set.success);
+        let get = client
+            .get_value(GetValueRequest {
+                key: "vehicle/mode".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(get.value, Some(value));
+    }
+}
@@ -0,0 +1,354 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Migration of the keys of an etcd
+//!
+//! Earlier releases kept the artifacts and states of Pullpiri in etcd.
+//! `MigrateFromEtcd` reads the keys under a prefix from a running etcd over
+//! the JSON gateway of its v3 API and imports them under the same keys into
+//! the default store, like `Restore` does, see [`crate::restore`]. The keys
+//! are read page by page, every page at the revision of the first one, so
+//! that the migrated keys are one consistent view of etcd even while it is
+//! written. Dumps taken with `etcdctl get --prefix "" -w json` hold the same
+//! layout and are imported with `ImportFromFile`, see [`crate::dump`].
+//!
+//! etcd keeps no write times, for newest-wins migrations the values are as
+//! old as the read. Values that are UTF-8 are imported as strings, other ones
+//! as binary values.
+
+use crate::config::EtcdMigrationConfig;
+use crate::restore::RestoreReport;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rust_kvs::kvs_value::KvsValue;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Keys read from etcd
+#[derive(Debug, Clone, PartialEq)]
+pub struct EtcdKeys {
+    pub entries: BTreeMap<String, KvsValue>,
+    /// Revision of etcd the keys were read at
+    pub revision: i64,
+}
+
+/// Outcome of a migration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EtcdMigration {
+    /// Keys read from etcd
+    pub read_keys: usize,
+    /// Revision of etcd the keys were read at
+    pub revision: i64,
+    pub report: RestoreReport,
+}
+
+/// Reader of the keys of an etcd
+#[derive(Debug)]
+pub struct EtcdReader {
+    client: reqwest::Client,
+    endpoint: String,
+    page_size: u32,
+}
+
+impl EtcdReader {
+    /// Reader of the etcd at `endpoint`, or at the configured one if it is empty
+    pub fn new(config: &EtcdMigrationConfig, endpoint: &str) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        let endpoint = if endpoint.is_empty() {
+            &config.endpoint
+        } else {
+            endpoint
+        };
+        Ok(Self {
+            client,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            page_size: config.page_size.max(1),
+        })
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Read every key under `prefix`, every key if it is empty
+    pub async fn read_prefix(&self, prefix: &str) -> Result<EtcdKeys, String> {
+        let range_end = range_end(prefix.as_bytes());
+        // The empty key is not a valid etcd key, start right after it
+        let mut start = if prefix.is_empty() {
+            vec![0]
+        } else {
+            prefix.as_bytes().to_vec()
+        };
+        let mut keys = EtcdKeys {
+            entries: BTreeMap::new(),
+            revision: 0,
+        };
+        loop {
+            let mut request = json!({
+                "key": STANDARD.encode(&start),
+                "range_end": STANDARD.encode(&range_end),
+                "limit": self.page_size,
+            });
+            if keys.revision > 0 {
+                // The gateway takes 64-bit integers as strings
+                request["revision"] = json!(keys.revision.to_string());
+            }
+            let response = self.range(&request).await?;
+            if keys.revision == 0 {
+                keys.revision = header_revision(&response)?;
+            }
+            let page = decode_kvs(&response)?;
+            let more = response.get("more").and_then(Value::as_bool) == Some(true);
+            // etcd sorts the keys by their bytes, like the page
+            let last = page.keys().next_back().cloned();
+            keys.entries.extend(page);
+            match (more, last) {
+                (true, Some(last)) => {
+                    start = last.into_bytes();
+                    start.push(0);
+                }
+                (true, None) => return Err("etcd announced more keys, but sent none".to_string()),
+                (false, _) => return Ok(keys),
+            }
+        }
+    }
+
+    /// POST a range request to the JSON gateway
+    async fn range(&self, request: &Value) -> Result<Map<String, Value>, String> {
+        let url = format!("{}/v3/kv/range", self.endpoint);
+        let response = self
+            .client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(request.to_string())
+            .send()
+            .await
+            .map_err(|e| format!("Request to {} failed: {}", url, e))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read the response of {}: {}", url, e))?;
+        if !status.is_success() {
+            return Err(format!(
+                "etcd at {} answered {}: {}",
+                url,
+                status,
+                body.trim()
+            ));
+        }
+        match serde_json::from_str(&body) {
+            Ok(Value::Object(response)) => Ok(response),
+            Ok(_) => Err(format!("etcd at {} answered no object", url)),
+            Err(e) => Err(format!("Failed to parse the response of {}: {}", url, e)),
+        }
+    }
+}
+
+/// End of the key range holding the keys under `prefix`, `\0` for every key
+fn range_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    vec![0]
+}
+
+/// Revision in the header of a response, sent as a string or a number
+fn header_revision(response: &Map<String, Value>) -> Result<i64, String> {
+    let revision = response
+        .get("header")
+        .and_then(|header| header.get("revision"));
+    match revision {
+        Some(Value::String(revision)) => revision.parse().ok(),
+        Some(Value::Number(revision)) => revision.as_i64(),
+        _ => None,
+    }
+    .ok_or_else(|| "etcd sent no revision".to_string())
+}
+
+/// Keys and values of a range response, or of the output of `etcdctl get -w json`
+///
+/// Keys and values are base64-encoded; keys have to be UTF-8.
+pub fn decode_kvs(response: &Map<String, Value>) -> Result<BTreeMap<String, KvsValue>, String> {
+    let decode = |pair: &Value, field: &str| -> Result<Vec<u8>, String> {
+        let encoded = pair.get(field).and_then(Value::as_str).unwrap_or_default();
+        STANDARD
+            .decode(encoded)
+            .map_err(|e| format!("Invalid base64 {} from etcd: {}", field, e))
+    };
+    let pairs = match response.get("kvs") {
+        Some(Value::Array(pairs)) => pairs.as_slice(),
+        // etcd leaves the pairs out if no key matched
+        None => &[],
+        Some(_) => return Err("The kvs from etcd are not a list".to_string()),
+    };
+    let mut entries = BTreeMap::new();
+    for pair in pairs {
+        let key = String::from_utf8(decode(pair, "key")?)
+            .map_err(|e| format!("Key {:?} from etcd is not UTF-8", e.as_bytes()))?;
+        if key.is_empty() {
+            return Err("Key from etcd is empty".to_string());
+        }
+        let value = match String::from_utf8(decode(pair, "value")?) {
+            Ok(value) => KvsValue::String(value),
+            Err(e) => KvsValue::Bytes(e.into_bytes()),
+        };
+        entries.insert(key, value);
+    }
+    Ok(entries)
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn pair(key: &str, value: &str) -> Value {
+        json!({"key": STANDARD.encode(key), "value": STANDARD.encode(value), "mod_revision": "3"})
+    }
+
+    /// Answer one request with each of `bodies`, returns the URL and the request bodies
+    async fn mock_etcd(bodies: Vec<Value>) -> (String, tokio::task::JoinHandle<Vec<Value>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for body in bodies {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut received = Vec::new();
+                let mut buf = vec![0u8; 64 * 1024];
+                // Read the head and the body, which follows it right away
+                let request = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    received.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&received).into_owned();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        if let Ok(request) = serde_json::from_str::<Value>(body) {
+                            assert!(head.starts_with("POST /v3/kv/range "), "{}", head);
+                            break request;
+                        }
+                    }
+                };
+                requests.push(request);
+                let body = body.to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn reader(endpoint: &str, page_size: u32) -> EtcdReader {
+        let config = EtcdMigrationConfig {
+            enabled: true,
+            page_size,
+            timeout_secs: 5,
+            ..EtcdMigrationConfig::default()
+        };
+        EtcdReader::new(&config, endpoint).unwrap()
+    }
+
+    #[test]
+    fn test_range_end() {
+        assert_eq!(range_end(b"Scenario/"), b"Scenario0");
+        assert_eq!(range_end(b"a\xff"), b"b");
+        assert_eq!(range_end(b"\xff\xff"), [0]);
+        assert_eq!(range_end(b""), [0]);
+    }
+
+    #[test]
+    fn test_endpoint_defaults_to_configuration() {
+        assert_eq!(reader("", 10).endpoint(), "http://127.0.0.1:2379");
+        assert_eq!(
+            reader("http://10.0.0.5:2379/", 10).endpoint(),
+            "http://10.0.0.5:2379"
+        );
+    }
+
+    #[test]
+    fn test_decode_kvs() {
+        let response = json!({
+            "kvs": [pair("Scenario/helloworld", "kind: Scenario"),
+                    {"key": STANDARD.encode("Package/icon"), "value": STANDARD.encode([0xff, 0xfe])}]
+        });
+        let entries = decode_kvs(response.as_object().unwrap()).unwrap();
+        assert!(
+            matches!(&entries["Scenario/helloworld"], KvsValue::String(v) if v == "kind: Scenario")
+        );
+        assert!(matches!(&entries["Package/icon"], KvsValue::Bytes(v) if v == &[0xff, 0xfe]));
+
+        // A key without value holds an empty one
+        let response = json!({"kvs": [{"key": STANDARD.encode("vehicle/mode")}]});
+        let entries = decode_kvs(response.as_object().unwrap()).unwrap();
+        assert!(matches!(&entries["vehicle/mode"], KvsValue::String(v) if v.is_empty()));
+
+        let response = json!({"kvs": [{"key": STANDARD.encode([0xc3])}]});
+        assert!(decode_kvs(response.as_object().unwrap())
+            .unwrap_err()
+            .contains("UTF-8"));
+        let response = json!({"kvs": [{"key": ""}]});
+        assert!(decode_kvs(response.as_object().unwrap())
+            .unwrap_err()
+            .contains("empty"));
+    }
+
+    #[tokio::test]
+    async fn test_read_prefix_in_pages_at_one_revision() {
+        let (url, server) = mock_etcd(vec![
+            json!({"header": {"revision": "17"}, "kvs": [pair("Scenario/a", "1"), pair("Scenario/b", "2")], "more": true, "count": "3"}),
+            json!({"header": {"revision": "18"}, "kvs": [pair("Scenario/c", "3")], "count": "3"}),
+        ])
+        .await;
+        let keys = reader(&url, 2).read_prefix("Scenario/").await.unwrap();
+        assert_eq!(keys.revision, 17);
+        assert_eq!(
+            keys.entries.keys().collect::<Vec<_>>(),
+            ["Scenario/a", "Scenario/b", "Scenario/c"]
+        );
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0]["key"], STANDARD.encode("Scenario/"));
+        assert_eq!(requests[0]["range_end"], STANDARD.encode("Scenario0"));
+        assert_eq!(requests[0]["limit"], 2);
+        assert!(requests[0].get("revision").is_none());
+        assert_eq!(requests[1]["key"], STANDARD.encode("Scenario/b\0"));
+        assert_eq!(requests[1]["revision"], "17");
+    }
+
+    #[tokio::test]
+    async fn test_read_every_key() {
+        let (url, server) = mock_etcd(vec![json!({"header": {"revision": 4}})]).await;
+        let keys = reader(&url, 10).read_prefix("").await.unwrap();
+        assert!(keys.entries.is_empty());
+        assert_eq!(keys.revision, 4);
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0]["key"], STANDARD.encode([0]));
+        assert_eq!(requests[0]["range_end"], STANDARD.encode([0]));
+    }
+
+    #[tokio::test]
+    async fn test_read_fails_without_service() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let err = reader(&url, 10).read_prefix("").await.unwrap_err();
+        assert!(err.contains("/v3/kv/range failed"), "{}", err);
+    }
+}
@@ -0,0 +1,258 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Key expiry
+//!
+//! `SetValue` requests with `ttl_seconds` give the key a deadline, so that
+//! transient state like the last DDS vehicle mode or discovery heartbeats
+//! disappears when its publisher stops refreshing it. Writing the key again
+//! replaces the deadline, a write without a TTL keeps the key for good.
+//!
+//! A sweeper removes the keys past their deadline every
+//! `expiry.sweep_interval_ms`, an expired key can be read until then. The
+//! deadlines are wall clock times kept in `persistency-expiry.json` next to
+//! the store, saved by the sweeper when they changed. Keys whose deadline
+//! passed while the service was down are removed by the first sweep;
+//! deadlines set shortly before a crash can be lost, those keys then stay.
+
+use crate::clock::SharedClock;
+use crate::config::ExpiryConfig;
+use crate::PersistencyServiceImpl;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+/// File the deadlines are kept in, next to the store
+pub const EXPIRY_FILE: &str = "persistency-expiry.json";
+
+/// Deadlines of the keys written with a time to live
+#[derive(Debug)]
+pub struct ExpiryTracker {
+    /// Deadline per key in Unix milliseconds
+    deadlines: Mutex<HashMap<String, u64>>,
+    /// Whether the deadlines changed since they were last saved
+    dirty: AtomicBool,
+    path: Option<PathBuf>,
+    clock: SharedClock,
+}
+
+impl ExpiryTracker {
+    /// Tracker kept in memory only
+    pub fn new(clock: SharedClock) -> Self {
+        Self {
+            deadlines: Mutex::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+            path: None,
+            clock,
+        }
+    }
+
+    /// Tracker saved in `dir`, with the deadlines saved there before
+    ///
+    /// An unreadable file is logged and the deadlines start empty.
+    pub fn load(dir: &Path, clock: SharedClock) -> Self {
+        let path = dir.join(EXPIRY_FILE);
+        let deadlines = match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable expiry file {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            deadlines: Mutex::new(deadlines),
+            dirty: AtomicBool::new(false),
+            path: Some(path),
+            clock,
+        }
+    }
+
+    /// Remove `key` `ttl` from now
+    pub fn set(&self, key: &str, ttl: Duration) {
+        let deadline = self.clock.now_ms().saturating_add(ttl.as_millis() as u64);
+        self.deadlines
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), deadline);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Keep `key` for good
+    pub fn clear(&self, key: &str) {
+        if self.deadlines.lock().unwrap().remove(key).is_some() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Keep every key for good, after a reset
+    pub fn clear_all(&self) {
+        let mut deadlines = self.deadlines.lock().unwrap();
+        if !deadlines.is_empty() {
+            deadlines.clear();
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Time until `key` is removed, `None` if it does not expire
+    pub fn remaining(&self, key: &str) -> Option<Duration> {
+        let deadline = *self.deadlines.lock().unwrap().get(key)?;
+        Some(Duration::from_millis(
+            deadline.saturating_sub(self.clock.now_ms()),
+        ))
+    }
+
+    /// Whether any key is past its deadline
+    pub fn has_expired(&self) -> bool {
+        let now = self.clock.now_ms();
+        self.deadlines
+            .lock()
+            .unwrap()
+            .values()
+            .any(|&deadline| deadline <= now)
+    }
+
+    /// Take the keys past their deadline, sorted
+    pub fn take_expired(&self) -> Vec<String> {
+        self.take_expired_where(|_| true)
+    }
+
+    /// Take the keys past their deadline that match `filter`, sorted
+    pub fn take_expired_where(&self, filter: impl Fn(&str) -> bool) -> Vec<String> {
+        let now = self.clock.now_ms();
+        let mut deadlines = self.deadlines.lock().unwrap();
+        let mut expired: Vec<String> = deadlines
+            .iter()
+            .filter(|(key, &deadline)| deadline <= now && filter(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            deadlines.remove(key);
+        }
+        if !expired.is_empty() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        expired.sort();
+        expired
+    }
+
+    /// Number of keys with a deadline
+    pub fn len(&self) -> usize {
+        self.deadlines.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Save the deadlines if they changed since the last save
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let content = serde_json::to_vec(&*self.deadlines.lock().unwrap())?;
+        let tmp = path.with_extension("tmp");
+        let result = fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, path));
+        if result.is_err() {
+            // Try again on the next sweep
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// Remove the expired keys every `config.sweep_interval_ms`
+pub async fn run(config: ExpiryConfig, service: Arc<PersistencyServiceImpl>) {
+    info!("Removing expired keys every {}ms", config.sweep_interval_ms);
+
+    let period = Duration::from_millis(config.sweep_interval_ms.max(1));
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        service.expire_keys().await;
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+
+    #[test]
+    fn test_keys_expire_at_their_deadline() {
+        let clock = Arc::new(TestClock::new(1_000));
+        let expiry = ExpiryTracker::new(clock.clone());
+        expiry.set("vehicle/mode", Duration::from_secs(10));
+        expiry.set("discovery/heartbeat", Duration::from_secs(30));
+
+        assert_eq!(
+            expiry.remaining("vehicle/mode"),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(expiry.remaining("vehicle/speed"), None);
+        assert!(!expiry.has_expired());
+        assert!(expiry.take_expired().is_empty());
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(expiry.remaining("vehicle/mode"), Some(Duration::ZERO));
+        assert!(expiry.has_expired());
+        assert!(expiry
+            .take_expired_where(|key| key.starts_with("discovery/"))
+            .is_empty());
+        assert_eq!(expiry.take_expired(), vec!["vehicle/mode".to_string()]);
+        assert_eq!(expiry.remaining("vehicle/mode"), None);
+        assert_eq!(expiry.len(), 1);
+    }
+
+    #[test]
+    fn test_set_replaces_and_clear_removes_deadline() {
+        let clock = Arc::new(TestClock::new(0));
+        let expiry = ExpiryTracker::new(clock.clone());
+        expiry.set("vehicle/mode", Duration::from_secs(5));
+        clock.advance(Duration::from_secs(4));
+        expiry.set("vehicle/mode", Duration::from_secs(5));
+        clock.advance(Duration::from_secs(4));
+        assert!(expiry.take_expired().is_empty());
+
+        expiry.clear("vehicle/mode");
+        clock.advance(Duration::from_secs(60));
+        assert!(expiry.take_expired().is_empty());
+
+        expiry.set("a", Duration::from_secs(1));
+        expiry.set("b", Duration::from_secs(1));
+        expiry.clear_all();
+        assert!(expiry.is_empty());
+    }
+
+    #[test]
+    fn test_deadlines_survive_restart() {
+        let dir = std::env::temp_dir().join(format!("persistency-expiry-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let clock = Arc::new(TestClock::new(1_000));
+
+        let expiry = ExpiryTracker::load(&dir, clock.clone());
+        expiry.set("vehicle/mode", Duration::from_secs(10));
+        expiry.save().unwrap();
+        assert!(dir.join(EXPIRY_FILE).exists());
+
+        // The service was down past the deadline
+        clock.advance(Duration::from_secs(20));
+        let reloaded = ExpiryTracker::load(&dir, clock.clone());
+        assert_eq!(reloaded.take_expired(), vec!["vehicle/mode".to_string()]);
+        reloaded.save().unwrap();
+        assert!(ExpiryTracker::load(&dir, clock).is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,331 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Consistent export of key prefixes
+//!
+//! An export is a JSON document holding every key under the requested
+//! prefixes together with its value. It is captured while no other request
+//! runs, so it never contains half of a multi-key update.
+//!
+//! Every export records the point of the [`crate::history`] it was captured
+//! at. A differential export against such a base only holds the keys changed
+//! since the base and lists the keys removed since then; applied on top of
+//! its base it yields the full export.
+
+use crate::backend::StorageBackend;
+use crate::clock::Clock;
+use crate::history::{Baseline, MutationHistory};
+use crate::spill::SpillStore;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rust_kvs::kvs_value::KvsValue;
+use rust_kvs::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Key of the JSON object holding a binary value in base64
+const BYTES_KEY: &str = "$bytes";
+
+/// Version of the export document layout
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// Exported keys and values
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Export {
+    pub format_version: u32,
+    /// Unix time of the capture in milliseconds
+    pub exported_at_ms: u64,
+    /// Requested prefixes, empty if every key was exported
+    pub prefixes: Vec<String>,
+    /// Values by key, sorted by key
+    pub entries: BTreeMap<String, Value>,
+    /// Mutation history the revisions refer to, 0 for exports of older services
+    #[serde(default)]
+    pub history_id: u64,
+    /// Revision of the last change included
+    #[serde(default)]
+    pub revision: u64,
+    /// Revision of the base of a differential export, `None` for a full export
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_revision: Option<u64>,
+    /// Keys under the prefixes removed since the base, differential exports only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+impl Export {
+    /// Point of the mutation history the export was captured at
+    pub fn baseline(&self) -> Baseline {
+        Baseline {
+            history_id: self.history_id,
+            revision: self.revision,
+        }
+    }
+
+    /// Whether the export only holds the changes since its base
+    pub fn is_differential(&self) -> bool {
+        self.base_revision.is_some()
+    }
+}
+
+/// Export the keys of `kvs` under `prefixes`
+///
+/// With a `base` covered by `history` only the changes since the base are
+/// exported, otherwise every key. The caller must hold the store's write lock
+/// for the export to be consistent.
+///
+/// ### Parameters
+/// * `kvs: &dyn StorageBackend` - store to export
+/// * `spill: Option<&SpillStore>` - spilled keys of the store in bounded memory mode
+/// * `prefixes: &[String]` - key prefixes to include, empty includes every key
+/// * `clock: &dyn Clock` - time source of the export timestamp
+/// * `history: &MutationHistory` - changes of the store since the service started
+/// * `base: Option<Baseline>` - earlier export to take a differential export against
+pub fn export_prefixes(
+    kvs: &dyn StorageBackend,
+    spill: Option<&SpillStore>,
+    prefixes: &[String],
+    clock: &dyn Clock,
+    history: &MutationHistory,
+    base: Option<Baseline>,
+) -> Result<Export, ErrorCode> {
+    let current = history.baseline();
+    let mut export = Export {
+        format_version: EXPORT_FORMAT_VERSION,
+        exported_at_ms: clock.now_ms(),
+        prefixes: prefixes.to_vec(),
+        entries: BTreeMap::new(),
+        history_id: current.history_id,
+        revision: current.revision,
+        base_revision: None,
+        removed: Vec::new(),
+    };
+
+    if let Some((base, changes)) = base.and_then(|base| Some((base, history.changes_since(base)?)))
+    {
+        for key in changes.changed {
+            if matches_prefixes(&key, prefixes) {
+                let value = match spill {
+                    Some(spill) => spill.get(kvs, &key)?,
+                    None => kvs.get_value(&key)?,
+                };
+                export.entries.insert(key, value_to_json(&value));
+            }
+        }
+        export.removed = changes
+            .removed
+            .into_iter()
+            .filter(|key| matches_prefixes(key, prefixes))
+            .collect();
+        export.base_revision = Some(base.revision);
+        return Ok(export);
+    }
+
+    for key in kvs.get_all_keys()? {
+        if matches_prefixes(&key, prefixes) {
+            let value = kvs.get_value(&key)?;
+            export.entries.insert(key, value_to_json(&value));
+        }
+    }
+    if let Some(spill) = spill {
+        spill.for_each_spilled(|key, value| {
+            if matches_prefixes(key, prefixes) {
+                export.entries.insert(key.to_string(), value_to_json(value));
+            }
+        })?;
+    }
+    Ok(export)
+}
+
+fn matches_prefixes(key: &str, prefixes: &[String]) -> bool {
+    prefixes.is_empty()
+        || prefixes
+            .iter()
+            .any(|prefix| key.starts_with(prefix.as_str()))
+}
+
+/// JSON form of a stored value
+///
+/// Integer types are not distinguished in the export; non-finite floats
+/// become `null`. Binary values become an object with their base64 under
+/// `"$bytes"`.
+pub fn value_to_json(value: &KvsValue) -> Value {
+    match value {
+        KvsValue::I32(v) => json!(v),
+        KvsValue::U32(v) => json!(v),
+        KvsValue::I64(v) => json!(v),
+        KvsValue::U64(v) => json!(v),
+        KvsValue::F64(v) => json!(v),
+        KvsValue::Boolean(v) => json!(v),
+        KvsValue::String(v) => json!(v),
+        KvsValue::Bytes(v) => json!({ BYTES_KEY: STANDARD.encode(v) }),
+        KvsValue::Null => Value::Null,
+        KvsValue::Array(values) => Value::Array(values.iter().map(value_to_json).collect()),
+        KvsValue::Object(values) => Value::Object(
+            values
+                .iter()
+                .map(|(key, value)| (key.clone(), value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Stored value of a JSON value of an export
+///
+/// The export does not keep integer types, integers become 64-bit values.
+pub fn json_to_value(value: &Value) -> KvsValue {
+    match value {
+        Value::Null => KvsValue::Null,
+        Value::Bool(v) => KvsValue::Boolean(*v),
+        Value::Number(v) => match (v.as_i64(), v.as_u64()) {
+            (Some(v), _) => KvsValue::I64(v),
+            (None, Some(v)) => KvsValue::U64(v),
+            _ => KvsValue::F64(v.as_f64().unwrap_or_default()),
+        },
+        Value::String(v) => KvsValue::String(v.clone()),
+        Value::Array(values) => KvsValue::Array(values.iter().map(json_to_value).collect()),
+        Value::Object(values) => match bytes_of(values) {
+            Some(bytes) => KvsValue::Bytes(bytes),
+            None => KvsValue::Object(
+                values
+                    .iter()
+                    .map(|(key, value)| (key.clone(), json_to_value(value)))
+                    .collect(),
+            ),
+        },
+    }
+}
+
+/// Binary value of an object written by [`value_to_json`] for one
+fn bytes_of(object: &serde_json::Map<String, Value>) -> Option<Vec<u8>> {
+    match object.get(BYTES_KEY) {
+        Some(Value::String(encoded)) if object.len() == 1 => STANDARD.decode(encoded).ok(),
+        _ => None,
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_matches_prefixes() {
+        let prefixes = vec!["Scenario/".to_string(), "vehicle/".to_string()];
+        assert!(matches_prefixes("Scenario/helloworld", &prefixes));
+        assert!(matches_prefixes("vehicle/mode", &prefixes));
+        assert!(!matches_prefixes("Package/helloworld", &prefixes));
+        assert!(matches_prefixes("Package/helloworld", &[]));
+    }
+
+    #[test]
+    fn test_value_to_json() {
+        let mut object = HashMap::new();
+        object.insert("speed".to_string(), KvsValue::F64(12.5));
+        object.insert("gear".to_string(), KvsValue::U32(3));
+        let value = KvsValue::Array(vec![
+            KvsValue::String("manual".to_string()),
+            KvsValue::Boolean(true),
+            KvsValue::Null,
+            KvsValue::F64(f64::NAN),
+            KvsValue::Object(object),
+        ]);
+        assert_eq!(
+            value_to_json(&value),
+            json!(["manual", true, null, null, {"speed": 12.5, "gear": 3}])
+        );
+    }
+
+    #[test]
+    fn test_json_to_value_round_trip() {
+        let json = json!(["manual", true, null, -3, 12.5, {"gear": 3}]);
+        assert_eq!(value_to_json(&json_to_value(&json)), json);
+        assert!(matches!(
+            json_to_value(&json!(u64::MAX)),
+            KvsValue::U64(u64::MAX)
+        ));
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let value = KvsValue::Bytes(vec![0x1f, 0x8b, 0x08, 0x00]);
+        let json = value_to_json(&value);
+        assert_eq!(json, json!({"$bytes": "H4sIAA=="}));
+        assert!(
+            matches!(json_to_value(&json), KvsValue::Bytes(bytes) if bytes == [0x1f, 0x8b, 0x08, 0x00])
+        );
+        // Objects that merely have the key stay objects
+        assert!(matches!(
+            json_to_value(&json!({"$bytes": "H4sIAA==", "gear": 3})),
+            KvsValue::Object(_)
+        ));
+        assert!(matches!(
+            json_to_value(&json!({"$bytes": "not base64!"})),
+            KvsValue::Object(_)
+        ));
+    }
+
+    #[test]
+    fn test_differential_export() {
+        let dir = std::env::temp_dir().join(format!("persistency-export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // Instances are pooled per id, the service itself uses instance 0
+        let kvs = KvsBuilder::new(InstanceId(5))
+            .dir(dir.display().to_string())
+            .build()
+            .unwrap();
+        let clock = crate::clock::TestClock::new(1_000);
+        let history = MutationHistory::new(1_000);
+        let put = |key: &str, value: &str| {
+            KvsApi::set_value(&kvs, key, value).unwrap();
+            history.put(key);
+        };
+        put("vehicle/mode", "manual");
+        put("vehicle/route", "A9");
+        put("trip/id", "17");
+        let prefixes = vec!["vehicle/".to_string()];
+
+        let full = export_prefixes(&kvs, None, &prefixes, &clock, &history, None).unwrap();
+        assert!(!full.is_differential());
+        assert_eq!(full.entries.len(), 2);
+        assert_eq!(full.revision, 3);
+
+        put("vehicle/mode", "autonomous");
+        put("trip/id", "18");
+        KvsApi::remove_key(&kvs, "vehicle/route").unwrap();
+        history.remove("vehicle/route");
+        let differential = export_prefixes(
+            &kvs,
+            None,
+            &prefixes,
+            &clock,
+            &history,
+            Some(full.baseline()),
+        )
+        .unwrap();
+        assert_eq!(differential.base_revision, Some(3));
+        assert_eq!(differential.revision, 6);
+        assert_eq!(
+            differential.entries,
+            BTreeMap::from([("vehicle/mode".to_string(), json!("autonomous"))])
+        );
+        assert_eq!(differential.removed, ["vehicle/route"]);
+
+        // A base of another run of the service is not covered
+        let base = crate::history::Baseline {
+            history_id: 999,
+            revision: 3,
+        };
+        let fallback =
+            export_prefixes(&kvs, None, &prefixes, &clock, &history, Some(base)).unwrap();
+        assert!(!fallback.is_differential());
+        assert_eq!(fallback.entries.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
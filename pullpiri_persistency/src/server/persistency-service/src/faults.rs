@@ -0,0 +1,117 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Fault injection for chaos testing
+//!
+//! Only built with the `fault-injection` feature, production builds cannot
+//! be made to fail on purpose. The `SetFaultInjection` admin RPC arms the
+//! faults, so that the retry and circuit breaker logic of the clients can be
+//! verified end-to-end against a real service:
+//!
+//! * the next N `SetValue` calls fail without touching the store
+//! * every read is delayed before it takes the store lock
+//! * flushes fail and leave the changes pending, until the fault is cleared
+//!
+//! A request replaces all armed faults, an empty configuration clears them.
+
+use common::persistency_proto::FaultInjectionConfig;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Faults armed through the admin RPC
+#[derive(Debug, Default)]
+pub struct FaultInjector {
+    fail_set_values: AtomicU32,
+    read_latency_ms: AtomicU32,
+    fail_flushes: AtomicBool,
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the armed faults
+    pub fn configure(&self, config: &FaultInjectionConfig) {
+        self.fail_set_values
+            .store(config.fail_set_values, Ordering::Relaxed);
+        self.read_latency_ms
+            .store(config.read_latency_ms, Ordering::Relaxed);
+        self.fail_flushes
+            .store(config.fail_flushes, Ordering::Relaxed);
+    }
+
+    /// Faults currently armed, with the `SetValue` failures still to come
+    pub fn config(&self) -> FaultInjectionConfig {
+        FaultInjectionConfig {
+            fail_set_values: self.fail_set_values.load(Ordering::Relaxed),
+            read_latency_ms: self.read_latency_ms.load(Ordering::Relaxed),
+            fail_flushes: self.fail_flushes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Whether the current `SetValue` has to fail, using up one armed failure
+    pub fn take_set_value_failure(&self) -> bool {
+        self.fail_set_values
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Delay to add to a read, `None` if reads are not delayed
+    pub fn read_latency(&self) -> Option<Duration> {
+        match self.read_latency_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms as u64)),
+        }
+    }
+
+    /// Whether flushes have to fail
+    pub fn flush_fails(&self) -> bool {
+        self.fail_flushes.load(Ordering::Relaxed)
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_value_failures_are_used_up() {
+        let faults = FaultInjector::new();
+        assert!(!faults.take_set_value_failure());
+
+        faults.configure(&FaultInjectionConfig {
+            fail_set_values: 2,
+            ..Default::default()
+        });
+        assert!(faults.take_set_value_failure());
+        assert_eq!(faults.config().fail_set_values, 1);
+        assert!(faults.take_set_value_failure());
+        assert!(!faults.take_set_value_failure());
+        assert_eq!(faults.config().fail_set_values, 0);
+    }
+
+    #[test]
+    fn test_configure_replaces_faults() {
+        let faults = FaultInjector::new();
+        let config = FaultInjectionConfig {
+            fail_set_values: 3,
+            read_latency_ms: 250,
+            fail_flushes: true,
+        };
+        faults.configure(&config);
+        assert_eq!(faults.config(), config);
+        assert_eq!(faults.read_latency(), Some(Duration::from_millis(250)));
+        assert!(faults.flush_fails());
+
+        faults.configure(&FaultInjectionConfig::default());
+        assert_eq!(faults.config(), FaultInjectionConfig::default());
+        assert_eq!(faults.read_latency(), None);
+        assert!(!faults.flush_fails());
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Batched flushing of `SetValue` writes
+//!
+//! rust_kvs writes the whole store to its file on every flush, so flushing
+//! after every `SetValue` costs a full store write per key and wears the
+//! flash of the ECU when components update their state at a high rate.
+//! `SetValue`, `Increment` and `Decrement` therefore only mark their key
+//! pending and count the write. A background task flushes every store with
+//! pending changes every `flush.interval_ms`, or as soon as
+//! `flush.max_writes` writes are counted.
+//!
+//! Writes acknowledged since the last flush are lost on a power cut; callers
+//! that need a write to be durable call `Flush` or `FlushPrefix`, which still
+//! flush at once. The service flushes once more when it is stopped.
+
+use crate::config::FlushConfig;
+use crate::PersistencyServiceImpl;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::MissedTickBehavior;
+use tracing::info;
+
+/// Counts the writes since the last flush and wakes the flusher early
+#[derive(Debug, Default)]
+pub struct FlushScheduler {
+    /// Writes that trigger a flush, 0 for no limit
+    max_writes: usize,
+    writes: AtomicUsize,
+    notify: Notify,
+}
+
+impl FlushScheduler {
+    pub fn new(config: &FlushConfig) -> Self {
+        Self {
+            max_writes: config.max_writes,
+            ..Self::default()
+        }
+    }
+
+    /// Count a write, waking the flusher once `max_writes` writes are counted
+    pub fn record_write(&self) {
+        let writes = self.writes.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.max_writes > 0 && writes == self.max_writes {
+            self.notify.notify_one();
+        }
+    }
+
+    /// Writes since the last flush, starting over
+    pub fn take_writes(&self) -> usize {
+        self.writes.swap(0, Ordering::Relaxed)
+    }
+
+    /// Wait until `max_writes` writes are counted
+    pub async fn wait_for_writes(&self) {
+        self.notify.notified().await;
+    }
+}
+
+/// Flush the pending writes every `config.interval_ms`, or once `config.max_writes` are counted
+pub async fn run(config: FlushConfig, service: Arc<PersistencyServiceImpl>) {
+    info!(
+        "Flushing writes every {}ms or after {} writes",
+        config.interval_ms, config.max_writes
+    );
+
+    let period = Duration::from_millis(config.interval_ms.max(1));
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = service.flush_scheduler().wait_for_writes() => interval.reset(),
+        }
+        service.flush_pending().await;
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_writes: usize) -> FlushConfig {
+        FlushConfig {
+            interval_ms: 1000,
+            max_writes,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_writes_wake_the_flusher() {
+        let scheduler = FlushScheduler::new(&config(3));
+        scheduler.record_write();
+        scheduler.record_write();
+        let early = tokio::time::timeout(Duration::from_millis(10), scheduler.wait_for_writes());
+        assert!(early.await.is_err());
+
+        // The permit is kept until the flusher waits again
+        scheduler.record_write();
+        scheduler.wait_for_writes().await;
+        assert_eq!(scheduler.take_writes(), 3);
+        assert_eq!(scheduler.take_writes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_no_limit_never_wakes_the_flusher() {
+        let scheduler = FlushScheduler::new(&config(0));
+        for _ in 0..10 {
+            scheduler.record_write();
+        }
+        let woken = tokio::time::timeout(Duration::from_millis(10), scheduler.wait_for_writes());
+        assert!(woken.await.is_err());
+        assert_eq!(scheduler.take_writes(), 10);
+    }
+}
@@ -0,0 +1,523 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Storage usage tracking and forecast
+//!
+//! Every write and removal is accounted to the prefix of its key: the bytes
+//! written and the net growth of the stored data. The rates over the
+//! configured window give a linear forecast of how many days are left until
+//! the store exceeds its quota or the disk threshold, so that fleet
+//! operations can act before a vehicle runs out of persistent storage. The
+//! forecast is reported by the `GetPrefixStats` RPC and the metrics.
+//!
+//! Sizes are approximated by the protobuf encoding of key and value; the
+//! JSON store file is somewhat larger. Usage history is kept in memory, so
+//! the rates are measured from the service start until the window is full.
+
+use crate::clock::SharedClock;
+use crate::config::StorageForecastConfig;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Number of buckets the window is divided into
+const BUCKETS: u32 = 24;
+
+const SECS_PER_DAY: f64 = 86_400.0;
+
+/// Name, help text and value of a per-prefix metric
+type PrefixMetric = (&'static str, &'static str, fn(&PrefixForecast) -> f64);
+
+/// Usage of one bucket of the window
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    /// Monotonic time the bucket starts at
+    start: Duration,
+    written: u64,
+    growth: i64,
+}
+
+/// Tracked usage of one prefix
+#[derive(Debug, Default)]
+struct PrefixUsage {
+    keys: u64,
+    stored_bytes: u64,
+    buckets: VecDeque<Bucket>,
+}
+
+/// Usage and forecast of one prefix
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefixForecast {
+    pub prefix: String,
+    pub keys: u64,
+    pub stored_bytes: u64,
+    /// Bytes written within the window
+    pub written_bytes: u64,
+    pub write_bytes_per_day: f64,
+    pub growth_bytes_per_day: f64,
+    /// Days until the limit if only this prefix kept growing, `None` if it does not grow
+    pub days_until_full: Option<f64>,
+}
+
+/// Limit the store must stay below
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageLimit {
+    Quota,
+    Disk,
+}
+
+impl StorageLimit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StorageLimit::Quota => "quota",
+            StorageLimit::Disk => "disk",
+        }
+    }
+}
+
+/// Usage and forecast of the whole store
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageForecast {
+    pub stored_bytes: u64,
+    /// Size the store may grow to and which limit sets it
+    pub limit: Option<(StorageLimit, u64)>,
+    pub growth_bytes_per_day: f64,
+    /// Days until the limit at the current growth, `None` if not growing or no limit
+    pub days_until_full: Option<f64>,
+    /// Time the rates are measured over
+    pub window: Duration,
+    pub prefixes: Vec<PrefixForecast>,
+}
+
+/// Write volume and stored bytes per key prefix over time
+#[derive(Debug)]
+pub struct StorageForecaster {
+    config: StorageForecastConfig,
+    clock: SharedClock,
+    /// Monotonic time tracking started at
+    started: Duration,
+    /// Directory holding the store files, for the disk threshold
+    storage_dir: PathBuf,
+    prefixes: Mutex<BTreeMap<String, PrefixUsage>>,
+}
+
+/// Approximate stored size of an entry
+///
+/// ### Parameters
+/// * `key` - key of the entry
+/// * `value` - value of the entry as sent over gRPC
+pub fn entry_size(key: &str, value: &common::persistency_proto::KvsValue) -> u64 {
+    (key.len() + prost::Message::encoded_len(value)) as u64
+}
+
+impl StorageForecaster {
+    pub fn new(config: &StorageForecastConfig, clock: SharedClock, storage_dir: PathBuf) -> Self {
+        Self {
+            config: config.clone(),
+            started: clock.monotonic(),
+            clock,
+            storage_dir,
+            prefixes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Prefix `key` is accounted to: its first `prefix_depth` segments including the `/`
+    ///
+    /// Keys with fewer segments are accounted to the part up to their last `/`.
+    pub fn prefix_of<'a>(&self, key: &'a str) -> &'a str {
+        let end = key
+            .match_indices('/')
+            .take(self.config.prefix_depth)
+            .last()
+            .map(|(index, _)| index + 1)
+            .unwrap_or(0);
+        &key[..end]
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.config.window_secs.max(1))
+    }
+
+    fn bucket_len(&self) -> Duration {
+        (self.window() / BUCKETS).max(Duration::from_secs(1))
+    }
+
+    /// Account the entries found in the store at startup, without growth
+    pub fn load(&self, entries: impl IntoIterator<Item = (String, u64)>) {
+        let mut prefixes = self.prefixes.lock().unwrap();
+        for (key, size) in entries {
+            let usage = prefixes
+                .entry(self.prefix_of(&key).to_string())
+                .or_default();
+            usage.keys += 1;
+            usage.stored_bytes += size;
+        }
+    }
+
+    fn record(&self, key: &str, keys: i64, written: u64, growth: i64) {
+        let mut prefixes = self.prefixes.lock().unwrap();
+        let usage = prefixes.entry(self.prefix_of(key).to_string()).or_default();
+        self.account(usage, keys, written, growth);
+    }
+
+    fn account(&self, usage: &mut PrefixUsage, keys: i64, written: u64, growth: i64) {
+        let now = self.clock.monotonic();
+        let bucket_len = self.bucket_len().as_secs();
+        let start = Duration::from_secs(now.as_secs() - now.as_secs() % bucket_len);
+
+        usage.keys = usage.keys.saturating_add_signed(keys);
+        usage.stored_bytes = usage.stored_bytes.saturating_add_signed(growth);
+        match usage.buckets.back_mut() {
+            Some(bucket) if bucket.start == start => {
+                bucket.written += written;
+                bucket.growth += growth;
+            }
+            _ => usage.buckets.push_back(Bucket {
+                start,
+                written,
+                growth,
+            }),
+        }
+        while usage
+            .buckets
+            .front()
+            .is_some_and(|bucket| bucket.start + self.window() <= now)
+        {
+            usage.buckets.pop_front();
+        }
+    }
+
+    /// Account a write of `key`
+    ///
+    /// ### Parameters
+    /// * `key` - written key
+    /// * `old_size` - size of the replaced entry, `None` for a new key
+    /// * `new_size` - size of the written entry
+    pub fn record_write(&self, key: &str, old_size: Option<u64>, new_size: u64) {
+        let keys = if old_size.is_none() { 1 } else { 0 };
+        let growth = new_size as i64 - old_size.unwrap_or(0) as i64;
+        self.record(key, keys, new_size, growth);
+    }
+
+    /// Account the removal of `key` with an entry of `old_size`
+    pub fn record_remove(&self, key: &str, old_size: u64) {
+        self.record(key, -1, 0, -(old_size as i64));
+    }
+
+    /// Account the removal of every key
+    pub fn record_reset(&self) {
+        self.record_reset_where(|_| true);
+    }
+
+    /// Account the removal of every key under the prefixes matching `filter`
+    pub fn record_reset_where(&self, filter: impl Fn(&str) -> bool) {
+        for (prefix, usage) in self.prefixes.lock().unwrap().iter_mut() {
+            if filter(prefix) {
+                let (keys, stored_bytes) = (usage.keys as i64, usage.stored_bytes as i64);
+                self.account(usage, -keys, 0, -stored_bytes);
+            }
+        }
+    }
+
+    /// Size the store may grow to and which limit sets it
+    fn limit(&self, stored_bytes: u64) -> Option<(StorageLimit, u64)> {
+        let quota =
+            (self.config.quota_bytes > 0).then_some((StorageLimit::Quota, self.config.quota_bytes));
+        let disk = (self.config.disk_threshold_percent > 0)
+            .then(|| disk_headroom(&self.storage_dir, self.config.disk_threshold_percent))
+            .flatten()
+            .map(|headroom| (StorageLimit::Disk, stored_bytes + headroom));
+        quota
+            .into_iter()
+            .chain(disk)
+            .min_by_key(|(_, bytes)| *bytes)
+    }
+
+    /// Usage and forecast of the prefixes starting with `filter`
+    ///
+    /// The store totals always cover every prefix.
+    pub fn forecast(&self, filter: &str) -> StorageForecast {
+        let now = self.clock.monotonic();
+        let window = self.window();
+        // Extrapolating from less than one bucket would wildly overestimate the rates
+        let observed = now
+            .saturating_sub(self.started)
+            .clamp(self.bucket_len(), window)
+            .as_secs_f64();
+        let per_day = |bytes: f64| bytes / observed * SECS_PER_DAY;
+
+        let prefixes = self.prefixes.lock().unwrap();
+        let stored_bytes = prefixes.values().map(|usage| usage.stored_bytes).sum();
+        let limit = self.limit(stored_bytes);
+        let headroom = limit.map(|(_, bytes)| bytes.saturating_sub(stored_bytes) as f64);
+        let days_until_full = |growth_per_day: f64| {
+            headroom
+                .filter(|_| growth_per_day > 0.0)
+                .map(|headroom| headroom / growth_per_day)
+        };
+
+        let mut total_growth = 0;
+        let mut forecasts = Vec::new();
+        for (prefix, usage) in prefixes.iter() {
+            let recent = usage
+                .buckets
+                .iter()
+                .filter(|bucket| bucket.start + window > now);
+            let (written, growth) = recent.fold((0, 0), |(written, growth), bucket| {
+                (written + bucket.written, growth + bucket.growth)
+            });
+            total_growth += growth;
+            // Prefixes emptied before the window are of no interest anymore
+            if !prefix.starts_with(filter) || (usage.keys == 0 && written == 0 && growth == 0) {
+                continue;
+            }
+            let growth_bytes_per_day = per_day(growth as f64);
+            forecasts.push(PrefixForecast {
+                prefix: prefix.clone(),
+                keys: usage.keys,
+                stored_bytes: usage.stored_bytes,
+                written_bytes: written,
+                write_bytes_per_day: per_day(written as f64),
+                growth_bytes_per_day,
+                days_until_full: days_until_full(growth_bytes_per_day),
+            });
+        }
+
+        let growth_bytes_per_day = per_day(total_growth as f64);
+        StorageForecast {
+            stored_bytes,
+            limit,
+            growth_bytes_per_day,
+            days_until_full: days_until_full(growth_bytes_per_day),
+            window,
+            prefixes: forecasts,
+        }
+    }
+
+    /// Usage and forecast in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let forecast = self.forecast("");
+        let mut out = String::new();
+        let per_prefix: [PrefixMetric; 4] = [
+            ("persistency_prefix_keys", "Keys stored per prefix", |p| {
+                p.keys as f64
+            }),
+            (
+                "persistency_prefix_stored_bytes",
+                "Approximate bytes stored per prefix",
+                |p| p.stored_bytes as f64,
+            ),
+            (
+                "persistency_prefix_write_bytes_per_day",
+                "Bytes written per day and prefix over the forecast window",
+                |p| p.write_bytes_per_day,
+            ),
+            (
+                "persistency_prefix_growth_bytes_per_day",
+                "Net growth per day and prefix over the forecast window",
+                |p| p.growth_bytes_per_day,
+            ),
+        ];
+        for (name, help, value) in per_prefix {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+            for prefix in &forecast.prefixes {
+                let _ = writeln!(
+                    out,
+                    "{}{{prefix=\"{}\"}} {}",
+                    name,
+                    escape_label(&prefix.prefix),
+                    value(prefix)
+                );
+            }
+        }
+
+        let totals = [
+            (
+                "persistency_stored_bytes",
+                "Approximate bytes stored",
+                forecast.stored_bytes as f64,
+            ),
+            (
+                "persistency_storage_limit_bytes",
+                "Size the store may grow to, 0 without limit",
+                forecast.limit.map_or(0.0, |(_, bytes)| bytes as f64),
+            ),
+            (
+                "persistency_growth_bytes_per_day",
+                "Net growth per day over the forecast window",
+                forecast.growth_bytes_per_day,
+            ),
+            (
+                "persistency_days_until_full",
+                "Days until the storage limit at the current growth",
+                forecast.days_until_full.unwrap_or(f64::INFINITY),
+            ),
+        ];
+        for (name, help, value) in totals {
+            let value = if value.is_infinite() {
+                "+Inf".to_string()
+            } else {
+                value.to_string()
+            };
+            let _ = writeln!(
+                out,
+                "# HELP {} {}\n# TYPE {} gauge\n{} {}",
+                name, help, name, name, value
+            );
+        }
+        out
+    }
+}
+
+/// Escape a Prometheus label value
+pub(crate) fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Bytes that can still be written to the filesystem of `dir` before it is `threshold_percent` full
+#[cfg(unix)]
+fn disk_headroom(dir: &std::path::Path, threshold_percent: u8) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read after statvfs succeeded
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    let block_size = stat.f_frsize as u64;
+    let total = stat.f_blocks as u64 * block_size;
+    let used = total.saturating_sub(stat.f_bfree as u64 * block_size);
+    let allowed = total / 100 * threshold_percent.min(100) as u64;
+    Some(allowed.saturating_sub(used))
+}
+
+#[cfg(not(unix))]
+fn disk_headroom(_dir: &std::path::Path, _threshold_percent: u8) -> Option<u64> {
+    None
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use std::sync::Arc;
+
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    fn forecaster(quota_bytes: u64) -> (Arc<TestClock>, StorageForecaster) {
+        let clock = Arc::new(TestClock::new(0));
+        let config = StorageForecastConfig {
+            quota_bytes,
+            disk_threshold_percent: 0,
+            window_secs: 7 * 86_400,
+            prefix_depth: 1,
+        };
+        let forecaster = StorageForecaster::new(&config, clock.clone(), PathBuf::from("."));
+        (clock, forecaster)
+    }
+
+    #[test]
+    fn test_prefix_of() {
+        let (_, forecaster) = forecaster(0);
+        assert_eq!(forecaster.prefix_of("vehicle/mode/last"), "vehicle/");
+        assert_eq!(forecaster.prefix_of("flat"), "");
+        let config = StorageForecastConfig {
+            prefix_depth: 2,
+            ..Default::default()
+        };
+        let forecaster =
+            StorageForecaster::new(&config, Arc::new(TestClock::new(0)), PathBuf::from("."));
+        assert_eq!(forecaster.prefix_of("vehicle/mode/last"), "vehicle/mode/");
+        assert_eq!(forecaster.prefix_of("vehicle/mode"), "vehicle/");
+    }
+
+    #[test]
+    fn test_forecast_days_until_quota() {
+        let (clock, forecaster) = forecaster(10_000);
+        forecaster.load([("logs/0".to_string(), 1_000)]);
+
+        // Two days of 500 new bytes per day in logs/, overwrites in config/
+        for day in 1..=2 {
+            forecaster.record_write(&format!("logs/{}", day), None, 500);
+            forecaster.record_write("config/mode", (day > 1).then_some(100), 100);
+            clock.advance(DAY);
+        }
+        forecaster.record_write("config/mode", Some(100), 100);
+
+        let forecast = forecaster.forecast("");
+        assert_eq!(forecast.stored_bytes, 2_100);
+        assert_eq!(forecast.limit, Some((StorageLimit::Quota, 10_000)));
+        // 1_000 bytes of logs and 100 bytes of config grew in two days
+        assert_eq!(forecast.growth_bytes_per_day, 550.0);
+        let days = forecast.days_until_full.unwrap();
+        assert!((days - 7_900.0 / 550.0).abs() < 1e-9);
+
+        let config = &forecast.prefixes[0];
+        assert_eq!(config.prefix, "config/");
+        assert_eq!(config.keys, 1);
+        assert_eq!(config.written_bytes, 300);
+        assert_eq!(config.growth_bytes_per_day, 50.0);
+
+        let logs = forecaster.forecast("logs/");
+        assert_eq!(logs.prefixes.len(), 1);
+        assert_eq!(logs.prefixes[0].keys, 3);
+        assert_eq!(logs.prefixes[0].growth_bytes_per_day, 500.0);
+    }
+
+    #[test]
+    fn test_forecast_forgets_writes_outside_window() {
+        let (clock, forecaster) = forecaster(10_000);
+        forecaster.record_write("logs/1", None, 700);
+        clock.advance(8 * DAY);
+        forecaster.record_remove("logs/1", 700);
+        clock.advance(DAY);
+
+        let forecast = forecaster.forecast("");
+        assert_eq!(forecast.stored_bytes, 0);
+        assert!(forecast.growth_bytes_per_day < 0.0);
+        assert_eq!(forecast.days_until_full, None);
+        assert_eq!(forecast.prefixes[0].written_bytes, 0);
+    }
+
+    #[test]
+    fn test_reset_and_metrics() {
+        let (clock, forecaster) = forecaster(0);
+        forecaster.record_write("vehicle/speed", None, 100);
+        clock.advance(DAY);
+        let metrics = forecaster.render_metrics();
+        assert!(metrics.contains("persistency_prefix_stored_bytes{prefix=\"vehicle/\"} 100"));
+        assert!(metrics.contains("persistency_storage_limit_bytes 0"));
+        assert!(metrics.contains("persistency_days_until_full +Inf"));
+
+        forecaster.record_reset();
+        let forecast = forecaster.forecast("");
+        assert_eq!(forecast.stored_bytes, 0);
+        assert_eq!(forecast.prefixes[0].keys, 0);
+        assert_eq!(forecast.growth_bytes_per_day, 0.0);
+        assert_eq!(escape_label("a\"b\\"), "a\\\"b\\\\");
+    }
+
+    #[test]
+    fn test_reset_of_some_prefixes() {
+        let (_, forecaster) = forecaster(0);
+        forecaster.record_write("vehicle/speed", None, 100);
+        forecaster.record_write("<runtime>/vehicle_mode", None, 50);
+
+        forecaster.record_reset_where(|prefix| prefix.starts_with("<runtime>/"));
+        let forecast = forecaster.forecast("");
+        assert_eq!(forecast.stored_bytes, 100);
+        assert_eq!(forecaster.forecast("vehicle/").prefixes[0].keys, 1);
+    }
+}
@@ -0,0 +1,286 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! gRPC-Web support for browser dashboards
+//!
+//! Requests in the binary and base64 text encodings of gRPC-Web are
+//! translated to plain gRPC for the tonic router by
+//! [`tonic_web::GrpcWebLayer`], CORS requests of the allowed origins are
+//! answered by a [`CorsLayer`]. In front of both, gRPC-Web requests are
+//! marked with [`GrpcWebOrigin`] so that the access policy can restrict them
+//! to read-only operations, and their bodies are limited to one message of
+//! at most [`MAX_MESSAGE_BYTES`].
+
+use crate::access::GrpcWebOrigin;
+use crate::config::GrpcWebConfig;
+use http::{header, HeaderName, HeaderValue, Method, Request};
+use http_body_util::Limited;
+use std::time::Duration;
+use tonic::body::BoxBody;
+use tower::layer::util::{Identity, Stack};
+use tower::util::{option_layer, Either, MapRequestLayer};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Largest message of a browser request, the default limit of tonic
+pub const MAX_MESSAGE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Length of the frame header in front of every gRPC message
+const FRAME_HEADER_BYTES: usize = 5;
+
+/// Content types of gRPC-Web, with and without `+proto`
+const GRPC_WEB: &str = "application/grpc-web";
+const GRPC_WEB_TEXT: &str = "application/grpc-web-text";
+
+/// Headers browsers may send with a request
+const ALLOW_HEADERS: [&str; 6] = [
+    "content-type",
+    "x-grpc-web",
+    "x-user-agent",
+    "grpc-timeout",
+    "authorization",
+    "x-component-name",
+];
+
+/// Headers of the response scripts may read
+const EXPOSE_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+
+/// Browsers may cache the answer to a preflight request for a day
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Layers serving browser clients: marking, CORS and translation
+pub type BrowserLayers = Stack<
+    tonic_web::GrpcWebLayer,
+    Stack<CorsLayer, MapRequestLayer<fn(Request<BoxBody>) -> Request<BoxBody>>>,
+>;
+
+/// Layer adding gRPC-Web in front of the tonic router
+///
+/// When gRPC-Web is disabled in the configuration the layer passes every
+/// request through unchanged.
+pub fn layer(config: &GrpcWebConfig) -> Either<BrowserLayers, Identity> {
+    option_layer(config.enabled.then(|| {
+        let mark: fn(Request<BoxBody>) -> Request<BoxBody> = mark;
+        Stack::new(
+            tonic_web::GrpcWebLayer::new(),
+            Stack::new(cors(config), MapRequestLayer::new(mark)),
+        )
+    }))
+}
+
+/// CORS of the origins allowed by `config`
+fn cors(config: &GrpcWebConfig) -> CorsLayer {
+    let origins = if config.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods([Method::POST])
+        .allow_headers(ALLOW_HEADERS.map(HeaderName::from_static))
+        .expose_headers(EXPOSE_HEADERS.map(HeaderName::from_static))
+        .max_age(PREFLIGHT_MAX_AGE)
+}
+
+/// Mark gRPC-Web requests and limit their bodies, others are left as they are
+fn mark(request: Request<BoxBody>) -> Request<BoxBody> {
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with(GRPC_WEB) {
+        return request;
+    }
+    let framed = MAX_MESSAGE_BYTES + FRAME_HEADER_BYTES;
+    let limit = if content_type.starts_with(GRPC_WEB_TEXT) {
+        framed.div_ceil(3) * 4
+    } else {
+        framed
+    };
+    let (mut parts, body) = request.into_parts();
+    parts.extensions.insert(GrpcWebOrigin);
+    Request::from_parts(parts, tonic::body::boxed(Limited::new(body, limit)))
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::{Response, StatusCode, Version};
+    use http_body_util::{BodyExt, Full};
+    use std::convert::Infallible;
+    use tower::{Layer, Service, ServiceExt};
+
+    fn enabled_config() -> GrpcWebConfig {
+        GrpcWebConfig {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    /// Inner service echoing the request body and reporting whether the
+    /// request was marked as coming from gRPC-Web
+    fn echo_service() -> impl Service<
+        Request<BoxBody>,
+        Response = Response<BoxBody>,
+        Error = Infallible,
+        Future = impl Send,
+    > + Clone
+           + Send {
+        tower::service_fn(|req: Request<BoxBody>| async move {
+            let marked = req.extensions().get::<GrpcWebOrigin>().is_some();
+            let content_type = req.headers().get(header::CONTENT_TYPE).cloned();
+            let body = req.into_body().collect().await.unwrap().to_bytes();
+
+            let mut response = Response::new(tonic::body::boxed(Full::new(body)));
+            response.headers_mut().insert(
+                "x-marked",
+                HeaderValue::from_static(if marked { "1" } else { "0" }),
+            );
+            if let Some(content_type) = content_type {
+                response
+                    .headers_mut()
+                    .insert("x-inner-content-type", content_type);
+            }
+            Ok::<_, Infallible>(response)
+        })
+    }
+
+    fn request(content_type: &str, body: impl Into<Bytes>) -> Request<BoxBody> {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/persistency.PersistencyService/GetValue")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(tonic::body::boxed(Full::new(body.into())))
+            .unwrap()
+    }
+
+    async fn call(config: &GrpcWebConfig, request: Request<BoxBody>) -> Response<BoxBody> {
+        layer(config)
+            .layer(echo_service())
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_binary_request_is_translated_and_marked() {
+        let response = call(
+            &enabled_config(),
+            request("application/grpc-web+proto", &b"payload"[..]),
+        )
+        .await;
+
+        assert_eq!(response.headers()["x-marked"], "1");
+        assert_eq!(
+            response.headers()["x-inner-content-type"],
+            "application/grpc"
+        );
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/grpc-web+proto"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.starts_with(b"payload"));
+    }
+
+    #[tokio::test]
+    async fn test_text_request_round_trip() {
+        // "payload" in base64
+        let mut text = request("application/grpc-web-text", &b"cGF5bG9hZA=="[..]);
+        text.headers_mut().insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/grpc-web-text"),
+        );
+        let response = call(&enabled_config(), text).await;
+
+        assert_eq!(response.headers()["x-marked"], "1");
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/grpc-web-text+proto"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        // The message comes back in a base64 chunk of its own
+        assert!(body.starts_with(b"cGF5bG9hZA=="));
+    }
+
+    #[tokio::test]
+    async fn test_native_grpc_passes_through() {
+        let mut native = request("application/grpc", &b"payload"[..]);
+        *native.version_mut() = Version::HTTP_2;
+        let response = call(&enabled_config(), native).await;
+
+        assert_eq!(response.headers()["x-marked"], "0");
+        assert_eq!(
+            response.headers()["x-inner-content-type"],
+            "application/grpc"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disabled_layer_does_not_translate() {
+        let response = call(
+            &GrpcWebConfig::default(),
+            request("application/grpc-web+proto", &b"payload"[..]),
+        )
+        .await;
+
+        assert_eq!(response.headers()["x-marked"], "0");
+        assert_eq!(
+            response.headers()["x-inner-content-type"],
+            "application/grpc-web+proto"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preflight_of_allowed_origins() {
+        let mut config = enabled_config();
+        config.allowed_origins = vec!["http://dashboard.local".to_string()];
+        let preflight = |origin| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/persistency.PersistencyService/GetValue")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .body(tonic::body::empty_body())
+                .unwrap()
+        };
+
+        let response = call(&config, preflight("http://dashboard.local")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://dashboard.local"
+        );
+        let response = call(&config, preflight("http://elsewhere.local")).await;
+        assert!(response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bodies_limited_to_one_message() {
+        let largest = vec![0; MAX_MESSAGE_BYTES + FRAME_HEADER_BYTES];
+        let body = mark(request("application/grpc-web", largest)).into_body();
+        assert!(body.collect().await.is_ok());
+
+        let oversized = vec![0; MAX_MESSAGE_BYTES + FRAME_HEADER_BYTES + 1];
+        let body = mark(request("application/grpc-web", oversized)).into_body();
+        assert!(body.collect().await.is_err());
+
+        // Native requests are limited by the decoder of tonic
+        let native = vec![0; MAX_MESSAGE_BYTES + FRAME_HEADER_BYTES + 1];
+        let body = mark(request("application/grpc", native)).into_body();
+        assert!(body.collect().await.is_ok());
+    }
+}
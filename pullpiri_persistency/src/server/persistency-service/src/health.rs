@@ -0,0 +1,161 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! gRPC health checking
+//!
+//! The server answers the standard `grpc.health.v1.Health` service, so that
+//! orchestrators can hold back the components depending on the store. The
+//! persistency service, and the server as a whole under the empty service
+//! name, are reported `NOT_SERVING` until the store is initialized and its
+//! background tasks run, while a whole store is flushed or a restore is in
+//! progress, and once the service shuts down.
+//!
+//! [`Health`] tracks the state, [`run`] reports its changes to the health
+//! service of the server.
+
+use crate::PersistencyServiceImpl;
+use common::persistency_proto::persistency_service_server::PersistencyServiceServer;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tonic::server::NamedService;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+use tracing::debug;
+
+#[derive(Debug, Default)]
+struct State {
+    ready: bool,
+    stopping: bool,
+    /// Flushes and restores in progress
+    busy: usize,
+}
+
+impl State {
+    fn serving(&self) -> bool {
+        self.ready && !self.stopping && self.busy == 0
+    }
+}
+
+/// Serving state of the persistency service
+#[derive(Debug)]
+pub struct Health {
+    state: Mutex<State>,
+    serving: watch::Sender<bool>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    /// Health of a service that is not ready yet
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            serving: watch::Sender::new(false),
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut State)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state);
+        self.serving.send_if_modified(|serving| {
+            let modified = *serving != state.serving();
+            *serving = state.serving();
+            modified
+        });
+    }
+
+    /// The store is initialized and takes requests
+    pub fn set_ready(&self) {
+        self.update(|state| state.ready = true);
+    }
+
+    /// The service shuts down
+    pub fn set_stopping(&self) {
+        self.update(|state| state.stopping = true);
+    }
+
+    /// Report not serving until the returned guard is dropped, e.g. during a restore
+    pub fn busy(&self) -> BusyGuard<'_> {
+        self.update(|state| state.busy += 1);
+        BusyGuard { health: self }
+    }
+
+    pub fn is_serving(&self) -> bool {
+        *self.serving.borrow()
+    }
+
+    /// Receiver notified of every change of the serving state
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.serving.subscribe()
+    }
+}
+
+/// Flush or restore in progress, see [`Health::busy`]
+#[derive(Debug)]
+pub struct BusyGuard<'a> {
+    health: &'a Health,
+}
+
+impl Drop for BusyGuard<'_> {
+    fn drop(&mut self) {
+        self.health.update(|state| state.busy -= 1);
+    }
+}
+
+/// Report the serving state of `service` through `reporter` until the service is dropped
+pub async fn run(service: Arc<PersistencyServiceImpl>, mut reporter: HealthReporter) {
+    let mut serving = service.health().subscribe();
+    drop(service);
+    let name = <PersistencyServiceServer<PersistencyServiceImpl> as NamedService>::NAME;
+    loop {
+        let status = if *serving.borrow_and_update() {
+            ServingStatus::Serving
+        } else {
+            ServingStatus::NotServing
+        };
+        debug!("Persistency service health: {}", status);
+        for name in ["", name] {
+            reporter.set_service_status(name, status).await;
+        }
+        if serving.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serving_once_ready_and_idle() {
+        let health = Health::new();
+        let mut changes = health.subscribe();
+        assert!(!health.is_serving());
+
+        health.set_ready();
+        assert!(health.is_serving());
+        assert!(changes.has_changed().unwrap());
+        changes.mark_unchanged();
+
+        {
+            let _restore = health.busy();
+            let _flush = health.busy();
+            assert!(!health.is_serving());
+        }
+        assert!(health.is_serving());
+        assert!(changes.has_changed().unwrap());
+
+        health.set_stopping();
+        assert!(!health.is_serving());
+        drop(health.busy());
+        assert!(!health.is_serving());
+    }
+}
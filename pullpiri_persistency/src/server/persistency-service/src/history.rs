@@ -0,0 +1,226 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Mutation history for differential exports
+//!
+//! Every put and removal of a key is numbered with a revision, and every
+//! export names the history and the revision it was captured at. A
+//! differential export against such a [`Baseline`] holds only the keys
+//! changed since then and the names of the keys removed since then, a
+//! fraction of a full export for frequent backups of a mostly static store.
+//!
+//! The history is kept in memory: the revision of the last put of every key
+//! and the last [`MAX_REMOVALS`] removals. It starts anew with every start of
+//! the service, so a base exported by an earlier run, or older than the oldest
+//! kept removal, is not covered and a full export is taken instead.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+/// Removals kept to compute differential exports
+pub const MAX_REMOVALS: usize = 4096;
+
+/// Point of the history an export was captured at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Baseline {
+    /// Identifies the history, differs between runs of the service
+    pub history_id: u64,
+    /// Revision of the last change included
+    pub revision: u64,
+}
+
+/// Keys changed since a baseline
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// Revision of the last change
+    pub revision: u64,
+    /// Keys put since the baseline and still stored
+    pub changed: HashSet<String>,
+    /// Keys removed since the baseline and not put again
+    pub removed: Vec<String>,
+}
+
+/// Revisions of the puts and removals since the service started
+#[derive(Debug)]
+pub struct MutationHistory {
+    id: u64,
+    state: Mutex<HistoryState>,
+}
+
+#[derive(Debug, Default)]
+struct HistoryState {
+    revision: u64,
+    /// Oldest revision the kept removals cover
+    floor: u64,
+    /// Revision of the last put of each stored key
+    puts: HashMap<String, u64>,
+    /// Revision of the last removal of each removed key
+    removals: HashMap<String, u64>,
+    /// The kept removals, oldest first
+    removal_order: BTreeMap<u64, String>,
+}
+
+impl MutationHistory {
+    /// Create an empty history
+    ///
+    /// ### Parameters
+    /// * `id: u64` - identifies this history, e.g. the start time of the service
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            state: Mutex::new(HistoryState::default()),
+        }
+    }
+
+    /// The current end of the history
+    pub fn baseline(&self) -> Baseline {
+        Baseline {
+            history_id: self.id,
+            revision: self.state.lock().unwrap().revision,
+        }
+    }
+
+    /// Record that `key` was written
+    pub fn put(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.revision += 1;
+        let revision = state.revision;
+        state.puts.insert(key.to_string(), revision);
+        if let Some(removed) = state.removals.remove(key) {
+            state.removal_order.remove(&removed);
+        }
+    }
+
+    /// Record that `key` was removed
+    pub fn remove(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.revision += 1;
+        let revision = state.revision;
+        state.puts.remove(key);
+        if let Some(removed) = state.removals.insert(key.to_string(), revision) {
+            state.removal_order.remove(&removed);
+        }
+        state.removal_order.insert(revision, key.to_string());
+
+        while state.removal_order.len() > MAX_REMOVALS {
+            let Some((oldest, key)) = state.removal_order.pop_first() else {
+                break;
+            };
+            state.removals.remove(&key);
+            // Bases before the forgotten removal would miss it
+            state.floor = state.floor.max(oldest);
+        }
+    }
+
+    /// Changes since `base`, `None` if the history does not cover it
+    pub fn changes_since(&self, base: Baseline) -> Option<Changes> {
+        let state = self.state.lock().unwrap();
+        if base.history_id != self.id
+            || base.revision < state.floor
+            || base.revision > state.revision
+        {
+            return None;
+        }
+        Some(Changes {
+            revision: state.revision,
+            changed: state
+                .puts
+                .iter()
+                .filter(|(_, revision)| **revision > base.revision)
+                .map(|(key, _)| key.clone())
+                .collect(),
+            removed: state
+                .removal_order
+                .range(base.revision + 1..)
+                .map(|(_, key)| key.clone())
+                .collect(),
+        })
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_since() {
+        let history = MutationHistory::new(7);
+        history.put("vehicle/mode");
+        history.put("vehicle/gear");
+        let base = history.baseline();
+        assert_eq!(
+            base,
+            Baseline {
+                history_id: 7,
+                revision: 2
+            }
+        );
+
+        history.put("vehicle/mode");
+        history.remove("vehicle/gear");
+        history.put("vehicle/route");
+        history.remove("vehicle/route");
+        history.remove("vehicle/limit");
+        let changes = history.changes_since(base).unwrap();
+        assert_eq!(changes.revision, 7);
+        assert_eq!(changes.changed, HashSet::from(["vehicle/mode".to_string()]));
+        assert_eq!(
+            changes.removed,
+            ["vehicle/gear", "vehicle/route", "vehicle/limit"]
+        );
+
+        history.put("vehicle/gear");
+        let changes = history.changes_since(base).unwrap();
+        assert!(changes.changed.contains("vehicle/gear"));
+        assert_eq!(changes.removed, ["vehicle/route", "vehicle/limit"]);
+
+        assert_eq!(
+            history
+                .changes_since(history.baseline())
+                .unwrap()
+                .changed
+                .len(),
+            0
+        );
+        assert!(history
+            .changes_since(Baseline {
+                history_id: 8,
+                revision: 2
+            })
+            .is_none());
+        assert!(history
+            .changes_since(Baseline {
+                history_id: 7,
+                revision: 99
+            })
+            .is_none());
+    }
+
+    #[test]
+    fn test_forgotten_removals_raise_floor() {
+        let history = MutationHistory::new(1);
+        let start = history.baseline();
+        for index in 0..MAX_REMOVALS {
+            history.remove(&format!("trip/{}", index));
+        }
+        assert_eq!(
+            history.changes_since(start).unwrap().removed.len(),
+            MAX_REMOVALS
+        );
+
+        history.remove("trip/last");
+        assert!(history.changes_since(start).is_none());
+        let changes = history
+            .changes_since(Baseline {
+                history_id: 1,
+                revision: 1,
+            })
+            .unwrap();
+        assert_eq!(changes.removed.len(), MAX_REMOVALS);
+        assert_eq!(changes.removed.last().unwrap(), "trip/last");
+    }
+}
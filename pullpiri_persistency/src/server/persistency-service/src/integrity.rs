@@ -0,0 +1,137 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Integrity check of the storage files
+//!
+//! rust_kvs writes an Adler-32 checksum next to every stored snapshot and
+//! only validates it when a snapshot is restored. Verifying recomputes the
+//! checksums of the current store file and every older snapshot, so that a
+//! corrupted file is found before it is needed.
+
+use rust_kvs::prelude::*;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Result of checking one stored snapshot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnapshotIntegrity {
+    /// Snapshot id, 0 is the current store file
+    pub snapshot: usize,
+    /// Path of the store file
+    pub file: String,
+    pub valid: bool,
+    /// Why the snapshot is not valid
+    pub error: Option<String>,
+}
+
+/// Verify the checksums of every snapshot of `kvs`
+///
+/// The caller must hold the store's write lock so that no flush rotates
+/// the files during the check.
+///
+/// ### Parameters
+/// * `kvs: &Kvs` - store to verify
+pub fn verify_snapshots(kvs: &Kvs) -> Vec<SnapshotIntegrity> {
+    (0..kvs.snapshot_count())
+        .map(|id| {
+            let snapshot_id = SnapshotId(id);
+            let file = kvs
+                .get_kvs_filename(snapshot_id)
+                .map(|path| path.display().to_string())
+                .unwrap_or_default();
+            let error = verify_snapshot(kvs, snapshot_id).err();
+            SnapshotIntegrity {
+                snapshot: id,
+                file,
+                valid: error.is_none(),
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Verify the checksum of one snapshot of `kvs`
+pub fn verify_snapshot(kvs: &Kvs, snapshot_id: SnapshotId) -> Result<(), String> {
+    let kvs_path = kvs
+        .get_kvs_filename(snapshot_id)
+        .map_err(|e| format!("Store file missing: {:?}", e))?;
+    let hash_path = kvs
+        .get_hash_filename(snapshot_id)
+        .map_err(|e| format!("Checksum file missing: {:?}", e))?;
+    verify_files(&kvs_path, &hash_path)
+}
+
+/// Verify a store file against its checksum file
+///
+/// Works on the files alone, also before the store is opened.
+pub fn verify_files(kvs_path: &Path, hash_path: &Path) -> Result<(), String> {
+    let content = fs::read(kvs_path).map_err(|e| format!("Failed to read store file: {}", e))?;
+    let hash = fs::read(hash_path).map_err(|e| format!("Failed to read checksum file: {}", e))?;
+
+    let expected: [u8; 4] = hash
+        .as_slice()
+        .try_into()
+        .map_err(|_| format!("Checksum file has {} bytes instead of 4", hash.len()))?;
+    let actual = adler32::RollingAdler32::from_buffer(&content).hash();
+    if actual == u32::from_be_bytes(expected) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Checksum mismatch: stored {:08x}, computed {:08x}",
+            u32::from_be_bytes(expected),
+            actual
+        ))
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_in(dir: &std::path::Path) -> Kvs {
+        // Instances are pooled per id, the service itself uses instance 0
+        KvsBuilder::new(InstanceId(9))
+            .dir(dir.display().to_string())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_verify_detects_corruption() {
+        let dir =
+            std::env::temp_dir().join(format!("persistency-integrity-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let kvs = store_in(&dir);
+        kvs.set_value("vehicle/mode", "autonomous").unwrap();
+        kvs.flush().unwrap();
+        kvs.set_value("vehicle/mode", "manual").unwrap();
+        kvs.flush().unwrap();
+
+        let report = verify_snapshots(&kvs);
+        assert_eq!(report.len(), 2);
+        assert!(report.iter().all(|snapshot| snapshot.valid));
+
+        let current = kvs.get_kvs_filename(SnapshotId(0)).unwrap();
+        fs::write(
+            &current,
+            b"{\"vehicle/mode\":{\"t\":\"str\",\"v\":\"emergency\"}}",
+        )
+        .unwrap();
+        let report = verify_snapshots(&kvs);
+        assert!(!report[0].valid);
+        assert!(report[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .starts_with("Checksum mismatch"));
+        assert!(report[1].valid);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
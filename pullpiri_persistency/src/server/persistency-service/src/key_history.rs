@@ -0,0 +1,249 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Kept versions of the keys
+//!
+//! With `key_history.enabled` every change of a key under the configured
+//! prefixes is kept together with the revision it gave the key, see
+//! [`crate::revision`], so that operators can see how e.g. a Scenario
+//! artifact or the vehicle mode evolved and write an earlier value back.
+//! Only the newest `key_history.versions` changes per key are kept; a
+//! removal is kept as a version without value.
+//!
+//! `GetHistory` lists the kept versions of a key. `GetValueAtRevision`
+//! answers the value a key had at a revision of the store, i.e. its newest
+//! version up to that revision. Values are kept like in an export, see
+//! [`crate::export`], so integers come back as 64-bit values.
+//!
+//! The versions are kept in `persistency-key-history.json` next to the store
+//! and saved with every flush.
+
+use crate::config::KeyHistoryConfig;
+use crate::namespace;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// File the versions are kept in, next to the store
+pub const KEY_HISTORY_FILE: &str = "persistency-key-history.json";
+
+/// Change of a key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyVersion {
+    /// Revision the key got with the change
+    pub revision: u64,
+    /// Value written as exported, `None` for a removal
+    pub value: Option<Value>,
+    /// Unix time of the change in milliseconds
+    pub written_at_ms: u64,
+}
+
+/// Kept versions of the keys, by tracked key
+#[derive(Debug)]
+pub struct KeyHistory {
+    config: KeyHistoryConfig,
+    /// Versions per tracked key, oldest first
+    keys: Mutex<HashMap<String, VecDeque<KeyVersion>>>,
+    /// Whether the versions changed since they were last saved
+    dirty: AtomicBool,
+    path: Option<PathBuf>,
+}
+
+impl KeyHistory {
+    /// History kept in memory only
+    pub fn new(config: &KeyHistoryConfig) -> Self {
+        Self {
+            config: config.clone(),
+            keys: Mutex::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+            path: None,
+        }
+    }
+
+    /// History saved in `dir`, with the versions saved there before
+    ///
+    /// Versions beyond the configured number or of keys no longer covered
+    /// are dropped. An unreadable file is logged and the history starts empty.
+    pub fn load(config: &KeyHistoryConfig, dir: &Path) -> Self {
+        let path = dir.join(KEY_HISTORY_FILE);
+        let history = Self {
+            path: Some(path.clone()),
+            ..Self::new(config)
+        };
+        if !history.is_enabled() {
+            return history;
+        }
+        let mut keys: HashMap<String, VecDeque<KeyVersion>> = match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable key history file {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        keys.retain(|tracked, versions| {
+            while versions.len() > history.config.versions {
+                versions.pop_front();
+            }
+            !versions.is_empty() && history.covers(namespace::split_tracked(tracked).1)
+        });
+        *history.keys.lock().unwrap() = keys;
+        history
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled && self.config.versions > 0
+    }
+
+    /// Whether the versions of `key`, in any store, are kept
+    pub fn covers(&self, key: &str) -> bool {
+        self.is_enabled()
+            && (self.config.prefixes.is_empty()
+                || self
+                    .config
+                    .prefixes
+                    .iter()
+                    .any(|prefix| key.starts_with(prefix.as_str())))
+    }
+
+    /// Keep the change of `tracked` to `value` at `revision`, `None` for a removal
+    pub fn record(&self, tracked: &str, revision: u64, value: Option<Value>, now_ms: u64) {
+        let mut keys = self.keys.lock().unwrap();
+        let versions = keys.entry(tracked.to_string()).or_default();
+        versions.push_back(KeyVersion {
+            revision,
+            value,
+            written_at_ms: now_ms,
+        });
+        while versions.len() > self.config.versions {
+            versions.pop_front();
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Kept versions of `tracked`, newest first
+    pub fn versions(&self, tracked: &str) -> Vec<KeyVersion> {
+        self.keys
+            .lock()
+            .unwrap()
+            .get(tracked)
+            .map(|versions| versions.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Newest version of `tracked` up to `revision`
+    ///
+    /// `None` if no version up to it is kept, e.g. because newer versions
+    /// pushed it out.
+    pub fn at_revision(&self, tracked: &str, revision: u64) -> Option<KeyVersion> {
+        self.keys
+            .lock()
+            .unwrap()
+            .get(tracked)?
+            .iter()
+            .rev()
+            .find(|version| version.revision <= revision)
+            .cloned()
+    }
+
+    /// Save the versions if they changed since the last save
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let content = serde_json::to_vec(&*self.keys.lock().unwrap())?;
+        let tmp = path.with_extension("tmp");
+        let result = fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, path));
+        if result.is_err() {
+            // Try again with the next flush
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(versions: usize, prefixes: &[&str]) -> KeyHistoryConfig {
+        KeyHistoryConfig {
+            enabled: true,
+            versions,
+            prefixes: prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_covered_keys() {
+        let history = KeyHistory::new(&config(3, &["Scenario/"]));
+        assert!(history.covers("Scenario/helloworld"));
+        assert!(!history.covers("vehicle/mode"));
+        assert!(KeyHistory::new(&config(3, &[])).covers("vehicle/mode"));
+        assert!(!KeyHistory::new(&config(0, &[])).covers("vehicle/mode"));
+        let disabled = KeyHistoryConfig::default();
+        assert!(!KeyHistory::new(&disabled).covers("vehicle/mode"));
+    }
+
+    #[test]
+    fn test_newest_versions_are_kept() {
+        let history = KeyHistory::new(&config(3, &[]));
+        history.record("vehicle/mode", 10, Some(json!("manual")), 1_000);
+        history.record("vehicle/mode", 12, Some(json!("autonomous")), 2_000);
+        history.record("vehicle/mode", 15, None, 3_000);
+        history.record("vehicle/mode", 20, Some(json!("parking")), 4_000);
+
+        let revisions: Vec<u64> = history
+            .versions("vehicle/mode")
+            .iter()
+            .map(|version| version.revision)
+            .collect();
+        assert_eq!(revisions, [20, 15, 12]);
+        assert!(history.versions("vehicle/gear").is_empty());
+
+        let at = |revision| history.at_revision("vehicle/mode", revision);
+        assert_eq!(at(13).unwrap().value, Some(json!("autonomous")));
+        assert_eq!(at(15).unwrap().value, None);
+        assert_eq!(at(99).unwrap().revision, 20);
+        // Pushed out by the newer versions
+        assert_eq!(at(11), None);
+    }
+
+    #[test]
+    fn test_versions_survive_restart() {
+        let dir =
+            std::env::temp_dir().join(format!("persistency-key-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let history = KeyHistory::load(&config(3, &[]), &dir);
+        history.record("Scenario/helloworld", 7, Some(json!("v1")), 1_000);
+        history.record("Scenario/helloworld", 9, Some(json!("v2")), 2_000);
+        history.record("<artifacts>/vehicle/mode", 8, Some(json!("manual")), 1_500);
+        history.save().unwrap();
+        assert!(dir.join(KEY_HISTORY_FILE).exists());
+
+        let reloaded = KeyHistory::load(&config(3, &[]), &dir);
+        assert_eq!(
+            reloaded.versions("Scenario/helloworld"),
+            history.versions("Scenario/helloworld")
+        );
+
+        // Fewer versions and prefixes configured meanwhile
+        let reloaded = KeyHistory::load(&config(1, &["Scenario/"]), &dir);
+        assert_eq!(reloaded.versions("Scenario/helloworld").len(), 1);
+        assert!(reloaded.versions("<artifacts>/vehicle/mode").is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Sorted index of the keys of a store
+//!
+//! rust_kvs keeps its keys in a hash map, so listing the keys under a prefix
+//! means copying and filtering every key of the store, and sorting the
+//! matches for a scan. A store keeps its keys in a sorted set next to it
+//! instead, so `GetAllWithPrefix` and `Scan` look up the keys of a prefix
+//! with a range scan and read their values in a single pass.
+//!
+//! The index follows the writes and removals of the service, see
+//! [`crate::PersistencyServiceImpl`]. A query may briefly list a key whose
+//! removal is in flight; its value is then missing and the key is left out.
+//! Stores in bounded memory mode keep no index, holding every key in memory
+//! is what that mode avoids, see [`crate::spill`].
+
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use std::sync::RwLock;
+
+/// Keys of one store in sorted order
+#[derive(Debug, Default)]
+pub struct KeyIndex {
+    keys: RwLock<BTreeSet<String>>,
+}
+
+impl KeyIndex {
+    /// Index of the stored `keys`
+    pub fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            keys: RwLock::new(keys.into_iter().collect()),
+        }
+    }
+
+    /// Account a write of `key`
+    pub fn insert(&self, key: &str) {
+        if self.keys.read().unwrap().contains(key) {
+            return;
+        }
+        self.keys.write().unwrap().insert(key.to_string());
+    }
+
+    /// Account the removal of `key`
+    pub fn remove(&self, key: &str) {
+        self.keys.write().unwrap().remove(key);
+    }
+
+    /// Forget every key after the store was reset
+    pub fn clear(&self) {
+        self.keys.write().unwrap().clear();
+    }
+
+    /// At most `limit` keys starting with `prefix` and passing `keep`,
+    /// sorted and after `after`
+    pub fn range(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+        keep: impl Fn(&str) -> bool,
+    ) -> Vec<String> {
+        let start = match after {
+            Some(after) if after >= prefix => Bound::Excluded(after),
+            _ => Bound::Included(prefix),
+        };
+        self.keys
+            .read()
+            .unwrap()
+            .range::<str, _>((start, Bound::Unbounded))
+            .take_while(|key| key.starts_with(prefix))
+            .filter(|key| keep(key))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.read().unwrap().is_empty()
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    fn all(_: &str) -> bool {
+        true
+    }
+
+    #[test]
+    fn test_range_of_prefix() {
+        let index = KeyIndex::new(keys(&[
+            "Scenario/c",
+            "Package/a",
+            "Scenario/a",
+            "Scenarios",
+            "Scenario/b",
+        ]));
+
+        assert_eq!(
+            index.range("Scenario/", None, usize::MAX, all),
+            keys(&["Scenario/a", "Scenario/b", "Scenario/c"])
+        );
+        assert_eq!(
+            index.range("Scenario/", Some("Scenario/a"), 1, all),
+            keys(&["Scenario/b"])
+        );
+        // A key before the prefix does not widen the range
+        assert_eq!(
+            index.range("Scenario/", Some("Package/z"), 1, all),
+            keys(&["Scenario/a"])
+        );
+        assert!(index
+            .range("Scenario/", Some("Scenario/c"), usize::MAX, all)
+            .is_empty());
+        assert_eq!(index.range("", None, usize::MAX, all).len(), 5);
+        // Left out keys do not count towards the limit
+        assert_eq!(
+            index.range("Scenario/", None, 1, |key| key != "Scenario/a"),
+            keys(&["Scenario/b"])
+        );
+    }
+
+    #[test]
+    fn test_index_follows_writes() {
+        let index = KeyIndex::default();
+        index.insert("vehicle/mode");
+        index.insert("vehicle/mode");
+        index.insert("vehicle/gear");
+        assert_eq!(index.len(), 2);
+
+        index.remove("vehicle/mode");
+        assert_eq!(
+            index.range("vehicle/", None, usize::MAX, all),
+            keys(&["vehicle/gear"])
+        );
+
+        index.clear();
+        assert!(index.is_empty());
+    }
+}
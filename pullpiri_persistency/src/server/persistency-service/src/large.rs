@@ -0,0 +1,201 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Chunks of the `SetLargeValue` and `GetLargeValue` RPCs
+//!
+//! tonic rejects messages above 4 MiB, which recorder outputs and artifact
+//! bundles exceed. `SetLargeValue` uploads such a value in chunks: the first
+//! request carries the key and the size of the whole value, every request the
+//! next chunk. The service assembles the value in memory, up to the
+//! configured limit, and stores it like `SetValue` once the client closed the
+//! stream. `GetLargeValue` sends a value back in chunks and tells in its first
+//! response whether the value is binary, so that clients get back the type
+//! they stored.
+
+use crate::chunked;
+use common::persistency_proto::{
+    kvs_value::Value, GetLargeValueResponse, KvsValue, SetLargeValueRequest, SetValueRequest,
+};
+
+/// Value assembled from the requests of a `SetLargeValue` call
+#[derive(Debug)]
+pub struct LargeValue {
+    /// First request, without its chunk
+    header: SetLargeValueRequest,
+    data: Vec<u8>,
+    max_bytes: u64,
+}
+
+impl LargeValue {
+    /// Start a value with the `first` request of a call
+    ///
+    /// Values declared larger than `max_bytes` are rejected before any
+    /// memory is reserved for them.
+    pub fn start(mut first: SetLargeValueRequest, max_bytes: u64) -> Result<Self, String> {
+        if first.key.is_empty() {
+            return Err("The first chunk carries no key".to_string());
+        }
+        if first.total_size > max_bytes {
+            return Err(format!(
+                "Value of {} bytes exceeds the limit of {} bytes",
+                first.total_size, max_bytes
+            ));
+        }
+        let chunk = std::mem::take(&mut first.chunk);
+        let mut value = Self {
+            data: Vec::with_capacity(first.total_size as usize),
+            header: first,
+            max_bytes,
+        };
+        value.push(chunk)?;
+        Ok(value)
+    }
+
+    pub fn key(&self) -> &str {
+        &self.header.key
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.header.namespace
+    }
+
+    /// Append the chunk of the next request
+    pub fn push(&mut self, chunk: Vec<u8>) -> Result<(), String> {
+        let size = (self.data.len() + chunk.len()) as u64;
+        if size > self.max_bytes {
+            return Err(format!(
+                "Value exceeds the limit of {} bytes",
+                self.max_bytes
+            ));
+        }
+        self.data.extend(chunk);
+        Ok(())
+    }
+
+    /// `SetValue` request storing the complete value
+    ///
+    /// Fails if the chunks do not add up to the declared size, e.g. when the
+    /// client gave up halfway, or a string value is no valid UTF-8.
+    pub fn finish(self) -> Result<SetValueRequest, String> {
+        if self.data.len() as u64 != self.header.total_size {
+            return Err(format!(
+                "Received {} of {} bytes",
+                self.data.len(),
+                self.header.total_size
+            ));
+        }
+        let value = if self.header.binary {
+            Value::BytesValue(self.data)
+        } else {
+            Value::StringValue(
+                String::from_utf8(self.data)
+                    .map_err(|e| format!("Value is no valid UTF-8: {}", e))?,
+            )
+        };
+        Ok(SetValueRequest {
+            key: self.header.key,
+            value: Some(KvsValue { value: Some(value) }),
+            ttl_seconds: self.header.ttl_seconds,
+            namespace: self.header.namespace,
+            expected_revision: 0,
+            lease_id: 0,
+        })
+    }
+}
+
+/// Responses carrying `value` in chunks of `chunk_size` bytes
+///
+/// The first response tells whether the value is `binary`.
+pub fn chunks(
+    value: Vec<u8>,
+    binary: bool,
+    chunk_size: usize,
+) -> impl Iterator<Item = GetLargeValueResponse> + Send {
+    chunked::chunks(value, chunk_size)
+        .enumerate()
+        .map(move |(index, response)| GetLargeValueResponse {
+            chunk: response.chunk,
+            total_size: response.total_size,
+            binary: binary && index == 0,
+        })
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first(total_size: u64, binary: bool, chunk: &[u8]) -> SetLargeValueRequest {
+        SetLargeValueRequest {
+            key: "recorder/drive".to_string(),
+            namespace: String::new(),
+            ttl_seconds: 60,
+            binary,
+            total_size,
+            chunk: chunk.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_chunks_assemble_the_value() {
+        let mut value = LargeValue::start(first(11, false, b"hello"), 1024).unwrap();
+        value.push(b" ".to_vec()).unwrap();
+        value.push(b"world".to_vec()).unwrap();
+
+        let request = value.finish().unwrap();
+        assert_eq!(request.key, "recorder/drive");
+        assert_eq!(request.ttl_seconds, 60);
+        assert_eq!(
+            request.value.unwrap().value,
+            Some(Value::StringValue("hello world".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_binary_value() {
+        let value = LargeValue::start(first(3, true, &[0xff, 0x00, 0xfe]), 1024).unwrap();
+        assert_eq!(
+            value.finish().unwrap().value.unwrap().value,
+            Some(Value::BytesValue(vec![0xff, 0x00, 0xfe]))
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let declared = LargeValue::start(first(2048, true, &[]), 1024).unwrap_err();
+        assert_eq!(
+            declared,
+            "Value of 2048 bytes exceeds the limit of 1024 bytes"
+        );
+
+        // Clients may send more than they declared
+        let mut value = LargeValue::start(first(4, true, &[0; 4]), 8).unwrap();
+        assert!(value.push(vec![0; 5]).is_err());
+    }
+
+    #[test]
+    fn test_incomplete_and_invalid_values() {
+        let value = LargeValue::start(first(10, true, &[0; 4]), 1024).unwrap();
+        assert_eq!(value.finish().unwrap_err(), "Received 4 of 10 bytes");
+
+        let value = LargeValue::start(first(2, false, &[0xc3, 0x28]), 1024).unwrap();
+        assert!(value.finish().is_err());
+
+        let mut keyless = first(0, false, &[]);
+        keyless.key.clear();
+        assert!(LargeValue::start(keyless, 1024).is_err());
+    }
+
+    #[test]
+    fn test_only_first_response_tells_binary() {
+        let responses: Vec<_> = chunks(vec![7; 10], true, 4).collect();
+        assert_eq!(responses.len(), 3);
+        assert!(responses[0].binary);
+        assert_eq!(responses[0].total_size, 10);
+        assert!(responses[1..]
+            .iter()
+            .all(|r| !r.binary && r.total_size == 0));
+    }
+}
@@ -0,0 +1,404 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Leases, removing the keys of components that stopped
+//!
+//! Presence and ownership keys, e.g. which node runs a Scenario, must not
+//! outlive the component that wrote them. A component grants itself a lease
+//! with `GrantLease`, attaches keys to it with the `lease_id` of `SetValue`
+//! and renews it over the `KeepAlive` stream. A lease that is revoked, or
+//! not renewed within its time to live because its component died, takes
+//! its keys with it: they are handed to the expiry tracker, see
+//! [`crate::expiry`], and removed like expired keys.
+//!
+//! A key belongs to at most one lease; writing it again without a lease
+//! detaches it, and removing it forgets it. The leases and their keys are
+//! kept in `persistency-leases.json` next to the store and saved with every
+//! flush. Deadlines are not saved: after a restart every lease lives for its
+//! whole time to live again, so that its component has the time to
+//! reconnect.
+
+use crate::clock::SharedClock;
+use crate::config::LeaseConfig;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+/// File the leases are kept in, next to the store
+pub const LEASES_FILE: &str = "persistency-leases.json";
+
+/// Granted lease
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Lease {
+    ttl_secs: u64,
+    /// Component that granted the lease, `None` without authentication
+    owner: Option<String>,
+    /// Tracked keys attached to the lease
+    keys: BTreeSet<String>,
+    /// Monotonic time the lease expires at
+    #[serde(skip)]
+    deadline: Duration,
+}
+
+/// Live lease as seen by the handlers
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaseInfo {
+    pub ttl_secs: u64,
+    pub owner: Option<String>,
+    /// Time until the lease expires unless renewed
+    pub remaining: Duration,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    leases: HashMap<u64, Lease>,
+    /// Lease of each attached tracked key
+    keys: HashMap<String, u64>,
+}
+
+/// Granted leases and their keys
+#[derive(Debug)]
+pub struct Leases {
+    config: LeaseConfig,
+    state: Mutex<State>,
+    /// Whether the leases changed since they were last saved
+    dirty: AtomicBool,
+    path: Option<PathBuf>,
+    clock: SharedClock,
+    random: SystemRandom,
+}
+
+impl Leases {
+    /// Leases kept in memory only
+    pub fn new(config: &LeaseConfig, clock: SharedClock) -> Self {
+        Self {
+            config: config.clone(),
+            state: Mutex::new(State::default()),
+            dirty: AtomicBool::new(false),
+            path: None,
+            clock,
+            random: SystemRandom::new(),
+        }
+    }
+
+    /// Leases saved in `dir`, with the leases saved there before
+    ///
+    /// Every loaded lease lives for its whole time to live from now. An
+    /// unreadable file is logged and the leases start empty.
+    pub fn load(config: &LeaseConfig, dir: &Path, clock: SharedClock) -> Self {
+        let path = dir.join(LEASES_FILE);
+        let leases = Self {
+            path: Some(path.clone()),
+            ..Self::new(config, clock)
+        };
+        let mut loaded: HashMap<u64, Lease> = match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable leases file {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        let now = leases.clock.monotonic();
+        let mut state = leases.state.lock().unwrap();
+        for (id, lease) in loaded.iter_mut() {
+            lease.deadline = now + Duration::from_secs(lease.ttl_secs);
+            for key in &lease.keys {
+                state.keys.insert(key.clone(), *id);
+            }
+        }
+        state.leases = loaded;
+        drop(state);
+        leases
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Check the time to live asked for a new lease
+    pub fn check_ttl(&self, ttl_secs: u64) -> Result<(), String> {
+        if ttl_secs == 0 {
+            return Err("A lease needs a time to live".to_string());
+        }
+        let max = self.config.max_ttl_secs;
+        if max > 0 && ttl_secs > max {
+            return Err(format!(
+                "Time to live of {}s exceeds the maximum of {}s",
+                ttl_secs, max
+            ));
+        }
+        Ok(())
+    }
+
+    /// Grant a lease living for `ttl_secs` to `owner` and return its id
+    ///
+    /// Fails if the configured number of leases is held already.
+    pub fn grant(&self, ttl_secs: u64, owner: Option<&str>) -> Result<u64, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.leases.len() >= self.config.max_leases {
+            return Err(format!(
+                "Limit of {} leases reached",
+                self.config.max_leases
+            ));
+        }
+        // Random ids, so that the id of a lost lease is not granted to another component
+        let id = loop {
+            let mut bytes = [0u8; 8];
+            self.random
+                .fill(&mut bytes)
+                .map_err(|_| "Failed to generate a lease id".to_string())?;
+            let id = u64::from_le_bytes(bytes);
+            if id != 0 && !state.leases.contains_key(&id) {
+                break id;
+            }
+        };
+        state.leases.insert(
+            id,
+            Lease {
+                ttl_secs,
+                owner: owner.map(str::to_string),
+                keys: BTreeSet::new(),
+                deadline: self.clock.monotonic() + Duration::from_secs(ttl_secs),
+            },
+        );
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(id)
+    }
+
+    /// Lease `id`, `None` if unknown or expired
+    pub fn get(&self, id: u64) -> Option<LeaseInfo> {
+        let now = self.clock.monotonic();
+        let state = self.state.lock().unwrap();
+        let lease = state.leases.get(&id).filter(|lease| lease.deadline > now)?;
+        Some(LeaseInfo {
+            ttl_secs: lease.ttl_secs,
+            owner: lease.owner.clone(),
+            remaining: lease.deadline - now,
+        })
+    }
+
+    /// Let lease `id` live for its whole time to live from now
+    ///
+    /// Returns the time to live, `None` if the lease is unknown or expired.
+    pub fn renew(&self, id: u64) -> Option<u64> {
+        let now = self.clock.monotonic();
+        let mut state = self.state.lock().unwrap();
+        let lease = state
+            .leases
+            .get_mut(&id)
+            .filter(|lease| lease.deadline > now)?;
+        lease.deadline = now + Duration::from_secs(lease.ttl_secs);
+        Some(lease.ttl_secs)
+    }
+
+    /// Attach `tracked` to lease `id`, detaching it from any other lease
+    ///
+    /// Returns `false` if the lease expired or was revoked meanwhile.
+    pub fn attach(&self, id: u64, tracked: &str) -> bool {
+        let now = self.clock.monotonic();
+        let mut state = self.state.lock().unwrap();
+        if state
+            .leases
+            .get(&id)
+            .is_none_or(|lease| lease.deadline <= now)
+        {
+            return false;
+        }
+        if let Some(previous) = state.keys.insert(tracked.to_string(), id) {
+            if let Some(lease) = state.leases.get_mut(&previous) {
+                lease.keys.remove(tracked);
+            }
+        }
+        if let Some(lease) = state.leases.get_mut(&id) {
+            lease.keys.insert(tracked.to_string());
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        true
+    }
+
+    /// Detach `tracked` from its lease, if any
+    pub fn detach(&self, tracked: &str) {
+        let mut state = self.state.lock().unwrap();
+        let Some(id) = state.keys.remove(tracked) else {
+            return;
+        };
+        if let Some(lease) = state.leases.get_mut(&id) {
+            lease.keys.remove(tracked);
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Lease of `tracked`, if attached to one
+    pub fn lease_of(&self, tracked: &str) -> Option<u64> {
+        self.state.lock().unwrap().keys.get(tracked).copied()
+    }
+
+    /// Revoke lease `id` and take its keys, `None` if unknown
+    pub fn revoke(&self, id: u64) -> Option<Vec<String>> {
+        let mut state = self.state.lock().unwrap();
+        let lease = state.leases.remove(&id)?;
+        for key in &lease.keys {
+            state.keys.remove(key);
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        Some(lease.keys.into_iter().collect())
+    }
+
+    /// Drop the expired leases and take their keys, sorted
+    pub fn take_expired(&self) -> Vec<String> {
+        let now = self.clock.monotonic();
+        let mut state = self.state.lock().unwrap();
+        let expired: Vec<u64> = state
+            .leases
+            .iter()
+            .filter(|(_, lease)| lease.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut keys = Vec::new();
+        for id in expired {
+            if let Some(lease) = state.leases.remove(&id) {
+                keys.extend(lease.keys);
+            }
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        for key in &keys {
+            state.keys.remove(key);
+        }
+        keys.sort();
+        keys
+    }
+
+    /// Number of live and not yet swept leases
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().leases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Save the leases if they changed since the last save
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let content = serde_json::to_vec(&self.state.lock().unwrap().leases)?;
+        let tmp = path.with_extension("tmp");
+        let result = fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, path));
+        if result.is_err() {
+            // Try again with the next flush
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use std::sync::Arc;
+
+    fn leases(clock: &Arc<TestClock>) -> Leases {
+        Leases::new(&LeaseConfig::default(), clock.clone())
+    }
+
+    #[test]
+    fn test_ttl_limits() {
+        let clock = Arc::new(TestClock::new(1_000));
+        let leases = leases(&clock);
+        assert!(leases.check_ttl(0).is_err());
+        assert!(leases.check_ttl(10).is_ok());
+        assert!(leases.check_ttl(3601).is_err());
+
+        let limited = Leases::new(
+            &LeaseConfig {
+                max_leases: 1,
+                ..Default::default()
+            },
+            clock.clone(),
+        );
+        limited.grant(10, None).unwrap();
+        assert!(limited.grant(10, None).is_err());
+    }
+
+    #[test]
+    fn test_lease_expires_unless_renewed() {
+        let clock = Arc::new(TestClock::new(1_000));
+        let leases = leases(&clock);
+        let id = leases.grant(10, Some("actioncontroller")).unwrap();
+        assert_ne!(id, 0);
+        assert!(leases.attach(id, "presence/actioncontroller"));
+        assert_eq!(leases.lease_of("presence/actioncontroller"), Some(id));
+        let info = leases.get(id).unwrap();
+        assert_eq!(info.owner.as_deref(), Some("actioncontroller"));
+        assert_eq!(info.remaining, Duration::from_secs(10));
+
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(leases.renew(id), Some(10));
+        clock.advance(Duration::from_secs(8));
+        assert!(leases.take_expired().is_empty());
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(leases.get(id), None);
+        assert_eq!(leases.renew(id), None);
+        assert!(!leases.attach(id, "presence/other"));
+        assert_eq!(leases.take_expired(), ["presence/actioncontroller"]);
+        assert_eq!(leases.lease_of("presence/actioncontroller"), None);
+        assert!(leases.is_empty());
+    }
+
+    #[test]
+    fn test_keys_move_between_leases() {
+        let clock = Arc::new(TestClock::new(1_000));
+        let leases = leases(&clock);
+        let first = leases.grant(10, None).unwrap();
+        let second = leases.grant(10, None).unwrap();
+        assert!(leases.attach(first, "owner/scenario"));
+        assert!(leases.attach(first, "presence/node"));
+        assert!(leases.attach(second, "owner/scenario"));
+        leases.detach("presence/node");
+
+        assert_eq!(leases.revoke(first), Some(Vec::new()));
+        assert_eq!(leases.revoke(first), None);
+        assert_eq!(
+            leases.revoke(second),
+            Some(vec!["owner/scenario".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_leases_survive_restart() {
+        let dir = std::env::temp_dir().join(format!("persistency-leases-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let clock = Arc::new(TestClock::new(1_000));
+        let leases = Leases::load(&LeaseConfig::default(), &dir, clock.clone());
+        let id = leases.grant(30, Some("statemanager")).unwrap();
+        leases.attach(id, "<artifacts>/owner/scenario");
+        clock.advance(Duration::from_secs(20));
+        leases.save().unwrap();
+        assert!(dir.join(LEASES_FILE).exists());
+
+        // The reloaded lease lives for its whole time to live again
+        let reloaded = Leases::load(&LeaseConfig::default(), &dir, clock.clone());
+        let info = reloaded.get(id).unwrap();
+        assert_eq!(info.owner.as_deref(), Some("statemanager"));
+        assert_eq!(info.remaining, Duration::from_secs(30));
+        assert_eq!(reloaded.lease_of("<artifacts>/owner/scenario"), Some(id));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
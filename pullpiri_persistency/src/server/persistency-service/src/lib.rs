@@ -8,391 +8,5160 @@
 //! This service provides a centralized persistency backend for all Pullpiri components,
 //! replacing PERSISTENCY usage. It wraps the rust_kvs library and exposes it through gRPC.
 
+// Handlers and their helpers return `tonic::Status` errors by design
+#![allow(clippy::result_large_err)]
+
+pub mod access;
+pub mod acl;
+#[cfg(feature = "admin-ui")]
+pub mod admin_ui;
+pub mod auth;
+pub mod backend;
+pub mod backup;
+pub mod cache;
+pub mod caller;
+pub mod capabilities;
+pub mod chunked;
+pub mod clock;
+pub mod config;
+pub mod counter;
+pub mod destructive;
+pub mod diagnostics;
+pub mod dump;
+pub mod durability;
+pub mod encryption;
+pub mod ephemeral;
+pub mod etcd;
+pub mod expiry;
+pub mod export;
+#[cfg(feature = "fault-injection")]
+pub mod faults;
+pub mod flusher;
+pub mod forecast;
+pub mod grpc_web;
+pub mod health;
+pub mod history;
+pub mod integrity;
+pub mod key_history;
+pub mod key_index;
+pub mod large;
+pub mod lease;
+pub mod listen;
+pub mod lite;
+pub mod lock;
+pub mod metrics;
+pub mod mirror;
+pub mod namespace;
+pub mod quota;
+pub mod recovery;
+pub mod replace;
+#[cfg(feature = "rest-gateway")]
+pub mod rest;
+pub mod restore;
+pub mod revision;
+pub mod sampling;
+pub mod scan;
+pub mod spill;
+pub mod stats;
+pub mod status;
+pub mod striping;
+pub mod timeseries;
+pub mod timing;
+pub mod trash;
+pub mod upload;
+pub mod usage;
+pub mod watch;
+
+use access::AccessPolicy;
+use backend::StorageBackend;
+use backup::{Backups, CreatedBackup};
+use cache::ReadCache;
+use clock::{SharedClock, SystemClock};
 use common::persistency_proto::{
-    persistency_service_server::{PersistencyService, PersistencyServiceServer},
-    GetAllKeysRequest, GetAllKeysResponse, GetAllWithPrefixRequest, GetAllWithPrefixResponse,
-    GetValueRequest, GetValueResponse, KeyExistsRequest, KeyExistsResponse, KvsArray, KvsObject,
-    KvsValue, NullValue, RemoveKeyRequest, RemoveKeyResponse, ResetRequest, ResetResponse,
-    SetValueRequest, SetValueResponse, FlushRequest, FlushResponse,
+    persistency_service_server::PersistencyService, restore_request, watch_request,
+    CancelPendingOperationRequest, CancelPendingOperationResponse, CompareAndSetRequest,
+    CompareAndSetResponse, CreateBackupRequest, CreateBackupResponse, DecrementRequest,
+    DecrementResponse, DumpStateForDiagnosticsRequest, DumpStateForDiagnosticsResponse,
+    ExportToFileRequest, ExportToFileResponse, FlushPrefixRequest, FlushPrefixResponse,
+    FlushRequest, FlushResponse, GetAllKeysRequest, GetAllKeysResponse, GetAllWithPrefixRequest,
+    GetAllWithPrefixResponse, GetCapabilitiesRequest, GetCapabilitiesResponse, GetHistoryRequest,
+    GetHistoryResponse, GetLargeValueRequest, GetLargeValueResponse, GetPrefixStatsRequest,
+    GetPrefixStatsResponse, GetProtoDescriptorRequest, GetProtoDescriptorResponse,
+    GetServiceInfoRequest, GetServiceInfoResponse, GetTraceSamplingRequest,
+    GetTraceSamplingResponse, GetTtlRequest, GetTtlResponse, GetUsageReportsRequest,
+    GetUsageReportsResponse, GetValueAtRevisionRequest, GetValueAtRevisionResponse,
+    GetValueRequest, GetValueResponse, GetValueStreamRequest, GetValueStreamResponse,
+    GetValuesRequest, GetValuesResponse, GrantLeaseRequest, GrantLeaseResponse,
+    ImportFromFileRequest, ImportFromFileResponse, IncrementRequest, IncrementResponse,
+    KeepAliveRequest, KeepAliveResponse, KeyConflict, KeyExistsRequest, KeyExistsResponse,
+    KeyVersion as KeyVersionProto, KvsArray, KvsObject, KvsValue, ListBackupsRequest,
+    ListBackupsResponse, ListDeletedRequest, ListDeletedResponse, LockRequest, LockResponse,
+    MigrateFromEtcdRequest, MigrateFromEtcdResponse, NullValue, PrefixStats, RemoveKeyRequest,
+    RemoveKeyResponse, RemoveKeysRequest, RemoveKeysResponse, ResetRequest, ResetResponse,
+    RestoreKeyRequest, RestoreKeyResponse, RestoreRequest, RestoreResponse, RestoreSnapshotRequest,
+    RestoreSnapshotResponse, RevokeLeaseRequest, RevokeLeaseResponse, ScanRequest, ScanResponse,
+    SetFaultInjectionRequest, SetFaultInjectionResponse, SetLargeValueRequest,
+    SetLargeValueResponse, SetTraceSamplingRequest, SetTraceSamplingResponse, SetValueRequest,
+    SetValueResponse, SetValuesRequest, SetValuesResponse, TraceSamplingConfig, UnlockRequest,
+    UnlockResponse, WatchFilter, WatchRequest, WatchResponse, API_VERSION, FILE_DESCRIPTOR_SET,
 };
+use destructive::{DelayedOperations, DestructiveOperation, PendingOperation};
+use diagnostics::{Diagnostics, StoreState};
+use dump::{DumpFile, DumpFormat, Dumps, SourceFormat};
+use encryption::Encryption;
+use etcd::{EtcdMigration, EtcdReader};
+use expiry::ExpiryTracker;
+use export::Export;
+#[cfg(feature = "fault-injection")]
+use faults::FaultInjector;
+use flusher::FlushScheduler;
+use forecast::{StorageForecast, StorageForecaster};
+use health::Health;
+use history::{Baseline, MutationHistory};
+use integrity::SnapshotIntegrity;
+use key_history::{KeyHistory, KeyVersion};
+use lease::Leases;
+use metrics::MetricsSource;
+use mirror::{Mirror, MirroredWrite};
+use namespace::{Namespaces, Store};
+use quota::Quotas;
+use recovery::RecoveryStatus;
+use replace::{Change, ReplaceReport, Snapshot};
+use restore::{ConflictStrategy, ModifiedTimes, RestoreReport, RestoreSource};
+use revision::Revisions;
 use rust_kvs::prelude::*;
+use sampling::{OperationFailed, TraceSampler};
+use spill::SpillStore;
+use stats::{ServiceStats, StoreStats};
+use status::Failure;
 use std::collections::HashMap;
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tonic::{Request, Response, Status};
+use std::time::Duration;
+use timeseries::TimeSeriesStatus;
+use timing::Deadline;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::{Code, Request, Response, Status, Streaming};
 use tracing::{debug, error, info, warn};
+use trash::{DeletedKey, Trash, TrashedValue};
+use upload::UploadStatus;
+use usage::{UsageReport, UsageTracker};
+use watch::{WatchEventKind, WatchHub, WatchOptions, WatchTarget};
 
 /// Persistency Service Implementation
 pub struct PersistencyServiceImpl {
-    kvs: Arc<RwLock<Kvs>>,
+    /// The default store and the configured namespaces
+    namespaces: Arc<Namespaces>,
+    access: AccessPolicy,
+    sampler: Arc<TraceSampler>,
+    watch: Arc<WatchHub>,
+    diagnostics: Diagnostics,
+    upload: Arc<UploadStatus>,
+    /// Recovery of the storage files at startup
+    recovery: Arc<RecoveryStatus>,
+    forecast: Arc<StorageForecaster>,
+    /// Operations per namespace for the usage reports
+    usage: Arc<UsageTracker>,
+    /// Points forwarded to the time-series sink
+    timeseries: Arc<TimeSeriesStatus>,
+    /// Writes queued for the staging instance
+    mirror: Arc<Mirror>,
+    /// RPC and flush counters for the metrics
+    stats: Arc<ServiceStats>,
+    /// Keys and storage file sizes for the metrics
+    store_stats: Arc<StoreStats>,
+    /// Serving state reported to the gRPC health service
+    health: Health,
+    /// Spilled keys of the default store in bounded memory mode
+    spill: Option<Arc<SpillStore>>,
+    /// Encryption of the stored values
+    encryption: Arc<Encryption>,
+    /// Values of the hot keys for `GetValue`
+    read_cache: Arc<ReadCache>,
+    /// Deadlines of the keys written with a time to live
+    expiry: ExpiryTracker,
+    /// Revisions of the keys for optimistic concurrency
+    revisions: Revisions,
+    /// Kept versions of the keys
+    key_history: KeyHistory,
+    /// Where removed keys go on request
+    trash: Trash,
+    /// Limits of the client writes
+    quotas: Quotas,
+    /// Leases of the components and the keys attached to them
+    leases: Arc<Leases>,
+    /// Destructive operations waiting for their grace period
+    delayed: DelayedOperations,
+    /// Local backups of the default store
+    backups: Backups,
+    /// JSON and YAML dumps of the default store
+    dumps: Dumps,
+    /// Writes waiting for the background flush
+    flusher: FlushScheduler,
+    /// Faults armed for chaos testing
+    #[cfg(feature = "fault-injection")]
+    faults: FaultInjector,
+    /// Write times of the default store keys, for newest-wins restores
+    modified: ModifiedTimes,
+    /// Revisions of the default store changes, for differential exports
+    history: MutationHistory,
+    config: config::ServiceConfig,
+    clock: SharedClock,
+    /// Monotonic time the service started at
+    started: Duration,
+}
+
+/// Build a response for an operation that completed with `success: false`
+///
+/// The response is marked with [`OperationFailed`] so that the trace
+/// sampling layer can always log failures.
+fn failed<T>(message: T) -> Response<T> {
+    let mut response = Response::new(message);
+    response.extensions_mut().insert(OperationFailed);
+    response
+}
+
+/// Answer an operation that failed with `code`, as a status if the caller asked for it, see [`status`]
+fn rejected<T: Failure>(code: Code, message: T) -> Result<Response<T>, Status> {
+    status::reject(code, message).map(failed)
+}
+
+/// Status code of a failed access to a single key
+fn key_error_code(error: &ErrorCode) -> Code {
+    match error {
+        ErrorCode::KeyNotFound => Code::NotFound,
+        _ => Code::Internal,
+    }
+}
+
+/// Failure to start the service
+#[derive(Debug)]
+pub enum InitError {
+    /// The configuration is inconsistent, see [`config::ServiceConfig::validate`]
+    Config(String),
+    /// A store or the state kept next to it could not be opened
+    Storage(ErrorCode),
+}
+
+impl From<ErrorCode> for InitError {
+    fn from(error: ErrorCode) -> Self {
+        InitError::Storage(error)
+    }
+}
+
+impl std::fmt::Display for InitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InitError::Config(e) => write!(f, "Invalid configuration: {}", e),
+            InitError::Storage(e) => write!(f, "Storage error: {:?}", e),
+        }
+    }
 }
 
+impl std::error::Error for InitError {}
+
 impl PersistencyServiceImpl {
     /// Create a new persistency service instance
-    pub fn new() -> Result<Self, ErrorCode> {
+    pub fn new() -> Result<Self, InitError> {
+        Self::with_config(&config::ServiceConfig::default())
+    }
+
+    /// Create a persistency service keeping its keys and state in memory only
+    ///
+    /// Nothing is read from or written to disk and everything is lost with
+    /// the service, see [`backend::memory`]. For the tests of the components
+    /// using the service, see [`ephemeral`].
+    pub fn new_in_memory() -> Result<Self, InitError> {
+        let mut config = config::ServiceConfig::default();
+        config.storage.backend = config::StorageBackendKind::Memory;
+        Self::with_config(&config)
+    }
+
+    /// Create a new persistency service instance with the given configuration
+    pub fn with_config(config: &config::ServiceConfig) -> Result<Self, InitError> {
+        Self::with_clock(config, SystemClock::shared())
+    }
+
+    /// Create a new persistency service instance reading time from `clock`
+    ///
+    /// Tests pass a [`clock::TestClock`] to control time-dependent behavior.
+    pub fn with_clock(
+        config: &config::ServiceConfig,
+        clock: SharedClock,
+    ) -> Result<Self, InitError> {
+        config.validate().map_err(InitError::Config)?;
         info!("Initializing persistency service with rust_kvs");
-        
+
         // Log current working directory where files will be created
         let current_dir = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
         info!("Storage files will be created in: {:?}", current_dir);
-        
-        let kvs = KvsBuilder::new(InstanceId(0))
-            .build()?;
-            
+
+        // A flush interrupted by power loss must not keep the service from starting
+        let recovered = match config.storage.backend {
+            config::StorageBackendKind::RustKvs => {
+                recovery::recover_store(&current_dir, namespace::DEFAULT_INSTANCE, clock.now_ms())
+            }
+            // The other backends recover their files when they are opened
+            _ => Ok(None),
+        };
+        let recovery = match recovered {
+            Ok(Some(recovery)) => {
+                match recovery.restored_snapshot {
+                    Some(snapshot) => warn!(
+                        "Store file rejected ({}), restored snapshot {}, rejected files kept as {:?}",
+                        recovery.reason, snapshot, recovery.preserved_files
+                    ),
+                    None => error!(
+                        "Store file rejected ({}) and no intact snapshot left, starting empty, rejected files kept as {:?}",
+                        recovery.reason, recovery.preserved_files
+                    ),
+                }
+                Some(recovery)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                error!("Recovery of the storage files failed: {}", e);
+                None
+            }
+        };
+
+        let encryption = Encryption::new(&config.encryption).map_err(|e| {
+            error!("{}", e);
+            ErrorCode::EncryptionFailed
+        })?;
+
+        let kvs = backend::open(&config.storage, &current_dir, namespace::DEFAULT_INSTANCE)?;
+
+        let spill = if config.memory.bounded {
+            Some(Arc::new(SpillStore::open(
+                &config.memory,
+                &current_dir,
+                kvs.as_ref(),
+            )?))
+        } else {
+            None
+        };
+
+        // Account the stored data, so that the forecast knows how close the store is to its limits
+        // Without persistent storage the state is kept in memory as well
+        let persistent = config.storage.backend.is_persistent();
+        let expiry = if persistent {
+            ExpiryTracker::load(&current_dir, clock.clone())
+        } else {
+            ExpiryTracker::new(clock.clone())
+        };
+        if !expiry.is_empty() {
+            info!("Loaded the deadlines of {} expiring keys", expiry.len());
+        }
+        let revisions = if persistent {
+            Revisions::load(&current_dir, clock.now_ms())
+        } else {
+            Revisions::new()
+        };
+        if recovery.is_some() {
+            revisions.forget_all();
+        }
+        // Revisions handed out from now on must not be handed out again after a crash
+        if let Err(e) = revisions.save() {
+            warn!("Failed to save the key revisions: {}", e);
+        }
+        let key_history = if persistent {
+            KeyHistory::load(&config.key_history, &current_dir)
+        } else {
+            KeyHistory::new(&config.key_history)
+        };
+        let leases = if persistent {
+            Leases::load(&config.leases, &current_dir, clock.clone())
+        } else {
+            Leases::new(&config.leases, clock.clone())
+        };
+        if !leases.is_empty() {
+            info!(
+                "Loaded {} leases, kept alive for their time to live",
+                leases.len()
+            );
+        }
+        let delayed = if persistent {
+            DelayedOperations::load(&config.destructive, &current_dir, clock.clone())
+        } else {
+            DelayedOperations::new(&config.destructive, clock.clone())
+        };
+        let backups = Backups::new(&config.backup, &current_dir);
+        let dumps = Dumps::new(&config.dump, &current_dir);
+
+        let forecast = StorageForecaster::new(&config.forecast, clock.clone(), current_dir.clone());
+        forecast.load(kvs.get_all_keys()?.into_iter().filter_map(|key| {
+            let size = Self::stored_size(kvs.as_ref(), None, &key)?;
+            Some((key, size))
+        }));
+        if let Some(spill) = &spill {
+            spill.for_each_spilled(|key, value| {
+                let size = forecast::entry_size(key, &Self::kvs_value_to_proto(value));
+                forecast.load([(key.to_string(), size)]);
+            })?;
+        }
+
+        let default = Store::new("", kvs, spill.clone())?;
+        let namespaces = Namespaces::open(
+            &config.namespaces,
+            &config.storage,
+            &current_dir,
+            default,
+            |name, kvs| {
+                forecast.load(kvs.get_all_keys()?.into_iter().filter_map(|key| {
+                    let size = Self::stored_size(kvs, None, &key)?;
+                    Some((namespace::tracked_key(name, &key).into_owned(), size))
+                }));
+                Ok(())
+            },
+        )?;
+        let namespaces = Arc::new(namespaces);
+        let store_stats = StoreStats::new(
+            namespaces.clone(),
+            &current_dir,
+            &config.storage,
+            &config.namespaces,
+        );
+
+        let forecast = Arc::new(forecast);
+        let usage = UsageTracker::new(&config.usage, forecast.clone(), clock.clone());
+
+        let mut diagnostics = Diagnostics::new(&config.diagnostics, clock.clone());
+        if config.memory.bounded {
+            diagnostics = diagnostics.with_access_limit(config.memory.max_resident_keys);
+        }
+
+        let service = Self {
+            namespaces,
+            access: AccessPolicy::new(config),
+            sampler: Arc::new(TraceSampler::new(&config.tracing)),
+            watch: Arc::new(WatchHub::new(config.watch.channel_capacity, clock.clone())),
+            diagnostics,
+            upload: Arc::new(UploadStatus::new(&config.upload, clock.clone())),
+            recovery: Arc::new(RecoveryStatus::new(recovery)),
+            forecast,
+            usage: Arc::new(usage),
+            timeseries: Arc::new(TimeSeriesStatus::new(clock.clone())),
+            mirror: Arc::new(Mirror::new(&config.mirror)),
+            stats: Arc::new(ServiceStats::new()),
+            store_stats: Arc::new(store_stats),
+            health: Health::new(),
+            spill,
+            encryption: Arc::new(encryption),
+            read_cache: Arc::new(ReadCache::new(&config.read_cache)),
+            expiry,
+            revisions,
+            key_history,
+            trash: Trash::new(&config.trash),
+            quotas: Quotas::new(&config.quotas),
+            leases: Arc::new(leases),
+            delayed,
+            backups,
+            dumps,
+            flusher: FlushScheduler::new(&config.flush),
+            #[cfg(feature = "fault-injection")]
+            faults: FaultInjector::new(),
+            modified: ModifiedTimes::new(),
+            history: MutationHistory::new(clock.now_ms()),
+            config: config.clone(),
+            started: clock.monotonic(),
+            clock,
+        };
+        service.seal_stored_values()?;
+
         info!("Persistency service initialized successfully");
-        
-        Ok(Self {
-            kvs: Arc::new(RwLock::new(kvs)),
+        Ok(service)
+    }
+
+    /// Seal the covered values stored in plaintext, see [`encryption`]
+    ///
+    /// Only called before the service takes requests. Spilled values are
+    /// sealed when they are written next.
+    fn seal_stored_values(&self) -> Result<(), ErrorCode> {
+        if !self.encryption.is_enabled() {
+            return Ok(());
+        }
+        for store in self.namespaces.iter() {
+            let kvs = store.try_read().ok_or(ErrorCode::ResourceBusy)?;
+            let mut sealed = 0;
+            for key in kvs.get_all_keys()? {
+                let tracked = store.tracked_key(&key);
+                let value = kvs.get_value(&key)?;
+                if !self.encryption.covers(&tracked) || encryption::is_sealed(&value) {
+                    continue;
+                }
+                let value = self.encryption.seal(&tracked, &value).map_err(|e| {
+                    error!("{}", e);
+                    ErrorCode::EncryptionFailed
+                })?;
+                kvs.set_value(&key, value)?;
+                sealed += 1;
+            }
+            if sealed > 0 {
+                info!(
+                    "Encrypted {} values of namespace '{}' stored in plaintext",
+                    sealed,
+                    store.name()
+                );
+                self.flush_store(&**kvs)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Change notification hub for key watchers
+    pub fn watch_hub(&self) -> Arc<WatchHub> {
+        self.watch.clone()
+    }
+
+    /// Trace sampler shared with the [`sampling::TraceSamplingLayer`]
+    pub fn trace_sampler(&self) -> Arc<TraceSampler> {
+        self.sampler.clone()
+    }
+
+    /// Write counter shared with the [`flusher`] task
+    pub fn flush_scheduler(&self) -> &FlushScheduler {
+        &self.flusher
+    }
+
+    /// Upload status shared with the [`upload`] task
+    pub fn upload_status(&self) -> Arc<UploadStatus> {
+        self.upload.clone()
+    }
+
+    /// Storage usage tracking shared with the [`metrics`] endpoint
+    pub fn storage_forecast(&self) -> Arc<StorageForecaster> {
+        self.forecast.clone()
+    }
+
+    /// Usage reports shared with the [`usage`] task
+    pub fn usage_tracker(&self) -> Arc<UsageTracker> {
+        self.usage.clone()
+    }
+
+    /// Time-series counters shared with the [`timeseries`] task
+    pub fn timeseries_status(&self) -> Arc<TimeSeriesStatus> {
+        self.timeseries.clone()
+    }
+
+    /// Mirrored writes shared with the [`mirror`] task
+    pub fn mirror(&self) -> Arc<Mirror> {
+        self.mirror.clone()
+    }
+
+    /// RPC counters shared with the [`metrics::MetricsLayer`]
+    pub fn stats(&self) -> Arc<ServiceStats> {
+        self.stats.clone()
+    }
+
+    /// Local backups of the default store
+    pub fn backups(&self) -> &Backups {
+        &self.backups
+    }
+
+    /// JSON and YAML dumps of the default store
+    pub fn dumps(&self) -> &Dumps {
+        &self.dumps
+    }
+
+    /// Serving state reported by the [`health`] task
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Components serving metrics on the [`metrics`] endpoint
+    pub fn metrics_sources(&self) -> Vec<Arc<dyn MetricsSource>> {
+        let mut sources: Vec<Arc<dyn MetricsSource>> = vec![
+            self.stats.clone(),
+            self.store_stats.clone(),
+            self.forecast.clone(),
+            self.recovery.clone(),
+        ];
+        if let Some(spill) = &self.spill {
+            sources.push(spill.clone());
+        }
+        if self.read_cache.is_enabled() {
+            sources.push(self.read_cache.clone());
+        }
+        if self.config.timeseries.enabled {
+            sources.push(self.timeseries.clone());
+        }
+        if self.config.mirror.enabled {
+            sources.push(self.mirror.clone());
+        }
+        sources
+    }
+
+    /// Approximate stored size of `key`, `None` if it does not exist
+    ///
+    /// A spilled key is looked up without reading it back into memory.
+    fn stored_size(kvs: &dyn StorageBackend, spill: Option<&SpillStore>, key: &str) -> Option<u64> {
+        let value = match spill {
+            Some(spill) => spill.peek(kvs, key)?,
+            None => Self::kvs_value_to_proto(&kvs.get_value(key).ok()?),
+        };
+        Some(forecast::entry_size(key, &value))
+    }
+
+    /// Store of the `namespace` of a request
+    fn store(&self, namespace: &str) -> Result<&Arc<Store>, Status> {
+        self.namespaces
+            .get(namespace)
+            .ok_or_else(|| Status::invalid_argument(format!("Unknown namespace '{}'", namespace)))
+    }
+
+    /// Value of `key`, read back from the spill files in bounded memory mode
+    fn read_value(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+    ) -> Result<rust_kvs::kvs_value::KvsValue, ErrorCode> {
+        let value = self.read_stored(store, kvs, key)?;
+        self.open_value(&store.tracked_key(key), value)
+    }
+
+    /// Value of `key` as stored, still sealed if it is encrypted, see [`encryption`]
+    fn read_stored(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+    ) -> Result<rust_kvs::kvs_value::KvsValue, ErrorCode> {
+        match store.spill() {
+            Some(spill) => spill.get(kvs, key),
+            None => kvs.get_value(key),
+        }
+    }
+
+    /// Open the stored `value` of `tracked` if it is sealed, see [`encryption`]
+    fn open_value(
+        &self,
+        tracked: &str,
+        value: rust_kvs::kvs_value::KvsValue,
+    ) -> Result<rust_kvs::kvs_value::KvsValue, ErrorCode> {
+        self.encryption.open(tracked, value).map_err(|e| {
+            error!("{}", e);
+            ErrorCode::EncryptionFailed
         })
     }
 
-    /// Convert rust_kvs::KvsValue to protobuf KvsValue
-    fn kvs_value_to_proto(value: &rust_kvs::kvs_value::KvsValue) -> KvsValue {
-        use rust_kvs::kvs_value::KvsValue as RustKvsValue;
-        
-        match value {
-            RustKvsValue::I32(v) => KvsValue {
-                value: Some(common::persistency_proto::kvs_value::Value::I32Value(*v)),
-            },
-            RustKvsValue::U32(v) => KvsValue {
-                value: Some(common::persistency_proto::kvs_value::Value::U32Value(*v)),
-            },
-            RustKvsValue::I64(v) => KvsValue {
-                value: Some(common::persistency_proto::kvs_value::Value::I64Value(*v)),
-            },
-            RustKvsValue::U64(v) => KvsValue {
-                value: Some(common::persistency_proto::kvs_value::Value::U64Value(*v)),
-            },
-            RustKvsValue::F64(v) => KvsValue {
-                value: Some(common::persistency_proto::kvs_value::Value::F64Value(*v)),
-            },
-            RustKvsValue::Boolean(v) => KvsValue {
-                value: Some(common::persistency_proto::kvs_value::Value::BooleanValue(*v)),
-            },
-            RustKvsValue::String(v) => KvsValue {
-                value: Some(common::persistency_proto::kvs_value::Value::StringValue(v.clone())),
-            },
-            RustKvsValue::Null => KvsValue {
-                value: Some(common::persistency_proto::kvs_value::Value::NullValue(NullValue {})),
-            },
-            RustKvsValue::Array(v) => {
-                let values = v.iter().map(Self::kvs_value_to_proto).collect();
-                KvsValue {
-                    value: Some(common::persistency_proto::kvs_value::Value::ArrayValue(KvsArray { values })),
+    /// Stored `value` of `tracked` as sent to the clients, opened if it is sealed
+    ///
+    /// A value that cannot be opened is sent as it is stored.
+    fn opened_proto(&self, tracked: &str, value: &rust_kvs::kvs_value::KvsValue) -> KvsValue {
+        match self.encryption.open(tracked, value.clone()) {
+            Ok(opened) => Self::kvs_value_to_proto(&opened),
+            Err(_) => Self::kvs_value_to_proto(value),
+        }
+    }
+
+    /// Value of `key` to report as previous value of a change, see [`watch`]
+    ///
+    /// Only read if a watcher of the key asked for previous values.
+    fn previous_for_watchers(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+    ) -> Option<KvsValue> {
+        if !self.watch.wants_previous(&store.tracked_key(key))
+            || !self.value_exists(store, kvs, key).unwrap_or(false)
+        {
+            return None;
+        }
+        self.read_value(store, kvs, key)
+            .ok()
+            .map(|value| Self::kvs_value_to_proto(&value))
+    }
+
+    /// Store the plaintext `value` under `key`, sealed if the key is covered, see [`encryption`]
+    fn write_value(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+        value: rust_kvs::kvs_value::KvsValue,
+    ) -> Result<(), ErrorCode> {
+        let tracked = store.tracked_key(key);
+        let value = if self.encryption.covers(&tracked) {
+            self.seal_value(&tracked, &value)?
+        } else {
+            value
+        };
+        self.store_value(store, kvs, key, value)
+    }
+
+    /// Store `value` under `key` as it was stored before, e.g. in a restored backup
+    ///
+    /// Sealed values are stored as they are, plaintext values of covered keys
+    /// are sealed, see [`encryption`].
+    fn write_stored_value(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+        value: rust_kvs::kvs_value::KvsValue,
+    ) -> Result<(), ErrorCode> {
+        let tracked = store.tracked_key(key);
+        let value = if self.encryption.covers(&tracked) && !encryption::is_sealed(&value) {
+            self.seal_value(&tracked, &value)?
+        } else {
+            value
+        };
+        self.store_value(store, kvs, key, value)
+    }
+
+    /// Seal `value` of `tracked`, see [`encryption`]
+    fn seal_value(
+        &self,
+        tracked: &str,
+        value: &rust_kvs::kvs_value::KvsValue,
+    ) -> Result<rust_kvs::kvs_value::KvsValue, ErrorCode> {
+        self.encryption.seal(tracked, value).map_err(|e| {
+            error!("{}", e);
+            ErrorCode::EncryptionFailed
+        })
+    }
+
+    /// Store the prepared `value` under `key`, spilling colder keys in bounded memory mode
+    ///
+    /// The key no longer expires, a time to live or lease has to be set
+    /// again, and moves on to the next revision.
+    fn store_value(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+        value: rust_kvs::kvs_value::KvsValue,
+    ) -> Result<(), ErrorCode> {
+        if store.is_default() && self.namespaces.is_reserved(key) {
+            warn!("Key {} is reserved for a namespace", key);
+            return Err(ErrorCode::ValidationFailed);
+        }
+        let tracked = store.tracked_key(key);
+        // Versions are kept as stored, so that sealed values stay sealed on disk
+        let version = self
+            .key_history
+            .covers(key)
+            .then(|| export::value_to_json(&value));
+        match store.spill() {
+            Some(spill) => spill.set(kvs, key, value),
+            None => kvs.set_value(key, value),
+        }?;
+        if let Some(index) = store.index() {
+            index.insert(key);
+        }
+        self.read_cache.invalidate(&tracked);
+        self.expiry.clear(&tracked);
+        self.leases.detach(&tracked);
+        let revision = self.revisions.bump(&tracked);
+        if version.is_some() {
+            self.key_history
+                .record(&tracked, revision, version, self.clock.now_ms());
+        }
+        Ok(())
+    }
+
+    /// Remove `key` from memory and, in bounded memory mode, from the spill files
+    fn delete_value(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+    ) -> Result<(), ErrorCode> {
+        match store.spill() {
+            Some(spill) => spill.remove(kvs, key),
+            None => kvs.remove_key(key),
+        }?;
+        if let Some(index) = store.index() {
+            index.remove(key);
+        }
+        self.forget_value(store, key);
+        Ok(())
+    }
+
+    /// Account the removal of `key` for its expiry, lease, revision and history
+    fn forget_value(&self, store: &Store, key: &str) {
+        let tracked = store.tracked_key(key);
+        self.read_cache.invalidate(&tracked);
+        self.expiry.clear(&tracked);
+        self.leases.detach(&tracked);
+        let revision = self.revisions.remove(&tracked);
+        if self.key_history.covers(key) {
+            self.key_history
+                .record(&tracked, revision, None, self.clock.now_ms());
+        }
+    }
+
+    /// Store `value` under `key` like `SetValue` does and return its revision
+    ///
+    /// The change is flushed in the background, see [`flusher`].
+    fn put_tracked(
+        &self,
+        operation: &'static str,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+        value: rust_kvs::kvs_value::KvsValue,
+    ) -> Result<u64, ErrorCode> {
+        let tracked = store.tracked_key(key);
+        let proto_value = Self::kvs_value_to_proto(&value);
+        let old_size = Self::stored_size(kvs, store.spill(), key);
+        let previous = self.previous_for_watchers(store, kvs, key);
+        self.write_value(store, kvs, key, value)?;
+        let new_size = forecast::entry_size(key, &proto_value);
+        self.forecast.record_write(&tracked, old_size, new_size);
+        self.usage.record_write(&tracked, new_size);
+        self.track_put(store, key);
+        self.watch
+            .publish(WatchEventKind::Put, &tracked, Some(proto_value), previous);
+        self.diagnostics.record_audit(operation, &tracked);
+        store.pending().mark(key);
+        self.flusher.record_write();
+        Ok(self.revisions.get(&tracked))
+    }
+
+    /// Remove `key` like `RemoveKey` does
+    fn remove_tracked(
+        &self,
+        operation: &'static str,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+    ) -> Result<(), ErrorCode> {
+        let tracked = store.tracked_key(key);
+        let old_size = Self::stored_size(kvs, store.spill(), key);
+        let previous = self.previous_for_watchers(store, kvs, key);
+        self.delete_value(store, kvs, key)?;
+        if let Some(old_size) = old_size {
+            self.forecast.record_remove(&tracked, old_size);
+        }
+        self.usage.record_remove(&tracked);
+        self.watch
+            .publish(WatchEventKind::Delete, &tracked, None, previous);
+        store.pending().mark(key);
+        self.diagnostics.record_audit(operation, &tracked);
+        self.diagnostics.forget_key(&tracked);
+        self.track_remove(store, key);
+        Ok(())
+    }
+
+    /// Store of the trash holding the removed keys of `store`, see [`trash`]
+    ///
+    /// Fails with the status code and message to answer while the trash is
+    /// disabled or for keys of the trash itself.
+    fn trash_for(&self, store: &Store) -> Result<&Arc<Store>, (Code, String)> {
+        let trash = self
+            .namespaces
+            .get(self.trash.namespace())
+            .filter(|_| self.trash.is_enabled())
+            .ok_or_else(|| (Code::FailedPrecondition, "Trash is disabled".to_string()))?;
+        if store.name() == trash.name() {
+            return Err((
+                Code::InvalidArgument,
+                "Keys of the trash namespace cannot be trashed".to_string(),
+            ));
+        }
+        Ok(trash)
+    }
+
+    /// Keep the value of `key` in `trash`, before the caller removes it
+    ///
+    /// The caller holds the lock of `key`. The trash is always locked after
+    /// the store the key is removed from or restored to.
+    async fn move_to_trash(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        trash: &Store,
+        key: &str,
+    ) -> Result<(), ErrorCode> {
+        let value = self.read_value(store, kvs, key)?;
+        let tracked = store.tracked_key(key);
+        let trashed = TrashedValue {
+            value,
+            deleted_at_ms: self.clock.now_ms(),
+        };
+
+        let trash_kvs = trash.read().await;
+        let _trash_key = trash.lock_key(&tracked).await;
+        self.put_tracked("Trash", trash, &**trash_kvs, &tracked, trashed.to_entry())?;
+        if let Some(retention) = self.trash.retention() {
+            self.expiry.set(&trash.tracked_key(&tracked), retention);
+        }
+        Ok(())
+    }
+
+    /// Kept version of `tracked` as sent to the clients, see [`key_history`]
+    fn key_version_to_proto(&self, tracked: &str, version: &KeyVersion) -> KeyVersionProto {
+        KeyVersionProto {
+            revision: version.revision,
+            value: version
+                .value
+                .as_ref()
+                .map(|value| self.opened_proto(tracked, &export::json_to_value(value))),
+            removed: version.value.is_none(),
+            written_at_ms: version.written_at_ms,
+        }
+    }
+
+    /// Current value of `key` as a version, for keys not changed since their versions are kept
+    ///
+    /// `None` if the key does not exist. The time of the change is unknown.
+    fn current_version(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+    ) -> Result<Option<KeyVersion>, ErrorCode> {
+        match self.read_stored(store, kvs, key) {
+            Ok(value) => Ok(Some(KeyVersion {
+                revision: self.revisions.get(&store.tracked_key(key)),
+                value: Some(export::value_to_json(&value)),
+                written_at_ms: 0,
+            })),
+            Err(ErrorCode::KeyNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Failure of a key history request for `key` that is not kept, `None` if it is
+    fn key_history_unavailable(&self, key: &str) -> Option<String> {
+        if !self.key_history.is_enabled() {
+            Some("Key history is disabled".to_string())
+        } else if !self.key_history.covers(key) {
+            Some(format!("Versions of key {} are not kept", key))
+        } else {
+            None
+        }
+    }
+
+    /// Revision of `key`, 0 if it does not exist, see [`revision`]
+    fn key_revision(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+    ) -> Result<u64, ErrorCode> {
+        if self.value_exists(store, kvs, key)? {
+            Ok(self.revisions.get(&store.tracked_key(key)))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Whether `key` exists in memory or in the spill files
+    fn value_exists(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        key: &str,
+    ) -> Result<bool, ErrorCode> {
+        match store.spill() {
+            Some(spill) => spill.contains(kvs, key),
+            None => kvs.key_exists(key),
+        }
+    }
+
+    /// Check that a key written with `ttl_seconds` can be attached to lease `id`, see [`lease`]
+    ///
+    /// Returns the status code and message of a rejection otherwise.
+    fn check_lease_write(&self, id: u64, ttl_seconds: u32) -> Result<(), (Code, String)> {
+        if !self.leases.is_enabled() {
+            return Err((Code::FailedPrecondition, "Leases are disabled".to_string()));
+        }
+        if ttl_seconds > 0 {
+            return Err((
+                Code::InvalidArgument,
+                "A key attached to a lease cannot have a TTL".to_string(),
+            ));
+        }
+        if self.leases.get(id).is_none() {
+            return Err((Code::FailedPrecondition, format!("Lease {} not found", id)));
+        }
+        Ok(())
+    }
+
+    /// Check that writing or removing `key` of `store` leaves a held lock alone, see [`lock`]
+    ///
+    /// Writes detach a key from its lease, which would release the lock of
+    /// its holder. Only SetValue with the token of the lock as `lease_id`
+    /// attaches the key to the lock again. Returns the status code and
+    /// message of a rejection otherwise; call it with the key locked.
+    fn check_lock_write(
+        &self,
+        store: &Store,
+        key: &str,
+        lease_id: u64,
+    ) -> Result<(), (Code, String)> {
+        if !store.is_default() || !key.starts_with(&self.config.leases.lock_prefix) {
+            return Ok(());
+        }
+        match self.leases.lease_of(&store.tracked_key(key)) {
+            Some(token) if token != lease_id && self.leases.get(token).is_some() => Err((
+                Code::FailedPrecondition,
+                format!("Key {} is a held lock, release it with Unlock", key),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that writing `values` to `store` stays within the quotas, see [`quota`]
+    ///
+    /// Returns the status code and message of a rejection otherwise.
+    fn check_quotas(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        values: &[(&str, &KvsValue)],
+    ) -> Result<(), (Code, String)> {
+        let exhausted = |e: String| {
+            if store.is_default() {
+                (Code::ResourceExhausted, e)
+            } else {
+                (
+                    Code::ResourceExhausted,
+                    format!("{} in namespace '{}'", e, store.name()),
+                )
+            }
+        };
+        let mut added = Vec::new();
+        for (key, value) in values {
+            self.quotas
+                .check_value(prost::Message::encoded_len(*value) as u64)
+                .map_err(exhausted)?;
+            if !self.quotas.limits_keys(key) || added.contains(key) {
+                continue;
+            }
+            match self.value_exists(store, kvs, key) {
+                Ok(true) => {}
+                Ok(false) => added.push(*key),
+                Err(e) => {
+                    return Err((
+                        Code::Internal,
+                        format!("Failed to read key {}: {:?}", key, e),
+                    ))
                 }
             }
-            RustKvsValue::Object(v) => {
-                let values = v.iter()
-                    .map(|(k, v)| (k.clone(), Self::kvs_value_to_proto(v)))
-                    .collect();
-                KvsValue {
-                    value: Some(common::persistency_proto::kvs_value::Value::ObjectValue(KvsObject { values })),
+        }
+        if added.is_empty() {
+            return Ok(());
+        }
+        let stored = self.all_keys(store, kvs).map_err(|e| {
+            (
+                Code::Internal,
+                format!("Failed to count the stored keys: {:?}", e),
+            )
+        })?;
+        self.quotas.check_keys(&stored, &added).map_err(exhausted)
+    }
+
+    /// Keys in memory followed by the spilled keys
+    fn all_keys(&self, store: &Store, kvs: &dyn StorageBackend) -> Result<Vec<String>, ErrorCode> {
+        let mut keys = kvs.get_all_keys()?;
+        if let Some(spill) = store.spill() {
+            spill.for_each_spilled(|key, _| keys.push(key.to_string()))?;
+        }
+        Ok(keys)
+    }
+
+    /// Account a write of `key` for restores and differential exports
+    ///
+    /// Both only cover the default store.
+    fn track_put(&self, store: &Store, key: &str) {
+        if store.is_default() {
+            self.modified.record(key, self.clock.now_ms());
+            self.history.put(key);
+        }
+    }
+
+    /// Account a removal of `key` for restores and differential exports
+    fn track_remove(&self, store: &Store, key: &str) {
+        if store.is_default() {
+            self.modified.forget(key);
+            self.history.remove(key);
+        }
+    }
+
+    /// Flush the store, timing the flush for the metrics
+    ///
+    /// The key revisions and versions are saved first, so that they never
+    /// fall behind the flushed values.
+    fn flush_store(&self, kvs: &dyn StorageBackend) -> Result<(), ErrorCode> {
+        if let Err(e) = self.revisions.save() {
+            warn!("Failed to save the key revisions: {}", e);
+        }
+        if let Err(e) = self.key_history.save() {
+            warn!("Failed to save the key history: {}", e);
+        }
+        if let Err(e) = self.leases.save() {
+            warn!("Failed to save the leases: {}", e);
+        }
+        let started = self.clock.monotonic();
+        let result = self.flush_kvs(kvs);
+        let elapsed = self.clock.monotonic().saturating_sub(started);
+        self.stats.record_flush(elapsed, result.is_ok());
+        result
+    }
+
+    /// Flush `kvs` of `store` and return the number of keys made durable
+    ///
+    /// Writers of single keys only share the store lock, so a key written
+    /// while the flush runs may miss its clone of the store. The pending keys are
+    /// therefore taken before the flush rather than cleared after it, keys
+    /// marked meanwhile stay pending for the next flush. The taken keys are
+    /// pending again if the flush fails.
+    fn flush_pending_keys(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+    ) -> Result<usize, ErrorCode> {
+        let keys = store.pending().take();
+        match self.flush_store(kvs) {
+            Ok(_) => Ok(keys.len()),
+            Err(e) => {
+                store.pending().restore(keys);
+                Err(e)
+            }
+        }
+    }
+
+    /// Flush `kvs`, failing instead while a flush fault is armed
+    #[cfg(feature = "fault-injection")]
+    fn flush_kvs(&self, kvs: &dyn StorageBackend) -> Result<(), ErrorCode> {
+        if self.faults.flush_fails() {
+            return Err(ErrorCode::PhysicalStorageFailure);
+        }
+        kvs.flush()
+    }
+
+    /// Flush `kvs`
+    #[cfg(not(feature = "fault-injection"))]
+    fn flush_kvs(&self, kvs: &dyn StorageBackend) -> Result<(), ErrorCode> {
+        kvs.flush()
+    }
+
+    /// Whether the current `SetValue` has to fail, see [`faults`]
+    #[cfg(feature = "fault-injection")]
+    fn injected_set_value_failure(&self) -> bool {
+        self.faults.take_set_value_failure()
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn injected_set_value_failure(&self) -> bool {
+        false
+    }
+
+    /// Delay a read while read latency is injected, see [`faults`]
+    #[cfg(feature = "fault-injection")]
+    async fn inject_read_latency(&self) {
+        if let Some(latency) = self.faults.read_latency() {
+            tokio::time::sleep(latency).await;
+        }
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    async fn inject_read_latency(&self) {}
+
+    /// Access policy applied to every request
+    pub fn access(&self) -> &AccessPolicy {
+        &self.access
+    }
+
+    /// Export the keys of the default store under `prefixes`, every key if `prefixes` is empty
+    pub async fn export(&self, prefixes: &[String]) -> Result<Export, ErrorCode> {
+        self.export_since(prefixes, None).await
+    }
+
+    /// Export the changes under `prefixes` since `base`
+    ///
+    /// Every key is exported if `base` is `None` or no longer covered by the
+    /// mutation history, see [`history`].
+    pub async fn export_since(
+        &self,
+        prefixes: &[String],
+        base: Option<Baseline>,
+    ) -> Result<Export, ErrorCode> {
+        // Like diagnostic dumps, hold off writers so the export is consistent
+        let kvs = self.namespaces.default_store().write().await;
+        export::export_prefixes(
+            &**kvs,
+            self.spill.as_deref(),
+            prefixes,
+            self.clock.as_ref(),
+            &self.history,
+            base,
+        )
+    }
+
+    /// Restore a snapshot or an export into the default store
+    ///
+    /// The differential exports in `increments` are applied on top of the
+    /// source in order. Conflicting keys are resolved with `strategy`, see
+    /// [`restore`].
+    pub async fn restore(
+        &self,
+        source: restore_request::Source,
+        increments: &[String],
+        strategy: ConflictStrategy,
+    ) -> Result<RestoreReport, String> {
+        let _busy = self.health.busy();
+        // Hold off other requests, so that the restore applies to a consistent store
+        let store = self.namespaces.default_store();
+        let kvs = store.write().await;
+        let (mut source, mut label) = match source {
+            restore_request::Source::SnapshotId(id) => {
+                let kvs = kvs
+                    .as_kvs()
+                    .ok_or("The storage backend keeps no snapshots")?;
+                (
+                    RestoreSource::from_snapshot(kvs, id as usize)?,
+                    format!("snapshot {}", id),
+                )
+            }
+            restore_request::Source::ExportJson(document) => {
+                (RestoreSource::from_export(&document)?, "export".to_string())
+            }
+        };
+        for (index, increment) in increments.iter().enumerate() {
+            source
+                .apply_increment(increment)
+                .map_err(|e| format!("Increment {}: {}", index + 1, e))?;
+        }
+        if !increments.is_empty() {
+            label = format!("{} with {} increments", label, increments.len());
+        }
+        self.restore_source(store, &**kvs, source, strategy, "Restore", &label)
+    }
+
+    /// Restore `source` into the default store, see [`restore`]
+    ///
+    /// The caller must hold the store's write lock. `operation` names the
+    /// request in the diagnostics, `label` the source.
+    fn restore_source(
+        &self,
+        store: &Store,
+        kvs: &dyn StorageBackend,
+        source: RestoreSource,
+        strategy: ConflictStrategy,
+        operation: &'static str,
+        label: &str,
+    ) -> Result<RestoreReport, String> {
+        let store_ms = kvs.as_kvs().map_or(0, restore::store_modified_ms);
+        // Sources hold sealed values as they are stored
+        let mut plan = restore::plan(source, strategy, |key| {
+            match self.read_stored(store, kvs, key) {
+                Ok(value) => Ok(Some((value, self.modified.get(key).unwrap_or(store_ms)))),
+                Err(ErrorCode::KeyNotFound) => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+        .map_err(|e| format!("Failed to read stored keys: {:?}", e))?;
+        // Held locks keep their value, they are reported as skipped conflicts
+        let (locked, writes): (Vec<_>, Vec<_>) = plan
+            .writes
+            .into_iter()
+            .partition(|(key, _, _)| self.check_lock_write(store, key, 0).is_err());
+        plan.writes = writes;
+        for (key, _, restored_ms) in locked {
+            match plan
+                .report
+                .conflicts
+                .iter_mut()
+                .find(|conflict| conflict.key == key)
+            {
+                Some(conflict) => conflict.resolution = restore::Resolution::Skipped,
+                None => plan.report.conflicts.push(restore::KeyConflict {
+                    stored_ms: self.modified.get(&key).unwrap_or(store_ms),
+                    key,
+                    resolution: restore::Resolution::Skipped,
+                    restored_ms,
+                }),
+            }
+            plan.report.restored -= 1;
+            plan.report.skipped += 1;
+        }
+
+        if !plan.report.rejected() {
+            for (key, value, restored_ms) in plan.writes {
+                let old_size = Self::stored_size(kvs, store.spill(), &key);
+                let previous = self.previous_for_watchers(store, kvs, &key);
+                let proto_value = self.opened_proto(&key, &value);
+                if let Err(e) = self.write_stored_value(store, kvs, &key, value) {
+                    self.diagnostics
+                        .record_error(operation, &key, format!("{:?}", e));
+                    return Err(format!("Failed to restore key {}: {:?}", key, e));
+                }
+                self.forecast.record_write(
+                    &key,
+                    old_size,
+                    forecast::entry_size(&key, &proto_value),
+                );
+                self.modified.record(&key, restored_ms);
+                self.history.put(&key);
+                store.pending().mark(&key);
+                self.watch
+                    .publish(WatchEventKind::Put, &key, Some(proto_value), previous);
+            }
+            if let Err(e) = self.flush_store(kvs) {
+                warn!("Failed to flush after restoring {}: {:?}", label, e);
+                self.diagnostics
+                    .record_error("Flush", "", format!("{:?}", e));
+            } else {
+                store.pending().clear();
+            }
+        }
+        self.diagnostics.record_audit(operation, label);
+        info!(
+            "{} of {} with {}: {} restored, {} unchanged, {} skipped, {} conflicts",
+            operation,
+            label,
+            strategy.as_str(),
+            plan.report.restored,
+            plan.report.unchanged,
+            plan.report.skipped,
+            plan.report.conflicts.len()
+        );
+        Ok(plan.report)
+    }
+
+    /// Replace the keys of the default store under the prefixes of `snapshot` with its entries
+    ///
+    /// With `dry_run` the changes are only reported, see [`replace`]. If a
+    /// change fails, the ones applied before are rolled back; the keys keep
+    /// their values then, but no longer expire.
+    pub async fn replace_store(
+        &self,
+        snapshot: Snapshot,
+        dry_run: bool,
+    ) -> Result<ReplaceReport, String> {
+        let _busy = (!dry_run).then(|| self.health.busy());
+        // Hold off other requests, so that they see either the old or the new contents
+        let store = self.namespaces.default_store();
+        let kvs = store.write().await;
+        if let Some(key) = snapshot
+            .entries
+            .keys()
+            .find(|key| self.namespaces.is_reserved(key))
+        {
+            return Err(format!("Key {} is reserved for a namespace", key));
+        }
+        let label = if snapshot.prefixes.is_empty() {
+            "the whole store".to_string()
+        } else {
+            format!("prefixes {}", snapshot.prefixes.join(", "))
+        };
+        let keys = self
+            .all_keys(store, &**kvs)
+            .map_err(|e| format!("Failed to read stored keys: {:?}", e))?;
+        // Snapshots hold sealed values as they are stored
+        let plan = replace::plan(snapshot, keys, |key| self.read_stored(store, &**kvs, key))
+            .map_err(|e| format!("Failed to read stored keys: {:?}", e))?;
+        if let Some(Err((_, e))) = plan
+            .changes
+            .iter()
+            .map(|change| self.check_lock_write(store, change.key(), 0))
+            .find(Result::is_err)
+        {
+            return Err(e);
+        }
+        let report = plan.report;
+        if dry_run {
+            info!(
+                "Dry run of a snapshot restore of {}: {} added, {} replaced, {} removed, {} unchanged",
+                label, report.added, report.replaced, report.removed, report.unchanged
+            );
+            return Ok(report);
+        }
+
+        let mut old_sizes = Vec::with_capacity(plan.changes.len());
+        let mut previous_values = Vec::with_capacity(plan.changes.len());
+        for (applied, change) in plan.changes.iter().enumerate() {
+            old_sizes.push(Self::stored_size(&**kvs, store.spill(), change.key()));
+            previous_values.push(self.previous_for_watchers(store, &**kvs, change.key()));
+            let result = match change {
+                Change::Put { key, value, .. } => {
+                    self.write_stored_value(store, &**kvs, key, value.clone())
+                }
+                Change::Remove { key, .. } => self.delete_value(store, &**kvs, key),
+            };
+            if let Err(e) = result {
+                self.diagnostics
+                    .record_error("RestoreSnapshot", change.key(), format!("{:?}", e));
+                let outcome = if self.roll_back(store, &**kvs, &plan.changes[..applied]) {
+                    "nothing was changed"
+                } else {
+                    "the store is partly restored"
+                };
+                return Err(format!(
+                    "Failed to restore key {}: {:?}, {}",
+                    change.key(),
+                    e,
+                    outcome
+                ));
+            }
+        }
+        for ((change, old_size), previous) in
+            plan.changes.into_iter().zip(old_sizes).zip(previous_values)
+        {
+            match change {
+                Change::Put { key, value, .. } => {
+                    let proto_value = self.opened_proto(&key, &value);
+                    self.forecast.record_write(
+                        &key,
+                        old_size,
+                        forecast::entry_size(&key, &proto_value),
+                    );
+                    self.track_put(store, &key);
+                    store.pending().mark(&key);
+                    self.watch
+                        .publish(WatchEventKind::Put, &key, Some(proto_value), previous);
+                }
+                Change::Remove { key, .. } => {
+                    if let Some(old_size) = old_size {
+                        self.forecast.record_remove(&key, old_size);
+                    }
+                    self.diagnostics.forget_key(&key);
+                    self.track_remove(store, &key);
+                    store.pending().mark(&key);
+                    self.watch
+                        .publish(WatchEventKind::Delete, &key, None, previous);
+                }
+            }
+        }
+        if let Err(e) = self.flush_store(&**kvs) {
+            warn!(
+                "Failed to flush after restoring a snapshot of {}: {:?}",
+                label, e
+            );
+            self.diagnostics
+                .record_error("Flush", "", format!("{:?}", e));
+        } else {
+            store.pending().clear();
+        }
+        self.diagnostics.record_audit("RestoreSnapshot", &label);
+        info!(
+            "Snapshot restore of {}: {} added, {} replaced, {} removed, {} unchanged",
+            label, report.added, report.replaced, report.removed, report.unchanged
+        );
+        Ok(report)
+    }
+
+    /// Undo the `applied` changes of a failed snapshot restore, the last one first
+    ///
+    /// Returns whether every change was undone.
+    fn roll_back(&self, store: &Store, kvs: &dyn StorageBackend, applied: &[Change]) -> bool {
+        let mut complete = true;
+        for change in applied.iter().rev() {
+            let result = match change {
+                Change::Put {
+                    key,
+                    previous: None,
+                    ..
+                } => self.delete_value(store, kvs, key),
+                Change::Put {
+                    key,
+                    previous: Some(previous),
+                    ..
+                }
+                | Change::Remove { key, previous } => {
+                    self.write_stored_value(store, kvs, key, previous.clone())
+                }
+            };
+            if let Err(e) = result {
+                error!("Failed to roll back key {}: {:?}", change.key(), e);
+                complete = false;
+            }
+        }
+        complete
+    }
+
+    /// Export the configured prefixes of the default store to a new backup, see [`backup`]
+    pub async fn create_backup(&self) -> Result<CreatedBackup, String> {
+        let export = self
+            .export(&self.config.backup.prefixes)
+            .await
+            .map_err(|e| format!("Export failed: {:?}", e))?;
+        let created = self.backups.write(&export)?;
+        self.diagnostics
+            .record_audit("CreateBackup", &created.file.name);
+        info!(
+            "Backed up {} keys to {}, removed {} old backups",
+            created.keys,
+            created.file.name,
+            created.removed.len()
+        );
+        Ok(created)
+    }
+
+    /// Export the keys of the default store under `prefix` to a new file of
+    /// the dump directory, see [`dump`]
+    pub async fn export_to_file(
+        &self,
+        prefix: &str,
+        format: DumpFormat,
+        file_name: &str,
+    ) -> Result<DumpFile, String> {
+        let prefixes = if prefix.is_empty() {
+            Vec::new()
+        } else {
+            vec![prefix.to_string()]
+        };
+        let export = self
+            .export(&prefixes)
+            .await
+            .map_err(|e| format!("Export failed: {:?}", e))?;
+        let written = self.dumps.write(&export, format, file_name)?;
+        self.diagnostics.record_audit("ExportToFile", &written.name);
+        info!(
+            "Exported {} keys under '{}' to {}",
+            written.keys, prefix, written.name
+        );
+        Ok(written)
+    }
+
+    /// Restore a file of the dump directory into the default store, see [`dump`]
+    pub async fn import_from_file(
+        &self,
+        file_name: &str,
+        strategy: ConflictStrategy,
+    ) -> Result<(SourceFormat, RestoreReport), String> {
+        let (source, format) = self.dumps.read(file_name)?;
+        let label = format!("{} file {}", format.as_str(), file_name);
+        let report = self
+            .import(source, strategy, "ImportFromFile", &label)
+            .await?;
+        Ok((format, report))
+    }
+
+    /// Import the keys under `prefix` of the etcd at `endpoint`, or at the
+    /// configured one if it is empty, into the default store, see [`etcd`]
+    pub async fn migrate_from_etcd(
+        &self,
+        endpoint: &str,
+        prefix: &str,
+        strategy: ConflictStrategy,
+    ) -> Result<EtcdMigration, String> {
+        let reader = EtcdReader::new(&self.config.etcd_migration, endpoint)?;
+        let keys = reader.read_prefix(prefix).await?;
+        let read_keys = keys.entries.len();
+        info!(
+            "Read {} keys under '{}' from etcd at {} at revision {}",
+            read_keys,
+            prefix,
+            reader.endpoint(),
+            keys.revision
+        );
+        let label = format!("etcd {} revision {}", reader.endpoint(), keys.revision);
+        let source = RestoreSource::from_entries(keys.entries, self.clock.now_ms());
+        let report = self
+            .import(source, strategy, "MigrateFromEtcd", &label)
+            .await?;
+        Ok(EtcdMigration {
+            read_keys,
+            revision: keys.revision,
+            report,
+        })
+    }
+
+    /// Restore keys of a foreign source into the default store, refusing
+    /// the keys reserved for namespaces
+    async fn import(
+        &self,
+        source: RestoreSource,
+        strategy: ConflictStrategy,
+        operation: &'static str,
+        label: &str,
+    ) -> Result<RestoreReport, String> {
+        let _busy = self.health.busy();
+        // Hold off other requests, so that the import applies to a consistent store
+        let store = self.namespaces.default_store();
+        let kvs = store.write().await;
+        if let Some(key) = source
+            .entries
+            .keys()
+            .find(|key| self.namespaces.is_reserved(key))
+        {
+            return Err(format!("Key {} is reserved for a namespace", key));
+        }
+        self.restore_source(store, &**kvs, source, strategy, operation, label)
+    }
+
+    /// Verify the checksums of the storage files of the default store
+    pub async fn verify_storage(&self) -> Vec<SnapshotIntegrity> {
+        // Hold off flushes, which rotate the files
+        let kvs = self.namespaces.default_store().write().await;
+        let report = kvs
+            .as_kvs()
+            .map(integrity::verify_snapshots)
+            .unwrap_or_default();
+        drop(kvs);
+        for snapshot in report.iter().filter(|snapshot| !snapshot.valid) {
+            self.diagnostics.record_error(
+                "VerifyStorage",
+                &snapshot.file,
+                snapshot.error.clone().unwrap_or_default(),
+            );
+        }
+        report
+    }
+
+    /// Add `delta` to the counter under `key` of `store`, or subtract it if `negate` is set
+    ///
+    /// Returns the new value, see [`counter`] for the arithmetic, or the status code and message of the failure.
+    async fn add_to_counter(
+        &self,
+        operation: &'static str,
+        store: &Store,
+        key: &str,
+        delta: Option<KvsValue>,
+        negate: bool,
+    ) -> Result<KvsValue, (Code, String)> {
+        let delta = delta.ok_or_else(|| {
+            (
+                Code::InvalidArgument,
+                "Missing delta in request".to_string(),
+            )
+        })?;
+
+        // Hold off other writers of the key between the read and the write
+        let kvs = store.read().await;
+        let _key = store.lock_key(key).await;
+        self.check_lock_write(store, key, 0)?;
+        let current = match self.read_value(store, &**kvs, key) {
+            Ok(value) => Some(Self::kvs_value_to_proto(&value)),
+            Err(ErrorCode::KeyNotFound) => None,
+            Err(e) => return Err((Code::Internal, format!("Failed to read value: {:?}", e))),
+        };
+        let proto_value = counter::apply(current.as_ref(), &delta, negate)
+            .map_err(|e| (Code::FailedPrecondition, e))?;
+        let rust_value =
+            Self::proto_to_kvs_value(&proto_value).map_err(|e| (Code::InvalidArgument, e))?;
+        self.check_quotas(store, &**kvs, &[(key, &proto_value)])?;
+
+        let tracked = store.tracked_key(key);
+        let old_size = Self::stored_size(&**kvs, store.spill(), key);
+        let previous = self.previous_for_watchers(store, &**kvs, key);
+        if let Err(e) = self.write_value(store, &**kvs, key, rust_value) {
+            self.diagnostics
+                .record_error(operation, &tracked, format!("{:?}", e));
+            return Err((Code::Internal, format!("Failed to set value: {:?}", e)));
+        }
+        let new_size = forecast::entry_size(key, &proto_value);
+        self.forecast.record_write(&tracked, old_size, new_size);
+        self.usage.record_write(&tracked, new_size);
+        self.track_put(store, key);
+        self.watch.publish(
+            WatchEventKind::Put,
+            &tracked,
+            Some(proto_value.clone()),
+            previous,
+        );
+        self.diagnostics.record_audit(operation, &tracked);
+        self.diagnostics.record_access(&tracked);
+
+        // Flushed in the background together with other writes, see `flusher`
+        store.pending().mark(key);
+        self.flusher.record_write();
+        Ok(proto_value)
+    }
+
+    /// Remove the keys past their time to live or of expired leases and save the deadlines, see [`expiry`]
+    pub async fn expire_keys(&self) {
+        // The keys of leases not kept alive expire with them, see [`lease`]
+        for tracked in self.leases.take_expired() {
+            self.expiry.set(&tracked, Duration::ZERO);
+        }
+        for store in self.namespaces.iter() {
+            if !self.expiry.has_expired() {
+                break;
+            }
+            // Hold off writers, so that a key written again meanwhile is not removed
+            let kvs = store.write().await;
+            let expired = self.expiry.take_expired_where(|tracked| {
+                self.namespaces.resolve(tracked).0.name() == store.name()
+            });
+            let mut removed = 0;
+            for tracked in expired {
+                let (_, key) = self.namespaces.resolve(&tracked);
+                let old_size = Self::stored_size(&**kvs, store.spill(), key);
+                let previous = self.previous_for_watchers(store, &**kvs, key);
+                match self.delete_value(store, &**kvs, key) {
+                    Ok(_) => {
+                        if let Some(old_size) = old_size {
+                            self.forecast.record_remove(&tracked, old_size);
+                        }
+                        self.usage.record_remove(&tracked);
+                        self.watch
+                            .publish(WatchEventKind::Delete, &tracked, None, previous);
+                        store.pending().mark(key);
+                        self.diagnostics.record_audit("Expire", &tracked);
+                        self.diagnostics.forget_key(&tracked);
+                        self.track_remove(store, key);
+                        removed += 1;
+                    }
+                    // Removed meanwhile, e.g. while the service was down
+                    Err(ErrorCode::KeyNotFound) => {}
+                    Err(e) => {
+                        warn!("Failed to remove expired key {}: {:?}", tracked, e);
+                        self.diagnostics
+                            .record_error("Expire", &tracked, format!("{:?}", e));
+                        // Retry on the next sweep
+                        self.expiry.set(&tracked, Duration::ZERO);
+                    }
+                }
+            }
+            if removed > 0 {
+                debug!("Removed {} expired keys", removed);
+                match self.flush_store(&**kvs) {
+                    Ok(_) => store.pending().clear(),
+                    Err(e) => warn!("Failed to flush after removing expired keys: {:?}", e),
+                }
+            }
+        }
+        if let Err(e) = self.expiry.save() {
+            warn!("Failed to save the key deadlines: {}", e);
+        }
+        if let Err(e) = self.leases.save() {
+            warn!("Failed to save the leases: {}", e);
+        }
+    }
+
+    /// Remove every key of `store`
+    async fn reset_store(&self, store: &Store) -> Result<(), ErrorCode> {
+        // Whole store, wait for the writers of single keys
+        let kvs = store.write().await;
+        let removed_keys = self.all_keys(store, &**kvs).unwrap_or_default();
+        let previous_values: Vec<_> = removed_keys
+            .iter()
+            .map(|key| self.previous_for_watchers(store, &**kvs, key))
+            .collect();
+
+        kvs.reset().and_then(|_| match store.spill() {
+            Some(spill) => spill.clear(),
+            None => Ok(()),
+        })?;
+        if let Some(index) = store.index() {
+            index.clear();
+        }
+        info!("Successfully reset KVS of namespace '{}'", store.name());
+        for (key, previous) in removed_keys.iter().zip(previous_values) {
+            let tracked = store.tracked_key(key);
+            self.watch
+                .publish(WatchEventKind::Delete, &tracked, None, previous);
+            self.diagnostics.forget_key(&tracked);
+            self.forget_value(store, key);
+            store.pending().mark(key);
+            self.track_remove(store, key);
+        }
+        self.diagnostics.record_audit("Reset", store.name());
+        if store.is_default() {
+            self.modified.forget_all();
+        }
+        self.forecast
+            .record_reset_where(|prefix| self.namespaces.resolve(prefix).0.name() == store.name());
+        Ok(())
+    }
+
+    /// Flush every store with pending changes, see [`flusher`]
+    ///
+    /// The keys of a store that failed to flush stay pending and are flushed
+    /// again by the next call.
+    pub async fn flush_pending(&self) {
+        // Writes from now on count towards the next flush
+        self.flusher.take_writes();
+        for store in self.namespaces.iter() {
+            if store.pending().is_empty() {
+                continue;
+            }
+            let kvs = store.read().await;
+            match self.flush_pending_keys(store, &**kvs) {
+                Ok(flushed) => debug!(
+                    "Flushed {} pending keys of namespace '{}'",
+                    flushed,
+                    store.name()
+                ),
+                Err(e) => {
+                    warn!(
+                        "Failed to flush the pending keys of namespace '{}': {:?}",
+                        store.name(),
+                        e
+                    );
+                    self.diagnostics
+                        .record_error("Flush", store.name(), format!("{:?}", e));
+                }
+            }
+        }
+    }
+
+    /// Execute the destructive operations whose grace period is over, see [`destructive`]
+    ///
+    /// A failed operation stays journaled and is tried again on the next check.
+    pub async fn execute_due_operations(&self) {
+        for pending in self.delayed.due() {
+            let result = match &pending.operation {
+                DestructiveOperation::Reset { namespace } => match self.namespaces.get(namespace) {
+                    Some(store) => self
+                        .reset_store(store)
+                        .await
+                        .map_err(|e| format!("{:?}", e)),
+                    None => {
+                        // Removed from the configuration meanwhile, the reset can never succeed
+                        error!(
+                            "Dropping pending reset {} of unknown namespace '{}'",
+                            pending.id, namespace
+                        );
+                        Ok(())
+                    }
+                },
+            };
+            match result {
+                Ok(()) => {
+                    info!(
+                        "Executed pending {} {} of namespace '{}' requested by '{}'",
+                        pending.operation.name(),
+                        pending.id,
+                        pending.operation.namespace(),
+                        pending.caller
+                    );
+                    if let Err(e) = self.delayed.complete(pending.id) {
+                        warn!(
+                            "Failed to remove executed operation {} from the journal: {}",
+                            pending.id, e
+                        );
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "Pending {} {} failed, retrying: {}",
+                        pending.operation.name(),
+                        pending.id,
+                        e
+                    );
+                    self.diagnostics.record_error(
+                        pending.operation.name(),
+                        pending.operation.namespace(),
+                        e,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Store a usage report under [`usage::REPORT_KEY_PREFIX`] of the default store
+    ///
+    /// Only the newest `usage.retained_reports` stored reports are kept.
+    pub async fn store_usage_report(&self, report: &UsageReport) -> Result<(), String> {
+        let json =
+            serde_json::to_string(report).map_err(|e| format!("Failed to encode report: {}", e))?;
+        let key = usage::report_key(report.period_start_ms);
+        let proto_value = KvsValue {
+            value: Some(common::persistency_proto::kvs_value::Value::StringValue(
+                json.clone(),
+            )),
+        };
+
+        let store = self.namespaces.default_store();
+        let kvs = store.read().await;
+        {
+            // Hold off other writers of the key, like SetValue does
+            let _key = store.lock_key(&key).await;
+            let old_size = Self::stored_size(&**kvs, store.spill(), &key);
+            let previous = self.previous_for_watchers(store, &**kvs, &key);
+            self.write_value(
+                store,
+                &**kvs,
+                &key,
+                rust_kvs::kvs_value::KvsValue::String(json),
+            )
+            .map_err(|e| format!("Failed to store {}: {:?}", key, e))?;
+            self.forecast
+                .record_write(&key, old_size, forecast::entry_size(&key, &proto_value));
+            self.track_put(store, &key);
+            store.pending().mark(&key);
+            self.watch
+                .publish(WatchEventKind::Put, &key, Some(proto_value), previous);
+        }
+
+        let mut stored: Vec<String> = self
+            .all_keys(store, &**kvs)
+            .map_err(|e| format!("Failed to list stored reports: {:?}", e))?
+            .into_iter()
+            .filter(|key| key.starts_with(usage::REPORT_KEY_PREFIX))
+            .collect();
+        stored.sort();
+        let expired = stored
+            .len()
+            .saturating_sub(self.config.usage.retained_reports);
+        for key in &stored[..expired] {
+            let _key = store.lock_key(key).await;
+            let old_size = Self::stored_size(&**kvs, store.spill(), key);
+            let previous = self.previous_for_watchers(store, &**kvs, key);
+            if let Err(e) = self.delete_value(store, &**kvs, key) {
+                warn!("Failed to remove expired usage report {}: {:?}", key, e);
+                continue;
+            }
+            if let Some(old_size) = old_size {
+                self.forecast.record_remove(key, old_size);
+            }
+            self.track_remove(store, key);
+            store.pending().mark(key);
+            self.watch
+                .publish(WatchEventKind::Delete, key, None, previous);
+        }
+
+        if let Err(e) = self.flush_pending_keys(store, &**kvs) {
+            warn!(
+                "Failed to flush after storing usage report {}: {:?}",
+                key, e
+            );
+            self.diagnostics
+                .record_error("Flush", &key, format!("{:?}", e));
+        }
+        Ok(())
+    }
+
+    /// Bytes of the string or binary value of `key` to send in chunks, and whether it is binary
+    async fn read_chunked_value(
+        &self,
+        store: &Store,
+        key: &str,
+    ) -> Result<(Vec<u8>, bool), Status> {
+        let tracked = store.tracked_key(key);
+        self.inject_read_latency().await;
+        let value = {
+            let kvs = store.read().await;
+            self.read_value(store, &**kvs, key)
+        };
+        let (value, binary) = match value {
+            Ok(rust_kvs::kvs_value::KvsValue::String(value)) => (value.into_bytes(), false),
+            Ok(rust_kvs::kvs_value::KvsValue::Bytes(value)) => (value, true),
+            Ok(_) => {
+                return Err(Status::failed_precondition(format!(
+                    "Key {} does not hold a string or binary value",
+                    key
+                )))
+            }
+            Err(e) => {
+                warn!("Failed to get value for key {}: {:?}", tracked, e);
+                return Err(Status::not_found(format!("Key not found: {:?}", e)));
+            }
+        };
+
+        self.diagnostics.record_access(&tracked);
+        self.usage
+            .record_read(&tracked, (key.len() + value.len()) as u64);
+        Ok((value, binary))
+    }
+
+    /// Convert rust_kvs::KvsValue to protobuf KvsValue
+    fn kvs_value_to_proto(value: &rust_kvs::kvs_value::KvsValue) -> KvsValue {
+        use rust_kvs::kvs_value::KvsValue as RustKvsValue;
+
+        match value {
+            RustKvsValue::I32(v) => KvsValue {
+                value: Some(common::persistency_proto::kvs_value::Value::I32Value(*v)),
+            },
+            RustKvsValue::U32(v) => KvsValue {
+                value: Some(common::persistency_proto::kvs_value::Value::U32Value(*v)),
+            },
+            RustKvsValue::I64(v) => KvsValue {
+                value: Some(common::persistency_proto::kvs_value::Value::I64Value(*v)),
+            },
+            RustKvsValue::U64(v) => KvsValue {
+                value: Some(common::persistency_proto::kvs_value::Value::U64Value(*v)),
+            },
+            RustKvsValue::F64(v) => KvsValue {
+                value: Some(common::persistency_proto::kvs_value::Value::F64Value(*v)),
+            },
+            RustKvsValue::Boolean(v) => KvsValue {
+                value: Some(common::persistency_proto::kvs_value::Value::BooleanValue(
+                    *v,
+                )),
+            },
+            RustKvsValue::String(v) => KvsValue {
+                value: Some(common::persistency_proto::kvs_value::Value::StringValue(
+                    v.clone(),
+                )),
+            },
+            RustKvsValue::Bytes(v) => KvsValue {
+                value: Some(common::persistency_proto::kvs_value::Value::BytesValue(
+                    v.clone(),
+                )),
+            },
+            RustKvsValue::Null => KvsValue {
+                value: Some(common::persistency_proto::kvs_value::Value::NullValue(
+                    NullValue {},
+                )),
+            },
+            RustKvsValue::Array(v) => {
+                let values = v.iter().map(Self::kvs_value_to_proto).collect();
+                KvsValue {
+                    value: Some(common::persistency_proto::kvs_value::Value::ArrayValue(
+                        KvsArray { values },
+                    )),
+                }
+            }
+            RustKvsValue::Object(v) => {
+                let values = v
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Self::kvs_value_to_proto(v)))
+                    .collect();
+                KvsValue {
+                    value: Some(common::persistency_proto::kvs_value::Value::ObjectValue(
+                        KvsObject { values },
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Convert protobuf KvsValue to rust_kvs::KvsValue
+    fn proto_to_kvs_value(value: &KvsValue) -> Result<rust_kvs::kvs_value::KvsValue, String> {
+        use common::persistency_proto::kvs_value::Value;
+        use rust_kvs::kvs_value::KvsValue as RustKvsValue;
+
+        match &value.value {
+            Some(Value::I32Value(v)) => Ok(RustKvsValue::I32(*v)),
+            Some(Value::U32Value(v)) => Ok(RustKvsValue::U32(*v)),
+            Some(Value::I64Value(v)) => Ok(RustKvsValue::I64(*v)),
+            Some(Value::U64Value(v)) => Ok(RustKvsValue::U64(*v)),
+            Some(Value::F64Value(v)) => Ok(RustKvsValue::F64(*v)),
+            Some(Value::BooleanValue(v)) => Ok(RustKvsValue::Boolean(*v)),
+            Some(Value::StringValue(v)) => Ok(RustKvsValue::String(v.clone())),
+            Some(Value::BytesValue(v)) => Ok(RustKvsValue::Bytes(v.clone())),
+            Some(Value::NullValue(_)) => Ok(RustKvsValue::Null),
+            Some(Value::ArrayValue(arr)) => {
+                let mut values = Vec::new();
+                for proto_val in &arr.values {
+                    values.push(Self::proto_to_kvs_value(proto_val)?);
+                }
+                Ok(RustKvsValue::Array(values))
+            }
+            Some(Value::ObjectValue(obj)) => {
+                let mut values = HashMap::new();
+                for (key, proto_val) in &obj.values {
+                    values.insert(key.clone(), Self::proto_to_kvs_value(proto_val)?);
+                }
+                Ok(RustKvsValue::Object(values))
+            }
+            None => Err("KvsValue has no value set".to_string()),
+        }
+    }
+
+    /// Convert the protobuf KvsValue written by a client to rust_kvs::KvsValue
+    ///
+    /// Objects shaped like a sealed value are rejected, see [`encryption`]:
+    /// they would be stored in place of a sealed value of a covered key, and
+    /// fail to open when any other key is read.
+    fn client_value(value: &KvsValue) -> Result<rust_kvs::kvs_value::KvsValue, String> {
+        let value = Self::proto_to_kvs_value(value)?;
+        if encryption::is_sealed(&value) {
+            return Err(format!(
+                "Objects with the single field {} are reserved for encrypted values",
+                encryption::SEALED_FIELD
+            ));
+        }
+        Ok(value)
+    }
+}
+
+#[tonic::async_trait]
+impl PersistencyService for PersistencyServiceImpl {
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<WatchResponse, Status>> + Send>>;
+    type GetValueStreamStream =
+        Pin<Box<dyn Stream<Item = Result<GetValueStreamResponse, Status>> + Send>>;
+    type ScanStream = Pin<Box<dyn Stream<Item = Result<ScanResponse, Status>> + Send>>;
+    type GetLargeValueStream =
+        Pin<Box<dyn Stream<Item = Result<GetLargeValueResponse, Status>> + Send>>;
+    type KeepAliveStream = Pin<Box<dyn Stream<Item = Result<KeepAliveResponse, Status>> + Send>>;
+
+    async fn set_value(
+        &self,
+        request: Request<SetValueRequest>,
+    ) -> Result<Response<SetValueResponse>, Status> {
+        self.access.check_write(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        if let Some(lease) = self.leases.get(request.get_ref().lease_id) {
+            self.access
+                .check_lease(request.extensions(), lease.owner.as_deref())?;
+        }
+        let mirrored = self
+            .mirror
+            .sample_write(|| MirroredWrite::SetValue(request.get_ref().clone()));
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!("SetValue request for key: {}", tracked);
+        timing::note_target(&tracked);
+
+        if self.injected_set_value_failure() {
+            warn!("Injected failure of SetValue for key: {}", req.key);
+            return rejected(
+                Code::Internal,
+                SetValueResponse {
+                    success: false,
+                    error_message: "Failed to set value: injected fault".to_string(),
+                    revision: 0,
+                },
+            );
+        }
+
+        let max_ttl_secs = self.config.expiry.max_ttl_secs;
+        if max_ttl_secs > 0 && req.ttl_seconds as u64 > max_ttl_secs {
+            return rejected(
+                Code::InvalidArgument,
+                SetValueResponse {
+                    success: false,
+                    error_message: format!(
+                        "TTL of {}s exceeds the limit of {}s",
+                        req.ttl_seconds, max_ttl_secs
+                    ),
+                    revision: 0,
+                },
+            );
+        }
+
+        if req.lease_id != 0 {
+            if let Err((code, e)) = self.check_lease_write(req.lease_id, req.ttl_seconds) {
+                warn!("SetValue of {} rejected: {}", tracked, e);
+                return rejected(
+                    code,
+                    SetValueResponse {
+                        success: false,
+                        error_message: e,
+                        revision: 0,
+                    },
+                );
+            }
+        }
+
+        let kvs = store.read().await;
+        let _key = store.lock_key(&req.key).await;
+
+        if let Err((code, e)) = self.check_lock_write(store, &req.key, req.lease_id) {
+            warn!("SetValue of {} rejected: {}", tracked, e);
+            return rejected(
+                code,
+                SetValueResponse {
+                    success: false,
+                    error_message: e,
+                    revision: 0,
+                },
+            );
+        }
+
+        // Another writer changed the key since the caller read it
+        if req.expected_revision > 0 {
+            match self.key_revision(store, &**kvs, &req.key) {
+                Ok(current) if current != req.expected_revision => {
+                    debug!(
+                        "SetValue of {} expected revision {}, current {}",
+                        tracked, req.expected_revision, current
+                    );
+                    return rejected(
+                        Code::Aborted,
+                        SetValueResponse {
+                            success: false,
+                            error_message: format!(
+                                "Revision conflict: expected {}, current {}",
+                                req.expected_revision, current
+                            ),
+                            revision: current,
+                        },
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!("Failed to read the revision of key {}: {:?}", tracked, e);
+                    return rejected(
+                        Code::Internal,
+                        SetValueResponse {
+                            success: false,
+                            error_message: format!("Failed to read revision: {:?}", e),
+                            revision: 0,
+                        },
+                    );
+                }
+            }
+        }
+
+        if let Some(proto_value) = &req.value {
+            if let Err((code, e)) = self.check_quotas(store, &**kvs, &[(&req.key, proto_value)]) {
+                warn!("SetValue of {} rejected: {}", tracked, e);
+                self.diagnostics
+                    .record_error("SetValue", &tracked, e.clone());
+                return rejected(
+                    code,
+                    SetValueResponse {
+                        success: false,
+                        error_message: e,
+                        revision: 0,
+                    },
+                );
+            }
+        }
+
+        match req.value {
+            Some(proto_value) => {
+                match Self::client_value(&proto_value) {
+                    Ok(rust_value) => {
+                        let old_size = Self::stored_size(&**kvs, store.spill(), &req.key);
+                        let previous = self.previous_for_watchers(store, &**kvs, &req.key);
+                        match self.write_value(store, &**kvs, &req.key, rust_value) {
+                            Ok(_) => {
+                                debug!("Successfully set value for key: {}", tracked);
+                                if req.ttl_seconds > 0 {
+                                    self.expiry
+                                        .set(&tracked, Duration::from_secs(req.ttl_seconds as u64));
+                                }
+                                if req.lease_id != 0 && !self.leases.attach(req.lease_id, &tracked)
+                                {
+                                    // The lease expired meanwhile, the key goes with it
+                                    self.expiry.set(&tracked, Duration::ZERO);
+                                }
+                                let new_size = forecast::entry_size(&req.key, &proto_value);
+                                self.forecast.record_write(&tracked, old_size, new_size);
+                                self.usage.record_write(&tracked, new_size);
+                                self.track_put(store, &req.key);
+                                self.watch.publish(
+                                    WatchEventKind::Put,
+                                    &tracked,
+                                    Some(proto_value),
+                                    previous,
+                                );
+                                self.diagnostics.record_audit("SetValue", &tracked);
+                                self.diagnostics.record_access(&tracked);
+
+                                // Flushed in the background together with other writes, see `flusher`
+                                store.pending().mark(&req.key);
+                                self.flusher.record_write();
+                                self.mirror.commit(mirrored);
+
+                                Ok(Response::new(SetValueResponse {
+                                    success: true,
+                                    error_message: String::new(),
+                                    revision: self.revisions.get(&tracked),
+                                }))
+                            }
+                            Err(e) => {
+                                error!("Failed to set value for key {}: {:?}", tracked, e);
+                                self.diagnostics.record_error(
+                                    "SetValue",
+                                    &tracked,
+                                    format!("{:?}", e),
+                                );
+                                rejected(
+                                    Code::Internal,
+                                    SetValueResponse {
+                                        success: false,
+                                        error_message: format!("Failed to set value: {:?}", e),
+                                        revision: 0,
+                                    },
+                                )
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to convert protobuf value: {}", e);
+                        self.diagnostics
+                            .record_error("SetValue", &tracked, e.clone());
+                        rejected(
+                            Code::InvalidArgument,
+                            SetValueResponse {
+                                success: false,
+                                error_message: format!("Value conversion error: {}", e),
+                                revision: 0,
+                            },
+                        )
+                    }
+                }
+            }
+            None => {
+                error!("SetValue request missing value for key: {}", tracked);
+                rejected(
+                    Code::InvalidArgument,
+                    SetValueResponse {
+                        success: false,
+                        error_message: "Missing value in request".to_string(),
+                        revision: 0,
+                    },
+                )
+            }
+        }
+    }
+
+    async fn get_value(
+        &self,
+        request: Request<GetValueRequest>,
+    ) -> Result<Response<GetValueResponse>, Status> {
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!("GetValue request for key: {}", tracked);
+        timing::note_target(&tracked);
+
+        self.inject_read_latency().await;
+        let kvs = store.read().await;
+        // Taken before the value, so that a concurrent write makes a later
+        // SetValue with this revision conflict instead of losing the write
+        let revision = self.revisions.get(&tracked);
+
+        // Values of encrypted keys are not cached, see [`cache`]
+        let cacheable = self.read_cache.is_enabled() && !self.encryption.covers(&tracked);
+        let ticket = self.read_cache.ticket(&tracked);
+        let value = match cacheable.then(|| self.read_cache.get(&tracked)).flatten() {
+            Some(value) if self.read_cache.shadow_read() => {
+                // Writers of the key hold its lock until they invalidated it
+                let _key = store.lock_key(&req.key).await;
+                match self.read_value(store, &**kvs, &req.key) {
+                    Ok(stored) => Ok(Some(Self::kvs_value_to_proto(&stored))),
+                    Err(ErrorCode::KeyNotFound) => Ok(None),
+                    Err(e) => Err(e),
+                }
+                .and_then(|stored| {
+                    self.read_cache
+                        .verify(&tracked, value, stored, ticket)
+                        .ok_or(ErrorCode::KeyNotFound)
+                })
+            }
+            Some(value) => Ok(value),
+            None => self.read_value(store, &**kvs, &req.key).map(|rust_value| {
+                let proto_value = Self::kvs_value_to_proto(&rust_value);
+                if cacheable {
+                    self.read_cache
+                        .insert(&tracked, proto_value.clone(), ticket);
+                }
+                proto_value
+            }),
+        };
+        match value {
+            Ok(proto_value) => {
+                debug!("Successfully retrieved value for key: {}", tracked);
+                self.diagnostics.record_access(&tracked);
+                self.usage
+                    .record_read(&tracked, forecast::entry_size(&req.key, &proto_value));
+                Ok(Response::new(GetValueResponse {
+                    success: true,
+                    value: Some(proto_value),
+                    error_message: String::new(),
+                    revision,
+                }))
+            }
+            Err(e) => {
+                warn!("Failed to get value for key {}: {:?}", tracked, e);
+                rejected(
+                    key_error_code(&e),
+                    GetValueResponse {
+                        success: false,
+                        value: None,
+                        error_message: format!("Key not found: {:?}", e),
+                        revision: 0,
+                    },
+                )
+            }
+        }
+    }
+
+    async fn remove_key(
+        &self,
+        request: Request<RemoveKeyRequest>,
+    ) -> Result<Response<RemoveKeyResponse>, Status> {
+        self.access.check_write(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let mirrored = self
+            .mirror
+            .sample_write(|| MirroredWrite::RemoveKey(request.get_ref().clone()));
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!("RemoveKey request for key: {}", tracked);
+        timing::note_target(&tracked);
+
+        let trash = match req.trash.then(|| self.trash_for(store)).transpose() {
+            Ok(trash) => trash,
+            Err((code, message)) => {
+                return rejected(
+                    code,
+                    RemoveKeyResponse {
+                        success: false,
+                        error_message: message,
+                    },
+                );
+            }
+        };
+
+        let kvs = store.read().await;
+        let _key = store.lock_key(&req.key).await;
+
+        if let Err((code, e)) = self.check_lock_write(store, &req.key, 0) {
+            warn!("RemoveKey of {} rejected: {}", tracked, e);
+            return rejected(
+                code,
+                RemoveKeyResponse {
+                    success: false,
+                    error_message: e,
+                },
+            );
+        }
+
+        let trashed = match trash {
+            Some(trash) => self.move_to_trash(store, &**kvs, trash, &req.key).await,
+            None => Ok(()),
+        };
+        match trashed.and_then(|_| self.remove_tracked("RemoveKey", store, &**kvs, &req.key)) {
+            Ok(_) => {
+                debug!("Successfully removed key: {}", tracked);
+                self.mirror.commit(mirrored);
+                Ok(Response::new(RemoveKeyResponse {
+                    success: true,
+                    error_message: String::new(),
+                }))
+            }
+            Err(e) => {
+                error!("Failed to remove key {}: {:?}", tracked, e);
+                self.diagnostics
+                    .record_error("RemoveKey", &tracked, format!("{:?}", e));
+                rejected(
+                    key_error_code(&e),
+                    RemoveKeyResponse {
+                        success: false,
+                        error_message: format!("Failed to remove key: {:?}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn set_values(
+        &self,
+        request: Request<SetValuesRequest>,
+    ) -> Result<Response<SetValuesResponse>, Status> {
+        for entry in &request.get_ref().entries {
+            self.access.check_write(
+                request.extensions(),
+                &request.get_ref().namespace,
+                &entry.key,
+            )?;
+        }
+        let mirrored = self
+            .mirror
+            .sample_write(|| MirroredWrite::SetValues(request.get_ref().clone()));
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        debug!("SetValues request for {} keys", req.entries.len());
+
+        // Reject the whole batch before writing anything if a value is unusable
+        let mut entries = Vec::with_capacity(req.entries.len());
+        for entry in req.entries {
+            let converted = entry
+                .value
+                .as_ref()
+                .ok_or_else(|| "Missing value in request".to_string())
+                .and_then(Self::client_value);
+            match converted {
+                Ok(rust_value) => {
+                    entries.push((entry.key, entry.value.unwrap_or_default(), rust_value))
+                }
+                Err(e) => {
+                    let tracked = store.tracked_key(&entry.key);
+                    error!(
+                        "SetValues request has an invalid value for key {}: {}",
+                        tracked, e
+                    );
+                    self.diagnostics
+                        .record_error("SetValues", &tracked, e.clone());
+                    return rejected(
+                        Code::InvalidArgument,
+                        SetValuesResponse {
+                            success: false,
+                            written_keys: 0,
+                            error_message: format!(
+                                "Value conversion error for key {}: {}",
+                                entry.key, e
+                            ),
+                        },
+                    );
+                }
+            }
+        }
+        if entries.is_empty() {
+            return Ok(Response::new(SetValuesResponse {
+                success: true,
+                written_keys: 0,
+                error_message: String::new(),
+            }));
+        }
+
+        // Hold off other requests, so that they never see part of the batch
+        let kvs = store.write().await;
+        let values: Vec<(&str, &KvsValue)> = entries
+            .iter()
+            .map(|(key, proto_value, _)| (key.as_str(), proto_value))
+            .collect();
+        let checked = values
+            .iter()
+            .try_for_each(|(key, _)| self.check_lock_write(store, key, 0))
+            .and_then(|_| self.check_quotas(store, &**kvs, &values));
+        if let Err((code, e)) = checked {
+            warn!("SetValues batch of {} keys rejected: {}", entries.len(), e);
+            self.diagnostics
+                .record_error("SetValues", store.name(), e.clone());
+            return rejected(
+                code,
+                SetValuesResponse {
+                    success: false,
+                    written_keys: 0,
+                    error_message: e,
+                },
+            );
+        }
+        let mut written_keys = 0;
+        let mut error_message = String::new();
+        for (key, proto_value, rust_value) in entries {
+            let tracked = store.tracked_key(&key);
+            let old_size = Self::stored_size(&**kvs, store.spill(), &key);
+            let previous = self.previous_for_watchers(store, &**kvs, &key);
+            if let Err(e) = self.write_value(store, &**kvs, &key, rust_value) {
+                error!("Failed to set value for key {} of batch: {:?}", tracked, e);
+                self.diagnostics
+                    .record_error("SetValues", &tracked, format!("{:?}", e));
+                error_message = format!("Failed to set value for key {}: {:?}", key, e);
+                break;
+            }
+            let new_size = forecast::entry_size(&key, &proto_value);
+            self.forecast.record_write(&tracked, old_size, new_size);
+            self.usage.record_write(&tracked, new_size);
+            self.track_put(store, &key);
+            store.pending().mark(&key);
+            self.watch
+                .publish(WatchEventKind::Put, &tracked, Some(proto_value), previous);
+            self.diagnostics.record_audit("SetValues", &tracked);
+            self.diagnostics.record_access(&tracked);
+            written_keys += 1;
+        }
+
+        if written_keys > 0 {
+            if let Err(e) = self.flush_store(&**kvs) {
+                warn!(
+                    "Failed to flush after setting {} keys: {:?}",
+                    written_keys, e
+                );
+                self.diagnostics
+                    .record_error("Flush", "", format!("{:?}", e));
+            } else {
+                store.pending().clear();
+                debug!(
+                    "Flushed data to storage files after setting {} keys",
+                    written_keys
+                );
+            }
+        }
+
+        let response = SetValuesResponse {
+            success: error_message.is_empty(),
+            written_keys,
+            error_message,
+        };
+        if response.success {
+            self.mirror.commit(mirrored);
+            Ok(Response::new(response))
+        } else {
+            Ok(failed(response))
+        }
+    }
+
+    async fn get_values(
+        &self,
+        request: Request<GetValuesRequest>,
+    ) -> Result<Response<GetValuesResponse>, Status> {
+        for key in &request.get_ref().keys {
+            self.access
+                .check_read(request.extensions(), &request.get_ref().namespace, key)?;
+        }
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        debug!("GetValues request for {} keys", req.keys.len());
+
+        self.inject_read_latency().await;
+        let kvs = store.read().await;
+        let mut key_values = HashMap::new();
+        let mut missing_keys = Vec::new();
+        for key in req.keys {
+            match self.read_value(store, &**kvs, &key) {
+                Ok(rust_value) => {
+                    let proto_value = Self::kvs_value_to_proto(&rust_value);
+                    let tracked = store.tracked_key(&key);
+                    self.diagnostics.record_access(&tracked);
+                    self.usage
+                        .record_read(&tracked, forecast::entry_size(&key, &proto_value));
+                    key_values.insert(key, proto_value);
+                }
+                Err(ErrorCode::KeyNotFound) => missing_keys.push(key),
+                Err(e) => {
+                    error!("Failed to get value for key {} of batch: {:?}", key, e);
+                    return rejected(
+                        Code::Internal,
+                        GetValuesResponse {
+                            success: false,
+                            error_message: format!("Failed to get value for key {}: {:?}", key, e),
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
+        }
+
+        debug!(
+            "Retrieved {} values, {} keys missing",
+            key_values.len(),
+            missing_keys.len()
+        );
+        Ok(Response::new(GetValuesResponse {
+            success: true,
+            key_values,
+            missing_keys,
+            error_message: String::new(),
+        }))
+    }
+
+    async fn remove_keys(
+        &self,
+        request: Request<RemoveKeysRequest>,
+    ) -> Result<Response<RemoveKeysResponse>, Status> {
+        for key in &request.get_ref().keys {
+            self.access
+                .check_write(request.extensions(), &request.get_ref().namespace, key)?;
+        }
+        let mirrored = self
+            .mirror
+            .sample_write(|| MirroredWrite::RemoveKeys(request.get_ref().clone()));
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        debug!("RemoveKeys request for {} keys", req.keys.len());
+
+        // Hold off other requests, so that they never see part of the batch
+        let kvs = store.write().await;
+        if let Err((_, e)) = req
+            .keys
+            .iter()
+            .try_for_each(|key| self.check_lock_write(store, key, 0))
+        {
+            warn!(
+                "RemoveKeys batch of {} keys rejected: {}",
+                req.keys.len(),
+                e
+            );
+            return Ok(failed(RemoveKeysResponse {
+                success: false,
+                error_message: e,
+                ..Default::default()
+            }));
+        }
+        let mut removed_keys = 0;
+        let mut missing_keys = Vec::new();
+        let mut error_message = String::new();
+        for key in req.keys {
+            let tracked = store.tracked_key(&key).into_owned();
+            let old_size = Self::stored_size(&**kvs, store.spill(), &key);
+            let previous = self.previous_for_watchers(store, &**kvs, &key);
+            match self.delete_value(store, &**kvs, &key) {
+                Ok(_) => {
+                    if let Some(old_size) = old_size {
+                        self.forecast.record_remove(&tracked, old_size);
+                    }
+                    self.usage.record_remove(&tracked);
+                    self.watch
+                        .publish(WatchEventKind::Delete, &tracked, None, previous);
+                    store.pending().mark(&key);
+                    self.diagnostics.record_audit("RemoveKeys", &tracked);
+                    self.diagnostics.forget_key(&tracked);
+                    self.track_remove(store, &key);
+                    removed_keys += 1;
+                }
+                Err(ErrorCode::KeyNotFound) => missing_keys.push(key),
+                Err(e) => {
+                    error!("Failed to remove key {} of batch: {:?}", tracked, e);
+                    self.diagnostics
+                        .record_error("RemoveKeys", &tracked, format!("{:?}", e));
+                    error_message = format!("Failed to remove key {}: {:?}", key, e);
+                    break;
+                }
+            }
+        }
+
+        if removed_keys > 0 {
+            if let Err(e) = self.flush_store(&**kvs) {
+                warn!(
+                    "Failed to flush after removing {} keys: {:?}",
+                    removed_keys, e
+                );
+                self.diagnostics
+                    .record_error("Flush", "", format!("{:?}", e));
+            } else {
+                store.pending().clear();
+            }
+        }
+
+        let response = RemoveKeysResponse {
+            success: error_message.is_empty(),
+            removed_keys,
+            missing_keys,
+            error_message,
+        };
+        if response.success {
+            self.mirror.commit(mirrored);
+            Ok(Response::new(response))
+        } else {
+            Ok(failed(response))
+        }
+    }
+
+    async fn compare_and_set(
+        &self,
+        request: Request<CompareAndSetRequest>,
+    ) -> Result<Response<CompareAndSetResponse>, Status> {
+        self.access.check_write(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let mirrored = self
+            .mirror
+            .sample_write(|| MirroredWrite::CompareAndSet(request.get_ref().clone()));
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!("CompareAndSet request for key: {}", tracked);
+        timing::note_target(&tracked);
+
+        // Compare in the stored representation, so that e.g. an i32 matches the i32 it was stored as
+        let expected = match req
+            .expected_value
+            .as_ref()
+            .map(Self::proto_to_kvs_value)
+            .transpose()
+        {
+            Ok(expected) => expected.map(|value| Self::kvs_value_to_proto(&value)),
+            Err(e) => {
+                error!(
+                    "CompareAndSet request has an invalid expected value for key {}: {}",
+                    tracked, e
+                );
+                return rejected(
+                    Code::InvalidArgument,
+                    CompareAndSetResponse {
+                        success: false,
+                        error_message: format!("Expected value conversion error: {}", e),
+                        ..Default::default()
+                    },
+                );
+            }
+        };
+        let (proto_value, rust_value) = match req.new_value {
+            Some(proto_value) => match Self::client_value(&proto_value) {
+                Ok(rust_value) => (proto_value, rust_value),
+                Err(e) => {
+                    error!(
+                        "CompareAndSet request has an invalid value for key {}: {}",
+                        tracked, e
+                    );
+                    self.diagnostics
+                        .record_error("CompareAndSet", &tracked, e.clone());
+                    return rejected(
+                        Code::InvalidArgument,
+                        CompareAndSetResponse {
+                            success: false,
+                            error_message: format!("Value conversion error: {}", e),
+                            ..Default::default()
+                        },
+                    );
+                }
+            },
+            None => {
+                return rejected(
+                    Code::InvalidArgument,
+                    CompareAndSetResponse {
+                        success: false,
+                        error_message: "Missing new value in request".to_string(),
+                        ..Default::default()
+                    },
+                );
+            }
+        };
+
+        // Hold off other writers of the key between the comparison and the write
+        let kvs = store.read().await;
+        let _key = store.lock_key(&req.key).await;
+        if let Err((code, e)) = self.check_lock_write(store, &req.key, 0) {
+            warn!("CompareAndSet of {} rejected: {}", tracked, e);
+            return rejected(
+                code,
+                CompareAndSetResponse {
+                    success: false,
+                    error_message: e,
+                    ..Default::default()
+                },
+            );
+        }
+        let current = match self.read_value(store, &**kvs, &req.key) {
+            Ok(value) => Some(Self::kvs_value_to_proto(&value)),
+            Err(ErrorCode::KeyNotFound) => None,
+            Err(e) => {
+                error!("Failed to read key {} for CompareAndSet: {:?}", tracked, e);
+                return rejected(
+                    Code::Internal,
+                    CompareAndSetResponse {
+                        success: false,
+                        error_message: format!("Failed to read value: {:?}", e),
+                        ..Default::default()
+                    },
+                );
+            }
+        };
+        if current != expected {
+            debug!(
+                "CompareAndSet of key {} not applied, the value changed",
+                tracked
+            );
+            return Ok(Response::new(CompareAndSetResponse {
+                success: true,
+                swapped: false,
+                current_value: current,
+                error_message: String::new(),
+            }));
+        }
+
+        if let Err((code, e)) = self.check_quotas(store, &**kvs, &[(&req.key, &proto_value)]) {
+            warn!("CompareAndSet of {} rejected: {}", tracked, e);
+            self.diagnostics
+                .record_error("CompareAndSet", &tracked, e.clone());
+            return rejected(
+                code,
+                CompareAndSetResponse {
+                    success: false,
+                    error_message: e,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let old_size = Self::stored_size(&**kvs, store.spill(), &req.key);
+        let previous = self.previous_for_watchers(store, &**kvs, &req.key);
+        if let Err(e) = self.write_value(store, &**kvs, &req.key, rust_value) {
+            error!("Failed to set value for key {}: {:?}", tracked, e);
+            self.diagnostics
+                .record_error("CompareAndSet", &tracked, format!("{:?}", e));
+            return rejected(
+                Code::Internal,
+                CompareAndSetResponse {
+                    success: false,
+                    error_message: format!("Failed to set value: {:?}", e),
+                    ..Default::default()
+                },
+            );
+        }
+        let new_size = forecast::entry_size(&req.key, &proto_value);
+        self.forecast.record_write(&tracked, old_size, new_size);
+        self.usage.record_write(&tracked, new_size);
+        self.track_put(store, &req.key);
+        self.watch
+            .publish(WatchEventKind::Put, &tracked, Some(proto_value), previous);
+        self.diagnostics.record_audit("CompareAndSet", &tracked);
+        self.diagnostics.record_access(&tracked);
+
+        store.pending().mark(&req.key);
+        if let Err(e) = self.flush_pending_keys(store, &**kvs) {
+            warn!("Failed to flush after setting key {}: {:?}", tracked, e);
+            self.diagnostics
+                .record_error("Flush", &tracked, format!("{:?}", e));
+        }
+        self.mirror.commit(mirrored);
+
+        Ok(Response::new(CompareAndSetResponse {
+            success: true,
+            swapped: true,
+            current_value: None,
+            error_message: String::new(),
+        }))
+    }
+
+    async fn increment(
+        &self,
+        request: Request<IncrementRequest>,
+    ) -> Result<Response<IncrementResponse>, Status> {
+        self.access.check_write(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let mirrored = self
+            .mirror
+            .sample_write(|| MirroredWrite::Increment(request.get_ref().clone()));
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        debug!("Increment request for key: {}", store.tracked_key(&req.key));
+
+        match self
+            .add_to_counter("Increment", store, &req.key, req.delta, false)
+            .await
+        {
+            Ok(value) => {
+                self.mirror.commit(mirrored);
+                Ok(Response::new(IncrementResponse {
+                    success: true,
+                    value: Some(value),
+                    error_message: String::new(),
+                }))
+            }
+            Err((code, e)) => {
+                warn!(
+                    "Failed to increment key {}: {}",
+                    store.tracked_key(&req.key),
+                    e
+                );
+                rejected(
+                    code,
+                    IncrementResponse {
+                        success: false,
+                        value: None,
+                        error_message: format!("Failed to increment: {}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn decrement(
+        &self,
+        request: Request<DecrementRequest>,
+    ) -> Result<Response<DecrementResponse>, Status> {
+        self.access.check_write(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let mirrored = self
+            .mirror
+            .sample_write(|| MirroredWrite::Decrement(request.get_ref().clone()));
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        debug!("Decrement request for key: {}", store.tracked_key(&req.key));
+
+        match self
+            .add_to_counter("Decrement", store, &req.key, req.delta, true)
+            .await
+        {
+            Ok(value) => {
+                self.mirror.commit(mirrored);
+                Ok(Response::new(DecrementResponse {
+                    success: true,
+                    value: Some(value),
+                    error_message: String::new(),
+                }))
+            }
+            Err((code, e)) => {
+                warn!(
+                    "Failed to decrement key {}: {}",
+                    store.tracked_key(&req.key),
+                    e
+                );
+                rejected(
+                    code,
+                    DecrementResponse {
+                        success: false,
+                        value: None,
+                        error_message: format!("Failed to decrement: {}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn get_history(
+        &self,
+        request: Request<GetHistoryRequest>,
+    ) -> Result<Response<GetHistoryResponse>, Status> {
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!("GetHistory request for key: {}", tracked);
+
+        if let Some(message) = self.key_history_unavailable(&req.key) {
+            return rejected(
+                Code::FailedPrecondition,
+                GetHistoryResponse {
+                    success: false,
+                    versions: Vec::new(),
+                    error_message: message,
+                },
+            );
+        }
+
+        let kvs = store.read().await;
+        let mut versions = self.key_history.versions(&tracked);
+        if versions.is_empty() {
+            match self.current_version(store, &**kvs, &req.key) {
+                Ok(Some(current)) => versions.push(current),
+                Ok(None) => {
+                    return rejected(
+                        Code::NotFound,
+                        GetHistoryResponse {
+                            success: false,
+                            versions: Vec::new(),
+                            error_message: format!("Key not found: {}", req.key),
+                        },
+                    );
+                }
+                Err(e) => {
+                    error!("Failed to read key {}: {:?}", tracked, e);
+                    return rejected(
+                        Code::Internal,
+                        GetHistoryResponse {
+                            success: false,
+                            versions: Vec::new(),
+                            error_message: format!("Failed to read key: {:?}", e),
+                        },
+                    );
+                }
+            }
+        }
+        Ok(Response::new(GetHistoryResponse {
+            success: true,
+            versions: versions
+                .iter()
+                .map(|version| self.key_version_to_proto(&tracked, version))
+                .collect(),
+            error_message: String::new(),
+        }))
+    }
+
+    async fn get_value_at_revision(
+        &self,
+        request: Request<GetValueAtRevisionRequest>,
+    ) -> Result<Response<GetValueAtRevisionResponse>, Status> {
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!(
+            "GetValueAtRevision request for key {} at revision {}",
+            tracked, req.revision
+        );
+
+        if let Some(message) = self.key_history_unavailable(&req.key) {
+            return rejected(
+                Code::FailedPrecondition,
+                GetValueAtRevisionResponse {
+                    success: false,
+                    version: None,
+                    error_message: message,
+                },
+            );
+        }
+
+        let kvs = store.read().await;
+        let version = match self.key_history.at_revision(&tracked, req.revision) {
+            Some(version) => Some(version),
+            None => match self.current_version(store, &**kvs, &req.key) {
+                Ok(current) => current.filter(|current| current.revision <= req.revision),
+                Err(e) => {
+                    error!("Failed to read key {}: {:?}", tracked, e);
+                    return rejected(
+                        Code::Internal,
+                        GetValueAtRevisionResponse {
+                            success: false,
+                            version: None,
+                            error_message: format!("Failed to read key: {:?}", e),
+                        },
+                    );
+                }
+            },
+        };
+        match version {
+            Some(version) if version.value.is_some() => {
+                Ok(Response::new(GetValueAtRevisionResponse {
+                    success: true,
+                    version: Some(self.key_version_to_proto(&tracked, &version)),
+                    error_message: String::new(),
+                }))
+            }
+            Some(version) => rejected(
+                Code::NotFound,
+                GetValueAtRevisionResponse {
+                    success: false,
+                    version: Some(self.key_version_to_proto(&tracked, &version)),
+                    error_message: format!(
+                        "Key {} was removed at revision {}",
+                        req.key, version.revision
+                    ),
+                },
+            ),
+            None => rejected(
+                Code::NotFound,
+                GetValueAtRevisionResponse {
+                    success: false,
+                    version: None,
+                    error_message: format!(
+                        "Revision {} of key {} is not kept",
+                        req.revision, req.key
+                    ),
+                },
+            ),
+        }
+    }
+
+    async fn list_deleted(
+        &self,
+        request: Request<ListDeletedRequest>,
+    ) -> Result<Response<ListDeletedResponse>, Status> {
+        let req = request.get_ref();
+        let store = self.store(&req.namespace)?;
+        debug!(
+            "ListDeleted request for prefix '{}' of namespace '{}'",
+            req.prefix, req.namespace
+        );
+
+        let trash = match self.trash_for(store) {
+            Ok(trash) => trash,
+            Err((code, message)) => {
+                return rejected(
+                    code,
+                    ListDeletedResponse {
+                        success: false,
+                        keys: Vec::new(),
+                        error_message: message,
+                    },
+                );
+            }
+        };
+
+        let kvs = trash.read().await;
+        let trashed = match self.all_keys(trash, &**kvs) {
+            Ok(trashed) => trashed,
+            Err(e) => {
+                error!("Failed to list the trash: {:?}", e);
+                return rejected(
+                    Code::Internal,
+                    ListDeletedResponse {
+                        success: false,
+                        keys: Vec::new(),
+                        error_message: format!("Failed to get keys: {:?}", e),
+                    },
+                );
+            }
+        };
+        let now_ms = self.clock.now_ms();
+        let mut keys = Vec::new();
+        for tracked in &trashed {
+            let (namespace, key) = namespace::split_tracked(tracked);
+            if namespace != req.namespace
+                || !key.starts_with(req.prefix.as_str())
+                || !self.access.can_read(request.extensions(), namespace, key)
+            {
+                continue;
+            }
+            // Keys written to the trash namespace directly are no trashed keys
+            let Some(entry) = self
+                .read_value(trash, &**kvs, tracked)
+                .ok()
+                .and_then(TrashedValue::from_entry)
+            else {
+                continue;
+            };
+            keys.push(DeletedKey {
+                namespace: namespace.to_string(),
+                key: key.to_string(),
+                deleted_at_ms: entry.deleted_at_ms,
+                expires_at_ms: self
+                    .expiry
+                    .remaining(&trash.tracked_key(tracked))
+                    .map_or(0, |remaining| {
+                        now_ms.saturating_add(remaining.as_millis() as u64)
+                    }),
+            });
+        }
+        keys.sort_by(|a, b| a.key.cmp(&b.key));
+        debug!("Listed {} deleted keys", keys.len());
+        Ok(Response::new(ListDeletedResponse {
+            success: true,
+            keys: keys.iter().map(DeletedKey::to_proto).collect(),
+            error_message: String::new(),
+        }))
+    }
+
+    async fn restore_key(
+        &self,
+        request: Request<RestoreKeyRequest>,
+    ) -> Result<Response<RestoreKeyResponse>, Status> {
+        self.access.check_write(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!("RestoreKey request for key: {}", tracked);
+
+        let trash = match self.trash_for(store) {
+            Ok(trash) => trash,
+            Err((code, message)) => {
+                return rejected(
+                    code,
+                    RestoreKeyResponse {
+                        success: false,
+                        revision: 0,
+                        error_message: message,
+                    },
+                );
+            }
+        };
+
+        // Same lock order as RemoveKey, the store of the key before the trash
+        let kvs = store.read().await;
+        let _key = store.lock_key(&req.key).await;
+        let trash_kvs = trash.read().await;
+        let _trash_key = trash.lock_key(&tracked).await;
+
+        match self.value_exists(store, &**kvs, &req.key) {
+            Ok(false) => {}
+            Ok(true) => {
+                return rejected(
+                    Code::AlreadyExists,
+                    RestoreKeyResponse {
+                        success: false,
+                        revision: 0,
+                        error_message: format!(
+                            "Key {} was written again since its removal",
+                            req.key
+                        ),
+                    },
+                );
+            }
+            Err(e) => {
+                error!("Failed to check if key {} exists: {:?}", tracked, e);
+                return rejected(
+                    Code::Internal,
+                    RestoreKeyResponse {
+                        success: false,
+                        revision: 0,
+                        error_message: format!("Failed to check key existence: {:?}", e),
+                    },
+                );
+            }
+        }
+        let trashed = match self.read_value(trash, &**trash_kvs, &tracked) {
+            Ok(entry) => TrashedValue::from_entry(entry),
+            Err(ErrorCode::KeyNotFound) => None,
+            Err(e) => {
+                error!("Failed to read key {} from the trash: {:?}", tracked, e);
+                return rejected(
+                    Code::Internal,
+                    RestoreKeyResponse {
+                        success: false,
+                        revision: 0,
+                        error_message: format!("Failed to read trash: {:?}", e),
+                    },
+                );
+            }
+        };
+        let Some(trashed) = trashed else {
+            return rejected(
+                Code::NotFound,
+                RestoreKeyResponse {
+                    success: false,
+                    revision: 0,
+                    error_message: format!("Key {} is not in the trash", req.key),
+                },
+            );
+        };
+
+        match self.put_tracked("RestoreKey", store, &**kvs, &req.key, trashed.value) {
+            Ok(revision) => {
+                // The key is back in any case, a leftover copy expires with the retention
+                if let Err(e) = self.remove_tracked("RestoreKey", trash, &**trash_kvs, &tracked) {
+                    warn!(
+                        "Failed to remove restored key {} from the trash: {:?}",
+                        tracked, e
+                    );
+                }
+                debug!("Successfully restored key: {}", tracked);
+                Ok(Response::new(RestoreKeyResponse {
+                    success: true,
+                    revision,
+                    error_message: String::new(),
+                }))
+            }
+            Err(e) => {
+                error!("Failed to restore key {}: {:?}", tracked, e);
+                self.diagnostics
+                    .record_error("RestoreKey", &tracked, format!("{:?}", e));
+                rejected(
+                    Code::Internal,
+                    RestoreKeyResponse {
+                        success: false,
+                        revision: 0,
+                        error_message: format!("Failed to restore key: {:?}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn grant_lease(
+        &self,
+        request: Request<GrantLeaseRequest>,
+    ) -> Result<Response<GrantLeaseResponse>, Status> {
+        let owner = AccessPolicy::component(request.extensions()).map(str::to_string);
+        self.access
+            .check_lease(request.extensions(), owner.as_deref())?;
+        let req = request.into_inner();
+        debug!("GrantLease request for {}s", req.ttl_seconds);
+
+        let failure = |code, error_message| {
+            rejected(
+                code,
+                GrantLeaseResponse {
+                    success: false,
+                    lease_id: 0,
+                    ttl_seconds: 0,
+                    error_message,
+                },
+            )
+        };
+        if !self.leases.is_enabled() {
+            return failure(Code::FailedPrecondition, "Leases are disabled".to_string());
+        }
+        if let Err(e) = self.leases.check_ttl(req.ttl_seconds as u64) {
+            return failure(Code::InvalidArgument, e);
+        }
+        match self.leases.grant(req.ttl_seconds as u64, owner.as_deref()) {
+            Ok(lease_id) => {
+                info!(
+                    "Granted lease {} for {}s to {}",
+                    lease_id,
+                    req.ttl_seconds,
+                    owner.as_deref().unwrap_or("unauthenticated caller")
+                );
+                Ok(Response::new(GrantLeaseResponse {
+                    success: true,
+                    lease_id,
+                    ttl_seconds: req.ttl_seconds,
+                    error_message: String::new(),
+                }))
+            }
+            Err(e) => {
+                warn!("GrantLease rejected: {}", e);
+                failure(Code::ResourceExhausted, e)
+            }
+        }
+    }
+
+    async fn keep_alive(
+        &self,
+        request: Request<Streaming<KeepAliveRequest>>,
+    ) -> Result<Response<Self::KeepAliveStream>, Status> {
+        self.access.check_streaming(request.extensions())?;
+        let caller = AccessPolicy::component(request.extensions()).map(str::to_string);
+        let mut requests = request.into_inner();
+        debug!("KeepAlive stream opened");
+
+        let leases = self.leases.clone();
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                let next = tokio::select! {
+                    // The client cancelled the call
+                    _ = tx.closed() => break,
+                    next = requests.message() => next,
+                };
+                let req = match next {
+                    Ok(Some(req)) => req,
+                    Ok(None) => break,
+                    Err(e) => {
+                        debug!("KeepAlive stream failed: {}", e);
+                        break;
+                    }
+                };
+                let owner = leases.get(req.lease_id).map(|lease| lease.owner);
+                if let Some(owner) = owner {
+                    if let Err(status) =
+                        AccessPolicy::check_lease_holder(caller.as_deref(), owner.as_deref())
+                    {
+                        let _ = tx.send(Err(status)).await;
+                        break;
+                    }
+                }
+                // An expired or unknown lease is answered with no time left
+                let ttl_seconds = leases.renew(req.lease_id).unwrap_or(0);
+                if tx
+                    .send(Ok(KeepAliveResponse {
+                        lease_id: req.lease_id,
+                        ttl_seconds: ttl_seconds as u32,
+                    }))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            debug!("KeepAlive stream closed");
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn revoke_lease(
+        &self,
+        request: Request<RevokeLeaseRequest>,
+    ) -> Result<Response<RevokeLeaseResponse>, Status> {
+        let owner = self
+            .leases
+            .get(request.get_ref().lease_id)
+            .and_then(|lease| lease.owner);
+        self.access
+            .check_lease(request.extensions(), owner.as_deref())?;
+        let req = request.into_inner();
+        debug!("RevokeLease request for lease {}", req.lease_id);
+
+        if !self.leases.is_enabled() {
+            return rejected(
+                Code::FailedPrecondition,
+                RevokeLeaseResponse {
+                    success: false,
+                    keys: 0,
+                    error_message: "Leases are disabled".to_string(),
+                },
+            );
+        }
+        let Some(keys) = self.leases.revoke(req.lease_id) else {
+            return rejected(
+                Code::NotFound,
+                RevokeLeaseResponse {
+                    success: false,
+                    keys: 0,
+                    error_message: format!("Lease {} not found", req.lease_id),
+                },
+            );
+        };
+        // Removed like expired keys, so that watchers and the audit see the removals
+        for tracked in &keys {
+            self.expiry.set(tracked, Duration::ZERO);
+        }
+        self.expire_keys().await;
+        info!("Revoked lease {} with {} keys", req.lease_id, keys.len());
+        Ok(Response::new(RevokeLeaseResponse {
+            success: true,
+            keys: keys.len() as u32,
+            error_message: String::new(),
+        }))
+    }
+
+    async fn lock(&self, request: Request<LockRequest>) -> Result<Response<LockResponse>, Status> {
+        let failure = |code, error_message| {
+            rejected(
+                code,
+                LockResponse {
+                    success: false,
+                    acquired: false,
+                    token: 0,
+                    holder: String::new(),
+                    error_message,
+                },
+            )
+        };
+        let req = request.get_ref();
+        let key = match lock::lock_key(&self.config.leases.lock_prefix, &req.name) {
+            Ok(key) => key,
+            Err(e) => return failure(Code::InvalidArgument, e),
+        };
+        self.access.check_write(request.extensions(), "", &key)?;
+        let owner = AccessPolicy::component(request.extensions()).map(str::to_string);
+        debug!("Lock request for {}", req.name);
+
+        if !self.leases.is_enabled() {
+            return failure(Code::FailedPrecondition, "Leases are disabled".to_string());
+        }
+        if let Err(e) = self.leases.check_ttl(req.ttl_seconds as u64) {
+            return failure(Code::InvalidArgument, e);
+        }
+
+        let store = self.namespaces.default_store();
+        let tracked = store.tracked_key(&key).into_owned();
+        let kvs = store.read().await;
+        let _key = store.lock_key(&key).await;
+
+        if self
+            .leases
+            .lease_of(&tracked)
+            .and_then(|id| self.leases.get(id))
+            .is_some()
+        {
+            let holder = match self.read_value(store, &**kvs, &key) {
+                Ok(rust_kvs::kvs_value::KvsValue::String(holder)) => holder,
+                _ => String::new(),
+            };
+            debug!("Lock {} is held by '{}'", req.name, holder);
+            return Ok(Response::new(LockResponse {
+                success: true,
+                acquired: false,
+                token: 0,
+                holder,
+                error_message: String::new(),
+            }));
+        }
+
+        let token = match self.leases.grant(req.ttl_seconds as u64, owner.as_deref()) {
+            Ok(token) => token,
+            Err(e) => {
+                warn!("Lock {} rejected: {}", req.name, e);
+                return failure(Code::ResourceExhausted, e);
+            }
+        };
+        let holder = rust_kvs::kvs_value::KvsValue::String(owner.unwrap_or_default());
+        if let Err(e) = self.put_tracked("Lock", store, &**kvs, &key, holder) {
+            error!("Failed to write lock {}: {:?}", tracked, e);
+            self.leases.revoke(token);
+            return failure(Code::Internal, format!("Failed to write lock: {:?}", e));
+        }
+        self.leases.attach(token, &tracked);
+        info!("Lock {} acquired for {}s", req.name, req.ttl_seconds);
+        Ok(Response::new(LockResponse {
+            success: true,
+            acquired: true,
+            token,
+            holder: String::new(),
+            error_message: String::new(),
+        }))
+    }
+
+    async fn unlock(
+        &self,
+        request: Request<UnlockRequest>,
+    ) -> Result<Response<UnlockResponse>, Status> {
+        let failure = |code, error_message| {
+            rejected(
+                code,
+                UnlockResponse {
+                    success: false,
+                    error_message,
+                },
+            )
+        };
+        let req = request.get_ref();
+        let key = match lock::lock_key(&self.config.leases.lock_prefix, &req.name) {
+            Ok(key) => key,
+            Err(e) => return failure(Code::InvalidArgument, e),
+        };
+        self.access.check_write(request.extensions(), "", &key)?;
+        debug!("Unlock request for {}", req.name);
+
+        if !self.leases.is_enabled() {
+            return failure(Code::FailedPrecondition, "Leases are disabled".to_string());
+        }
+        let tracked = self
+            .namespaces
+            .default_store()
+            .tracked_key(&key)
+            .into_owned();
+        if req.token == 0 || self.leases.lease_of(&tracked) != Some(req.token) {
+            return failure(
+                Code::FailedPrecondition,
+                format!("Lock {} is not held with token {}", req.name, req.token),
+            );
+        }
+        let owner = self.leases.get(req.token).and_then(|lease| lease.owner);
+        self.access
+            .check_lease(request.extensions(), owner.as_deref())?;
+
+        // Keys written with the token go with the lock
+        for tracked in self.leases.revoke(req.token).unwrap_or_default() {
+            self.expiry.set(&tracked, Duration::ZERO);
+        }
+        self.expire_keys().await;
+        info!("Lock {} released", req.name);
+        Ok(Response::new(UnlockResponse {
+            success: true,
+            error_message: String::new(),
+        }))
+    }
+
+    async fn get_all_keys(
+        &self,
+        request: Request<GetAllKeysRequest>,
+    ) -> Result<Response<GetAllKeysResponse>, Status> {
+        let store = self.store(&request.get_ref().namespace)?;
+        debug!("GetAllKeys request");
+
+        self.inject_read_latency().await;
+        let kvs = store.read().await;
+
+        match self.all_keys(store, &**kvs) {
+            Ok(keys) => {
+                let keys: Vec<String> = keys
+                    .into_iter()
+                    .filter(|key| {
+                        self.access.can_read(
+                            request.extensions(),
+                            &request.get_ref().namespace,
+                            key,
+                        )
+                    })
+                    .collect();
+                debug!("Successfully retrieved {} keys", keys.len());
+                Ok(Response::new(GetAllKeysResponse {
+                    success: true,
+                    keys,
+                    error_message: String::new(),
+                }))
+            }
+            Err(e) => {
+                error!("Failed to get all keys: {:?}", e);
+                rejected(
+                    Code::Internal,
+                    GetAllKeysResponse {
+                        success: false,
+                        keys: vec![],
+                        error_message: format!("Failed to get keys: {:?}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn key_exists(
+        &self,
+        request: Request<KeyExistsRequest>,
+    ) -> Result<Response<KeyExistsResponse>, Status> {
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!("KeyExists request for key: {}", tracked);
+        timing::note_target(&tracked);
+
+        self.inject_read_latency().await;
+        let kvs = store.read().await;
+
+        match self.value_exists(store, &**kvs, &req.key) {
+            Ok(exists) => {
+                debug!("Key {} exists: {}", tracked, exists);
+                self.usage.record_read(&tracked, 0);
+                Ok(Response::new(KeyExistsResponse {
+                    success: true,
+                    exists,
+                    error_message: String::new(),
+                }))
+            }
+            Err(e) => {
+                error!("Failed to check if key {} exists: {:?}", tracked, e);
+                rejected(
+                    Code::Internal,
+                    KeyExistsResponse {
+                        success: false,
+                        exists: false,
+                        error_message: format!("Failed to check key existence: {:?}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn get_ttl(
+        &self,
+        request: Request<GetTtlRequest>,
+    ) -> Result<Response<GetTtlResponse>, Status> {
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!("GetTtl request for key: {}", tracked);
+        timing::note_target(&tracked);
+
+        let kvs = store.read().await;
+
+        match self.value_exists(store, &**kvs, &req.key) {
+            Ok(true) => {
+                let remaining = self.expiry.remaining(&tracked);
+                Ok(Response::new(GetTtlResponse {
+                    success: true,
+                    has_ttl: remaining.is_some(),
+                    remaining_ms: remaining.map_or(0, |remaining| remaining.as_millis() as u64),
+                    error_message: String::new(),
+                }))
+            }
+            Ok(false) => rejected(
+                Code::NotFound,
+                GetTtlResponse {
+                    success: false,
+                    has_ttl: false,
+                    remaining_ms: 0,
+                    error_message: format!("Key not found: {}", req.key),
+                },
+            ),
+            Err(e) => {
+                error!("Failed to check if key {} exists: {:?}", tracked, e);
+                rejected(
+                    Code::Internal,
+                    GetTtlResponse {
+                        success: false,
+                        has_ttl: false,
+                        remaining_ms: 0,
+                        error_message: format!("Failed to check key existence: {:?}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn get_all_with_prefix(
+        &self,
+        request: Request<GetAllWithPrefixRequest>,
+    ) -> Result<Response<GetAllWithPrefixResponse>, Status> {
+        let (_, extensions, req) = request.into_parts();
+        let store = self.store(&req.namespace)?;
+        let tracked_prefix = store.tracked_key(&req.prefix);
+        debug!("GetAllWithPrefix request for prefix: {}", tracked_prefix);
+        timing::note_target(&tracked_prefix);
+        let deadline = Deadline::current();
+
+        self.inject_read_latency().await;
+        let kvs = store.read().await;
+
+        // Look up the keys of the prefix in the key index, or filter all keys without one
+        let keys = match store.index() {
+            Some(index) => Ok(index.range(&req.prefix, None, usize::MAX, |key| {
+                self.access.can_read(&extensions, &req.namespace, key)
+            })),
+            None => kvs.get_all_keys(),
+        };
+        match keys {
+            Ok(keys) => {
+                let mut key_values = HashMap::new();
+
+                for key in keys {
+                    // Give up on prefixes too large to read before the client's deadline
+                    if deadline.expired() {
+                        warn!(
+                            "Read of prefix {} aborted after {} keys, deadline exceeded",
+                            tracked_prefix,
+                            key_values.len()
+                        );
+                        return Err(Status::deadline_exceeded(
+                            "Deadline exceeded during GetAllWithPrefix",
+                        ));
+                    }
+                    if key.starts_with(&req.prefix)
+                        && self.access.can_read(&extensions, &req.namespace, &key)
+                    {
+                        match kvs
+                            .get_value(&key)
+                            .and_then(|value| self.open_value(&store.tracked_key(&key), value))
+                        {
+                            Ok(rust_value) => {
+                                let proto_value = Self::kvs_value_to_proto(&rust_value);
+                                key_values.insert(key, proto_value);
+                            }
+                            Err(e) => {
+                                warn!(
+                                    "Failed to get value for key {} during prefix search: {:?}",
+                                    key, e
+                                );
+                            }
+                        }
+                    }
+                }
+
+                // Spilled values are read in place, a prefix scan must not evict the hot keys
+                deadline.check("GetAllWithPrefix")?;
+                if let Some(spill) = store.spill() {
+                    let spilled = spill.for_each_spilled(|key, rust_value| {
+                        if key.starts_with(&req.prefix)
+                            && self.access.can_read(&extensions, &req.namespace, key)
+                        {
+                            match self
+                                .encryption
+                                .open(&store.tracked_key(key), rust_value.clone())
+                            {
+                                Ok(opened) => {
+                                    key_values
+                                        .insert(key.to_string(), Self::kvs_value_to_proto(&opened));
+                                }
+                                Err(e) => warn!(
+                                    "Failed to open spilled value of {} during prefix search: {}",
+                                    key, e
+                                ),
+                            }
+                        }
+                    });
+                    if let Err(e) = spilled {
+                        warn!("Failed to read spilled keys during prefix search: {:?}", e);
+                    }
+                }
+
+                debug!(
+                    "Successfully retrieved {} keys with prefix '{}'",
+                    key_values.len(),
+                    req.prefix
+                );
+                if self.usage.enabled() {
+                    for (key, value) in &key_values {
+                        self.usage
+                            .record_read(&store.tracked_key(key), forecast::entry_size(key, value));
+                    }
+                }
+                Ok(Response::new(GetAllWithPrefixResponse {
+                    success: true,
+                    key_values,
+                    error_message: String::new(),
+                }))
+            }
+            Err(e) => {
+                error!("Failed to get keys for prefix search: {:?}", e);
+                rejected(
+                    Code::Internal,
+                    GetAllWithPrefixResponse {
+                        success: false,
+                        key_values: HashMap::new(),
+                        error_message: format!("Failed to get keys: {:?}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn reset(
+        &self,
+        request: Request<ResetRequest>,
+    ) -> Result<Response<ResetResponse>, Status> {
+        // Whole store
+        self.access
+            .check_write(request.extensions(), &request.get_ref().namespace, "")?;
+        let store = self.store(&request.get_ref().namespace)?;
+        debug!("Reset request for namespace '{}'", store.name());
+
+        if self.delayed.enabled() {
+            let operation = DestructiveOperation::Reset {
+                namespace: store.name().to_string(),
+            };
+            return match self.delayed.schedule(operation) {
+                Ok(pending) => {
+                    warn!(
+                        "Reset of namespace '{}' delayed until {} as operation {}",
+                        store.name(),
+                        pending.execute_at_ms,
+                        pending.id
+                    );
+                    self.diagnostics.record_audit("ScheduleReset", store.name());
+                    Ok(Response::new(ResetResponse {
+                        success: true,
+                        error_message: String::new(),
+                        pending: Some(pending.to_proto()),
+                    }))
+                }
+                Err(e) => {
+                    error!("Failed to journal the reset: {}", e);
+                    self.diagnostics
+                        .record_error("Reset", store.name(), e.to_string());
+                    rejected(
+                        Code::Internal,
+                        ResetResponse {
+                            success: false,
+                            error_message: format!("Failed to journal the reset: {}", e),
+                            pending: None,
+                        },
+                    )
+                }
+            };
+        }
+
+        match self.reset_store(store).await {
+            Ok(_) => Ok(Response::new(ResetResponse {
+                success: true,
+                error_message: String::new(),
+                pending: None,
+            })),
+            Err(e) => {
+                error!("Failed to reset KVS: {:?}", e);
+                self.diagnostics
+                    .record_error("Reset", "", format!("{:?}", e));
+                rejected(
+                    Code::Internal,
+                    ResetResponse {
+                        success: false,
+                        error_message: format!("Failed to reset: {:?}", e),
+                        pending: None,
+                    },
+                )
+            }
+        }
+    }
+
+    async fn flush(
+        &self,
+        request: Request<FlushRequest>,
+    ) -> Result<Response<FlushResponse>, Status> {
+        // Whole store
+        self.access
+            .check_write(request.extensions(), &request.get_ref().namespace, "")?;
+        let store = self.store(&request.get_ref().namespace)?;
+        debug!("Flush request for namespace '{}'", store.name());
+
+        let _busy = self.health.busy();
+        let kvs = store.read().await;
+
+        match self.flush_pending_keys(store, &**kvs) {
+            Ok(_) => {
+                debug!("Successfully flushed KVS");
+                self.diagnostics.record_audit("Flush", store.name());
+                Ok(Response::new(FlushResponse {
+                    success: true,
+                    error_message: String::new(),
+                }))
+            }
+            Err(e) => {
+                error!("Failed to flush KVS: {:?}", e);
+                self.diagnostics
+                    .record_error("Flush", "", format!("{:?}", e));
+                rejected(
+                    Code::Internal,
+                    FlushResponse {
+                        success: false,
+                        error_message: format!("Failed to flush: {:?}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn flush_prefix(
+        &self,
+        request: Request<FlushPrefixRequest>,
+    ) -> Result<Response<FlushPrefixResponse>, Status> {
+        self.access.check_write(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().prefix,
+        )?;
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked_prefix = store.tracked_key(&req.prefix);
+        debug!("FlushPrefix request for prefix: {}", tracked_prefix);
+        timing::note_target(&tracked_prefix);
+
+        let kvs = store.read().await;
+
+        // Changes of other keys are not our concern, skip the write if ours are durable
+        let pending = store.pending().count_with_prefix(&req.prefix);
+        if pending == 0 {
+            debug!("No pending changes with prefix '{}'", req.prefix);
+            return Ok(Response::new(FlushPrefixResponse {
+                success: true,
+                flushed_keys: 0,
+                error_message: String::new(),
+            }));
+        }
+
+        // rust_kvs stores everything in one file, so flushing the prefix flushes the store
+        match self.flush_pending_keys(store, &**kvs) {
+            Ok(_) => {
+                debug!(
+                    "Flushed {} pending changes with prefix '{}'",
+                    pending, tracked_prefix
+                );
+                self.diagnostics
+                    .record_audit("FlushPrefix", &tracked_prefix);
+                Ok(Response::new(FlushPrefixResponse {
+                    success: true,
+                    flushed_keys: pending as u32,
+                    error_message: String::new(),
+                }))
+            }
+            Err(e) => {
+                error!("Failed to flush KVS for prefix {}: {:?}", tracked_prefix, e);
+                self.diagnostics
+                    .record_error("FlushPrefix", &tracked_prefix, format!("{:?}", e));
+                rejected(
+                    Code::Internal,
+                    FlushPrefixResponse {
+                        success: false,
+                        flushed_keys: 0,
+                        error_message: format!("Failed to flush: {:?}", e),
+                    },
+                )
+            }
+        }
+    }
+
+    async fn get_trace_sampling(
+        &self,
+        request: Request<GetTraceSamplingRequest>,
+    ) -> Result<Response<GetTraceSamplingResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        debug!("GetTraceSampling request");
+
+        let config = self.sampler.config();
+        Ok(Response::new(GetTraceSamplingResponse {
+            success: true,
+            config: Some(TraceSamplingConfig {
+                read_rate: config.read_rate,
+                write_rate: config.write_rate,
+                trace_errors: config.trace_errors,
+            }),
+            error_message: String::new(),
+        }))
+    }
+
+    async fn set_trace_sampling(
+        &self,
+        request: Request<SetTraceSamplingRequest>,
+    ) -> Result<Response<SetTraceSamplingResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        let req = request.into_inner();
+        debug!("SetTraceSampling request: {:?}", req.config);
+
+        let Some(proto_config) = req.config else {
+            return rejected(
+                Code::InvalidArgument,
+                SetTraceSamplingResponse {
+                    success: false,
+                    error_message: "Missing config in request".to_string(),
+                },
+            );
+        };
+
+        let config = config::TraceSamplingConfig {
+            read_rate: proto_config.read_rate,
+            write_rate: proto_config.write_rate,
+            trace_errors: proto_config.trace_errors,
+        };
+        match self.sampler.update(&config) {
+            Ok(()) => {
+                info!("Trace sampling updated: {:?}", config);
+                self.diagnostics.record_audit("SetTraceSampling", "");
+                Ok(Response::new(SetTraceSamplingResponse {
+                    success: true,
+                    error_message: String::new(),
+                }))
+            }
+            Err(e) => {
+                warn!("Rejected trace sampling update: {}", e);
+                rejected(
+                    Code::InvalidArgument,
+                    SetTraceSamplingResponse {
+                        success: false,
+                        error_message: e,
+                    },
+                )
+            }
+        }
+    }
+
+    async fn dump_state_for_diagnostics(
+        &self,
+        request: Request<DumpStateForDiagnosticsRequest>,
+    ) -> Result<Response<DumpStateForDiagnosticsResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        let req = request.into_inner();
+        debug!("DumpStateForDiagnostics request: {:?}", req);
+
+        if let Err(retry_in) = self.diagnostics.try_begin_dump() {
+            return Err(Status::resource_exhausted(format!(
+                "Diagnostic dumps are rate-limited, retry in {:.1}s",
+                retry_in.as_secs_f64()
+            )));
+        }
+
+        // The write locks wait for running requests and hold off new ones, so the dump is consistent
+        let mut locked = Vec::new();
+        let mut keys = Vec::new();
+        let mut pending_changes = 0;
+        for store in self.namespaces.iter() {
+            let kvs = store.write().await;
+            match self.all_keys(store, &**kvs) {
+                Ok(store_keys) => keys.extend(
+                    store_keys
+                        .iter()
+                        .map(|key| store.tracked_key(key).into_owned()),
+                ),
+                Err(e) => {
+                    error!(
+                        "Failed to get keys of namespace '{}' for diagnostic dump: {:?}",
+                        store.name(),
+                        e
+                    );
+                    self.diagnostics.record_error(
+                        "DumpStateForDiagnostics",
+                        store.name(),
+                        format!("{:?}", e),
+                    );
+                    return rejected(
+                        Code::Internal,
+                        DumpStateForDiagnosticsResponse {
+                            success: false,
+                            state_json: String::new(),
+                            error_message: format!("Failed to get keys: {:?}", e),
+                        },
+                    );
                 }
             }
+            pending_changes += store.pending().len();
+            locked.push(kvs);
         }
+        let store = StoreState {
+            keys,
+            pending_changes,
+            watch_channels: self.watch.channel_count(),
+        };
+        let dump = self.diagnostics.dump(
+            &store,
+            &self.config,
+            &self.sampler.config(),
+            req.include_keys,
+            req.audit_events as usize,
+        );
+        drop(locked);
+
+        info!("Captured diagnostic dump of {} keys", store.keys.len());
+        Ok(Response::new(DumpStateForDiagnosticsResponse {
+            success: true,
+            state_json: dump.to_string(),
+            error_message: String::new(),
+        }))
     }
 
-    /// Convert protobuf KvsValue to rust_kvs::KvsValue
-    fn proto_to_kvs_value(value: &KvsValue) -> Result<rust_kvs::kvs_value::KvsValue, String> {
-        use common::persistency_proto::kvs_value::Value;
-        use rust_kvs::kvs_value::KvsValue as RustKvsValue;
-        
-        match &value.value {
-            Some(Value::I32Value(v)) => Ok(RustKvsValue::I32(*v)),
-            Some(Value::U32Value(v)) => Ok(RustKvsValue::U32(*v)),
-            Some(Value::I64Value(v)) => Ok(RustKvsValue::I64(*v)),
-            Some(Value::U64Value(v)) => Ok(RustKvsValue::U64(*v)),
-            Some(Value::F64Value(v)) => Ok(RustKvsValue::F64(*v)),
-            Some(Value::BooleanValue(v)) => Ok(RustKvsValue::Boolean(*v)),
-            Some(Value::StringValue(v)) => Ok(RustKvsValue::String(v.clone())),
-            Some(Value::NullValue(_)) => Ok(RustKvsValue::Null),
-            Some(Value::ArrayValue(arr)) => {
-                let mut values = Vec::new();
-                for proto_val in &arr.values {
-                    values.push(Self::proto_to_kvs_value(proto_val)?);
-                }
-                Ok(RustKvsValue::Array(values))
-            }
-            Some(Value::ObjectValue(obj)) => {
-                let mut values = HashMap::new();
-                for (key, proto_val) in &obj.values {
-                    values.insert(key.clone(), Self::proto_to_kvs_value(proto_val)?);
-                }
-                Ok(RustKvsValue::Object(values))
-            }
-            None => Err("KvsValue has no value set".to_string()),
+    async fn get_service_info(
+        &self,
+        request: Request<GetServiceInfoRequest>,
+    ) -> Result<Response<GetServiceInfoResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        debug!("GetServiceInfo request");
+
+        Ok(Response::new(GetServiceInfoResponse {
+            success: true,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_secs: self
+                .clock
+                .monotonic()
+                .saturating_sub(self.started)
+                .as_secs(),
+            upload: Some(self.upload.to_proto()),
+            error_message: String::new(),
+            recovery: Some(self.recovery.to_proto()),
+            pending_operations: self
+                .delayed
+                .pending()
+                .iter()
+                .map(PendingOperation::to_proto)
+                .collect(),
+        }))
+    }
+
+    async fn get_prefix_stats(
+        &self,
+        request: Request<GetPrefixStatsRequest>,
+    ) -> Result<Response<GetPrefixStatsResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        let req = request.into_inner();
+        debug!("GetPrefixStats request for prefix: {}", req.prefix);
+
+        let StorageForecast {
+            stored_bytes,
+            limit,
+            growth_bytes_per_day,
+            days_until_full,
+            window,
+            prefixes,
+        } = self.forecast.forecast(&req.prefix);
+        let prefixes = prefixes
+            .into_iter()
+            .map(|prefix| PrefixStats {
+                prefix: prefix.prefix,
+                keys: prefix.keys,
+                stored_bytes: prefix.stored_bytes,
+                written_bytes: prefix.written_bytes,
+                write_bytes_per_day: prefix.write_bytes_per_day,
+                growth_bytes_per_day: prefix.growth_bytes_per_day,
+                days_until_full: prefix.days_until_full.unwrap_or(-1.0),
+            })
+            .collect();
+        Ok(Response::new(GetPrefixStatsResponse {
+            success: true,
+            prefixes,
+            total: Some(common::persistency_proto::StorageForecast {
+                stored_bytes,
+                limit_bytes: limit.map_or(0, |(_, bytes)| bytes),
+                limit: limit
+                    .map(|(limit, _)| limit.as_str().to_string())
+                    .unwrap_or_default(),
+                growth_bytes_per_day,
+                days_until_full: days_until_full.unwrap_or(-1.0),
+                window_secs: window.as_secs(),
+            }),
+            error_message: String::new(),
+        }))
+    }
+
+    async fn get_usage_reports(
+        &self,
+        request: Request<GetUsageReportsRequest>,
+    ) -> Result<Response<GetUsageReportsResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        let req = request.into_inner();
+        debug!("GetUsageReports request: {:?}", req);
+
+        if !self.usage.enabled() {
+            return rejected(
+                Code::FailedPrecondition,
+                GetUsageReportsResponse {
+                    success: false,
+                    reports: vec![],
+                    error_message: "Usage reports are disabled in the configuration".to_string(),
+                },
+            );
         }
+        let reports = self
+            .usage
+            .reports(&req.namespace, req.limit as usize, req.include_current)
+            .iter()
+            .map(UsageReport::to_proto)
+            .collect();
+        Ok(Response::new(GetUsageReportsResponse {
+            success: true,
+            reports,
+            error_message: String::new(),
+        }))
     }
-}
 
-#[tonic::async_trait]
-impl PersistencyService for PersistencyServiceImpl {
-    async fn set_value(
+    async fn restore(
         &self,
-        request: Request<SetValueRequest>,
-    ) -> Result<Response<SetValueResponse>, Status> {
+        request: Request<RestoreRequest>,
+    ) -> Result<Response<RestoreResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
         let req = request.into_inner();
-        debug!("SetValue request for key: {}", req.key);
+        debug!("Restore request with strategy {:?}", req.strategy());
 
-        let kvs = self.kvs.read().await;
-        
-        match req.value {
-            Some(proto_value) => {
-                match Self::proto_to_kvs_value(&proto_value) {
-                    Ok(rust_value) => {
-                        match kvs.set_value(&req.key, rust_value) {
-                            Ok(_) => {
-                                debug!("Successfully set value for key: {}", req.key);
-                                
-                                // Try to flush immediately to ensure files are written
-                                if let Err(e) = kvs.flush() {
-                                    warn!("Failed to flush after setting key {}: {:?}", req.key, e);
-                                } else {
-                                    debug!("Flushed data to storage files after setting key: {}", req.key);
-                                }
-                                
-                                Ok(Response::new(SetValueResponse {
-                                    success: true,
-                                    error_message: String::new(),
-                                }))
-                            }
-                            Err(e) => {
-                                error!("Failed to set value for key {}: {:?}", req.key, e);
-                                Ok(Response::new(SetValueResponse {
-                                    success: false,
-                                    error_message: format!("Failed to set value: {:?}", e),
-                                }))
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to convert protobuf value: {}", e);
-                        Ok(Response::new(SetValueResponse {
-                            success: false,
-                            error_message: format!("Value conversion error: {}", e),
-                        }))
-                    }
-                }
+        let strategy = match req.strategy() {
+            common::persistency_proto::ConflictStrategy::FailOnConflict => {
+                ConflictStrategy::FailOnConflict
             }
-            None => {
-                error!("SetValue request missing value for key: {}", req.key);
-                Ok(Response::new(SetValueResponse {
+            common::persistency_proto::ConflictStrategy::SkipExisting => {
+                ConflictStrategy::SkipExisting
+            }
+            common::persistency_proto::ConflictStrategy::Overwrite => ConflictStrategy::Overwrite,
+            common::persistency_proto::ConflictStrategy::NewestWins => ConflictStrategy::NewestWins,
+        };
+        let Some(source) = req.source else {
+            return rejected(
+                Code::InvalidArgument,
+                RestoreResponse {
                     success: false,
-                    error_message: "Missing value in request".to_string(),
-                }))
+                    error_message: "Missing source in request".to_string(),
+                    ..Default::default()
+                },
+            );
+        };
+
+        match PersistencyServiceImpl::restore(self, source, &req.increments_json, strategy).await {
+            Ok(report) => {
+                let rejected = report.rejected();
+                let response = RestoreResponse {
+                    success: !rejected,
+                    restored_keys: report.restored,
+                    unchanged_keys: report.unchanged,
+                    skipped_keys: report.skipped,
+                    conflicts: report
+                        .conflicts
+                        .into_iter()
+                        .map(|conflict| KeyConflict {
+                            key: conflict.key,
+                            resolution: conflict.resolution.as_str().to_string(),
+                            stored_ms: conflict.stored_ms,
+                            restored_ms: conflict.restored_ms,
+                        })
+                        .collect(),
+                    error_message: String::new(),
+                };
+                if rejected {
+                    warn!(
+                        "Restore rejected, {} keys conflict",
+                        response.conflicts.len()
+                    );
+                    Ok(failed(RestoreResponse {
+                        error_message: format!(
+                            "{} keys conflict, nothing was restored",
+                            response.conflicts.len()
+                        ),
+                        ..response
+                    }))
+                } else {
+                    Ok(Response::new(response))
+                }
+            }
+            Err(e) => {
+                error!("Failed to restore: {}", e);
+                self.diagnostics.record_error("Restore", "", e.clone());
+                rejected(
+                    Code::FailedPrecondition,
+                    RestoreResponse {
+                        success: false,
+                        error_message: e,
+                        ..Default::default()
+                    },
+                )
             }
         }
     }
 
-    async fn get_value(
+    async fn cancel_pending_operation(
         &self,
-        request: Request<GetValueRequest>,
-    ) -> Result<Response<GetValueResponse>, Status> {
-        let req = request.into_inner();
-        debug!("GetValue request for key: {}", req.key);
+        request: Request<CancelPendingOperationRequest>,
+    ) -> Result<Response<CancelPendingOperationResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        let id = request.into_inner().id;
+        debug!("CancelPendingOperation request for operation {}", id);
 
-        let kvs = self.kvs.read().await;
-        
-        match kvs.get_value(&req.key) {
-            Ok(rust_value) => {
-                let proto_value = Self::kvs_value_to_proto(&rust_value);
-                debug!("Successfully retrieved value for key: {}", req.key);
-                Ok(Response::new(GetValueResponse {
+        match self.delayed.cancel(id) {
+            Ok(Some(cancelled)) => {
+                warn!(
+                    "Cancelled pending {} {} of namespace '{}' requested by '{}'",
+                    cancelled.operation.name(),
+                    cancelled.id,
+                    cancelled.operation.namespace(),
+                    cancelled.caller
+                );
+                self.diagnostics
+                    .record_audit("CancelPendingOperation", cancelled.operation.namespace());
+                Ok(Response::new(CancelPendingOperationResponse {
                     success: true,
-                    value: Some(proto_value),
                     error_message: String::new(),
+                    cancelled: Some(cancelled.to_proto()),
                 }))
             }
+            Ok(None) => rejected(
+                Code::NotFound,
+                CancelPendingOperationResponse {
+                    success: false,
+                    error_message: format!("No pending operation {}", id),
+                    cancelled: None,
+                },
+            ),
             Err(e) => {
-                warn!("Failed to get value for key {}: {:?}", req.key, e);
-                Ok(Response::new(GetValueResponse {
+                error!("Failed to cancel pending operation {}: {}", id, e);
+                rejected(
+                    Code::Internal,
+                    CancelPendingOperationResponse {
+                        success: false,
+                        error_message: format!("Failed to update the journal: {}", e),
+                        cancelled: None,
+                    },
+                )
+            }
+        }
+    }
+
+    async fn list_backups(
+        &self,
+        request: Request<ListBackupsRequest>,
+    ) -> Result<Response<ListBackupsResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        debug!("ListBackups request");
+
+        let directory = self.backups.dir().display().to_string();
+        if !self.config.backup.enabled {
+            return rejected(
+                Code::FailedPrecondition,
+                ListBackupsResponse {
                     success: false,
-                    value: None,
-                    error_message: format!("Key not found: {:?}", e),
-                }))
+                    directory,
+                    error_message: "Backups are disabled".to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+        match self.backups.list() {
+            Ok(backups) => Ok(Response::new(ListBackupsResponse {
+                success: true,
+                backups: backups.iter().map(backup::BackupFile::to_proto).collect(),
+                directory,
+                error_message: String::new(),
+            })),
+            Err(e) => {
+                error!("Failed to list backups: {}", e);
+                rejected(
+                    Code::Internal,
+                    ListBackupsResponse {
+                        success: false,
+                        directory,
+                        error_message: format!("Failed to list backups: {}", e),
+                        ..Default::default()
+                    },
+                )
             }
         }
     }
 
-    async fn remove_key(
+    async fn create_backup(
         &self,
-        request: Request<RemoveKeyRequest>,
-    ) -> Result<Response<RemoveKeyResponse>, Status> {
+        request: Request<CreateBackupRequest>,
+    ) -> Result<Response<CreateBackupResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        debug!("CreateBackup request");
+
+        if !self.config.backup.enabled {
+            return rejected(
+                Code::FailedPrecondition,
+                CreateBackupResponse {
+                    success: false,
+                    error_message: "Backups are disabled".to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+        match PersistencyServiceImpl::create_backup(self).await {
+            Ok(created) => Ok(Response::new(CreateBackupResponse {
+                success: true,
+                backup: Some(created.file.to_proto()),
+                keys: created.keys as u32,
+                removed: created.removed,
+                error_message: String::new(),
+            })),
+            Err(e) => {
+                error!("Failed to create backup: {}", e);
+                self.diagnostics.record_error("CreateBackup", "", e.clone());
+                rejected(
+                    Code::Internal,
+                    CreateBackupResponse {
+                        success: false,
+                        error_message: e,
+                        ..Default::default()
+                    },
+                )
+            }
+        }
+    }
+
+    async fn export_to_file(
+        &self,
+        request: Request<ExportToFileRequest>,
+    ) -> Result<Response<ExportToFileResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
         let req = request.into_inner();
-        debug!("RemoveKey request for key: {}", req.key);
+        debug!(
+            "ExportToFile request for prefix '{}' as {:?}",
+            req.prefix,
+            req.format()
+        );
 
-        let kvs = self.kvs.read().await;
-        
-        match kvs.remove_key(&req.key) {
-            Ok(_) => {
-                debug!("Successfully removed key: {}", req.key);
-                Ok(Response::new(RemoveKeyResponse {
-                    success: true,
-                    error_message: String::new(),
-                }))
+        let directory = self.dumps.dir().display().to_string();
+        if !self.config.dump.enabled {
+            return rejected(
+                Code::FailedPrecondition,
+                ExportToFileResponse {
+                    success: false,
+                    directory,
+                    error_message: "File dumps are disabled".to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+        if !req.file_name.is_empty() {
+            if let Err(e) = dump::validate_file_name(&req.file_name) {
+                return rejected(
+                    Code::InvalidArgument,
+                    ExportToFileResponse {
+                        success: false,
+                        directory,
+                        error_message: e,
+                        ..Default::default()
+                    },
+                );
             }
+        }
+        let format = match req.format() {
+            common::persistency_proto::DumpFormat::Json => DumpFormat::Json,
+            common::persistency_proto::DumpFormat::Yaml => DumpFormat::Yaml,
+        };
+        match PersistencyServiceImpl::export_to_file(self, &req.prefix, format, &req.file_name)
+            .await
+        {
+            Ok(written) => Ok(Response::new(ExportToFileResponse {
+                success: true,
+                file_name: written.name,
+                keys: written.keys as u32,
+                size_bytes: written.size_bytes,
+                directory,
+                error_message: String::new(),
+            })),
             Err(e) => {
-                error!("Failed to remove key {}: {:?}", req.key, e);
-                Ok(Response::new(RemoveKeyResponse {
-                    success: false,
-                    error_message: format!("Failed to remove key: {:?}", e),
-                }))
+                error!("Failed to export to file: {}", e);
+                self.diagnostics
+                    .record_error("ExportToFile", &req.prefix, e.clone());
+                rejected(
+                    Code::Internal,
+                    ExportToFileResponse {
+                        success: false,
+                        directory,
+                        error_message: e,
+                        ..Default::default()
+                    },
+                )
             }
         }
     }
 
-    async fn get_all_keys(
+    async fn import_from_file(
         &self,
-        _request: Request<GetAllKeysRequest>,
-    ) -> Result<Response<GetAllKeysResponse>, Status> {
-        debug!("GetAllKeys request");
+        request: Request<ImportFromFileRequest>,
+    ) -> Result<Response<ImportFromFileResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
+        let req = request.into_inner();
+        debug!(
+            "ImportFromFile request for {} with strategy {:?}",
+            req.file_name,
+            req.strategy()
+        );
 
-        let kvs = self.kvs.read().await;
-        
-        match kvs.get_all_keys() {
-            Ok(keys) => {
-                debug!("Successfully retrieved {} keys", keys.len());
-                Ok(Response::new(GetAllKeysResponse {
-                    success: true,
-                    keys,
+        if !self.config.dump.enabled {
+            return rejected(
+                Code::FailedPrecondition,
+                ImportFromFileResponse {
+                    success: false,
+                    error_message: "File dumps are disabled".to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+        if let Err(e) = dump::validate_file_name(&req.file_name) {
+            return rejected(
+                Code::InvalidArgument,
+                ImportFromFileResponse {
+                    success: false,
+                    error_message: e,
+                    ..Default::default()
+                },
+            );
+        }
+        let strategy = match req.strategy() {
+            common::persistency_proto::ConflictStrategy::FailOnConflict => {
+                ConflictStrategy::FailOnConflict
+            }
+            common::persistency_proto::ConflictStrategy::SkipExisting => {
+                ConflictStrategy::SkipExisting
+            }
+            common::persistency_proto::ConflictStrategy::Overwrite => ConflictStrategy::Overwrite,
+            common::persistency_proto::ConflictStrategy::NewestWins => ConflictStrategy::NewestWins,
+        };
+
+        match PersistencyServiceImpl::import_from_file(self, &req.file_name, strategy).await {
+            Ok((format, report)) => {
+                let rejected = report.rejected();
+                let response = ImportFromFileResponse {
+                    success: !rejected,
+                    source_format: format.as_str().to_string(),
+                    restored_keys: report.restored,
+                    unchanged_keys: report.unchanged,
+                    skipped_keys: report.skipped,
+                    conflicts: report
+                        .conflicts
+                        .into_iter()
+                        .map(|conflict| KeyConflict {
+                            key: conflict.key,
+                            resolution: conflict.resolution.as_str().to_string(),
+                            stored_ms: conflict.stored_ms,
+                            restored_ms: conflict.restored_ms,
+                        })
+                        .collect(),
                     error_message: String::new(),
-                }))
+                };
+                if rejected {
+                    warn!(
+                        "Import of {} rejected, {} keys conflict",
+                        req.file_name,
+                        response.conflicts.len()
+                    );
+                    Ok(failed(ImportFromFileResponse {
+                        error_message: format!(
+                            "{} keys conflict, nothing was imported",
+                            response.conflicts.len()
+                        ),
+                        ..response
+                    }))
+                } else {
+                    Ok(Response::new(response))
+                }
             }
             Err(e) => {
-                error!("Failed to get all keys: {:?}", e);
-                Ok(Response::new(GetAllKeysResponse {
-                    success: false,
-                    keys: vec![],
-                    error_message: format!("Failed to get keys: {:?}", e),
-                }))
+                error!("Failed to import {}: {}", req.file_name, e);
+                self.diagnostics
+                    .record_error("ImportFromFile", "", e.clone());
+                rejected(
+                    Code::FailedPrecondition,
+                    ImportFromFileResponse {
+                        success: false,
+                        error_message: e,
+                        ..Default::default()
+                    },
+                )
             }
         }
     }
 
-    async fn key_exists(
+    async fn migrate_from_etcd(
         &self,
-        request: Request<KeyExistsRequest>,
-    ) -> Result<Response<KeyExistsResponse>, Status> {
+        request: Request<MigrateFromEtcdRequest>,
+    ) -> Result<Response<MigrateFromEtcdResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
         let req = request.into_inner();
-        debug!("KeyExists request for key: {}", req.key);
+        debug!(
+            "MigrateFromEtcd request for prefix '{}' from '{}' with strategy {:?}",
+            req.prefix,
+            req.endpoint,
+            req.strategy()
+        );
 
-        let kvs = self.kvs.read().await;
-        
-        match kvs.key_exists(&req.key) {
-            Ok(exists) => {
-                debug!("Key {} exists: {}", req.key, exists);
-                Ok(Response::new(KeyExistsResponse {
-                    success: true,
-                    exists,
+        if !self.config.etcd_migration.enabled {
+            return rejected(
+                Code::FailedPrecondition,
+                MigrateFromEtcdResponse {
+                    success: false,
+                    error_message: "etcd migration is disabled".to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+        let strategy = match req.strategy() {
+            common::persistency_proto::ConflictStrategy::FailOnConflict => {
+                ConflictStrategy::FailOnConflict
+            }
+            common::persistency_proto::ConflictStrategy::SkipExisting => {
+                ConflictStrategy::SkipExisting
+            }
+            common::persistency_proto::ConflictStrategy::Overwrite => ConflictStrategy::Overwrite,
+            common::persistency_proto::ConflictStrategy::NewestWins => ConflictStrategy::NewestWins,
+        };
+
+        match PersistencyServiceImpl::migrate_from_etcd(self, &req.endpoint, &req.prefix, strategy)
+            .await
+        {
+            Ok(migration) => {
+                let rejected = migration.report.rejected();
+                let response = MigrateFromEtcdResponse {
+                    success: !rejected,
+                    read_keys: migration.read_keys as u32,
+                    etcd_revision: migration.revision,
+                    restored_keys: migration.report.restored,
+                    unchanged_keys: migration.report.unchanged,
+                    skipped_keys: migration.report.skipped,
+                    conflicts: migration
+                        .report
+                        .conflicts
+                        .into_iter()
+                        .map(|conflict| KeyConflict {
+                            key: conflict.key,
+                            resolution: conflict.resolution.as_str().to_string(),
+                            stored_ms: conflict.stored_ms,
+                            restored_ms: conflict.restored_ms,
+                        })
+                        .collect(),
                     error_message: String::new(),
-                }))
+                };
+                if rejected {
+                    warn!(
+                        "etcd migration rejected, {} keys conflict",
+                        response.conflicts.len()
+                    );
+                    Ok(failed(MigrateFromEtcdResponse {
+                        error_message: format!(
+                            "{} keys conflict, nothing was migrated",
+                            response.conflicts.len()
+                        ),
+                        ..response
+                    }))
+                } else {
+                    Ok(Response::new(response))
+                }
             }
             Err(e) => {
-                error!("Failed to check if key {} exists: {:?}", req.key, e);
-                Ok(Response::new(KeyExistsResponse {
-                    success: false,
-                    exists: false,
-                    error_message: format!("Failed to check key existence: {:?}", e),
-                }))
+                error!("Failed to migrate from etcd: {}", e);
+                self.diagnostics
+                    .record_error("MigrateFromEtcd", &req.prefix, e.clone());
+                rejected(
+                    Code::FailedPrecondition,
+                    MigrateFromEtcdResponse {
+                        success: false,
+                        error_message: e,
+                        ..Default::default()
+                    },
+                )
             }
         }
     }
 
-    async fn get_all_with_prefix(
+    async fn set_fault_injection(
         &self,
-        request: Request<GetAllWithPrefixRequest>,
-    ) -> Result<Response<GetAllWithPrefixResponse>, Status> {
+        request: Request<SetFaultInjectionRequest>,
+    ) -> Result<Response<SetFaultInjectionResponse>, Status> {
+        self.access.check_admin(request.extensions())?;
         let req = request.into_inner();
-        debug!("GetAllWithPrefix request for prefix: {}", req.prefix);
+        debug!("SetFaultInjection request: {:?}", req.config);
 
-        let kvs = self.kvs.read().await;
-        
-        // Get all keys and filter by prefix
-        match kvs.get_all_keys() {
-            Ok(all_keys) => {
-                let mut key_values = HashMap::new();
-                
-                for key in all_keys {
-                    if key.starts_with(&req.prefix) {
-                        match kvs.get_value(&key) {
-                            Ok(rust_value) => {
-                                let proto_value = Self::kvs_value_to_proto(&rust_value);
-                                key_values.insert(key, proto_value);
-                            }
-                            Err(e) => {
-                                warn!("Failed to get value for key {} during prefix search: {:?}", key, e);
-                            }
+        #[cfg(feature = "fault-injection")]
+        {
+            let config = req.config.unwrap_or_default();
+            self.faults.configure(&config);
+            warn!("Fault injection set to {:?}", config);
+            self.diagnostics.record_audit("SetFaultInjection", "");
+            Ok(Response::new(SetFaultInjectionResponse {
+                success: true,
+                active: Some(self.faults.config()),
+                error_message: String::new(),
+            }))
+        }
+
+        #[cfg(not(feature = "fault-injection"))]
+        rejected(
+            Code::Unimplemented,
+            SetFaultInjectionResponse {
+                success: false,
+                active: None,
+                error_message: "Fault injection is not available in this build".to_string(),
+            },
+        )
+    }
+
+    async fn get_proto_descriptor(
+        &self,
+        _request: Request<GetProtoDescriptorRequest>,
+    ) -> Result<Response<GetProtoDescriptorResponse>, Status> {
+        // The API description holds no data, every caller may read it
+        debug!("GetProtoDescriptor request");
+
+        Ok(Response::new(GetProtoDescriptorResponse {
+            success: true,
+            file_descriptor_set: FILE_DESCRIPTOR_SET.to_vec(),
+            service: common::persistency_proto::persistency_service_server::SERVICE_NAME
+                .to_string(),
+            error_message: String::new(),
+        }))
+    }
+
+    async fn scan(
+        &self,
+        request: Request<ScanRequest>,
+    ) -> Result<Response<Self::ScanStream>, Status> {
+        self.access.check_streaming(request.extensions())?;
+        let (_, extensions, req) = request.into_parts();
+        let store = self.store(&req.namespace)?.clone();
+        let tracked_prefix = store.tracked_key(&req.prefix).into_owned();
+        debug!("Scan request for prefix: {}", tracked_prefix);
+        timing::note_target(&tracked_prefix);
+        // The batches are streamed after the handler returned, under the deadline of the call
+        let timing = timing::current();
+        let deadline = Deadline::current();
+
+        self.inject_read_latency().await;
+        let batch_size = scan::batch_size(req.batch_size);
+        let access = self.access.clone();
+        let usage = self.usage.clone();
+        let encryption = self.encryption.clone();
+        let (tx, rx) = mpsc::channel(2);
+        tokio::spawn(async move {
+            let mut after: Option<String> = None;
+            let mut scanned = 0;
+            loop {
+                if deadline.expired() {
+                    warn!(
+                        "Scan of prefix {} aborted after {} keys, deadline exceeded",
+                        tracked_prefix, scanned
+                    );
+                    let _ = tx
+                        .send(Err(Status::deadline_exceeded(
+                            "Deadline exceeded during Scan",
+                        )))
+                        .await;
+                    break;
+                }
+                let batch = {
+                    let kvs = store.read().await;
+                    scan::read_batch(
+                        &store,
+                        &**kvs,
+                        &encryption,
+                        &req.prefix,
+                        after.as_deref(),
+                        batch_size,
+                        |key| access.can_read(&extensions, &req.namespace, key),
+                    )
+                };
+                let entries = match batch {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        error!(
+                            "Failed to scan prefix {} after {:?}: {:?}",
+                            req.prefix, after, e
+                        );
+                        let _ = tx
+                            .send(Err(Status::internal(format!("Failed to scan: {:?}", e))))
+                            .await;
+                        return;
+                    }
+                };
+                if entries.is_empty() {
+                    break;
+                }
+                if usage.enabled() {
+                    for entry in &entries {
+                        if let Some(value) = &entry.value {
+                            usage.record_read(
+                                &store.tracked_key(&entry.key),
+                                forecast::entry_size(&entry.key, value),
+                            );
                         }
                     }
                 }
-                
-                debug!("Successfully retrieved {} keys with prefix '{}'", key_values.len(), req.prefix);
-                Ok(Response::new(GetAllWithPrefixResponse {
-                    success: true,
-                    key_values,
-                    error_message: String::new(),
-                }))
+                let last = entries.len() < batch_size;
+                after = entries.last().map(|entry| entry.key.clone());
+                scanned += entries.len();
+                // The client cancelled the call
+                if tx.send(Ok(ScanResponse { entries })).await.is_err() || last {
+                    break;
+                }
             }
-            Err(e) => {
-                error!("Failed to get keys for prefix search: {:?}", e);
-                Ok(Response::new(GetAllWithPrefixResponse {
-                    success: false,
-                    key_values: HashMap::new(),
-                    error_message: format!("Failed to get keys: {:?}", e),
-                }))
+            debug!("Scanned {} keys with prefix '{}'", scanned, req.prefix);
+            if let Some(timing) = timing {
+                timing.log_if_slow();
             }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn get_capabilities(
+        &self,
+        request: Request<GetCapabilitiesRequest>,
+    ) -> Result<Response<GetCapabilitiesResponse>, Status> {
+        // Clients negotiate before any other request, every caller may ask
+        debug!("GetCapabilities request");
+
+        let features = capabilities::features(
+            self.access.can_stream(request.extensions()),
+            self.namespaces.has_named(),
+            self.key_history.is_enabled(),
+            self.trash.is_enabled(),
+            self.leases.is_enabled(),
+        );
+        Ok(Response::new(GetCapabilitiesResponse {
+            success: true,
+            api_version: API_VERSION,
+            features,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            error_message: String::new(),
+        }))
+    }
+
+    async fn watch(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        self.access.check_streaming(request.extensions())?;
+        if let Some(watch_request::Target::Key(key) | watch_request::Target::Prefix(key)) =
+            &request.get_ref().target
+        {
+            self.access
+                .check_read(request.extensions(), &request.get_ref().namespace, key)?;
         }
+        let req = request.into_inner();
+        debug!("Watch request: {:?}", req);
+
+        let store = self.store(&req.namespace)?;
+        let target = match req.target {
+            Some(watch_request::Target::Key(key)) if !key.is_empty() => {
+                WatchTarget::Key(store.tracked_key(&key).into_owned())
+            }
+            Some(watch_request::Target::Prefix(prefix)) => {
+                WatchTarget::Prefix(store.tracked_key(&prefix).into_owned())
+            }
+            _ => {
+                return Err(Status::invalid_argument(
+                    "A key or prefix to watch is required",
+                ))
+            }
+        };
+
+        let options = WatchOptions {
+            puts: !req.filters.contains(&(WatchFilter::Noput as i32)),
+            deletes: !req.filters.contains(&(WatchFilter::Nodelete as i32)),
+            previous: req.prev_value,
+        };
+        let mut subscription = self.watch.subscribe_with(target, options);
+        let namespaces = self.namespaces.clone();
+        let namespace = req.namespace;
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                let notification = tokio::select! {
+                    // The client cancelled the call
+                    _ = tx.closed() => break,
+                    notification = subscription.recv() => notification,
+                };
+                let Some(notification) = notification else {
+                    break;
+                };
+                // Report the keys as the client knows them, without the keys of other namespaces
+                let mut response = notification.to_proto();
+                // Another watcher of the key may have asked for the previous value
+                if !options.previous {
+                    response.prev_value = None;
+                }
+                if !response.key.is_empty() {
+                    let (store, key) = namespaces.resolve(&response.key);
+                    if store.name() != namespace {
+                        continue;
+                    }
+                    response.key = key.to_string();
+                }
+                if tx.send(Ok(response)).await.is_err() {
+                    break;
+                }
+            }
+            debug!("Watcher of {:?} stopped", subscription.target());
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
     }
 
-    async fn reset(
+    async fn get_value_stream(
         &self,
-        _request: Request<ResetRequest>,
-    ) -> Result<Response<ResetResponse>, Status> {
-        debug!("Reset request");
+        request: Request<GetValueStreamRequest>,
+    ) -> Result<Response<Self::GetValueStreamStream>, Status> {
+        self.access.check_streaming(request.extensions())?;
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        debug!(
+            "GetValueStream request for key: {}",
+            store.tracked_key(&req.key)
+        );
 
-        let kvs = self.kvs.read().await;
-        
-        match kvs.reset() {
-            Ok(_) => {
-                info!("Successfully reset KVS");
-                Ok(Response::new(ResetResponse {
-                    success: true,
-                    error_message: String::new(),
-                }))
+        let (value, _) = self.read_chunked_value(store, &req.key).await?;
+        let chunks = chunked::chunks(value, chunked::chunk_size(req.chunk_size));
+        Ok(Response::new(Box::pin(tokio_stream::iter(chunks.map(Ok)))))
+    }
+
+    async fn set_large_value(
+        &self,
+        request: Request<Streaming<SetLargeValueRequest>>,
+    ) -> Result<Response<SetLargeValueResponse>, Status> {
+        self.access.check_streaming(request.extensions())?;
+        let (metadata, extensions, mut requests) = request.into_parts();
+        let invalid = |error_message: String| {
+            rejected(
+                Code::InvalidArgument,
+                SetLargeValueResponse {
+                    success: false,
+                    error_message,
+                },
+            )
+        };
+
+        let Some(first) = requests.message().await? else {
+            return invalid("No chunk received".to_string());
+        };
+        let mut value =
+            match large::LargeValue::start(first, self.config.large_values.max_value_bytes) {
+                Ok(value) => value,
+                Err(e) => return invalid(e),
+            };
+        // Reject forbidden keys and unknown namespaces before receiving the whole value
+        self.access
+            .check_write(&extensions, value.namespace(), value.key())?;
+        let tracked = self
+            .store(value.namespace())?
+            .tracked_key(value.key())
+            .into_owned();
+        debug!("SetLargeValue request for key: {}", tracked);
+
+        while let Some(request) = requests.message().await? {
+            if let Err(e) = value.push(request.chunk) {
+                warn!("SetLargeValue for key {} rejected: {}", tracked, e);
+                self.diagnostics
+                    .record_error("SetLargeValue", &tracked, e.clone());
+                return invalid(e);
             }
+        }
+        let set_request = match value.finish() {
+            Ok(set_request) => set_request,
             Err(e) => {
-                error!("Failed to reset KVS: {:?}", e);
-                Ok(Response::new(ResetResponse {
-                    success: false,
-                    error_message: format!("Failed to reset: {:?}", e),
-                }))
+                warn!("SetLargeValue for key {} rejected: {}", tracked, e);
+                self.diagnostics
+                    .record_error("SetLargeValue", &tracked, e.clone());
+                return invalid(e);
             }
+        };
+
+        // Stored like any other value, with the checks and bookkeeping of SetValue
+        let response = self
+            .set_value(Request::from_parts(metadata, extensions, set_request))
+            .await?
+            .into_inner();
+        if response.success {
+            Ok(Response::new(SetLargeValueResponse {
+                success: true,
+                error_message: String::new(),
+            }))
+        } else {
+            // Only without status codes, SetValue reported its failure as a status otherwise
+            invalid(response.error_message)
         }
     }
 
-    async fn flush(
+    async fn get_large_value(
         &self,
-        _request: Request<FlushRequest>,
-    ) -> Result<Response<FlushResponse>, Status> {
-        debug!("Flush request");
+        request: Request<GetLargeValueRequest>,
+    ) -> Result<Response<Self::GetLargeValueStream>, Status> {
+        self.access.check_streaming(request.extensions())?;
+        self.access.check_read(
+            request.extensions(),
+            &request.get_ref().namespace,
+            &request.get_ref().key,
+        )?;
+        let req = request.into_inner();
+        let store = self.store(&req.namespace)?;
+        let tracked = store.tracked_key(&req.key);
+        debug!("GetLargeValue request for key: {}", tracked);
+        timing::note_target(&tracked);
 
-        let kvs = self.kvs.read().await;
-        
-        match kvs.flush() {
-            Ok(_) => {
-                debug!("Successfully flushed KVS");
-                Ok(Response::new(FlushResponse {
-                    success: true,
-                    error_message: String::new(),
-                }))
+        let (value, binary) = self.read_chunked_value(store, &req.key).await?;
+        let chunks = large::chunks(value, binary, chunked::chunk_size(req.chunk_size));
+        Ok(Response::new(Box::pin(tokio_stream::iter(chunks.map(Ok)))))
+    }
+
+    async fn restore_snapshot(
+        &self,
+        request: Request<Streaming<RestoreSnapshotRequest>>,
+    ) -> Result<Response<RestoreSnapshotResponse>, Status> {
+        self.access.check_streaming(request.extensions())?;
+        self.access.check_admin(request.extensions())?;
+        let mut requests = request.into_inner();
+        let invalid = |error_message: String| {
+            rejected(
+                Code::InvalidArgument,
+                RestoreSnapshotResponse {
+                    success: false,
+                    error_message,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let Some(first) = requests.message().await? else {
+            return invalid("No chunk received".to_string());
+        };
+        let mut upload = match replace::SnapshotUpload::start(
+            first,
+            self.config.snapshot_restore.max_snapshot_bytes,
+        ) {
+            Ok(upload) => upload,
+            Err(e) => return invalid(e),
+        };
+        let dry_run = upload.dry_run();
+        debug!("RestoreSnapshot request, dry run: {}", dry_run);
+
+        while let Some(request) = requests.message().await? {
+            if let Err(e) = upload.push(request.chunk) {
+                warn!("RestoreSnapshot rejected: {}", e);
+                self.diagnostics
+                    .record_error("RestoreSnapshot", "", e.clone());
+                return invalid(e);
+            }
+        }
+        let snapshot = match upload.finish() {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("RestoreSnapshot rejected: {}", e);
+                self.diagnostics
+                    .record_error("RestoreSnapshot", "", e.clone());
+                return invalid(e);
             }
+        };
+
+        let prefixes = snapshot.prefixes.clone();
+        match self.replace_store(snapshot, dry_run).await {
+            Ok(report) => Ok(Response::new(RestoreSnapshotResponse {
+                success: true,
+                applied: !dry_run,
+                prefixes,
+                added_keys: report.added,
+                replaced_keys: report.replaced,
+                removed_keys: report.removed,
+                unchanged_keys: report.unchanged,
+                error_message: String::new(),
+            })),
             Err(e) => {
-                error!("Failed to flush KVS: {:?}", e);
-                Ok(Response::new(FlushResponse {
-                    success: false,
-                    error_message: format!("Failed to flush: {:?}", e),
-                }))
+                error!("Failed to restore snapshot: {}", e);
+                self.diagnostics
+                    .record_error("RestoreSnapshot", "", e.clone());
+                rejected(
+                    Code::FailedPrecondition,
+                    RestoreSnapshotResponse {
+                        success: false,
+                        prefixes,
+                        error_message: e,
+                        ..Default::default()
+                    },
+                )
             }
         }
     }
-}
\ No newline at end of file
+}
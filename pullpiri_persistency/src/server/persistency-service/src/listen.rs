@@ -0,0 +1,126 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Listeners of the gRPC server
+//!
+//! The service is served over TCP on port 47007 and, with a socket path
+//! configured, over a Unix domain socket. Components on the same host that
+//! connect over the socket skip the TCP stack, and a service only used on
+//! its host can leave TCP off to expose no port at all. Only the user of the
+//! service may connect to the socket, see [`SOCKET_MODE`], and requests over
+//! it are authenticated like requests over TCP.
+
+use std::fs::{DirBuilder, Permissions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::Path;
+use tokio::net::UnixListener;
+use tokio_stream::wrappers::UnixListenerStream;
+
+/// Permissions of the sockets, read and write for the user of the service
+pub const SOCKET_MODE: u32 = 0o600;
+
+/// Permissions of the directories created for the sockets
+pub const SOCKET_DIR_MODE: u32 = 0o700;
+
+/// Listen on the Unix domain socket at `socket_path`
+///
+/// Missing directories of the socket are created with [`SOCKET_DIR_MODE`],
+/// existing ones are left as they are. A socket left over from a previous
+/// run is replaced, as it would make the bind fail, but any other file at
+/// `socket_path` is an error.
+pub fn bind_unix(socket_path: &str) -> io::Result<UnixListener> {
+    let path = Path::new(socket_path);
+    if let Some(parent) = path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(SOCKET_DIR_MODE)
+            .create(parent)?;
+    }
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", socket_path),
+            ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, Permissions::from_mode(SOCKET_MODE))?;
+    Ok(listener)
+}
+
+/// Connections of gRPC clients to the Unix domain socket at `socket_path`
+pub fn unix_incoming(socket_path: &str) -> io::Result<UnixListenerStream> {
+    bind_unix(socket_path).map(UnixListenerStream::new)
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PersistencyServiceImpl;
+    use common::persistency_client::{PersistencyClient, UNIX_SCHEME};
+    use common::persistency_proto::persistency_service_server::PersistencyServiceServer;
+    use tonic::transport::Server;
+
+    fn socket_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("persistency-listen-{}", std::process::id()));
+        dir.join(format!("{}.sock", name)).display().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_grpc_over_unix_socket() {
+        let path = socket_path("grpc");
+        let incoming = unix_incoming(&path).unwrap();
+        let service = PersistencyServiceImpl::new_in_memory().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(PersistencyServiceServer::new(service))
+                .serve_with_incoming(incoming),
+        );
+
+        let endpoint = format!("{}{}", UNIX_SCHEME, path);
+        let mut client = PersistencyClient::connect_to(&endpoint).await.unwrap();
+        client.put("vehicle/mode", "autonomous").await.unwrap();
+        assert_eq!(client.get("vehicle/mode").await.unwrap(), "autonomous");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_stale_socket_replaced() {
+        let path = socket_path("stale");
+        drop(bind_unix(&path).unwrap());
+        // The socket file outlives its listener
+        assert!(Path::new(&path).exists());
+        assert!(bind_unix(&path).is_ok());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_other_files_not_replaced() {
+        let path = socket_path("regular");
+        std::fs::create_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+        std::fs::write(&path, "kept").unwrap();
+        let error = bind_unix(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "kept");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_socket_only_for_the_service_user() {
+        let dir = std::env::temp_dir().join(format!("persistency-mode-{}", std::process::id()));
+        let path = dir.join("run").join("mode.sock");
+        let _listener = bind_unix(path.to_str().unwrap()).unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), SOCKET_MODE);
+        assert_eq!(mode(path.parent().unwrap()), SOCKET_DIR_MODE);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
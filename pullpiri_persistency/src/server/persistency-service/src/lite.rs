@@ -0,0 +1,297 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Length-prefixed framing protocol for clients without gRPC
+//!
+//! Serves `common::persistency_lite` clients on a Unix domain socket. Each
+//! request is dispatched to the same handlers as the gRPC service, so writes
+//! are flushed, published to watchers and recorded for diagnostics exactly
+//! like native gRPC requests. Requests are authenticated like gRPC requests
+//! with the component and token of their frame, see [`crate::auth`], and the
+//! access control lists apply to them.
+
+use crate::auth::AuthInterceptor;
+use crate::config::AuthConfig;
+use crate::PersistencyServiceImpl;
+use common::identity::NAME_METADATA;
+use common::persistency_lite::{
+    decode_payload, encode_frame, LiteError, LiteFrame, LiteRequest, LiteResponse, MAX_FRAME_LEN,
+};
+use common::persistency_proto::{
+    kvs_value::Value, persistency_service_server::PersistencyService, GetValueRequest, KvsValue,
+    RemoveKeyRequest, SetValueRequest, AUTHORIZATION_METADATA,
+};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tonic::{Request, Status};
+use tracing::{debug, info};
+
+/// Accept lite clients on `socket_path` forever
+///
+/// ### Parameters
+/// * `socket_path: &str` - Unix domain socket to listen on, see [`crate::listen::bind_unix`]
+/// * `service: Arc<PersistencyServiceImpl>` - service shared with the gRPC server
+/// * `auth: &AuthConfig` - authentication of the callers, as for gRPC requests
+pub async fn serve(
+    socket_path: &str,
+    service: Arc<PersistencyServiceImpl>,
+    auth: &AuthConfig,
+) -> std::io::Result<()> {
+    let listener = crate::listen::bind_unix(socket_path)?;
+    let auth = AuthInterceptor::new(auth);
+    info!("Lite protocol listening on {}", socket_path);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let service = service.clone();
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &service, &auth).await {
+                debug!("Lite connection closed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: UnixStream,
+    service: &PersistencyServiceImpl,
+    auth: &AuthInterceptor,
+) -> Result<(), LiteError> {
+    loop {
+        let mut len = [0u8; 4];
+        match stream.read_exact(&mut len).await {
+            Ok(_) => {}
+            // The client closed the connection between two requests
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            // The stream cannot be resynchronized after an oversized frame
+            let response = LiteResponse::error(format!(
+                "frame of {} bytes exceeds the limit of {} bytes",
+                len, MAX_FRAME_LEN
+            ));
+            stream.write_all(&encode_frame(&response)?).await?;
+            return Err(LiteError::Protocol(response.error_message));
+        }
+
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).await?;
+        let response = match decode_payload::<LiteFrame>(&payload) {
+            Ok(frame) => handle(service, auth, frame).await,
+            Err(e) => LiteResponse::error(e.to_string()),
+        };
+        stream.write_all(&encode_frame(&response)?).await?;
+    }
+}
+
+/// RPC request of `message` for the caller named in `frame`
+fn request<T>(auth: &AuthInterceptor, frame: &LiteFrame, message: T) -> Result<Request<T>, Status> {
+    let mut presented = Request::new(());
+    let metadata = presented.metadata_mut();
+    if !frame.component.is_empty() {
+        let component = frame
+            .component
+            .parse()
+            .map_err(|_| Status::unauthenticated("Component name is not valid metadata"))?;
+        metadata.insert(NAME_METADATA, component);
+    }
+    if !frame.token.is_empty() {
+        let authorization = format!("Bearer {}", frame.token)
+            .parse()
+            .map_err(|_| Status::unauthenticated("Token is not valid metadata"))?;
+        metadata.insert(AUTHORIZATION_METADATA, authorization);
+    }
+    let component = auth.authenticate(&presented)?;
+    let mut request = Request::new(message);
+    if let Some(component) = component {
+        request.extensions_mut().insert(component);
+    }
+    Ok(request)
+}
+
+/// Execute one lite request
+pub async fn handle(
+    service: &PersistencyServiceImpl,
+    auth: &AuthInterceptor,
+    frame: LiteFrame,
+) -> LiteResponse {
+    debug!("Lite request of '{}': {:?}", frame.component, frame.request);
+    execute(service, auth, &frame)
+        .await
+        .unwrap_or_else(|status| LiteResponse::error(status.message()))
+}
+
+async fn execute(
+    service: &PersistencyServiceImpl,
+    auth: &AuthInterceptor,
+    frame: &LiteFrame,
+) -> Result<LiteResponse, Status> {
+    let response = match frame.request.clone() {
+        LiteRequest::Put { key, value } => {
+            let request = request(
+                auth,
+                frame,
+                SetValueRequest {
+                    key,
+                    value: Some(KvsValue {
+                        value: Some(Value::StringValue(value)),
+                    }),
+                    ttl_seconds: 0,
+                    namespace: String::new(),
+                    expected_revision: 0,
+                    lease_id: 0,
+                },
+            )?;
+            let response = service.set_value(request).await?.into_inner();
+            if response.success {
+                LiteResponse::ok(None)
+            } else {
+                LiteResponse::error(response.error_message)
+            }
+        }
+        LiteRequest::Get { key } => {
+            let request = request(
+                auth,
+                frame,
+                GetValueRequest {
+                    key,
+                    namespace: String::new(),
+                },
+            )?;
+            let response = service.get_value(request).await?.into_inner();
+            match response.value.as_ref().map(value_to_string) {
+                Some(Ok(value)) if response.success => LiteResponse::ok(Some(value)),
+                Some(Err(e)) => LiteResponse::error(e),
+                _ => LiteResponse::error(response.error_message),
+            }
+        }
+        LiteRequest::Delete { key } => {
+            let request = request(
+                auth,
+                frame,
+                RemoveKeyRequest {
+                    key,
+                    namespace: String::new(),
+                    trash: false,
+                },
+            )?;
+            let response = service.remove_key(request).await?.into_inner();
+            if response.success {
+                LiteResponse::ok(None)
+            } else {
+                LiteResponse::error(response.error_message)
+            }
+        }
+    };
+    Ok(response)
+}
+
+/// String form of a value, as returned by the gRPC client's `get`
+fn value_to_string(value: &KvsValue) -> Result<String, String> {
+    match &value.value {
+        Some(Value::StringValue(v)) => Ok(v.clone()),
+        Some(Value::I32Value(v)) => Ok(v.to_string()),
+        Some(Value::U32Value(v)) => Ok(v.to_string()),
+        Some(Value::I64Value(v)) => Ok(v.to_string()),
+        Some(Value::U64Value(v)) => Ok(v.to_string()),
+        Some(Value::F64Value(v)) => Ok(v.to_string()),
+        Some(Value::BooleanValue(v)) => Ok(v.to_string()),
+        Some(Value::NullValue(_)) => Ok("null".to_string()),
+        Some(Value::ArrayValue(_)) | Some(Value::ObjectValue(_)) => {
+            Err("Complex types not supported in string conversion".to_string())
+        }
+        Some(Value::BytesValue(_)) => {
+            Err("Binary values not supported in string conversion".to_string())
+        }
+        None => Err("Empty value".to_string()),
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServiceConfig;
+    use common::persistency_proto::KvsArray;
+
+    fn value(value: Value) -> KvsValue {
+        KvsValue { value: Some(value) }
+    }
+
+    #[test]
+    fn test_value_to_string() {
+        assert_eq!(
+            value_to_string(&value(Value::StringValue("manual".to_string()))),
+            Ok("manual".to_string())
+        );
+        assert_eq!(
+            value_to_string(&value(Value::U32Value(7))),
+            Ok("7".to_string())
+        );
+        assert_eq!(
+            value_to_string(&value(Value::BooleanValue(true))),
+            Ok("true".to_string())
+        );
+        assert!(value_to_string(&value(Value::ArrayValue(KvsArray { values: vec![] }))).is_err());
+        assert!(value_to_string(&KvsValue { value: None }).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_requests_authenticated_like_grpc() {
+        let yaml = r#"
+storage:
+  backend: memory
+auth:
+  enabled: true
+  tokens:
+    dashboard: 5e1f0c
+acl:
+  enabled: true
+  components:
+    dashboard:
+      read: ["vehicle/"]
+"#;
+        let config = ServiceConfig::from_yaml(yaml).unwrap();
+        let service = PersistencyServiceImpl::with_config(&config).unwrap();
+        let auth = AuthInterceptor::new(&config.auth);
+        let frame = |request, token: &str| LiteFrame {
+            request,
+            component: "dashboard".to_string(),
+            token: token.to_string(),
+        };
+        let get = || LiteRequest::Get {
+            key: "vehicle/mode".to_string(),
+        };
+
+        let anonymous = LiteFrame {
+            request: get(),
+            component: String::new(),
+            token: String::new(),
+        };
+        let response = handle(&service, &auth, anonymous).await;
+        assert_eq!(response.error_message, "Request names no component");
+        let response = handle(&service, &auth, frame(get(), "wrong")).await;
+        assert_eq!(
+            response.error_message,
+            "Invalid token for component 'dashboard'"
+        );
+
+        // The access control list of the component applies
+        let response = handle(&service, &auth, frame(get(), "5e1f0c")).await;
+        assert!(!response.success);
+        assert!(response.error_message.contains("not found"));
+        let put = LiteRequest::Put {
+            key: "vehicle/mode".to_string(),
+            value: "manual".to_string(),
+        };
+        let response = handle(&service, &auth, frame(put, "5e1f0c")).await;
+        assert!(!response.success);
+        assert!(response.error_message.contains("not writable"));
+    }
+}
@@ -0,0 +1,50 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Locks for the coordination of components
+//!
+//! Several apiserver replicas or workers may pick up the same artifact; the
+//! one holding its lock processes it. `Lock` grants a lease, see
+//! [`crate::lease`], and writes the key `<lock_prefix><name>` of the default
+//! store attached to it, holding the name of the locking component. The id
+//! of the lease is the token of the lock: its holder keeps the lock with
+//! `KeepAlive` and releases it with `Unlock`. A holder that dies releases
+//! the lock once the lease expires.
+//!
+//! `Lock` does not wait: a lock held by a live lease is answered with
+//! `acquired: false` and its holder, and the caller tries again later. A
+//! lock key left without a live lease, e.g. written directly, is free.
+//! Writing or removing a held lock key would detach it from the lease and is
+//! rejected, except for SetValue with the token as its `lease_id`. Restores
+//! and imports skip held locks, `RestoreSnapshot` fails if it would change one.
+//! Callers need write access to the lock key.
+
+/// Key of the lock `name` under `prefix`
+pub fn lock_key(prefix: &str, name: &str) -> Result<String, String> {
+    if name.is_empty() {
+        return Err("A lock needs a name".to_string());
+    }
+    // Keys in angle brackets are reserved for the namespaces
+    if name.contains(['<', '>']) {
+        return Err(format!("Invalid lock name '{}'", name));
+    }
+    Ok(format!("{}{}", prefix, name))
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_key() {
+        assert_eq!(
+            lock_key("locks/", "artifact/helloworld").unwrap(),
+            "locks/artifact/helloworld"
+        );
+        assert!(lock_key("locks/", "").is_err());
+        assert!(lock_key("locks/", "<artifacts>").is_err());
+    }
+}
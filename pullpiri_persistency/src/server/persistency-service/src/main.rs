@@ -8,35 +8,304 @@
 //! A standalone gRPC service that provides centralized persistency for all Pullpiri components.
 
 use common::persistency_proto::persistency_service_server::PersistencyServiceServer;
+use persistency_service::auth::AuthInterceptor;
+use persistency_service::caller::CallerLayer;
+use persistency_service::metrics::MetricsLayer;
+use persistency_service::sampling::TraceSamplingLayer;
+use persistency_service::status::StatusCodesLayer;
+use persistency_service::timing::TimingLayer;
 use persistency_service::PersistencyServiceImpl;
+use std::sync::Arc;
+use tonic::service::interceptor::InterceptedService;
 use tonic::transport::Server;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> common::Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
     info!("Starting Pullpiri Persistency Service");
 
+    let config = persistency_service::config::load();
+
     // Create the persistency service
-    let service = match PersistencyServiceImpl::new() {
-        Ok(service) => service,
+    let service = match PersistencyServiceImpl::with_config(&config) {
+        Ok(service) => Arc::new(service),
         Err(e) => {
-            error!("Failed to initialize persistency service: {:?}", e);
+            error!("Failed to initialize persistency service: {}", e);
             std::process::exit(1);
         }
     };
 
+    let sampler = service.trace_sampler();
+    info!("Request trace sampling: {:?}", sampler.config());
+
     // Get the server address
     let addr = common::persistency::open_server().parse()?;
-    info!("Persistency service listening on {}", addr);
+    let socket_path = config.listen.socket_path.clone();
+    if !config.listen.tcp && socket_path.is_empty() {
+        error!("TCP is turned off and no socket path is configured, gRPC would not be served");
+        std::process::exit(1);
+    }
+    if config.listen.tcp {
+        info!("Persistency service listening on {}", addr);
+    }
+    if !socket_path.is_empty() {
+        info!(
+            "Persistency service listening on unix socket {}",
+            socket_path
+        );
+    }
+    if config.grpc_web.enabled {
+        info!(
+            "gRPC-Web enabled for browser clients (readable prefixes: {:?})",
+            config.grpc_web.read_prefixes
+        );
+    }
+    if config.auth.enabled {
+        info!(
+            "Component authentication enabled ({} components configured)",
+            config.auth.tokens.len()
+        );
+    }
+    if config.acl.enabled {
+        if config.auth.enabled {
+            info!(
+                "Access control lists enabled for {} components",
+                config.acl.components.len()
+            );
+        } else {
+            warn!("Access control lists take no effect without component authentication");
+        }
+    }
+    if config.metrics.enabled {
+        info!("Metrics served at http://{}{}", addr, config.metrics.path);
+    }
+
+    // Describe the services to grpcurl-style tools, over both reflection
+    // versions as older tools only speak v1alpha
+    let (reflection, reflection_v1alpha) = if config.reflection.enabled {
+        info!("gRPC server reflection enabled");
+        let reflection = reflection_builder().build_v1().map_err(reflection_error)?;
+        let reflection_v1alpha = reflection_builder()
+            .build_v1alpha()
+            .map_err(reflection_error)?;
+        (Some(reflection), Some(reflection_v1alpha))
+    } else {
+        (None, None)
+    };
+
+    // Serve clients without gRPC on the same store
+    if config.lite.enabled {
+        let socket_path = config.lite.socket_path.clone();
+        let auth = config.auth.clone();
+        let service = service.clone();
+        tokio::spawn(async move {
+            let served = persistency_service::lite::serve(&socket_path, service, &auth).await;
+            if let Err(e) = served {
+                error!("Lite protocol on {} stopped: {}", socket_path, e);
+            }
+        });
+    }
+
+    // Back up the configured prefixes to the fleet endpoint
+    if config.upload.enabled {
+        let upload = config.upload.clone();
+        let service = service.clone();
+        tokio::spawn(async move {
+            if let Err(e) = persistency_service::upload::run(upload, service).await {
+                error!("Uploader stopped: {:#}", e);
+            }
+        });
+    }
+
+    // Back up the store to the local backup directory
+    if config.backup.enabled && config.backup.interval_secs > 0 {
+        tokio::spawn(persistency_service::backup::run(
+            config.backup.clone(),
+            service.clone(),
+        ));
+    }
+
+    // Keep the trends of numeric values in the time-series sink
+    if config.timeseries.enabled {
+        let timeseries = config.timeseries.clone();
+        let service = service.clone();
+        tokio::spawn(async move {
+            if let Err(e) = persistency_service::timeseries::run(timeseries, service).await {
+                error!("Time-series exporter stopped: {:#}", e);
+            }
+        });
+    }
+
+    // Send a share of the writes to the staging instance
+    if config.mirror.enabled {
+        let mirror = config.mirror.clone();
+        let service = service.clone();
+        tokio::spawn(async move {
+            if let Err(e) = persistency_service::mirror::run(mirror, service).await {
+                error!("Request mirror stopped: {:#}", e);
+            }
+        });
+    }
+
+    // Remove the keys written with a time to live once it is over
+    tokio::spawn(persistency_service::expiry::run(
+        config.expiry.clone(),
+        service.clone(),
+    ));
+
+    // Flush the SetValue writes in batches
+    tokio::spawn(persistency_service::flusher::run(
+        config.flush.clone(),
+        service.clone(),
+    ));
+
+    // Execute the resets whose grace period is over
+    tokio::spawn(persistency_service::destructive::run(service.clone()));
+
+    // Attribute the storage costs to the namespaces
+    if config.usage.enabled {
+        tokio::spawn(persistency_service::usage::run(service.clone()));
+    }
+
+    // Browse and edit the store from a browser during field debugging
+    if config.admin_ui.enabled {
+        #[cfg(feature = "admin-ui")]
+        {
+            let listen_addr = config.admin_ui.listen_addr.clone();
+            let service = service.clone();
+            tokio::spawn(async move {
+                if let Err(e) = persistency_service::admin_ui::serve(&listen_addr, service).await {
+                    error!("Admin web UI on {} stopped: {}", listen_addr, e);
+                }
+            });
+        }
+        #[cfg(not(feature = "admin-ui"))]
+        tracing::warn!("The admin web UI is enabled in the configuration, but this build lacks the admin-ui feature");
+    }
+
+    // Read and write keys over HTTP for dashboards and scripts
+    if config.rest.enabled {
+        #[cfg(feature = "rest-gateway")]
+        {
+            let listen_addr = config.rest.listen_addr.clone();
+            let auth = config.auth.clone();
+            let service = service.clone();
+            tokio::spawn(async move {
+                let served = persistency_service::rest::serve(&listen_addr, service, &auth).await;
+                if let Err(e) = served {
+                    error!("REST gateway on {} stopped: {}", listen_addr, e);
+                }
+            });
+        }
+        #[cfg(not(feature = "rest-gateway"))]
+        tracing::warn!("The REST gateway is enabled in the configuration, but this build lacks the rest-gateway feature");
+    }
 
-    // Start the gRPC server
-    Server::builder()
-        .add_service(PersistencyServiceServer::new(service))
-        .serve(addr)
-        .await?;
+    // Tell orchestrators whether the store takes requests
+    let (reporter, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(persistency_service::health::run(service.clone(), reporter));
+    service.health().set_ready();
+
+    // Start the gRPC server on each transport, flushing the batched writes
+    // once all of them stopped
+    let (stop, stopped) = tokio::sync::watch::channel(false);
+    let stopping = service.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        stopping.health().set_stopping();
+        let _ = stop.send(true);
+    });
+    let router = || {
+        Server::builder()
+            .accept_http1(config.grpc_web.enabled || config.metrics.enabled)
+            .layer(MetricsLayer::new(
+                config.metrics.clone(),
+                service.stats(),
+                service.metrics_sources(),
+            ))
+            .layer(persistency_service::grpc_web::layer(&config.grpc_web))
+            .layer(CallerLayer::new())
+            .layer(TimingLayer::new(&config.slow_requests))
+            .layer(StatusCodesLayer::new())
+            .layer(TraceSamplingLayer::new(sampler.clone()))
+            .add_service(health_service.clone())
+            .add_optional_service(reflection.clone())
+            .add_optional_service(reflection_v1alpha.clone())
+            .add_service(InterceptedService::new(
+                PersistencyServiceServer::from_arc(service.clone()),
+                AuthInterceptor::new(&config.auth),
+            ))
+    };
+    let tcp = async {
+        if config.listen.tcp {
+            router()
+                .serve_with_shutdown(addr, stopped_signal(stopped.clone()))
+                .await?;
+        }
+        Ok::<_, common::error::Error>(())
+    };
+    let unix = async {
+        if !socket_path.is_empty() {
+            let incoming = persistency_service::listen::unix_incoming(&socket_path)?;
+            router()
+                .serve_with_incoming_shutdown(incoming, stopped_signal(stopped.clone()))
+                .await?;
+        }
+        Ok::<_, common::error::Error>(())
+    };
+    tokio::try_join!(tcp, unix)?;
+    service.flush_pending().await;
+    info!("Persistency service stopped");
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Reflection of the persistency and health services
+fn reflection_builder() -> tonic_reflection::server::Builder<'static> {
+    tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(common::persistency_proto::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+}
+
+fn reflection_error(e: tonic_reflection::server::Error) -> common::error::Error {
+    common::error::Error::internal(format!("Invalid service descriptors: {}", e))
+}
+
+/// Completes once the service is told to stop
+async fn stopped_signal(mut stopped: tokio::sync::watch::Receiver<bool>) {
+    let _ = stopped.wait_for(|stopped| *stopped).await;
+}
+
+/// Completes on Ctrl-C or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    info!("Shutting down the persistency service");
+}
@@ -0,0 +1,296 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Prometheus metrics endpoint
+//!
+//! Answers `GET` requests for the configured path on the gRPC port with the
+//! metrics of every [`MetricsSource`] in the Prometheus text format, such as
+//! the storage usage and forecast of the [`StorageForecaster`]. Every other
+//! request is passed on to the tonic router, and the persistency RPCs among
+//! them are counted and timed in the [`ServiceStats`].
+
+use crate::cache::ReadCache;
+use crate::config::MetricsConfig;
+use crate::forecast::StorageForecaster;
+use crate::mirror::Mirror;
+use crate::recovery::RecoveryStatus;
+use crate::spill::SpillStore;
+use crate::stats::{ServiceStats, StoreStats};
+use crate::timeseries::TimeSeriesStatus;
+use http::{header, HeaderValue, Method, Request, Response};
+use http_body_util::Full;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::body::BoxBody;
+use tonic::Code;
+use tower::{Layer, Service};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Component exposing metrics on the endpoint
+pub trait MetricsSource: Send + Sync {
+    /// Current metrics in the Prometheus text format
+    fn render_metrics(&self) -> String;
+}
+
+impl MetricsSource for StorageForecaster {
+    fn render_metrics(&self) -> String {
+        StorageForecaster::render_metrics(self)
+    }
+}
+
+impl MetricsSource for Mirror {
+    fn render_metrics(&self) -> String {
+        Mirror::render_metrics(self)
+    }
+}
+
+impl MetricsSource for RecoveryStatus {
+    fn render_metrics(&self) -> String {
+        RecoveryStatus::render_metrics(self)
+    }
+}
+
+impl MetricsSource for SpillStore {
+    fn render_metrics(&self) -> String {
+        SpillStore::render_metrics(self)
+    }
+}
+
+impl MetricsSource for ReadCache {
+    fn render_metrics(&self) -> String {
+        ReadCache::render_metrics(self)
+    }
+}
+
+impl MetricsSource for TimeSeriesStatus {
+    fn render_metrics(&self) -> String {
+        TimeSeriesStatus::render_metrics(self)
+    }
+}
+
+impl MetricsSource for ServiceStats {
+    fn render_metrics(&self) -> String {
+        ServiceStats::render_metrics(self)
+    }
+}
+
+impl MetricsSource for StoreStats {
+    fn render_metrics(&self) -> String {
+        StoreStats::render_metrics(self)
+    }
+}
+
+/// Tower layer serving the metrics in front of the tonic router
+///
+/// When the metrics are disabled in the configuration the layer passes every
+/// request through unchanged.
+#[derive(Clone)]
+pub struct MetricsLayer {
+    config: Arc<MetricsConfig>,
+    stats: Arc<ServiceStats>,
+    sources: Arc<[Arc<dyn MetricsSource>]>,
+}
+
+impl MetricsLayer {
+    /// ### Parameters
+    /// * `config` - endpoint configuration
+    /// * `stats` - counters the RPCs are recorded in
+    /// * `sources` - components whose metrics are served, in this order
+    pub fn new(
+        config: MetricsConfig,
+        stats: Arc<ServiceStats>,
+        sources: Vec<Arc<dyn MetricsSource>>,
+    ) -> Self {
+        Self {
+            config: Arc::new(config),
+            stats,
+            sources: sources.into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            config: self.config.clone(),
+            stats: self.stats.clone(),
+            sources: self.sources.clone(),
+        }
+    }
+}
+
+/// Service produced by [`MetricsLayer`]
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+    config: Arc<MetricsConfig>,
+    stats: Arc<ServiceStats>,
+    sources: Arc<[Arc<dyn MetricsSource>]>,
+}
+
+impl<S> Service<Request<BoxBody>> for MetricsService<S>
+where
+    S: Service<Request<BoxBody>, Response = Response<BoxBody>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<BoxBody>) -> Self::Future {
+        if !self.config.enabled {
+            return Box::pin(self.inner.call(req));
+        }
+        if req.method() != Method::GET || req.uri().path() != self.config.path {
+            let Some(method) = ServiceStats::method(req.uri().path()).map(str::to_string) else {
+                return Box::pin(self.inner.call(req));
+            };
+            let stats = self.stats.clone();
+            let started = Instant::now();
+            let future = self.inner.call(req);
+            return Box::pin(async move {
+                let response = future.await?;
+                // Failures are answered with headers only, successes carry the status in the trailers
+                let code = response
+                    .headers()
+                    .get("grpc-status")
+                    .map_or(Code::Ok, |status| Code::from_bytes(status.as_bytes()));
+                stats.record_rpc(&method, code, started.elapsed());
+                Ok(response)
+            });
+        }
+
+        let body: String = self
+            .sources
+            .iter()
+            .map(|source| source.render_metrics())
+            .collect();
+        Box::pin(async move {
+            let mut response = Response::new(tonic::body::boxed(Full::from(body)));
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+            Ok(response)
+        })
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use crate::config::StorageForecastConfig;
+    use http::StatusCode;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use tower::ServiceExt;
+
+    fn metrics_service(
+        enabled: bool,
+        stats: Arc<ServiceStats>,
+    ) -> impl Service<Request<BoxBody>, Response = Response<BoxBody>, Error = Infallible> {
+        let forecast = Arc::new(StorageForecaster::new(
+            &StorageForecastConfig::default(),
+            Arc::new(TestClock::new(0)),
+            std::env::temp_dir(),
+        ));
+        forecast.record_write("vehicle/mode", None, 42);
+        let config = MetricsConfig {
+            enabled,
+            ..Default::default()
+        };
+        let sources = vec![forecast as Arc<dyn MetricsSource>, stats.clone()];
+        MetricsLayer::new(config, stats, sources).layer(tower::service_fn(
+            |req: Request<BoxBody>| async move {
+                let mut response = Response::new(tonic::body::empty_body());
+                if req.uri().path().ends_with("/GetValue") {
+                    let status = HeaderValue::from(Code::NotFound as i32);
+                    response.headers_mut().insert("grpc-status", status);
+                } else {
+                    *response.status_mut() = StatusCode::IM_A_TEAPOT;
+                }
+                Ok::<_, Infallible>(response)
+            },
+        ))
+    }
+
+    fn get(path: &str) -> Request<BoxBody> {
+        Request::get(path).body(tonic::body::empty_body()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_serves_metrics() {
+        let response = metrics_service(true, Arc::default())
+            .oneshot(get("/metrics"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("persistency_prefix_stored_bytes{prefix=\"vehicle/\"} 42"));
+        assert!(body.contains("# TYPE persistency_days_until_full gauge"));
+    }
+
+    #[tokio::test]
+    async fn test_passes_other_requests_through() {
+        let response = metrics_service(true, Arc::default())
+            .oneshot(get("/other"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+        let response = metrics_service(false, Arc::default())
+            .oneshot(get("/metrics"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+    }
+
+    #[tokio::test]
+    async fn test_counts_rpcs() {
+        let stats = Arc::new(ServiceStats::new());
+        let mut service = metrics_service(true, stats.clone());
+        for path in [
+            "/persistency.PersistencyService/SetValue",
+            "/persistency.PersistencyService/GetValue",
+            "/other",
+        ] {
+            service
+                .ready()
+                .await
+                .unwrap()
+                .call(get(path))
+                .await
+                .unwrap();
+        }
+        assert_eq!(stats.rpcs("SetValue", Code::Ok), 1);
+        assert_eq!(stats.rpcs("GetValue", Code::NotFound), 1);
+
+        let response = service.oneshot(get("/metrics")).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("persistency_rpcs_total{method=\"SetValue\",code=\"Ok\"} 1"));
+        assert!(!body.contains("method=\"other\""));
+
+        let disabled = Arc::new(ServiceStats::new());
+        metrics_service(false, disabled.clone())
+            .oneshot(get("/persistency.PersistencyService/SetValue"))
+            .await
+            .unwrap();
+        assert_eq!(disabled.rpcs("SetValue", Code::Ok), 0);
+    }
+}
@@ -0,0 +1,454 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Mirroring of write RPCs to a staging instance
+//!
+//! New builds of the service are validated against real vehicle traffic
+//! before they are promoted. With `mirror.enabled` a share of the write RPCs
+//! (`SetValue`, `SetValues`, `RemoveKey`, `RemoveKeys`, `CompareAndSet`,
+//! `Increment` and `Decrement`) is sent once more to the staging instance at
+//! `mirror.endpoint`. Resets, restores, admin RPCs and chunked uploads are
+//! never mirrored.
+//!
+//! Handlers copy a sampled request that passed the access checks, and queue
+//! the copy only once the primary committed the write, so that writes the
+//! primary rejects, e.g. for a quota or a revision conflict, never reach the
+//! staging instance. A background task sends the queued writes one after the
+//! other, in the order they were committed and with the identity of the
+//! original caller. The primary neither waits for the staging instance nor
+//! looks at its answers, so its latency and results stay the same. A write
+//! committed while the queue is full is not mirrored.
+//!
+//! Sampling is deterministic like the trace sampling: 10% samples exactly
+//! every 10th write received. Below 100% the staging store only sees part of the
+//! writes, so e.g. its counters differ from the primary's. Its key revisions
+//! differ as well, so `SetValue` is mirrored without its `expected_revision`,
+//! and without its `lease_id`, as leases are granted by the primary only.
+//! `RemoveKey` is mirrored without `trash`, as the staging instance need not
+//! have a trash configured.
+//! Sent, dropped, failed and rejected writes are counted on the metrics
+//! endpoint.
+
+use crate::caller;
+use crate::config::MirrorConfig;
+use crate::PersistencyServiceImpl;
+use common::error::Error;
+use common::identity::ComponentIdentity;
+use common::persistency_proto::persistency_service_client::PersistencyServiceClient;
+use common::persistency_proto::{
+    CompareAndSetRequest, DecrementRequest, IncrementRequest, RemoveKeyRequest, RemoveKeysRequest,
+    SetValueRequest, SetValuesRequest,
+};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tonic::transport::{Channel, Endpoint};
+use tonic::Status;
+use tracing::{debug, info, warn};
+
+/// Mirroring shares are stored as parts per million
+const RATE_SCALE: u64 = 1_000_000;
+
+/// Copy of a write RPC for the staging instance
+#[derive(Debug, Clone, PartialEq)]
+pub enum MirroredWrite {
+    SetValue(SetValueRequest),
+    SetValues(SetValuesRequest),
+    RemoveKey(RemoveKeyRequest),
+    RemoveKeys(RemoveKeysRequest),
+    CompareAndSet(CompareAndSetRequest),
+    Increment(IncrementRequest),
+    Decrement(DecrementRequest),
+}
+
+impl MirroredWrite {
+    /// gRPC method name of the write
+    pub fn method(&self) -> &'static str {
+        match self {
+            MirroredWrite::SetValue(_) => "SetValue",
+            MirroredWrite::SetValues(_) => "SetValues",
+            MirroredWrite::RemoveKey(_) => "RemoveKey",
+            MirroredWrite::RemoveKeys(_) => "RemoveKeys",
+            MirroredWrite::CompareAndSet(_) => "CompareAndSet",
+            MirroredWrite::Increment(_) => "Increment",
+            MirroredWrite::Decrement(_) => "Decrement",
+        }
+    }
+}
+
+/// Queued write with the caller of the original request
+#[derive(Debug)]
+struct QueuedWrite {
+    write: MirroredWrite,
+    caller: Option<Arc<ComponentIdentity>>,
+}
+
+/// Copy of a sampled write, queued by [`Mirror::commit`]
+///
+/// Dropped without being queued if the primary rejects the write.
+#[derive(Debug)]
+#[must_use]
+pub struct SampledWrite(Option<QueuedWrite>);
+
+/// Sampling and queue of the mirrored writes, with their counters
+#[derive(Debug)]
+pub struct Mirror {
+    enabled: bool,
+    rate: u64,
+    count: AtomicU64,
+    sender: mpsc::Sender<QueuedWrite>,
+    /// Taken by the [`run`] task
+    receiver: Mutex<Option<mpsc::Receiver<QueuedWrite>>>,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    failures: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl Mirror {
+    pub fn new(config: &MirrorConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.queue_capacity.max(1));
+        Self {
+            enabled: config.enabled,
+            rate: (config.percent.clamp(0.0, 100.0) / 100.0 * RATE_SCALE as f64).round() as u64,
+            count: AtomicU64::new(0),
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            sent: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Decide whether the next write is mirrored
+    fn sample(&self) -> bool {
+        let n = self.count.fetch_add(1, Ordering::Relaxed);
+        // Mirror whenever the accumulated share crosses the next integer
+        (n + 1) * self.rate / RATE_SCALE > n * self.rate / RATE_SCALE
+    }
+
+    /// Copy the write built by `write` if it is sampled
+    ///
+    /// `write` is only called for sampled writes, so that the others are not copied.
+    pub fn sample_write(&self, write: impl FnOnce() -> MirroredWrite) -> SampledWrite {
+        if !self.enabled || !self.sample() {
+            return SampledWrite(None);
+        }
+        SampledWrite(Some(QueuedWrite {
+            write: write(),
+            caller: caller::caller(),
+        }))
+    }
+
+    /// Queue `sampled` once the primary committed it, without waiting
+    pub fn commit(&self, sampled: SampledWrite) {
+        let Some(queued) = sampled.0 else {
+            return;
+        };
+        if self.sender.try_send(queued).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Receiving end of the queue, `None` once taken
+    fn take_queue(&self) -> Option<mpsc::Receiver<QueuedWrite>> {
+        self.receiver.lock().ok()?.take()
+    }
+
+    /// Writes sent to the staging instance, including the rejected ones
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    /// Sampled writes not mirrored because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Mirrored writes the staging instance did not answer or answered with a gRPC error
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Mirrored writes the staging instance answered with `success: false`
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Mirroring metrics in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let queued = (self.sender.max_capacity() - self.sender.capacity()) as u64;
+        let metrics = [
+            (
+                "persistency_mirror_sent_total",
+                "counter",
+                "Writes mirrored to the staging instance",
+                self.sent(),
+            ),
+            (
+                "persistency_mirror_dropped_total",
+                "counter",
+                "Sampled writes not mirrored because the queue was full",
+                self.dropped(),
+            ),
+            (
+                "persistency_mirror_failures_total",
+                "counter",
+                "Mirrored writes the staging instance did not answer or answered with an error",
+                self.failures(),
+            ),
+            (
+                "persistency_mirror_rejected_total",
+                "counter",
+                "Mirrored writes the staging instance answered with success false",
+                self.rejected(),
+            ),
+            (
+                "persistency_mirror_queued_writes",
+                "gauge",
+                "Writes waiting for the staging instance",
+                queued,
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(
+                out,
+                "# HELP {} {}\n# TYPE {} {}\n{} {}",
+                name, help, name, kind, name, value
+            );
+        }
+        out
+    }
+}
+
+/// Request carrying `message` with the identity of `caller`
+fn request<T>(message: T, caller: Option<&ComponentIdentity>) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    if let Some(caller) = caller {
+        let metadata = request.metadata_mut();
+        for (name, value) in caller.metadata() {
+            // Values that are not valid metadata are left out, as by the clients
+            if let Ok(value) = value.parse() {
+                metadata.insert(name, value);
+            }
+        }
+    }
+    request
+}
+
+/// Send `queued` to the staging instance, whether it reported success
+async fn send(
+    client: &mut PersistencyServiceClient<Channel>,
+    queued: QueuedWrite,
+) -> Result<bool, Status> {
+    let caller = queued.caller.as_deref();
+    let success = match queued.write {
+        MirroredWrite::SetValue(mut write) => {
+            write.expected_revision = 0;
+            write.lease_id = 0;
+            client
+                .set_value(request(write, caller))
+                .await?
+                .into_inner()
+                .success
+        }
+        MirroredWrite::SetValues(write) => {
+            client
+                .set_values(request(write, caller))
+                .await?
+                .into_inner()
+                .success
+        }
+        MirroredWrite::RemoveKey(mut write) => {
+            write.trash = false;
+            client
+                .remove_key(request(write, caller))
+                .await?
+                .into_inner()
+                .success
+        }
+        MirroredWrite::RemoveKeys(write) => {
+            client
+                .remove_keys(request(write, caller))
+                .await?
+                .into_inner()
+                .success
+        }
+        MirroredWrite::CompareAndSet(write) => {
+            client
+                .compare_and_set(request(write, caller))
+                .await?
+                .into_inner()
+                .success
+        }
+        MirroredWrite::Increment(write) => {
+            client
+                .increment(request(write, caller))
+                .await?
+                .into_inner()
+                .success
+        }
+        MirroredWrite::Decrement(write) => {
+            client
+                .decrement(request(write, caller))
+                .await?
+                .into_inner()
+                .success
+        }
+    };
+    Ok(success)
+}
+
+/// Send the queued writes to `config.endpoint` until the service stops
+pub async fn run(config: MirrorConfig, service: Arc<PersistencyServiceImpl>) -> common::Result<()> {
+    let mirror = service.mirror();
+    let mut queue = mirror
+        .take_queue()
+        .ok_or_else(|| Error::internal("Writes are already mirrored by another task"))?;
+    if config.endpoint.is_empty() {
+        return Err(Error::config(
+            "No endpoint configured for the mirrored writes",
+        ));
+    }
+    let timeout = Duration::from_millis(config.timeout_ms.max(1));
+    let channel = Endpoint::from_shared(config.endpoint.clone())
+        .map_err(|e| Error::config(format!("Invalid endpoint {}: {}", config.endpoint, e)))?
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .connect_lazy();
+    let mut client = PersistencyServiceClient::new(channel);
+    info!(
+        "Mirroring {}% of the writes to {}",
+        config.percent, config.endpoint
+    );
+
+    // Log when the staging instance becomes unavailable and when it is back, not every failure
+    let mut available = true;
+    while let Some(queued) = queue.recv().await {
+        let method = queued.write.method();
+        match send(&mut client, queued).await {
+            Ok(success) => {
+                mirror.sent.fetch_add(1, Ordering::Relaxed);
+                if !success {
+                    mirror.rejected.fetch_add(1, Ordering::Relaxed);
+                    debug!("Staging instance rejected the mirrored {}", method);
+                }
+                if !available {
+                    info!("Staging instance {} is available again", config.endpoint);
+                    available = true;
+                }
+            }
+            Err(status) => {
+                mirror.failures.fetch_add(1, Ordering::Relaxed);
+                if available {
+                    warn!(
+                        "Mirrored {} to {} failed: {}",
+                        method, config.endpoint, status
+                    );
+                    available = false;
+                } else {
+                    debug!("Mirrored {} failed: {}", method, status);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirror(percent: f64, queue_capacity: usize) -> Mirror {
+        Mirror::new(&MirrorConfig {
+            enabled: true,
+            endpoint: "http://127.0.0.1:47007".to_string(),
+            percent,
+            queue_capacity,
+            timeout_ms: 1000,
+        })
+    }
+
+    fn write(key: &str) -> MirroredWrite {
+        MirroredWrite::RemoveKey(RemoveKeyRequest {
+            key: key.to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_percent_is_mirrored_in_order() {
+        let mirror = mirror(10.0, 100);
+        for i in 0..100 {
+            mirror.commit(mirror.sample_write(|| write(&format!("vehicle/key/{}", i))));
+        }
+        let mut queue = mirror.take_queue().unwrap();
+        let keys: Vec<_> = std::iter::from_fn(|| queue.try_recv().ok())
+            .map(|queued| match queued.write {
+                MirroredWrite::RemoveKey(request) => request.key,
+                other => panic!("unexpected write {:?}", other),
+            })
+            .collect();
+        assert_eq!(keys.len(), 10);
+        assert_eq!(keys[0], "vehicle/key/9");
+        assert_eq!(keys[9], "vehicle/key/99");
+        assert!(mirror.take_queue().is_none());
+    }
+
+    #[test]
+    fn test_full_queue_drops_writes() {
+        let mirror = mirror(100.0, 2);
+        for _ in 0..3 {
+            mirror.commit(mirror.sample_write(|| write("vehicle/mode")));
+        }
+        assert_eq!(mirror.dropped(), 1);
+        assert!(mirror
+            .render_metrics()
+            .contains("persistency_mirror_queued_writes 2\n"));
+
+        let disabled = Mirror::new(&MirrorConfig::default());
+        disabled.commit(disabled.sample_write(|| panic!("disabled mirror copied a write")));
+        assert_eq!(disabled.dropped(), 0);
+    }
+
+    #[test]
+    fn test_rejected_writes_are_not_queued() {
+        let mirror = mirror(100.0, 10);
+        let rejected = mirror.sample_write(|| write("vehicle/mode"));
+        drop(rejected);
+        mirror.commit(mirror.sample_write(|| write("vehicle/gear")));
+
+        let mut queue = mirror.take_queue().unwrap();
+        match queue.try_recv().unwrap().write {
+            MirroredWrite::RemoveKey(request) => assert_eq!(request.key, "vehicle/gear"),
+            other => panic!("unexpected write {:?}", other),
+        }
+        assert!(queue.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_request_carries_caller_identity() {
+        let caller = ComponentIdentity {
+            name: "statemanager".to_string(),
+            instance: "1".to_string(),
+            version: "0.1.0".to_string(),
+            node: "HPC".to_string(),
+        };
+        let request = request(write("vehicle/mode"), Some(&caller));
+        let found = ComponentIdentity::from_lookup(|name| {
+            request
+                .metadata()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        });
+        assert_eq!(found, Some(caller));
+        assert!(super::request((), None).metadata().is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Namespaces of the store
+//!
+//! Next to the default store, the service hosts the rust_kvs instances
+//! configured under `namespaces.instances`, e.g. to keep the apiserver
+//! artifacts apart from the runtime state of the filtergateway. Requests
+//! select a store with their `namespace` field, an empty namespace is the
+//! default store. Every namespace has its own storage file and its own
+//! pending changes, so it can be reset or flushed without touching the
+//! others.
+//!
+//! Key expiry, change notifications, the storage forecast, the usage reports
+//! and the diagnostics are shared by all stores. They know the keys of a
+//! namespace as `<namespace>/key`, see [`tracked_key`], so keys of that form
+//! are reserved in the default store. Bounded memory mode, exports,
+//! restores, snapshots and the recovery at startup only cover the default
+//! store.
+
+use crate::backend::{self, StorageBackend};
+use crate::config::{NamespacesConfig, StorageConfig};
+use crate::durability::PendingChanges;
+use crate::key_index::KeyIndex;
+use crate::spill::SpillStore;
+use crate::striping::KeyLocks;
+use rust_kvs::prelude::*;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::{error, info};
+
+/// rust_kvs instance of the default store
+pub const DEFAULT_INSTANCE: usize = 0;
+
+/// One rust_kvs instance with the state kept per store
+pub struct Store {
+    /// Namespace name, empty for the default store
+    name: String,
+    /// Keys and values, see [`crate::backend`]
+    kvs: RwLock<Box<dyn StorageBackend>>,
+    /// Spilled keys in bounded memory mode
+    spill: Option<Arc<SpillStore>>,
+    /// Sorted keys, unless in bounded memory mode
+    index: Option<KeyIndex>,
+    pending: PendingChanges,
+    /// Locks of single keys, see [`crate::striping`]
+    keys: KeyLocks,
+}
+
+impl Store {
+    pub fn new(
+        name: &str,
+        kvs: Box<dyn StorageBackend>,
+        spill: Option<Arc<SpillStore>>,
+    ) -> Result<Self, ErrorCode> {
+        let index = match spill {
+            Some(_) => None,
+            None => Some(KeyIndex::new(kvs.get_all_keys()?)),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            kvs: RwLock::new(kvs),
+            spill,
+            index,
+            pending: PendingChanges::new(),
+            keys: KeyLocks::default(),
+        })
+    }
+
+    /// Namespace name, empty for the default store
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// Share the store with other readers
+    pub async fn read(&self) -> RwLockReadGuard<'_, Box<dyn StorageBackend>> {
+        self.kvs.read().await
+    }
+
+    /// Share the store with other readers, `None` while a writer holds it
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, Box<dyn StorageBackend>>> {
+        self.kvs.try_read().ok()
+    }
+
+    /// Hold off every other request to the store
+    pub async fn write(&self) -> RwLockWriteGuard<'_, Box<dyn StorageBackend>> {
+        self.kvs.write().await
+    }
+
+    /// Hold off the other writers of `key`, while sharing the store with [`Self::read`]
+    pub async fn lock_key(&self, key: &str) -> MutexGuard<'_, ()> {
+        self.keys.lock(key).await
+    }
+
+    pub fn spill(&self) -> Option<&SpillStore> {
+        self.spill.as_deref()
+    }
+
+    /// Sorted keys of the store, see [`crate::key_index`]
+    pub fn index(&self) -> Option<&KeyIndex> {
+        self.index.as_ref()
+    }
+
+    /// Keys of the store whose latest change is not durable yet
+    pub fn pending(&self) -> &PendingChanges {
+        &self.pending
+    }
+
+    /// `key` as known to the state shared by all stores
+    pub fn tracked_key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        tracked_key(&self.name, key)
+    }
+}
+
+/// `key` of `namespace` as known to the state shared by all stores
+///
+/// Keys of the default store are tracked as they are.
+pub fn tracked_key<'a>(namespace: &str, key: &'a str) -> Cow<'a, str> {
+    if namespace.is_empty() {
+        Cow::Borrowed(key)
+    } else {
+        Cow::Owned(format!("<{}>/{}", namespace, key))
+    }
+}
+
+/// Namespace and key of a tracked key, an empty namespace for the default store
+pub fn split_tracked(tracked: &str) -> (&str, &str) {
+    tracked
+        .strip_prefix('<')
+        .and_then(|rest| rest.split_once(">/"))
+        .filter(|(namespace, _)| is_valid_name(namespace))
+        .unwrap_or(("", tracked))
+}
+
+/// Whether `name` can name a namespace: letters, digits, `-` and `_`
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Check the names and rust_kvs instances of the configured namespaces
+pub fn validate(config: &NamespacesConfig) -> Result<(), String> {
+    let mut instances = HashSet::new();
+    for (name, &instance) in &config.instances {
+        if !is_valid_name(name) {
+            return Err(format!(
+                "Invalid namespace name '{}', use letters, digits, '-' and '_'",
+                name
+            ));
+        }
+        if instance == DEFAULT_INSTANCE || instance >= KvsBuilder::max_instances() {
+            return Err(format!(
+                "Namespace {} uses instance {}, expected 1 to {}",
+                name,
+                instance,
+                KvsBuilder::max_instances() - 1
+            ));
+        }
+        if !instances.insert(instance) {
+            return Err(format!(
+                "Namespace {} uses instance {} of another namespace",
+                name, instance
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The default store and the configured namespaces
+pub struct Namespaces {
+    default: Arc<Store>,
+    named: BTreeMap<String, Arc<Store>>,
+}
+
+impl Namespaces {
+    /// Open the configured namespaces next to the `default` store
+    ///
+    /// The namespaces are kept in the `storage` backend in `dir`. `opened` is
+    /// called with the name and the stored data of every namespace before it
+    /// takes requests.
+    pub fn open(
+        config: &NamespacesConfig,
+        storage: &StorageConfig,
+        dir: &Path,
+        default: Store,
+        mut opened: impl FnMut(&str, &dyn StorageBackend) -> Result<(), ErrorCode>,
+    ) -> Result<Self, ErrorCode> {
+        if let Err(e) = validate(config) {
+            error!("{}", e);
+            return Err(ErrorCode::InvalidInstanceId);
+        }
+        let mut named = BTreeMap::new();
+        for (name, &instance) in &config.instances {
+            let kvs = backend::open(storage, dir, instance)?;
+            opened(name, kvs.as_ref())?;
+            info!("Namespace {} stored in instance {}", name, instance);
+            named.insert(name.clone(), Arc::new(Store::new(name, kvs, None)?));
+        }
+        Ok(Self {
+            default: Arc::new(default),
+            named,
+        })
+    }
+
+    /// Store of `namespace`, the default store for an empty namespace
+    pub fn get(&self, namespace: &str) -> Option<&Arc<Store>> {
+        if namespace.is_empty() {
+            Some(&self.default)
+        } else {
+            self.named.get(namespace)
+        }
+    }
+
+    /// Whether any namespace is configured next to the default store
+    pub fn has_named(&self) -> bool {
+        !self.named.is_empty()
+    }
+
+    pub fn default_store(&self) -> &Arc<Store> {
+        &self.default
+    }
+
+    /// Every store, the default store first
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Store>> {
+        std::iter::once(&self.default).chain(self.named.values())
+    }
+
+    /// Store and key of a tracked key
+    pub fn resolve<'a>(&self, tracked: &'a str) -> (&Arc<Store>, &'a str) {
+        let (namespace, key) = split_tracked(tracked);
+        match self.named.get(namespace) {
+            Some(store) => (store, key),
+            None => (&self.default, tracked),
+        }
+    }
+
+    /// Whether `key` of the default store would be taken for a key of a namespace
+    pub fn is_reserved(&self, key: &str) -> bool {
+        !self.resolve(key).0.is_default()
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracked_keys_round_trip() {
+        assert_eq!(tracked_key("", "vehicle/mode"), "vehicle/mode");
+        assert_eq!(
+            tracked_key("artifacts", "scenario/helloworld"),
+            "<artifacts>/scenario/helloworld"
+        );
+
+        assert_eq!(
+            split_tracked("<artifacts>/scenario/helloworld"),
+            ("artifacts", "scenario/helloworld")
+        );
+        assert_eq!(split_tracked("vehicle/mode"), ("", "vehicle/mode"));
+        assert_eq!(split_tracked("<not a name>/key"), ("", "<not a name>/key"));
+        assert_eq!(split_tracked("<runtime"), ("", "<runtime"));
+    }
+
+    #[test]
+    fn test_valid_names() {
+        assert!(is_valid_name("artifacts"));
+        assert!(is_valid_name("filter-gateway_2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("run/time"));
+        assert!(!is_valid_name("<runtime>"));
+    }
+
+    #[test]
+    fn test_validate_instances() {
+        let mut config = NamespacesConfig::default();
+        assert!(validate(&config).is_ok());
+
+        config.instances.insert("artifacts".to_string(), 2);
+        config.instances.insert("runtime".to_string(), 3);
+        assert!(validate(&config).is_ok());
+
+        // The default store is instance 0
+        config.instances.insert("runtime".to_string(), 0);
+        assert!(validate(&config).is_err());
+
+        config.instances.insert("runtime".to_string(), 2);
+        let err = validate(&config).unwrap_err();
+        assert_eq!(
+            err,
+            "Namespace runtime uses instance 2 of another namespace"
+        );
+
+        config.instances.insert("runtime".to_string(), 10);
+        assert!(validate(&config).is_err());
+
+        config.instances.remove("runtime");
+        config.instances.insert("run time".to_string(), 3);
+        assert!(validate(&config).is_err());
+    }
+}
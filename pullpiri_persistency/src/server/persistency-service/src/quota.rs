@@ -0,0 +1,154 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Limits on the values and keys the clients store
+//!
+//! A component writing ever new keys or oversized values would fill the
+//! flash of the ECU and keep every other component from persisting its
+//! state. The `quotas` limit the encoded size of a value, the number of keys
+//! of a store and the number of keys of a store under configured prefixes.
+//! A write beyond a limit is rejected with `RESOURCE_EXHAUSTED` before
+//! anything is written; a batch is rejected as a whole.
+//!
+//! The limits apply to the writes of the clients: `SetValue`, which
+//! `SetLargeValue` and the lite protocol go through, `SetValues`,
+//! `CompareAndSet` and the counters. Restores, keys restored from the trash
+//! and keys written by the service itself are not limited, and a store
+//! beyond a limit keeps its keys. Writers of different new keys count the
+//! stored keys concurrently, so a key limit may be exceeded by the writes
+//! in flight.
+
+use crate::config::QuotaConfig;
+
+/// Limits of the client writes
+#[derive(Debug)]
+pub struct Quotas {
+    config: QuotaConfig,
+}
+
+impl Quotas {
+    pub fn new(config: &QuotaConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Check the encoded `size` of a value to write
+    pub fn check_value(&self, size: u64) -> Result<(), String> {
+        let max = self.config.max_value_bytes;
+        if max > 0 && size > max {
+            return Err(format!(
+                "Value of {} bytes exceeds the quota of {} bytes",
+                size, max
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether adding `key` to a store is limited, so that its keys have to be counted
+    pub fn limits_keys(&self, key: &str) -> bool {
+        self.config.max_keys > 0
+            || self
+                .config
+                .prefixes
+                .keys()
+                .any(|prefix| key.starts_with(prefix.as_str()))
+    }
+
+    /// Check that adding the new keys `added` to a store holding `stored` stays within the limits
+    pub fn check_keys(&self, stored: &[String], added: &[&str]) -> Result<(), String> {
+        if added.is_empty() {
+            return Ok(());
+        }
+        let max = self.config.max_keys;
+        if max > 0 && (stored.len() + added.len()) as u64 > max {
+            return Err(format!("Quota of {} keys exceeded", max));
+        }
+        for (prefix, max) in &self.config.prefixes {
+            let adding = added
+                .iter()
+                .filter(|key| key.starts_with(prefix.as_str()))
+                .count();
+            if adding == 0 {
+                continue;
+            }
+            let holding = stored
+                .iter()
+                .filter(|key| key.starts_with(prefix.as_str()))
+                .count();
+            if (holding + adding) as u64 > *max {
+                return Err(format!(
+                    "Quota of {} keys under prefix {} exceeded",
+                    max, prefix
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn test_value_size() {
+        let quotas = Quotas::new(&QuotaConfig {
+            max_value_bytes: 100,
+            ..Default::default()
+        });
+        assert!(quotas.check_value(100).is_ok());
+        assert!(quotas.check_value(101).is_err());
+        assert!(Quotas::new(&QuotaConfig::default())
+            .check_value(u64::MAX)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_key_count() {
+        let quotas = Quotas::new(&QuotaConfig {
+            max_keys: 3,
+            ..Default::default()
+        });
+        assert!(quotas.limits_keys("vehicle/mode"));
+        let stored = keys(&["vehicle/mode", "vehicle/gear"]);
+        assert!(quotas.check_keys(&stored, &["vehicle/speed"]).is_ok());
+        assert!(quotas
+            .check_keys(&stored, &["vehicle/speed", "vehicle/vin"])
+            .is_err());
+        // Rewriting stored keys adds none
+        let full = keys(&[
+            "vehicle/mode",
+            "vehicle/gear",
+            "vehicle/speed",
+            "vehicle/vin",
+        ]);
+        assert!(quotas.check_keys(&full, &[]).is_ok());
+
+        let unlimited = Quotas::new(&QuotaConfig::default());
+        assert!(!unlimited.limits_keys("vehicle/mode"));
+        assert!(unlimited.check_keys(&full, &["vehicle/door"]).is_ok());
+    }
+
+    #[test]
+    fn test_keys_under_prefix() {
+        let quotas = Quotas::new(&QuotaConfig {
+            prefixes: [("diag/".to_string(), 2)].into(),
+            ..Default::default()
+        });
+        assert!(quotas.limits_keys("diag/dtc/1"));
+        assert!(!quotas.limits_keys("vehicle/mode"));
+
+        let stored = keys(&["diag/dtc/1", "diag/dtc/2", "vehicle/mode"]);
+        let error = quotas.check_keys(&stored, &["diag/dtc/3"]).unwrap_err();
+        assert!(error.contains("diag/"), "{}", error);
+        assert!(quotas.check_keys(&stored, &["vehicle/gear"]).is_ok());
+    }
+}
@@ -0,0 +1,291 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Recovery from partially written storage files
+//!
+//! rust_kvs flushes by rotating the snapshots and then writing the store file
+//! followed by its checksum file. Losing power in between leaves a truncated
+//! store file or one without checksum file. rust_kvs then either refuses to
+//! open the store, which stopped the service at startup, or silently opens it
+//! empty. Before the store is opened, the current store file is therefore
+//! verified. A rejected store file is replaced by the newest intact snapshot
+//! and moved aside with a `.damaged-<ms>` suffix for analysis. Without an
+//! intact snapshot the store starts empty.
+//!
+//! The recovery is reported by `GetServiceInfo` and the
+//! `persistency_storage_recoveries_total` metric.
+
+use crate::integrity::verify_files;
+use common::persistency_proto::StorageRecovery;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Snapshots kept by rust_kvs, including the current store file
+pub const SNAPSHOT_COUNT: usize = 3;
+
+/// Recovery performed before the store was opened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recovery {
+    /// Why the store file was rejected
+    pub reason: String,
+    /// Snapshot the store was restored from, `None` if it started empty
+    pub restored_snapshot: Option<usize>,
+    /// Rejected files, moved aside
+    pub preserved_files: Vec<PathBuf>,
+    /// Unix time of the recovery
+    pub recovered_at_ms: u64,
+}
+
+/// Store and checksum file of a snapshot, named like rust_kvs's JSON backend does
+pub(crate) fn snapshot_files(dir: &Path, instance: usize, snapshot: usize) -> (PathBuf, PathBuf) {
+    (
+        dir.join(format!("kvs_{}_{}.json", instance, snapshot)),
+        dir.join(format!("kvs_{}_{}.hash", instance, snapshot)),
+    )
+}
+
+/// Check that a snapshot can be loaded by rust_kvs
+fn check(kvs_path: &Path, hash_path: &Path) -> Result<(), String> {
+    verify_files(kvs_path, hash_path)?;
+    let content = fs::read(kvs_path).map_err(|e| format!("Failed to read store file: {}", e))?;
+    match serde_json::from_slice::<serde_json::Value>(&content) {
+        Ok(value) if value.is_object() => Ok(()),
+        Ok(_) => Err("Store file does not contain an object".to_string()),
+        Err(e) => Err(format!("Store file is not valid JSON: {}", e)),
+    }
+}
+
+/// Replace a partially written store file by the newest intact snapshot
+///
+/// Has to run before the store is opened, as rust_kvs only reads the store
+/// file when the instance is created.
+///
+/// ### Parameters
+/// * `dir` - directory of the storage files
+/// * `instance` - KVS instance id
+/// * `now_ms` - current Unix time, suffix of the preserved files
+///
+/// ### Returns
+/// The recovery, `None` if the store file is intact or does not exist yet
+pub fn recover_store(dir: &Path, instance: usize, now_ms: u64) -> io::Result<Option<Recovery>> {
+    let (kvs_path, hash_path) = snapshot_files(dir, instance, 0);
+    let reason = match (kvs_path.exists(), hash_path.exists()) {
+        (false, false) => return Ok(None),
+        (true, false) => "Checksum file missing".to_string(),
+        (false, true) => "Store file missing".to_string(),
+        (true, true) => match check(&kvs_path, &hash_path) {
+            Ok(()) => return Ok(None),
+            Err(reason) => reason,
+        },
+    };
+
+    let restored_snapshot = (1..SNAPSHOT_COUNT).find(|&snapshot| {
+        let (kvs_path, hash_path) = snapshot_files(dir, instance, snapshot);
+        check(&kvs_path, &hash_path).is_ok()
+    });
+
+    let mut preserved_files = Vec::new();
+    for path in [&kvs_path, &hash_path] {
+        if path.exists() {
+            let mut preserved = path.clone().into_os_string();
+            preserved.push(format!(".damaged-{}", now_ms));
+            let preserved = PathBuf::from(preserved);
+            fs::rename(path, &preserved)?;
+            preserved_files.push(preserved);
+        }
+    }
+
+    if let Some(snapshot) = restored_snapshot {
+        let (snapshot_kvs, snapshot_hash) = snapshot_files(dir, instance, snapshot);
+        // Checksum last, so that an interrupted recovery is recovered again on the next start
+        fs::copy(snapshot_kvs, &kvs_path)?;
+        fs::copy(snapshot_hash, &hash_path)?;
+    }
+
+    Ok(Some(Recovery {
+        reason,
+        restored_snapshot,
+        preserved_files,
+        recovered_at_ms: now_ms,
+    }))
+}
+
+/// Recovery at startup, as reported by `GetServiceInfo` and the metrics
+#[derive(Debug, Default)]
+pub struct RecoveryStatus {
+    recovery: Option<Recovery>,
+}
+
+impl RecoveryStatus {
+    pub fn new(recovery: Option<Recovery>) -> Self {
+        Self { recovery }
+    }
+
+    /// Recovery at startup, `None` if the store file was intact
+    pub fn recovery(&self) -> Option<&Recovery> {
+        self.recovery.as_ref()
+    }
+
+    /// Status as reported by `GetServiceInfo`
+    pub fn to_proto(&self) -> StorageRecovery {
+        match &self.recovery {
+            Some(recovery) => StorageRecovery {
+                recovered: true,
+                reason: recovery.reason.clone(),
+                restored_snapshot: recovery.restored_snapshot.unwrap_or_default() as u32,
+                preserved_files: recovery
+                    .preserved_files
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect(),
+                recovered_at_ms: recovery.recovered_at_ms,
+            },
+            None => StorageRecovery::default(),
+        }
+    }
+
+    /// Recovery metrics in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let metrics = [
+            (
+                "persistency_storage_recoveries_total",
+                "counter",
+                "Partially written store files recovered at startup",
+                self.recovery.is_some() as usize,
+            ),
+            (
+                "persistency_storage_restored_snapshot",
+                "gauge",
+                "Snapshot the store was restored from at startup, 0 if none",
+                self.recovery
+                    .as_ref()
+                    .and_then(|recovery| recovery.restored_snapshot)
+                    .unwrap_or_default(),
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(
+                out,
+                "# HELP {} {}\n# TYPE {} {}\n{} {}",
+                name, help, name, kind, name, value
+            );
+        }
+        out
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_kvs::prelude::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("persistency-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write a snapshot like rust_kvs, `written` bytes of the store file reaching the disk
+    fn write_snapshot(dir: &Path, instance: usize, snapshot: usize, mode: &str, written: usize) {
+        let json = format!("{{\"vehicle/mode\":{{\"t\":\"str\",\"v\":\"{}\"}}}}", mode);
+        let (kvs_path, hash_path) = snapshot_files(dir, instance, snapshot);
+        let hash = adler32::RollingAdler32::from_buffer(json.as_bytes()).hash();
+        fs::write(kvs_path, &json.as_bytes()[..written.min(json.len())]).unwrap();
+        fs::write(hash_path, hash.to_be_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_restores_previous_snapshot() {
+        let dir = temp_dir("recovery-restore");
+        write_snapshot(&dir, 1, 1, "manual", usize::MAX);
+        write_snapshot(&dir, 1, 0, "autonomous", 20);
+
+        let recovery = recover_store(&dir, 1, 1_700_000_000_000).unwrap().unwrap();
+        assert!(recovery.reason.starts_with("Checksum mismatch"));
+        assert_eq!(recovery.restored_snapshot, Some(1));
+        assert_eq!(recovery.preserved_files.len(), 2);
+        assert!(recovery.preserved_files.iter().all(
+            |path| path.exists() && path.to_string_lossy().ends_with(".damaged-1700000000000")
+        ));
+
+        // Instances are pooled per id, the service itself uses instance 0
+        let kvs = KvsBuilder::new(InstanceId(1))
+            .dir(dir.display().to_string())
+            .build()
+            .unwrap();
+        assert_eq!(
+            kvs.get_value_as::<String>("vehicle/mode").unwrap(),
+            "manual"
+        );
+        assert_eq!(recover_store(&dir, 1, 1_700_000_001_000).unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_checksum_skips_damaged_snapshots() {
+        let dir = temp_dir("recovery-checksum");
+        write_snapshot(&dir, 2, 2, "emergency", usize::MAX);
+        write_snapshot(&dir, 2, 1, "manual", 10);
+        write_snapshot(&dir, 2, 0, "autonomous", usize::MAX);
+        fs::remove_file(snapshot_files(&dir, 2, 0).1).unwrap();
+
+        let recovery = recover_store(&dir, 2, 1_700_000_000_000).unwrap().unwrap();
+        assert_eq!(recovery.reason, "Checksum file missing");
+        assert_eq!(recovery.restored_snapshot, Some(2));
+        assert_eq!(recovery.preserved_files.len(), 1);
+        let (kvs_path, hash_path) = snapshot_files(&dir, 2, 0);
+        assert!(check(&kvs_path, &hash_path).is_ok());
+
+        let status = RecoveryStatus::new(Some(recovery));
+        assert_eq!(status.to_proto().restored_snapshot, 2);
+        assert!(status
+            .render_metrics()
+            .contains("persistency_storage_recoveries_total 1\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_starts_empty_without_intact_snapshot() {
+        let dir = temp_dir("recovery-empty");
+        write_snapshot(&dir, 3, 0, "autonomous", 5);
+
+        let recovery = recover_store(&dir, 3, 1_700_000_000_000).unwrap().unwrap();
+        assert!(recovery.reason.starts_with("Checksum mismatch"));
+        assert_eq!(recovery.restored_snapshot, None);
+        let (kvs_path, hash_path) = snapshot_files(&dir, 3, 0);
+        assert!(!kvs_path.exists() && !hash_path.exists());
+
+        let status = RecoveryStatus::new(Some(recovery));
+        assert!(status.to_proto().recovered);
+        assert_eq!(status.to_proto().restored_snapshot, 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_intact_store_is_kept() {
+        let dir = temp_dir("recovery-intact");
+        assert_eq!(recover_store(&dir, 3, 0).unwrap(), None);
+
+        write_snapshot(&dir, 3, 0, "autonomous", usize::MAX);
+        assert_eq!(recover_store(&dir, 3, 0).unwrap(), None);
+        assert!(snapshot_files(&dir, 3, 0).0.exists());
+
+        let status = RecoveryStatus::default();
+        assert!(!status.to_proto().recovered);
+        assert!(status
+            .render_metrics()
+            .contains("persistency_storage_recoveries_total 0\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
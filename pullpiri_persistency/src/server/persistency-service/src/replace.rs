@@ -0,0 +1,337 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Replacement of the store with an exported snapshot
+//!
+//! Unlike [`crate::restore`], which merges a source into the store,
+//! `RestoreSnapshot` makes the default store hold exactly the keys of an
+//! export document, so that a corrupted store can be recovered in the field
+//! from an earlier export. The document is uploaded in chunks like a large
+//! value, see [`crate::large`]: the first request carries the size of the
+//! whole document, every request the next chunk.
+//!
+//! Only the keys under the prefixes of the export are replaced: stored keys
+//! under them that are missing from the export are removed, keys outside of
+//! them are kept. An export of the whole store replaces every key.
+//! Differential exports hold only changes and are rejected. A dry run
+//! validates the document and reports the changes without applying them.
+
+use crate::export::{json_to_value, value_to_json, Export, EXPORT_FORMAT_VERSION};
+use common::persistency_proto::RestoreSnapshotRequest;
+use rust_kvs::kvs_value::KvsValue;
+use rust_kvs::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Export document assembled from the requests of a `RestoreSnapshot` call
+#[derive(Debug)]
+pub struct SnapshotUpload {
+    dry_run: bool,
+    total_size: u64,
+    data: Vec<u8>,
+    max_bytes: u64,
+}
+
+impl SnapshotUpload {
+    /// Start a document with the `first` request of a call
+    ///
+    /// Documents declared larger than `max_bytes` are rejected before any
+    /// memory is reserved for them.
+    pub fn start(mut first: RestoreSnapshotRequest, max_bytes: u64) -> Result<Self, String> {
+        if first.total_size > max_bytes {
+            return Err(format!(
+                "Snapshot of {} bytes exceeds the limit of {} bytes",
+                first.total_size, max_bytes
+            ));
+        }
+        let chunk = std::mem::take(&mut first.chunk);
+        let mut upload = Self {
+            dry_run: first.dry_run,
+            total_size: first.total_size,
+            data: Vec::with_capacity(first.total_size as usize),
+            max_bytes,
+        };
+        upload.push(chunk)?;
+        Ok(upload)
+    }
+
+    /// Whether the changes are only reported, not applied
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Append the chunk of the next request
+    pub fn push(&mut self, chunk: Vec<u8>) -> Result<(), String> {
+        let size = (self.data.len() + chunk.len()) as u64;
+        if size > self.max_bytes {
+            return Err(format!(
+                "Snapshot exceeds the limit of {} bytes",
+                self.max_bytes
+            ));
+        }
+        self.data.extend(chunk);
+        Ok(())
+    }
+
+    /// Parse the complete document
+    ///
+    /// Fails if the chunks do not add up to the declared size, e.g. when the
+    /// client gave up halfway, or the document is no valid full export.
+    pub fn finish(self) -> Result<Snapshot, String> {
+        if self.data.len() as u64 != self.total_size {
+            return Err(format!(
+                "Received {} of {} bytes",
+                self.data.len(),
+                self.total_size
+            ));
+        }
+        Snapshot::parse(&self.data)
+    }
+}
+
+/// Keys and values of a full export
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// Prefixes the export covers, empty for the whole store
+    pub prefixes: Vec<String>,
+    pub entries: BTreeMap<String, KvsValue>,
+}
+
+impl Snapshot {
+    /// Parse and validate an export document
+    pub fn parse(document: &[u8]) -> Result<Self, String> {
+        let export: Export = serde_json::from_slice(document)
+            .map_err(|e| format!("Failed to parse snapshot: {}", e))?;
+        if export.format_version != EXPORT_FORMAT_VERSION {
+            return Err(format!(
+                "Export format version {} is not supported, expected {}",
+                export.format_version, EXPORT_FORMAT_VERSION
+            ));
+        }
+        if export.is_differential() {
+            return Err("Snapshot is a differential export, restore it with Restore".to_string());
+        }
+        let snapshot = Self {
+            prefixes: export.prefixes,
+            entries: export
+                .entries
+                .iter()
+                .map(|(key, value)| (key.clone(), json_to_value(value)))
+                .collect(),
+        };
+        if let Some(key) = snapshot.entries.keys().find(|key| !snapshot.covers(key)) {
+            return Err(format!("Key {} is outside of the exported prefixes", key));
+        }
+        Ok(snapshot)
+    }
+
+    /// Whether `key` is replaced by the snapshot
+    pub fn covers(&self, key: &str) -> bool {
+        self.prefixes.is_empty()
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| key.starts_with(prefix.as_str()))
+    }
+}
+
+/// Changes of a replacement
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplaceReport {
+    /// Keys of the snapshot that were not stored
+    pub added: u32,
+    /// Keys stored with a different value
+    pub replaced: u32,
+    /// Stored keys missing from the snapshot
+    pub removed: u32,
+    /// Keys already stored with the snapshot value
+    pub unchanged: u32,
+}
+
+/// Change of one key, with its stored value to roll back to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Store the value, replacing the stored one if any
+    Put {
+        key: String,
+        value: KvsValue,
+        previous: Option<KvsValue>,
+    },
+    /// Remove the stored value
+    Remove { key: String, previous: KvsValue },
+}
+
+impl Change {
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Put { key, .. } | Change::Remove { key, .. } => key,
+        }
+    }
+}
+
+/// Changes of a replacement together with its report
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplacePlan {
+    pub changes: Vec<Change>,
+    pub report: ReplaceReport,
+}
+
+/// Resolve the snapshot against the stored keys
+///
+/// ### Parameters
+/// * `snapshot: Snapshot` - keys and values the store shall hold
+/// * `stored_keys: Vec<String>` - every stored key
+/// * `stored: FnMut(&str) -> Result<KvsValue, ErrorCode>` - stored value of a key
+pub fn plan<F>(
+    snapshot: Snapshot,
+    stored_keys: Vec<String>,
+    mut stored: F,
+) -> Result<ReplacePlan, ErrorCode>
+where
+    F: FnMut(&str) -> Result<KvsValue, ErrorCode>,
+{
+    let mut plan = ReplacePlan::default();
+    let stored_keys: BTreeSet<String> = stored_keys
+        .into_iter()
+        .filter(|key| snapshot.covers(key))
+        .collect();
+    for key in &stored_keys {
+        if !snapshot.entries.contains_key(key) {
+            plan.changes.push(Change::Remove {
+                key: key.clone(),
+                previous: stored(key)?,
+            });
+            plan.report.removed += 1;
+        }
+    }
+    for (key, value) in snapshot.entries {
+        if !stored_keys.contains(&key) {
+            plan.changes.push(Change::Put {
+                key,
+                value,
+                previous: None,
+            });
+            plan.report.added += 1;
+            continue;
+        }
+        let previous = stored(&key)?;
+        // Export values lost their integer types, compare them as exported
+        if value_to_json(&previous) == value_to_json(&value) {
+            plan.report.unchanged += 1;
+            continue;
+        }
+        plan.changes.push(Change::Put {
+            key,
+            value,
+            previous: Some(previous),
+        });
+        plan.report.replaced += 1;
+    }
+    Ok(plan)
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = r#"{
+        "format_version": 1,
+        "exported_at_ms": 1700000000000,
+        "prefixes": ["Scenario/"],
+        "entries": {"Scenario/a": "kept", "Scenario/b": 2, "Scenario/c": true}
+    }"#;
+
+    fn request(dry_run: bool, total_size: u64, chunk: &[u8]) -> RestoreSnapshotRequest {
+        RestoreSnapshotRequest {
+            dry_run,
+            total_size,
+            chunk: chunk.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_chunks_assemble_the_snapshot() {
+        let (head, tail) = SNAPSHOT.as_bytes().split_at(40);
+        let mut upload =
+            SnapshotUpload::start(request(true, SNAPSHOT.len() as u64, head), 1024).unwrap();
+        assert!(upload.dry_run());
+        upload.push(tail.to_vec()).unwrap();
+
+        let snapshot = upload.finish().unwrap();
+        assert_eq!(snapshot.prefixes, ["Scenario/"]);
+        assert_eq!(snapshot.entries.len(), 3);
+        assert!(snapshot.covers("Scenario/d"));
+        assert!(!snapshot.covers("Package/a"));
+    }
+
+    #[test]
+    fn test_limits_and_incomplete_uploads() {
+        let declared = SnapshotUpload::start(request(false, 2048, &[]), 1024).unwrap_err();
+        assert_eq!(
+            declared,
+            "Snapshot of 2048 bytes exceeds the limit of 1024 bytes"
+        );
+
+        let mut upload = SnapshotUpload::start(request(false, 4, b"{}"), 8).unwrap();
+        assert!(upload.push(vec![b' '; 7]).is_err());
+
+        let upload = SnapshotUpload::start(request(false, 10, b"{}"), 1024).unwrap();
+        assert_eq!(upload.finish().unwrap_err(), "Received 2 of 10 bytes");
+    }
+
+    #[test]
+    fn test_invalid_snapshots() {
+        assert!(Snapshot::parse(b"{").is_err());
+        let unsupported = SNAPSHOT.replace("\"format_version\": 1", "\"format_version\": 9");
+        assert!(Snapshot::parse(unsupported.as_bytes()).is_err());
+        let differential = SNAPSHOT.replace("\"prefixes\"", "\"base_revision\": 3, \"prefixes\"");
+        assert!(Snapshot::parse(differential.as_bytes())
+            .unwrap_err()
+            .contains("differential"));
+        let outside = SNAPSHOT.replace("\"Scenario/c\"", "\"Package/c\"");
+        assert_eq!(
+            Snapshot::parse(outside.as_bytes()).unwrap_err(),
+            "Key Package/c is outside of the exported prefixes"
+        );
+    }
+
+    #[test]
+    fn test_plan_replaces_the_prefixes() {
+        let snapshot = Snapshot::parse(SNAPSHOT.as_bytes()).unwrap();
+        let stored: BTreeMap<&str, KvsValue> = [
+            ("Scenario/a", KvsValue::String("kept".to_string())),
+            ("Scenario/b", KvsValue::U32(1)),
+            ("Scenario/old", KvsValue::Boolean(false)),
+            ("Package/a", KvsValue::I32(7)),
+        ]
+        .into_iter()
+        .collect();
+        let keys = stored.keys().map(|key| key.to_string()).collect();
+        let plan = plan(snapshot, keys, |key| {
+            stored.get(key).cloned().ok_or(ErrorCode::KeyNotFound)
+        })
+        .unwrap();
+
+        assert_eq!(
+            plan.report,
+            ReplaceReport {
+                added: 1,
+                replaced: 1,
+                removed: 1,
+                unchanged: 1,
+            }
+        );
+        let keys: Vec<&str> = plan.changes.iter().map(Change::key).collect();
+        assert_eq!(keys, ["Scenario/old", "Scenario/b", "Scenario/c"]);
+        assert_eq!(
+            plan.changes[1],
+            Change::Put {
+                key: "Scenario/b".to_string(),
+                value: KvsValue::I64(2),
+                previous: Some(KvsValue::U32(1)),
+            }
+        );
+    }
+}
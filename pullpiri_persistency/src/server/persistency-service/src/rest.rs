@@ -0,0 +1,440 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! REST gateway to the key-value API
+//!
+//! Dashboards and scripts read and write keys over plain HTTP and JSON
+//! instead of generating gRPC stubs:
+//!
+//! * `GET /kv/{key}` - value and revision of a key
+//! * `PUT /kv/{key}` - set a key to the `value` of a JSON body
+//! * `DELETE /kv/{key}` - remove a key
+//! * `GET /kv?prefix=` - keys under a prefix with their values, sorted by key
+//!
+//! Every endpoint takes a `namespace` query parameter for the stores next to
+//! the default one. Values are JSON as in exports, see
+//! [`value_to_json`]: written integers become 64-bit values and binary
+//! values are objects with their base64 under `"$bytes"`.
+//!
+//! Requests go through the regular RPC handlers, so watchers, revisions and
+//! quotas see them like any other request. They are authenticated like gRPC
+//! requests, see [`crate::auth`], and failures are answered with the HTTP
+//! status of their gRPC status code.
+
+use crate::auth::{AuthInterceptor, AuthenticatedComponent};
+use crate::config::AuthConfig;
+use crate::export::{json_to_value, value_to_json};
+use crate::status::with_status_codes;
+use crate::PersistencyServiceImpl;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use common::persistency_proto::persistency_service_server::PersistencyService;
+use common::persistency_proto::{
+    GetValueRequest, KvsValue, RemoveKeyRequest, ScanRequest, SetValueRequest,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request};
+use tracing::info;
+
+/// Service the gateway forwards to and the authentication of its callers
+#[derive(Clone)]
+struct Gateway {
+    service: Arc<PersistencyServiceImpl>,
+    auth: AuthInterceptor,
+}
+
+/// Error response of the gateway
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.0,
+            Json(json!({ "success": false, "error_message": self.1 })),
+        )
+            .into_response()
+    }
+}
+
+impl From<tonic::Status> for ApiError {
+    fn from(status: tonic::Status) -> Self {
+        ApiError(http_status(status.code()), status.message().to_string())
+    }
+}
+
+/// HTTP status of a failed request with gRPC status `code`
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::InvalidArgument | Code::OutOfRange => StatusCode::BAD_REQUEST,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted | Code::FailedPrecondition => StatusCode::CONFLICT,
+        Code::ResourceExhausted => StatusCode::INSUFFICIENT_STORAGE,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+type ApiResult = Result<Json<Value>, ApiError>;
+
+/// Router of the gateway
+///
+/// ### Parameters
+/// * `service` - service whose stores are served
+/// * `auth` - authentication of the callers, as for gRPC requests
+pub fn router(service: Arc<PersistencyServiceImpl>, auth: &AuthConfig) -> Router {
+    let gateway = Gateway {
+        service,
+        auth: AuthInterceptor::new(auth),
+    };
+    Router::new()
+        .route("/kv", get(list))
+        .route("/kv/*key", get(get_key).put(put_key).delete(delete_key))
+        .with_state(gateway)
+}
+
+/// Serve the gateway on `listen_addr` until the process ends
+pub async fn serve(
+    listen_addr: &str,
+    service: Arc<PersistencyServiceImpl>,
+    auth: &AuthConfig,
+) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen_addr).await?;
+    info!("REST gateway listening on http://{}", listen_addr);
+    axum::serve(listener, router(service, auth)).await
+}
+
+impl Gateway {
+    /// RPC request of `message` for the caller of `headers`
+    fn request<T>(&self, headers: &HeaderMap, message: T) -> Result<Request<T>, ApiError> {
+        let presented = Request::from_parts(
+            MetadataMap::from_headers(headers.clone()),
+            Default::default(),
+            (),
+        );
+        let component: Option<AuthenticatedComponent> = self.auth.authenticate(&presented)?;
+        let mut request = Request::new(message);
+        if let Some(component) = component {
+            request.extensions_mut().insert(component);
+        }
+        Ok(request)
+    }
+}
+
+fn to_json(value: &KvsValue) -> Result<Value, ApiError> {
+    PersistencyServiceImpl::proto_to_kvs_value(value)
+        .map(|value| value_to_json(&value))
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct NamespaceQuery {
+    namespace: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ListQuery {
+    prefix: String,
+    namespace: String,
+}
+
+async fn get_key(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Path(key): Path<String>,
+    Query(query): Query<NamespaceQuery>,
+) -> ApiResult {
+    let request = gateway.request(
+        &headers,
+        GetValueRequest {
+            key: key.clone(),
+            namespace: query.namespace,
+        },
+    )?;
+    let response = with_status_codes(gateway.service.get_value(request))
+        .await?
+        .into_inner();
+    let value = match response.value {
+        Some(value) if response.success => to_json(&value)?,
+        _ => {
+            return Err(ApiError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                response.error_message,
+            ))
+        }
+    };
+    Ok(Json(json!({
+        "success": true,
+        "key": key,
+        "value": value,
+        "revision": response.revision,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct PutBody {
+    value: Value,
+    /// Remove the key after this many seconds, 0 keeps it
+    #[serde(default)]
+    ttl_seconds: u32,
+    /// Write only if the key is at this revision, 0 writes unconditionally
+    #[serde(default)]
+    expected_revision: u64,
+}
+
+async fn put_key(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Path(key): Path<String>,
+    Query(query): Query<NamespaceQuery>,
+    Json(body): Json<PutBody>,
+) -> ApiResult {
+    let value = PersistencyServiceImpl::kvs_value_to_proto(&json_to_value(&body.value));
+    let request = gateway.request(
+        &headers,
+        SetValueRequest {
+            key,
+            value: Some(value),
+            ttl_seconds: body.ttl_seconds,
+            namespace: query.namespace,
+            expected_revision: body.expected_revision,
+            lease_id: 0,
+        },
+    )?;
+    let response = with_status_codes(gateway.service.set_value(request))
+        .await?
+        .into_inner();
+    if !response.success {
+        return Err(ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            response.error_message,
+        ));
+    }
+    Ok(Json(
+        json!({ "success": true, "revision": response.revision }),
+    ))
+}
+
+async fn delete_key(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Path(key): Path<String>,
+    Query(query): Query<NamespaceQuery>,
+) -> ApiResult {
+    let request = gateway.request(
+        &headers,
+        RemoveKeyRequest {
+            key,
+            namespace: query.namespace,
+            trash: false,
+        },
+    )?;
+    let response = with_status_codes(gateway.service.remove_key(request))
+        .await?
+        .into_inner();
+    if !response.success {
+        return Err(ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            response.error_message,
+        ));
+    }
+    Ok(Json(json!({ "success": true })))
+}
+
+/// Keys under the prefix, read in batches so that writers are not held up
+async fn list(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Query(query): Query<ListQuery>,
+) -> ApiResult {
+    let request = gateway.request(
+        &headers,
+        ScanRequest {
+            prefix: query.prefix,
+            namespace: query.namespace,
+            batch_size: 0,
+        },
+    )?;
+    let mut batches = with_status_codes(gateway.service.scan(request))
+        .await?
+        .into_inner();
+    let mut keys = Vec::new();
+    while let Some(batch) = batches.next().await {
+        for entry in batch?.entries {
+            let value = match &entry.value {
+                Some(value) => to_json(value)?,
+                None => Value::Null,
+            };
+            keys.push(json!({ "key": entry.key, "value": value }));
+        }
+    }
+    Ok(Json(json!({ "success": true, "keys": keys })))
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServiceConfig;
+    use axum::body::Body;
+    use axum::http::{header, Request as HttpRequest};
+    use common::identity::NAME_METADATA;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn gateway(config: &ServiceConfig) -> Router {
+        let service = PersistencyServiceImpl::with_config(config).unwrap();
+        router(Arc::new(service), &config.auth)
+    }
+
+    fn in_memory() -> ServiceConfig {
+        ServiceConfig::from_yaml("storage:\n  backend: memory\n").unwrap()
+    }
+
+    async fn call(
+        router: &Router,
+        method: &str,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = HttpRequest::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let body = match body {
+            Some(body) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = router
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_put_get_and_delete() {
+        let router = gateway(&in_memory());
+        let value = json!({ "speed": 42.5, "gear": 3, "lights": true });
+
+        let (status, body) = call(
+            &router,
+            "PUT",
+            "/kv/vehicle/state",
+            &[],
+            Some(json!({ "value": value })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let revision = body["revision"].as_u64().unwrap();
+
+        let (status, body) = call(&router, "GET", "/kv/vehicle/state", &[], None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["key"], "vehicle/state");
+        assert_eq!(body["value"], value);
+        assert_eq!(body["revision"], revision);
+
+        // A write at a stale revision conflicts
+        let stale = json!({ "value": "manual", "expected_revision": revision + 1 });
+        let (status, _) = call(&router, "PUT", "/kv/vehicle/state", &[], Some(stale)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, _) = call(&router, "DELETE", "/kv/vehicle/state", &[], None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = call(&router, "GET", "/kv/vehicle/state", &[], None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["success"], false);
+        assert!(!body["error_message"].as_str().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_prefix() {
+        let router = gateway(&in_memory());
+        for (key, value) in [
+            ("vehicle/speed", json!(42)),
+            ("vehicle/mode", json!("autonomous")),
+            ("Scenario/helloworld", json!("running")),
+        ] {
+            let uri = format!("/kv/{}", key);
+            let body = Some(json!({ "value": value }));
+            assert_eq!(
+                call(&router, "PUT", &uri, &[], body).await.0,
+                StatusCode::OK
+            );
+        }
+
+        let (status, body) = call(&router, "GET", "/kv?prefix=vehicle/", &[], None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["keys"],
+            json!([
+                { "key": "vehicle/mode", "value": "autonomous" },
+                { "key": "vehicle/speed", "value": 42 },
+            ])
+        );
+        let (_, body) = call(&router, "GET", "/kv", &[], None).await;
+        assert_eq!(body["keys"].as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_requests_authenticated_like_grpc() {
+        let yaml = r#"
+storage:
+  backend: memory
+auth:
+  enabled: true
+  tokens:
+    dashboard: 5e1f0c
+acl:
+  enabled: true
+  components:
+    dashboard:
+      read: ["vehicle/"]
+"#;
+        let router = gateway(&ServiceConfig::from_yaml(yaml).unwrap());
+
+        let (status, _) = call(&router, "GET", "/kv/vehicle/mode", &[], None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let wrong = [
+            (NAME_METADATA, "dashboard"),
+            ("authorization", "Bearer wrong"),
+        ];
+        let (status, _) = call(&router, "GET", "/kv/vehicle/mode", &wrong, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // The access control list of the component applies
+        let valid = [
+            (NAME_METADATA, "dashboard"),
+            ("authorization", "Bearer 5e1f0c"),
+        ];
+        let (status, _) = call(&router, "GET", "/kv/vehicle/mode", &valid, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let body = Some(json!({ "value": "manual" }));
+        let (status, _) = call(&router, "PUT", "/kv/vehicle/mode", &valid, body).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,576 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Restore of snapshots and exports into a non-empty store
+//!
+//! A restore merges the keys of an older store snapshot or of an export
+//! document into the store; stored keys missing from the source are kept. A
+//! restored key that is stored with a different value is a conflict, resolved
+//! by the requested [`ConflictStrategy`]. Every conflict is reported with its
+//! resolution, so that the caller sees which stored values were replaced.
+//!
+//! An export can be followed by differential exports, see [`crate::history`],
+//! which are applied on top of it in order before the result is restored.
+//!
+//! For [`ConflictStrategy::NewestWins`] a restored value is as old as its
+//! source: the snapshot file or the capture of the export or increment it was
+//! taken from. A stored value is as old as its last write since the service
+//! started, or else as the store file, which was written after every older
+//! change.
+
+use crate::export::{json_to_value, value_to_json, Export, EXPORT_FORMAT_VERSION};
+use crate::history::Baseline;
+use rust_kvs::kvs_value::KvsValue;
+use rust_kvs::prelude::*;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// How a restored key that is stored with a different value is resolved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Keep the stored value
+    SkipExisting,
+    /// Replace the stored value
+    Overwrite,
+    /// Replace the stored value if the restored one is newer
+    NewestWins,
+    /// Restore nothing if any key conflicts
+    #[default]
+    FailOnConflict,
+}
+
+impl ConflictStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConflictStrategy::SkipExisting => "skip-existing",
+            ConflictStrategy::Overwrite => "overwrite",
+            ConflictStrategy::NewestWins => "newest-wins",
+            ConflictStrategy::FailOnConflict => "fail-on-conflict",
+        }
+    }
+}
+
+/// Outcome of one conflicting key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The stored value was kept
+    Skipped,
+    /// The stored value was replaced
+    Overwritten,
+    /// The conflict failed the restore
+    Rejected,
+}
+
+impl Resolution {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Resolution::Skipped => "skipped",
+            Resolution::Overwritten => "overwritten",
+            Resolution::Rejected => "rejected",
+        }
+    }
+}
+
+/// A restored key stored with a different value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyConflict {
+    pub key: String,
+    pub resolution: Resolution,
+    /// Unix time in milliseconds the stored value was written
+    pub stored_ms: u64,
+    /// Unix time in milliseconds of the restored value
+    pub restored_ms: u64,
+}
+
+/// Outcome of a restore
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestoreReport {
+    /// Keys written, new ones and overwritten ones
+    pub restored: u32,
+    /// Keys already stored with the restored value
+    pub unchanged: u32,
+    /// Conflicting keys that kept their stored value
+    pub skipped: u32,
+    pub conflicts: Vec<KeyConflict>,
+}
+
+impl RestoreReport {
+    /// Whether a conflict failed the restore, nothing was written then
+    pub fn rejected(&self) -> bool {
+        self.conflicts
+            .iter()
+            .any(|conflict| conflict.resolution == Resolution::Rejected)
+    }
+}
+
+/// Keys and values to restore, as old as their source
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestoreSource {
+    pub entries: BTreeMap<String, KvsValue>,
+    /// Unix time in milliseconds of the source
+    pub modified_ms: u64,
+    /// Unix time in milliseconds of the keys taken from increments
+    pub increment_ms: HashMap<String, u64>,
+    /// Point of the mutation history the entries reflect, `None` for snapshots
+    pub baseline: Option<Baseline>,
+}
+
+impl RestoreSource {
+    /// Read an older snapshot of `kvs`, after verifying its checksum
+    ///
+    /// The caller must hold the store's write lock, flushes rotate the snapshots.
+    pub fn from_snapshot(kvs: &Kvs, snapshot_id: usize) -> Result<Self, String> {
+        if snapshot_id == 0 || snapshot_id >= kvs.snapshot_count() {
+            return Err(format!(
+                "No snapshot {}, the store has snapshots 1 to {}",
+                snapshot_id,
+                kvs.snapshot_count().saturating_sub(1)
+            ));
+        }
+        crate::integrity::verify_snapshot(kvs, SnapshotId(snapshot_id))?;
+        let path = kvs
+            .get_kvs_filename(SnapshotId(snapshot_id))
+            .map_err(|e| format!("Snapshot file missing: {:?}", e))?;
+        let content =
+            fs::read(&path).map_err(|e| format!("Failed to read snapshot file: {}", e))?;
+        let document: Value = serde_json::from_slice(&content)
+            .map_err(|e| format!("Failed to parse snapshot file: {}", e))?;
+        let Some(entries) = store_file_entries(&document) else {
+            return Err("Snapshot file does not hold a map".to_string());
+        };
+        Ok(Self::from_entries(entries, file_modified_ms(&path)))
+    }
+
+    /// Keys and values of a source without history, e.g. a foreign store,
+    /// as old as `modified_ms`
+    pub fn from_entries(entries: BTreeMap<String, KvsValue>, modified_ms: u64) -> Self {
+        Self {
+            entries,
+            modified_ms,
+            increment_ms: HashMap::new(),
+            baseline: None,
+        }
+    }
+
+    /// Read an export document, as written by the upload and the admin UI
+    pub fn from_export(document: &str) -> Result<Self, String> {
+        Ok(Self::from_parsed_export(&parse_export(document)?))
+    }
+
+    /// Keys and values of an export document of a supported version
+    pub fn from_parsed_export(export: &Export) -> Self {
+        Self {
+            entries: export
+                .entries
+                .iter()
+                .map(|(key, value)| (key.clone(), json_to_value(value)))
+                .collect(),
+            modified_ms: export.exported_at_ms,
+            increment_ms: HashMap::new(),
+            baseline: Some(export.baseline()),
+        }
+    }
+
+    /// Apply a differential export on top of the source
+    ///
+    /// The increment must be taken from the same mutation history against a
+    /// base the source already contains. Keys it removed are not restored;
+    /// like every key missing from the source, stored ones are kept.
+    pub fn apply_increment(&mut self, document: &str) -> Result<(), String> {
+        let increment = parse_export(document)?;
+        let Some(base_revision) = increment.base_revision else {
+            return Err("Increment is not a differential export".to_string());
+        };
+        let Some(baseline) = self.baseline else {
+            return Err("Increments only apply to exports, not to store snapshots".to_string());
+        };
+        if increment.history_id != baseline.history_id {
+            return Err(format!(
+                "Increment belongs to mutation history {}, the source to {}",
+                increment.history_id, baseline.history_id
+            ));
+        }
+        if base_revision > baseline.revision || increment.revision < baseline.revision {
+            return Err(format!(
+                "Increment from revision {} to {} does not follow revision {} of the source",
+                base_revision, increment.revision, baseline.revision
+            ));
+        }
+
+        for (key, value) in &increment.entries {
+            self.entries.insert(key.clone(), json_to_value(value));
+            self.increment_ms
+                .insert(key.clone(), increment.exported_at_ms);
+        }
+        for key in &increment.removed {
+            self.entries.remove(key);
+            self.increment_ms.remove(key);
+        }
+        self.baseline = Some(increment.baseline());
+        Ok(())
+    }
+}
+
+fn parse_export(document: &str) -> Result<Export, String> {
+    let export: Export =
+        serde_json::from_str(document).map_err(|e| format!("Failed to parse export: {}", e))?;
+    check_format_version(&export)?;
+    Ok(export)
+}
+
+/// Reject export documents of another layout version
+pub fn check_format_version(export: &Export) -> Result<(), String> {
+    if export.format_version != EXPORT_FORMAT_VERSION {
+        return Err(format!(
+            "Export format version {} is not supported, expected {}",
+            export.format_version, EXPORT_FORMAT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+/// Keys and values of the JSON document of a rust_kvs store file, `None` if
+/// it does not hold a map
+pub fn store_file_entries(document: &Value) -> Option<BTreeMap<String, KvsValue>> {
+    // rust_kvs stores the map either as a tagged object or as a plain one
+    let map = match document.get("t").and_then(Value::as_str) {
+        Some("obj") => document.get("v")?,
+        _ => document,
+    };
+    let Value::Object(map) = map else {
+        return None;
+    };
+    Some(
+        map.iter()
+            .map(|(key, value)| (key.clone(), tagged_to_value(value)))
+            .collect(),
+    )
+}
+
+/// Value of the type-tagged JSON of a rust_kvs store file
+fn tagged_to_value(value: &Value) -> KvsValue {
+    let (Some(tag), Some(value)) = (value.get("t").and_then(Value::as_str), value.get("v")) else {
+        return KvsValue::Null;
+    };
+    match (tag, value) {
+        ("i32", Value::Number(v)) => KvsValue::I32(v.as_f64().unwrap_or_default() as i32),
+        ("u32", Value::Number(v)) => KvsValue::U32(v.as_f64().unwrap_or_default() as u32),
+        ("i64", Value::Number(v)) => KvsValue::I64(v.as_f64().unwrap_or_default() as i64),
+        ("u64", Value::Number(v)) => KvsValue::U64(v.as_f64().unwrap_or_default() as u64),
+        ("f64", Value::Number(v)) => KvsValue::F64(v.as_f64().unwrap_or_default()),
+        ("bool", Value::Bool(v)) => KvsValue::Boolean(*v),
+        ("str", Value::String(v)) => KvsValue::String(v.clone()),
+        ("arr", Value::Array(values)) => {
+            KvsValue::Array(values.iter().map(tagged_to_value).collect())
+        }
+        ("obj", Value::Object(values)) => KvsValue::Object(
+            values
+                .iter()
+                .map(|(key, value)| (key.clone(), tagged_to_value(value)))
+                .collect(),
+        ),
+        _ => KvsValue::Null,
+    }
+}
+
+/// Unix time in milliseconds `path` was last written, 0 if unknown
+pub fn file_modified_ms(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Unix time in milliseconds the current store file of `kvs` was written
+pub fn store_modified_ms(kvs: &Kvs) -> u64 {
+    kvs.get_kvs_filename(SnapshotId(0))
+        .map_or(0, |path| file_modified_ms(&path))
+}
+
+/// Time of the last write of each key since the service started
+#[derive(Debug, Default)]
+pub struct ModifiedTimes {
+    times: Mutex<HashMap<String, u64>>,
+}
+
+impl ModifiedTimes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, key: &str, modified_ms: u64) {
+        self.times
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), modified_ms);
+    }
+
+    pub fn forget(&self, key: &str) {
+        self.times.lock().unwrap().remove(key);
+    }
+
+    pub fn forget_all(&self) {
+        self.times.lock().unwrap().clear();
+    }
+
+    /// Time of the last write of `key`, `None` if not written since the start
+    pub fn get(&self, key: &str) -> Option<u64> {
+        self.times.lock().unwrap().get(key).copied()
+    }
+}
+
+/// Writes of a restore together with its report
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestorePlan {
+    /// Keys, values and their Unix times in milliseconds to write, empty if
+    /// the restore was rejected
+    pub writes: Vec<(String, KvsValue, u64)>,
+    pub report: RestoreReport,
+}
+
+/// Resolve the restored keys against the stored ones
+///
+/// ### Parameters
+/// * `source: RestoreSource` - keys and values to restore
+/// * `strategy: ConflictStrategy` - how conflicting keys are resolved
+/// * `stored: FnMut(&str) -> Result<Option<(KvsValue, u64)>, ErrorCode>` - stored
+///   value of a key and the Unix time in milliseconds it was written, `None` if
+///   the key is not stored
+pub fn plan<F>(
+    source: RestoreSource,
+    strategy: ConflictStrategy,
+    mut stored: F,
+) -> Result<RestorePlan, ErrorCode>
+where
+    F: FnMut(&str) -> Result<Option<(KvsValue, u64)>, ErrorCode>,
+{
+    let mut writes = Vec::new();
+    let mut report = RestoreReport::default();
+    for (key, value) in source.entries {
+        let restored_ms = source
+            .increment_ms
+            .get(&key)
+            .copied()
+            .unwrap_or(source.modified_ms);
+        let Some((stored_value, stored_ms)) = stored(&key)? else {
+            writes.push((key, value, restored_ms));
+            continue;
+        };
+        // Export values lost their integer types, compare them as exported
+        if value_to_json(&stored_value) == value_to_json(&value) {
+            report.unchanged += 1;
+            continue;
+        }
+        let resolution = match strategy {
+            ConflictStrategy::SkipExisting => Resolution::Skipped,
+            ConflictStrategy::Overwrite => Resolution::Overwritten,
+            ConflictStrategy::NewestWins if restored_ms > stored_ms => Resolution::Overwritten,
+            ConflictStrategy::NewestWins => Resolution::Skipped,
+            ConflictStrategy::FailOnConflict => Resolution::Rejected,
+        };
+        report.conflicts.push(KeyConflict {
+            key: key.clone(),
+            resolution,
+            stored_ms,
+            restored_ms,
+        });
+        match resolution {
+            Resolution::Overwritten => writes.push((key, value, restored_ms)),
+            Resolution::Skipped => report.skipped += 1,
+            Resolution::Rejected => {}
+        }
+    }
+    if report.rejected() {
+        writes.clear();
+    }
+    report.restored = writes.len() as u32;
+    Ok(RestorePlan { writes, report })
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> RestoreSource {
+        RestoreSource {
+            entries: BTreeMap::from([
+                ("vehicle/mode".to_string(), KvsValue::from("manual")),
+                ("vehicle/gear".to_string(), KvsValue::I64(3)),
+                ("vehicle/limit".to_string(), KvsValue::F64(50.0)),
+                ("vehicle/route".to_string(), KvsValue::from("A9")),
+            ]),
+            modified_ms: 2_000,
+            increment_ms: HashMap::new(),
+            baseline: None,
+        }
+    }
+
+    fn stored(key: &str) -> Result<Option<(KvsValue, u64)>, ErrorCode> {
+        Ok(match key {
+            "vehicle/mode" => Some((KvsValue::from("autonomous"), 1_000)),
+            "vehicle/gear" => Some((KvsValue::U32(3), 1_000)),
+            "vehicle/limit" => Some((KvsValue::F64(80.0), 3_000)),
+            _ => None,
+        })
+    }
+
+    fn written(plan: &RestorePlan) -> Vec<&str> {
+        plan.writes.iter().map(|(key, _, _)| key.as_str()).collect()
+    }
+
+    #[test]
+    fn test_plan_strategies() {
+        let plan = super::plan(source(), ConflictStrategy::SkipExisting, stored).unwrap();
+        assert_eq!(written(&plan), ["vehicle/route"]);
+        assert_eq!(plan.report.unchanged, 1);
+        assert_eq!(plan.report.skipped, 2);
+
+        let plan = super::plan(source(), ConflictStrategy::Overwrite, stored).unwrap();
+        assert_eq!(
+            written(&plan),
+            ["vehicle/limit", "vehicle/mode", "vehicle/route"]
+        );
+        assert_eq!(plan.report.restored, 3);
+
+        let plan = super::plan(source(), ConflictStrategy::NewestWins, stored).unwrap();
+        assert_eq!(written(&plan), ["vehicle/mode", "vehicle/route"]);
+        let limit = &plan.report.conflicts[0];
+        assert_eq!(limit.key, "vehicle/limit");
+        assert_eq!(limit.resolution, Resolution::Skipped);
+        assert_eq!((limit.stored_ms, limit.restored_ms), (3_000, 2_000));
+
+        let plan = super::plan(source(), ConflictStrategy::FailOnConflict, stored).unwrap();
+        assert!(plan.writes.is_empty());
+        assert!(plan.report.rejected());
+        assert_eq!(plan.report.restored, 0);
+        assert_eq!(plan.report.conflicts.len(), 2);
+    }
+
+    #[test]
+    fn test_restore_from_snapshot() {
+        let dir = std::env::temp_dir().join(format!("persistency-restore-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // Instances are pooled per id, the service itself uses instance 0
+        let kvs = KvsBuilder::new(InstanceId(6))
+            .dir(dir.display().to_string())
+            .build()
+            .unwrap();
+        kvs.set_value("vehicle/mode", "autonomous").unwrap();
+        kvs.set_value("vehicle/gear", 3u32).unwrap();
+        kvs.flush().unwrap();
+        kvs.set_value("vehicle/mode", "manual").unwrap();
+        kvs.flush().unwrap();
+
+        assert!(RestoreSource::from_snapshot(&kvs, 0).is_err());
+        assert!(RestoreSource::from_snapshot(&kvs, 2).is_err());
+        let source = RestoreSource::from_snapshot(&kvs, 1).unwrap();
+        assert_eq!(source.entries["vehicle/mode"], KvsValue::from("autonomous"));
+        assert_eq!(source.entries["vehicle/gear"], KvsValue::U32(3));
+        assert!(source.modified_ms > 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_from_export() {
+        let source = RestoreSource::from_export(
+            r#"{"format_version":1,"exported_at_ms":1700000000000,"prefixes":[],"entries":{"vehicle/mode":"manual"}}"#,
+        )
+        .unwrap();
+        assert_eq!(source.modified_ms, 1_700_000_000_000);
+        assert_eq!(source.entries["vehicle/mode"], KvsValue::from("manual"));
+
+        let error = RestoreSource::from_export(
+            r#"{"format_version":2,"exported_at_ms":0,"prefixes":[],"entries":{}}"#,
+        )
+        .unwrap_err();
+        assert!(error.contains("not supported"));
+    }
+
+    #[test]
+    fn test_restore_with_increments() {
+        let mut source = RestoreSource::from_export(
+            r#"{"format_version":1,"exported_at_ms":1000,"prefixes":[],"entries":{"vehicle/mode":"manual","vehicle/gear":3},"history_id":42,"revision":5}"#,
+        )
+        .unwrap();
+        source
+            .apply_increment(
+                r#"{"format_version":1,"exported_at_ms":2000,"prefixes":[],"entries":{"vehicle/mode":"autonomous","vehicle/route":"A9"},"history_id":42,"revision":8,"base_revision":5,"removed":["vehicle/gear"]}"#,
+            )
+            .unwrap();
+        // Differential to the same base, applied on top of the first increment
+        source
+            .apply_increment(
+                r#"{"format_version":1,"exported_at_ms":3000,"prefixes":[],"entries":{"vehicle/mode":"autonomous","vehicle/route":"B27"},"history_id":42,"revision":9,"base_revision":5,"removed":["vehicle/gear"]}"#,
+            )
+            .unwrap();
+        assert_eq!(
+            source.entries,
+            BTreeMap::from([
+                ("vehicle/mode".to_string(), KvsValue::from("autonomous")),
+                ("vehicle/route".to_string(), KvsValue::from("B27")),
+            ])
+        );
+        assert_eq!(
+            source.baseline,
+            Some(Baseline {
+                history_id: 42,
+                revision: 9
+            })
+        );
+
+        let plan = super::plan(source, ConflictStrategy::NewestWins, |key| {
+            Ok((key == "vehicle/route").then(|| (KvsValue::from("A7"), 2_500)))
+        })
+        .unwrap();
+        assert_eq!(
+            plan.writes[1],
+            ("vehicle/route".to_string(), KvsValue::from("B27"), 3_000)
+        );
+        assert_eq!(plan.report.conflicts[0].restored_ms, 3_000);
+    }
+
+    #[test]
+    fn test_increment_must_follow_source() {
+        let base = r#"{"format_version":1,"exported_at_ms":1000,"prefixes":[],"entries":{},"history_id":42,"revision":5}"#;
+        let increment = |history_id: u64, base: u64, revision: u64| {
+            format!(
+                r#"{{"format_version":1,"exported_at_ms":2000,"prefixes":[],"entries":{{}},"history_id":{},"revision":{},"base_revision":{}}}"#,
+                history_id, revision, base
+            )
+        };
+        let apply = |document: &str| {
+            RestoreSource::from_export(base)
+                .unwrap()
+                .apply_increment(document)
+        };
+
+        assert!(apply(&increment(42, 5, 7)).is_ok());
+        assert!(apply(&increment(42, 3, 7)).is_ok());
+        assert!(apply(&increment(43, 5, 7)).unwrap_err().contains("history"));
+        assert!(apply(&increment(42, 6, 7))
+            .unwrap_err()
+            .contains("does not follow"));
+        assert!(apply(&increment(42, 2, 4))
+            .unwrap_err()
+            .contains("does not follow"));
+        assert!(apply(base).unwrap_err().contains("not a differential"));
+
+        let mut snapshot = RestoreSource {
+            entries: BTreeMap::new(),
+            modified_ms: 0,
+            increment_ms: HashMap::new(),
+            baseline: None,
+        };
+        assert!(snapshot.apply_increment(&increment(42, 5, 7)).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Per-key revisions for optimistic concurrency
+//!
+//! Every change of a key, in any store, gives it the next value of a
+//! service-wide counter. `GetValue` and `SetValue` report the revision of
+//! the key, and a `SetValue` with an `expected_revision` only writes while
+//! the key is still at that revision, so that apiserver and statemanager
+//! can read, modify and write a key without losing a concurrent update.
+//!
+//! The counter and the revisions are kept in `persistency-revisions.json`
+//! next to the store and saved with every flush, before the store itself.
+//! After a restart the counter resumes well above the saved value, so that
+//! revisions handed out after the last flush are never handed out again.
+//! Keys stored before their revisions were tracked report the base revision
+//! of the file; after the store was recovered at startup every key does,
+//! as the saved revisions may belong to values that were lost.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// File the revisions are kept in, next to the store
+pub const REVISIONS_FILE: &str = "persistency-revisions.json";
+
+/// Revisions skipped after a restart, more than are handed out between two flushes
+const RESUME_GAP: u64 = 1 << 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct State {
+    /// Revision of the latest change
+    counter: u64,
+    /// Revision of the keys without a tracked revision
+    base: u64,
+    /// Revision per tracked key
+    keys: HashMap<String, u64>,
+}
+
+impl State {
+    fn starting_at(revision: u64) -> Self {
+        Self {
+            counter: revision,
+            base: revision,
+            keys: HashMap::new(),
+        }
+    }
+}
+
+/// Revisions of the stored keys
+#[derive(Debug)]
+pub struct Revisions {
+    state: Mutex<State>,
+    /// Whether the revisions changed since they were last saved
+    dirty: AtomicBool,
+    path: Option<PathBuf>,
+}
+
+impl Revisions {
+    /// Revisions kept in memory only
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State::starting_at(1)),
+            dirty: AtomicBool::new(false),
+            path: None,
+        }
+    }
+
+    /// Revisions saved in `dir`, resuming after the ones saved there before
+    ///
+    /// An unreadable file is logged and the revisions start over at
+    /// `now_ms`, above any counter a store of that age can have reached.
+    pub fn load(dir: &Path, now_ms: u64) -> Self {
+        let path = dir.join(REVISIONS_FILE);
+        let state = match fs::read(&path) {
+            Ok(content) => match serde_json::from_slice::<State>(&content) {
+                Ok(mut state) => {
+                    state.counter = state.counter.saturating_add(RESUME_GAP);
+                    state
+                }
+                Err(e) => {
+                    warn!("Ignoring unreadable revisions file {:?}: {}", path, e);
+                    State::starting_at(now_ms.max(1))
+                }
+            },
+            Err(_) => State::starting_at(1),
+        };
+        Self {
+            state: Mutex::new(state),
+            dirty: AtomicBool::new(true),
+            path: Some(path),
+        }
+    }
+
+    /// Revision of the stored `key`
+    pub fn get(&self, key: &str) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.keys.get(key).copied().unwrap_or(state.base)
+    }
+
+    /// Give the changed `key` the next revision and return it
+    pub fn bump(&self, key: &str) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.counter += 1;
+        let revision = state.counter;
+        state.keys.insert(key.to_string(), revision);
+        self.dirty.store(true, Ordering::Relaxed);
+        revision
+    }
+
+    /// Forget the removed `key` and return the revision of the removal
+    ///
+    /// The counter still moves on, so that the key starts at a new revision
+    /// when it is written again.
+    pub fn remove(&self, key: &str) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.counter += 1;
+        state.keys.remove(key);
+        self.dirty.store(true, Ordering::Relaxed);
+        state.counter
+    }
+
+    /// Forget the revision of every key, so that all of them report the next one
+    ///
+    /// Used after a recovery, when the values may no longer match their revisions.
+    pub fn forget_all(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.counter += 1;
+        let counter = state.counter;
+        *state = State::starting_at(counter);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Save the revisions if they changed since the last save
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let content = serde_json::to_vec(&*self.state.lock().unwrap())?;
+        let tmp = path.with_extension("tmp");
+        let result = fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, path));
+        if result.is_err() {
+            // Try again with the next flush
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl Default for Revisions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_raise_the_revision() {
+        let revisions = Revisions::new();
+        assert_eq!(revisions.get("vehicle/mode"), 1);
+
+        let first = revisions.bump("vehicle/mode");
+        let other = revisions.bump("vehicle/gear");
+        assert!(first > 1);
+        assert!(other > first);
+        assert_eq!(revisions.get("vehicle/mode"), first);
+
+        revisions.remove("vehicle/mode");
+        let recreated = revisions.bump("vehicle/mode");
+        assert!(recreated > other + 1);
+
+        revisions.forget_all();
+        assert!(revisions.get("vehicle/mode") > recreated);
+        assert_eq!(revisions.get("vehicle/mode"), revisions.get("vehicle/gear"));
+    }
+
+    #[test]
+    fn test_revisions_survive_restart() {
+        let dir =
+            std::env::temp_dir().join(format!("persistency-revisions-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let revisions = Revisions::load(&dir, 1_000);
+        let legacy = revisions.get("vehicle/speed");
+        let written = revisions.bump("vehicle/mode");
+        revisions.save().unwrap();
+        assert!(dir.join(REVISIONS_FILE).exists());
+
+        let reloaded = Revisions::load(&dir, 1_000);
+        assert_eq!(reloaded.get("vehicle/mode"), written);
+        assert_eq!(reloaded.get("vehicle/speed"), legacy);
+        // Revisions handed out after the last save are skipped
+        assert!(reloaded.bump("vehicle/gear") > written + RESUME_GAP);
+
+        fs::write(dir.join(REVISIONS_FILE), "{").unwrap();
+        let restarted = Revisions::load(&dir, 1_700_000_000_000);
+        assert_eq!(restarted.get("vehicle/mode"), 1_700_000_000_000);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Per-operation request trace sampling
+//!
+//! Every request is classified as a read or a write from its gRPC method
+//! name. A configurable fraction of each class is traced: the request runs
+//! inside a `persistency_request` span and its latency and outcome are logged
+//! at info level. Failed requests are always logged when `trace_errors` is
+//! set, whether they were sampled or not.
+//!
+//! Sampling is deterministic: a rate of 0.01 traces exactly every 100th
+//! request of that class. Rates can be changed at runtime through the
+//! `SetTraceSampling` admin RPC.
+
+use crate::config::TraceSamplingConfig;
+use http::{Request, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::body::BoxBody;
+use tower::{Layer, Service};
+use tracing::{info, info_span, warn, Instrument, Span};
+
+/// Sampling rates are stored as parts per million
+const RATE_SCALE: u64 = 1_000_000;
+
+/// Method name prefixes of read-only RPCs
+const READ_METHOD_PREFIXES: &[&str] = &["Get", "KeyExists"];
+
+/// Response extension marking a request that completed with `success: false`
+#[derive(Debug, Clone, Copy)]
+pub struct OperationFailed;
+
+/// Class of a persistency operation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperationKind {
+    Read,
+    Write,
+}
+
+impl OperationKind {
+    /// Classify a gRPC method name, e.g. `GetValue`
+    pub fn from_method(method: &str) -> Self {
+        if READ_METHOD_PREFIXES
+            .iter()
+            .any(|prefix| method.starts_with(prefix))
+        {
+            OperationKind::Read
+        } else {
+            OperationKind::Write
+        }
+    }
+}
+
+/// Runtime-adjustable sampling decisions shared by the layer and the admin RPC
+#[derive(Debug)]
+pub struct TraceSampler {
+    read_rate: AtomicU32,
+    write_rate: AtomicU32,
+    trace_errors: AtomicBool,
+    read_count: AtomicU64,
+    write_count: AtomicU64,
+}
+
+impl TraceSampler {
+    pub fn new(config: &TraceSamplingConfig) -> Self {
+        let sampler = Self {
+            read_rate: AtomicU32::new(0),
+            write_rate: AtomicU32::new(0),
+            trace_errors: AtomicBool::new(false),
+            read_count: AtomicU64::new(0),
+            write_count: AtomicU64::new(0),
+        };
+        sampler.store(config);
+        sampler
+    }
+
+    /// Current sampling configuration
+    pub fn config(&self) -> TraceSamplingConfig {
+        TraceSamplingConfig {
+            read_rate: from_ppm(self.read_rate.load(Ordering::Relaxed)),
+            write_rate: from_ppm(self.write_rate.load(Ordering::Relaxed)),
+            trace_errors: self.trace_errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Replace the sampling configuration
+    ///
+    /// ### Parameters
+    /// * `config: &TraceSamplingConfig` - new rates, each between 0.0 and 1.0
+    pub fn update(&self, config: &TraceSamplingConfig) -> Result<(), String> {
+        for (name, rate) in [
+            ("read_rate", config.read_rate),
+            ("write_rate", config.write_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(format!(
+                    "{} must be between 0.0 and 1.0, got {}",
+                    name, rate
+                ));
+            }
+        }
+        self.store(config);
+        Ok(())
+    }
+
+    fn store(&self, config: &TraceSamplingConfig) {
+        self.read_rate
+            .store(to_ppm(config.read_rate), Ordering::Relaxed);
+        self.write_rate
+            .store(to_ppm(config.write_rate), Ordering::Relaxed);
+        self.trace_errors
+            .store(config.trace_errors, Ordering::Relaxed);
+    }
+
+    /// Decide whether the next request of `kind` is traced
+    pub fn sample(&self, kind: OperationKind) -> bool {
+        let (rate, count) = match kind {
+            OperationKind::Read => (&self.read_rate, &self.read_count),
+            OperationKind::Write => (&self.write_rate, &self.write_count),
+        };
+        let rate = rate.load(Ordering::Relaxed) as u64;
+        let n = count.fetch_add(1, Ordering::Relaxed);
+        // Trace whenever the accumulated rate crosses the next integer
+        (n + 1) * rate / RATE_SCALE > n * rate / RATE_SCALE
+    }
+
+    /// Whether failed requests are always traced
+    pub fn trace_errors(&self) -> bool {
+        self.trace_errors.load(Ordering::Relaxed)
+    }
+}
+
+fn to_ppm(rate: f64) -> u32 {
+    (rate.clamp(0.0, 1.0) * RATE_SCALE as f64).round() as u32
+}
+
+fn from_ppm(ppm: u32) -> f64 {
+    ppm as f64 / RATE_SCALE as f64
+}
+
+/// Whether the response reports a failed operation
+fn is_failure(response: &Response<BoxBody>) -> bool {
+    let grpc_error = response
+        .headers()
+        .get("grpc-status")
+        .is_some_and(|status| status != "0");
+    grpc_error || response.extensions().get::<OperationFailed>().is_some()
+}
+
+/// Tower layer applying [`TraceSampler`] decisions to every request
+#[derive(Debug, Clone)]
+pub struct TraceSamplingLayer {
+    sampler: Arc<TraceSampler>,
+}
+
+impl TraceSamplingLayer {
+    pub fn new(sampler: Arc<TraceSampler>) -> Self {
+        Self { sampler }
+    }
+}
+
+impl<S> Layer<S> for TraceSamplingLayer {
+    type Service = TraceSamplingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceSamplingService {
+            inner,
+            sampler: self.sampler.clone(),
+        }
+    }
+}
+
+/// Service produced by [`TraceSamplingLayer`]
+#[derive(Debug, Clone)]
+pub struct TraceSamplingService<S> {
+    inner: S,
+    sampler: Arc<TraceSampler>,
+}
+
+impl<S, B> Service<Request<B>> for TraceSamplingService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let method = req
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let kind = OperationKind::from_method(&method);
+        let sampled = self.sampler.sample(kind);
+        let sampler = self.sampler.clone();
+
+        let span = if sampled {
+            info_span!("persistency_request", method = %method, kind = ?kind)
+        } else {
+            Span::none()
+        };
+        let start = Instant::now();
+        let future = self.inner.call(req).instrument(span.clone());
+
+        Box::pin(async move {
+            let result = future.await;
+            if let Ok(response) = &result {
+                let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+                let _entered = span.enter();
+                if is_failure(response) {
+                    if sampled || sampler.trace_errors() {
+                        warn!(method = %method, kind = ?kind, elapsed_ms, "Persistency request failed");
+                    }
+                } else if sampled {
+                    info!(method = %method, kind = ?kind, elapsed_ms, "Persistency request completed");
+                }
+            }
+            result
+        })
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Empty;
+    use std::convert::Infallible;
+
+    fn new_sampler(read_rate: f64, write_rate: f64) -> TraceSampler {
+        TraceSampler::new(&TraceSamplingConfig {
+            read_rate,
+            write_rate,
+            trace_errors: true,
+        })
+    }
+
+    #[test]
+    fn test_operation_kind_from_method() {
+        assert_eq!(OperationKind::from_method("GetValue"), OperationKind::Read);
+        assert_eq!(OperationKind::from_method("KeyExists"), OperationKind::Read);
+        assert_eq!(OperationKind::from_method("SetValue"), OperationKind::Write);
+        assert_eq!(OperationKind::from_method("Reset"), OperationKind::Write);
+    }
+
+    #[test]
+    fn test_sampling_rates() {
+        let sampler = new_sampler(0.01, 1.0);
+        let reads = (0..1000)
+            .filter(|_| sampler.sample(OperationKind::Read))
+            .count();
+        let writes = (0..1000)
+            .filter(|_| sampler.sample(OperationKind::Write))
+            .count();
+        assert_eq!(reads, 10);
+        assert_eq!(writes, 1000);
+
+        let sampler = new_sampler(0.0, 0.0);
+        assert!(!(0..1000).any(|_| sampler.sample(OperationKind::Read)));
+    }
+
+    #[test]
+    fn test_update_validates_rates() {
+        let sampler = new_sampler(0.01, 1.0);
+        let invalid = TraceSamplingConfig {
+            read_rate: 1.5,
+            ..Default::default()
+        };
+        assert!(sampler.update(&invalid).is_err());
+        assert_eq!(sampler.config().read_rate, 0.01);
+
+        let valid = TraceSamplingConfig {
+            read_rate: 0.25,
+            write_rate: 0.5,
+            trace_errors: false,
+        };
+        sampler.update(&valid).unwrap();
+        assert_eq!(sampler.config(), valid);
+    }
+
+    #[test]
+    fn test_is_failure() {
+        let ok = Response::new(tonic::body::empty_body());
+        assert!(!is_failure(&ok));
+
+        let mut status_error = Response::new(tonic::body::empty_body());
+        status_error
+            .headers_mut()
+            .insert("grpc-status", http::HeaderValue::from_static("7"));
+        assert!(is_failure(&status_error));
+
+        let mut failed = Response::new(tonic::body::empty_body());
+        failed.extensions_mut().insert(OperationFailed);
+        assert!(is_failure(&failed));
+    }
+
+    #[tokio::test]
+    async fn test_layer_passes_responses_through() {
+        let inner = tower::service_fn(|_req: Request<Empty<bytes::Bytes>>| async {
+            let mut response = Response::new(tonic::body::empty_body());
+            response.extensions_mut().insert(OperationFailed);
+            Ok::<_, Infallible>(response)
+        });
+        let sampler = Arc::new(new_sampler(0.0, 0.0));
+        let mut service = TraceSamplingLayer::new(sampler.clone()).layer(inner);
+
+        let request = Request::builder()
+            .uri("/persistency.PersistencyService/GetValue")
+            .body(Empty::new())
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+        assert!(response.extensions().get::<OperationFailed>().is_some());
+        // The request was counted as a read
+        assert_eq!(sampler.read_count.load(Ordering::Relaxed), 1);
+        assert_eq!(sampler.write_count.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,146 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Batches of the `Scan` RPC
+//!
+//! `GetAllWithPrefix` answers with every pair under the prefix in one
+//! message, which both sides hold in memory at once. A scan sends the pairs
+//! sorted by key in batches instead. Every batch is read under its own store
+//! lock, starting after the last key of the previous batch, so that writers
+//! are not held off for the whole scan and only one batch of values is held
+//! at a time. Keys written during a scan are reported if they sort after the
+//! batches already sent.
+
+use crate::backend::StorageBackend;
+use crate::encryption::Encryption;
+use crate::namespace::Store;
+use crate::PersistencyServiceImpl;
+use common::persistency_proto::KeyValue;
+use rust_kvs::prelude::*;
+use tracing::error;
+
+/// Batch size of requests without one
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// Largest batch size
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// Batch size to use for the `requested` one, 0 for the default
+pub fn batch_size(requested: u32) -> usize {
+    match requested as usize {
+        0 => DEFAULT_BATCH_SIZE,
+        requested => requested.min(MAX_BATCH_SIZE),
+    }
+}
+
+/// Keys of the next batch: at most `limit` keys starting with `prefix`,
+/// sorted and after `after`
+pub fn select_keys(
+    keys: impl IntoIterator<Item = String>,
+    prefix: &str,
+    after: Option<&str>,
+    limit: usize,
+) -> Vec<String> {
+    let mut selected: Vec<String> = keys
+        .into_iter()
+        .filter(|key| key.starts_with(prefix) && after.is_none_or(|after| key.as_str() > after))
+        .collect();
+    selected.sort_unstable();
+    selected.truncate(limit);
+    selected
+}
+
+/// Read the next batch of `store` after `after`
+///
+/// The keys are looked up in the key index of the store if it has one, see
+/// [`crate::key_index`]. Only keys passing `readable` are read. Spilled
+/// values are read in place, a scan must not evict the hot keys. Sealed
+/// values are opened, see [`crate::encryption`].
+pub fn read_batch(
+    store: &Store,
+    kvs: &dyn StorageBackend,
+    encryption: &Encryption,
+    prefix: &str,
+    after: Option<&str>,
+    limit: usize,
+    readable: impl Fn(&str) -> bool,
+) -> Result<Vec<KeyValue>, ErrorCode> {
+    let keys = match store.index() {
+        Some(index) => index.range(prefix, after, limit, &readable),
+        None => {
+            let mut keys = kvs.get_all_keys()?;
+            if let Some(spill) = store.spill() {
+                spill.for_each_spilled(|key, _| keys.push(key.to_string()))?;
+            }
+            let keys = keys.into_iter().filter(|key| readable(key));
+            select_keys(keys, prefix, after, limit)
+        }
+    };
+    let mut entries = Vec::new();
+    for key in keys {
+        let value = match store.spill() {
+            Some(spill) => spill.peek(kvs, &key),
+            None => kvs
+                .get_value(&key)
+                .ok()
+                .map(|value| PersistencyServiceImpl::kvs_value_to_proto(&value)),
+        };
+        // Removed since the keys were listed
+        let Some(value) = value else {
+            continue;
+        };
+        let value = encryption
+            .open_proto(&store.tracked_key(&key), value)
+            .map_err(|e| {
+                error!("{}", e);
+                ErrorCode::EncryptionFailed
+            })?;
+        entries.push(KeyValue {
+            key,
+            value: Some(value),
+        });
+    }
+    Ok(entries)
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn test_batch_sizes() {
+        assert_eq!(batch_size(0), DEFAULT_BATCH_SIZE);
+        assert_eq!(batch_size(10), 10);
+        assert_eq!(batch_size(u32::MAX), MAX_BATCH_SIZE);
+    }
+
+    #[test]
+    fn test_batches_continue_after_the_last_key() {
+        let stored = keys(&[
+            "Scenario/c",
+            "Package/a",
+            "Scenario/a",
+            "Scenario/d",
+            "Scenario/b",
+        ]);
+
+        let first = select_keys(stored.clone(), "Scenario/", None, 2);
+        assert_eq!(first, keys(&["Scenario/a", "Scenario/b"]));
+        let second = select_keys(stored.clone(), "Scenario/", Some("Scenario/b"), 2);
+        assert_eq!(second, keys(&["Scenario/c", "Scenario/d"]));
+        assert!(select_keys(stored, "Scenario/", Some("Scenario/d"), 2).is_empty());
+    }
+
+    #[test]
+    fn test_empty_prefix_selects_every_key() {
+        let stored = keys(&["b", "a"]);
+        assert_eq!(select_keys(stored, "", None, 10), keys(&["a", "b"]));
+    }
+}
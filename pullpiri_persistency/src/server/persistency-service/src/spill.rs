@@ -0,0 +1,790 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Bounded memory mode with a spill-to-disk index
+//!
+//! rust_kvs holds every key in memory, so the memory usage of the service
+//! grows with the number of keys. In bounded mode only the
+//! `max_resident_keys` most recently used keys stay in rust_kvs. Colder keys
+//! are spilled to one of `spill_buckets` files, chosen by a hash of the key,
+//! and read back into rust_kvs when they are accessed again. Memory holds the
+//! resident keys and two counters per bucket, independent of the number of
+//! spilled keys.
+//!
+//! Bucket files are append-only logs of length-delimited `KvsObject` records
+//! with one entry each; an entry without value removes the key. A bucket is
+//! compacted once it holds more than twice as many records as it had live
+//! keys after its last compaction. A truncated record at the end, left by a
+//! crash during an append, is ignored.
+//!
+//! A key read back from a bucket stays in it while resident, so evicting it
+//! again costs no write unless it changed meanwhile. Writing a key that is
+//! not resident does not look it up in its bucket, so the count of spilled
+//! keys may include overwritten records until the bucket is compacted. The
+//! rust_kvs snapshot holds the resident keys only; a key found in both is
+//! read from rust_kvs.
+
+use crate::backend::StorageBackend;
+use crate::config::MemoryConfig;
+use crate::PersistencyServiceImpl;
+use common::persistency_proto::{KvsObject, KvsValue as ProtoValue};
+use prost::Message;
+use rust_kvs::kvs_value::KvsValue;
+use rust_kvs::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{ErrorKind, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// File in the spill directory recording the number of buckets
+const BUCKET_COUNT_FILE: &str = "buckets";
+
+/// Records a bucket may hold beyond twice its compacted keys before it is compacted again
+const COMPACTION_SLACK: u32 = 64;
+
+/// Records and live keys of one bucket file
+#[derive(Debug, Clone, Copy, Default)]
+struct BucketStats {
+    records: u32,
+    /// Live keys, possibly counting keys overwritten without lookup twice
+    live: u32,
+    /// Live keys counted when the bucket was last read in full
+    compacted: u32,
+}
+
+/// Bookkeeping of a key held in rust_kvs
+#[derive(Debug, Clone, Copy)]
+struct Resident {
+    /// Position in the least recently used order
+    tick: u64,
+    /// Changed since it was last written to its bucket
+    dirty: bool,
+    /// Known to have a live record in its bucket
+    spilled: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    resident: HashMap<String, Resident>,
+    /// Resident keys by tick, least recently used first
+    lru: BTreeMap<u64, String>,
+    tick: u64,
+    buckets: Vec<BucketStats>,
+}
+
+impl State {
+    fn touch(&mut self, key: &str, dirty: bool, spilled: bool) {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.resident.get_mut(key) {
+            Some(resident) => {
+                self.lru.remove(&resident.tick);
+                resident.tick = tick;
+                resident.dirty |= dirty;
+            }
+            None => {
+                self.resident.insert(
+                    key.to_string(),
+                    Resident {
+                        tick,
+                        dirty,
+                        spilled,
+                    },
+                );
+            }
+        }
+        self.lru.insert(tick, key.to_string());
+    }
+
+    fn forget(&mut self, key: &str) -> Option<Resident> {
+        let resident = self.resident.remove(key)?;
+        self.lru.remove(&resident.tick);
+        Some(resident)
+    }
+}
+
+/// Memory usage of the bounded mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpillStats {
+    pub resident_keys: u64,
+    pub max_resident_keys: u64,
+    pub spilled_keys: u64,
+    /// Keys moved from memory to disk since the start
+    pub evictions: u64,
+    /// Keys read back from disk since the start
+    pub spill_reads: u64,
+    /// Bucket files rewritten without stale records since the start
+    pub compactions: u64,
+}
+
+/// Store operations of the bounded mode, wrapping the rust_kvs instance
+///
+/// Every access to a key goes through the store, which keeps rust_kvs to
+/// the resident keys. Operations are serialized, so that an eviction never
+/// races with a write of the evicted key.
+#[derive(Debug)]
+pub struct SpillStore {
+    dir: PathBuf,
+    max_resident: usize,
+    bucket_count: u32,
+    state: Mutex<State>,
+    evictions: AtomicU64,
+    spill_reads: AtomicU64,
+    compactions: AtomicU64,
+}
+
+/// Stable hash of a key, independent of the Rust version
+fn fnv1a(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl SpillStore {
+    /// Open the spill files next to the store and evict keys beyond the limit
+    ///
+    /// The number of buckets is fixed when the spill directory is created;
+    /// a different configured number is ignored with a warning.
+    ///
+    /// ### Parameters
+    /// * `config` - bounded memory configuration
+    /// * `base_dir` - storage directory the spill directory is relative to
+    /// * `kvs` - store holding the resident keys
+    pub fn open(
+        config: &MemoryConfig,
+        base_dir: &Path,
+        kvs: &dyn StorageBackend,
+    ) -> Result<Self, ErrorCode> {
+        let dir = base_dir.join(&config.spill_dir);
+        fs::create_dir_all(&dir)?;
+
+        let count_path = dir.join(BUCKET_COUNT_FILE);
+        let configured = config.spill_buckets.max(1);
+        let bucket_count = match fs::read_to_string(&count_path) {
+            Ok(content) => match content.trim().parse::<u32>() {
+                Ok(count) if count > 0 => {
+                    if count != configured {
+                        warn!(
+                            "Spill directory {:?} has {} buckets, ignoring configured {}",
+                            dir, count, configured
+                        );
+                    }
+                    count
+                }
+                _ => return Err(ErrorCode::ValidationFailed),
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                fs::write(&count_path, configured.to_string())?;
+                configured
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let store = Self {
+            dir,
+            max_resident: config.max_resident_keys.max(1),
+            bucket_count,
+            state: Mutex::new(State::default()),
+            evictions: AtomicU64::new(0),
+            spill_reads: AtomicU64::new(0),
+            compactions: AtomicU64::new(0),
+        };
+
+        let mut state = store.state.lock().unwrap();
+        for key in kvs.get_all_keys()? {
+            state.touch(&key, true, false);
+        }
+        // One bucket at a time, so that opening needs no memory per spilled key
+        for index in 0..bucket_count {
+            let (entries, records) = store.read_bucket(index)?;
+            for key in entries.keys() {
+                if let Some(resident) = state.resident.get_mut(key) {
+                    resident.spilled = true;
+                }
+            }
+            let live = entries.len() as u32;
+            state.buckets.push(BucketStats {
+                records,
+                live,
+                compacted: live,
+            });
+        }
+        store.evict(&mut state, kvs)?;
+        info!(
+            "Bounded memory mode: {} resident keys (limit {}), {} spilled keys in {:?}",
+            state.resident.len(),
+            store.max_resident,
+            state
+                .buckets
+                .iter()
+                .map(|bucket| u64::from(bucket.live))
+                .sum::<u64>(),
+            store.dir
+        );
+        drop(state);
+        Ok(store)
+    }
+
+    fn bucket_of(&self, key: &str) -> u32 {
+        (fnv1a(key) % u64::from(self.bucket_count)) as u32
+    }
+
+    fn bucket_path(&self, index: u32) -> PathBuf {
+        self.dir.join(format!("bucket_{:04}.pb", index))
+    }
+
+    /// Live entries of a bucket and the number of records in its file
+    fn read_bucket(&self, index: u32) -> Result<(HashMap<String, ProtoValue>, u32), ErrorCode> {
+        let data = match fs::read(self.bucket_path(index)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = HashMap::new();
+        let mut records = 0;
+        let mut buf = data.as_slice();
+        while !buf.is_empty() {
+            match KvsObject::decode_length_delimited(&mut buf) {
+                Ok(record) => {
+                    for (key, value) in record.values {
+                        records += 1;
+                        if value.value.is_some() {
+                            entries.insert(key, value);
+                        } else {
+                            entries.remove(&key);
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Ignoring truncated record in spill bucket {}: {}", index, e);
+                    break;
+                }
+            }
+        }
+        Ok((entries, records))
+    }
+
+    /// Value of a key in its bucket file
+    fn read_spilled(&self, key: &str) -> Result<Option<ProtoValue>, ErrorCode> {
+        let (mut entries, _) = self.read_bucket(self.bucket_of(key))?;
+        Ok(entries.remove(key))
+    }
+
+    /// Append records to a bucket file, a record without value removes its key
+    fn append(
+        &self,
+        index: u32,
+        records: &[(String, Option<ProtoValue>)],
+    ) -> Result<(), ErrorCode> {
+        let mut data = Vec::new();
+        for (key, value) in records {
+            let record = KvsObject {
+                values: HashMap::from([(key.clone(), value.clone().unwrap_or_default())]),
+            };
+            record
+                .encode_length_delimited(&mut data)
+                .map_err(|_| ErrorCode::ConversionFailed)?;
+        }
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.bucket_path(index))?
+            .write_all(&data)?;
+        Ok(())
+    }
+
+    /// Rewrite a bucket file with its live entries if it holds too many stale records
+    fn compact_if_needed(&self, state: &mut State, index: u32) -> Result<(), ErrorCode> {
+        let stats = state.buckets[index as usize];
+        if stats.records
+            <= stats
+                .compacted
+                .saturating_mul(2)
+                .saturating_add(COMPACTION_SLACK)
+        {
+            return Ok(());
+        }
+        let (entries, _) = self.read_bucket(index)?;
+        let mut data = Vec::new();
+        for (key, value) in &entries {
+            let record = KvsObject {
+                values: HashMap::from([(key.clone(), value.clone())]),
+            };
+            record
+                .encode_length_delimited(&mut data)
+                .map_err(|_| ErrorCode::ConversionFailed)?;
+        }
+        let path = self.bucket_path(index);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &data)?;
+        fs::rename(&tmp, &path)?;
+        let live = entries.len() as u32;
+        state.buckets[index as usize] = BucketStats {
+            records: live,
+            live,
+            compacted: live,
+        };
+        self.compactions.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Move the least recently used keys to their buckets until the limit is kept
+    ///
+    /// Keys are evicted in batches of a tenth of the limit, so that the
+    /// appends to a bucket are combined.
+    fn evict(&self, state: &mut State, kvs: &dyn StorageBackend) -> Result<(), ErrorCode> {
+        if state.resident.len() <= self.max_resident {
+            return Ok(());
+        }
+        let target = self.max_resident - self.max_resident / 10;
+
+        let mut victims: BTreeMap<u32, Vec<(String, Resident)>> = BTreeMap::new();
+        while state.resident.len() > target {
+            let Some((_, key)) = state.lru.pop_first() else {
+                break;
+            };
+            let resident = state.resident.remove(&key).unwrap();
+            victims
+                .entry(self.bucket_of(&key))
+                .or_default()
+                .push((key, resident));
+        }
+
+        let mut result = Ok(());
+        for (index, keys) in victims {
+            let mut records = Vec::new();
+            for (key, resident) in &keys {
+                if resident.dirty {
+                    let value = kvs.get_value(key)?;
+                    records.push((
+                        key.clone(),
+                        Some(PersistencyServiceImpl::kvs_value_to_proto(&value)),
+                    ));
+                }
+            }
+            if let Err(e) = self.append(index, &records) {
+                // Keep the keys in memory rather than losing them
+                warn!(
+                    "Failed to spill {} keys to bucket {}: {:?}",
+                    records.len(),
+                    index,
+                    e
+                );
+                for (key, resident) in keys {
+                    state.touch(&key, resident.dirty, resident.spilled);
+                }
+                result = Err(e);
+                continue;
+            }
+
+            let stats = &mut state.buckets[index as usize];
+            stats.records += records.len() as u32;
+            stats.live += keys
+                .iter()
+                .filter(|(_, resident)| !resident.spilled)
+                .count() as u32;
+            for (key, _) in &keys {
+                match kvs.remove_key(key) {
+                    Ok(()) | Err(ErrorCode::KeyNotFound) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            self.evictions
+                .fetch_add(keys.len() as u64, Ordering::Relaxed);
+            self.compact_if_needed(state, index)?;
+        }
+        result
+    }
+
+    /// Value of `key`, read back into memory if it was spilled
+    pub fn get(&self, kvs: &dyn StorageBackend, key: &str) -> Result<KvsValue, ErrorCode> {
+        let mut state = self.state.lock().unwrap();
+        match kvs.get_value(key) {
+            Ok(value) => {
+                if state.resident.contains_key(key) {
+                    state.touch(key, false, false);
+                }
+                Ok(value)
+            }
+            Err(ErrorCode::KeyNotFound) => {
+                let proto = self.read_spilled(key)?.ok_or(ErrorCode::KeyNotFound)?;
+                let value = PersistencyServiceImpl::proto_to_kvs_value(&proto)
+                    .map_err(|_| ErrorCode::ConversionFailed)?;
+                kvs.set_value(key, value.clone())?;
+                state.touch(key, false, true);
+                self.spill_reads.fetch_add(1, Ordering::Relaxed);
+                if let Err(e) = self.evict(&mut state, kvs) {
+                    warn!("Failed to evict keys after reading {}: {:?}", key, e);
+                }
+                Ok(value)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Value of `key` without reading it back into memory, `None` if it does not exist
+    pub fn peek(&self, kvs: &dyn StorageBackend, key: &str) -> Option<ProtoValue> {
+        let state = self.state.lock().unwrap();
+        if let Ok(value) = kvs.get_value(key) {
+            return Some(PersistencyServiceImpl::kvs_value_to_proto(&value));
+        }
+        if state.resident.contains_key(key) {
+            return None;
+        }
+        self.read_spilled(key).ok().flatten()
+    }
+
+    /// Store `value` under `key` in memory, evicting colder keys beyond the limit
+    pub fn set(
+        &self,
+        kvs: &dyn StorageBackend,
+        key: &str,
+        value: KvsValue,
+    ) -> Result<(), ErrorCode> {
+        let mut state = self.state.lock().unwrap();
+        let spilled = state
+            .resident
+            .get(key)
+            .is_some_and(|resident| resident.spilled);
+        kvs.set_value(key, value)?;
+        state.touch(key, true, spilled);
+        if let Err(e) = self.evict(&mut state, kvs) {
+            warn!("Failed to evict keys after writing {}: {:?}", key, e);
+        }
+        Ok(())
+    }
+
+    /// Remove `key` from memory and from its bucket
+    pub fn remove(&self, kvs: &dyn StorageBackend, key: &str) -> Result<(), ErrorCode> {
+        let mut state = self.state.lock().unwrap();
+        let index = self.bucket_of(key);
+        let spilled = match state.resident.get(key) {
+            Some(resident) if resident.spilled => true,
+            _ => state.buckets[index as usize].live > 0 && self.read_spilled(key)?.is_some(),
+        };
+        // Remove the bucket record first, so that a failure cannot bring back the key
+        if spilled {
+            self.append(index, &[(key.to_string(), None)])?;
+            let stats = &mut state.buckets[index as usize];
+            stats.records += 1;
+            stats.live = stats.live.saturating_sub(1);
+        }
+        let resident = state.forget(key).is_some();
+        match kvs.remove_key(key) {
+            Ok(()) => {}
+            Err(ErrorCode::KeyNotFound) if spilled || resident => {}
+            Err(e) => return Err(e),
+        }
+        if spilled {
+            self.compact_if_needed(&mut state, index)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `key` exists in memory or in its bucket
+    pub fn contains(&self, kvs: &dyn StorageBackend, key: &str) -> Result<bool, ErrorCode> {
+        let state = self.state.lock().unwrap();
+        if kvs.key_exists(key)? {
+            return Ok(true);
+        }
+        if state.resident.contains_key(key) || state.buckets[self.bucket_of(key) as usize].live == 0
+        {
+            return Ok(false);
+        }
+        Ok(self.read_spilled(key)?.is_some())
+    }
+
+    /// Call `f` with every spilled key that is not resident and its value
+    ///
+    /// Buckets are read one at a time. Resident keys are left out, their
+    /// current value is in rust_kvs.
+    pub fn for_each_spilled(&self, mut f: impl FnMut(&str, &KvsValue)) -> Result<(), ErrorCode> {
+        let state = self.state.lock().unwrap();
+        for index in 0..self.bucket_count {
+            if state.buckets[index as usize].live == 0 {
+                continue;
+            }
+            let (entries, _) = self.read_bucket(index)?;
+            for (key, proto) in entries {
+                if state.resident.contains_key(&key) {
+                    continue;
+                }
+                match PersistencyServiceImpl::proto_to_kvs_value(&proto) {
+                    Ok(value) => f(&key, &value),
+                    Err(e) => warn!("Skipping spilled key {} with invalid value: {}", key, e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget every spilled key after the store was reset
+    pub fn clear(&self) -> Result<(), ErrorCode> {
+        let mut state = self.state.lock().unwrap();
+        for index in 0..self.bucket_count {
+            match fs::remove_file(self.bucket_path(index)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+            state.buckets[index as usize] = BucketStats::default();
+        }
+        state.resident.clear();
+        state.lru.clear();
+        Ok(())
+    }
+
+    /// Current memory usage and counters
+    pub fn stats(&self) -> SpillStats {
+        let state = self.state.lock().unwrap();
+        SpillStats {
+            resident_keys: state.resident.len() as u64,
+            max_resident_keys: self.max_resident as u64,
+            spilled_keys: state
+                .buckets
+                .iter()
+                .map(|bucket| u64::from(bucket.live))
+                .sum(),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            spill_reads: self.spill_reads.load(Ordering::Relaxed),
+            compactions: self.compactions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Memory usage and counters in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let stats = self.stats();
+        let metrics = [
+            (
+                "persistency_memory_resident_keys",
+                "gauge",
+                "Keys held in memory",
+                stats.resident_keys,
+            ),
+            (
+                "persistency_memory_max_resident_keys",
+                "gauge",
+                "Keys that may be held in memory",
+                stats.max_resident_keys,
+            ),
+            (
+                "persistency_memory_spilled_keys",
+                "gauge",
+                "Keys in the spill files, including resident copies",
+                stats.spilled_keys,
+            ),
+            (
+                "persistency_memory_evictions_total",
+                "counter",
+                "Keys moved from memory to the spill files",
+                stats.evictions,
+            ),
+            (
+                "persistency_memory_spill_reads_total",
+                "counter",
+                "Keys read back from the spill files",
+                stats.spill_reads,
+            ),
+            (
+                "persistency_memory_compactions_total",
+                "counter",
+                "Spill files rewritten without stale records",
+                stats.compactions,
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(
+                out,
+                "# HELP {} {}\n# TYPE {} {}\n{} {}",
+                name, help, name, kind, name, value
+            );
+        }
+        out
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("persistency-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn config(max_resident_keys: usize) -> MemoryConfig {
+        MemoryConfig {
+            bounded: true,
+            max_resident_keys,
+            spill_buckets: 4,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_spills_and_reads_back() {
+        let dir = temp_dir("spill");
+        // Instances are pooled per id, the service itself uses instance 0
+        let kvs = KvsBuilder::new(InstanceId(8))
+            .dir(dir.display().to_string())
+            .build()
+            .unwrap();
+        let store = SpillStore::open(&config(10), &dir, &kvs).unwrap();
+
+        for i in 0..100 {
+            store
+                .set(&kvs, &format!("key/{}", i), KvsValue::U32(i))
+                .unwrap();
+        }
+        let stats = store.stats();
+        assert!(stats.resident_keys <= 10);
+        assert_eq!(
+            KvsApi::get_all_keys(&kvs).unwrap().len() as u64,
+            stats.resident_keys
+        );
+        assert!(stats.spilled_keys >= 90);
+
+        // Cold keys are read back, changed and removed like resident ones
+        assert_eq!(store.get(&kvs, "key/0").unwrap(), KvsValue::U32(0));
+        assert!(KvsApi::key_exists(&kvs, "key/0").unwrap());
+        assert!(store.contains(&kvs, "key/1").unwrap());
+        assert!(!store.contains(&kvs, "key/100").unwrap());
+        store.set(&kvs, "key/2", KvsValue::U32(200)).unwrap();
+        store.remove(&kvs, "key/3").unwrap();
+        assert!(matches!(
+            store.remove(&kvs, "key/3"),
+            Err(ErrorCode::KeyNotFound)
+        ));
+        assert!(matches!(
+            store.get(&kvs, "key/3"),
+            Err(ErrorCode::KeyNotFound)
+        ));
+
+        // Evict everything that changed, then check the files on their own
+        for i in 100..200 {
+            store
+                .set(&kvs, &format!("key/{}", i), KvsValue::U32(i))
+                .unwrap();
+        }
+        let mut spilled = HashMap::new();
+        store
+            .for_each_spilled(|key, value| {
+                spilled.insert(key.to_string(), value.clone());
+            })
+            .unwrap();
+        assert_eq!(spilled.get("key/2"), Some(&KvsValue::U32(200)));
+        assert!(!spilled.contains_key("key/3"));
+        assert_eq!(
+            spilled.len() + KvsApi::get_all_keys(&kvs).unwrap().len(),
+            199,
+            "every key but the removed one is either resident or spilled"
+        );
+
+        // Reopening finds the spilled keys and the resident ones
+        let reopened = SpillStore::open(&config(10), &dir, &kvs).unwrap();
+        assert!(reopened.stats().spilled_keys >= spilled.len() as u64);
+        assert!(reopened.stats().spilled_keys <= store.stats().spilled_keys);
+        assert_eq!(reopened.get(&kvs, "key/50").unwrap(), KvsValue::U32(50));
+        assert!(reopened
+            .render_metrics()
+            .contains("persistency_memory_max_resident_keys 10"));
+
+        store.clear().unwrap();
+        assert_eq!(store.stats().spilled_keys, 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ignores_truncated_record() {
+        let dir = temp_dir("spill-truncated");
+        let store = SpillStore {
+            dir: dir.clone(),
+            max_resident: 1,
+            bucket_count: 1,
+            state: Mutex::new(State::default()),
+            evictions: AtomicU64::new(0),
+            spill_reads: AtomicU64::new(0),
+            compactions: AtomicU64::new(0),
+        };
+        let value = PersistencyServiceImpl::kvs_value_to_proto(&KvsValue::Boolean(true));
+        store
+            .append(0, &[("a".to_string(), Some(value.clone()))])
+            .unwrap();
+        store.append(0, &[("b".to_string(), Some(value))]).unwrap();
+        let path = store.bucket_path(0);
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 2]).unwrap();
+
+        let (entries, records) = store.read_bucket(0).unwrap();
+        assert_eq!(records, 1);
+        assert!(entries.contains_key("a"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Resident memory stays bounded while the number of keys grows tenfold
+    ///
+    /// Run with `cargo test --release -- --ignored test_memory_is_bounded`.
+    #[test]
+    #[ignore]
+    fn test_memory_is_bounded() {
+        fn rss_kib() -> u64 {
+            let status = fs::read_to_string("/proc/self/status").unwrap();
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmRSS:"))
+                .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+                .unwrap()
+        }
+
+        let dir = temp_dir("spill-bench");
+        let kvs = KvsBuilder::new(InstanceId(7))
+            .dir(dir.display().to_string())
+            .build()
+            .unwrap();
+        let store = SpillStore::open(
+            &MemoryConfig {
+                bounded: true,
+                max_resident_keys: 10_000,
+                ..Default::default()
+            },
+            &dir,
+            &kvs,
+        )
+        .unwrap();
+
+        let value = "x".repeat(64);
+        let mut rss_at_100k = 0;
+        for i in 0..1_000_000u32 {
+            store
+                .set(
+                    &kvs,
+                    &format!("bench/{:07}", i),
+                    KvsValue::String(value.clone()),
+                )
+                .unwrap();
+            if i == 100_000 {
+                rss_at_100k = rss_kib();
+            }
+        }
+        let growth_kib = rss_kib().saturating_sub(rss_at_100k);
+        let stats = store.stats();
+        assert_eq!(stats.resident_keys + stats.spilled_keys, 1_000_000);
+        assert!(stats.resident_keys <= 10_000);
+        assert!(
+            growth_kib < 64 * 1024,
+            "resident memory grew by {} KiB",
+            growth_kib
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,344 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Request, flush and store metrics
+//!
+//! [`ServiceStats`] counts the RPCs per method and gRPC status code and keeps
+//! a latency histogram per method, recorded by the
+//! [`crate::metrics::MetricsLayer`] for every request it passes on. It also
+//! times the flushes of the stores. [`StoreStats`] reads the keys and the
+//! storage file size of every store when the metrics are rendered.
+
+use crate::backend::storage_file;
+use crate::config::{NamespacesConfig, StorageConfig};
+use crate::forecast::escape_label;
+use crate::namespace::{Namespaces, DEFAULT_INSTANCE};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of the RPC latency buckets in seconds
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+];
+
+/// Upper bounds of the flush duration buckets in seconds
+pub const FLUSH_BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// Prefix of the HTTP paths of the persistency RPCs
+const RPC_PATH_PREFIX: &str = "/persistency.PersistencyService/";
+
+/// Prometheus histogram with cumulative buckets
+#[derive(Debug, Clone)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// Observations up to each bound
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (bound, bucket) in self.bounds.iter().zip(&mut self.buckets) {
+            if secs <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    /// Append the samples of `name` with `labels`, e.g. `method="GetValue"`
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let bounds = self.bounds.iter().map(|bound| bound.to_string());
+        for (bound, bucket) in bounds
+            .chain(["+Inf".to_string()])
+            .zip(self.buckets.iter().chain([&self.count]))
+        {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, bound, bucket
+            );
+        }
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+#[derive(Debug)]
+struct Counters {
+    /// Requests per method and status code
+    requests: BTreeMap<(String, String), u64>,
+    latency: BTreeMap<String, Histogram>,
+    flushes: Histogram,
+    failed_flushes: u64,
+}
+
+/// RPC and flush counters of the service
+#[derive(Debug)]
+pub struct ServiceStats {
+    counters: Mutex<Counters>,
+}
+
+impl Default for ServiceStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServiceStats {
+    pub fn new() -> Self {
+        Self {
+            counters: Mutex::new(Counters {
+                requests: BTreeMap::new(),
+                latency: BTreeMap::new(),
+                flushes: Histogram::new(&FLUSH_BUCKETS),
+                failed_flushes: 0,
+            }),
+        }
+    }
+
+    /// RPC method of an HTTP request path, `None` for other requests
+    pub fn method(path: &str) -> Option<&str> {
+        path.strip_prefix(RPC_PATH_PREFIX)
+            .filter(|method| !method.is_empty())
+    }
+
+    /// Record an RPC of `method` answered with `code` after `elapsed`
+    pub fn record_rpc(&self, method: &str, code: tonic::Code, elapsed: Duration) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters
+            .requests
+            .entry((method.to_string(), format!("{:?}", code)))
+            .or_default() += 1;
+        counters
+            .latency
+            .entry(method.to_string())
+            .or_insert_with(|| Histogram::new(&LATENCY_BUCKETS))
+            .observe(elapsed);
+    }
+
+    /// Record a flush of a store that took `elapsed`
+    pub fn record_flush(&self, elapsed: Duration, succeeded: bool) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.flushes.observe(elapsed);
+        if !succeeded {
+            counters.failed_flushes += 1;
+        }
+    }
+
+    /// RPCs of `method` answered with `code` so far
+    pub fn rpcs(&self, method: &str, code: tonic::Code) -> u64 {
+        let key = (method.to_string(), format!("{:?}", code));
+        let counters = self.counters.lock().unwrap();
+        counters.requests.get(&key).copied().unwrap_or_default()
+    }
+
+    /// Flushes of the stores so far, failed ones included
+    pub fn flushes(&self) -> u64 {
+        self.counters.lock().unwrap().flushes.count
+    }
+
+    /// Current metrics in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut out = String::new();
+
+        let name = "persistency_rpcs_total";
+        let _ = writeln!(
+            out,
+            "# HELP {} RPCs handled per method and gRPC status code\n# TYPE {} counter",
+            name, name
+        );
+        for ((method, code), count) in &counters.requests {
+            let _ = writeln!(
+                out,
+                "{}{{method=\"{}\",code=\"{}\"}} {}",
+                name,
+                escape_label(method),
+                code,
+                count
+            );
+        }
+
+        let name = "persistency_rpc_duration_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time until the response headers per method\n# TYPE {} histogram",
+            name, name
+        );
+        for (method, latency) in &counters.latency {
+            let labels = format!("method=\"{}\"", escape_label(method));
+            latency.render(&mut out, name, &labels);
+        }
+
+        let name = "persistency_flush_duration_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time taken by the flushes of the stores\n# TYPE {} histogram",
+            name, name
+        );
+        counters.flushes.render(&mut out, name, "");
+
+        let name = "persistency_flush_failures_total";
+        let _ = writeln!(
+            out,
+            "# HELP {} Flushes of the stores that failed\n# TYPE {} counter\n{} {}",
+            name, name, name, counters.failed_flushes
+        );
+        out
+    }
+}
+
+/// Keys and storage file size of every store
+pub struct StoreStats {
+    namespaces: Arc<Namespaces>,
+    /// Storage file per namespace, the default store under the empty name,
+    /// none for the memory backend
+    files: BTreeMap<String, PathBuf>,
+}
+
+impl StoreStats {
+    /// ### Parameters
+    /// * `namespaces` - the stores
+    /// * `dir` - directory of the storage files
+    /// * `storage` - backend of the stores
+    /// * `config` - rust_kvs instance of every namespace
+    pub fn new(
+        namespaces: Arc<Namespaces>,
+        dir: &Path,
+        storage: &StorageConfig,
+        config: &NamespacesConfig,
+    ) -> Self {
+        let file = |instance| storage_file(storage.backend, dir, instance);
+        let files = std::iter::once((String::new(), DEFAULT_INSTANCE))
+            .chain(
+                config
+                    .instances
+                    .iter()
+                    .map(|(name, &instance)| (name.clone(), instance)),
+            )
+            .filter_map(|(name, instance)| Some((name, file(instance)?)))
+            .collect();
+        Self { namespaces, files }
+    }
+
+    /// Current metrics in the Prometheus text format
+    ///
+    /// The keys of a store a writer holds are left out rather than waiting
+    /// for it, the file size of a store that was never flushed as well.
+    pub fn render_metrics(&self) -> String {
+        let mut keys = String::new();
+        let mut sizes = String::new();
+        for store in self.namespaces.iter() {
+            let namespace = escape_label(store.name());
+            if let Some(count) = store
+                .try_read()
+                .and_then(|kvs| kvs.get_all_keys().ok())
+                .map(|keys| keys.len())
+            {
+                let _ = writeln!(
+                    keys,
+                    "persistency_keys{{namespace=\"{}\"}} {}",
+                    namespace, count
+                );
+            }
+            if let Some(size) = self
+                .files
+                .get(store.name())
+                .and_then(|file| std::fs::metadata(file).ok())
+                .map(|metadata| metadata.len())
+            {
+                let _ = writeln!(
+                    sizes,
+                    "persistency_storage_file_bytes{{namespace=\"{}\"}} {}",
+                    namespace, size
+                );
+            }
+        }
+        format!(
+            "# HELP persistency_keys Keys per namespace\n# TYPE persistency_keys gauge\n{}\
+             # HELP persistency_storage_file_bytes Size of the storage file per namespace\n\
+             # TYPE persistency_storage_file_bytes gauge\n{}",
+            keys, sizes
+        )
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[test]
+    fn test_method_of_path() {
+        assert_eq!(
+            ServiceStats::method("/persistency.PersistencyService/GetValue"),
+            Some("GetValue")
+        );
+        assert_eq!(
+            ServiceStats::method("/persistency.PersistencyService/"),
+            None
+        );
+        assert_eq!(ServiceStats::method("/metrics"), None);
+    }
+
+    #[test]
+    fn test_rpcs_counted_per_code() {
+        let stats = ServiceStats::new();
+        stats.record_rpc("GetValue", Code::Ok, Duration::from_micros(800));
+        stats.record_rpc("GetValue", Code::Ok, Duration::from_millis(20));
+        stats.record_rpc("GetValue", Code::NotFound, Duration::from_secs(2));
+        assert_eq!(stats.rpcs("GetValue", Code::Ok), 2);
+        assert_eq!(stats.rpcs("SetValue", Code::Ok), 0);
+
+        let metrics = stats.render_metrics();
+        assert!(metrics.contains("persistency_rpcs_total{method=\"GetValue\",code=\"Ok\"} 2"));
+        assert!(metrics.contains("persistency_rpcs_total{method=\"GetValue\",code=\"NotFound\"} 1"));
+        assert!(metrics.contains(
+            "persistency_rpc_duration_seconds_bucket{method=\"GetValue\",le=\"0.001\"} 1"
+        ));
+        assert!(metrics
+            .contains("persistency_rpc_duration_seconds_bucket{method=\"GetValue\",le=\"1\"} 2"));
+        assert!(metrics.contains(
+            "persistency_rpc_duration_seconds_bucket{method=\"GetValue\",le=\"+Inf\"} 3"
+        ));
+        assert!(metrics.contains("persistency_rpc_duration_seconds_count{method=\"GetValue\"} 3"));
+    }
+
+    #[test]
+    fn test_flushes_timed() {
+        let stats = ServiceStats::new();
+        stats.record_flush(Duration::from_millis(3), true);
+        stats.record_flush(Duration::from_millis(300), false);
+
+        let metrics = stats.render_metrics();
+        assert!(metrics.contains("persistency_flush_duration_seconds_bucket{le=\"0.005\"} 1"));
+        assert!(metrics.contains("persistency_flush_duration_seconds_count 2"));
+        assert!(metrics.contains("persistency_flush_failures_total 1"));
+        assert_eq!(stats.flushes(), 2);
+    }
+}
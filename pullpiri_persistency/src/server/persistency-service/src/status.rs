@@ -0,0 +1,223 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! gRPC status codes for failed operations
+//!
+//! The service answers failed operations with `success: false` and a free
+//! form `error_message`, so clients cannot tell a missing key from a broken
+//! disk without parsing the message. Clients sending
+//! [`STATUS_CODES_HEADER`] get a gRPC status instead: `NOT_FOUND` for a
+//! missing key, `INVALID_ARGUMENT` or `FAILED_PRECONDITION` for a request
+//! that cannot succeed as sent, `ABORTED` for a write whose expected
+//! revision is stale, `RESOURCE_EXHAUSTED` for a write beyond the quotas,
+//! `INTERNAL` for storage errors. Older clients keep getting the responses
+//! they parse.
+//!
+//! The [`StatusCodesLayer`] records per request whether the caller asked for
+//! status codes, and the handlers answer failures through [`reject`].
+//! Responses that report results of a partly failed operation, e.g. the
+//! keys a batch wrote before it failed, are always sent as responses.
+//! Requests that do not pass the layer, e.g. of the lite protocol or the
+//! admin web UI, get responses. The REST gateway always asks for status
+//! codes, see [`with_status_codes`].
+
+use common::persistency_proto::{
+    CancelPendingOperationResponse, CompareAndSetResponse, CreateBackupResponse, DecrementResponse,
+    DumpStateForDiagnosticsResponse, ExportToFileResponse, FlushPrefixResponse, FlushResponse,
+    GetAllKeysResponse, GetAllWithPrefixResponse, GetHistoryResponse, GetTtlResponse,
+    GetUsageReportsResponse, GetValueAtRevisionResponse, GetValueResponse, GetValuesResponse,
+    GrantLeaseResponse, ImportFromFileResponse, IncrementResponse, KeyExistsResponse,
+    ListBackupsResponse, ListDeletedResponse, LockResponse, MigrateFromEtcdResponse,
+    RemoveKeyResponse, ResetResponse, RestoreKeyResponse, RestoreResponse, RestoreSnapshotResponse,
+    RevokeLeaseResponse, SetFaultInjectionResponse, SetLargeValueResponse,
+    SetTraceSamplingResponse, SetValueResponse, SetValuesResponse, UnlockResponse,
+    STATUS_CODES_HEADER,
+};
+use http::{Request, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+
+tokio::task_local! {
+    static STATUS_CODES: bool;
+}
+
+/// Whether the caller of the request being handled asked for status codes
+pub fn wanted() -> bool {
+    STATUS_CODES.try_with(|wanted| *wanted).unwrap_or(false)
+}
+
+/// Run `f` as if the caller asked for status codes or not, for tests
+pub fn scope<R>(wanted: bool, f: impl FnOnce() -> R) -> R {
+    STATUS_CODES.sync_scope(wanted, f)
+}
+
+/// Response of an operation that can fail, with its error message
+pub trait Failure {
+    fn error_message(&self) -> &str;
+}
+
+macro_rules! impl_failure {
+    ($($response:ty),* $(,)?) => {
+        $(impl Failure for $response {
+            fn error_message(&self) -> &str {
+                &self.error_message
+            }
+        })*
+    };
+}
+
+impl_failure!(
+    SetValueResponse,
+    GetValueResponse,
+    RemoveKeyResponse,
+    SetValuesResponse,
+    GetValuesResponse,
+    CompareAndSetResponse,
+    IncrementResponse,
+    DecrementResponse,
+    GetAllKeysResponse,
+    KeyExistsResponse,
+    GetTtlResponse,
+    GetHistoryResponse,
+    GetValueAtRevisionResponse,
+    GetAllWithPrefixResponse,
+    ResetResponse,
+    FlushResponse,
+    FlushPrefixResponse,
+    SetTraceSamplingResponse,
+    DumpStateForDiagnosticsResponse,
+    GetUsageReportsResponse,
+    RestoreResponse,
+    CancelPendingOperationResponse,
+    SetFaultInjectionResponse,
+    SetLargeValueResponse,
+    RestoreSnapshotResponse,
+    ListBackupsResponse,
+    CreateBackupResponse,
+    ExportToFileResponse,
+    ImportFromFileResponse,
+    MigrateFromEtcdResponse,
+    ListDeletedResponse,
+    RestoreKeyResponse,
+    GrantLeaseResponse,
+    RevokeLeaseResponse,
+    LockResponse,
+    UnlockResponse,
+);
+
+/// Run `future` as if the caller asked for status codes
+pub async fn with_status_codes<F: Future>(future: F) -> F::Output {
+    STATUS_CODES.scope(true, future).await
+}
+
+/// Status with `code` carrying the error message of `response` if the caller
+/// asked for status codes, `response` otherwise
+pub fn reject<T: Failure>(code: Code, response: T) -> Result<T, Status> {
+    if wanted() {
+        Err(Status::new(code, response.error_message()))
+    } else {
+        Ok(response)
+    }
+}
+
+/// Tower layer recording whether the caller asked for status codes
+#[derive(Debug, Clone, Default)]
+pub struct StatusCodesLayer;
+
+impl StatusCodesLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for StatusCodesLayer {
+    type Service = StatusCodesService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        StatusCodesService { inner }
+    }
+}
+
+/// Service produced by [`StatusCodesLayer`]
+#[derive(Debug, Clone)]
+pub struct StatusCodesService<S> {
+    inner: S,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for StatusCodesService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let wanted = req
+            .headers()
+            .get(STATUS_CODES_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"));
+        let future = self.inner.call(req);
+        Box::pin(STATUS_CODES.scope(wanted, future))
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Empty;
+    use std::convert::Infallible;
+
+    fn missing() -> GetValueResponse {
+        GetValueResponse {
+            success: false,
+            value: None,
+            error_message: "Key not found: KeyNotFound".to_string(),
+            revision: 0,
+        }
+    }
+
+    #[test]
+    fn test_reject() {
+        let response = scope(false, || reject(Code::NotFound, missing())).unwrap();
+        assert!(!response.success);
+
+        let status = scope(true, || reject(Code::NotFound, missing())).unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "Key not found: KeyNotFound");
+
+        // Outside of a request
+        assert!(reject(Code::Internal, missing()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_layer_reads_header() {
+        let inner = tower::service_fn(|_req: Request<Empty<bytes::Bytes>>| async {
+            Ok::<_, Infallible>(Response::new(wanted()))
+        });
+        let mut service = StatusCodesLayer::new().layer(inner);
+
+        let request = Request::builder()
+            .header(STATUS_CODES_HEADER, "true")
+            .body(Empty::new())
+            .unwrap();
+        assert!(*service.call(request).await.unwrap().body());
+        assert!(!*service
+            .call(Request::new(Empty::new()))
+            .await
+            .unwrap()
+            .body());
+    }
+}
@@ -0,0 +1,106 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Key locks striped by the hash of the key
+//!
+//! Every store sits behind one `RwLock`. Requests touching single keys share
+//! it, and rust_kvs only holds its own lock for the map access itself, so
+//! writes of different keys run side by side. `CompareAndSet`, `Increment`
+//! and `Decrement` however have to keep other writers of their key away
+//! between their read and their write. Taking the store lock exclusively for
+//! that stalled every request of every component while one counter was
+//! updated.
+//!
+//! Instead, every single-key write locks the stripe its key hashes to, in
+//! addition to sharing the store lock. Writes of keys in different stripes
+//! do not contend; a read-modify-write only holds off the writers of the keys
+//! of its stripe. Operations on the whole store or on batches of keys still
+//! take the store lock exclusively, which waits for every stripe holder.
+//!
+//! The stripes are locks only, the data is not partitioned and stays in one
+//! rust_kvs instance per store: rust_kvs hosts at most 10 instances, most of
+//! them taken by the namespaces, and snapshots, exports and the recovery at
+//! startup rely on the single storage file of the store.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Stripes per store, enough that unrelated keys rarely share one
+pub const STRIPES: usize = 64;
+
+/// Stripe of `key` among `stripes`, the same in every run of the service
+pub fn stripe(key: &str, stripes: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % stripes.max(1) as u64) as usize
+}
+
+/// Locks of the keys of one store, one per stripe
+#[derive(Debug)]
+pub struct KeyLocks {
+    stripes: Vec<Mutex<()>>,
+}
+
+impl Default for KeyLocks {
+    fn default() -> Self {
+        Self::new(STRIPES)
+    }
+}
+
+impl KeyLocks {
+    pub fn new(stripes: usize) -> Self {
+        Self {
+            stripes: (0..stripes.max(1)).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    /// Stripe `key` hashes to, see [`stripe`]
+    pub fn stripe(&self, key: &str) -> usize {
+        stripe(key, self.stripes.len())
+    }
+
+    /// Hold off the other writers of the keys in the stripe of `key`
+    pub async fn lock(&self, key: &str) -> MutexGuard<'_, ()> {
+        self.stripes[self.stripe(key)].lock().await
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A key in another stripe than `key`
+    fn other_stripe(locks: &KeyLocks, key: &str) -> String {
+        (0..)
+            .map(|i| format!("vehicle/other/{}", i))
+            .find(|other| locks.stripe(other) != locks.stripe(key))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_keys_hash_to_stable_stripes() {
+        let locks = KeyLocks::default();
+        let stripe = locks.stripe("vehicle/mode");
+        assert!(stripe < STRIPES);
+        assert_eq!(KeyLocks::default().stripe("vehicle/mode"), stripe);
+        assert_eq!(KeyLocks::new(0).stripe("vehicle/mode"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_only_keys_of_the_same_stripe_wait() {
+        let locks = KeyLocks::default();
+        let _held = locks.lock("vehicle/mode").await;
+
+        let other = other_stripe(&locks, "vehicle/mode");
+        let unrelated = tokio::time::timeout(Duration::from_millis(100), locks.lock(&other));
+        assert!(unrelated.await.is_ok());
+
+        let same = tokio::time::timeout(Duration::from_millis(10), locks.lock("vehicle/mode"));
+        assert!(same.await.is_err());
+    }
+}
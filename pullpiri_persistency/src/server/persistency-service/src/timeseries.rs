@@ -0,0 +1,618 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Write-through of numeric values to a time-series sink
+//!
+//! Keys such as vehicle speeds or collision risks are overwritten many times
+//! a second, so the store only ever holds their latest value. For the
+//! configured prefixes the exporter forwards every write of a numeric value
+//! as a point in the InfluxDB line protocol:
+//!
+//! ```text
+//! persistency,prefix=vehicle/,key=vehicle/speed value=42.5 1700000000000
+//! ```
+//!
+//! Integers are written as integer fields (`42i`, or `42u` if unsigned) and
+//! floats as float fields; other values and removals are not forwarded. The
+//! exporter watches the prefixes through the [`WatchHub`] like any other
+//! watcher, so a point is stamped when the exporter receives the change,
+//! and a burst larger than the watch channel loses its oldest changes.
+//!
+//! Points are written in batches of `batch_size` or after
+//! `flush_interval_ms`. While the sink is unavailable up to
+//! `max_buffered_points` points are kept and written once it is back, older
+//! points are dropped. Written, dropped and failed points are counted on the
+//! metrics endpoint.
+
+use crate::clock::SharedClock;
+use crate::config::{TimeSeriesConfig, TimeSeriesSinkKind};
+use crate::watch::{WatchEventKind, WatchHub, WatchNotification, WatchTarget};
+use crate::PersistencyServiceImpl;
+use common::error::Error;
+use common::persistency_proto::kvs_value::Value;
+use common::persistency_proto::KvsValue;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+/// Field value of a point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldValue {
+    Integer(i64),
+    Unsigned(u64),
+    Float(f64),
+}
+
+/// Field value of a stored value, `None` if it is not numeric
+///
+/// Non-finite floats cannot be represented in the line protocol.
+pub fn field_value(value: &KvsValue) -> Option<FieldValue> {
+    match value.value.as_ref()? {
+        Value::I32Value(v) => Some(FieldValue::Integer(i64::from(*v))),
+        Value::I64Value(v) => Some(FieldValue::Integer(*v)),
+        Value::U32Value(v) => Some(FieldValue::Unsigned(u64::from(*v))),
+        Value::U64Value(v) => Some(FieldValue::Unsigned(*v)),
+        Value::F64Value(v) if v.is_finite() => Some(FieldValue::Float(*v)),
+        _ => None,
+    }
+}
+
+/// A write of a numeric value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    /// Configured prefix the key was matched by
+    pub prefix: String,
+    pub key: String,
+    pub value: FieldValue,
+    /// Unix time the write was received in milliseconds
+    pub timestamp_ms: u64,
+}
+
+impl Point {
+    /// Line of the point in the InfluxDB line protocol
+    pub fn to_line(&self, measurement: &str) -> String {
+        let mut line = String::new();
+        let _ = write!(
+            line,
+            "{},prefix={},key={} value=",
+            escape(measurement, false),
+            escape(&self.prefix, true),
+            escape(&self.key, true)
+        );
+        let _ = match self.value {
+            FieldValue::Integer(v) => write!(line, "{}i", v),
+            FieldValue::Unsigned(v) => write!(line, "{}u", v),
+            FieldValue::Float(v) => write!(line, "{:?}", v),
+        };
+        let _ = write!(line, " {}", self.timestamp_ms);
+        line
+    }
+}
+
+/// Escape a measurement or, with `tag`, a tag value of the line protocol
+fn escape(value: &str, tag: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ',' | ' ' => escaped.push('\\'),
+            '=' if tag => escaped.push('\\'),
+            // A line break would end the line
+            '\n' | '\r' => {
+                escaped.push_str("\\ ");
+                continue;
+            }
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Prefixes to watch, without prefixes covered by a shorter one
+///
+/// A key matching overlapping prefixes is forwarded once, tagged with the
+/// shortest prefix.
+pub fn watched_prefixes(prefixes: &[String]) -> Vec<String> {
+    let mut sorted = prefixes.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    let mut watched: Vec<String> = Vec::new();
+    for prefix in sorted {
+        if !watched
+            .iter()
+            .any(|shorter| prefix.starts_with(shorter.as_str()))
+        {
+            watched.push(prefix);
+        }
+    }
+    watched
+}
+
+/// Lines waiting for the sink, dropping the oldest beyond the capacity
+#[derive(Debug)]
+struct PointBuffer {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl PointBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Add a line, returning the number of dropped lines
+    fn push(&mut self, line: String) -> u64 {
+        let mut dropped = 0;
+        while self.lines.len() >= self.capacity {
+            self.lines.pop_front();
+            dropped += 1;
+        }
+        self.lines.push_back(line);
+        dropped
+    }
+
+    /// Body of the next batch and its number of lines
+    fn batch(&self, batch_size: usize) -> (String, usize) {
+        let count = self.lines.len().min(batch_size.max(1));
+        let mut body = String::new();
+        for line in self.lines.iter().take(count) {
+            body.push_str(line);
+            body.push('\n');
+        }
+        (body, count)
+    }
+
+    fn remove(&mut self, count: usize) {
+        self.lines.drain(..count.min(self.lines.len()));
+    }
+
+    fn len(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+/// Counters of the exporter, shared with the metrics endpoint
+#[derive(Debug)]
+pub struct TimeSeriesStatus {
+    clock: SharedClock,
+    written: AtomicU64,
+    dropped: AtomicU64,
+    failures: AtomicU64,
+    buffered: AtomicU64,
+}
+
+impl TimeSeriesStatus {
+    pub fn new(clock: SharedClock) -> Self {
+        Self {
+            clock,
+            written: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            buffered: AtomicU64::new(0),
+        }
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    fn record_written(&self, points: usize) {
+        self.written.fetch_add(points as u64, Ordering::Relaxed);
+    }
+
+    fn record_dropped(&self, changes: u64) {
+        self.dropped.fetch_add(changes, Ordering::Relaxed);
+    }
+
+    fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn set_buffered(&self, points: usize) {
+        self.buffered.store(points as u64, Ordering::Relaxed);
+    }
+
+    /// Points written to the sink
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    /// Changes lost because the exporter fell behind or the sink was unavailable
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Exporter metrics in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let metrics = [
+            (
+                "persistency_timeseries_points_total",
+                "counter",
+                "Points written to the time-series sink",
+                self.written(),
+            ),
+            (
+                "persistency_timeseries_dropped_total",
+                "counter",
+                "Changes not forwarded because the exporter fell behind or the sink was unavailable",
+                self.dropped(),
+            ),
+            (
+                "persistency_timeseries_write_failures_total",
+                "counter",
+                "Failed writes to the time-series sink",
+                self.failures.load(Ordering::Relaxed),
+            ),
+            (
+                "persistency_timeseries_buffered_points",
+                "gauge",
+                "Points waiting for the time-series sink",
+                self.buffered.load(Ordering::Relaxed),
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(
+                out,
+                "# HELP {} {}\n# TYPE {} {}\n{} {}",
+                name, help, name, kind, name, value
+            );
+        }
+        out
+    }
+}
+
+/// Destination of the batches
+enum Sink {
+    Http {
+        client: reqwest::Client,
+        endpoint: String,
+        auth: Option<(String, String)>,
+    },
+    File {
+        path: String,
+    },
+}
+
+impl Sink {
+    fn new(config: &TimeSeriesConfig) -> Result<Self, String> {
+        match config.sink {
+            TimeSeriesSinkKind::Http => {
+                if config.endpoint.is_empty() {
+                    return Err("No endpoint configured for the HTTP sink".to_string());
+                }
+                let client = reqwest::Client::builder()
+                    .timeout(Duration::from_secs(config.timeout_secs))
+                    .build()
+                    .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+                let auth = std::env::var(&config.auth_value_env)
+                    .ok()
+                    .map(|value| (config.auth_header.clone(), value));
+                Ok(Sink::Http {
+                    client,
+                    endpoint: config.endpoint.clone(),
+                    auth,
+                })
+            }
+            TimeSeriesSinkKind::File => Ok(Sink::File {
+                path: config.file_path.clone(),
+            }),
+        }
+    }
+
+    fn describe(&self) -> &str {
+        match self {
+            Sink::Http { endpoint, .. } => endpoint,
+            Sink::File { path } => path,
+        }
+    }
+
+    async fn write(&self, body: String) -> Result<(), String> {
+        match self {
+            Sink::Http {
+                client,
+                endpoint,
+                auth,
+            } => {
+                let mut request = client
+                    .post(endpoint)
+                    .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                    .body(body);
+                if let Some((header, value)) = auth {
+                    request = request.header(header.as_str(), value.as_str());
+                }
+                let response = request
+                    .send()
+                    .await
+                    .map_err(|e| format!("Request failed: {}", e))?;
+                if response.status().is_success() {
+                    Ok(())
+                } else {
+                    Err(format!("Endpoint answered {}", response.status()))
+                }
+            }
+            Sink::File { path } => {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+                let appended = async {
+                    file.write_all(body.as_bytes()).await?;
+                    // tokio completes file writes in the background until flushed
+                    file.flush().await
+                };
+                appended
+                    .await
+                    .map_err(|e| format!("Failed to append to {}: {}", path, e))
+            }
+        }
+    }
+}
+
+/// Forward the numeric writes of the configured prefixes forever
+///
+/// ### Parameters
+/// * `config: TimeSeriesConfig` - exporter configuration
+/// * `service: Arc<PersistencyServiceImpl>` - service whose writes are forwarded
+pub async fn run(
+    config: TimeSeriesConfig,
+    service: Arc<PersistencyServiceImpl>,
+) -> common::Result<()> {
+    export(config, service.watch_hub(), service.timeseries_status())
+        .await
+        .map_err(Error::config)
+}
+
+/// Forward the numeric writes published on `hub` until the hub is gone
+async fn export(
+    config: TimeSeriesConfig,
+    hub: Arc<WatchHub>,
+    status: Arc<TimeSeriesStatus>,
+) -> Result<(), String> {
+    let prefixes = watched_prefixes(&config.prefixes);
+    if prefixes.is_empty() {
+        return Err("No prefixes configured".to_string());
+    }
+    let sink = Sink::new(&config)?;
+    info!(
+        "Forwarding numeric values under {:?} to {}",
+        prefixes,
+        sink.describe()
+    );
+
+    let (sender, mut receiver) = mpsc::channel::<Point>(config.batch_size.max(1));
+    for prefix in prefixes {
+        let mut subscription = hub.subscribe(WatchTarget::Prefix(prefix.clone()));
+        let sender = sender.clone();
+        let status = status.clone();
+        tokio::spawn(async move {
+            while let Some(notification) = subscription.recv().await {
+                let event = match notification {
+                    WatchNotification::Event(event) => event,
+                    WatchNotification::Lagged { dropped } => {
+                        status.record_dropped(dropped);
+                        continue;
+                    }
+                };
+                if event.kind != WatchEventKind::Put {
+                    continue;
+                }
+                let Some(value) = event.value.as_ref().and_then(field_value) else {
+                    continue;
+                };
+                let point = Point {
+                    prefix: prefix.clone(),
+                    key: event.key.clone(),
+                    value,
+                    timestamp_ms: status.now_ms(),
+                };
+                if sender.send(point).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    // The exporter ends once every subscription is gone
+    drop(hub);
+    drop(sender);
+
+    let mut buffer = PointBuffer::new(config.max_buffered_points);
+    let mut failing = false;
+    let mut interval =
+        tokio::time::interval(Duration::from_millis(config.flush_interval_ms.max(1)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let closed = tokio::select! {
+            point = receiver.recv() => match point {
+                Some(point) => {
+                    status.record_dropped(buffer.push(point.to_line(&config.measurement)));
+                    status.set_buffered(buffer.len());
+                    if buffer.len() < config.batch_size || failing {
+                        continue;
+                    }
+                    false
+                }
+                None => true,
+            },
+            _ = interval.tick() => false,
+        };
+
+        while buffer.len() > 0 {
+            let (body, count) = buffer.batch(config.batch_size);
+            match sink.write(body).await {
+                Ok(()) => {
+                    if failing {
+                        info!("Time-series sink {} is available again", sink.describe());
+                        failing = false;
+                    }
+                    buffer.remove(count);
+                    status.record_written(count);
+                }
+                Err(e) => {
+                    status.record_failure();
+                    if failing {
+                        debug!("Time-series write still failing: {}", e);
+                    } else {
+                        warn!(
+                            "Time-series write to {} failed, buffering points: {}",
+                            sink.describe(),
+                            e
+                        );
+                        failing = true;
+                    }
+                    break;
+                }
+            }
+        }
+        status.set_buffered(buffer.len());
+
+        if closed {
+            return Ok(());
+        }
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+
+    fn value(value: Value) -> KvsValue {
+        KvsValue { value: Some(value) }
+    }
+
+    fn point(key: &str, value: FieldValue) -> Point {
+        Point {
+            prefix: "vehicle/".to_string(),
+            key: key.to_string(),
+            value,
+            timestamp_ms: 1_700_000_000_000,
+        }
+    }
+
+    #[test]
+    fn test_field_value() {
+        assert_eq!(
+            field_value(&value(Value::I32Value(-3))),
+            Some(FieldValue::Integer(-3))
+        );
+        assert_eq!(
+            field_value(&value(Value::U32Value(7))),
+            Some(FieldValue::Unsigned(7))
+        );
+        assert_eq!(
+            field_value(&value(Value::F64Value(42.5))),
+            Some(FieldValue::Float(42.5))
+        );
+        assert_eq!(field_value(&value(Value::F64Value(f64::NAN))), None);
+        assert_eq!(field_value(&value(Value::BooleanValue(true))), None);
+        assert_eq!(
+            field_value(&value(Value::StringValue("42".to_string()))),
+            None
+        );
+        assert_eq!(field_value(&KvsValue { value: None }), None);
+    }
+
+    #[test]
+    fn test_line_protocol() {
+        assert_eq!(
+            point("vehicle/speed", FieldValue::Float(42.0)).to_line("persistency"),
+            "persistency,prefix=vehicle/,key=vehicle/speed value=42.0 1700000000000"
+        );
+        assert_eq!(
+            point("vehicle/count", FieldValue::Unsigned(3)).to_line("persistency"),
+            "persistency,prefix=vehicle/,key=vehicle/count value=3u 1700000000000"
+        );
+        assert_eq!(
+            point("vehicle/a b,c=d", FieldValue::Integer(-1)).to_line("vehicle data"),
+            "vehicle\\ data,prefix=vehicle/,key=vehicle/a\\ b\\,c\\=d value=-1i 1700000000000"
+        );
+    }
+
+    #[test]
+    fn test_watched_prefixes() {
+        let prefixes = ["vehicle/speed", "vehicle/", "risk/", "vehicle/"].map(String::from);
+        assert_eq!(watched_prefixes(&prefixes), vec!["risk/", "vehicle/"]);
+        assert!(watched_prefixes(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_buffer_drops_oldest() {
+        let mut buffer = PointBuffer::new(2);
+        assert_eq!(buffer.push("a".to_string()), 0);
+        assert_eq!(buffer.push("b".to_string()), 0);
+        assert_eq!(buffer.push("c".to_string()), 1);
+        assert_eq!(buffer.batch(10), ("b\nc\n".to_string(), 2));
+        assert_eq!(buffer.batch(1), ("b\n".to_string(), 1));
+        buffer.remove(1);
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_file_sink_receives_numeric_writes() {
+        let path =
+            std::env::temp_dir().join(format!("persistency-timeseries-{}.lp", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = TimeSeriesConfig {
+            enabled: true,
+            prefixes: vec!["vehicle/".to_string()],
+            sink: TimeSeriesSinkKind::File,
+            file_path: path.display().to_string(),
+            flush_interval_ms: 10,
+            ..Default::default()
+        };
+        let clock = Arc::new(TestClock::new(1_700_000_000_000));
+        let hub = Arc::new(WatchHub::new(16, clock.clone()));
+        let status = Arc::new(TimeSeriesStatus::new(clock));
+        let exporter = tokio::spawn(export(config, hub.clone(), status.clone()));
+        while hub.channel_count() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let speed = Some(value(Value::F64Value(42.5)));
+        hub.publish(WatchEventKind::Put, "vehicle/speed", speed.clone(), None);
+        hub.publish(
+            WatchEventKind::Put,
+            "vehicle/mode",
+            Some(value(Value::StringValue("manual".to_string()))),
+            None,
+        );
+        hub.publish(WatchEventKind::Delete, "vehicle/speed", None, None);
+        hub.publish(WatchEventKind::Put, "settings/speed", speed, None);
+        hub.publish(
+            WatchEventKind::Put,
+            "vehicle/risk",
+            Some(value(Value::I32Value(80))),
+            None,
+        );
+        drop(hub);
+        exporter.await.unwrap().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "persistency,prefix=vehicle/,key=vehicle/speed value=42.5 1700000000000\n\
+             persistency,prefix=vehicle/,key=vehicle/risk value=80i 1700000000000\n"
+        );
+        assert_eq!(status.written(), 2);
+        assert_eq!(status.dropped(), 0);
+        assert!(status
+            .render_metrics()
+            .contains("persistency_timeseries_points_total 2\n"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Request deadlines and the slow request log
+//!
+//! Clients bound their calls with a gRPC deadline, sent as `grpc-timeout`
+//! metadata. tonic gives up on a call once its deadline passed, but a handler
+//! reading a large prefix only notices when it next yields, holding the store
+//! lock all the while, and a scan streams on after the call was given up. The
+//! [`TimingLayer`] makes the deadline of every request available through
+//! [`Deadline::current`], and prefix reads check it between keys and end with
+//! `DEADLINE_EXCEEDED`.
+//!
+//! Requests taking longer than `slow_requests.threshold_ms` are logged at warn
+//! level with the key or prefix the handler noted with [`note_target`], so
+//! that operators can find pathological access patterns. The log runs inside
+//! the span of the caller, see [`crate::caller`]. Scans stream their batches
+//! after the handler returned and log themselves once done.
+
+use crate::config::SlowRequestConfig;
+use http::{Request, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tonic::Status;
+use tower::{Layer, Service};
+use tracing::warn;
+
+/// Metadata carrying the deadline of a gRPC call
+const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+tokio::task_local! {
+    static REQUEST: Arc<RequestTiming>;
+}
+
+/// Point in time a request has to be answered by
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    /// Deadline `timeout` from now
+    pub fn after(timeout: Duration) -> Self {
+        Self(Instant::now().checked_add(timeout))
+    }
+
+    /// Deadline of the request being handled, none outside of a request or if
+    /// the client did not set one
+    pub fn current() -> Self {
+        current().map(|timing| timing.deadline).unwrap_or_default()
+    }
+
+    /// Whether the deadline passed
+    pub fn expired(&self) -> bool {
+        self.0.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Fail with `DEADLINE_EXCEEDED` once the deadline passed
+    ///
+    /// ### Parameters
+    /// * `operation: &str` - what was aborted, for the status message
+    pub fn check(&self, operation: &str) -> Result<(), Status> {
+        if self.expired() {
+            return Err(Status::deadline_exceeded(format!(
+                "Deadline exceeded during {}",
+                operation
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Timing of one request
+#[derive(Debug)]
+pub struct RequestTiming {
+    method: String,
+    started: Instant,
+    deadline: Deadline,
+    slow_after: Option<Duration>,
+    target: Mutex<Option<String>>,
+}
+
+impl RequestTiming {
+    fn new(method: String, deadline: Deadline, slow_after: Option<Duration>) -> Self {
+        Self {
+            method,
+            started: Instant::now(),
+            deadline,
+            slow_after,
+            target: Mutex::new(None),
+        }
+    }
+
+    /// Deadline of the request
+    pub fn deadline(&self) -> Deadline {
+        self.deadline
+    }
+
+    /// Log the request if it took longer than the slow request threshold
+    pub fn log_if_slow(&self) {
+        let Some(slow_after) = self.slow_after else {
+            return;
+        };
+        let elapsed = self.started.elapsed();
+        if elapsed < slow_after {
+            return;
+        }
+        let target = self
+            .target
+            .lock()
+            .map(|target| target.clone())
+            .unwrap_or_default();
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        match target {
+            Some(target) => {
+                warn!(method = %self.method, target = %target, elapsed_ms, "Slow persistency request")
+            }
+            None => warn!(method = %self.method, elapsed_ms, "Slow persistency request"),
+        }
+    }
+}
+
+/// Timing of the request being handled, `None` outside of a request
+pub fn current() -> Option<Arc<RequestTiming>> {
+    REQUEST.try_with(|timing| timing.clone()).ok()
+}
+
+/// Note the key or prefix the request being handled accesses, for the slow
+/// request log
+pub fn note_target(target: &str) {
+    let _ = REQUEST.try_with(|timing| {
+        if let Ok(mut noted) = timing.target.lock() {
+            *noted = Some(target.to_string());
+        }
+    });
+}
+
+/// Parse a `grpc-timeout` value: at most 8 digits followed by the unit
+pub fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    if !amount.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = amount.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 3600)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// Tower layer timing every request
+#[derive(Debug, Clone)]
+pub struct TimingLayer {
+    slow_after: Option<Duration>,
+}
+
+impl TimingLayer {
+    pub fn new(config: &SlowRequestConfig) -> Self {
+        let slow_after =
+            (config.threshold_ms > 0).then(|| Duration::from_millis(config.threshold_ms));
+        Self { slow_after }
+    }
+}
+
+impl<S> Layer<S> for TimingLayer {
+    type Service = TimingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimingService {
+            inner,
+            slow_after: self.slow_after,
+        }
+    }
+}
+
+/// Service produced by [`TimingLayer`]
+#[derive(Debug, Clone)]
+pub struct TimingService<S> {
+    inner: S,
+    slow_after: Option<Duration>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for TimingService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let method = req
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let deadline = req
+            .headers()
+            .get(GRPC_TIMEOUT_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_grpc_timeout)
+            .map(Deadline::after)
+            .unwrap_or_default();
+        let timing = Arc::new(RequestTiming::new(method, deadline, self.slow_after));
+        let future = REQUEST.scope(timing.clone(), self.inner.call(req));
+
+        Box::pin(async move {
+            let result = future.await;
+            timing.log_if_slow();
+            result
+        })
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Empty;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_parse_grpc_timeout() {
+        assert_eq!(parse_grpc_timeout("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(parse_grpc_timeout("2S"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_grpc_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(
+            parse_grpc_timeout("99999999n"),
+            Some(Duration::from_nanos(99_999_999))
+        );
+        assert_eq!(parse_grpc_timeout("100000000m"), None);
+        assert_eq!(parse_grpc_timeout("m"), None);
+        assert_eq!(parse_grpc_timeout("-1m"), None);
+        assert_eq!(parse_grpc_timeout("10x"), None);
+    }
+
+    #[test]
+    fn test_deadline_expiry() {
+        assert!(!Deadline::default().expired());
+        assert!(Deadline::default().check("GetAllWithPrefix").is_ok());
+        assert!(!Deadline::after(Duration::from_secs(60)).expired());
+
+        let passed = Deadline::after(Duration::ZERO);
+        assert!(passed.expired());
+        let status = passed.check("GetAllWithPrefix").unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    async fn test_layer_scopes_deadline() {
+        let inner = tower::service_fn(|_req: Request<Empty<bytes::Bytes>>| async {
+            note_target("Scenario/");
+            Ok::<_, Infallible>(Response::new(Deadline::current()))
+        });
+        let config = SlowRequestConfig { threshold_ms: 0 };
+        let mut service = TimingLayer::new(&config).layer(inner);
+
+        let request = Request::builder()
+            .uri("/persistency.PersistencyService/GetAllWithPrefix")
+            .header(GRPC_TIMEOUT_HEADER, "60S")
+            .body(Empty::new())
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+        assert!(response.body().0.is_some());
+        assert!(!response.body().expired());
+
+        let response = service.call(Request::new(Empty::new())).await.unwrap();
+        assert_eq!(*response.body(), Deadline::default());
+        assert!(current().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_prefix_read_aborted_after_deadline() {
+        use crate::PersistencyServiceImpl;
+        use common::persistency_proto::persistency_service_server::PersistencyService;
+        use common::persistency_proto::GetAllWithPrefixRequest;
+
+        let service = PersistencyServiceImpl::new_in_memory().unwrap();
+        let request = || {
+            tonic::Request::new(GetAllWithPrefixRequest {
+                prefix: "Scenario/".to_string(),
+                namespace: String::new(),
+            })
+        };
+        let expired = RequestTiming::new(
+            "GetAllWithPrefix".to_string(),
+            Deadline::after(Duration::ZERO),
+            None,
+        );
+        let status = REQUEST
+            .scope(Arc::new(expired), service.get_all_with_prefix(request()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+
+        // Without a deadline the prefix is read in full
+        assert!(service.get_all_with_prefix(request()).await.is_ok());
+    }
+}
@@ -0,0 +1,182 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Restorable removal of keys
+//!
+//! With `trash.enabled`, a `RemoveKey` with `trash` set moves the key into
+//! the trash namespace instead of dropping it, so that e.g. an artifact
+//! removed from the apiserver by mistake can be brought back. The key is
+//! kept there under its tracked key, see [`crate::namespace::tracked_key`],
+//! together with the time of the removal, and expires after
+//! `trash.retention_secs` like a key written with a time to live, see
+//! [`crate::expiry`]. Removing a key again replaces its earlier trashed value.
+//!
+//! `ListDeleted` lists the trashed keys removed from a namespace,
+//! `RestoreKey` writes a trashed key back unless it was written again
+//! meanwhile.
+
+use crate::config::{NamespacesConfig, TrashConfig};
+use common::persistency_proto::DeletedKey as DeletedKeyProto;
+use rust_kvs::kvs_value::{KvsMap, KvsValue};
+use std::time::Duration;
+
+/// Field of a trash entry holding the removed value
+const VALUE_FIELD: &str = "value";
+/// Field of a trash entry holding the time of the removal
+const DELETED_AT_FIELD: &str = "deleted_at_ms";
+
+/// Removed value of a key as kept in the trash
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashedValue {
+    pub value: KvsValue,
+    /// Unix time of the removal in milliseconds
+    pub deleted_at_ms: u64,
+}
+
+impl TrashedValue {
+    /// Value stored in the trash namespace
+    pub fn to_entry(&self) -> KvsValue {
+        KvsValue::Object(KvsMap::from([
+            (VALUE_FIELD.to_string(), self.value.clone()),
+            (
+                DELETED_AT_FIELD.to_string(),
+                KvsValue::U64(self.deleted_at_ms),
+            ),
+        ]))
+    }
+
+    /// Removed value of a trash `entry`, `None` if it is no trash entry
+    pub fn from_entry(entry: KvsValue) -> Option<Self> {
+        let KvsValue::Object(mut fields) = entry else {
+            return None;
+        };
+        let deleted_at_ms = match fields.get(DELETED_AT_FIELD)? {
+            KvsValue::U64(v) => *v,
+            KvsValue::I64(v) => u64::try_from(*v).ok()?,
+            KvsValue::U32(v) => u64::from(*v),
+            KvsValue::I32(v) => u64::try_from(*v).ok()?,
+            _ => return None,
+        };
+        Some(Self {
+            value: fields.remove(VALUE_FIELD)?,
+            deleted_at_ms,
+        })
+    }
+}
+
+/// Trashed key as listed by `ListDeleted`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletedKey {
+    /// Namespace the key was removed from, empty for the default store
+    pub namespace: String,
+    pub key: String,
+    /// Unix time of the removal in milliseconds
+    pub deleted_at_ms: u64,
+    /// Unix time the key is removed for good in milliseconds
+    pub expires_at_ms: u64,
+}
+
+impl DeletedKey {
+    pub fn to_proto(&self) -> DeletedKeyProto {
+        DeletedKeyProto {
+            key: self.key.clone(),
+            namespace: self.namespace.clone(),
+            deleted_at_ms: self.deleted_at_ms,
+            expires_at_ms: self.expires_at_ms,
+        }
+    }
+}
+
+/// Where removed keys go and how long they stay there
+#[derive(Debug)]
+pub struct Trash {
+    config: TrashConfig,
+}
+
+impl Trash {
+    pub fn new(config: &TrashConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Namespace holding the removed keys
+    pub fn namespace(&self) -> &str {
+        &self.config.namespace
+    }
+
+    /// Time a removed key stays restorable, `None` until it is restored
+    pub fn retention(&self) -> Option<Duration> {
+        (self.config.retention_secs > 0).then(|| Duration::from_secs(self.config.retention_secs))
+    }
+}
+
+/// Check that an enabled trash is one of the configured namespaces
+pub fn validate(config: &TrashConfig, namespaces: &NamespacesConfig) -> Result<(), String> {
+    if config.enabled && !namespaces.instances.contains_key(&config.namespace) {
+        return Err(format!(
+            "Trash namespace '{}' is not a configured namespace",
+            config.namespace
+        ));
+    }
+    Ok(())
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trash_entry_round_trip() {
+        let trashed = TrashedValue {
+            value: KvsValue::String("kind: Scenario".to_string()),
+            deleted_at_ms: 1_700_000_000_000,
+        };
+        assert_eq!(TrashedValue::from_entry(trashed.to_entry()), Some(trashed));
+
+        // Times read back from the storage file as signed integers
+        let entry = KvsValue::Object(KvsMap::from([
+            (VALUE_FIELD.to_string(), KvsValue::Boolean(true)),
+            (DELETED_AT_FIELD.to_string(), KvsValue::I64(5)),
+        ]));
+        assert_eq!(TrashedValue::from_entry(entry).unwrap().deleted_at_ms, 5);
+    }
+
+    #[test]
+    fn test_trash_has_to_be_a_namespace() {
+        let mut namespaces = NamespacesConfig::default();
+        let mut config = TrashConfig::default();
+        assert!(validate(&config, &namespaces).is_ok());
+        config.enabled = true;
+        assert!(validate(&config, &namespaces).is_err());
+        namespaces.instances.insert("trash".to_string(), 4);
+        assert!(validate(&config, &namespaces).is_ok());
+
+        assert_eq!(
+            Trash::new(&config).retention(),
+            Some(Duration::from_secs(7 * 24 * 3600))
+        );
+        config.retention_secs = 0;
+        assert_eq!(Trash::new(&config).retention(), None);
+    }
+
+    #[test]
+    fn test_other_values_are_no_trash_entries() {
+        assert_eq!(TrashedValue::from_entry(KvsValue::U64(1)), None);
+        let without_time =
+            KvsValue::Object(KvsMap::from([(VALUE_FIELD.to_string(), KvsValue::Null)]));
+        assert_eq!(TrashedValue::from_entry(without_time), None);
+        let negative_time = KvsValue::Object(KvsMap::from([
+            (VALUE_FIELD.to_string(), KvsValue::Null),
+            (DELETED_AT_FIELD.to_string(), KvsValue::I64(-1)),
+        ]));
+        assert_eq!(TrashedValue::from_entry(negative_time), None);
+    }
+}
@@ -0,0 +1,392 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Scheduled export to a cloud backup endpoint
+//!
+//! The uploader periodically exports the configured prefixes and POSTs the
+//! export as JSON to the fleet backup endpoint. Failed uploads are retried
+//! with exponential backoff; once the retries are used up the uploader waits
+//! for the next interval. The outcome of the last upload is reported by the
+//! `GetServiceInfo` RPC.
+//!
+//! Between full uploads the uploader can send differential exports against the
+//! last successful full upload, see [`crate::history`]. Each of them replaces
+//! the previous one, so the endpoint only needs the last full upload and the
+//! last differential upload to restore. When the history no longer covers the
+//! base, e.g. after a restart of the service, a full export is sent instead.
+
+use crate::clock::SharedClock;
+use crate::config::UploadConfig;
+use crate::export::Export;
+use crate::history::Baseline;
+use crate::PersistencyServiceImpl;
+use common::error::Error;
+use common::persistency_proto::UploadStatus as UploadStatusProto;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+/// Outcome of past uploads, shared with `GetServiceInfo`
+#[derive(Debug)]
+pub struct UploadStatus {
+    enabled: bool,
+    endpoint: String,
+    clock: SharedClock,
+    state: Mutex<UploadState>,
+}
+
+#[derive(Debug, Default)]
+struct UploadState {
+    last_success_ms: u64,
+    last_attempt_ms: u64,
+    last_error: String,
+    consecutive_failures: u32,
+    uploads: u64,
+    differential_uploads: u64,
+}
+
+impl UploadStatus {
+    pub fn new(config: &UploadConfig, clock: SharedClock) -> Self {
+        Self {
+            enabled: config.enabled,
+            endpoint: config.endpoint.clone(),
+            clock,
+            state: Mutex::new(UploadState::default()),
+        }
+    }
+
+    fn record_attempt(&self) {
+        self.state.lock().unwrap().last_attempt_ms = self.clock.now_ms();
+    }
+
+    fn record_success(&self, differential: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.last_success_ms = self.clock.now_ms();
+        state.last_error.clear();
+        state.consecutive_failures = 0;
+        state.uploads += 1;
+        if differential {
+            state.differential_uploads += 1;
+        }
+    }
+
+    fn record_failure(&self, error: String) {
+        let mut state = self.state.lock().unwrap();
+        state.last_error = error;
+        state.consecutive_failures += 1;
+    }
+
+    /// Status as reported by `GetServiceInfo`
+    pub fn to_proto(&self) -> UploadStatusProto {
+        let state = self.state.lock().unwrap();
+        UploadStatusProto {
+            enabled: self.enabled,
+            endpoint: self.endpoint.clone(),
+            last_success_ms: state.last_success_ms,
+            last_attempt_ms: state.last_attempt_ms,
+            last_error: state.last_error.clone(),
+            consecutive_failures: state.consecutive_failures,
+            uploads: state.uploads,
+            differential_uploads: state.differential_uploads,
+        }
+    }
+}
+
+/// Choice between full and differential uploads
+#[derive(Debug)]
+struct UploadSchedule {
+    full_every: u32,
+    /// Point of the history of the last successful full upload
+    last_full: Option<Baseline>,
+    /// Successful differential uploads since then
+    differentials: u32,
+}
+
+impl UploadSchedule {
+    fn new(full_every: u32) -> Self {
+        Self {
+            full_every,
+            last_full: None,
+            differentials: 0,
+        }
+    }
+
+    /// Base of the next upload, `None` for a full upload
+    fn next_base(&self) -> Option<Baseline> {
+        if self.differentials + 1 >= self.full_every {
+            return None;
+        }
+        self.last_full
+    }
+
+    fn uploaded(&mut self, export: &Export) {
+        if export.is_differential() {
+            self.differentials += 1;
+        } else {
+            self.last_full = Some(export.baseline());
+            self.differentials = 0;
+        }
+    }
+}
+
+/// HTTP side of the uploader
+struct Uploader {
+    client: reqwest::Client,
+    config: UploadConfig,
+    /// Authentication header value read from the environment
+    auth_value: Option<String>,
+}
+
+impl Uploader {
+    fn new(config: UploadConfig) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        let auth_value = std::env::var(&config.auth_value_env).ok();
+        if auth_value.is_none() {
+            info!(
+                "{} is not set, uploading without authentication header",
+                config.auth_value_env
+            );
+        }
+        Ok(Self {
+            client,
+            config,
+            auth_value,
+        })
+    }
+
+    /// POST `body`, retrying failed attempts
+    async fn post_with_retry(&self, body: Vec<u8>) -> Result<(), String> {
+        let mut backoff = Duration::from_millis(self.config.retry_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match self.post(body.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.config.max_retries => {
+                    attempt += 1;
+                    debug!(
+                        "Upload failed ({}), retry {}/{} in {:?}",
+                        e, attempt, self.config.max_retries, backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn post(&self, body: Vec<u8>) -> Result<(), String> {
+        let mut request = self
+            .client
+            .post(&self.config.endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
+        if let Some(value) = &self.auth_value {
+            request = request.header(self.config.auth_header.as_str(), value.as_str());
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("Endpoint answered {}", response.status()))
+        }
+    }
+}
+
+/// Export and upload the configured prefixes forever
+///
+/// ### Parameters
+/// * `config: UploadConfig` - uploader configuration
+/// * `service: Arc<PersistencyServiceImpl>` - service whose store is exported
+pub async fn run(config: UploadConfig, service: Arc<PersistencyServiceImpl>) -> common::Result<()> {
+    let uploader = Uploader::new(config).map_err(Error::internal)?;
+    let status = service.upload_status();
+    info!(
+        "Uploading prefixes {:?} to {} every {}s",
+        uploader.config.prefixes, uploader.config.endpoint, uploader.config.interval_secs
+    );
+
+    let mut schedule = UploadSchedule::new(uploader.config.full_every);
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(uploader.config.interval_secs.max(1)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        status.record_attempt();
+        let base = schedule.next_base();
+        let export = match service.export_since(&uploader.config.prefixes, base).await {
+            Ok(export) => export,
+            Err(e) => {
+                warn!("Failed to export for upload: {:?}", e);
+                status.record_failure(format!("Export failed: {:?}", e));
+                continue;
+            }
+        };
+        let keys = export.entries.len();
+        if base.is_some() && !export.is_differential() {
+            info!(
+                "Changes since the last full upload are no longer known, uploading a full export"
+            );
+        }
+        let result = match serde_json::to_vec(&export) {
+            Ok(body) => uploader.post_with_retry(body).await,
+            Err(e) => Err(format!("Failed to encode export: {}", e)),
+        };
+        match result {
+            Ok(()) => {
+                if export.is_differential() {
+                    info!(
+                        "Uploaded differential export of {} changed and {} removed keys",
+                        keys,
+                        export.removed.len()
+                    );
+                } else {
+                    info!("Uploaded export of {} keys", keys);
+                }
+                status.record_success(export.is_differential());
+                schedule.uploaded(&export);
+            }
+            Err(e) => {
+                warn!("Upload to {} failed: {}", uploader.config.endpoint, e);
+                status.record_failure(e);
+            }
+        }
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Answer one request per entry of `statuses` and return the request heads
+    async fn mock_endpoint(statuses: Vec<u16>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/backup", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut heads = Vec::new();
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 64 * 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                heads.push(String::from_utf8_lossy(&buf[..n]).to_lowercase());
+                let response = format!(
+                    "HTTP/1.1 {} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            heads
+        });
+        (url, server)
+    }
+
+    fn uploader(endpoint: String, auth_value: Option<&str>) -> Uploader {
+        let config = UploadConfig {
+            enabled: true,
+            endpoint,
+            max_retries: 2,
+            retry_backoff_ms: 1,
+            timeout_secs: 5,
+            auth_header: "X-Fleet-Token".to_string(),
+            ..UploadConfig::default()
+        };
+        Uploader {
+            client: reqwest::Client::new(),
+            config,
+            auth_value: auth_value.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_post_retries_until_success() {
+        let (url, server) = mock_endpoint(vec![503, 500, 200]).await;
+        let uploader = uploader(url, Some("secret"));
+        assert!(uploader.post_with_retry(b"{}".to_vec()).await.is_ok());
+
+        let heads = server.await.unwrap();
+        assert_eq!(heads.len(), 3);
+        assert!(heads
+            .iter()
+            .all(|head| head.contains("x-fleet-token: secret")));
+        assert!(heads[0].starts_with("post /backup"));
+    }
+
+    #[tokio::test]
+    async fn test_post_gives_up_after_retries() {
+        let (url, server) = mock_endpoint(vec![500, 500, 500]).await;
+        let uploader = uploader(url, None);
+        let err = uploader.post_with_retry(b"{}".to_vec()).await.unwrap_err();
+        assert!(err.contains("500"));
+
+        let heads = server.await.unwrap();
+        assert!(heads.iter().all(|head| !head.contains("x-fleet-token")));
+    }
+
+    #[test]
+    fn test_status_tracks_last_success() {
+        let clock = Arc::new(TestClock::new(1_000));
+        let status = UploadStatus::new(&UploadConfig::default(), clock.clone());
+        status.record_attempt();
+        status.record_failure("Endpoint answered 500".to_string());
+        let proto = status.to_proto();
+        assert_eq!(proto.consecutive_failures, 1);
+        assert_eq!(proto.last_success_ms, 0);
+        assert_eq!(proto.last_attempt_ms, 1_000);
+
+        clock.advance(Duration::from_secs(5));
+        status.record_success(true);
+        let proto = status.to_proto();
+        assert_eq!(proto.consecutive_failures, 0);
+        assert!(proto.last_error.is_empty());
+        assert_eq!(proto.uploads, 1);
+        assert_eq!(proto.differential_uploads, 1);
+        assert_eq!(proto.last_success_ms, 6_000);
+    }
+
+    #[test]
+    fn test_schedule_alternates_full_and_differential() {
+        let export = |revision: u64, base_revision: Option<u64>| Export {
+            format_version: crate::export::EXPORT_FORMAT_VERSION,
+            exported_at_ms: 0,
+            prefixes: Vec::new(),
+            entries: Default::default(),
+            history_id: 1,
+            revision,
+            base_revision,
+            removed: Vec::new(),
+        };
+
+        let mut schedule = UploadSchedule::new(3);
+        assert_eq!(schedule.next_base(), None);
+        schedule.uploaded(&export(10, None));
+        let base = Baseline {
+            history_id: 1,
+            revision: 10,
+        };
+        assert_eq!(schedule.next_base(), Some(base));
+        schedule.uploaded(&export(12, Some(10)));
+        assert_eq!(schedule.next_base(), Some(base));
+        schedule.uploaded(&export(15, Some(10)));
+        assert_eq!(schedule.next_base(), None);
+
+        // A fallback to a full export starts a new cycle
+        schedule.uploaded(&export(20, None));
+        assert_eq!(schedule.next_base().unwrap().revision, 20);
+
+        let schedule = UploadSchedule::new(1);
+        assert_eq!(schedule.next_base(), None);
+    }
+}
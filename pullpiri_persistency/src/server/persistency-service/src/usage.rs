@@ -0,0 +1,460 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Usage reports per namespace
+//!
+//! The store is shared by the demo apps and the core pullpiri components.
+//! To attribute its cost between them, the reads, writes and removals of
+//! every namespace are counted with the bytes they moved and the keys they
+//! touched. Every period the counts are closed into a report, together with
+//! the stored footprint of each namespace and its busiest keys. The last
+//! reports are served by the `GetUsageReports` admin RPC and can be stored
+//! under `__reports/usage/<period start ms>`.
+//!
+//! The namespace of a key is the prefix the storage forecast accounts it to,
+//! see [`StorageForecaster::prefix_of`]. Keys of a configured
+//! [`crate::namespace`] are accounted as `<namespace>/key`, so their prefixes
+//! start with the namespace. Sizes are the approximation of the
+//! forecast. Reports are kept in memory and start over with the service.
+
+use crate::clock::SharedClock;
+use crate::config::UsageReportConfig;
+use crate::forecast::StorageForecaster;
+use crate::PersistencyServiceImpl;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+/// Key prefix of the stored reports
+pub const REPORT_KEY_PREFIX: &str = "__reports/usage/";
+
+/// Key a report of the period starting at `period_start_ms` is stored under
+///
+/// The start time is zero-padded, so that the keys sort by time.
+pub fn report_key(period_start_ms: u64) -> String {
+    format!("{}{:020}", REPORT_KEY_PREFIX, period_start_ms)
+}
+
+/// Usage of one key within a period
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct KeyUsage {
+    pub key: String,
+    pub operations: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+/// Usage of one namespace within a period
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NamespaceUsage {
+    pub namespace: String,
+    pub reads: u64,
+    pub writes: u64,
+    pub removes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Keys stored at the end of the period
+    pub keys: u64,
+    /// Bytes stored at the end of the period
+    pub stored_bytes: u64,
+    /// Busiest keys, most operations first
+    pub top_keys: Vec<KeyUsage>,
+}
+
+/// Usage of every namespace within a period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UsageReport {
+    pub period_start_ms: u64,
+    pub period_end_ms: u64,
+    /// `false` for the running period
+    pub complete: bool,
+    pub namespaces: Vec<NamespaceUsage>,
+}
+
+impl UsageReport {
+    /// Report as sent by `GetUsageReports`
+    pub fn to_proto(&self) -> common::persistency_proto::UsageReport {
+        use common::persistency_proto as proto;
+
+        proto::UsageReport {
+            period_start_ms: self.period_start_ms,
+            period_end_ms: self.period_end_ms,
+            complete: self.complete,
+            namespaces: self
+                .namespaces
+                .iter()
+                .map(|usage| proto::NamespaceUsage {
+                    namespace: usage.namespace.clone(),
+                    reads: usage.reads,
+                    writes: usage.writes,
+                    removes: usage.removes,
+                    bytes_read: usage.bytes_read,
+                    bytes_written: usage.bytes_written,
+                    keys: usage.keys,
+                    stored_bytes: usage.stored_bytes,
+                    top_keys: usage
+                        .top_keys
+                        .iter()
+                        .map(|key| proto::KeyUsage {
+                            key: key.key.clone(),
+                            operations: key.operations,
+                            bytes_read: key.bytes_read,
+                            bytes_written: key.bytes_written,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Counted operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Read,
+    Write,
+    Remove,
+}
+
+/// Counts of one namespace in the running period
+#[derive(Debug, Default)]
+struct Counters {
+    usage: NamespaceUsage,
+    keys: HashMap<String, KeyUsage>,
+}
+
+/// Running period
+#[derive(Debug)]
+struct Period {
+    start_ms: u64,
+    namespaces: BTreeMap<String, Counters>,
+}
+
+/// Operation counts per namespace and the reports of the completed periods
+#[derive(Debug)]
+pub struct UsageTracker {
+    config: UsageReportConfig,
+    forecast: Arc<StorageForecaster>,
+    clock: SharedClock,
+    period: Mutex<Period>,
+    /// Completed reports, oldest first
+    reports: Mutex<VecDeque<UsageReport>>,
+}
+
+impl UsageTracker {
+    pub fn new(
+        config: &UsageReportConfig,
+        forecast: Arc<StorageForecaster>,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            config: config.clone(),
+            forecast,
+            period: Mutex::new(Period {
+                start_ms: clock.now_ms(),
+                namespaces: BTreeMap::new(),
+            }),
+            clock,
+            reports: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Account a read of `key` returning `bytes`
+    pub fn record_read(&self, key: &str, bytes: u64) {
+        self.record(key, Operation::Read, bytes);
+    }
+
+    /// Account a write of `bytes` to `key`
+    pub fn record_write(&self, key: &str, bytes: u64) {
+        self.record(key, Operation::Write, bytes);
+    }
+
+    /// Account the removal of `key`
+    pub fn record_remove(&self, key: &str) {
+        self.record(key, Operation::Remove, 0);
+    }
+
+    fn record(&self, key: &str, operation: Operation, bytes: u64) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut period = self.period.lock().unwrap();
+        let counters = period
+            .namespaces
+            .entry(self.forecast.prefix_of(key).to_string())
+            .or_default();
+        let key_usage = counters
+            .keys
+            .entry(key.to_string())
+            .or_insert_with(|| KeyUsage {
+                key: key.to_string(),
+                ..Default::default()
+            });
+        key_usage.operations += 1;
+        let usage = &mut counters.usage;
+        match operation {
+            Operation::Read => {
+                usage.reads += 1;
+                usage.bytes_read += bytes;
+                key_usage.bytes_read += bytes;
+            }
+            Operation::Write => {
+                usage.writes += 1;
+                usage.bytes_written += bytes;
+                key_usage.bytes_written += bytes;
+            }
+            Operation::Remove => usage.removes += 1,
+        }
+    }
+
+    /// Report of `period` up to now, with the stored footprint of every namespace
+    fn report(&self, period: &Period, complete: bool) -> UsageReport {
+        let mut namespaces: BTreeMap<String, NamespaceUsage> = BTreeMap::new();
+        for prefix in self.forecast.forecast("").prefixes {
+            if prefix.keys > 0 {
+                let usage = namespaces.entry(prefix.prefix).or_default();
+                usage.keys = prefix.keys;
+                usage.stored_bytes = prefix.stored_bytes;
+            }
+        }
+        for (namespace, counters) in &period.namespaces {
+            let usage = namespaces.entry(namespace.clone()).or_default();
+            let mut top_keys: Vec<KeyUsage> = counters.keys.values().cloned().collect();
+            top_keys.sort_by(|a, b| {
+                b.operations
+                    .cmp(&a.operations)
+                    .then((b.bytes_read + b.bytes_written).cmp(&(a.bytes_read + a.bytes_written)))
+                    .then(a.key.cmp(&b.key))
+            });
+            top_keys.truncate(self.config.top_keys);
+            *usage = NamespaceUsage {
+                keys: usage.keys,
+                stored_bytes: usage.stored_bytes,
+                top_keys,
+                ..counters.usage.clone()
+            };
+        }
+
+        UsageReport {
+            period_start_ms: period.start_ms,
+            period_end_ms: self.clock.now_ms(),
+            complete,
+            namespaces: namespaces
+                .into_iter()
+                .map(|(namespace, usage)| NamespaceUsage { namespace, ..usage })
+                .collect(),
+        }
+    }
+
+    /// Close the running period into a report and start the next one
+    pub fn close_period(&self) -> UsageReport {
+        let mut period = self.period.lock().unwrap();
+        let report = self.report(&period, true);
+        *period = Period {
+            start_ms: report.period_end_ms,
+            namespaces: BTreeMap::new(),
+        };
+        drop(period);
+
+        let mut reports = self.reports.lock().unwrap();
+        reports.push_back(report.clone());
+        while reports.len() > self.config.retained_reports {
+            reports.pop_front();
+        }
+        report
+    }
+
+    /// Reports of the namespaces starting with `filter`, oldest first
+    ///
+    /// ### Parameters
+    /// * `filter` - namespace prefix, empty for every namespace
+    /// * `limit` - most recent completed reports, 0 for all kept
+    /// * `include_current` - add the report of the running period
+    pub fn reports(&self, filter: &str, limit: usize, include_current: bool) -> Vec<UsageReport> {
+        let reports = self.reports.lock().unwrap();
+        let skip = match limit {
+            0 => 0,
+            limit => reports.len().saturating_sub(limit),
+        };
+        let mut selected: Vec<UsageReport> = reports.iter().skip(skip).cloned().collect();
+        drop(reports);
+        if include_current {
+            let period = self.period.lock().unwrap();
+            selected.push(self.report(&period, false));
+        }
+        for report in &mut selected {
+            report
+                .namespaces
+                .retain(|usage| usage.namespace.starts_with(filter));
+        }
+        selected
+    }
+}
+
+/// Close a report every period and store it if configured, forever
+///
+/// ### Parameters
+/// * `service: Arc<PersistencyServiceImpl>` - service whose usage is reported
+pub async fn run(service: Arc<PersistencyServiceImpl>) {
+    let tracker = service.usage_tracker();
+    let config = tracker.config.clone();
+    info!(
+        "Reporting usage per namespace every {}s{}",
+        config.period_secs,
+        if config.persist {
+            format!(", stored under {}", REPORT_KEY_PREFIX)
+        } else {
+            String::new()
+        }
+    );
+
+    let period = Duration::from_secs(config.period_secs.max(1));
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let report = tracker.close_period();
+        info!(
+            "Usage report of {} namespaces for the period since {}",
+            report.namespaces.len(),
+            report.period_start_ms
+        );
+        if config.persist {
+            if let Err(e) = service.store_usage_report(&report).await {
+                warn!("Failed to store usage report: {}", e);
+            }
+        }
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use crate::config::StorageForecastConfig;
+    use std::path::PathBuf;
+
+    fn tracker(top_keys: usize, retained_reports: usize) -> (Arc<TestClock>, UsageTracker) {
+        let clock = Arc::new(TestClock::new(1_000));
+        let forecast = Arc::new(StorageForecaster::new(
+            &StorageForecastConfig::default(),
+            clock.clone(),
+            PathBuf::from("."),
+        ));
+        forecast.load([
+            ("adas/mode".to_string(), 40),
+            ("adas/route".to_string(), 60),
+            ("pullpiri/scenario".to_string(), 500),
+        ]);
+        let config = UsageReportConfig {
+            enabled: true,
+            top_keys,
+            retained_reports,
+            ..Default::default()
+        };
+        (clock.clone(), UsageTracker::new(&config, forecast, clock))
+    }
+
+    #[test]
+    fn test_report_per_namespace() {
+        let (clock, tracker) = tracker(2, 24);
+        tracker.record_write("adas/mode", 40);
+        tracker.record_write("adas/mode", 40);
+        tracker.record_read("adas/mode", 40);
+        tracker.record_read("adas/route", 60);
+        tracker.record_write("console/episode/1", 200);
+        tracker.record_remove("console/episode/1");
+        tracker.record_read("adas/speed", 10);
+        clock.advance(Duration::from_secs(3600));
+
+        let report = tracker.close_period();
+        assert!(report.complete);
+        assert_eq!(
+            (report.period_start_ms, report.period_end_ms),
+            (1_000, 3_601_000)
+        );
+        let namespaces: Vec<&str> = report
+            .namespaces
+            .iter()
+            .map(|usage| usage.namespace.as_str())
+            .collect();
+        assert_eq!(namespaces, ["adas/", "console/", "pullpiri/"]);
+
+        let adas = &report.namespaces[0];
+        assert_eq!((adas.reads, adas.writes, adas.removes), (3, 2, 0));
+        assert_eq!((adas.bytes_read, adas.bytes_written), (110, 80));
+        assert_eq!((adas.keys, adas.stored_bytes), (2, 100));
+        let top: Vec<(&str, u64)> = adas
+            .top_keys
+            .iter()
+            .map(|key| (key.key.as_str(), key.operations))
+            .collect();
+        assert_eq!(top, [("adas/mode", 3), ("adas/route", 1)]);
+
+        let console = &report.namespaces[1];
+        assert_eq!((console.writes, console.removes), (1, 1));
+        assert_eq!(console.top_keys[0].bytes_written, 200);
+
+        // Namespaces without operations are reported with their footprint
+        let pullpiri = &report.namespaces[2];
+        assert_eq!((pullpiri.reads, pullpiri.writes), (0, 0));
+        assert_eq!(pullpiri.stored_bytes, 500);
+        assert!(pullpiri.top_keys.is_empty());
+
+        let proto = report.to_proto();
+        assert_eq!(proto.namespaces[0].top_keys[0].key, "adas/mode");
+    }
+
+    #[test]
+    fn test_periods_and_retention() {
+        let (clock, tracker) = tracker(5, 2);
+        for period in 0..3 {
+            for _ in 0..=period {
+                tracker.record_write("adas/mode", 40);
+            }
+            clock.advance(Duration::from_secs(60));
+            tracker.close_period();
+        }
+        tracker.record_read("pullpiri/scenario", 500);
+
+        let reports = tracker.reports("", 0, false);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].period_start_ms, 61_000);
+        assert_eq!(reports[0].namespaces[0].writes, 2);
+        assert_eq!(reports[1].namespaces[0].writes, 3);
+
+        let reports = tracker.reports("pullpiri/", 1, true);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].namespaces[0].writes, 0);
+        let current = &reports[1];
+        assert!(!current.complete);
+        assert_eq!(current.period_start_ms, 181_000);
+        assert_eq!(current.namespaces.len(), 1);
+        assert_eq!(current.namespaces[0].reads, 1);
+    }
+
+    #[test]
+    fn test_disabled_tracker_counts_nothing() {
+        let clock = Arc::new(TestClock::new(0));
+        let forecast = Arc::new(StorageForecaster::new(
+            &StorageForecastConfig::default(),
+            clock.clone(),
+            PathBuf::from("."),
+        ));
+        let tracker = UsageTracker::new(&UsageReportConfig::default(), forecast, clock);
+        tracker.record_write("adas/mode", 40);
+        assert!(!tracker.enabled());
+        assert!(tracker.reports("", 0, true)[0].namespaces.is_empty());
+        assert_eq!(report_key(61_000), "__reports/usage/00000000000000061000");
+    }
+}
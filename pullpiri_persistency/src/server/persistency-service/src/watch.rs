@@ -0,0 +1,524 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Change notification fan-out for key watchers
+//!
+//! Watchers subscribe to a single key or to a key prefix. All watchers of the
+//! same key or prefix share one broadcast channel, so publishing a change
+//! costs one send per distinct watched key/prefix regardless of how many
+//! watchers there are.
+//!
+//! Every channel is a bounded ring buffer. A watcher that falls behind loses
+//! its oldest pending events (drop-oldest) and is told how many events it
+//! missed through [`WatchNotification::Lagged`], while fast watchers of the
+//! same channel are unaffected.
+//!
+//! Watchers may leave out puts or deletions, see [`WatchOptions`], and ask
+//! for the value a key had before the change. Writers read that value only
+//! while such a watcher of the key is subscribed, see
+//! [`WatchHub::wants_previous`].
+//!
+//! Every event carries the time of the change on the clock of the service,
+//! so that bridges relaying the events, e.g. to DDS, stamp them with when
+//! the store changed rather than when the event arrived.
+
+use crate::clock::SharedClock;
+use common::persistency_proto::{KvsValue, WatchEventType, WatchResponse};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+/// Kind of change reported to watchers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchEventKind {
+    Put,
+    Delete,
+}
+
+/// A change of a single key
+#[derive(Debug, Clone)]
+pub struct WatchEvent {
+    pub kind: WatchEventKind,
+    pub key: String,
+    /// New value, `None` for deletions
+    pub value: Option<KvsValue>,
+    /// Value before the change, if a watcher asked for it and the key had one
+    pub previous: Option<KvsValue>,
+    /// Monotonic sequence number of the change
+    pub revision: u64,
+    /// Time of the change, Unix milliseconds
+    pub changed_at_ms: u64,
+}
+
+/// What a watcher is subscribed to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WatchTarget {
+    Key(String),
+    Prefix(String),
+}
+
+impl WatchTarget {
+    /// Whether changes of `key` are reported to watchers of this target
+    pub fn covers(&self, key: &str) -> bool {
+        match self {
+            WatchTarget::Key(watched) => watched == key,
+            WatchTarget::Prefix(prefix) => key.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// Changes a watcher is told about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchOptions {
+    /// Report keys that were set
+    pub puts: bool,
+    /// Report keys that were removed
+    pub deletes: bool,
+    /// Report the value a key had before the change
+    pub previous: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            puts: true,
+            deletes: true,
+            previous: false,
+        }
+    }
+}
+
+impl WatchOptions {
+    fn reports(&self, kind: WatchEventKind) -> bool {
+        match kind {
+            WatchEventKind::Put => self.puts,
+            WatchEventKind::Delete => self.deletes,
+        }
+    }
+}
+
+/// Item received by a watcher
+#[derive(Debug, Clone)]
+pub enum WatchNotification {
+    Event(Arc<WatchEvent>),
+    /// The watcher was too slow and the oldest `dropped` events were discarded
+    Lagged {
+        dropped: u64,
+    },
+}
+
+impl WatchNotification {
+    /// Message streamed to `Watch` RPC clients
+    pub fn to_proto(&self) -> WatchResponse {
+        match self {
+            WatchNotification::Event(event) => WatchResponse {
+                r#type: match event.kind {
+                    WatchEventKind::Put => WatchEventType::Put,
+                    WatchEventKind::Delete => WatchEventType::Delete,
+                } as i32,
+                key: event.key.clone(),
+                value: event.value.clone(),
+                revision: event.revision,
+                dropped: 0,
+                prev_value: event.previous.clone(),
+                changed_at_ms: event.changed_at_ms,
+            },
+            WatchNotification::Lagged { dropped } => WatchResponse {
+                r#type: WatchEventType::Lagged as i32,
+                dropped: *dropped,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+type Channel = broadcast::Sender<Arc<WatchEvent>>;
+
+/// Registry of watch channels shared by all RPC handlers
+#[derive(Debug)]
+pub struct WatchHub {
+    keys: Mutex<HashMap<String, Channel>>,
+    prefixes: Mutex<HashMap<String, Channel>>,
+    /// Targets of the watchers asking for previous values, alive while a
+    /// subscription holds a clone of the token
+    previous: Mutex<HashMap<WatchTarget, Arc<()>>>,
+    capacity: usize,
+    revision: AtomicU64,
+    clock: SharedClock,
+}
+
+impl WatchHub {
+    /// Create a hub whose channels buffer up to `capacity` events per watcher
+    /// and whose events are stamped with the time of `clock`
+    pub fn new(capacity: usize, clock: SharedClock) -> Self {
+        Self {
+            keys: Mutex::new(HashMap::new()),
+            prefixes: Mutex::new(HashMap::new()),
+            previous: Mutex::new(HashMap::new()),
+            capacity: capacity.max(1),
+            revision: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    /// Subscribe to every change of a key or prefix
+    pub fn subscribe(&self, target: WatchTarget) -> WatchSubscription {
+        self.subscribe_with(target, WatchOptions::default())
+    }
+
+    /// Subscribe to the changes of a key or prefix selected by `options`
+    pub fn subscribe_with(&self, target: WatchTarget, options: WatchOptions) -> WatchSubscription {
+        let previous = options.previous.then(|| {
+            self.previous
+                .lock()
+                .unwrap()
+                .entry(target.clone())
+                .or_default()
+                .clone()
+        });
+        let (channels, name) = match &target {
+            WatchTarget::Key(key) => (&self.keys, key),
+            WatchTarget::Prefix(prefix) => (&self.prefixes, prefix),
+        };
+        let mut channels = channels.lock().unwrap();
+        let receiver = channels
+            .entry(name.clone())
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .subscribe();
+        debug!("New watcher for {:?}", target);
+
+        WatchSubscription {
+            target,
+            options,
+            receiver,
+            dropped: 0,
+            _previous: previous,
+        }
+    }
+
+    /// Whether a watcher of `key` asked for previous values
+    pub fn wants_previous(&self, key: &str) -> bool {
+        let mut previous = self.previous.lock().unwrap();
+        if previous.is_empty() {
+            return false;
+        }
+        // Drop the targets whose watchers are all gone
+        previous.retain(|_, token| Arc::strong_count(token) > 1);
+        previous.keys().any(|target| target.covers(key))
+    }
+
+    /// Notify the watchers of `key` and of every prefix of it
+    ///
+    /// ### Parameters
+    /// * `kind: WatchEventKind` - put or delete
+    /// * `key: &str` - changed key
+    /// * `value: Option<KvsValue>` - new value, `None` for deletions
+    /// * `previous: Option<KvsValue>` - value before the change, if asked for
+    pub fn publish(
+        &self,
+        kind: WatchEventKind,
+        key: &str,
+        value: Option<KvsValue>,
+        previous: Option<KvsValue>,
+    ) {
+        let event = Arc::new(WatchEvent {
+            kind,
+            key: key.to_string(),
+            value,
+            previous,
+            revision: self.revision.fetch_add(1, Ordering::Relaxed) + 1,
+            changed_at_ms: self.clock.now_ms(),
+        });
+
+        {
+            let mut keys = self.keys.lock().unwrap();
+            if let Some(channel) = keys.get(key) {
+                if channel.send(event.clone()).is_err() {
+                    // Every watcher of this key is gone
+                    keys.remove(key);
+                }
+            }
+        }
+
+        let mut prefixes = self.prefixes.lock().unwrap();
+        prefixes.retain(|prefix, channel| {
+            if channel.receiver_count() == 0 {
+                return false;
+            }
+            if key.starts_with(prefix.as_str()) {
+                let _ = channel.send(event.clone());
+            }
+            true
+        });
+    }
+
+    /// Number of live watchers of `target`
+    pub fn watcher_count(&self, target: &WatchTarget) -> usize {
+        let (channels, name) = match target {
+            WatchTarget::Key(key) => (&self.keys, key),
+            WatchTarget::Prefix(prefix) => (&self.prefixes, prefix),
+        };
+        channels
+            .lock()
+            .unwrap()
+            .get(name)
+            .map_or(0, |channel| channel.receiver_count())
+    }
+
+    /// Number of distinct watched keys and prefixes
+    pub fn channel_count(&self) -> usize {
+        self.keys.lock().unwrap().len() + self.prefixes.lock().unwrap().len()
+    }
+}
+
+/// A single watcher's view of a shared channel
+#[derive(Debug)]
+pub struct WatchSubscription {
+    target: WatchTarget,
+    options: WatchOptions,
+    receiver: broadcast::Receiver<Arc<WatchEvent>>,
+    dropped: u64,
+    /// Token keeping the previous values of the target read
+    _previous: Option<Arc<()>>,
+}
+
+impl WatchSubscription {
+    /// Wait for the next notification, `None` once the hub is gone
+    ///
+    /// Changes left out by the options of the watcher are skipped.
+    pub async fn recv(&mut self) -> Option<WatchNotification> {
+        loop {
+            return match self.receiver.recv().await {
+                Ok(event) if !self.options.reports(event.kind) => continue,
+                Ok(event) => Some(WatchNotification::Event(event)),
+                Err(RecvError::Lagged(dropped)) => {
+                    self.dropped += dropped;
+                    warn!(
+                        "Slow watcher of {:?} dropped {} events ({} in total)",
+                        self.target, dropped, self.dropped
+                    );
+                    Some(WatchNotification::Lagged { dropped })
+                }
+                Err(RecvError::Closed) => None,
+            };
+        }
+    }
+
+    /// What this watcher is subscribed to
+    pub fn target(&self) -> &WatchTarget {
+        &self.target
+    }
+
+    /// Changes this watcher is told about
+    pub fn options(&self) -> WatchOptions {
+        self.options
+    }
+
+    /// Total number of events this watcher missed because it was too slow
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use common::persistency_proto::kvs_value::Value;
+
+    const NOW_MS: u64 = 1_700_000_000_000;
+
+    fn test_hub(capacity: usize) -> WatchHub {
+        WatchHub::new(capacity, Arc::new(TestClock::new(NOW_MS)))
+    }
+
+    fn value(v: i32) -> Option<KvsValue> {
+        Some(KvsValue {
+            value: Some(Value::I32Value(v)),
+        })
+    }
+
+    async fn next_event(subscription: &mut WatchSubscription) -> Arc<WatchEvent> {
+        match subscription.recv().await {
+            Some(WatchNotification::Event(event)) => event,
+            other => panic!("Expected event, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_and_prefix_watchers() {
+        let hub = test_hub(16);
+        let mut key_watcher = hub.subscribe(WatchTarget::Key("vehicle/mode".to_string()));
+        let mut prefix_watcher = hub.subscribe(WatchTarget::Prefix("vehicle/".to_string()));
+        let other_watcher = hub.subscribe(WatchTarget::Prefix("Scenario/".to_string()));
+
+        hub.publish(WatchEventKind::Put, "vehicle/mode", value(1), None);
+        hub.publish(WatchEventKind::Delete, "vehicle/speed", None, None);
+
+        let event = next_event(&mut key_watcher).await;
+        assert_eq!(event.key, "vehicle/mode");
+        assert_eq!(event.kind, WatchEventKind::Put);
+        assert_eq!(event.revision, 1);
+
+        assert_eq!(next_event(&mut prefix_watcher).await.key, "vehicle/mode");
+        let event = next_event(&mut prefix_watcher).await;
+        assert_eq!(event.key, "vehicle/speed");
+        assert_eq!(event.kind, WatchEventKind::Delete);
+        assert!(event.value.is_none());
+
+        assert!(other_watcher.receiver.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hundreds_of_concurrent_watchers() {
+        const WATCHERS: usize = 500;
+        const EVENTS: i32 = 20;
+
+        let hub = Arc::new(test_hub(64));
+        let key = WatchTarget::Key("vehicle/mode".to_string());
+        let mut handles = Vec::new();
+        for i in 0..WATCHERS {
+            let target = if i % 2 == 0 {
+                key.clone()
+            } else {
+                WatchTarget::Prefix("vehicle/".to_string())
+            };
+            let mut subscription = hub.subscribe(target);
+            handles.push(tokio::spawn(async move {
+                let mut revisions = Vec::new();
+                while revisions.len() < EVENTS as usize {
+                    match subscription.recv().await {
+                        Some(WatchNotification::Event(event)) => revisions.push(event.revision),
+                        other => panic!("Unexpected notification {:?}", other),
+                    }
+                }
+                revisions
+            }));
+        }
+        // One channel per distinct target, not per watcher
+        assert_eq!(hub.channel_count(), 2);
+        assert_eq!(hub.watcher_count(&key), WATCHERS / 2);
+
+        for i in 0..EVENTS {
+            hub.publish(WatchEventKind::Put, "vehicle/mode", value(i), None);
+        }
+
+        let expected: Vec<u64> = (1..=EVENTS as u64).collect();
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_watcher_drops_oldest() {
+        let hub = test_hub(4);
+        let mut slow = hub.subscribe(WatchTarget::Key("k".to_string()));
+        let mut fast = hub.subscribe(WatchTarget::Key("k".to_string()));
+
+        for i in 0..3 {
+            hub.publish(WatchEventKind::Put, "k", value(i), None);
+            assert_eq!(next_event(&mut fast).await.revision, i as u64 + 1);
+        }
+        for i in 3..10 {
+            hub.publish(WatchEventKind::Put, "k", value(i), None);
+        }
+
+        // The slow watcher is told about the gap and resumes with the newest events
+        match slow.recv().await {
+            Some(WatchNotification::Lagged { dropped }) => assert_eq!(dropped, 6),
+            other => panic!("Expected lag notification, got {:?}", other),
+        }
+        assert_eq!(slow.dropped(), 6);
+        assert_eq!(next_event(&mut slow).await.revision, 7);
+
+        // The fast watcher lags only by the events it has not read yet
+        match fast.recv().await {
+            Some(WatchNotification::Lagged { dropped }) => assert_eq!(dropped, 3),
+            other => panic!("Expected lag notification, got {:?}", other),
+        }
+        assert_eq!(next_event(&mut fast).await.revision, 7);
+    }
+
+    #[tokio::test]
+    async fn test_unused_channels_are_pruned() {
+        let hub = test_hub(4);
+        let key_watcher = hub.subscribe(WatchTarget::Key("k".to_string()));
+        let prefix_watcher = hub.subscribe(WatchTarget::Prefix("k".to_string()));
+        assert_eq!(hub.channel_count(), 2);
+
+        drop(key_watcher);
+        drop(prefix_watcher);
+        hub.publish(WatchEventKind::Put, "k", value(1), None);
+        assert_eq!(hub.channel_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_filtered_watcher_skips_events() {
+        let hub = test_hub(16);
+        let options = WatchOptions {
+            deletes: false,
+            ..Default::default()
+        };
+        let mut puts = hub.subscribe_with(WatchTarget::Key("vehicle/mode".to_string()), options);
+        assert_eq!(puts.options(), options);
+
+        hub.publish(WatchEventKind::Delete, "vehicle/mode", None, None);
+        hub.publish(WatchEventKind::Put, "vehicle/mode", value(1), None);
+
+        let event = next_event(&mut puts).await;
+        assert_eq!(event.kind, WatchEventKind::Put);
+        assert_eq!(event.revision, 2);
+        assert!(puts.receiver.is_empty());
+    }
+
+    #[test]
+    fn test_previous_values_read_while_asked_for() {
+        let hub = test_hub(4);
+        let _plain = hub.subscribe(WatchTarget::Prefix("vehicle/".to_string()));
+        assert!(!hub.wants_previous("vehicle/mode"));
+
+        let options = WatchOptions {
+            previous: true,
+            ..Default::default()
+        };
+        let first = hub.subscribe_with(WatchTarget::Prefix("vehicle/".to_string()), options);
+        let second = hub.subscribe_with(WatchTarget::Prefix("vehicle/".to_string()), options);
+        assert!(hub.wants_previous("vehicle/mode"));
+        assert!(!hub.wants_previous("Scenario/helloworld"));
+
+        drop(first);
+        assert!(hub.wants_previous("vehicle/mode"));
+        drop(second);
+        assert!(!hub.wants_previous("vehicle/mode"));
+    }
+
+    #[tokio::test]
+    async fn test_notification_to_proto() {
+        let hub = test_hub(1);
+        let mut watcher = hub.subscribe(WatchTarget::Prefix("vehicle/".to_string()));
+        hub.publish(WatchEventKind::Delete, "vehicle/mode", None, None);
+
+        let response = watcher.recv().await.unwrap().to_proto();
+        assert_eq!(response.r#type(), WatchEventType::Delete);
+        assert_eq!(response.key, "vehicle/mode");
+        assert!(response.value.is_none());
+        assert_eq!(response.revision, 1);
+        assert!(response.prev_value.is_none());
+        assert_eq!(response.changed_at_ms, NOW_MS);
+
+        hub.publish(WatchEventKind::Put, "vehicle/mode", value(1), value(0));
+        let response = watcher.recv().await.unwrap().to_proto();
+        assert_eq!(response.prev_value, value(0));
+
+        hub.publish(WatchEventKind::Put, "vehicle/mode", value(1), None);
+        hub.publish(WatchEventKind::Put, "vehicle/mode", value(2), None);
+        let response = watcher.recv().await.unwrap().to_proto();
+        assert_eq!(response.r#type(), WatchEventType::Lagged);
+        assert_eq!(response.dropped, 1);
+    }
+}
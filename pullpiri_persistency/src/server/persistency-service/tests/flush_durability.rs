@@ -0,0 +1,117 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Writes acknowledged while a Flush runs are made durable
+//!
+//! rust_kvs keeps its storage files in the working directory, so this test
+//! is a binary of its own that moves to a fresh directory first.
+
+use common::persistency_proto::persistency_service_client::PersistencyServiceClient;
+use common::persistency_proto::*;
+use persistency_service::clock::TestClock;
+use persistency_service::config::ServiceConfig;
+use persistency_service::ephemeral;
+use persistency_service::PersistencyServiceImpl;
+use std::sync::Arc;
+
+const ROUNDS: usize = 50;
+const WRITERS: usize = 8;
+const KEYS_PER_WRITER: usize = 4;
+
+fn key(round: usize, writer: usize, n: usize) -> String {
+    format!("round{}/writer{}/key{}", round, writer, n)
+}
+
+/// Keys of `round` missing from the storage file
+fn unflushed(round: usize) -> usize {
+    let stored: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("kvs_0_0.json").unwrap()).unwrap();
+    (0..WRITERS)
+        .flat_map(|writer| (0..KEYS_PER_WRITER).map(move |n| key(round, writer, n)))
+        .filter(|key| stored["v"].get(key).is_none())
+        .count()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_set_value_during_flush_is_durable() {
+    let dir = std::env::temp_dir().join(format!(
+        "persistency-flush-durability-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+
+    let config = ServiceConfig::default();
+    let clock = Arc::new(TestClock::new(1_700_000_000_000));
+    let service = Arc::new(PersistencyServiceImpl::with_clock(&config, clock).unwrap());
+    let served = ephemeral::serve(service).unwrap();
+    let mut clients = Vec::new();
+    for _ in 0..=WRITERS {
+        clients.push(
+            PersistencyServiceClient::connect(served.endpoint().to_string())
+                .await
+                .unwrap(),
+        );
+    }
+
+    for round in 0..ROUNDS {
+        // A Flush in the middle of the writes
+        let mut writes = Vec::new();
+        for (writer, client) in clients[..WRITERS].iter().enumerate() {
+            let mut client = client.clone();
+            writes.push(tokio::spawn(async move {
+                for n in 0..KEYS_PER_WRITER {
+                    let request = SetValueRequest {
+                        key: key(round, writer, n),
+                        value: Some(KvsValue {
+                            value: Some(kvs_value::Value::I32Value(n as i32)),
+                        }),
+                        ..Default::default()
+                    };
+                    assert!(
+                        client
+                            .set_value(request)
+                            .await
+                            .unwrap()
+                            .into_inner()
+                            .success
+                    );
+                }
+            }));
+        }
+        let flushed = clients[WRITERS]
+            .flush(FlushRequest::default())
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(flushed.success);
+        for write in writes {
+            write.await.unwrap();
+        }
+
+        // Acknowledged writes the Flush missed must be left for the next one
+        let missing = unflushed(round);
+        let flushed = clients[WRITERS]
+            .flush_prefix(FlushPrefixRequest {
+                prefix: format!("round{}/", round),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(flushed.success);
+        assert!(
+            flushed.flushed_keys as usize >= missing,
+            "round {}: {} of {} unflushed keys pending",
+            round,
+            flushed.flushed_keys,
+            missing
+        );
+        assert_eq!(unflushed(round), 0, "round {}", round);
+    }
+
+    drop(served);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
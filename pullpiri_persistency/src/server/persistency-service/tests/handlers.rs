@@ -0,0 +1,1041 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! The write RPCs of the service, called over gRPC
+//!
+//! Every test serves an in-memory service of its own, see
+//! [`persistency_service::ephemeral`], with the configuration it needs and a
+//! [`TestClock`] it fast-forwards instead of sleeping. Expiry sweeps are run
+//! by the tests, the background tasks of the service are not started.
+
+use common::persistency_proto::persistency_service_client::PersistencyServiceClient;
+use common::persistency_proto::*;
+use persistency_service::clock::TestClock;
+use persistency_service::config::{ServiceConfig, StorageBackendKind};
+use persistency_service::ephemeral;
+use persistency_service::PersistencyServiceImpl;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::transport::Channel;
+use tonic::Code;
+
+/// Service under test with a client connected to it
+struct Harness {
+    service: Arc<PersistencyServiceImpl>,
+    clock: Arc<TestClock>,
+    client: PersistencyServiceClient<Channel>,
+}
+
+/// Serve an in-memory service configured with `yaml`
+async fn start(yaml: &str) -> Harness {
+    let mut config = ServiceConfig::from_yaml(yaml).unwrap();
+    config.storage.backend = StorageBackendKind::Memory;
+    let clock = Arc::new(TestClock::new(1_700_000_000_000));
+    let service = Arc::new(PersistencyServiceImpl::with_clock(&config, clock.clone()).unwrap());
+    let served = ephemeral::serve(service.clone()).unwrap();
+    let client = PersistencyServiceClient::connect(served.endpoint().to_string())
+        .await
+        .unwrap();
+    Harness {
+        service,
+        clock,
+        client,
+    }
+}
+
+/// `message` of a caller asking for failures as status codes
+fn coded<T>(message: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    request
+        .metadata_mut()
+        .insert(STATUS_CODES_HEADER, "true".parse().unwrap());
+    request
+}
+
+fn string(value: &str) -> KvsValue {
+    KvsValue {
+        value: Some(kvs_value::Value::StringValue(value.to_string())),
+    }
+}
+
+fn int(value: i32) -> KvsValue {
+    KvsValue {
+        value: Some(kvs_value::Value::I32Value(value)),
+    }
+}
+
+fn uint(value: u32) -> KvsValue {
+    KvsValue {
+        value: Some(kvs_value::Value::U32Value(value)),
+    }
+}
+
+impl Harness {
+    async fn set(&mut self, key: &str, value: KvsValue) -> SetValueResponse {
+        self.client
+            .set_value(SetValueRequest {
+                key: key.to_string(),
+                value: Some(value),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner()
+    }
+
+    /// Value of `key`, `None` if it is not stored
+    async fn get(&mut self, key: &str) -> Option<KvsValue> {
+        let request = coded(GetValueRequest {
+            key: key.to_string(),
+            ..Default::default()
+        });
+        match self.client.get_value(request).await {
+            Ok(response) => response.into_inner().value,
+            Err(status) if status.code() == Code::NotFound => None,
+            Err(status) => panic!("GetValue of {} failed: {}", key, status),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_set_value_at_expected_revision() {
+    let mut harness = start("").await;
+    let first = harness.set("vehicle/mode", string("manual")).await;
+    assert!(first.success);
+
+    let request = SetValueRequest {
+        key: "vehicle/mode".to_string(),
+        value: Some(string("autonomous")),
+        expected_revision: first.revision,
+        ..Default::default()
+    };
+    let second = harness
+        .client
+        .set_value(request.clone())
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(second.success);
+    assert!(second.revision > first.revision);
+
+    // The caller read the key before the second write
+    let status = harness.client.set_value(coded(request)).await.unwrap_err();
+    assert_eq!(status.code(), Code::Aborted);
+    let stale = harness.client.set_value(SetValueRequest {
+        key: "vehicle/mode".to_string(),
+        value: Some(string("parked")),
+        expected_revision: first.revision,
+        ..Default::default()
+    });
+    let stale = stale.await.unwrap().into_inner();
+    assert!(!stale.success);
+    assert_eq!(stale.revision, second.revision);
+    assert_eq!(
+        harness.get("vehicle/mode").await,
+        Some(string("autonomous"))
+    );
+}
+
+fn batch(keys: &[String], value: KvsValue) -> SetValuesRequest {
+    SetValuesRequest {
+        entries: keys
+            .iter()
+            .map(|key| KeyValue {
+                key: key.clone(),
+                value: Some(value.clone()),
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_readers_never_see_half_a_batch() {
+    const ROUNDS: u32 = 200;
+    let mut harness = start("").await;
+    let keys: Vec<String> = (0..8).map(|n| format!("route/waypoint{}", n)).collect();
+    let first = harness
+        .client
+        .set_values(batch(&keys, uint(0)))
+        .await
+        .unwrap();
+    assert!(first.into_inner().success);
+
+    let mut writer = harness.client.clone();
+    let written = keys.clone();
+    let writes = tokio::spawn(async move {
+        for round in 1..=ROUNDS {
+            let response = writer
+                .set_values(batch(&written, uint(round)))
+                .await
+                .unwrap();
+            assert!(response.into_inner().success);
+        }
+    });
+    while !writes.is_finished() {
+        let read = harness.client.get_values(GetValuesRequest {
+            keys: keys.clone(),
+            ..Default::default()
+        });
+        let read = read.await.unwrap().into_inner();
+        assert!(read.success && read.missing_keys.is_empty());
+        let first = &read.key_values[&keys[0]];
+        assert!(read.key_values.values().all(|value| value == first));
+    }
+    writes.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_rejected_entry_fails_the_whole_batch() {
+    let mut harness = start("").await;
+    let mut request = batch(&["route/origin".to_string()], string("Seoul"));
+    request.entries.push(KeyValue {
+        key: "route/destination".to_string(),
+        value: None,
+    });
+    let status = harness
+        .client
+        .set_values(coded(request.clone()))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let response = harness
+        .client
+        .set_values(request)
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!response.success);
+    assert_eq!(response.written_keys, 0);
+    assert_eq!(harness.get("route/origin").await, None);
+}
+
+#[tokio::test]
+async fn test_remove_keys_reports_missing_keys() {
+    let mut harness = start("").await;
+    let keys = ["route/origin".to_string(), "route/destination".to_string()];
+    let written = harness
+        .client
+        .set_values(batch(&keys, string("Seoul")))
+        .await
+        .unwrap();
+    assert!(written.into_inner().success);
+
+    let removed = harness.client.remove_keys(RemoveKeysRequest {
+        keys: vec![
+            "route/origin".to_string(),
+            "route/stopover".to_string(),
+            "route/destination".to_string(),
+        ],
+        ..Default::default()
+    });
+    let removed = removed.await.unwrap().into_inner();
+    assert!(removed.success);
+    assert_eq!(removed.removed_keys, 2);
+    assert_eq!(removed.missing_keys, vec!["route/stopover".to_string()]);
+
+    let read = harness.client.get_values(GetValuesRequest {
+        keys: keys.to_vec(),
+        ..Default::default()
+    });
+    let read = read.await.unwrap().into_inner();
+    assert!(read.key_values.is_empty());
+    assert_eq!(read.missing_keys, keys.to_vec());
+}
+
+#[tokio::test]
+async fn test_batch_is_flushed_once() {
+    let mut harness = start("").await;
+    let stats = harness.service.stats();
+    let keys: Vec<String> = (0..8).map(|n| format!("route/waypoint{}", n)).collect();
+
+    let flushes = stats.flushes();
+    let written = harness
+        .client
+        .set_values(batch(&keys, uint(1)))
+        .await
+        .unwrap();
+    assert_eq!(written.into_inner().written_keys, 8);
+    assert_eq!(stats.flushes(), flushes + 1);
+
+    let removed = harness.client.remove_keys(RemoveKeysRequest {
+        keys: keys.clone(),
+        ..Default::default()
+    });
+    assert_eq!(removed.await.unwrap().into_inner().removed_keys, 8);
+    assert_eq!(stats.flushes(), flushes + 2);
+}
+
+#[tokio::test]
+async fn test_compare_and_set_swaps_the_expected_value() {
+    let mut harness = start("").await;
+
+    // Unset expected value, the key must not exist yet
+    let created = harness
+        .client
+        .compare_and_set(CompareAndSetRequest {
+            key: "scenario/owner".to_string(),
+            new_value: Some(string("apiserver")),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(created.success && created.swapped);
+
+    let swapped = harness
+        .client
+        .compare_and_set(CompareAndSetRequest {
+            key: "scenario/owner".to_string(),
+            expected_value: Some(string("apiserver")),
+            new_value: Some(string("statemanager")),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(swapped.success && swapped.swapped);
+    assert_eq!(
+        harness.get("scenario/owner").await,
+        Some(string("statemanager"))
+    );
+
+    let status = harness
+        .client
+        .compare_and_set(coded(CompareAndSetRequest {
+            key: "scenario/owner".to_string(),
+            expected_value: Some(string("statemanager")),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_compare_and_set_keeps_a_changed_value() {
+    let mut harness = start("").await;
+    assert!(
+        harness
+            .set("scenario/owner", string("apiserver"))
+            .await
+            .success
+    );
+
+    let lost = harness
+        .client
+        .compare_and_set(CompareAndSetRequest {
+            key: "scenario/owner".to_string(),
+            expected_value: Some(string("statemanager")),
+            new_value: Some(string("policymanager")),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(lost.success && !lost.swapped);
+    assert_eq!(lost.current_value, Some(string("apiserver")));
+    assert_eq!(
+        harness.get("scenario/owner").await,
+        Some(string("apiserver"))
+    );
+}
+
+#[tokio::test]
+async fn test_compare_and_set_of_a_missing_key() {
+    let mut harness = start("").await;
+
+    let missing = harness
+        .client
+        .compare_and_set(CompareAndSetRequest {
+            key: "scenario/owner".to_string(),
+            expected_value: Some(string("apiserver")),
+            new_value: Some(string("statemanager")),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(missing.success && !missing.swapped);
+    assert_eq!(missing.current_value, None);
+    assert_eq!(harness.get("scenario/owner").await, None);
+}
+
+#[tokio::test]
+async fn test_counters() {
+    let mut harness = start("").await;
+
+    let value = harness
+        .client
+        .increment(IncrementRequest {
+            key: "stats/restarts".to_string(),
+            delta: Some(uint(3)),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(value.success);
+    assert_eq!(value.value, Some(uint(3)));
+
+    let value = harness
+        .client
+        .decrement(DecrementRequest {
+            key: "stats/restarts".to_string(),
+            delta: Some(uint(1)),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(value.value, Some(uint(2)));
+    assert_eq!(harness.get("stats/restarts").await, Some(uint(2)));
+
+    // A u32 counter does not go below zero
+    let status = harness
+        .client
+        .decrement(coded(DecrementRequest {
+            key: "stats/restarts".to_string(),
+            delta: Some(uint(5)),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    harness.set("vehicle/mode", string("manual")).await;
+    let status = harness
+        .client
+        .increment(coded(IncrementRequest {
+            key: "vehicle/mode".to_string(),
+            delta: Some(int(1)),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let status = harness
+        .client
+        .increment(coded(IncrementRequest {
+            key: "stats/restarts".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(harness.get("stats/restarts").await, Some(uint(2)));
+}
+
+#[tokio::test]
+async fn test_keys_expire_after_their_ttl() {
+    let mut harness = start("expiry: {max_ttl_secs: 60}").await;
+    let set = harness.client.set_value(SetValueRequest {
+        key: "v2x/hazard".to_string(),
+        value: Some(string("ice")),
+        ttl_seconds: 10,
+        ..Default::default()
+    });
+    assert!(set.await.unwrap().into_inner().success);
+
+    let ttl = harness
+        .client
+        .get_ttl(GetTtlRequest {
+            key: "v2x/hazard".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(ttl.has_ttl);
+    assert_eq!(ttl.remaining_ms, 10_000);
+
+    harness.clock.advance(Duration::from_secs(9));
+    harness.service.expire_keys().await;
+    assert_eq!(harness.get("v2x/hazard").await, Some(string("ice")));
+
+    harness.clock.advance(Duration::from_secs(1));
+    harness.service.expire_keys().await;
+    assert_eq!(harness.get("v2x/hazard").await, None);
+
+    let status = harness
+        .client
+        .get_ttl(coded(GetTtlRequest {
+            key: "v2x/hazard".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let status = harness
+        .client
+        .set_value(coded(SetValueRequest {
+            key: "v2x/hazard".to_string(),
+            value: Some(string("fog")),
+            ttl_seconds: 61,
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_rewrite_without_ttl_keeps_the_key() {
+    let mut harness = start("").await;
+    let set = harness.client.set_value(SetValueRequest {
+        key: "v2x/hazard".to_string(),
+        value: Some(string("ice")),
+        ttl_seconds: 10,
+        ..Default::default()
+    });
+    assert!(set.await.unwrap().into_inner().success);
+    harness.set("v2x/hazard", string("cleared")).await;
+
+    harness.clock.advance(Duration::from_secs(10));
+    harness.service.expire_keys().await;
+    assert_eq!(harness.get("v2x/hazard").await, Some(string("cleared")));
+}
+
+const TRASH: &str = "
+trash: {enabled: true, namespace: trash, retention_secs: 60}
+namespaces: {instances: {trash: 1}}
+";
+
+#[tokio::test]
+async fn test_trash_and_restore() {
+    let mut harness = start(TRASH).await;
+    harness
+        .set("scenario/parking", string("kind: Scenario"))
+        .await;
+
+    let removed = harness
+        .client
+        .remove_key(RemoveKeyRequest {
+            key: "scenario/parking".to_string(),
+            trash: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(removed.success);
+    assert_eq!(harness.get("scenario/parking").await, None);
+
+    let deleted = harness
+        .client
+        .list_deleted(ListDeletedRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(deleted.success);
+    let keys: Vec<_> = deleted
+        .keys
+        .iter()
+        .map(|deleted| deleted.key.as_str())
+        .collect();
+    assert_eq!(keys, vec!["scenario/parking"]);
+    assert_eq!(deleted.keys[0].deleted_at_ms, 1_700_000_000_000);
+    assert_eq!(deleted.keys[0].expires_at_ms, 1_700_000_060_000);
+
+    let restored = harness
+        .client
+        .restore_key(RestoreKeyRequest {
+            key: "scenario/parking".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(restored.success);
+    assert!(restored.revision > 0);
+    assert_eq!(
+        harness.get("scenario/parking").await,
+        Some(string("kind: Scenario"))
+    );
+
+    let status = harness
+        .client
+        .restore_key(coded(RestoreKeyRequest {
+            key: "scenario/parking".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists);
+    let status = harness
+        .client
+        .restore_key(coded(RestoreKeyRequest {
+            key: "scenario/highway".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_trashed_keys_expire_after_the_retention() {
+    let mut harness = start(TRASH).await;
+    harness
+        .set("scenario/parking", string("kind: Scenario"))
+        .await;
+    let removed = harness.client.remove_key(RemoveKeyRequest {
+        key: "scenario/parking".to_string(),
+        trash: true,
+        ..Default::default()
+    });
+    assert!(removed.await.unwrap().into_inner().success);
+
+    harness.clock.advance(Duration::from_secs(60));
+    harness.service.expire_keys().await;
+    let status = harness
+        .client
+        .restore_key(coded(RestoreKeyRequest {
+            key: "scenario/parking".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_remove_to_disabled_trash() {
+    let mut harness = start("").await;
+    harness
+        .set("scenario/parking", string("kind: Scenario"))
+        .await;
+    let status = harness
+        .client
+        .remove_key(coded(RemoveKeyRequest {
+            key: "scenario/parking".to_string(),
+            trash: true,
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert_eq!(
+        harness.get("scenario/parking").await,
+        Some(string("kind: Scenario"))
+    );
+}
+
+#[tokio::test]
+async fn test_quotas() {
+    let mut harness =
+        start("quotas: {max_value_bytes: 32, max_keys: 3, prefixes: {telemetry/: 1}}").await;
+
+    let status = harness
+        .client
+        .set_value(coded(SetValueRequest {
+            key: "vehicle/blob".to_string(),
+            value: Some(string(&"x".repeat(64))),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert_eq!(harness.get("vehicle/blob").await, None);
+
+    assert!(harness.set("telemetry/1", int(1)).await.success);
+    // Overwriting a stored key adds none
+    assert!(harness.set("telemetry/1", int(2)).await.success);
+    let status = harness
+        .client
+        .set_value(coded(SetValueRequest {
+            key: "telemetry/2".to_string(),
+            value: Some(int(1)),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    assert!(harness.set("vehicle/mode", string("manual")).await.success);
+    assert!(harness.set("vehicle/gear", int(3)).await.success);
+    let status = harness
+        .client
+        .increment(coded(IncrementRequest {
+            key: "vehicle/odometer".to_string(),
+            delta: Some(int(1)),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert_eq!(harness.get("vehicle/odometer").await, None);
+}
+
+#[tokio::test]
+async fn test_keys_expire_with_their_lease() {
+    let mut harness = start("").await;
+    let lease = harness
+        .client
+        .grant_lease(GrantLeaseRequest { ttl_seconds: 5 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(lease.success);
+
+    let set = harness.client.set_value(SetValueRequest {
+        key: "nodes/hpc/alive".to_string(),
+        value: Some(string("yes")),
+        lease_id: lease.lease_id,
+        ..Default::default()
+    });
+    assert!(set.await.unwrap().into_inner().success);
+
+    // Kept alive past the first time to live
+    harness.clock.advance(Duration::from_secs(4));
+    let mut keep_alive = harness
+        .client
+        .keep_alive(tokio_stream::iter([KeepAliveRequest {
+            lease_id: lease.lease_id,
+        }]))
+        .await
+        .unwrap()
+        .into_inner();
+    let renewed = keep_alive.message().await.unwrap().unwrap();
+    assert_eq!(renewed.ttl_seconds, 5);
+    harness.clock.advance(Duration::from_secs(4));
+    harness.service.expire_keys().await;
+    assert_eq!(harness.get("nodes/hpc/alive").await, Some(string("yes")));
+
+    harness.clock.advance(Duration::from_secs(1));
+    harness.service.expire_keys().await;
+    assert_eq!(harness.get("nodes/hpc/alive").await, None);
+
+    let status = harness
+        .client
+        .set_value(coded(SetValueRequest {
+            key: "nodes/hpc/alive".to_string(),
+            value: Some(string("yes")),
+            lease_id: lease.lease_id,
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+}
+
+#[tokio::test]
+async fn test_revoke_lease() {
+    let mut harness = start("leases: {max_ttl_secs: 60}").await;
+    let status = harness
+        .client
+        .grant_lease(coded(GrantLeaseRequest { ttl_seconds: 61 }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let lease = harness
+        .client
+        .grant_lease(GrantLeaseRequest { ttl_seconds: 60 })
+        .await
+        .unwrap()
+        .into_inner();
+    for key in ["nodes/hpc/alive", "nodes/hpc/load"] {
+        let set = harness.client.set_value(SetValueRequest {
+            key: key.to_string(),
+            value: Some(string("yes")),
+            lease_id: lease.lease_id,
+            ..Default::default()
+        });
+        assert!(set.await.unwrap().into_inner().success);
+    }
+    // Written again without the lease, the key stays
+    harness.set("nodes/hpc/load", int(80)).await;
+
+    let revoked = harness
+        .client
+        .revoke_lease(RevokeLeaseRequest {
+            lease_id: lease.lease_id,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(revoked.success);
+    assert_eq!(revoked.keys, 1);
+    assert_eq!(harness.get("nodes/hpc/alive").await, None);
+    assert_eq!(harness.get("nodes/hpc/load").await, Some(int(80)));
+
+    let status = harness
+        .client
+        .revoke_lease(coded(RevokeLeaseRequest {
+            lease_id: lease.lease_id,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_lock_and_unlock() {
+    let mut harness = start("").await;
+    let lock = || LockRequest {
+        name: "ota-update".to_string(),
+        ttl_seconds: 10,
+    };
+
+    let held = harness.client.lock(lock()).await.unwrap().into_inner();
+    assert!(held.success && held.acquired);
+    assert_ne!(held.token, 0);
+
+    let busy = harness.client.lock(lock()).await.unwrap().into_inner();
+    assert!(busy.success && !busy.acquired);
+    assert_eq!(busy.token, 0);
+
+    let status = harness
+        .client
+        .unlock(coded(UnlockRequest {
+            name: "ota-update".to_string(),
+            token: held.token + 1,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let unlocked = harness
+        .client
+        .unlock(UnlockRequest {
+            name: "ota-update".to_string(),
+            token: held.token,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(unlocked.success);
+
+    let again = harness.client.lock(lock()).await.unwrap().into_inner();
+    assert!(again.acquired);
+
+    let status = harness
+        .client
+        .lock(coded(LockRequest {
+            name: String::new(),
+            ttl_seconds: 10,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_lock_expires_with_its_lease() {
+    let mut harness = start("").await;
+    let lock = || LockRequest {
+        name: "ota-update".to_string(),
+        ttl_seconds: 10,
+    };
+    let held = harness.client.lock(lock()).await.unwrap().into_inner();
+    assert!(held.acquired);
+
+    harness.clock.advance(Duration::from_secs(10));
+    harness.service.expire_keys().await;
+    let taken_over = harness.client.lock(lock()).await.unwrap().into_inner();
+    assert!(taken_over.acquired);
+    assert_ne!(taken_over.token, held.token);
+}
+
+#[tokio::test]
+async fn test_writes_keep_held_locks() {
+    let mut harness = start("").await;
+    let lock = || LockRequest {
+        name: "ota-update".to_string(),
+        ttl_seconds: 10,
+    };
+    let held = harness.client.lock(lock()).await.unwrap().into_inner();
+    assert!(held.acquired);
+
+    let status = harness
+        .client
+        .set_value(coded(SetValueRequest {
+            key: "locks/ota-update".to_string(),
+            value: Some(string("intruder")),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let status = harness
+        .client
+        .remove_key(coded(RemoveKeyRequest {
+            key: "locks/ota-update".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let busy = harness.client.lock(lock()).await.unwrap().into_inner();
+    assert!(!busy.acquired);
+
+    // The holder writes with the token and keeps the lock
+    let set = harness
+        .client
+        .set_value(SetValueRequest {
+            key: "locks/ota-update".to_string(),
+            value: Some(string("updater")),
+            lease_id: held.token,
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(set.success);
+    let busy = harness.client.lock(lock()).await.unwrap().into_inner();
+    assert!(!busy.acquired);
+    assert_eq!(busy.holder, "updater");
+}
+
+#[tokio::test]
+async fn test_restores_keep_held_locks() {
+    let mut harness = start("").await;
+    let held = harness
+        .client
+        .lock(LockRequest {
+            name: "ota-update".to_string(),
+            ttl_seconds: 10,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(held.acquired);
+    let export = r#"{"format_version":1,"exported_at_ms":1700000000000,"prefixes":[],
+        "entries":{"locks/ota-update":"intruder","vehicle/mode":"manual"}}"#;
+
+    let restored = harness.client.restore(RestoreRequest {
+        source: Some(restore_request::Source::ExportJson(export.to_string())),
+        strategy: ConflictStrategy::Overwrite as i32,
+        ..Default::default()
+    });
+    let restored = restored.await.unwrap().into_inner();
+    assert!(restored.success);
+    assert_eq!((restored.restored_keys, restored.skipped_keys), (1, 1));
+    assert_eq!(restored.conflicts[0].key, "locks/ota-update");
+    assert_eq!(restored.conflicts[0].resolution, "skipped");
+    assert_eq!(harness.get("vehicle/mode").await, Some(string("manual")));
+
+    // The snapshot holds no lock, replacing the whole store would remove it
+    let snapshot = r#"{"format_version":1,"exported_at_ms":1700000000000,"prefixes":[],
+        "entries":{"vehicle/mode":"autonomous"}}"#;
+    let replaced = harness
+        .client
+        .restore_snapshot(tokio_stream::iter([RestoreSnapshotRequest {
+            dry_run: false,
+            total_size: snapshot.len() as u64,
+            chunk: snapshot.as_bytes().to_vec(),
+        }]))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!replaced.success && !replaced.applied);
+    assert!(replaced.error_message.contains("held lock"));
+    assert_eq!(harness.get("vehicle/mode").await, Some(string("manual")));
+
+    let busy = harness.client.lock(LockRequest {
+        name: "ota-update".to_string(),
+        ttl_seconds: 10,
+    });
+    assert!(!busy.await.unwrap().into_inner().acquired);
+}
+
+#[tokio::test]
+async fn test_values_shaped_like_sealed_ones_are_rejected() {
+    let mut harness = start("").await;
+    let sealed = KvsValue {
+        value: Some(kvs_value::Value::ObjectValue(KvsObject {
+            values: [(
+                "$encrypted".to_string(),
+                KvsValue {
+                    value: Some(kvs_value::Value::BytesValue(vec![1, 2, 3])),
+                },
+            )]
+            .into(),
+        })),
+    };
+
+    let status = harness
+        .client
+        .set_value(coded(SetValueRequest {
+            key: "credentials/mqtt".to_string(),
+            value: Some(sealed.clone()),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let status = harness
+        .client
+        .set_values(coded(SetValuesRequest {
+            entries: vec![KeyValue {
+                key: "credentials/mqtt".to_string(),
+                value: Some(sealed.clone()),
+            }],
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let status = harness
+        .client
+        .compare_and_set(coded(CompareAndSetRequest {
+            key: "credentials/mqtt".to_string(),
+            new_value: Some(sealed),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(harness.get("credentials/mqtt").await, None);
+}
+
+#[tokio::test]
+async fn test_only_committed_writes_are_mirrored() {
+    let mut harness = start(
+        "
+mirror: {enabled: true, endpoint: 'http://127.0.0.1:1', percent: 100.0}
+quotas: {max_value_bytes: 32}
+",
+    )
+    .await;
+    let queued = |harness: &Harness| {
+        let metrics = harness.service.mirror().render_metrics();
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix("persistency_mirror_queued_writes "))
+            .unwrap()
+            .to_string()
+    };
+
+    let status = harness
+        .client
+        .set_value(coded(SetValueRequest {
+            key: "vehicle/blob".to_string(),
+            value: Some(string(&"x".repeat(64))),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    let status = harness
+        .client
+        .decrement(coded(DecrementRequest {
+            key: "stats/restarts".to_string(),
+            delta: Some(uint(1)),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert_eq!(queued(&harness), "0");
+
+    assert!(harness.set("vehicle/mode", string("manual")).await.success);
+    assert_eq!(queued(&harness), "1");
+}
@@ -0,0 +1,135 @@
+/*
+ * SPDX-FileCopyrightText: Copyright 2024 LG Electronics Inc.
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! The persistency client of the components against an in-memory service
+//!
+//! `common::persistency` talks to the service through one client shared by
+//! the whole process, so these tests run in a process of their own, against a
+//! service spawned with [`persistency_service::ephemeral::spawn`].
+
+use common::persistency::{
+    delete, delete_all_with_prefix, get, get_all_with_prefix, put, use_client,
+};
+use common::persistency_client::{PersistencyClient, PersistencyError};
+
+// Test constants
+const TEST_KEY: &str = "unit_test_key";
+const TEST_VALUE: &str = "unit_test_value";
+const TEST_PREFIX: &str = "unit_test_";
+
+/// Make the functions of `common::persistency` use an in-memory service
+///
+/// The service is shared by the tests, each of them uses keys of its own.
+async fn use_in_memory_service() {
+    static SERVICE: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+    SERVICE
+        .get_or_init(|| async {
+            let service = persistency_service::ephemeral::spawn().unwrap();
+            let endpoint = service.endpoint().to_string();
+            let client = service
+                .run(async move { PersistencyClient::connect_to(&endpoint).await })
+                .await
+                .unwrap();
+            assert!(use_client(client), "A client was connected before");
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_put_and_get() {
+    use_in_memory_service().await;
+    let key = format!("{}/put_and_get", TEST_KEY);
+
+    put(&key, TEST_VALUE).await.unwrap();
+    assert_eq!(get(&key).await.unwrap(), TEST_VALUE);
+    put(&key, "updated").await.unwrap();
+    assert_eq!(get(&key).await.unwrap(), "updated");
+}
+
+#[tokio::test]
+async fn test_get_nonexistent_key() {
+    use_in_memory_service().await;
+    let result = get("nonexistent_key_12345").await;
+    assert!(
+        matches!(result, Err(PersistencyError::NotFound)),
+        "Expected NotFound for nonexistent key"
+    );
+}
+
+#[tokio::test]
+async fn test_get_all_with_prefix() {
+    use_in_memory_service().await;
+    let prefix = format!("{}get_all/", TEST_PREFIX);
+    put(&format!("{}key1", prefix), "value1").await.unwrap();
+    put(&format!("{}key2", prefix), "value2").await.unwrap();
+    put(&format!("{}get_all_other", TEST_PREFIX), "other_value")
+        .await
+        .unwrap();
+
+    let mut kvs = get_all_with_prefix(&prefix).await.unwrap();
+    kvs.sort_by(|a, b| a.key.cmp(&b.key));
+    let pairs: Vec<_> = kvs
+        .iter()
+        .map(|kv| (kv.key.as_str(), kv.value.as_str()))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![
+            (format!("{}key1", prefix).as_str(), "value1"),
+            (format!("{}key2", prefix).as_str(), "value2"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_delete() {
+    use_in_memory_service().await;
+    let key = format!("{}/delete", TEST_KEY);
+    put(&key, TEST_VALUE).await.unwrap();
+
+    delete(&key).await.unwrap();
+    assert!(
+        get(&key).await.is_err(),
+        "Key should not exist after deletion"
+    );
+}
+
+#[tokio::test]
+async fn test_delete_all_with_prefix() {
+    use_in_memory_service().await;
+    let prefix = format!("{}delete_all/", TEST_PREFIX);
+    let other_key = format!("{}delete_all_other", TEST_PREFIX);
+    put(&format!("{}key1", prefix), "value1").await.unwrap();
+    put(&format!("{}key2", prefix), "value2").await.unwrap();
+    put(&other_key, "other_value").await.unwrap();
+
+    delete_all_with_prefix(&prefix).await.unwrap();
+    let remaining = get_all_with_prefix(&prefix).await.unwrap();
+    assert_eq!(remaining.len(), 0, "No keys should remain with the prefix");
+
+    // Keys outside the prefix are kept
+    assert_eq!(get(&other_key).await.unwrap(), "other_value");
+}
+
+#[tokio::test]
+async fn test_invalid_key_validation() {
+    use_in_memory_service().await;
+
+    // Test empty key
+    let result = put("", TEST_VALUE).await;
+    assert!(result.is_err(), "Empty key should be rejected");
+
+    // Test key with invalid characters
+    let result = put("key<with>invalid?chars{}", TEST_VALUE).await;
+    assert!(
+        result.is_err(),
+        "Key with invalid characters should be rejected"
+    );
+
+    // Test overly long key
+    let long_key = "a".repeat(2048);
+    let result = put(&long_key, TEST_VALUE).await;
+    assert!(result.is_err(), "Overly long key should be rejected");
+}
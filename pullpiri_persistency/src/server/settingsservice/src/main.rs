@@ -57,6 +57,11 @@ struct Args {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Identify this instance in the requests it sends, e.g. to the persistency service
+    common::identity::init(common::identity::ComponentIdentity::bootstrap(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+    ));
     let args = Args::parse();
 
     // Initialize logging
@@ -0,0 +1,249 @@
+//! Access log of the REST API
+//!
+//! Every request gets a request id, echoed in the `X-Request-Id` response
+//! header so that the dashboard can show it next to the data it loaded. A
+//! request id sent by the client is kept, so the same id shows up in the logs
+//! of every hop. Requests are logged as one JSON line each with method, path,
+//! status, latency and client IP, which lets a complaint like "data was stale
+//! at 14:03" be matched with what the server answered at that time.
+//!
+//! `ACCESS_LOG_LEVEL` selects what is logged: `off`, `error` (5xx answers),
+//! `warning` (4xx and 5xx), `info` (every request, the default) or `debug`
+//! (every request with query string and user agent). `ACCESS_LOG_SAMPLE_RATE`
+//! (0.0 to 1.0, default 1.0) logs only that share of the successful requests;
+//! failed requests are always logged.
+
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use warp::http::{HeaderValue, Method, StatusCode};
+use warp::path::FullPath;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+/// Environment variable selecting what is logged
+pub const ACCESS_LOG_LEVEL_ENV: &str = "ACCESS_LOG_LEVEL";
+/// Environment variable selecting the share of successful requests logged
+pub const ACCESS_LOG_SAMPLE_RATE_ENV: &str = "ACCESS_LOG_SAMPLE_RATE";
+/// Header carrying the request id
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request id taken over from a client
+const MAX_CLIENT_REQUEST_ID_LEN: usize = 64;
+
+/// Severity of a logged request, also the configured threshold
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Off,
+}
+
+impl Level {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warning" | "warn" => Some(Level::Warning),
+            "error" => Some(Level::Error),
+            "off" | "none" => Some(Level::Off),
+            _ => None,
+        }
+    }
+
+    /// Severity of an answer with `status`
+    fn of(status: StatusCode) -> Self {
+        if status.is_server_error() {
+            Level::Error
+        } else if status.is_client_error() {
+            Level::Warning
+        } else {
+            Level::Info
+        }
+    }
+}
+
+/// Request details known before the request is handled
+#[derive(Debug)]
+pub struct RequestInfo {
+    method: Method,
+    path: String,
+    query: String,
+    client_ip: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+}
+
+/// One line of the access log
+#[derive(Serialize)]
+struct AccessEntry<'a> {
+    timestamp: i64,
+    level: Level,
+    request_id: &'a str,
+    method: &'a str,
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<&'a str>,
+    status: u16,
+    latency_ms: f64,
+    client_ip: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_agent: Option<&'a str>,
+}
+
+/// Request id generation and the configured logging
+pub struct AccessLog {
+    level: Level,
+    sample_rate: f64,
+    /// Distinguishes the request ids of different runs of the app
+    run_id: u64,
+    requests: AtomicU64,
+    successes: AtomicU64,
+}
+
+impl AccessLog {
+    /// Create the access log with the level and sampling from the environment
+    pub fn from_env() -> Self {
+        let level = std::env::var(ACCESS_LOG_LEVEL_ENV).ok().and_then(|value| Level::parse(&value)).unwrap_or(Level::Info);
+        let sample_rate = std::env::var(ACCESS_LOG_SAMPLE_RATE_ENV)
+            .ok()
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|rate| rate.is_finite())
+            .map_or(1.0, |rate| rate.clamp(0.0, 1.0));
+        let run_id = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        Self { level, sample_rate, run_id, requests: AtomicU64::new(0), successes: AtomicU64::new(0) }
+    }
+
+    /// Human-readable configuration for the startup messages
+    pub fn describe(&self) -> String {
+        match self.level {
+            Level::Off => "off".to_string(),
+            level => format!("{:?}, {:.0}% of successful requests", level, self.sample_rate * 100.0).to_lowercase(),
+        }
+    }
+
+    /// Id of a request, the client's one if it sent a usable id
+    fn request_id(&self, client_id: Option<&str>) -> String {
+        let sequence = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        match client_id {
+            Some(id)
+                if !id.is_empty()
+                    && id.len() <= MAX_CLIENT_REQUEST_ID_LEN
+                    && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) =>
+            {
+                id.to_string()
+            }
+            _ => format!("{:x}-{:06}", self.run_id, sequence),
+        }
+    }
+
+    /// Whether a successful request is logged, the configured share of them
+    fn sampled(&self) -> bool {
+        let index = self.successes.fetch_add(1, Ordering::Relaxed) as f64;
+        // Spread the logged requests evenly instead of logging bursts
+        ((index + 1.0) * self.sample_rate).floor() > (index * self.sample_rate).floor()
+    }
+
+    fn record(&self, info: &RequestInfo, request_id: &str, status: StatusCode, started: Instant) {
+        let level = Level::of(status);
+        if self.level == Level::Off || level < self.level || (level == Level::Info && !self.sampled()) {
+            return;
+        }
+        let debug = self.level == Level::Debug;
+        let entry = AccessEntry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as i64),
+            level,
+            request_id,
+            method: info.method.as_str(),
+            path: &info.path,
+            query: (debug && !info.query.is_empty()).then_some(info.query.as_str()),
+            status: status.as_u16(),
+            latency_ms: (started.elapsed().as_secs_f64() * 1_000_000.0).round() / 1000.0,
+            client_ip: info.client_ip.as_deref(),
+            user_agent: if debug { info.user_agent.as_deref() } else { None },
+        };
+        if let Ok(line) = serde_json::to_string(&entry) {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Client address, the first hop of `X-Forwarded-For` behind a proxy
+fn client_ip(forwarded_for: Option<&str>, remote: Option<SocketAddr>) -> Option<String> {
+    forwarded_for
+        .and_then(|hops| hops.split(',').next())
+        .map(|hop| hop.trim().to_string())
+        .filter(|hop| !hop.is_empty())
+        .or_else(|| remote.map(|remote| remote.ip().to_string()))
+}
+
+fn request_info() -> impl Filter<Extract = (RequestInfo,), Error = Rejection> + Clone {
+    warp::method()
+        .and(warp::path::full())
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and(warp::addr::remote())
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .and(warp::header::optional::<String>("user-agent"))
+        .and(warp::header::optional::<String>(REQUEST_ID_HEADER))
+        .map(
+            |method: Method,
+             path: FullPath,
+             query: String,
+             remote: Option<SocketAddr>,
+             forwarded_for: Option<String>,
+             user_agent: Option<String>,
+             request_id: Option<String>| RequestInfo {
+                method,
+                path: path.as_str().to_string(),
+                query,
+                client_ip: client_ip(forwarded_for.as_deref(), remote),
+                user_agent,
+                request_id,
+            },
+        )
+}
+
+/// Answer of a request no route accepted, as warp answers it by default
+async fn rejection_response(rejection: Rejection) -> Result<Response, Infallible> {
+    let status = if rejection.is_not_found() {
+        StatusCode::NOT_FOUND
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        StatusCode::METHOD_NOT_ALLOWED
+    } else if rejection.find::<warp::reject::InvalidQuery>().is_some() {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    Ok(warp::reply::with_status(warp::reply(), status).into_response())
+}
+
+/// Log every request to `api` and echo its request id
+///
+/// ### Parameters
+/// * `api` - routes of the REST API
+/// * `log: Arc<AccessLog>` - request id generation and logging configuration
+pub fn wrap<F, T>(api: F, log: Arc<AccessLog>) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (T,), Error = Rejection> + Clone + Send + Sync + 'static,
+    T: Reply,
+{
+    let api = api.map(|reply: T| reply.into_response()).recover(rejection_response).unify();
+    warp::any().map(Instant::now).and(request_info()).and(api).map(
+        move |started: Instant, info: RequestInfo, mut response: Response| {
+            let request_id = log.request_id(info.request_id.as_deref());
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                response.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+            // Let the dashboard read the request id of cross-origin requests
+            response.headers_mut().insert("Access-Control-Expose-Headers", HeaderValue::from_static("X-Request-Id"));
+            log.record(&info, &request_id, response.status(), started);
+            response
+        },
+    )
+}
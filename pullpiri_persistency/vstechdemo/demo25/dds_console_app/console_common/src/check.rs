@@ -0,0 +1,104 @@
+//! Startup configuration validation
+//!
+//! `--check` validates the configuration of the app without starting the REST
+//! server or the DDS loop, prints a report and exits non-zero if any check
+//! failed, so deployment scripts can fail fast before the demo.
+
+use crate::AppConfig;
+use dust_dds::domain::domain_participant_factory::DomainParticipantFactory;
+use dust_dds::infrastructure::qos::{DataReaderQos, QosKind};
+use dust_dds::topic_definition::type_support::TypeSupport;
+use std::net::{TcpListener, ToSocketAddrs};
+
+/// Environment variable overriding the persistency service endpoint
+pub const PERSISTENCY_ENDPOINT_ENV: &str = "PERSISTENCY_ENDPOINT";
+/// Default persistency service endpoint
+pub const DEFAULT_PERSISTENCY_ENDPOINT: &str = "localhost:47007";
+
+/// Outcome of one check of a report
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Run every check of an app reading `T` with `reader_qos`, print the report and return whether all passed
+pub fn run<T: TypeSupport + 'static>(app: &AppConfig, reader_qos: DataReaderQos) -> bool {
+    let results = vec![
+        check_port("REST port", app.rest_port),
+        check_port("gRPC port", app.grpc_port),
+        check_dds::<T>(app, reader_qos),
+        check_persistency_endpoint(),
+    ];
+    report(&format!("{} configuration check", app.name), &results)
+}
+
+/// Print `results` under `title` and return whether all passed
+pub fn report(title: &str, results: &[CheckResult]) -> bool {
+    println!("{}", title);
+    for result in results {
+        let status = if result.passed { " OK " } else { "FAIL" };
+        println!("  [{}] {}: {}", status, result.name, result.detail);
+    }
+
+    let failed = results.iter().filter(|result| !result.passed).count();
+    if failed == 0 {
+        println!("✅ All {} checks passed", results.len());
+    } else {
+        println!("❌ {} of {} checks failed", failed, results.len());
+    }
+    failed == 0
+}
+
+fn check_port(name: &'static str, port: u16) -> CheckResult {
+    let (passed, detail) = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(_) => (true, format!("port {} is free", port)),
+        Err(e) => (false, format!("port {} is not available: {}", port, e)),
+    };
+    CheckResult { name, passed, detail }
+}
+
+/// Create a participant, topic and reader with the runtime QoS and tear them down again
+fn check_dds<T: TypeSupport + 'static>(app: &AppConfig, reader_qos: DataReaderQos) -> CheckResult {
+    let factory = DomainParticipantFactory::get_instance();
+    let participant = match factory.create_participant(app.domain_id, QosKind::Default, None, &[]) {
+        Ok(participant) => participant,
+        Err(e) => {
+            return CheckResult {
+                name: "DDS",
+                passed: false,
+                detail: format!("domain {} is not reachable: {:?}", app.domain_id, e),
+            };
+        }
+    };
+
+    let result = participant
+        .create_subscriber(QosKind::Default, None, &[])
+        .and_then(|subscriber| {
+            let topic = participant.create_topic::<T>(app.topic, app.topic, QosKind::Default, None, &[])?;
+            subscriber.create_datareader::<T>(&topic, QosKind::Specific(reader_qos), None, &[])
+        });
+
+    let (passed, detail) = match result {
+        Ok(_) => (true, format!("domain {} reachable, reader QoS for topic '{}' accepted", app.domain_id, app.topic)),
+        Err(e) => (false, format!("reader QoS for topic '{}' rejected: {:?}", app.topic, e)),
+    };
+
+    let _ = participant.delete_contained_entities();
+    let _ = factory.delete_participant(&participant);
+
+    CheckResult { name: "DDS", passed, detail }
+}
+
+fn check_persistency_endpoint() -> CheckResult {
+    let endpoint = std::env::var(PERSISTENCY_ENDPOINT_ENV)
+        .unwrap_or_else(|_| DEFAULT_PERSISTENCY_ENDPOINT.to_string());
+    let (passed, detail) = match endpoint.to_socket_addrs() {
+        Ok(mut addrs) => match addrs.next() {
+            Some(addr) => (true, format!("{} resolves to {}", endpoint, addr)),
+            None => (false, format!("{} resolves to no address", endpoint)),
+        },
+        Err(e) => (false, format!("{} cannot be resolved: {}", endpoint, e)),
+    };
+    CheckResult { name: "Persistency endpoint", passed, detail }
+}
@@ -0,0 +1,92 @@
+//! Message version compatibility checks
+//!
+//! mini-adas stamps every DDS message with the schema version of its type in
+//! a trailing `schema_version` field. While old and new agents are mixed
+//! during a rolling update, samples with a different version than this app
+//! was built for are logged and counted instead of being interpreted with a
+//! mismatched layout. Samples that cannot be decoded at all, e.g. from a
+//! publisher predating the version field, are counted as undecodable. The
+//! counters are served by `GET /compat`.
+
+use serde::Serialize;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// A mismatch is logged on its first occurrence and then every this many
+const LOG_EVERY: u64 = 100;
+
+/// A DDS message carrying the schema version of its publisher
+pub use vehicle_types::Versioned;
+
+/// Version check and counters of one subscribed topic
+pub struct VersionCheck {
+    topic: &'static str,
+    expected: u32,
+    accepted: AtomicU64,
+    mismatches: AtomicU64,
+    undecodable: AtomicU64,
+    last_mismatch: AtomicU32,
+}
+
+/// Counters of one topic as served by `GET /compat`
+#[derive(Debug, Serialize)]
+pub struct VersionReport {
+    pub topic: &'static str,
+    pub expected_version: u32,
+    pub accepted: u64,
+    pub mismatches: u64,
+    pub undecodable: u64,
+    /// Version of the last mismatching sample, 0 if there was none
+    pub last_mismatch_version: u32,
+}
+
+impl VersionCheck {
+    pub const fn new(topic: &'static str, expected: u32) -> Self {
+        Self {
+            topic,
+            expected,
+            accepted: AtomicU64::new(0),
+            mismatches: AtomicU64::new(0),
+            undecodable: AtomicU64::new(0),
+            last_mismatch: AtomicU32::new(0),
+        }
+    }
+
+    /// Pass a decoded sample on if its schema version matches, count it otherwise
+    pub fn check<T: Versioned, E: Debug>(&self, sample: Result<T, E>) -> Option<T> {
+        match sample {
+            Ok(data) if data.schema_version() == self.expected => {
+                self.accepted.fetch_add(1, Ordering::Relaxed);
+                Some(data)
+            }
+            Ok(data) => {
+                let count = self.mismatches.fetch_add(1, Ordering::Relaxed) + 1;
+                self.last_mismatch.store(data.schema_version(), Ordering::Relaxed);
+                if count % LOG_EVERY == 1 {
+                    println!("⚠️  {} sample with schema version {} ignored, expected {} ({} mismatches so far)",
+                        self.topic, data.schema_version(), self.expected, count);
+                }
+                None
+            }
+            Err(e) => {
+                let count = self.undecodable.fetch_add(1, Ordering::Relaxed) + 1;
+                if count % LOG_EVERY == 1 {
+                    println!("⚠️  {} sample could not be decoded, publisher may use an incompatible schema: {:?} ({} so far)",
+                        self.topic, e, count);
+                }
+                None
+            }
+        }
+    }
+
+    pub fn report(&self) -> VersionReport {
+        VersionReport {
+            topic: self.topic,
+            expected_version: self.expected,
+            accepted: self.accepted.load(Ordering::Relaxed),
+            mismatches: self.mismatches.load(Ordering::Relaxed),
+            undecodable: self.undecodable.load(Ordering::Relaxed),
+            last_mismatch_version: self.last_mismatch.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Event feed served by `GET /events`
+//!
+//! All mini-adas activities publish structured events on the `EventLog` topic:
+//! component, severity, a stable code, a message and a context map. The app
+//! keeps the most recent events of the whole pipeline in memory, so that the
+//! dashboard reads a single machine-readable feed instead of the text logs of
+//! every process.
+//!
+//! `GET /events` returns the newest matching events, oldest first. Filters:
+//! `since` (Unix time in milliseconds), `min_severity` (`debug`, `info`,
+//! `warning`, `error`, `critical`), `component`, `code` and `limit`.
+
+use crate::compat::Versioned;
+use dust_dds::topic_definition::type_support::DdsType;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Environment variable overriding the number of events kept in memory
+pub const EVENTS_CAPACITY_ENV: &str = "EVENTS_CAPACITY";
+
+/// Events kept when the environment does not set a capacity
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+/// Events returned by `/events` when no limit is given
+pub const DEFAULT_QUERY_LIMIT: usize = 200;
+
+/// Events mini-adas keeps for late joiners, and read per wake-up
+pub const EVENTS_HISTORY_DEPTH: i32 = 50;
+
+/// Key and value of the context of an event
+#[derive(DdsType, Clone, Debug, Serialize, Deserialize)]
+pub struct EventContext {
+    pub key: String,
+    pub value: String,
+}
+
+/// Notable event of a mini-adas activity, served by `GET /events`
+#[derive(DdsType, Clone, Debug, Serialize, Deserialize)]
+pub struct EventLog {
+    pub component: String,
+    pub severity: String,
+    pub code: String,
+    pub message: String,
+    pub context: Vec<EventContext>,
+    pub sequence: u64,
+    pub timestamp: i64,
+    pub schema_version: u32,
+}
+
+impl Versioned for EventLog {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+/// Query parameters of `GET /events`
+#[derive(Debug, Default, Deserialize)]
+pub struct EventQuery {
+    /// Only events with a timestamp at or after this Unix time in milliseconds
+    pub since: Option<i64>,
+    /// Only events at least this severe
+    pub min_severity: Option<String>,
+    /// Only events of this activity, e.g. `CarModeCalculator`
+    pub component: Option<String>,
+    /// Only events with this code, e.g. `MODE_CHANGED`
+    pub code: Option<String>,
+    /// Return at most this many of the newest matching events
+    pub limit: Option<usize>,
+}
+
+/// An event as served, with its context as a JSON object
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EventEntry {
+    pub component: String,
+    pub severity: String,
+    pub code: String,
+    pub message: String,
+    pub context: BTreeMap<String, String>,
+    pub sequence: u64,
+    pub timestamp: i64,
+}
+
+impl From<&EventLog> for EventEntry {
+    fn from(event: &EventLog) -> Self {
+        Self {
+            component: event.component.clone(),
+            severity: event.severity.clone(),
+            code: event.code.clone(),
+            message: event.message.clone(),
+            context: event.context.iter().map(|entry| (entry.key.clone(), entry.value.clone())).collect(),
+            sequence: event.sequence,
+            timestamp: event.timestamp,
+        }
+    }
+}
+
+/// Rank of a severity for `min_severity`, unknown severities rank lowest
+pub fn severity_rank(severity: &str) -> u8 {
+    match severity {
+        "info" => 1,
+        "warning" => 2,
+        "error" => 3,
+        "critical" => 4,
+        _ => 0,
+    }
+}
+
+/// Recent events of all components, in the order received
+pub struct EventFeed {
+    capacity: usize,
+    events: VecDeque<EventEntry>,
+}
+
+impl EventFeed {
+    /// Create the feed with the capacity from the environment
+    pub fn from_env() -> Self {
+        let capacity = std::env::var(EVENTS_CAPACITY_ENV)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|capacity| *capacity > 0)
+            .unwrap_or(DEFAULT_CAPACITY);
+        Self { capacity, events: VecDeque::new() }
+    }
+
+    /// Record an event, returning `false` if it was already received
+    ///
+    /// Late joiners receive the recent events of every publisher again, so
+    /// events are recognized by component, sequence and timestamp.
+    pub fn push(&mut self, event: &EventLog) -> bool {
+        let duplicate = self.events.iter().any(|known| {
+            known.sequence == event.sequence && known.timestamp == event.timestamp && known.component == event.component
+        });
+        if duplicate {
+            return false;
+        }
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(EventEntry::from(event));
+        true
+    }
+
+    /// Newest events matching the query, oldest first
+    pub fn query(&self, query: &EventQuery) -> Vec<EventEntry> {
+        let min_rank = query.min_severity.as_deref().map(severity_rank).unwrap_or_default();
+        let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+        let mut events: Vec<EventEntry> = self
+            .events
+            .iter()
+            .rev()
+            .filter(|event| query.since.is_none_or(|since| event.timestamp >= since))
+            .filter(|event| severity_rank(&event.severity) >= min_rank)
+            .filter(|event| query.component.as_ref().is_none_or(|component| &event.component == component))
+            .filter(|event| query.code.as_ref().is_none_or(|code| &event.code == code))
+            .take(limit)
+            .cloned()
+            .collect();
+        events.reverse();
+        events
+    }
+}
@@ -0,0 +1,75 @@
+//! gRPC exposition of the data served over REST
+//!
+//! In-vehicle Rust components consume the latest sample, the history and a
+//! live stream of samples through the console services of `common::console`
+//! instead of parsing the REST API's JSON. Every app implements its service
+//! on a [`ConsoleState`], which reads the same shared state as the REST API,
+//! so both APIs always agree.
+
+use crate::history::{History, Timestamped, TimeWindow};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::Status;
+
+/// Samples buffered per streaming client; slower clients skip the oldest
+pub const STREAM_CAPACITY: usize = 256;
+
+/// Stream of the sample messages `S` of a console service
+pub type SampleStream<S> = Pin<Box<dyn Stream<Item = Result<S, Status>> + Send>>;
+
+/// State shared with the REST API, served as the gRPC messages `S` of the samples `T`
+pub struct ConsoleState<T, S> {
+    latest: Arc<Mutex<Option<T>>>,
+    history: Arc<Mutex<History<T>>>,
+    samples: broadcast::Sender<T>,
+    to_sample: fn(&T) -> S,
+}
+
+impl<T, S> ConsoleState<T, S>
+where
+    T: Timestamped + Serialize + DeserializeOwned + Clone + Send + 'static,
+    S: Send + 'static,
+{
+    /// ### Parameters
+    /// * `latest` - latest sample, as served by `GET /data`
+    /// * `history` - recorded samples, as served by `GET /history`
+    /// * `samples` - every sample received from DDS
+    /// * `to_sample` - gRPC message of a sample
+    pub fn new(
+        latest: Arc<Mutex<Option<T>>>,
+        history: Arc<Mutex<History<T>>>,
+        samples: broadcast::Sender<T>,
+        to_sample: fn(&T) -> S,
+    ) -> Self {
+        Self { latest, history, samples, to_sample }
+    }
+
+    /// Latest sample, `None` before the first sample
+    pub fn latest(&self) -> Option<S> {
+        self.latest.lock().unwrap().as_ref().map(self.to_sample)
+    }
+
+    /// Newest samples since `since`, oldest first
+    pub fn history(&self, since: Option<i64>, limit: Option<u32>) -> Vec<S> {
+        let window = TimeWindow { from: since, to: None };
+        let limit = limit.map(|limit| limit as usize);
+        self.history.lock().unwrap().query(window, limit).iter().map(self.to_sample).collect()
+    }
+
+    /// Every sample from now on, preceded by the latest one if `include_latest` is set
+    pub fn stream(&self, include_latest: bool) -> SampleStream<S> {
+        // Subscribe before reading the latest sample so that nothing is missed in between
+        let receiver = self.samples.subscribe();
+        let latest = if include_latest { self.latest() } else { None };
+
+        // A lagging client skips the samples it missed instead of being disconnected
+        let to_sample = self.to_sample;
+        let live = BroadcastStream::new(receiver).filter_map(move |sample| sample.ok().map(|data| to_sample(&data)));
+        Box::pin(tokio_stream::iter(latest).chain(live).map(Ok))
+    }
+}
@@ -0,0 +1,207 @@
+//! Readiness document served by `GET /health`
+//!
+//! Container orchestrators and the demo pre-flight checklist poll this one
+//! endpoint instead of piecing the state of the app together from `/data`,
+//! its other endpoints and the console output. Every component reports `ok`,
+//! `degraded` or `down` and the overall status is the worst of them. The
+//! response is `200 OK` unless a component is down, then it is
+//! `503 Service Unavailable`, so the endpoint can serve as readiness probe.
+//! Next to the components every app has, an app reports its own
+//! [`Components`].
+//!
+//! A sample older than `HEALTH_MAX_SAMPLE_AGE_MS` only degrades the DDS
+//! status: mini-adas publishes the data of a driving mode in that mode only,
+//! so a stale sample does not make the app unready. `0` disables the check,
+//! without the variable an app checks the age it was built with, if any.
+
+use crate::AppConfig;
+use crate::history::HistoryStatus;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicI64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable overriding the sample age that degrades the DDS status
+pub const HEALTH_MAX_SAMPLE_AGE_ENV: &str = "HEALTH_MAX_SAMPLE_AGE_MS";
+
+/// Status of a component, ordered from best to worst
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Degraded,
+    Down,
+}
+
+/// Components of an app reported next to the ones every app has
+pub trait Components: Serialize {
+    /// Worst status of the components
+    fn status(&self) -> Status;
+}
+
+/// No components of its own
+impl Components for () {
+    fn status(&self) -> Status {
+        Status::Ok
+    }
+}
+
+/// Response of `GET /health`
+#[derive(Debug, Serialize)]
+pub struct HealthReport<C> {
+    /// Worst status of the components
+    pub status: Status,
+    /// Unix timestamp of the report in milliseconds
+    pub timestamp: i64,
+    pub dds: DdsHealth,
+    pub rest: ServerHealth,
+    pub grpc: ServerHealth,
+    /// Components of the app
+    #[serde(flatten)]
+    pub components: C,
+    pub history: HistoryHealth,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DdsHealth {
+    pub status: Status,
+    pub domain_id: i32,
+    pub topic: &'static str,
+    pub participant_alive: bool,
+    pub matched_publishers: i32,
+    /// Time since the last sample was received, `None` before the first sample
+    pub last_sample_age_ms: Option<i64>,
+    /// Sample age that degrades the status, `None` if not checked
+    pub max_sample_age_ms: Option<i64>,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServerHealth {
+    pub status: Status,
+    pub port: u16,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryHealth {
+    pub status: Status,
+    #[serde(flatten)]
+    pub history: HistoryStatus,
+    pub detail: String,
+}
+
+/// State of the app, updated by the DDS loop and the API servers
+pub struct Health {
+    app: AppConfig,
+    max_sample_age_ms: Option<i64>,
+    participant_alive: AtomicBool,
+    matched_publishers: AtomicI32,
+    /// Unix time in milliseconds the last sample was received, 0 before the first sample
+    last_sample_ms: AtomicI64,
+    grpc_stopped: AtomicBool,
+}
+
+impl Health {
+    /// ### Parameters
+    /// * `app` - the app reporting
+    /// * `default_max_sample_age_ms` - sample age that degrades the DDS status unless overridden, `None` to not check it
+    pub fn from_env(app: AppConfig, default_max_sample_age_ms: Option<i64>) -> Self {
+        let max_sample_age_ms = match std::env::var(HEALTH_MAX_SAMPLE_AGE_ENV).ok().and_then(|value| value.parse::<i64>().ok()) {
+            Some(max_sample_age_ms) => (max_sample_age_ms > 0).then_some(max_sample_age_ms),
+            None => default_max_sample_age_ms,
+        };
+        Self {
+            app,
+            max_sample_age_ms,
+            participant_alive: AtomicBool::new(false),
+            matched_publishers: AtomicI32::new(0),
+            last_sample_ms: AtomicI64::new(0),
+            grpc_stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// The DDS participant and the readers are created
+    pub fn set_participant_alive(&self) {
+        self.participant_alive.store(true, Ordering::Relaxed);
+    }
+
+    pub fn set_matched_publishers(&self, count: i32) {
+        self.matched_publishers.store(count, Ordering::Relaxed);
+    }
+
+    pub fn sample_received(&self) {
+        self.last_sample_ms.store(now_ms(), Ordering::Relaxed);
+    }
+
+    /// The gRPC server stopped, e.g. because its port is taken
+    pub fn set_grpc_stopped(&self) {
+        self.grpc_stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn report<C: Components>(&self, history: HistoryStatus, components: C) -> HealthReport<C> {
+        let now_ms = now_ms();
+        let dds = self.dds(now_ms);
+        let rest = ServerHealth { status: Status::Ok, port: self.app.rest_port, detail: "serving".to_string() };
+        let grpc = if self.grpc_stopped.load(Ordering::Relaxed) {
+            ServerHealth { status: Status::Degraded, port: self.app.grpc_port, detail: "stopped, see the console output".to_string() }
+        } else {
+            ServerHealth { status: Status::Ok, port: self.app.grpc_port, detail: "serving".to_string() }
+        };
+        let history = history_health(history);
+
+        HealthReport {
+            status: dds.status.max(rest.status).max(grpc.status).max(components.status()).max(history.status),
+            timestamp: now_ms,
+            dds,
+            rest,
+            grpc,
+            components,
+            history,
+        }
+    }
+
+    fn dds(&self, now_ms: i64) -> DdsHealth {
+        let participant_alive = self.participant_alive.load(Ordering::Relaxed);
+        let matched_publishers = self.matched_publishers.load(Ordering::Relaxed);
+        let last_sample_ms = self.last_sample_ms.load(Ordering::Relaxed);
+        let last_sample_age_ms = (last_sample_ms > 0).then(|| now_ms - last_sample_ms);
+
+        let (status, detail) = if !participant_alive {
+            (Status::Down, format!("no participant in domain {}", self.app.domain_id))
+        } else if matched_publishers <= 0 {
+            (Status::Degraded, format!("no publisher matched on topic '{}'", self.app.topic))
+        } else {
+            match (last_sample_age_ms, self.max_sample_age_ms) {
+                (None, Some(_)) => (Status::Degraded, "no sample received yet".to_string()),
+                (Some(age), Some(max)) if age > max => {
+                    (Status::Degraded, format!("last sample {:.1}s ago, more than {:.1}s", age as f64 / 1000.0, max as f64 / 1000.0))
+                }
+                _ => (Status::Ok, format!("{} publisher(s) matched", matched_publishers)),
+            }
+        };
+
+        DdsHealth {
+            status,
+            domain_id: self.app.domain_id,
+            topic: self.app.topic,
+            participant_alive,
+            matched_publishers,
+            last_sample_age_ms,
+            max_sample_age_ms: self.max_sample_age_ms,
+            detail,
+        }
+    }
+}
+
+fn history_health(history: HistoryStatus) -> HistoryHealth {
+    let (status, detail) = match &history.file {
+        Some(file) if !history.file_open => (Status::Degraded, format!("history file {} cannot be written, keeping history in memory only", file)),
+        Some(file) => (Status::Ok, format!("{} samples in memory, appending to {}", history.samples, file)),
+        None => (Status::Ok, format!("{} samples in memory", history.samples)),
+    };
+    HistoryHealth { status, history, detail }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
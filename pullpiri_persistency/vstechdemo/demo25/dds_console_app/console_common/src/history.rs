@@ -0,0 +1,465 @@
+//! Sample history served by `GET /history` and `GET /stats`
+//!
+//! Recent samples are kept in an in-memory ring for hot queries. When
+//! `HISTORY_FILE` is set, every sample is also appended to that file as one
+//! JSON object per line, so `/history` can cover hours of demo time and
+//! survives restarts of the app. The file is compacted to the configured
+//! retention: samples older than `HISTORY_RETENTION_SECS` are dropped, and the
+//! oldest samples are dropped while the file exceeds `HISTORY_MAX_BYTES`.
+//!
+//! Both endpoints take a time window: `from` and `to` (Unix time in
+//! milliseconds, `since` is an alias of `from`) or `last` relative to now,
+//! e.g. `last=5m`. Samples arrive in time order, so the window is found by
+//! binary search in the ring. The file keeps a sparse index of the byte
+//! offset of every [`INDEX_STRIDE`]th sample, so a window far back only reads
+//! the lines from the nearest indexed sample on.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{vec_deque, BTreeMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use vehicle_types::{AutonomousCarData, EmergencyModeData};
+
+/// Environment variable enabling the disk-backed history
+pub const HISTORY_FILE_ENV: &str = "HISTORY_FILE";
+/// Environment variable overriding the retention time of the history file
+pub const HISTORY_RETENTION_ENV: &str = "HISTORY_RETENTION_SECS";
+/// Environment variable overriding the size limit of the history file
+pub const HISTORY_MAX_BYTES_ENV: &str = "HISTORY_MAX_BYTES";
+/// Environment variable overriding the number of samples kept in memory
+pub const HISTORY_CAPACITY_ENV: &str = "HISTORY_CAPACITY";
+
+/// Samples returned by `/history` when no limit is given
+pub const DEFAULT_QUERY_LIMIT: usize = 500;
+
+/// Samples of the history file between two entries of its index
+pub const INDEX_STRIDE: usize = 256;
+
+/// How often the history file is checked against the retention time
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A sample carrying its own Unix timestamp in milliseconds
+pub trait Timestamped {
+    fn timestamp_ms(&self) -> i64;
+}
+
+impl Timestamped for AutonomousCarData {
+    fn timestamp_ms(&self) -> i64 {
+        self.timestamp
+    }
+}
+
+impl Timestamped for EmergencyModeData {
+    fn timestamp_ms(&self) -> i64 {
+        self.timestamp
+    }
+}
+
+/// History configuration, read from the environment
+#[derive(Clone, Debug)]
+pub struct HistoryConfig {
+    /// Number of samples kept in memory
+    pub capacity: usize,
+    /// Append-only history file, `None` for an in-memory history only
+    pub file: Option<PathBuf>,
+    /// Samples older than this are dropped from the file
+    pub retention: Duration,
+    /// Size limit of the file
+    pub max_bytes: u64,
+}
+
+impl HistoryConfig {
+    pub fn from_env() -> Self {
+        fn parse<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+        }
+
+        Self {
+            capacity: parse(HISTORY_CAPACITY_ENV, 600),
+            file: std::env::var(HISTORY_FILE_ENV).ok().filter(|path| !path.is_empty()).map(PathBuf::from),
+            retention: Duration::from_secs(parse(HISTORY_RETENTION_ENV, 4 * 60 * 60)),
+            max_bytes: parse(HISTORY_MAX_BYTES_ENV, 64 * 1024 * 1024),
+        }
+    }
+}
+
+/// Query parameters of `GET /history` and `GET /stats`
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryQuery {
+    /// Only samples with a timestamp at or after this Unix time in milliseconds
+    #[serde(alias = "since")]
+    pub from: Option<i64>,
+    /// Only samples with a timestamp at or before this Unix time in milliseconds
+    pub to: Option<i64>,
+    /// Only samples of this period up to now, e.g. `90s`, `5m`, `2h` or `1d`; replaces `from`
+    pub last: Option<String>,
+    /// Return at most this many of the newest matching samples, `/history` only
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    /// Time window selected by the query
+    pub fn window(&self) -> Result<TimeWindow, String> {
+        let from = match &self.last {
+            Some(last) => Some(now_ms() - parse_period(last)?.as_millis() as i64),
+            None => self.from,
+        };
+        let window = TimeWindow { from, to: self.to };
+        if let (Some(from), Some(to)) = (window.from, window.to)
+            && from > to
+        {
+            return Err(format!("Window starts at {} after its end at {}", from, to));
+        }
+        Ok(window)
+    }
+}
+
+/// Period of a relative window: a number with the unit `s`, `m`, `h` or `d`, seconds without unit
+pub fn parse_period(period: &str) -> Result<Duration, String> {
+    let period = period.trim();
+    let (number, unit_secs) = match period.char_indices().last() {
+        Some((index, 's')) => (&period[..index], 1),
+        Some((index, 'm')) => (&period[..index], 60),
+        Some((index, 'h')) => (&period[..index], 60 * 60),
+        Some((index, 'd')) => (&period[..index], 24 * 60 * 60),
+        _ => (period, 1),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid period '{}', expected e.g. 90s, 5m, 2h or 1d", period))?;
+    Ok(Duration::from_secs(number.saturating_mul(unit_secs)))
+}
+
+/// Time window of a query, both ends inclusive and optional
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeWindow {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+impl TimeWindow {
+    fn is_before(&self, timestamp: i64) -> bool {
+        self.from.is_some_and(|from| timestamp < from)
+    }
+
+    fn is_after(&self, timestamp: i64) -> bool {
+        self.to.is_some_and(|to| timestamp > to)
+    }
+}
+
+/// Minimum, maximum and mean of one numeric field
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+/// Statistics of the samples of a window, served by `GET /stats`
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct HistoryStats {
+    pub samples: usize,
+    /// Timestamp of the oldest sample of the window
+    pub first_ms: Option<i64>,
+    /// Timestamp of the newest sample of the window
+    pub last_ms: Option<i64>,
+    /// Statistics of every numeric field of the samples, by field name
+    pub fields: BTreeMap<String, FieldStats>,
+}
+
+/// State of the history reported by `GET /health`
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryStatus {
+    /// Samples held in memory
+    pub samples: usize,
+    pub capacity: usize,
+    /// Configured history file
+    pub file: Option<String>,
+    /// Whether samples are appended to the history file
+    pub file_open: bool,
+    pub file_bytes: u64,
+}
+
+/// In-memory ring of recent samples, optionally backed by a history file
+pub struct History<T> {
+    config: HistoryConfig,
+    ring: VecDeque<T>,
+    file: Option<File>,
+    file_bytes: u64,
+    /// Timestamp and byte offset of every [`INDEX_STRIDE`]th sample of the file
+    index: Vec<(i64, u64)>,
+    /// Samples of the file since its last index entry
+    unindexed: usize,
+    last_compaction: Instant,
+}
+
+impl<T: Timestamped + Serialize + DeserializeOwned + Clone> History<T> {
+    /// Create the history and load the retained samples of the history file
+    pub fn open(config: HistoryConfig) -> Self {
+        let mut history = Self {
+            ring: VecDeque::with_capacity(config.capacity),
+            file: None,
+            file_bytes: 0,
+            index: Vec::new(),
+            unindexed: 0,
+            last_compaction: Instant::now(),
+            config,
+        };
+
+        if let Some(path) = history.config.file.clone() {
+            history.compact();
+            for sample in history.read_file(TimeWindow::default()) {
+                history.push_ring(sample);
+            }
+            match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => {
+                    println!("📜 History file {} loaded with {} samples", path.display(), history.ring.len());
+                    history.file = Some(file);
+                }
+                Err(e) => println!("⚠️  History file {} cannot be opened, keeping history in memory only: {}", path.display(), e),
+            }
+        }
+        history
+    }
+
+    /// Record a new sample
+    pub fn push(&mut self, sample: T) {
+        if let Some(file) = self.file.as_mut() {
+            match serde_json::to_string(&sample) {
+                Ok(mut line) => {
+                    line.push('\n');
+                    match file.write_all(line.as_bytes()) {
+                        Ok(()) => {
+                            let offset = self.file_bytes;
+                            self.file_bytes += line.len() as u64;
+                            self.index_line(sample.timestamp_ms(), offset);
+                        }
+                        Err(e) => println!("⚠️  Failed to append to history file: {}", e),
+                    }
+                }
+                Err(e) => println!("⚠️  Failed to serialize history sample: {}", e),
+            }
+
+            if self.file_bytes > self.config.max_bytes || self.last_compaction.elapsed() >= COMPACTION_INTERVAL {
+                self.compact();
+            }
+        }
+        self.push_ring(sample);
+    }
+
+    /// Newest samples of the window, oldest first
+    ///
+    /// Queries reaching further back than the in-memory ring are answered
+    /// from the history file.
+    pub fn query(&self, window: TimeWindow, limit: Option<usize>) -> Vec<T> {
+        let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+        let ring = self.ring_window(window);
+        let ring_covers_query = self.ring_covers(window)
+            // Without `from` the newest samples are wanted, the ring has them unless it holds fewer than the limit
+            || (window.from.is_none() && ring.len() >= limit);
+
+        let samples: Vec<T> = if ring_covers_query {
+            ring.cloned().collect()
+        } else {
+            self.read_file(window)
+        };
+
+        let skip = samples.len().saturating_sub(limit);
+        samples.into_iter().skip(skip).collect()
+    }
+
+    /// Statistics of all samples of the window
+    pub fn stats(&self, window: TimeWindow) -> HistoryStats {
+        let samples: Vec<T> = if self.ring_covers(window) {
+            self.ring_window(window).cloned().collect()
+        } else {
+            self.read_file(window)
+        };
+
+        let mut sums: BTreeMap<String, (f64, f64, f64, usize)> = BTreeMap::new();
+        for sample in &samples {
+            let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(sample) else {
+                continue;
+            };
+            for (name, value) in fields {
+                let Some(value) = value.as_f64().filter(|_| name != "timestamp") else {
+                    continue;
+                };
+                let (min, max, sum, count) = sums.entry(name).or_insert((f64::INFINITY, f64::NEG_INFINITY, 0.0, 0));
+                *min = min.min(value);
+                *max = max.max(value);
+                *sum += value;
+                *count += 1;
+            }
+        }
+
+        HistoryStats {
+            samples: samples.len(),
+            first_ms: samples.first().map(T::timestamp_ms),
+            last_ms: samples.last().map(T::timestamp_ms),
+            fields: sums
+                .into_iter()
+                .map(|(name, (min, max, sum, count))| (name, FieldStats { min, max, mean: sum / count as f64 }))
+                .collect(),
+        }
+    }
+
+    pub fn status(&self) -> HistoryStatus {
+        HistoryStatus {
+            samples: self.ring.len(),
+            capacity: self.config.capacity,
+            file: self.config.file.as_ref().map(|path| path.display().to_string()),
+            file_open: self.file.is_some(),
+            file_bytes: self.file_bytes,
+        }
+    }
+
+    fn push_ring(&mut self, sample: T) {
+        if self.ring.len() >= self.config.capacity {
+            self.ring.pop_front();
+        }
+        if self.config.capacity > 0 {
+            self.ring.push_back(sample);
+        }
+    }
+
+    /// Samples of the ring within the window
+    fn ring_window(&self, window: TimeWindow) -> vec_deque::Iter<'_, T> {
+        let start = self.ring.partition_point(|sample| window.is_before(sample.timestamp_ms()));
+        let end = self.ring.partition_point(|sample| !window.is_after(sample.timestamp_ms()));
+        self.ring.range(start..end.max(start))
+    }
+
+    /// Whether the ring holds every sample of the window
+    fn ring_covers(&self, window: TimeWindow) -> bool {
+        // Without a file the ring holds everything there is, even if the window reaches further back
+        self.file.is_none()
+            || match (window.from, self.ring.front()) {
+                (Some(from), Some(oldest)) => from >= oldest.timestamp_ms(),
+                _ => false,
+            }
+    }
+
+    /// Account a line appended to the file at `offset` in the index
+    fn index_line(&mut self, timestamp: i64, offset: u64) {
+        if self.unindexed == 0 {
+            self.index.push((timestamp, offset));
+        }
+        self.unindexed = (self.unindexed + 1) % INDEX_STRIDE;
+    }
+
+    /// Samples of the history file within the window, oldest first
+    fn read_file(&self, window: TimeWindow) -> Vec<T> {
+        let Some(path) = &self.config.file else {
+            return Vec::new();
+        };
+        let Ok(mut file) = File::open(path) else {
+            return Vec::new();
+        };
+
+        // Start at the last indexed sample before the window, the samples in between are skipped below
+        let indexed = self.index.partition_point(|(timestamp, _)| window.is_before(*timestamp));
+        let offset = indexed.checked_sub(1).map_or(0, |entry| self.index[entry].1);
+        if file.seek(SeekFrom::Start(offset)).is_err() {
+            return Vec::new();
+        }
+
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<T>(&line).ok())
+            .skip_while(|sample| window.is_before(sample.timestamp_ms()))
+            .take_while(|sample| !window.is_after(sample.timestamp_ms()))
+            .collect()
+    }
+
+    /// Rebuild the index and size of the history file from its content
+    fn reindex(&mut self) {
+        self.index.clear();
+        self.unindexed = 0;
+        self.file_bytes = 0;
+        let Some(path) = &self.config.file else {
+            return;
+        };
+        let Ok(file) = File::open(path) else {
+            return;
+        };
+
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        while let Ok(read) = reader.read_line(&mut line) {
+            if read == 0 {
+                break;
+            }
+            let offset = self.file_bytes;
+            self.file_bytes += read as u64;
+            if let Ok(sample) = serde_json::from_str::<T>(&line) {
+                self.index_line(sample.timestamp_ms(), offset);
+            }
+            line.clear();
+        }
+    }
+
+    /// Rewrite the history file keeping only the samples within retention
+    fn compact(&mut self) {
+        self.last_compaction = Instant::now();
+        let Some(path) = self.config.file.clone() else {
+            return;
+        };
+
+        let cutoff = now_ms() - self.config.retention.as_millis() as i64;
+        let lines: Vec<(i64, String)> = self
+            .read_file(TimeWindow { from: Some(cutoff), to: None })
+            .iter()
+            .filter_map(|sample| Some((sample.timestamp_ms(), serde_json::to_string(sample).ok()?)))
+            .collect();
+
+        // Keep the newest samples within three quarters of the size limit, so compaction does not run on every push
+        let budget = self.config.max_bytes / 4 * 3;
+        let mut kept_bytes = 0;
+        let keep = lines
+            .iter()
+            .rev()
+            .take_while(|(_, line)| {
+                kept_bytes += line.len() as u64 + 1;
+                kept_bytes <= budget
+            })
+            .count();
+        let lines = &lines[lines.len() - keep..];
+
+        let tmp_path = path.with_extension("tmp");
+        let written = File::create(&tmp_path).and_then(|mut tmp| {
+            for (_, line) in lines {
+                writeln!(tmp, "{}", line)?;
+            }
+            tmp.sync_all()?;
+            std::fs::rename(&tmp_path, &path)
+        });
+        if let Err(e) = written {
+            println!("⚠️  Failed to compact history file {}: {}", path.display(), e);
+            // The file is unchanged, but the index may not cover it yet
+            self.reindex();
+            return;
+        }
+
+        self.index.clear();
+        self.unindexed = 0;
+        self.file_bytes = 0;
+        for (timestamp, line) in lines {
+            let offset = self.file_bytes;
+            self.file_bytes += line.len() as u64 + 1;
+            self.index_line(*timestamp, offset);
+        }
+        if self.file.is_some() {
+            // The old handle points to the replaced file
+            self.file = OpenOptions::new().append(true).open(&path).ok();
+        }
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
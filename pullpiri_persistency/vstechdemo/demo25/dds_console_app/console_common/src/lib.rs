@@ -0,0 +1,29 @@
+//! Building blocks shared by the DDS console apps
+//!
+//! The autonomous and emergency console apps serve the samples of their DDS
+//! topic the same way and only differ in the data type they subscribe to.
+//! What does not depend on that type lives in this crate instead of being
+//! copied into every app.
+
+pub mod access_log;
+pub mod check;
+pub mod compat;
+pub mod events;
+pub mod grpc;
+pub mod health;
+pub mod history;
+
+/// Where a console app reads its samples from and serves them
+#[derive(Clone, Copy, Debug)]
+pub struct AppConfig {
+    /// Name of the app in reports, e.g. `Autonomous app`
+    pub name: &'static str,
+    /// DDS domain shared with mini-adas
+    pub domain_id: i32,
+    /// DDS topic and type name of the samples
+    pub topic: &'static str,
+    /// Port of the REST API
+    pub rest_port: u16,
+    /// Port of the gRPC API serving the same data
+    pub grpc_port: u16,
+}
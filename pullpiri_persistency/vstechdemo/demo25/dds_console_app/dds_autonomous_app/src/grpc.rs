@@ -0,0 +1,62 @@
+//! gRPC exposition of the data served over REST
+//!
+//! The `AutonomousConsole` service of `common::console`, see `console_common::grpc`.
+
+use crate::AutonomousCarData;
+use common::console::autonomous_console_server::{AutonomousConsole, AutonomousConsoleServer};
+use common::console::{AutonomousHistory, AutonomousSample, HistoryRequest, LatestRequest, StreamRequest};
+use console_common::grpc::{ConsoleState, SampleStream};
+use tonic::{Request, Response, Status};
+
+/// gRPC message of a sample
+pub(crate) fn to_sample(data: &AutonomousCarData) -> AutonomousSample {
+    AutonomousSample {
+        vehicle_speed: data.vehicle_speed,
+        lane_position: data.lane_position,
+        obstacle_detected: data.obstacle_detected,
+        obstacle_distance: data.obstacle_distance,
+        traffic_signal: data.traffic_signal.clone(),
+        steering_angle: data.steering_angle,
+        brake_force: data.brake_force,
+        acceleration: data.acceleration,
+        weather_condition: data.weather_condition.clone(),
+        road_condition: data.road_condition.clone(),
+        timestamp: data.timestamp,
+        is_valid: data.is_valid,
+        speed_limit: data.speed_limit,
+        speed_limit_violations: data.speed_limit_violations,
+    }
+}
+
+/// `AutonomousConsole` service on the state shared with the REST API
+pub struct ConsoleService {
+    state: ConsoleState<AutonomousCarData, AutonomousSample>,
+}
+
+impl ConsoleService {
+    pub fn new(state: ConsoleState<AutonomousCarData, AutonomousSample>) -> AutonomousConsoleServer<Self> {
+        AutonomousConsoleServer::new(Self { state })
+    }
+}
+
+#[tonic::async_trait]
+impl AutonomousConsole for ConsoleService {
+    async fn get_latest(&self, _request: Request<LatestRequest>) -> Result<Response<AutonomousSample>, Status> {
+        match self.state.latest() {
+            Some(sample) => Ok(Response::new(sample)),
+            None => Err(Status::not_found("No autonomous data available yet")),
+        }
+    }
+
+    async fn get_history(&self, request: Request<HistoryRequest>) -> Result<Response<AutonomousHistory>, Status> {
+        let request = request.into_inner();
+        let samples = self.state.history(request.since, request.limit);
+        Ok(Response::new(AutonomousHistory { samples }))
+    }
+
+    type StreamSamplesStream = SampleStream<AutonomousSample>;
+
+    async fn stream_samples(&self, request: Request<StreamRequest>) -> Result<Response<Self::StreamSamplesStream>, Status> {
+        Ok(Response::new(self.state.stream(request.get_ref().include_latest)))
+    }
+}
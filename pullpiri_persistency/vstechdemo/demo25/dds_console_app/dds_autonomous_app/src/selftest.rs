@@ -0,0 +1,243 @@
+//! End-to-end self test of the DDS pipeline
+//!
+//! `--selftest` starts the app as usual, but on a DDS domain of its own and
+//! with an in-memory history only. An in-process writer with the QoS of the
+//! mini-adas publisher then publishes a known sequence of samples, and the
+//! app checks that `GET /data`, `GET /history` and the gRPC sample stream
+//! serve exactly those samples within a deadline. After a dust_dds update the
+//! app may still start but never discover the publisher or reject its QoS,
+//! which goes unnoticed until the demo; the self test catches that in CI.
+//! The app prints a report and exits non-zero if any check failed.
+//!
+//! The REST and gRPC ports are the regular ones, so the self test cannot run
+//! next to the app.
+
+use crate::{AutonomousCarData, GRPC_PORT, REST_PORT, TOPIC_NAME};
+use common::console::autonomous_console_client::AutonomousConsoleClient;
+use common::console::{AutonomousSample, StreamRequest};
+use console_common::check::{self, CheckResult};
+use console_common::compat::Versioned;
+use dust_dds::domain::domain_participant::DomainParticipant;
+use dust_dds::domain::domain_participant_factory::DomainParticipantFactory;
+use dust_dds::infrastructure::qos::{DataWriterQos, QosKind};
+use dust_dds::infrastructure::qos_policy::{
+    DurabilityQosPolicy, DurabilityQosPolicyKind, HistoryQosPolicy, HistoryQosPolicyKind,
+    ReliabilityQosPolicy, ReliabilityQosPolicyKind,
+};
+use dust_dds::infrastructure::time::DurationKind;
+use serde::de::DeserializeOwned;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tonic::Streaming;
+
+/// DDS domain of the self test, apart from the one shared with mini-adas so
+/// that a running vehicle does not add its samples
+pub const DDS_DOMAIN_ID: i32 = 142;
+/// Samples published by the self test
+const SAMPLE_COUNT: usize = 5;
+/// Time the whole self test may take, discovery included
+const DEADLINE: Duration = Duration::from_secs(30);
+/// Time between two published samples
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+/// Time between two polls of the APIs and the discovery
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Whether the app was started with `--selftest`
+pub fn requested() -> bool {
+    std::env::args().any(|arg| arg == "--selftest")
+}
+
+/// Writer QoS of the mini-adas AutonomousModePublisher
+fn writer_qos() -> DataWriterQos {
+    DataWriterQos {
+        reliability: ReliabilityQosPolicy {
+            kind: ReliabilityQosPolicyKind::BestEffort,
+            max_blocking_time: DurationKind::Finite(dust_dds::infrastructure::time::Duration::new(0, 100_000_000)),
+        },
+        durability: DurabilityQosPolicy {
+            kind: DurabilityQosPolicyKind::TransientLocal,
+        },
+        history: HistoryQosPolicy {
+            kind: HistoryQosPolicyKind::KeepLast(5),
+        },
+        ..Default::default()
+    }
+}
+
+/// Known sequence of samples, the first one stamped `start_ms`
+fn samples(start_ms: i64) -> Vec<AutonomousCarData> {
+    (0..SAMPLE_COUNT)
+        .map(|index| AutonomousCarData {
+            vehicle_speed: 30.0 + 5.0 * index as f64,
+            lane_position: 0.1 * index as f64,
+            obstacle_distance: 100.0 - 10.0 * index as f64,
+            traffic_signal: "green".to_string(),
+            acceleration: 1.5,
+            weather_condition: "clear".to_string(),
+            road_condition: "dry".to_string(),
+            timestamp: start_ms + index as i64 * SAMPLE_INTERVAL.as_millis() as i64,
+            is_valid: true,
+            speed_limit: 50.0,
+            schema_version: AutonomousCarData::SCHEMA_VERSION,
+            run_id: "selftest".to_string(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Publish the known sequence, check what the APIs serve, print the report
+/// and return whether all checks passed
+pub async fn run() -> bool {
+    let deadline = Instant::now() + DEADLINE;
+    let start_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+    let expected = samples(start_ms);
+
+    // The stream only carries samples received after subscribing, so subscribe before publishing
+    let stream = connect_stream(deadline).await;
+    let collected = tokio::spawn(collect(stream, expected.len(), deadline));
+
+    let published = {
+        let expected = expected.clone();
+        tokio::task::spawn_blocking(move || publish(&expected, deadline))
+            .await
+            .unwrap_or_else(|e| Err(format!("publisher panicked: {}", e)))
+    };
+    let mut results = vec![match &published {
+        Ok(_) => CheckResult { name: "DDS", passed: true, detail: format!("{} samples published on domain {}", expected.len(), DDS_DOMAIN_ID) },
+        Err(e) => CheckResult { name: "DDS", passed: false, detail: e.clone() },
+    }];
+
+    let last = expected.last().cloned();
+    results.push(poll("REST /data", "/data", deadline, |data: &AutonomousCarData| last.as_ref() == Some(data)).await);
+    let history_path = format!("/history?since={}", start_ms);
+    results.push(poll("REST /history", &history_path, deadline, |history: &Vec<AutonomousCarData>| *history == expected).await);
+    results.push(check_stream(collected.await.unwrap_or_else(|e| Err(format!("stream task panicked: {}", e))), &expected));
+
+    // Keep the writer until the checks are done, late readers are served from its history
+    if let Ok(participant) = published {
+        let _ = participant.delete_contained_entities();
+        let _ = DomainParticipantFactory::get_instance().delete_participant(&participant);
+    }
+
+    check::report("Autonomous app self test", &results)
+}
+
+/// Write `samples` once the app's reader was discovered, returning the participant of the writer
+fn publish(samples: &[AutonomousCarData], deadline: Instant) -> Result<DomainParticipant, String> {
+    let factory = DomainParticipantFactory::get_instance();
+    let participant = factory
+        .create_participant(DDS_DOMAIN_ID, QosKind::Default, None, &[])
+        .map_err(|e| format!("domain {} is not reachable: {:?}", DDS_DOMAIN_ID, e))?;
+
+    let fail = |detail: String| {
+        let _ = participant.delete_contained_entities();
+        let _ = factory.delete_participant(&participant);
+        Err(detail)
+    };
+
+    let result = participant
+        .create_publisher(QosKind::Default, None, &[])
+        .and_then(|publisher| {
+            let topic = participant.create_topic::<AutonomousCarData>(TOPIC_NAME, TOPIC_NAME, QosKind::Default, None, &[])?;
+            publisher.create_datawriter::<AutonomousCarData>(&topic, QosKind::Specific(writer_qos()), None, &[])
+        });
+    let writer = match result {
+        Ok(writer) => writer,
+        Err(e) => return fail(format!("writer QoS for topic '{}' rejected: {:?}", TOPIC_NAME, e)),
+    };
+    while writer.get_publication_matched_status().map(|status| status.current_count).unwrap_or(0) == 0 {
+        if Instant::now() >= deadline {
+            return fail(format!("the app's reader of topic '{}' was not discovered, check discovery and QoS", TOPIC_NAME));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    for sample in samples {
+        if let Err(e) = writer.write(sample, None) {
+            return fail(format!("sample {} not written: {:?}", sample.timestamp, e));
+        }
+        std::thread::sleep(SAMPLE_INTERVAL);
+    }
+    Ok(participant)
+}
+
+/// Subscribe to the gRPC sample stream, retrying until the server is up
+async fn connect_stream(deadline: Instant) -> Result<Streaming<AutonomousSample>, String> {
+    let endpoint = format!("http://127.0.0.1:{}", GRPC_PORT);
+    loop {
+        let attempt = async {
+            let mut client = AutonomousConsoleClient::connect(endpoint.clone()).await.map_err(|e| e.to_string())?;
+            let response = client.stream_samples(StreamRequest { include_latest: false }).await.map_err(|e| e.to_string())?;
+            Ok::<_, String>(response.into_inner())
+        };
+        match attempt.await {
+            Ok(stream) => return Ok(stream),
+            Err(e) if Instant::now() >= deadline => return Err(format!("{} not reachable: {}", endpoint, e)),
+            Err(_) => tokio::time::sleep(POLL_INTERVAL).await,
+        }
+    }
+}
+
+/// Receive up to `count` samples of `stream` until the deadline
+async fn collect(stream: Result<Streaming<AutonomousSample>, String>, count: usize, deadline: Instant) -> Result<Vec<AutonomousSample>, String> {
+    let mut stream = stream?;
+    let mut received = Vec::new();
+    while received.len() < count {
+        match tokio::time::timeout_at(deadline.into(), stream.message()).await {
+            Ok(Ok(Some(sample))) => received.push(sample),
+            Ok(Ok(None)) => return Err(format!("stream ended after {} samples", received.len())),
+            Ok(Err(status)) => return Err(format!("stream failed after {} samples: {}", received.len(), status)),
+            // Compared below, the report tells how many arrived
+            Err(_) => break,
+        }
+    }
+    Ok(received)
+}
+
+fn check_stream(received: Result<Vec<AutonomousSample>, String>, expected: &[AutonomousCarData]) -> CheckResult {
+    let expected: Vec<AutonomousSample> = expected.iter().map(crate::grpc::to_sample).collect();
+    let (passed, detail) = match received {
+        Ok(received) if received == expected => (true, format!("streamed the {} samples", received.len())),
+        Ok(received) => (false, format!("streamed {} of {} samples, or others than published", received.len(), expected.len())),
+        Err(e) => (false, e),
+    };
+    CheckResult { name: "gRPC stream", passed, detail }
+}
+
+/// Poll `path` of the REST API until its response passes `check` or the deadline passed
+async fn poll<T: DeserializeOwned>(name: &'static str, path: &str, deadline: Instant, check: impl Fn(&T) -> bool) -> CheckResult {
+    loop {
+        let outcome = match get(path).await {
+            // Responses without data, e.g. the error of `/data` before the first sample, do not decode
+            Ok(body) => match serde_json::from_str::<T>(&body) {
+                Ok(response) if check(&response) => return CheckResult { name, passed: true, detail: format!("GET {} serves the published samples", path) },
+                Ok(_) => format!("GET {} serves other samples than published", path),
+                Err(_) => format!("GET {} serves no samples: {}", path, body),
+            },
+            Err(e) => e,
+        };
+        if Instant::now() >= deadline {
+            return CheckResult { name, passed: false, detail: outcome };
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Body of a `GET` of `path` on the REST API
+async fn get(path: &str) -> Result<String, String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", REST_PORT))
+        .await
+        .map_err(|e| format!("REST port {} not reachable: {}", REST_PORT, e))?;
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.map_err(|e| e.to_string())?;
+
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response.split_once("\r\n\r\n").ok_or_else(|| format!("GET {}: malformed response", path))?;
+    let status = head.lines().next().unwrap_or_default();
+    if !status.contains(" 200 ") {
+        return Err(format!("GET {}: {}", path, status));
+    }
+    Ok(body.to_string())
+}
@@ -0,0 +1,225 @@
+//! Emergency episodes served by `GET /episodes`
+//!
+//! mini-adas publishes emergency samples continuously while the vehicle is in
+//! emergency mode. Consecutive samples, i.e. samples less than
+//! `EPISODE_GAP_MS` apart, are grouped into one episode, so distinct
+//! emergency events of a drive can be counted. An episode is completed by the
+//! first sample after a longer gap or when no sample arrived for that long.
+//! Completed episodes are written to the persistency service through its
+//! lightweight socket protocol, one key per episode.
+
+use crate::EmergencyModeData;
+use common::persistency_lite::{LiteClient, LiteError};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable overriding the gap that ends an episode
+pub const EPISODE_GAP_ENV: &str = "EPISODE_GAP_MS";
+/// Completed episodes kept for `GET /episodes`
+pub const EPISODE_CAPACITY: usize = 100;
+/// Key prefix of the episodes in the persistency service
+pub const EPISODE_KEY_PREFIX: &str = "demo/emergency/episodes/";
+
+/// Consecutive emergency samples
+#[derive(Clone, Debug, Serialize)]
+pub struct Episode {
+    /// Sequence number since the app started
+    pub id: u64,
+    /// Unix timestamp of the first sample in milliseconds
+    pub start_ms: i64,
+    /// Unix timestamp of the last sample in milliseconds
+    pub end_ms: i64,
+    pub duration_ms: i64,
+    pub samples: u64,
+    /// percentage (0-100)
+    pub max_collision_risk: f64,
+    /// meters
+    pub min_obstacle_distance: f64,
+    /// Emergency type of the first sample
+    pub emergency_type: String,
+}
+
+impl Episode {
+    fn start(id: u64, sample: &EmergencyModeData) -> Self {
+        Self {
+            id,
+            start_ms: sample.timestamp,
+            end_ms: sample.timestamp,
+            duration_ms: 0,
+            samples: 1,
+            max_collision_risk: sample.collision_risk,
+            min_obstacle_distance: sample.obstacle_distance,
+            emergency_type: sample.emergency_type.clone(),
+        }
+    }
+
+    fn extend(&mut self, sample: &EmergencyModeData) {
+        self.end_ms = self.end_ms.max(sample.timestamp);
+        self.duration_ms = self.end_ms - self.start_ms;
+        self.samples += 1;
+        self.max_collision_risk = self.max_collision_risk.max(sample.collision_risk);
+        self.min_obstacle_distance = self.min_obstacle_distance.min(sample.obstacle_distance);
+    }
+}
+
+/// Response of `GET /episodes`
+#[derive(Debug, Serialize)]
+pub struct EpisodesReport {
+    /// Episode still receiving samples
+    pub ongoing: Option<Episode>,
+    /// Recently completed episodes, oldest first
+    pub completed: Vec<Episode>,
+    /// Episodes completed since the app started
+    pub total_completed: u64,
+}
+
+/// Outcome of writing the completed episodes to the persistency service
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Persistence {
+    pub persisted: u64,
+    pub failed: u64,
+    /// Error of the last write, `None` after a successful write
+    pub last_error: Option<String>,
+}
+
+/// Groups emergency samples into episodes
+pub struct EpisodeTracker {
+    gap_ms: i64,
+    current: Option<Episode>,
+    completed: VecDeque<Episode>,
+    total_completed: u64,
+    next_id: u64,
+    /// Completed episodes waiting to be persisted
+    persist: Sender<Episode>,
+    persistence: Arc<Mutex<Persistence>>,
+}
+
+impl EpisodeTracker {
+    /// Create a tracker persisting completed episodes on a background thread
+    pub fn from_env() -> Self {
+        let gap_ms = std::env::var(EPISODE_GAP_ENV).ok().and_then(|value| value.parse().ok()).unwrap_or(2000);
+        let (persist, episodes) = mpsc::channel::<Episode>();
+        let persistence = Arc::new(Mutex::new(Persistence::default()));
+        let written = persistence.clone();
+        std::thread::spawn(move || {
+            let mut client = None;
+            for episode in episodes {
+                let result = persist_episode(&mut client, &episode);
+                let mut written = written.lock().unwrap();
+                match result {
+                    Ok(()) => {
+                        written.persisted += 1;
+                        written.last_error = None;
+                    }
+                    Err(e) => {
+                        written.failed += 1;
+                        written.last_error = Some(e);
+                    }
+                }
+            }
+        });
+
+        Self {
+            gap_ms,
+            current: None,
+            completed: VecDeque::with_capacity(EPISODE_CAPACITY),
+            total_completed: 0,
+            next_id: 1,
+            persist,
+            persistence,
+        }
+    }
+
+    /// Record a new emergency sample
+    pub fn push(&mut self, sample: &EmergencyModeData) {
+        if !sample.is_valid {
+            return;
+        }
+        match self.current.as_mut() {
+            Some(episode) if sample.timestamp - episode.end_ms <= self.gap_ms => episode.extend(sample),
+            _ => {
+                self.complete();
+                println!("🚨 Emergency episode #{} started ({})", self.next_id, sample.emergency_type);
+                self.current = Some(Episode::start(self.next_id, sample));
+                self.next_id += 1;
+            }
+        }
+    }
+
+    /// Complete the ongoing episode if no sample arrived for longer than the gap
+    pub fn expire(&mut self) {
+        let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+        if self.current.as_ref().is_some_and(|episode| now_ms - episode.end_ms > self.gap_ms) {
+            self.complete();
+        }
+    }
+
+    pub fn report(&self) -> EpisodesReport {
+        EpisodesReport {
+            ongoing: self.current.clone(),
+            completed: self.completed.iter().cloned().collect(),
+            total_completed: self.total_completed,
+        }
+    }
+
+    /// Outcome of the episode writes so far
+    pub fn persistence(&self) -> Persistence {
+        self.persistence.lock().unwrap().clone()
+    }
+
+    fn complete(&mut self) {
+        let Some(episode) = self.current.take() else {
+            return;
+        };
+        println!("✅ Emergency episode #{} ended after {:.1}s: max collision risk {:.1}%, min obstacle distance {:.1}m",
+            episode.id, episode.duration_ms as f64 / 1000.0, episode.max_collision_risk, episode.min_obstacle_distance);
+
+        if self.completed.len() >= EPISODE_CAPACITY {
+            self.completed.pop_front();
+        }
+        self.completed.push_back(episode.clone());
+        self.total_completed += 1;
+        let _ = self.persist.send(episode);
+    }
+}
+
+/// Write one episode, reconnecting once if the previous connection broke
+fn persist_episode(client: &mut Option<LiteClient>, episode: &Episode) -> Result<(), String> {
+    let key = format!("{}{}", EPISODE_KEY_PREFIX, episode.start_ms);
+    let value = match serde_json::to_string(episode) {
+        Ok(value) => value,
+        Err(e) => {
+            println!("⚠️  Failed to serialize episode #{}: {}", episode.id, e);
+            return Err(e.to_string());
+        }
+    };
+
+    let put = |client: &mut Option<LiteClient>| -> Result<(), LiteError> {
+        if client.is_none() {
+            *client = Some(LiteClient::connect()?);
+        }
+        let result = client.as_mut().map_or(Ok(()), |client| client.put(&key, &value));
+        if result.is_err() {
+            *client = None;
+        }
+        result
+    };
+
+    let result = match put(client) {
+        Err(LiteError::Io(_)) => put(client),
+        result => result,
+    };
+    match result {
+        Ok(()) => {
+            println!("💾 Episode #{} persisted as {}", episode.id, key);
+            Ok(())
+        }
+        Err(e) => {
+            println!("⚠️  Failed to persist episode #{}: {}", episode.id, e);
+            Err(e.to_string())
+        }
+    }
+}
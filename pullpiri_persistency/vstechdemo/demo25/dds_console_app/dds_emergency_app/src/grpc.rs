@@ -0,0 +1,69 @@
+//! gRPC exposition of the data served over REST
+//!
+//! The `EmergencyConsole` service of `common::console`, see `console_common::grpc`.
+
+use crate::EmergencyModeData;
+use common::console::emergency_console_server::{EmergencyConsole, EmergencyConsoleServer};
+use common::console::{EmergencyHistory, EmergencySample, HistoryRequest, LatestRequest, StreamRequest};
+use console_common::grpc::{ConsoleState, SampleStream};
+use tonic::{Request, Response, Status};
+
+/// gRPC message of a sample
+pub(crate) fn to_sample(data: &EmergencyModeData) -> EmergencySample {
+    EmergencySample {
+        vehicle_speed: data.vehicle_speed,
+        steering_angle: data.steering_angle,
+        brake_force: data.brake_force,
+        obstacle_detected: data.obstacle_detected,
+        obstacle_distance: data.obstacle_distance,
+        collision_risk: data.collision_risk,
+        stability_control: data.stability_control,
+        traffic_signal: data.traffic_signal.clone(),
+        seatbelt_tightened: data.seatbelt_tightened,
+        emergency_lights: data.emergency_lights,
+        emergency_type: data.emergency_type.clone(),
+        emergency_brake_force: data.emergency_brake_force,
+        airbag_ready: data.airbag_ready,
+        timestamp: data.timestamp,
+        is_valid: data.is_valid,
+        safe_state_version: data.safe_state_version.clone(),
+        brake_temperature: data.brake_temperature,
+        brake_fade: data.brake_fade,
+        occupants: data.occupants,
+        belted_occupants: data.belted_occupants,
+        passenger_airbag_ready: data.passenger_airbag_ready,
+    }
+}
+
+/// `EmergencyConsole` service on the state shared with the REST API
+pub struct ConsoleService {
+    state: ConsoleState<EmergencyModeData, EmergencySample>,
+}
+
+impl ConsoleService {
+    pub fn new(state: ConsoleState<EmergencyModeData, EmergencySample>) -> EmergencyConsoleServer<Self> {
+        EmergencyConsoleServer::new(Self { state })
+    }
+}
+
+#[tonic::async_trait]
+impl EmergencyConsole for ConsoleService {
+    async fn get_latest(&self, _request: Request<LatestRequest>) -> Result<Response<EmergencySample>, Status> {
+        match self.state.latest() {
+            Some(sample) => Ok(Response::new(sample)),
+            None => Err(Status::not_found("No emergency data available yet")),
+        }
+    }
+
+    async fn get_history(&self, request: Request<HistoryRequest>) -> Result<Response<EmergencyHistory>, Status> {
+        let request = request.into_inner();
+        let samples = self.state.history(request.since, request.limit);
+        Ok(Response::new(EmergencyHistory { samples }))
+    }
+
+    type StreamSamplesStream = SampleStream<EmergencySample>;
+
+    async fn stream_samples(&self, request: Request<StreamRequest>) -> Result<Response<Self::StreamSamplesStream>, Status> {
+        Ok(Response::new(self.state.stream(request.get_ref().include_latest)))
+    }
+}
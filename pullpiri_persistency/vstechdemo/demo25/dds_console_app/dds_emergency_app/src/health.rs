@@ -0,0 +1,52 @@
+//! Components of the readiness document of this app, see `console_common::health`
+//!
+//! mini-adas publishes emergency data in the emergency mode only, so the age
+//! of the last sample is reported but not checked unless
+//! `HEALTH_MAX_SAMPLE_AGE_MS` is set, e.g. for an emergency rehearsal. The
+//! persistency service, which stores the completed episodes, degrades the
+//! status when its socket is not reachable or the last episode write failed.
+
+use crate::episodes::Persistence;
+use common::persistency_lite::{LiteClient, DEFAULT_SOCKET_PATH, SOCKET_PATH_ENV};
+use console_common::health::{Components, Status};
+use serde::Serialize;
+
+/// Components of the app reported next to the ones of every console app
+#[derive(Debug, Serialize)]
+pub struct EmergencyComponents {
+    pub persistency: PersistencyHealth,
+}
+
+impl Components for EmergencyComponents {
+    fn status(&self) -> Status {
+        self.persistency.status
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PersistencyHealth {
+    pub status: Status,
+    /// Socket of the lightweight protocol of the persistency service
+    pub socket: String,
+    pub reachable: bool,
+    /// Writes of the completed episodes
+    pub episodes: Persistence,
+    pub detail: String,
+}
+
+/// Components of the app, given the state of the episode writes
+pub fn components(episodes: Persistence) -> EmergencyComponents {
+    EmergencyComponents { persistency: persistency_health(episodes) }
+}
+
+/// Connect to the persistency service, the connection is closed right away
+fn persistency_health(episodes: Persistence) -> PersistencyHealth {
+    let socket = std::env::var(SOCKET_PATH_ENV).unwrap_or_else(|_| DEFAULT_SOCKET_PATH.to_string());
+    let connected = LiteClient::connect_to(&socket);
+    let (status, detail) = match (&connected, &episodes.last_error) {
+        (Err(e), _) => (Status::Degraded, format!("{} is not reachable, episodes are not persisted: {}", socket, e)),
+        (Ok(_), Some(e)) => (Status::Degraded, format!("last episode write failed: {}", e)),
+        (Ok(_), None) => (Status::Ok, format!("{} reachable", socket)),
+    };
+    PersistencyHealth { status, reachable: connected.is_ok(), socket, episodes, detail }
+}
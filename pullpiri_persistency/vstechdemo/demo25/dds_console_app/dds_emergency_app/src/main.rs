@@ -1,41 +1,148 @@
+use console_common::access_log::{self, AccessLog};
+use console_common::check;
+use console_common::compat::{VersionCheck, Versioned};
+use console_common::events::{self, EventFeed, EventLog, EventQuery, EVENTS_HISTORY_DEPTH};
+use console_common::grpc::{ConsoleState, STREAM_CAPACITY};
+use console_common::health::{Health, Status};
+use console_common::history::{History, HistoryConfig, HistoryQuery};
+use console_common::AppConfig;
 use dust_dds::domain::domain_participant_factory::DomainParticipantFactory;
-use dust_dds::infrastructure::listeners::NoOpListener;
 use dust_dds::infrastructure::qos::{DataReaderQos, QosKind};
 use dust_dds::infrastructure::qos_policy::{
     DurabilityQosPolicy, DurabilityQosPolicyKind, ReliabilityQosPolicy,
     ReliabilityQosPolicyKind,
 };
-use dust_dds::infrastructure::status::{StatusKind, NO_STATUS};
+use dust_dds::infrastructure::status::StatusKind;
 use dust_dds::infrastructure::time::{Duration, DurationKind};
 use dust_dds::infrastructure::wait_set::{Condition, WaitSet};
 use dust_dds::subscription::sample_info::{ANY_INSTANCE_STATE, ANY_SAMPLE_STATE, ANY_VIEW_STATE};
 use dust_dds::topic_definition::type_support::DdsType;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
 use tokio::signal;
+use vehicle_types::EmergencyModeData;
 use warp::Filter;
+use warp::http::StatusCode;
 
+mod episodes;
+mod grpc;
+mod health;
+mod selftest;
+
+use episodes::EpisodeTracker;
+
+/// Port of the REST API
+pub const REST_PORT: u16 = 9082;
+/// Port of the gRPC API serving the same data
+pub const GRPC_PORT: u16 = 9182;
+/// DDS domain shared with mini-adas
+pub const DDS_DOMAIN_ID: i32 = 100;
+/// DDS topic and type name
+pub const TOPIC_NAME: &str = "EmergencyModeData";
+/// The app as described to the modules of `console_common`
+pub const APP: AppConfig = AppConfig {
+    name: "Emergency app",
+    domain_id: DDS_DOMAIN_ID,
+    topic: TOPIC_NAME,
+    rest_port: REST_PORT,
+    grpc_port: GRPC_PORT,
+};
+
+impl Versioned for ModeDecisionExplanation {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+impl Versioned for IntegrationHealth {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+/// Schema version checks of the subscribed topics
+static DATA_VERSION: VersionCheck = VersionCheck::new(TOPIC_NAME, EmergencyModeData::SCHEMA_VERSION);
+static EXPLANATION_VERSION: VersionCheck =
+    VersionCheck::new("ModeDecisionExplanation", ModeDecisionExplanation::SCHEMA_VERSION);
+static HEALTH_VERSION: VersionCheck = VersionCheck::new("IntegrationHealth", IntegrationHealth::SCHEMA_VERSION);
+static EVENTS_VERSION: VersionCheck = VersionCheck::new("EventLog", EventLog::SCHEMA_VERSION);
+
+/// Why mini-adas last changed the driving mode
 #[derive(DdsType, Clone, Debug, Serialize, Deserialize)]
-pub struct EmergencyModeData {
-    pub vehicle_speed: f64,         // km/h
-    pub steering_angle: f64,        // degrees (-45 to 45)
-    pub brake_force: f64,          // percentage (0-100)
-    pub obstacle_detected: bool,    // true if immediate threat
-    pub obstacle_distance: f64,     // meters
-    pub collision_risk: f64,        // percentage (0-100)
-    pub stability_control: bool,    // true if stability systems active
-    pub traffic_signal: String,     // "green", "yellow", "red", "stop"
-    pub seatbelt_tightened: bool,   // true if emergency seatbelt tightening
-    pub emergency_lights: bool,     // true if hazard lights on
-    pub emergency_type: String,     // "collision_avoidance", "obstacle", "medical", "system_failure"
-    pub emergency_brake_force: f64, // percentage (0-100)
-    pub airbag_ready: bool,         // true if airbag systems primed
-    pub timestamp: i64,             // Unix timestamp in milliseconds
-    pub is_valid: bool,             // Data validity flag
+pub struct ModeDecisionExplanation {
+    pub decision_id: u64,
+    pub driving_mode: String,
+    pub previous_mode: String,
+    pub rule: String,
+    pub num_people: u32,
+    pub num_cars: u32,
+    pub distance_obstacle: f64,
+    pub emergency_condition: bool,
+    pub manual_distance_condition: bool,
+    pub manual_people_condition: bool,
+    pub manual_cars_condition: bool,
+    pub timestamp: i64,
+    pub schema_version: u32,
+}
+
+/// QoS compatibility of a mini-adas DDS writer with its discovered readers
+#[derive(DdsType, Clone, Debug, Serialize, Deserialize)]
+pub struct IntegrationHealth {
+    pub publisher: String,
+    pub topic: String,
+    pub offered_reliability: String,
+    pub offered_durability: String,
+    pub matched_readers: u32,
+    pub incompatible_readers: u32,
+    pub incompatible_policies: String,
+    pub healthy: bool,
+    pub timestamp: i64,
+    pub schema_version: u32,
+}
+
+/// Reader QoS matching the mini-adas publisher
+pub fn reader_qos() -> DataReaderQos {
+    DataReaderQos {
+        reliability: ReliabilityQosPolicy {
+            kind: ReliabilityQosPolicyKind::BestEffort, // Match publisher BestEffort QoS
+            max_blocking_time: DurationKind::Finite(Duration::new(1, 0)), // Reduced timeout
+        },
+        durability: DurabilityQosPolicy {
+            kind: DurabilityQosPolicyKind::TransientLocal, // Keep for historical data
+        },
+        history: dust_dds::infrastructure::qos_policy::HistoryQosPolicy {
+            kind: dust_dds::infrastructure::qos_policy::HistoryQosPolicyKind::KeepLast(5), // Reduced history
+        },
+        ..Default::default()
+    }
+}
+
+/// Reader QoS of the event log, keeping as many events as mini-adas retains
+pub fn events_reader_qos() -> DataReaderQos {
+    DataReaderQos {
+        history: dust_dds::infrastructure::qos_policy::HistoryQosPolicy {
+            kind: dust_dds::infrastructure::qos_policy::HistoryQosPolicyKind::KeepLast(EVENTS_HISTORY_DEPTH as u32),
+        },
+        ..reader_qos()
+    }
 }
 
 #[tokio::main]
 async fn main() {
+    // Validate the configuration and exit without starting the loops
+    if std::env::args().any(|arg| arg == "--check") {
+        let ok = check::run::<EmergencyModeData>(&APP, reader_qos());
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    // Publish a known sequence in-process and check what the APIs serve, see `selftest`
+    let selftest = selftest::requested();
+
     // Shared state for latest data
     let latest_data = Arc::new(Mutex::new(None::<EmergencyModeData>));
     let latest_data_filter = warp::any().map({
@@ -43,6 +150,56 @@ async fn main() {
         move || latest_data.clone()
     });
 
+    // Recent samples in memory, optionally backed by the history file
+    let mut history_config = HistoryConfig::from_env();
+    if selftest {
+        // Keep the self test samples out of the recorded history
+        history_config.file = None;
+    }
+    let history = Arc::new(Mutex::new(History::<EmergencyModeData>::open(history_config)));
+    let history_filter = warp::any().map({
+        let history = history.clone();
+        move || history.clone()
+    });
+
+    // Readiness of DDS, the APIs, the persistency service and the history
+    let health = Arc::new(Health::from_env(APP, None));
+    let health_filter = warp::any().map({
+        let health = health.clone();
+        move || health.clone()
+    });
+
+    // Every received sample, for gRPC streaming clients
+    let (samples_tx, _) = tokio::sync::broadcast::channel::<EmergencyModeData>(STREAM_CAPACITY);
+
+    // Emergency episodes grouped from consecutive samples
+    let episodes = Arc::new(Mutex::new(EpisodeTracker::from_env()));
+    let episodes_filter = warp::any().map({
+        let episodes = episodes.clone();
+        move || episodes.clone()
+    });
+
+    // Shared state for the latest mode decision explanation
+    let latest_explanation = Arc::new(Mutex::new(None::<ModeDecisionExplanation>));
+    let latest_explanation_filter = warp::any().map({
+        let latest_explanation = latest_explanation.clone();
+        move || latest_explanation.clone()
+    });
+
+    // Shared state for the latest integration health report per publisher and topic
+    let integration = Arc::new(Mutex::new(BTreeMap::<String, IntegrationHealth>::new()));
+    let integration_filter = warp::any().map({
+        let integration = integration.clone();
+        move || integration.clone()
+    });
+
+    // Recent structured events of all mini-adas activities
+    let events = Arc::new(Mutex::new(EventFeed::from_env()));
+    let events_filter = warp::any().map({
+        let events = events.clone();
+        move || events.clone()
+    });
+
     // REST endpoint: GET /data
     let get_data = warp::path("data")
         .and(warp::get())
@@ -77,69 +234,296 @@ async fn main() {
             )
         });
 
-    let api = get_data.or(options_data);
+    // REST endpoint: GET /history?from=<ms>&to=<ms>&last=<period>&limit=<n> - recorded samples, oldest first
+    let get_history = warp::path("history")
+        .and(warp::get())
+        .and(warp::query::<HistoryQuery>())
+        .and(history_filter.clone())
+        .map(|query: HistoryQuery, history: Arc<Mutex<History<EmergencyModeData>>>| {
+            let response = match query.window() {
+                Ok(window) => {
+                    let samples = history.lock().unwrap().query(window, query.limit);
+                    warp::reply::with_status(warp::reply::json(&samples), StatusCode::OK)
+                }
+                Err(e) => warp::reply::with_status(warp::reply::json(&serde_json::json!({"error": e})), StatusCode::BAD_REQUEST),
+            };
+            warp::reply::with_header(
+                warp::reply::with_header(
+                    warp::reply::with_header(response, "Access-Control-Allow-Origin", "*"),
+                    "Access-Control-Allow-Methods", "GET, POST, OPTIONS"
+                ),
+                "Access-Control-Allow-Headers", "Content-Type"
+            )
+        });
+
+    // REST endpoint: GET /stats?from=<ms>&to=<ms>&last=<period> - min, max and mean of the numeric fields of the recorded samples
+    let get_stats = warp::path("stats")
+        .and(warp::get())
+        .and(warp::query::<HistoryQuery>())
+        .and(history_filter.clone())
+        .map(|query: HistoryQuery, history: Arc<Mutex<History<EmergencyModeData>>>| {
+            let response = match query.window() {
+                Ok(window) => {
+                    let stats = history.lock().unwrap().stats(window);
+                    warp::reply::with_status(warp::reply::json(&stats), StatusCode::OK)
+                }
+                Err(e) => warp::reply::with_status(warp::reply::json(&serde_json::json!({"error": e})), StatusCode::BAD_REQUEST),
+            };
+            warp::reply::with_header(
+                warp::reply::with_header(
+                    warp::reply::with_header(response, "Access-Control-Allow-Origin", "*"),
+                    "Access-Control-Allow-Methods", "GET, POST, OPTIONS"
+                ),
+                "Access-Control-Allow-Headers", "Content-Type"
+            )
+        });
+
+    // REST endpoint: GET /episodes - ongoing and recently completed emergency episodes
+    let get_episodes = warp::path("episodes")
+        .and(warp::get())
+        .and(episodes_filter.clone())
+        .map(|episodes: Arc<Mutex<EpisodeTracker>>| {
+            let report = episodes.lock().unwrap().report();
+            warp::reply::with_header(
+                warp::reply::with_header(
+                    warp::reply::with_header(warp::reply::json(&report), "Access-Control-Allow-Origin", "*"),
+                    "Access-Control-Allow-Methods", "GET, POST, OPTIONS"
+                ),
+                "Access-Control-Allow-Headers", "Content-Type"
+            )
+        });
+
+    // REST endpoint: GET /explanation - why the driving mode last changed
+    let get_explanation = warp::path("explanation")
+        .and(warp::get())
+        .and(latest_explanation_filter)
+        .map(|latest_explanation: Arc<Mutex<Option<ModeDecisionExplanation>>>| {
+            let explanation = latest_explanation.lock().unwrap();
+            let response = if let Some(ref e) = *explanation {
+                warp::reply::json(e)
+            } else {
+                warp::reply::json(&serde_json::json!({"error": "No mode decision explanation available yet"}))
+            };
+
+            warp::reply::with_header(
+                warp::reply::with_header(
+                    warp::reply::with_header(response, "Access-Control-Allow-Origin", "*"),
+                    "Access-Control-Allow-Methods", "GET, POST, OPTIONS"
+                ),
+                "Access-Control-Allow-Headers", "Content-Type"
+            )
+        });
+
+    // REST endpoint: GET /integration - QoS mismatches reported by the mini-adas publishers
+    let get_integration = warp::path("integration")
+        .and(warp::get())
+        .and(integration_filter)
+        .map(|integration: Arc<Mutex<BTreeMap<String, IntegrationHealth>>>| {
+            let reports: Vec<IntegrationHealth> = integration.lock().unwrap().values().cloned().collect();
+            warp::reply::with_header(
+                warp::reply::with_header(
+                    warp::reply::with_header(warp::reply::json(&reports), "Access-Control-Allow-Origin", "*"),
+                    "Access-Control-Allow-Methods", "GET, POST, OPTIONS"
+                ),
+                "Access-Control-Allow-Headers", "Content-Type"
+            )
+        });
+
+    // REST endpoint: GET /events?since=<ms>&min_severity=<severity>&component=<name>&code=<code>&limit=<n>
+    let get_events = warp::path("events")
+        .and(warp::get())
+        .and(warp::query::<EventQuery>())
+        .and(events_filter)
+        .map(|query: EventQuery, events: Arc<Mutex<EventFeed>>| {
+            let events = events.lock().unwrap().query(&query);
+            warp::reply::with_header(
+                warp::reply::with_header(
+                    warp::reply::with_header(warp::reply::json(&events), "Access-Control-Allow-Origin", "*"),
+                    "Access-Control-Allow-Methods", "GET, POST, OPTIONS"
+                ),
+                "Access-Control-Allow-Headers", "Content-Type"
+            )
+        });
+
+    // REST endpoint: GET /compat - schema version mismatches of the subscribed topics
+    let get_compat = warp::path("compat")
+        .and(warp::get())
+        .map(|| {
+            let reports = [DATA_VERSION.report(), EXPLANATION_VERSION.report(), HEALTH_VERSION.report(), EVENTS_VERSION.report()];
+            warp::reply::with_header(
+                warp::reply::with_header(
+                    warp::reply::with_header(warp::reply::json(&reports), "Access-Control-Allow-Origin", "*"),
+                    "Access-Control-Allow-Methods", "GET, POST, OPTIONS"
+                ),
+                "Access-Control-Allow-Headers", "Content-Type"
+            )
+        });
+
+    // REST endpoint: GET /health - readiness document, 503 if a component is down
+    let get_health = warp::path("health")
+        .and(warp::get())
+        .and(health_filter)
+        .and(history_filter)
+        .and(episodes_filter.clone())
+        .map(|health: Arc<Health>, history: Arc<Mutex<History<EmergencyModeData>>>, episodes: Arc<Mutex<EpisodeTracker>>| {
+            let persistence = episodes.lock().unwrap().persistence();
+            let report = health.report(history.lock().unwrap().status(), health::components(persistence));
+            let status = if report.status == Status::Down { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+            warp::reply::with_header(
+                warp::reply::with_header(
+                    warp::reply::with_header(warp::reply::with_status(warp::reply::json(&report), status), "Access-Control-Allow-Origin", "*"),
+                    "Access-Control-Allow-Methods", "GET, POST, OPTIONS"
+                ),
+                "Access-Control-Allow-Headers", "Content-Type"
+            )
+        });
+
+    let api = get_data.or(options_data).or(get_explanation).or(get_history).or(get_stats).or(get_episodes).or(get_integration).or(get_compat).or(get_events).or(get_health);
+    // Log every request with a request id echoed to the client
+    let access_log = Arc::new(AccessLog::from_env());
+    let api = access_log::wrap(api, access_log.clone());
 
     // Spawn REST API server in background (port 9082 for emergency)
     let rest_handle = tokio::spawn(async move {
-        println!("Emergency Mode Data REST API running on http://localhost:9082/data");
-        warp::serve(api).run(([0, 0, 0, 0], 9082)).await;
+        println!("Emergency Mode Data REST API running on http://localhost:{}/data", REST_PORT);
+        println!("Emergency history REST API running on http://localhost:{}/history", REST_PORT);
+        println!("Emergency history statistics REST API running on http://localhost:{}/stats", REST_PORT);
+        println!("Emergency episodes REST API running on http://localhost:{}/episodes", REST_PORT);
+        println!("Emergency compatibility REST API running on http://localhost:{}/compat", REST_PORT);
+        println!("Emergency integration health REST API running on http://localhost:{}/integration", REST_PORT);
+        println!("Emergency event log REST API running on http://localhost:{}/events", REST_PORT);
+        println!("Emergency readiness REST API running on http://localhost:{}/health", REST_PORT);
+        println!("Emergency REST API access log: {}", access_log.describe());
+        warp::serve(api).run(([0, 0, 0, 0], REST_PORT)).await;
+    });
+
+    // Spawn gRPC API server in background, reading the same state as REST
+    let grpc_service = grpc::ConsoleService::new(ConsoleState::new(latest_data.clone(), history.clone(), samples_tx.clone(), grpc::to_sample));
+    let health_grpc = health.clone();
+    let grpc_handle = tokio::spawn(async move {
+        println!("Emergency gRPC API running on localhost:{}", GRPC_PORT);
+        if let Err(e) = tonic::transport::Server::builder()
+            .add_service(grpc_service)
+            .serve(([0, 0, 0, 0], GRPC_PORT).into())
+            .await
+        {
+            println!("⚠️  gRPC API stopped: {}", e);
+        }
+        health_grpc.set_grpc_stopped();
     });
 
     // Spawn DDS subscriber in background task
     let latest_data_sub = latest_data.clone();
+    let history_sub = history.clone();
+    let samples_sub = samples_tx.clone();
+    let health_sub = health.clone();
+    let episodes_sub = episodes.clone();
+    let latest_explanation_sub = latest_explanation.clone();
+    let integration_sub = integration.clone();
+    let events_sub = events.clone();
+    let domain_id = if selftest { selftest::DDS_DOMAIN_ID } else { DDS_DOMAIN_ID };
     let dds_handle = tokio::spawn(async move {
-        let domain_id = 100;
-        let topic_name = "EmergencyModeData";
-        let type_name = "EmergencyModeData";
+        let topic_name = TOPIC_NAME;
+        let type_name = TOPIC_NAME;
 
         let participant_factory = DomainParticipantFactory::get_instance();
         let participant = participant_factory
-            .create_participant(domain_id, QosKind::Default, NoOpListener::new(), NO_STATUS)
+            .create_participant(domain_id, QosKind::Default, None, &[])
             .expect("Failed to create participant");
 
         let subscriber = participant
-        .create_subscriber(QosKind::Default, NoOpListener::new(), NO_STATUS)
+        .create_subscriber(QosKind::Default, None, &[])
         .expect("Failed to create subscriber");
 
         let topic = participant
-        .create_topic(
+        .create_topic::<EmergencyModeData>(
             topic_name,
             type_name,
             QosKind::Default,
-            NoOpListener::new(),
-            NO_STATUS,
+            None,
+            &[],
         )
         .expect("Failed to create topic");
 
-        let reader_qos = DataReaderQos {
-            reliability: ReliabilityQosPolicy {
-                kind: ReliabilityQosPolicyKind::BestEffort, // Match publisher BestEffort QoS
-                max_blocking_time: DurationKind::Finite(Duration::new(1, 0)), // Reduced timeout
-            },
-            durability: DurabilityQosPolicy {
-                kind: DurabilityQosPolicyKind::TransientLocal, // Keep for historical data
-            },
-            history: dust_dds::infrastructure::qos_policy::HistoryQosPolicy {
-                kind: dust_dds::infrastructure::qos_policy::HistoryQosPolicyKind::KeepLast(5), // Reduced history
-            },
-            ..Default::default()
-        };
-
         let reader = subscriber
-            .create_datareader::<EmergencyModeData>(&topic, QosKind::Specific(reader_qos), NoOpListener::new(), NO_STATUS)
+            .create_datareader::<EmergencyModeData>(&topic, QosKind::Specific(reader_qos()), None, &[])
             .expect("Failed to create datareader");
 
         // Wait for publisher discovery and data
-        let reader_cond = reader.get_statuscondition().expect("Failed to get status condition");
+        let reader_cond = reader.get_statuscondition();
         reader_cond
             .set_enabled_statuses(&[StatusKind::SubscriptionMatched, StatusKind::DataAvailable])
             .expect("Failed to set enabled statuses");
         
+        // Mode decision explanations published by mini-adas alongside CarData
+        let explanation_topic = participant
+            .create_topic::<ModeDecisionExplanation>(
+                "ModeDecisionExplanation",
+                "ModeDecisionExplanation",
+                QosKind::Default,
+                None,
+                &[],
+            )
+            .expect("Failed to create explanation topic");
+        let explanation_reader = subscriber
+            .create_datareader::<ModeDecisionExplanation>(&explanation_topic, QosKind::Specific(reader_qos()), None, &[])
+            .expect("Failed to create explanation datareader");
+        let explanation_cond = explanation_reader.get_statuscondition();
+        explanation_cond
+            .set_enabled_statuses(&[StatusKind::DataAvailable])
+            .expect("Failed to set enabled statuses");
+
+        // QoS mismatch reports of the mini-adas publishers
+        let health_topic = participant
+            .create_topic::<IntegrationHealth>(
+                "IntegrationHealth",
+                "IntegrationHealth",
+                QosKind::Default,
+                None,
+                &[],
+            )
+            .expect("Failed to create integration health topic");
+        let health_reader = subscriber
+            .create_datareader::<IntegrationHealth>(&health_topic, QosKind::Specific(reader_qos()), None, &[])
+            .expect("Failed to create integration health datareader");
+        let health_cond = health_reader.get_statuscondition();
+        health_cond
+            .set_enabled_statuses(&[StatusKind::DataAvailable])
+            .expect("Failed to set enabled statuses");
+
+        // Structured events of the mini-adas activities
+        let events_topic = participant
+            .create_topic::<EventLog>(
+                "EventLog",
+                "EventLog",
+                QosKind::Default,
+                None,
+                &[],
+            )
+            .expect("Failed to create event log topic");
+        let events_reader = subscriber
+            .create_datareader::<EventLog>(&events_topic, QosKind::Specific(events_reader_qos()), None, &[])
+            .expect("Failed to create event log datareader");
+        let events_cond = events_reader.get_statuscondition();
+        events_cond
+            .set_enabled_statuses(&[StatusKind::DataAvailable])
+            .expect("Failed to set enabled statuses");
+
         let mut wait_set = WaitSet::new();
         wait_set
             .attach_condition(Condition::StatusCondition(reader_cond.clone()))
             .expect("Failed to attach condition");
+        wait_set
+            .attach_condition(Condition::StatusCondition(explanation_cond.clone()))
+            .expect("Failed to attach condition");
+        wait_set
+            .attach_condition(Condition::StatusCondition(health_cond.clone()))
+            .expect("Failed to attach condition");
+        wait_set
+            .attach_condition(Condition::StatusCondition(events_cond.clone()))
+            .expect("Failed to attach condition");
 
+        health_sub.set_participant_alive();
         println!("Emergency DDS Subscriber ready - waiting for data...");
         
         let mut publisher_discovered = false;
@@ -149,6 +533,7 @@ async fn main() {
                 Ok(_) => {
                     // Check subscription status
                     let subscription_matched_status = reader.get_subscription_matched_status().unwrap_or_default();
+                    health_sub.set_matched_publishers(subscription_matched_status.current_count);
                     if subscription_matched_status.current_count > 0 && !publisher_discovered {
                         println!("✅ Publisher discovered! {} publisher(s) matched", subscription_matched_status.current_count);
                         publisher_discovered = true;
@@ -164,10 +549,14 @@ async fn main() {
                         if !historical_samples.is_empty() {
                             println!("📜 Found {} historical samples from TransientLocal durability", historical_samples.len());
                             for sample in historical_samples {
-                                if let Ok(data) = sample.data() {
+                                if let Some(data) = DATA_VERSION.check(sample.data()) {
                                     println!("🚨 Received historical emergency data: speed={}, brake={:.1}%", 
                                         data.vehicle_speed, data.emergency_brake_force);
                                     *latest_data_sub.lock().unwrap() = Some(data.clone());
+                                    health_sub.sample_received();
+                                    episodes_sub.lock().unwrap().push(&data);
+                                    history_sub.lock().unwrap().push(data.clone());
+                                    let _ = samples_sub.send(data.clone());
                                 }
                             }
                         } else {
@@ -182,10 +571,14 @@ async fn main() {
                             if !retry_samples.is_empty() {
                                 println!("📜 Found {} historical samples on retry", retry_samples.len());
                                 for sample in retry_samples {
-                                    if let Ok(data) = sample.data() {
+                                    if let Some(data) = DATA_VERSION.check(sample.data()) {
                                         println!("🚨 Received historical emergency data (retry): speed={}, brake={:.1}%", 
                                             data.vehicle_speed, data.emergency_brake_force);
                                         *latest_data_sub.lock().unwrap() = Some(data.clone());
+                                        health_sub.sample_received();
+                                        episodes_sub.lock().unwrap().push(&data);
+                                        history_sub.lock().unwrap().push(data.clone());
+                                        let _ = samples_sub.send(data.clone());
                                     }
                                 }
                             }
@@ -198,26 +591,76 @@ async fn main() {
                         .unwrap_or_default();
 
                     for sample in samples {
-                        if let Ok(data) = sample.data() {
+                        if let Some(data) = DATA_VERSION.check(sample.data()) {
                             println!("🚨 Received fresh emergency data: speed={}, brake={:.1}%", 
                                 data.vehicle_speed, data.emergency_brake_force);
                             // Update shared state for REST API
                             *latest_data_sub.lock().unwrap() = Some(data.clone());
+                            health_sub.sample_received();
+                            episodes_sub.lock().unwrap().push(&data);
+                            history_sub.lock().unwrap().push(data.clone());
+                            let _ = samples_sub.send(data.clone());
+                        }
+                    }
+
+                    let explanations = explanation_reader
+                        .take(10, ANY_SAMPLE_STATE, ANY_VIEW_STATE, ANY_INSTANCE_STATE)
+                        .unwrap_or_default();
+                    for sample in explanations {
+                        if let Some(explanation) = EXPLANATION_VERSION.check(sample.data()) {
+                            println!("💡 Mode decision #{}: {} → {} ({})",
+                                explanation.decision_id, explanation.previous_mode, explanation.driving_mode, explanation.rule);
+                            *latest_explanation_sub.lock().unwrap() = Some(explanation.clone());
+                        }
+                    }
+
+                    let health_reports = health_reader
+                        .take(10, ANY_SAMPLE_STATE, ANY_VIEW_STATE, ANY_INSTANCE_STATE)
+                        .unwrap_or_default();
+                    for sample in health_reports {
+                        if let Some(health) = HEALTH_VERSION.check(sample.data()) {
+                            let key = format!("{}/{}", health.publisher, health.topic);
+                            let previous = integration_sub.lock().unwrap().insert(key, health.clone());
+                            if !health.healthy && previous.is_none_or(|previous| previous.incompatible_readers != health.incompatible_readers) {
+                                println!("⚠️  {} '{}': {} reader(s) rejected for incompatible {} QoS (offered {}/{}) - they receive no data",
+                                    health.publisher, health.topic, health.incompatible_readers, health.incompatible_policies,
+                                    health.offered_reliability, health.offered_durability);
+                            }
+                        }
+                    }
+
+                    let event_samples = events_reader
+                        .take(EVENTS_HISTORY_DEPTH, ANY_SAMPLE_STATE, ANY_VIEW_STATE, ANY_INSTANCE_STATE)
+                        .unwrap_or_default();
+                    for sample in event_samples {
+                        if let Some(event) = EVENTS_VERSION.check(sample.data()) {
+                            if events_sub.lock().unwrap().push(&event) && events::severity_rank(&event.severity) >= events::severity_rank("warning") {
+                                println!("📋 [{}] {} {}: {}", event.severity, event.component, event.code, event.message);
+                            }
                         }
                     }
                 }
                 Err(_) => {
                     // Timeout - check if we still have publishers
                     let status = reader.get_subscription_matched_status().unwrap_or_default();
+                    health_sub.set_matched_publishers(status.current_count);
                     if status.current_count == 0 && publisher_discovered {
                         println!("⚠️  Publisher disconnected, waiting for reconnection...");
                         publisher_discovered = false;
                     }
                 }
             }
+
+            // Explanations also wake the loop, so check the episode end on every pass
+            episodes_sub.lock().unwrap().expire();
         }
     });
 
-    // Wait for both tasks to complete
-    let _ = tokio::join!(rest_handle, dds_handle);
+    if selftest {
+        let ok = selftest::run().await;
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    // Wait for all tasks to complete
+    let _ = tokio::join!(rest_handle, grpc_handle, dds_handle);
 }
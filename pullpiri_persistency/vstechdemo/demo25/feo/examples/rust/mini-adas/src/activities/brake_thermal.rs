@@ -0,0 +1,83 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Brake temperature and fade
+//!
+//! The BrakeController heats the brake discs with the braking power and lets
+//! them cool down towards the ambient temperature, faster at speed. Sustained
+//! heavy braking, e.g. repeated emergency stops, heats them up to where the
+//! pads lose friction: above [`FADE_START`] °C the brakes deliver less torque
+//! for the same brake level, down to [`MIN_FADE_FACTOR`] of it at
+//! [`FADE_FULL`] °C.
+//!
+//! The CarModeCalculator reads the temperature from the fed back VehicleState
+//! and accounts for the fade:
+//!
+//! - the emergency and manual obstacle thresholds grow with the longer
+//!   stopping distance, so that it intervenes earlier
+//! - it brakes closer above the target speed
+//! - it raises the brake levels to make up for the lost torque, as far as
+//!   full braking allows
+
+/// Temperature of cold brakes (°C)
+pub const AMBIENT_TEMPERATURE: f64 = 25.0;
+
+/// Heat capacity of the brake discs (J/K), 4 discs of 5 kg cast iron
+const HEAT_CAPACITY: f64 = 4.0 * 5.0 * 460.0;
+
+/// Heat transfer of the discs to the air at standstill (W/K)
+const COOLING_STANDSTILL: f64 = 10.0;
+
+/// Additional heat transfer per m/s of speed (W/K)
+const COOLING_PER_SPEED: f64 = 2.5;
+
+/// Temperature above which the brakes fade (°C)
+pub const FADE_START: f64 = 300.0;
+
+/// Temperature of fully faded brakes (°C)
+pub const FADE_FULL: f64 = 600.0;
+
+/// Share of the brake torque left with fully faded brakes
+pub const MIN_FADE_FACTOR: f64 = 0.5;
+
+/// Brake temperature after `dt` seconds (°C)
+///
+/// `brake_power` is the power dissipated by the brakes (W), `speed` the
+/// vehicle speed cooling them (m/s).
+pub fn next_temperature(temperature: f64, brake_power: f64, speed: f64, dt: f64) -> f64 {
+    let cooling = (COOLING_STANDSTILL + COOLING_PER_SPEED * speed) * (temperature - AMBIENT_TEMPERATURE);
+    (temperature + (brake_power - cooling) * dt / HEAT_CAPACITY).max(AMBIENT_TEMPERATURE)
+}
+
+/// Share of the brake torque delivered at `temperature` (°C), 1.0 for cool brakes
+pub fn fade_factor(temperature: f64) -> f64 {
+    let faded = ((temperature - FADE_START) / (FADE_FULL - FADE_START)).clamp(0.0, 1.0);
+    1.0 - faded * (1.0 - MIN_FADE_FACTOR)
+}
+
+/// Whether the brakes deliver less than their full torque at `temperature` (°C)
+pub fn is_fading(temperature: f64) -> bool {
+    temperature > FADE_START
+}
+
+/// Brake level delivering the torque of `level` with brakes at `fade`, at most full braking
+pub fn compensated_level(level: f64, fade: f64) -> f64 {
+    (level / fade).min(1.0)
+}
+
+/// Obstacle `threshold` (m) stretched by the longer stopping distance of brakes at `fade`
+///
+/// The stopping distance grows with the inverse of the deceleration.
+pub fn earlier_threshold(threshold: f64, fade: f64) -> f64 {
+    threshold / fade
+}
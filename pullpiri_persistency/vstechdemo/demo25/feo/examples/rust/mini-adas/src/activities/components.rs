@@ -42,9 +42,19 @@
 //! oversight in unpredictable situations.
 
 use crate::activities::messages::{
-    BrakeInstruction, CameraImage, RadarScan, Scene, Steering,
+    ActiveScenario, BrakeInstruction, CameraImage, DemoScenario, RadarScan, Scene, Steering,
     CarData, AutonomousCarData, ManualCarData, EmergencyModeData,
+    MapAttributes, RoadClass, ModeDecisionExplanation, ThrottleInstruction, VehicleState,
+    Versioned, HazardKind, SafeStateRequest, V2xHazard, V2xHazardWarning, Occupancy,
 };
+use crate::activities::brake_thermal;
+use crate::activities::eco;
+use crate::activities::event_log::{EventCode, EventLogger, Severity};
+use crate::activities::occupancy::{self, ScriptedChange};
+use crate::activities::qos_check::QosMonitor;
+use crate::activities::safe_state::SafeStateMatrix;
+use crate::activities::v2x::{self, ScriptedHazard};
+use crate::activities::validation::{self, Expectation, Outcome, ScenarioRun};
  use feo_log::info;
 use core::fmt;
 use core::hash::{BuildHasher as _, Hasher as _};
@@ -55,6 +65,8 @@ use dust_dds::{
     domain::domain_participant_factory::DomainParticipantFactory,
     infrastructure::qos::QosKind,
     publication::data_writer::DataWriter,
+    subscription::data_reader::DataReader,
+    subscription::sample_info::{ANY_INSTANCE_STATE, ANY_SAMPLE_STATE, ANY_VIEW_STATE},
     domain::domain_participant::DomainParticipant,
     infrastructure::qos_policy::{
         DurabilityQosPolicy, DurabilityQosPolicyKind, ReliabilityQosPolicy,
@@ -65,13 +77,16 @@ use core::time::Duration;
 use feo::activity::Activity;
 use feo::ids::ActivityId;
 use feo_com::interface::{ActivityInput, ActivityOutput};
+use crate::com::com_backend;
+use feo_com::interface::ComBackend;
 #[cfg(feature = "com_iox2")]
 use feo_com::iox2::{Iox2Input, Iox2Output};
-#[cfg(feature = "com_linux_shm")]
 use feo_com::linux_shm::{LinuxShmInput, LinuxShmOutput};
 use feo_log::debug;
+use feo_log::error;
 use feo_log::warn;
 use feo_tracing::instrument;
+use std::collections::VecDeque;
 use std::hash::RandomState;
 use std::thread;
 
@@ -87,6 +102,67 @@ fn create_dds_participant() -> DomainParticipant {
 
 const SLEEP_RANGE: Range<i64> = 10..45;
 
+/// Tolerance above the speed limit before a sample counts as a violation (km/h)
+const SPEED_LIMIT_TOLERANCE: f64 = 2.0;
+
+/// Simulated route as (road class, speed limit in km/h, duration in steps, road friction)
+const ROUTE: &[(RoadClass, f64, usize, f64)] = &[
+    (RoadClass::Highway, 100.0, 60, 0.9), // dry asphalt
+    (RoadClass::Rural, 80.0, 40, 0.6),    // wet asphalt
+    (RoadClass::Suburban, 50.0, 40, 0.8),
+    (RoadClass::Urban, 30.0, 30, 0.5),    // wet cobblestone
+    (RoadClass::Suburban, 50.0, 30, 0.8),
+];
+
+/// Road friction assumed until map data is available
+const DEFAULT_ROAD_FRICTION: f64 = 0.8;
+
+// Longitudinal vehicle dynamics model of the BrakeController
+/// Vehicle mass (kg)
+const VEHICLE_MASS: f64 = 1500.0;
+/// Dynamic wheel radius (m)
+const WHEEL_RADIUS: f64 = 0.31;
+/// Drive force at the wheels at full throttle (N)
+const MAX_DRIVE_FORCE: f64 = 5000.0;
+/// Brake torque map as (brake level, brake torque summed over all wheels in Nm)
+const BRAKE_TORQUE_MAP: &[(f64, f64)] = &[
+    (0.0, 0.0),
+    (0.2, 600.0),
+    (0.5, 2200.0),
+    (0.8, 4200.0),
+    (1.0, 5000.0),
+];
+/// Rolling resistance coefficient
+const ROLLING_RESISTANCE: f64 = 0.012;
+/// Aerodynamic drag as 0.5 * air density * drag coefficient * frontal area (kg/m)
+const DRAG_FACTOR: f64 = 0.5 * 1.2 * 0.3 * 2.2;
+/// Gravitational acceleration (m/s²)
+const GRAVITY: f64 = 9.81;
+/// Longest time step integrated at once, so that a stalled task chain does not cause jumps (s)
+const MAX_DYNAMICS_STEP: f64 = 0.5;
+/// Speed of the vehicle when the simulation starts (km/h)
+const INITIAL_SPEED: f64 = 50.0;
+
+// Speed controller of the CarModeCalculator
+/// Throttle per km/h below the target speed
+const THROTTLE_GAIN: f64 = 0.04;
+/// Brake level per km/h above the target speed
+const BRAKE_GAIN: f64 = 0.03;
+/// Speed above the target that is tolerated before braking (km/h)
+const BRAKE_DEADBAND: f64 = 3.0;
+
+/// Persistency key of the last driving mode, stored as `<mode>;<timestamp_ms>`
+const MODE_STATE_KEY: &str = "mini-adas/mode/last";
+
+/// Persistency key of the mode history, one `<timestamp_ms>;<mode>` entry per line
+pub const MODE_HISTORY_KEY: &str = "mini-adas/mode/history";
+
+/// Number of mode changes kept in the persisted history
+const MODE_HISTORY_LEN: usize = 20;
+
+/// Persisted mode state older than this is ignored at startup
+const MODE_STATE_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
 /// Camera activity
 ///
 /// This activity emulates a camera generating a [CameraImage].
@@ -94,8 +170,12 @@ const SLEEP_RANGE: Range<i64> = 10..45;
 pub struct Camera {
     /// ID of the activity
     activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
     /// Image output
     output_image: Box<dyn ActivityOutput<CameraImage>>,
+    /// Active scenario output
+    output_scenario: Box<dyn ActivityOutput<ActiveScenario>>,
 
     // Local state for realistic scenario generation
     num_people: usize,
@@ -106,21 +186,32 @@ pub struct Camera {
     scenario_timer: usize,
     current_scenario: String, // "highway", "city", "suburban", "emergency_test"
     scenario_duration: usize,
+    scenario_run: u32,
+    /// Scenarios of the run as (scenario, duration in steps), repeated in order
+    schedule: Vec<(DemoScenario, usize)>,
+    schedule_index: usize,
 }
 
 impl Camera {
-    pub fn build(activity_id: ActivityId, image_topic: &str) -> Box<dyn Activity> {
+    pub fn build(activity_id: ActivityId, image_topic: &str, scenario_topic: &str) -> Box<dyn Activity> {
+        let schedule = crate::runs::current().schedule();
+        let (first_scenario, first_duration) = schedule[0];
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("Camera"),
             output_image: activity_output(image_topic),
+            output_scenario: activity_output(scenario_topic),
             num_people: 2,      // Start with moderate count
             num_cars: 3,        // Start with moderate count  
             distance_obstacle: 10.0,  // Start in mid-range to allow all modes
             
             // Initialize scenario tracking for realistic patterns
             scenario_timer: 0,
-            current_scenario: "highway".to_string(), // Start with highway scenario
-            scenario_duration: 40, // Steps before first scenario change
+            current_scenario: first_scenario.as_str().to_string(), // Start with the first scenario of the run
+            scenario_duration: first_duration, // Steps before first scenario change
+            scenario_run: 0,
+            schedule,
+            schedule_index: 0,
         })
     }
 
@@ -131,26 +222,21 @@ impl Camera {
         if self.scenario_timer >= self.scenario_duration {
             // Change to a new scenario
             self.scenario_timer = 0;
-            self.current_scenario = match self.current_scenario.as_str() {
-                "highway" => {
-                    self.scenario_duration = 30; // Shorter for testing
-                    "city".to_string()
-                },
-                "city" => {
-                    self.scenario_duration = 25; // Shorter for testing  
-                    "suburban".to_string()
-                },
-                "suburban" => {
-                    self.scenario_duration = 15; // Short emergency test
-                    "emergency_test".to_string()
-                },
-                "emergency_test" => {
-                    self.scenario_duration = 40; // Back to highway
-                    "highway".to_string()
-                },
-                _ => "highway".to_string(),
-            };
+            self.schedule_index = (self.schedule_index + 1) % self.schedule.len();
+            let (scenario, duration) = self.schedule[self.schedule_index];
+            self.current_scenario = scenario.as_str().to_string();
+            self.scenario_duration = duration;
+            self.scenario_run += 1;
             info!("🎬 Camera: Switching to {} scenario for {} steps", self.current_scenario, self.scenario_duration);
+            self.events.emit(
+                Severity::Info,
+                EventCode::ScenarioChanged,
+                format!("Switching to {} scenario", self.current_scenario),
+                &[
+                    ("scenario", self.current_scenario.clone()),
+                    ("steps", self.scenario_duration.to_string()),
+                ],
+            );
         }
 
         // Generate realistic data based on current scenario
@@ -205,7 +291,11 @@ impl Activity for Camera {
     }
 
     #[instrument(name = "Camera startup")]
-    fn startup(&mut self) {}
+    fn startup(&mut self) {
+        self.events.started();
+        // The camera owns the scenario schedule, so it stores the run's seed record
+        crate::runs::current().persist();
+    }
 
     #[instrument(name = "Camera")]
     fn step(&mut self) {
@@ -218,10 +308,21 @@ impl Activity for Camera {
             let camera = camera.write_payload(image);
             camera.send().unwrap();
         }
+
+        // After the image, which may have switched the scenario
+        if let Ok(scenario) = self.output_scenario.write_uninit() {
+            let scenario = scenario.write_payload(ActiveScenario {
+                scenario: DemoScenario::parse(&self.current_scenario).unwrap_or_default(),
+                run: self.scenario_run,
+            });
+            scenario.send().unwrap();
+        }
     }
 
     #[instrument(name = "Camera shutdown")]
-    fn shutdown(&mut self) {}
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
 }
 
 /// Radar activity
@@ -231,6 +332,8 @@ impl Activity for Camera {
 pub struct Radar {
     /// ID of the activity
     activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
     /// Radar scan output
     output_scan: Box<dyn ActivityOutput<RadarScan>>,
 
@@ -242,6 +345,7 @@ impl Radar {
     pub fn build(activity_id: ActivityId, radar_topic: &str) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("Radar"),
             output_scan: activity_output(radar_topic),
             distance_obstacle: 10.0,  // Start in mid-range to allow all modes
         })
@@ -268,7 +372,9 @@ impl Activity for Radar {
     }
 
     #[instrument(name = "Radar startup")]
-    fn startup(&mut self) {}
+    fn startup(&mut self) {
+        self.events.started();
+    }
 
     #[instrument(name = "Radar")]
     fn step(&mut self) {
@@ -284,7 +390,420 @@ impl Activity for Radar {
     }
 
     #[instrument(name = "Radar shutdown")]
-    fn shutdown(&mut self) {}
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
+}
+
+/// Route simulator activity
+///
+/// This activity emulates a GPS receiver matched against a map,
+/// generating [MapAttributes] for the road segment the vehicle is on.
+/// The vehicle follows the segments of [ROUTE] in a loop.
+#[derive(Debug)]
+pub struct RouteSimulator {
+    /// ID of the activity
+    activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
+    /// Map attributes output
+    output_map: Box<dyn ActivityOutput<MapAttributes>>,
+
+    // Position on the simulated route
+    segment: usize,
+    segment_timer: usize,
+}
+
+impl RouteSimulator {
+    pub fn build(activity_id: ActivityId, map_topic: &str) -> Box<dyn Activity> {
+        Box::new(Self {
+            activity_id,
+            events: EventLogger::new("RouteSimulator"),
+            output_map: activity_output(map_topic),
+            segment: 0,
+            segment_timer: 0,
+        })
+    }
+
+    fn get_attributes(&mut self) -> MapAttributes {
+        self.segment_timer += 1;
+
+        let (_, _, duration, _) = ROUTE[self.segment];
+        if self.segment_timer >= duration {
+            self.segment_timer = 0;
+            self.segment = (self.segment + 1) % ROUTE.len();
+
+            let (road_class, speed_limit, duration, road_friction) = ROUTE[self.segment];
+            info!("🗺️ RouteSimulator: Entering {:?} segment, speed limit {:.0} km/h, friction {:.1} for {} steps",
+                road_class, speed_limit, road_friction, duration);
+            self.events.emit(
+                Severity::Info,
+                EventCode::RouteSegmentEntered,
+                format!("Entering {:?} segment", road_class),
+                &[
+                    ("road_class", format!("{:?}", road_class)),
+                    ("speed_limit", format!("{:.0}", speed_limit)),
+                    ("road_friction", format!("{:.1}", road_friction)),
+                    ("steps", duration.to_string()),
+                ],
+            );
+        }
+
+        let (road_class, speed_limit, _, road_friction) = ROUTE[self.segment];
+        MapAttributes {
+            speed_limit,
+            road_class,
+            road_friction,
+        }
+    }
+}
+
+impl Activity for RouteSimulator {
+    fn id(&self) -> ActivityId {
+        self.activity_id
+    }
+
+    #[instrument(name = "RouteSimulator startup")]
+    fn startup(&mut self) {
+        self.events.started();
+    }
+
+    #[instrument(name = "RouteSimulator")]
+    fn step(&mut self) {
+        debug!("Stepping RouteSimulator");
+        sleep_random();
+
+        if let Ok(map) = self.output_map.write_uninit() {
+            let attributes = self.get_attributes();
+            debug!("Sending map attributes: {attributes:?}");
+            let map = map.write_payload(attributes);
+            map.send().unwrap();
+        }
+    }
+
+    #[instrument(name = "RouteSimulator shutdown")]
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
+}
+
+/// V2X receiver activity
+///
+/// This activity emulates a V2X receiver, generating the most relevant
+/// [V2xHazard] of a V2X script and of the [V2xHazardWarning]s received over DDS.
+/// See [v2x] for the script format.
+pub struct V2xReceiver {
+    /// ID of the activity
+    activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
+    /// Hazard output
+    output_hazard: Box<dyn ActivityOutput<V2xHazard>>,
+
+    // Scripted warnings, timed from startup
+    script: Vec<ScriptedHazard>,
+    started: Option<std::time::Instant>,
+
+    // Last warning received over DDS and when it expires
+    dds_warning: Option<(V2xHazard, std::time::Instant)>,
+    reader: Option<DataReader<V2xHazardWarning>>,
+    participant: Option<DomainParticipant>, // Own participant for clean restart
+
+    // Last published hazard, to log changes only
+    last_hazard: V2xHazard,
+}
+
+impl V2xReceiver {
+    pub fn build(activity_id: ActivityId, hazard_topic: &str) -> Box<dyn Activity> {
+        Box::new(Self {
+            activity_id,
+            events: EventLogger::new("V2xReceiver"),
+            output_hazard: activity_output(hazard_topic),
+            script: Vec::new(),
+            started: None,
+            dds_warning: None,
+            reader: None,
+            participant: None, // Will be created in startup
+            last_hazard: V2xHazard::default(),
+        })
+    }
+
+    /// Take the warnings received over DDS, the newest one replacing older ones
+    fn receive_warnings(&mut self) {
+        let Some(reader) = &self.reader else {
+            return;
+        };
+        // Taking fails with NoData if nothing was received
+        let Ok(samples) = reader.take(10, ANY_SAMPLE_STATE, ANY_VIEW_STATE, ANY_INSTANCE_STATE) else {
+            return;
+        };
+
+        for sample in samples {
+            let Ok(warning) = sample.data() else {
+                continue;
+            };
+            let reject = |reason: &str| {
+                self.events.emit(
+                    Severity::Warning,
+                    EventCode::V2xWarningRejected,
+                    format!("Ignoring V2X warning with {}", reason),
+                    &[
+                        ("hazard", warning.hazard.clone()),
+                        ("distance", format!("{:.0}", warning.distance)),
+                        ("schema_version", warning.schema_version.to_string()),
+                    ],
+                );
+            };
+            if warning.schema_version != V2xHazardWarning::SCHEMA_VERSION {
+                warn!("📡 Ignoring V2X warning with schema version {} (expected {})",
+                    warning.schema_version, V2xHazardWarning::SCHEMA_VERSION);
+                reject("unsupported schema version");
+                continue;
+            }
+            let Some(kind) = HazardKind::parse(&warning.hazard) else {
+                warn!("📡 Ignoring V2X warning with unknown hazard '{}'", warning.hazard);
+                reject("unknown hazard");
+                continue;
+            };
+            if !warning.distance.is_finite() || warning.distance < 0.0 {
+                warn!("📡 Ignoring V2X warning with invalid distance {}", warning.distance);
+                reject("invalid distance");
+                continue;
+            }
+
+            if kind == HazardKind::None {
+                info!("📡 V2X warning cleared over DDS");
+                self.dds_warning = None;
+                continue;
+            }
+            let validity = match warning.valid_for_ms {
+                0 => v2x::DEFAULT_VALIDITY,
+                valid_for_ms => Duration::from_millis(valid_for_ms),
+            };
+            info!("📡 V2X warning received over DDS: {} {:.0}m ahead, valid for {:.0}s",
+                kind.as_str(), warning.distance, validity.as_secs_f64());
+            self.dds_warning = Some((
+                V2xHazard { kind, distance: warning.distance },
+                std::time::Instant::now() + validity,
+            ));
+        }
+    }
+}
+
+impl Activity for V2xReceiver {
+    fn id(&self) -> ActivityId {
+        self.activity_id
+    }
+
+    fn startup(&mut self) {
+        self.events.started();
+        self.script = v2x::load_script();
+        self.started = Some(std::time::Instant::now());
+
+        // Create individual DDS participant for this component - prevents shared state issues
+        let participant = create_dds_participant();
+
+        let topic = participant
+            .create_topic::<V2xHazardWarning>(
+                v2x::V2X_WARNING_TOPIC,
+                v2x::V2X_WARNING_TOPIC,
+                QosKind::Default,
+                None,
+                &[],
+            )
+            .unwrap();
+
+        let subscriber = participant
+            .create_subscriber(QosKind::Default, None, &[])
+            .unwrap();
+
+        let reader = subscriber
+            .create_datareader::<V2xHazardWarning>(&topic, QosKind::Default, None, &[])
+            .unwrap();
+
+        self.reader = Some(reader);
+        self.participant = Some(participant); // Store for clean shutdown
+        info!("📡 V2xReceiver listening for hazard warnings on DDS topic '{}'", v2x::V2X_WARNING_TOPIC);
+    }
+
+    fn step(&mut self) {
+        debug!("Stepping V2xReceiver");
+        sleep_random();
+
+        self.receive_warnings();
+        if self.dds_warning.is_some_and(|(_, expiry)| expiry <= std::time::Instant::now()) {
+            info!("📡 V2X warning received over DDS expired");
+            self.dds_warning = None;
+        }
+
+        let elapsed = self.started.map(|started| started.elapsed()).unwrap_or_default();
+        let hazard = v2x::most_relevant(
+            v2x::scripted_hazard(&self.script, elapsed),
+            self.dds_warning.map(|(hazard, _)| hazard).unwrap_or_default(),
+        );
+        if hazard.kind != self.last_hazard.kind {
+            match hazard.kind {
+                HazardKind::None => {
+                    info!("📡 V2X hazard {} no longer active", self.last_hazard.kind.as_str());
+                    self.events.emit(
+                        Severity::Info,
+                        EventCode::V2xHazardCleared,
+                        format!("V2X hazard {} no longer active", self.last_hazard.kind.as_str()),
+                        &[("hazard", self.last_hazard.kind.as_str().to_string())],
+                    );
+                }
+                kind => {
+                    info!("📡 V2X hazard active: {} {:.0}m ahead", kind.as_str(), hazard.distance);
+                    self.events.emit(
+                        Severity::Warning,
+                        EventCode::V2xHazardActive,
+                        format!("V2X hazard {} {:.0}m ahead", kind.as_str(), hazard.distance),
+                        &[
+                            ("hazard", kind.as_str().to_string()),
+                            ("distance", format!("{:.0}", hazard.distance)),
+                            ("forces_manual", v2x::forces_manual(&hazard).to_string()),
+                        ],
+                    );
+                }
+            }
+        }
+        self.last_hazard = hazard;
+
+        if let Ok(output) = self.output_hazard.write_uninit() {
+            debug!("Sending V2X hazard: {hazard:?}");
+            let output = output.write_payload(hazard);
+            output.send().unwrap();
+        }
+    }
+
+    fn shutdown(&mut self) {
+        self.events.stopped();
+        self.reader = None;
+        self.participant = None; // Clean shutdown of individual participant
+    }
+}
+
+/// Occupancy model activity
+///
+/// This activity simulates the occupants, seatbelts and doors of the cabin,
+/// generating [Occupancy] from a cabin script. Occupants board and leave only
+/// while the [VehicleState] reports standstill. See [occupancy] for the
+/// script format.
+pub struct OccupancyModel {
+    /// ID of the activity
+    activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
+    /// Vehicle state input, for standstill
+    input_vehicle_state: Box<dyn ActivityInput<VehicleState>>,
+    /// Occupancy output
+    output_occupancy: Box<dyn ActivityOutput<Occupancy>>,
+
+    // Scripted changes, timed from startup, and the next one to apply
+    script: Vec<ScriptedChange>,
+    next_change: usize,
+    started: Option<std::time::Instant>,
+
+    // Current cabin and when its open doors close, indexed by Door
+    cabin: Occupancy,
+    doors_close: [Option<std::time::Instant>; 4],
+}
+
+impl OccupancyModel {
+    pub fn build(activity_id: ActivityId, vehicle_state_topic: &str, occupancy_topic: &str) -> Box<dyn Activity> {
+        Box::new(Self {
+            activity_id,
+            events: EventLogger::new("OccupancyModel"),
+            input_vehicle_state: activity_input(vehicle_state_topic),
+            output_occupancy: activity_output(occupancy_topic),
+            script: Vec::new(),
+            next_change: 0,
+            started: None,
+            cabin: occupancy::initial_cabin(),
+            doors_close: [None; 4],
+        })
+    }
+
+    /// Apply a scripted change to the cabin, opening the door of the seat for boarding and leaving
+    fn apply(&mut self, scripted: ScriptedChange, now: std::time::Instant) {
+        let ScriptedChange { change, seat, .. } = scripted;
+        if !occupancy::apply(&mut self.cabin, change, seat) {
+            debug!("🪑 Cabin change {} of seat {} changes nothing", change.as_str(), seat.as_str());
+            return;
+        }
+        if change.needs_standstill() {
+            let door = occupancy::door(seat);
+            self.cabin.doors_open[door as usize] = true;
+            self.doors_close[door as usize] = Some(now + occupancy::DOOR_OPEN_TIME);
+            debug!("🪑 Door {} opened", door.as_str());
+        }
+
+        info!("🪑 Cabin change {} of seat {}: {} occupants, {} belted",
+            change.as_str(), seat.as_str(), self.cabin.occupants(), self.cabin.belted_occupants());
+        self.events.emit(
+            Severity::Info,
+            EventCode::OccupancyChanged,
+            format!("Cabin change {} of seat {}", change.as_str(), seat.as_str()),
+            &[
+                ("seat", seat.as_str().to_string()),
+                ("change", change.as_str().to_string()),
+                ("occupants", self.cabin.occupants().to_string()),
+                ("belted_occupants", self.cabin.belted_occupants().to_string()),
+            ],
+        );
+    }
+}
+
+impl Activity for OccupancyModel {
+    fn id(&self) -> ActivityId {
+        self.activity_id
+    }
+
+    fn startup(&mut self) {
+        self.events.started();
+        self.script = occupancy::load_script();
+        self.next_change = 0;
+        self.started = Some(std::time::Instant::now());
+        info!("🪑 OccupancyModel started with {} occupants, {} belted",
+            self.cabin.occupants(), self.cabin.belted_occupants());
+    }
+
+    fn step(&mut self) {
+        debug!("Stepping OccupancyModel");
+        sleep_random();
+
+        let now = std::time::Instant::now();
+        let elapsed = self.started.map(|started| started.elapsed()).unwrap_or_default();
+        // Standing still until the vehicle dynamics report a speed
+        let speed = self.input_vehicle_state.read().ok().map_or(0.0, |state| state.speed);
+        let standstill = speed < occupancy::STANDSTILL_SPEED;
+
+        // Changes apply in order, so a change waiting for standstill holds back the later ones
+        while let Some(&scripted) = self.script.get(self.next_change) {
+            if scripted.at > elapsed || (scripted.change.needs_standstill() && !standstill) {
+                break;
+            }
+            self.next_change += 1;
+            self.apply(scripted, now);
+        }
+
+        for (open, closes) in self.cabin.doors_open.iter_mut().zip(self.doors_close.iter_mut()) {
+            if closes.is_some_and(|closes| closes <= now) {
+                *open = false;
+                *closes = None;
+            }
+        }
+
+        if let Ok(output) = self.output_occupancy.write_uninit() {
+            debug!("Sending occupancy: {:?}", self.cabin);
+            let output = output.write_payload(self.cabin);
+            output.send().unwrap();
+        }
+    }
+
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
 }
 
 /// Neural network activity
@@ -296,6 +815,8 @@ impl Activity for Radar {
 pub struct NeuralNet {
     /// ID of the activity
     activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
     /// Image input
     input_image: Box<dyn ActivityInput<CameraImage>>,
     /// Radar scan input
@@ -313,6 +834,7 @@ impl NeuralNet {
     ) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("NeuralNet"),
             input_image: activity_input(image_topic),
             input_scan: activity_input(scan_topic),
             output_scene: activity_output(scene_topic),
@@ -351,7 +873,9 @@ impl Activity for NeuralNet {
     }
 
     #[instrument(name = "NeuralNet startup")]
-    fn startup(&mut self) {}
+    fn startup(&mut self) {
+        self.events.started();
+    }
 
     #[instrument(name = "NeuralNet")]
     fn step(&mut self) {
@@ -374,7 +898,9 @@ impl Activity for NeuralNet {
     }
 
     #[instrument(name = "NeuralNet shutdown")]
-    fn shutdown(&mut self) {}
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
 }
 
 /// Emergency braking activity
@@ -387,6 +913,8 @@ impl Activity for NeuralNet {
 pub struct EmergencyBraking {
     /// ID of the activity
     activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
     /// Scene input
     input_scene: Box<dyn ActivityInput<Scene>>,
     /// Brake instruction output
@@ -401,6 +929,7 @@ impl EmergencyBraking {
     ) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("EmergencyBraking"),
             input_scene: activity_input(scene_topic),
             output_brake_instruction: activity_output(brake_instruction_topic),
         })
@@ -413,7 +942,9 @@ impl Activity for EmergencyBraking {
     }
 
     #[instrument(name = "EmergencyBraking startup")]
-    fn startup(&mut self) {}
+    fn startup(&mut self) {
+        self.events.started();
+    }
 
     #[instrument(name = "EmergencyBraking")]
     fn step(&mut self) {
@@ -451,7 +982,9 @@ impl Activity for EmergencyBraking {
     }
 
     #[instrument(name = "EmergencyBraking shutdown")]
-    fn shutdown(&mut self) {}
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
 }
 
 /// Brake controller activity
@@ -460,21 +993,88 @@ impl Activity for EmergencyBraking {
 /// which triggers the brakes based on an instruction
 /// and therefore might run in a separate process
 /// with only other ASIL-D activities.
+///
+/// It actuates the brake and throttle instructions on a longitudinal
+/// vehicle dynamics model (mass, brake torque map, brake fade, driving
+/// resistances, road friction from the map) and publishes the resulting
+/// [VehicleState].
 #[derive(Debug)]
 pub struct BrakeController {
     /// ID of the activity
     activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
     /// Brake instruction input
     input_brake_instruction: Box<dyn ActivityInput<BrakeInstruction>>,
+    /// Throttle instruction input
+    input_throttle_instruction: Box<dyn ActivityInput<ThrottleInstruction>>,
+    /// Map attributes input
+    input_map: Box<dyn ActivityInput<MapAttributes>>,
+    /// Vehicle state output
+    output_vehicle_state: Box<dyn ActivityOutput<VehicleState>>,
+
+    // Integrated vehicle speed (m/s)
+    speed: f64,
+    // Integrated traction work (J)
+    drive_energy: f64,
+    // Integrated brake disc temperature (°C)
+    brake_temperature: f64,
+    last_step: Option<std::time::Instant>,
 }
 
 impl BrakeController {
-    pub fn build(activity_id: ActivityId, brake_instruction_topic: &str) -> Box<dyn Activity> {
+    pub fn build(
+        activity_id: ActivityId,
+        brake_instruction_topic: &str,
+        throttle_instruction_topic: &str,
+        map_topic: &str,
+        vehicle_state_topic: &str,
+    ) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("BrakeController"),
             input_brake_instruction: activity_input(brake_instruction_topic),
+            input_throttle_instruction: activity_input(throttle_instruction_topic),
+            input_map: activity_input(map_topic),
+            output_vehicle_state: activity_output(vehicle_state_topic),
+            speed: INITIAL_SPEED / 3.6,
+            drive_energy: 0.0,
+            brake_temperature: brake_thermal::AMBIENT_TEMPERATURE,
+            last_step: None,
         })
     }
+
+    /// Integrate the actuated commands over `dt` seconds
+    fn integrate(&mut self, brake_level: f64, throttle_level: f64, road_friction: f64, dt: f64) -> VehicleState {
+        // Neither drive nor brake force can exceed what the tires can transmit
+        let traction_limit = road_friction * VEHICLE_MASS * GRAVITY;
+        // Hot brakes deliver less torque for the same brake level
+        let brake_torque = brake_torque(brake_level) * brake_thermal::fade_factor(self.brake_temperature);
+        let brake_force = (brake_torque / WHEEL_RADIUS).min(traction_limit);
+        let drive_force = (throttle_level * MAX_DRIVE_FORCE).min(traction_limit);
+
+        let mut acceleration = (drive_force - brake_force - driving_resistance(self.speed)) / VEHICLE_MASS;
+        if self.speed <= 0.0 && acceleration < 0.0 {
+            // Brakes and resistances hold a standing vehicle, they do not move it backwards
+            acceleration = 0.0;
+        }
+        self.drive_energy += drive_force * self.speed * dt;
+        // A standing vehicle is held without dissipating energy
+        self.brake_temperature =
+            brake_thermal::next_temperature(self.brake_temperature, brake_force * self.speed, self.speed, dt);
+        self.speed = (self.speed + acceleration * dt).max(0.0);
+
+        VehicleState {
+            speed: self.speed * 3.6,
+            acceleration,
+            brake_torque,
+            brake_level,
+            throttle_level,
+            road_friction,
+            drive_energy: self.drive_energy / 3600.0,
+            brake_temperature: self.brake_temperature,
+        }
+    }
 }
 
 impl Activity for BrakeController {
@@ -483,25 +1083,56 @@ impl Activity for BrakeController {
     }
 
     #[instrument(name = "BrakeController startup")]
-    fn startup(&mut self) {}
+    fn startup(&mut self) {
+        self.events.started();
+    }
 
     #[instrument(name = "BrakeController")]
     fn step(&mut self) {
         debug!("Stepping BrakeController");
         sleep_random();
 
-        if let Ok(brake_instruction) = self.input_brake_instruction.read() {
-            if brake_instruction.active {
+        let now = std::time::Instant::now();
+        let dt = self
+            .last_step
+            .map_or(0.0, |last| (now - last).as_secs_f64())
+            .min(MAX_DYNAMICS_STEP);
+        self.last_step = Some(now);
+
+        let brake_level = match self.input_brake_instruction.read() {
+            Ok(brake_instruction) if brake_instruction.active => {
                 debug!(
                     "BrakeController activating brakes with level {:.3}",
                     brake_instruction.level
-                )
+                );
+                brake_instruction.level.clamp(0.0, 1.0)
             }
+            _ => 0.0,
+        };
+        // Braking always overrides the throttle
+        let throttle_level = match self.input_throttle_instruction.read() {
+            Ok(throttle_instruction) if brake_level == 0.0 => throttle_instruction.level.clamp(0.0, 1.0),
+            _ => 0.0,
+        };
+        let road_friction = self
+            .input_map
+            .read()
+            .map(|map| map.road_friction)
+            .unwrap_or(DEFAULT_ROAD_FRICTION);
+
+        let state = self.integrate(brake_level, throttle_level, road_friction, dt);
+        debug!("BrakeController vehicle state: {state:?}");
+
+        if let Ok(output) = self.output_vehicle_state.write_uninit() {
+            let output = output.write_payload(state);
+            output.send().unwrap();
         }
     }
 
     #[instrument(name = "BrakeController shutdown")]
-    fn shutdown(&mut self) {}
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
 }
 
 /// Environment renderer activity
@@ -513,6 +1144,8 @@ impl Activity for BrakeController {
 pub struct EnvironmentRenderer {
     /// ID of the activity
     activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
     /// Scene input
     input_scene: Box<dyn ActivityInput<Scene>>,
 }
@@ -521,6 +1154,7 @@ impl EnvironmentRenderer {
     pub fn build(activity_id: ActivityId, scene_topic: &str) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("EnvironmentRenderer"),
             input_scene: activity_input(scene_topic),
         })
     }
@@ -532,7 +1166,9 @@ impl Activity for EnvironmentRenderer {
     }
 
     #[instrument(name = "EnvironmentRenderer startup")]
-    fn startup(&mut self) {}
+    fn startup(&mut self) {
+        self.events.started();
+    }
 
     #[instrument(name = "EnvironmentRenderer")]
     fn step(&mut self) {
@@ -545,7 +1181,9 @@ impl Activity for EnvironmentRenderer {
     }
 
     #[instrument(name = "EnvironmentRenderer shutdown")]
-    fn shutdown(&mut self) {}
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
 }
 
 /// Steering controller activity
@@ -558,6 +1196,8 @@ impl Activity for EnvironmentRenderer {
 pub struct SteeringController {
     /// ID of the activity
     activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
     /// Steering input
     input_steering: Box<dyn ActivityInput<Steering>>,
 }
@@ -566,6 +1206,7 @@ impl SteeringController {
     pub fn build(activity_id: ActivityId, steering_topic: &str) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("SteeringController"),
             input_steering: activity_input(steering_topic),
         })
     }
@@ -577,7 +1218,9 @@ impl Activity for SteeringController {
     }
 
     #[instrument(name = "SteeringController startup")]
-    fn startup(&mut self) {}
+    fn startup(&mut self) {
+        self.events.started();
+    }
 
     #[instrument(name = "SteeringController")]
     fn step(&mut self) {
@@ -593,7 +1236,9 @@ impl Activity for SteeringController {
     }
 
     #[instrument(name = "SteeringController shutdown")]
-    fn shutdown(&mut self) {}
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
 }
 
 /// Car Mode Calculator activity
@@ -610,10 +1255,26 @@ impl Activity for SteeringController {
 pub struct CarModeCalculator {
     /// ID of the activity
     activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
     /// Scene input
     input_scene: Box<dyn ActivityInput<Scene>>,
+    /// Map attributes input
+    input_map: Box<dyn ActivityInput<MapAttributes>>,
+    /// V2X hazard input
+    input_v2x: Box<dyn ActivityInput<V2xHazard>>,
+    /// Vehicle state input, fed back from the BrakeController of the previous cycle
+    input_vehicle_state: Box<dyn ActivityInput<VehicleState>>,
+    /// Safe-state request of the activity supervision
+    input_safe_state: Box<dyn ActivityInput<SafeStateRequest>>,
     /// Car data output
     output_car_data: Box<dyn ActivityOutput<CarData>>,
+    /// Explanation of the last mode decision
+    output_explanation: Box<dyn ActivityOutput<ModeDecisionExplanation>>,
+    /// Brake instruction output
+    output_brake_instruction: Box<dyn ActivityOutput<BrakeInstruction>>,
+    /// Throttle instruction output
+    output_throttle_instruction: Box<dyn ActivityOutput<ThrottleInstruction>>,
 
     // Local state for mode calculation and smooth transitions
     current_mode: String,
@@ -627,6 +1288,30 @@ pub struct CarModeCalculator {
     target_speed: f64,
     steering_angle: f64,
     brake_force: f64,
+
+    // Reactions to the emergency mode, the brake force of the matching rules is the minimum braking
+    safe_state: SafeStateMatrix,
+
+    // Speed limit of the current road segment, if map data is available
+    speed_limit: Option<f64>,
+    speed_limit_violations: u32,
+    speeding: bool, // Above the limit in the last step, to report each violation once
+
+    // Hazard announced over V2X, forcing manual mode within its range
+    active_hazard: V2xHazard,
+
+    // Share of the brake torque delivered by the brakes, below 1.0 while they fade
+    brake_fade: f64,
+
+    // Safe state requested by the supervision, forcing emergency mode until the agents restart
+    safe_state_request: SafeStateRequest,
+
+    // Explanation of the last mode change, republished every step
+    decision_count: u64,
+    last_explanation: Option<ModeDecisionExplanation>,
+
+    // Recent mode changes as (timestamp in ms, mode), persisted across restarts
+    mode_history: VecDeque<(i64, String)>,
     
     // Minimum time between mode changes (1 minute)
     mode_change_cooldown: Duration,
@@ -636,12 +1321,27 @@ impl CarModeCalculator {
     pub fn build(
         activity_id: ActivityId,
         scene_topic: &str,
+        map_topic: &str,
+        v2x_topic: &str,
+        vehicle_state_topic: &str,
+        safe_state_topic: &str,
         car_data_topic: &str,
+        explanation_topic: &str,
+        brake_instruction_topic: &str,
+        throttle_instruction_topic: &str,
     ) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("CarModeCalculator"),
             input_scene: activity_input(scene_topic),
+            input_map: activity_input(map_topic),
+            input_v2x: activity_input(v2x_topic),
+            input_vehicle_state: activity_input(vehicle_state_topic),
+            input_safe_state: activity_input(safe_state_topic),
             output_car_data: activity_output(car_data_topic),
+            output_explanation: activity_output(explanation_topic),
+            output_brake_instruction: activity_output(brake_instruction_topic),
+            output_throttle_instruction: activity_output(throttle_instruction_topic),
             current_mode: "manual".to_string(),
             previous_published_mode: "".to_string(), // Initialize as empty to force first publish
             last_mode_change_time: None,
@@ -649,10 +1349,27 @@ impl CarModeCalculator {
             emergency_threshold: 4.0, // meters - emergency mode threshold
             
             // Initialize vehicle state for realistic behavior
-            current_speed: 50.0,  // Start at moderate highway speed
-            target_speed: 50.0,
+            current_speed: INITIAL_SPEED,  // Start at moderate highway speed
+            target_speed: INITIAL_SPEED,
             steering_angle: 0.0,
             brake_force: 0.0,
+
+            safe_state: SafeStateMatrix::builtin(), // Loaded from the matrix file in startup
+
+            speed_limit: None,
+            speed_limit_violations: 0,
+            speeding: false,
+
+            active_hazard: V2xHazard::default(),
+
+            brake_fade: 1.0,
+
+            safe_state_request: SafeStateRequest::default(),
+
+            decision_count: 0,
+            last_explanation: None,
+
+            mode_history: VecDeque::with_capacity(MODE_HISTORY_LEN),
             
             mode_change_cooldown: Duration::from_secs(15), // 15 seconds for testing (change to 60 for production)
         })
@@ -666,45 +1383,95 @@ impl Activity for CarModeCalculator {
 
     #[instrument(name = "CarModeCalculator startup")]
     fn startup(&mut self) {
+        self.events.started();
         info!("🚗 CarModeCalculator starting up - smooth mode transitions enabled");
         info!("⏱️ Mode change cooldown: {}s (minimum time between mode changes)", self.mode_change_cooldown.as_secs());
         info!("🎯 Current thresholds: emergency <{}m, manual <{}m or >4 people or >5 cars", 
               self.emergency_threshold, self.obstacle_threshold);
+        self.safe_state = SafeStateMatrix::load();
+        info!("🛟 Safe-state matrix {} active for emergency braking", self.safe_state.version);
+        self.restore_mode_state();
     }
 
     #[instrument(name = "CarModeCalculator step")]
     fn step(&mut self) {
+        if let Ok(map) = self.input_map.read() {
+            self.update_speed_limit(map.speed_limit, map.road_class);
+        }
+
+        if let Ok(hazard) = self.input_v2x.read() {
+            self.update_hazard(*hazard);
+        }
+
+        let vehicle_state = self.input_vehicle_state.read().ok().map(|state| *state);
+        if let Some(state) = &vehicle_state {
+            self.update_brake_fade(state.brake_temperature);
+        }
+
+        if let Ok(request) = self.input_safe_state.read() {
+            self.update_safe_state_request(*request);
+        }
+
         if let Ok(scene) = self.input_scene.read() {
             debug!("CarModeCalculator processing scene: people={}, cars={}, obstacle_distance={:.1}", 
                    scene.num_people, scene.num_cars, scene.distance_obstacle);
 
+            // Faded brakes need longer to stop, intervene earlier
+            let emergency_threshold = brake_thermal::earlier_threshold(self.emergency_threshold, self.brake_fade);
+            let obstacle_threshold = brake_thermal::earlier_threshold(self.obstacle_threshold, self.brake_fade);
+
             // Show detailed condition evaluation
-            let emergency_cond = scene.distance_obstacle < self.emergency_threshold;
-            let manual_distance_cond = scene.distance_obstacle < self.obstacle_threshold;
+            let emergency_cond = scene.distance_obstacle < emergency_threshold;
+            let manual_distance_cond = scene.distance_obstacle < obstacle_threshold;
             let manual_people_cond = scene.num_people > 4;
             let manual_cars_cond = scene.num_cars > 5;
+            let manual_hazard_cond = v2x::forces_manual(&self.active_hazard);
+            let safe_state_cond = self.safe_state_request.active;
             
-            debug!("🔍 Conditions: Emergency({}<{}): {}, Manual distance({}<{}): {}, people({}): {}, cars({}): {}", 
-                scene.distance_obstacle, self.emergency_threshold, emergency_cond,
-                scene.distance_obstacle, self.obstacle_threshold, manual_distance_cond,
+            debug!("🔍 Conditions: Emergency({}<{:.1}): {}, Manual distance({}<{:.1}): {}, people({}): {}, cars({}): {}, V2X({} {:.0}m): {}", 
+                scene.distance_obstacle, emergency_threshold, emergency_cond,
+                scene.distance_obstacle, obstacle_threshold, manual_distance_cond,
                 scene.num_people, manual_people_cond,
-                scene.num_cars, manual_cars_cond);
+                scene.num_cars, manual_cars_cond,
+                self.active_hazard.kind.as_str(), self.active_hazard.distance, manual_hazard_cond);
 
             // Determine potential new driving mode based on scene conditions
-            let potential_new_mode = if emergency_cond {
+            let potential_new_mode = if safe_state_cond || emergency_cond {
                 "emergency".to_string()
-            } else if manual_distance_cond || manual_people_cond || manual_cars_cond {
+            } else if manual_distance_cond || manual_people_cond || manual_cars_cond || manual_hazard_cond {
                 "manual".to_string()
             } else {
                 "autonomous".to_string()
             };
             
-            debug!("🎯 Potential new mode: {} (current: {})", potential_new_mode, self.current_mode);
+            // Rule behind the potential new mode, in the same order as evaluated above
+            let rule = if safe_state_cond {
+                format!("emergency: safe state requested, activity {} failed {} times in a row",
+                    self.safe_state_request.activity, self.safe_state_request.failures)
+            } else if emergency_cond {
+                format!("emergency: distance_obstacle {:.1} < {:.1}", scene.distance_obstacle, emergency_threshold)
+            } else if manual_distance_cond {
+                format!("manual: distance_obstacle {:.1} < {:.1}", scene.distance_obstacle, obstacle_threshold)
+            } else if manual_people_cond {
+                format!("manual: num_people {} > 4", scene.num_people)
+            } else if manual_cars_cond {
+                format!("manual: num_cars {} > 5", scene.num_cars)
+            } else if manual_hazard_cond {
+                format!("manual: V2X {} {:.0}m ahead <= {:.0}", self.active_hazard.kind.as_str(),
+                    self.active_hazard.distance, v2x::manual_range(self.active_hazard.kind))
+            } else {
+                "autonomous: no emergency or manual condition met".to_string()
+            };
+            
+            debug!("🎯 Potential new mode: {} (current: {}, rule: {})", potential_new_mode, self.current_mode, rule);
 
             // Check if enough time has passed since last mode change (except for emergency)
             let can_change_mode = if potential_new_mode == "emergency" {
                 info!("🚨 Emergency mode requested - bypassing cooldown");
                 true // Emergency mode can always be activated immediately
+            } else if manual_hazard_cond && potential_new_mode != self.current_mode {
+                info!("📡 V2X hazard ahead - bypassing cooldown for manual mode");
+                true // Hand over to the driver before reaching the hazard
             } else if let Some(last_change) = self.last_mode_change_time {
                 let elapsed = last_change.elapsed();
                 let can_change = elapsed >= self.mode_change_cooldown;
@@ -725,8 +1492,44 @@ impl Activity for CarModeCalculator {
                 info!("🔄 Mode transition APPROVED: {} → {} (conditions: distance={:.1}m, people={}, cars={})", 
                     self.current_mode, potential_new_mode, scene.distance_obstacle, scene.num_people, scene.num_cars);
                 
+                self.decision_count += 1;
+                self.last_explanation = Some(ModeDecisionExplanation {
+                    decision_id: self.decision_count,
+                    driving_mode: potential_new_mode.clone(),
+                    previous_mode: self.current_mode.clone(),
+                    rule: rule.clone(),
+                    num_people: scene.num_people as u32,
+                    num_cars: scene.num_cars as u32,
+                    distance_obstacle: scene.distance_obstacle,
+                    emergency_condition: emergency_cond,
+                    manual_distance_condition: manual_distance_cond,
+                    manual_people_condition: manual_people_cond,
+                    manual_cars_condition: manual_cars_cond,
+                    timestamp: std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap()
+                        .as_millis() as i64,
+                    schema_version: ModeDecisionExplanation::SCHEMA_VERSION,
+                });
+                info!("💡 Mode decision #{}: {}", self.decision_count, rule);
+                self.events.emit(
+                    if potential_new_mode == "emergency" { Severity::Critical } else { Severity::Info },
+                    EventCode::ModeChanged,
+                    format!("Mode changed from {} to {}", self.current_mode, potential_new_mode),
+                    &[
+                        ("decision_id", self.decision_count.to_string()),
+                        ("previous_mode", self.current_mode.clone()),
+                        ("mode", potential_new_mode.clone()),
+                        ("rule", rule.clone()),
+                        ("distance_obstacle", format!("{:.1}", scene.distance_obstacle)),
+                        ("num_people", scene.num_people.to_string()),
+                        ("num_cars", scene.num_cars.to_string()),
+                    ],
+                );
+
                 self.current_mode = potential_new_mode.clone();
                 self.last_mode_change_time = Some(std::time::Instant::now());
+                self.persist_mode_state();
                 info!("🕐 Next mode change allowed in {:.0} seconds", self.mode_change_cooldown.as_secs_f64());
                 
                 // Update vehicle behavior based on new mode
@@ -740,18 +1543,25 @@ impl Activity for CarModeCalculator {
                     self.current_mode, potential_new_mode, remaining);
             }
 
-            // Gradually adjust current speed towards target based on mode
-            self.adjust_vehicle_dynamics();
+            // Follow the actual speed of the vehicle and command brakes and throttle towards the target
+            let measured_speed = vehicle_state.map(|state| state.speed);
+            if self.current_mode == "emergency" {
+                // Track the scene, the brake force of the safe-state matrix depends on it
+                self.brake_force = self.safe_state.evaluate(&scene).brake_force;
+            }
+            self.adjust_vehicle_dynamics(measured_speed);
+            self.check_speed_limit();
+            self.send_longitudinal_commands();
 
             // Always publish car data so other components can read current mode
             // Only log mode changes to prevent spam, but always provide data
             if self.current_mode != self.previous_published_mode {
                 // Log current conditions and mode with clear reasoning (only on actual change)
                 match self.current_mode.as_str() {
-                    "emergency" => info!("🚨 EMERGENCY MODE: Critical distance {:.1}m < {}m - speed reduced to {:.0} km/h!", 
-                                       scene.distance_obstacle, self.emergency_threshold, self.current_speed),
+                    "emergency" => info!("🚨 EMERGENCY MODE: Critical distance {:.1}m < {:.1}m - speed reduced to {:.0} km/h!", 
+                                       scene.distance_obstacle, emergency_threshold, self.current_speed),
                     "manual" => {
-                        if scene.distance_obstacle < self.obstacle_threshold {
+                        if scene.distance_obstacle < obstacle_threshold {
                             info!("👤 MANUAL MODE: Close obstacle {:.1}m - human control, speed {:.0} km/h", 
                                  scene.distance_obstacle, self.current_speed);
                         } else if scene.num_people > 4 {
@@ -760,6 +1570,9 @@ impl Activity for CarModeCalculator {
                         } else if scene.num_cars > 5 {
                             info!("👤 MANUAL MODE: Dense vehicle traffic ({}) - speed {:.0} km/h", 
                                  scene.num_cars, self.current_speed);
+                        } else if v2x::forces_manual(&self.active_hazard) {
+                            info!("👤 MANUAL MODE: V2X {} {:.0}m ahead - speed {:.0} km/h",
+                                 self.active_hazard.kind.as_str(), self.active_hazard.distance, self.current_speed);
                         }
                     },
                     "autonomous" => info!("🤖 AUTONOMOUS MODE: Safe conditions - cruising at {:.0} km/h (distance {:.1}m)", 
@@ -777,6 +1590,13 @@ impl Activity for CarModeCalculator {
             if let Ok(car_data_output) = self.output_car_data.write_uninit() {
                 let car_data = CarData {
                     driving_mode: self.current_mode.clone(),
+                    schema_version: CarData::SCHEMA_VERSION,
+                    active_hazard: match self.active_hazard.kind {
+                        HazardKind::None => String::new(),
+                        kind => kind.as_str().to_string(),
+                    },
+                    hazard_distance: self.active_hazard.distance,
+                    run_id: crate::runs::current().run_id.clone(),
                 };
                 
                 let car_data_output = car_data_output.write_payload(car_data);
@@ -784,6 +1604,14 @@ impl Activity for CarModeCalculator {
                 debug!("📤 CarModeCalculator published CarData: {} (speed: {:.0} km/h)", 
                     self.current_mode, self.current_speed);
             }
+
+            // Republish the explanation of the last mode change alongside CarData
+            if let Some(explanation) = &self.last_explanation {
+                if let Ok(explanation_output) = self.output_explanation.write_uninit() {
+                    let explanation_output = explanation_output.write_payload(explanation.clone());
+                    explanation_output.send().unwrap();
+                }
+            }
         } else {
             debug!("CarModeCalculator: No scene data available");
         }
@@ -792,17 +1620,97 @@ impl Activity for CarModeCalculator {
     }
 
     #[instrument(name = "CarModeCalculator shutdown")]
-    fn shutdown(&mut self) {}
+    fn shutdown(&mut self) {
+        self.events.stopped();
+    }
 }
 
 impl CarModeCalculator {
+    /// Start from the last persisted driving mode and mode history
+    ///
+    /// State older than [MODE_STATE_MAX_AGE] is ignored and the calculator
+    /// starts in manual mode as before. A restored mode is subject to the
+    /// normal cooldown, so a restart does not cause an immediate mode change.
+    fn restore_mode_state(&mut self) {
+        let Some(value) = crate::persistency::get(MODE_STATE_KEY) else {
+            info!("💾 No persisted driving mode - starting in {} mode", self.current_mode);
+            return;
+        };
+        let Some((mode, timestamp)) = value
+            .split_once(';')
+            .and_then(|(mode, timestamp)| Some((mode, timestamp.parse::<i64>().ok()?)))
+            .filter(|(mode, _)| matches!(*mode, "autonomous" | "manual" | "emergency"))
+        else {
+            warn!("💾 Ignoring malformed persisted driving mode '{}'", value);
+            return;
+        };
+
+        let age = Duration::from_millis((now_ms() - timestamp).max(0) as u64);
+        if age > MODE_STATE_MAX_AGE {
+            info!("💾 Ignoring stale persisted driving mode '{}' ({}s old, limit {}s)",
+                mode, age.as_secs(), MODE_STATE_MAX_AGE.as_secs());
+            return;
+        }
+
+        self.current_mode = mode.to_string();
+        self.last_mode_change_time = Some(std::time::Instant::now());
+        self.mode_history = crate::persistency::get(MODE_HISTORY_KEY)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (timestamp, mode) = line.split_once(';')?;
+                Some((timestamp.parse().ok()?, mode.to_string()))
+            })
+            .collect();
+        info!("💾 Restored driving mode '{}' ({}s old) with {} history entries",
+            mode, age.as_secs(), self.mode_history.len());
+        self.events.emit(
+            Severity::Info,
+            EventCode::ModeRestored,
+            format!("Restored driving mode {}", mode),
+            &[
+                ("mode", mode.to_string()),
+                ("age_secs", age.as_secs().to_string()),
+                ("history_entries", self.mode_history.len().to_string()),
+            ],
+        );
+    }
+
+    /// Persist the current driving mode and append it to the mode history
+    fn persist_mode_state(&mut self) {
+        let timestamp = now_ms();
+        if self.mode_history.len() >= MODE_HISTORY_LEN {
+            self.mode_history.pop_front();
+        }
+        self.mode_history.push_back((timestamp, self.current_mode.clone()));
+
+        let history = self
+            .mode_history
+            .iter()
+            .map(|(timestamp, mode)| format!("{};{}", timestamp, mode))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let state = format!("{};{}", self.current_mode, timestamp);
+        if !crate::persistency::put(MODE_STATE_KEY, &state)
+            || !crate::persistency::put(MODE_HISTORY_KEY, &history)
+        {
+            warn!("💾 Driving mode '{}' not persisted", self.current_mode);
+            self.events.emit(
+                Severity::Error,
+                EventCode::ModePersistFailed,
+                format!("Driving mode {} not persisted", self.current_mode),
+                &[("mode", self.current_mode.clone())],
+            );
+        }
+    }
+
     /// Update vehicle behavior parameters based on driving mode
     fn update_vehicle_behavior(&mut self, mode: &str, scene: &Scene) {
         match mode {
             "emergency" => {
                 // Emergency: immediate speed reduction, heavy braking
                 self.target_speed = (self.current_speed * 0.3).max(10.0); // Reduce to 30% or minimum 10 km/h
-                self.brake_force = 80.0; // Heavy braking
+                self.brake_force = self.safe_state.evaluate(scene).brake_force; // Heavy braking of the safe-state matrix
                 info!("🚨 Emergency behavior: Target speed reduced to {:.0} km/h, brake force {:.0}%", 
                     self.target_speed, self.brake_force);
             },
@@ -818,10 +1726,8 @@ impl CarModeCalculator {
                 info!("👤 Manual behavior: Target speed {:.0} km/h, variable driving", self.target_speed);
             },
             "autonomous" => {
-                // Autonomous: steady, efficient cruising speed
-                let base_speed = 65.0; // Higher base speed for autonomous
-                self.target_speed = base_speed + random_walk_float(0.0, 0.1, 5.0); // Very stable speed
-                self.target_speed = self.target_speed.clamp(60.0, 75.0);
+                // Autonomous: steady, efficient cruising speed within the speed limit
+                self.target_speed = self.autonomous_target();
                 self.brake_force = random_walk_float(0.0, 0.1, 5.0).clamp(0.0, 15.0); // Minimal braking
                 info!("🤖 Autonomous behavior: Steady cruising at {:.0} km/h", self.target_speed);
             },
@@ -829,18 +1735,200 @@ impl CarModeCalculator {
         }
     }
 
-    /// Gradually adjust vehicle dynamics towards target values
-    fn adjust_vehicle_dynamics(&mut self) {
-        // Smooth speed adjustment (not instant)
-        let speed_diff = self.target_speed - self.current_speed;
-        self.current_speed += speed_diff * 0.1; // 10% adjustment per step for smooth transition
-        
-        // Clamp to realistic ranges
-        self.current_speed = self.current_speed.clamp(5.0, 120.0);
+    /// Autonomous target speed, lowered in the [eco] sub-mode
+    fn autonomous_target(&self) -> f64 {
+        let target = autonomous_target_speed(self.speed_limit);
+        if eco::enabled() {
+            eco::target_speed(target, self.speed_limit)
+        } else {
+            target
+        }
+    }
+
+    /// Track the speed limit of the current road segment
+    ///
+    /// In autonomous mode the target speed follows the new limit immediately,
+    /// other modes pick it up on their next behavior update.
+    fn update_speed_limit(&mut self, speed_limit: f64, road_class: RoadClass) {
+        if self.speed_limit == Some(speed_limit) {
+            return;
+        }
+
+        info!("🗺️ Speed limit changed to {:.0} km/h ({:?} road)", speed_limit, road_class);
+        self.events.emit(
+            Severity::Info,
+            EventCode::SpeedLimitChanged,
+            format!("Speed limit changed to {:.0} km/h", speed_limit),
+            &[
+                ("speed_limit", format!("{:.0}", speed_limit)),
+                ("road_class", format!("{:?}", road_class)),
+            ],
+        );
+        self.speed_limit = Some(speed_limit);
+
+        if self.current_mode == "autonomous" {
+            self.target_speed = self.autonomous_target();
+            info!("🤖 Autonomous behavior: Target speed adapted to {:.0} km/h", self.target_speed);
+        }
+    }
+
+    /// Track the hazard announced over V2X
+    /// Follow the fade of the brakes at the measured brake `temperature` (°C)
+    fn update_brake_fade(&mut self, temperature: f64) {
+        let was_fading = self.brake_fade < 1.0;
+        self.brake_fade = brake_thermal::fade_factor(temperature);
+        if brake_thermal::is_fading(temperature) == was_fading {
+            return;
+        }
+
+        if was_fading {
+            info!("🔥 Brakes cooled down to {:.0} °C, full brake torque available again", temperature);
+            self.events.emit(
+                Severity::Info,
+                EventCode::BrakeFadeCleared,
+                format!("Brakes cooled down to {:.0} °C", temperature),
+                &[("brake_temperature", format!("{:.0}", temperature))],
+            );
+        } else {
+            warn!("🔥 Brake fade at {:.0} °C - intervening earlier, emergency threshold {:.1}m",
+                temperature, brake_thermal::earlier_threshold(self.emergency_threshold, self.brake_fade));
+            self.events.emit(
+                Severity::Warning,
+                EventCode::BrakeFadeActive,
+                format!("Brake fade at {:.0} °C", temperature),
+                &[
+                    ("brake_temperature", format!("{:.0}", temperature)),
+                    ("brake_fade", format!("{:.2}", self.brake_fade)),
+                ],
+            );
+        }
+    }
+
+    /// Latch the first active safe-state request of the supervision
+    fn update_safe_state_request(&mut self, request: SafeStateRequest) {
+        if !request.active || self.safe_state_request.active {
+            return;
+        }
+        error!("🛑 Safe state requested: activity {} failed {} times in a row - switching to emergency mode",
+            request.activity, request.failures);
+        self.safe_state_request = request;
+    }
+
+    fn update_hazard(&mut self, hazard: V2xHazard) {
+        if hazard.kind != self.active_hazard.kind {
+            match hazard.kind {
+                HazardKind::None => info!("📡 V2X hazard {} cleared", self.active_hazard.kind.as_str()),
+                kind => info!("📡 V2X hazard {} {:.0}m ahead (manual mode within {:.0}m)",
+                    kind.as_str(), hazard.distance, v2x::manual_range(kind)),
+            }
+        }
+        self.active_hazard = hazard;
+    }
+
+    /// Log autonomous driving above the speed limit
+    fn check_speed_limit(&mut self) {
+        if self.current_mode != "autonomous" {
+            self.speeding = false;
+            return;
+        }
+
+        let mut speeding = false;
+        if let Some(limit) = self.speed_limit {
+            if self.current_speed > limit + SPEED_LIMIT_TOLERANCE {
+                speeding = true;
+                self.speed_limit_violations += 1;
+                warn!("⚠️ Speed limit violation: {:.0} km/h in {:.0} km/h zone (violations: {})",
+                    self.current_speed, limit, self.speed_limit_violations);
+                if !self.speeding {
+                    self.events.emit(
+                        Severity::Warning,
+                        EventCode::SpeedLimitViolation,
+                        format!("{:.0} km/h in {:.0} km/h zone", self.current_speed, limit),
+                        &[
+                            ("speed", format!("{:.0}", self.current_speed)),
+                            ("speed_limit", format!("{:.0}", limit)),
+                            ("violations", self.speed_limit_violations.to_string()),
+                        ],
+                    );
+                }
+            }
+        }
+        self.speeding = speeding;
+    }
+
+    /// Track the vehicle speed and adjust the remaining vehicle dynamics
+    fn adjust_vehicle_dynamics(&mut self, measured_speed: Option<f64>) {
+        match measured_speed {
+            // Speed integrated by the BrakeController from the actuated commands
+            Some(speed) => self.current_speed = speed,
+            None => {
+                // No feedback yet (first cycle): approximate a smooth speed adjustment
+                let speed_diff = self.target_speed - self.current_speed;
+                self.current_speed += speed_diff * 0.1; // 10% adjustment per step for smooth transition
+                self.current_speed = self.current_speed.clamp(5.0, 120.0);
+            }
+        }
         
         // Adjust steering angle slightly for realism
         self.steering_angle = random_walk_float(self.steering_angle, 0.1, 2.0).clamp(-10.0, 10.0);
     }
+
+    /// Brake and throttle levels steering the current speed towards the target speed
+    ///
+    /// The throttle compensates the driving resistances at the current speed
+    /// plus a proportional share of the speed deficit. The brakes engage once
+    /// the vehicle is more than [BRAKE_DEADBAND] above the target, and at
+    /// least with the mode's brake force in emergency mode. In the [eco]
+    /// sub-mode of the autonomous mode the vehicle coasts above the target
+    /// and accelerates more gently.
+    fn longitudinal_commands(&self) -> (f64, f64) {
+        let error = self.target_speed - self.current_speed;
+        let emergency_brake = if self.current_mode == "emergency" { self.brake_force / 100.0 } else { 0.0 };
+        let eco = eco::enabled() && self.current_mode == "autonomous";
+        let (deadband, gain, max_throttle) = if eco {
+            (eco::COAST_BAND, eco::THROTTLE_GAIN, eco::MAX_THROTTLE)
+        } else {
+            (BRAKE_DEADBAND, THROTTLE_GAIN, 1.0)
+        };
+        // Faded brakes start braking closer to the target speed
+        let deadband = deadband * self.brake_fade;
+
+        let brake = if error < -deadband {
+            (-error - deadband) * BRAKE_GAIN
+        } else {
+            0.0
+        }
+        .max(emergency_brake);
+        // Raise the level to make up for the torque lost to brake fade
+        let brake = brake_thermal::compensated_level(brake, self.brake_fade).clamp(0.0, 1.0);
+
+        let throttle = if brake > 0.0 || (eco && error < 0.0) {
+            0.0 // Braking, or coasting down to the eco target
+        } else {
+            let cruise = driving_resistance(self.current_speed / 3.6) / MAX_DRIVE_FORCE;
+            (cruise + error * gain).clamp(0.0, max_throttle)
+        };
+        (brake, throttle)
+    }
+
+    /// Send the brake and throttle instructions to the BrakeController
+    fn send_longitudinal_commands(&mut self) {
+        let (brake, throttle) = self.longitudinal_commands();
+        debug!("🦶 Longitudinal commands: brake {:.2}, throttle {:.2} (speed {:.1} → {:.1} km/h)",
+            brake, throttle, self.current_speed, self.target_speed);
+
+        if let Ok(output) = self.output_brake_instruction.write_uninit() {
+            let output = output.write_payload(BrakeInstruction {
+                active: brake > 0.0,
+                level: brake,
+            });
+            output.send().unwrap();
+        }
+        if let Ok(output) = self.output_throttle_instruction.write_uninit() {
+            let output = output.write_payload(ThrottleInstruction { level: throttle });
+            output.send().unwrap();
+        }
+    }
 }
 
 /// Car Data Publisher activity
@@ -849,23 +1937,36 @@ impl CarModeCalculator {
 /// to receive the current driving mode.
 pub struct CarDataPublisher {
     activity_id: ActivityId,
+    events: EventLogger, // Structured events on the EventLog topic
     input_car_data: Box<dyn ActivityInput<CarData>>,
+    input_explanation: Box<dyn ActivityInput<ModeDecisionExplanation>>,
     writer: Option<DataWriter<CarData>>,
+    explanation_writer: Option<DataWriter<ModeDecisionExplanation>>,
     participant: Option<DomainParticipant>, // Own participant for clean restart
+    qos_monitor: QosMonitor, // Reports readers rejected for incompatible QoS
     last_published_mode: Option<String>, // Track last published mode to prevent spam
+    last_published_hazard: Option<String>, // Track last published V2X hazard to prevent spam
+    last_published_decision: Option<u64>, // Track last published explanation to prevent spam
 }
 
 impl CarDataPublisher {
     pub fn build(
         activity_id: ActivityId,
         car_data_topic: &str,
+        explanation_topic: &str,
     ) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("CarDataPublisher"),
             input_car_data: activity_input(car_data_topic),
+            input_explanation: activity_input(explanation_topic),
             writer: None,
+            explanation_writer: None,
             participant: None, // Will be created in startup
+            qos_monitor: QosMonitor::new("CarDataPublisher"),
             last_published_mode: None, // Initialize as None
+            last_published_hazard: None,
+            last_published_decision: None,
         })
     }
 }
@@ -876,6 +1977,7 @@ impl Activity for CarDataPublisher {
     }
 
     fn startup(&mut self) {
+        self.events.started();
         info!("🚀 CarDataPublisher started, initializing DDS with INDIVIDUAL participant for clean restart...");
         
         // Create individual DDS participant for this component - prevents shared state issues
@@ -916,6 +2018,25 @@ impl Activity for CarDataPublisher {
         let writer = publisher
             .create_datawriter::<CarData>(
                 &topic,
+                QosKind::Specific(writer_qos.clone()),
+                None,
+                &[],
+            )
+            .unwrap();
+
+        let explanation_topic = participant
+            .create_topic::<ModeDecisionExplanation>(
+                "ModeDecisionExplanation",
+                "ModeDecisionExplanation",
+                QosKind::Default,
+                None,
+                &[],
+            )
+            .unwrap();
+
+        let explanation_writer = publisher
+            .create_datawriter::<ModeDecisionExplanation>(
+                &explanation_topic,
                 QosKind::Specific(writer_qos),
                 None,
                 &[],
@@ -925,6 +2046,8 @@ impl Activity for CarDataPublisher {
         info!("📡 CarDataPublisher setup with INDIVIDUAL participant - Enhanced QoS & clean restart support");
         
         self.writer = Some(writer);
+        self.explanation_writer = Some(explanation_writer);
+        self.qos_monitor.startup(&participant);
         self.participant = Some(participant); // Store for clean shutdown
         
         thread::sleep(Duration::from_millis(200));
@@ -932,12 +2055,22 @@ impl Activity for CarDataPublisher {
     }
 
     fn step(&mut self) {
+        // Detect subscribers that never receive data due to QoS mismatches
+        if self.qos_monitor.due() {
+            if let Some(writer) = &self.writer {
+                self.qos_monitor.check("CarData", writer);
+            }
+            if let Some(writer) = &self.explanation_writer {
+                self.qos_monitor.check("ModeDecisionExplanation", writer);
+            }
+        }
+
         if let Ok(car_data) = self.input_car_data.read() {
             // Check if this is a new/different mode compared to what we last published
             let should_publish = match &self.last_published_mode {
                 Some(last_mode) => last_mode != &car_data.driving_mode,
                 None => true, // First publish
-            };
+            } || self.last_published_hazard.as_ref() != Some(&car_data.active_hazard);
             
             if should_publish {
                 info!("📡 NEW MODE DETECTED - Publishing CarData to DDS for pullpiri: {} → {}", 
@@ -946,6 +2079,10 @@ impl Activity for CarDataPublisher {
                 if let Some(writer) = &mut self.writer {
                     let dds_car_data = CarData {
                         driving_mode: car_data.driving_mode.clone(),
+                        schema_version: CarData::SCHEMA_VERSION,
+                        active_hazard: car_data.active_hazard.clone(),
+                        hazard_distance: car_data.hazard_distance,
+                        run_id: car_data.run_id.clone(),
                     };
 
                     info!("🌐 [DDS] Sending NEW CarData for pullpiri integration: {:?}", dds_car_data);
@@ -956,12 +2093,14 @@ impl Activity for CarDataPublisher {
                         Ok(_) => {
                             info!("✅ [DDS] NEW CarData successfully published to topic 'CarData'");
                             self.last_published_mode = Some(car_data.driving_mode.clone());
+                            self.last_published_hazard = Some(car_data.active_hazard.clone());
                         },
                         Err(_) => {
                             // Silently handle - data is stored in TransientLocal durability anyway
                             // Subscribers will get it when they join
                             debug!("📝 [DDS] CarData cached in TransientLocal (no active subscribers)");
                             self.last_published_mode = Some(car_data.driving_mode.clone());
+                            self.last_published_hazard = Some(car_data.active_hazard.clone());
                         },
                     }
                 }
@@ -972,12 +2111,29 @@ impl Activity for CarDataPublisher {
             debug!("CarDataPublisher: No car data available to publish");
         }
 
+        // Publish the explanation once per mode decision
+        if let Ok(explanation) = self.input_explanation.read() {
+            if self.last_published_decision != Some(explanation.decision_id) {
+                if let Some(writer) = &mut self.explanation_writer {
+                    info!("💡 [DDS] Publishing mode decision #{}: {} → {} ({})",
+                        explanation.decision_id, explanation.previous_mode, explanation.driving_mode, explanation.rule);
+                    if writer.write(&*explanation, None).is_err() {
+                        debug!("📝 [DDS] ModeDecisionExplanation cached in TransientLocal (no active subscribers)");
+                    }
+                    self.last_published_decision = Some(explanation.decision_id);
+                }
+            }
+        }
+
         sleep_random();
     }
 
     fn shutdown(&mut self) {
+        self.events.stopped();
         info!("🔄 CarDataPublisher shutting down - cleaning up individual DDS participant");
         self.writer = None;
+        self.explanation_writer = None;
+        self.qos_monitor.shutdown();
         self.participant = None; // Clean shutdown of individual participant
     }
 }
@@ -988,11 +2144,16 @@ impl Activity for CarDataPublisher {
 /// when the car is in autonomous mode.
 pub struct AutonomousModePublisher {
     activity_id: ActivityId,
+    events: EventLogger, // Structured events on the EventLog topic
     input_car_data: Box<dyn ActivityInput<CarData>>,
     input_scene: Box<dyn ActivityInput<Scene>>,
+    input_map: Box<dyn ActivityInput<MapAttributes>>,
+    input_vehicle_state: Box<dyn ActivityInput<VehicleState>>,
     writer: Option<DataWriter<AutonomousCarData>>,
     participant: Option<DomainParticipant>, // Own participant for clean restart
+    qos_monitor: QosMonitor, // Reports readers rejected for incompatible QoS
     discovery_counter: u32, // Counter for periodic subscriber re-discovery
+    speed_limit_violations: u32, // Published samples above the speed limit
 }
 
 impl AutonomousModePublisher {
@@ -1000,15 +2161,22 @@ impl AutonomousModePublisher {
         activity_id: ActivityId,
         car_data_topic: &str,
         scene_topic: &str,
+        map_topic: &str,
+        vehicle_state_topic: &str,
         _autonomous_data_topic: &str,
     ) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("AutonomousModePublisher"),
             input_car_data: activity_input(car_data_topic),
             input_scene: activity_input(scene_topic),
+            input_map: activity_input(map_topic),
+            input_vehicle_state: activity_input(vehicle_state_topic),
             writer: None,
             participant: None, // Will be created in startup
+            qos_monitor: QosMonitor::new("AutonomousModePublisher"),
             discovery_counter: 0, // Initialize discovery counter
+            speed_limit_violations: 0,
         })
     }
 }
@@ -1019,6 +2187,7 @@ impl Activity for AutonomousModePublisher {
     }
 
     fn startup(&mut self) {
+        self.events.started();
         info!("🤖 AutonomousModePublisher started with INDIVIDUAL participant - publishes IMMEDIATELY when autonomous mode is active");
         
         // Create individual DDS participant for this component - prevents shared state issues  
@@ -1065,6 +2234,7 @@ impl Activity for AutonomousModePublisher {
             .unwrap();
 
         self.writer = Some(writer);
+        self.qos_monitor.startup(&participant);
         self.participant = Some(participant); // Store for clean shutdown
         
         // Longer initial discovery time to ensure subscribers are detected after restarts
@@ -1074,6 +2244,13 @@ impl Activity for AutonomousModePublisher {
     }
 
     fn step(&mut self) {
+        // Detect subscribers that never receive data due to QoS mismatches
+        if self.qos_monitor.due() {
+            if let Some(writer) = &self.writer {
+                self.qos_monitor.check("AutonomousCarData", writer);
+            }
+        }
+
         // Increment discovery counter for periodic subscriber re-discovery
         self.discovery_counter += 1;
         
@@ -1083,9 +2260,11 @@ impl Activity for AutonomousModePublisher {
             thread::sleep(Duration::from_millis(50)); // Brief pause for discovery refresh
         }
         
-        // Try to read both inputs
+        // Try to read all inputs
         let car_data_result = self.input_car_data.read();
         let scene_result = self.input_scene.read();
+        let speed_limit = self.input_map.read().ok().map(|map| map.speed_limit);
+        let vehicle_state = self.input_vehicle_state.read().ok().map(|state| *state);
         
         // Check if we're in autonomous mode either from car_data or by calculating from scene
         let is_autonomous_mode = if let Ok(car_data) = &car_data_result {
@@ -1121,7 +2300,26 @@ impl Activity for AutonomousModePublisher {
                     
                     // Autonomous systems maintain steady, efficient speeds
                     let realistic_speed = optimal_speed - (scene.num_cars as f64 * 0.5); // Slight reduction for traffic
-                    let final_speed = realistic_speed.clamp(45.0, 75.0);
+                    let target_speed = match speed_limit {
+                        Some(limit) => realistic_speed.min(autonomous_target_speed(Some(limit))),
+                        None => realistic_speed.clamp(45.0, 75.0),
+                    };
+                    let target_speed = if eco::enabled() {
+                        eco::target_speed(target_speed, speed_limit)
+                    } else {
+                        target_speed
+                    };
+
+                    // Actual speed from the vehicle dynamics, the target until it is available
+                    let final_speed = vehicle_state.map_or(target_speed, |state| state.speed);
+
+                    if let Some(limit) = speed_limit {
+                        if final_speed > limit + SPEED_LIMIT_TOLERANCE {
+                            self.speed_limit_violations += 1;
+                            warn!("⚠️ Autonomous speed {:.0} km/h exceeds limit {:.0} km/h (violations: {})",
+                                final_speed, limit, self.speed_limit_violations);
+                        }
+                    }
                     
                     // Autonomous systems make precise, minimal steering adjustments
                     let realistic_steering = if scene.distance_left_lane < scene.distance_right_lane {
@@ -1145,18 +2343,25 @@ impl Activity for AutonomousModePublisher {
                     };
 
                     let autonomous_data = AutonomousCarData {
-                        vehicle_speed: final_speed, // Real speed based on conditions
+                        vehicle_speed: final_speed, // Actual speed from the vehicle dynamics
                         lane_position: (realistic_steering as f64 / 10.0).clamp(-0.5f64, 0.5f64), // Real lane position
                         obstacle_detected: scene.distance_obstacle < 30.0,
                         obstacle_distance: scene.distance_obstacle,
                         traffic_signal: if scene.num_cars > 5 { "red".to_string() } else { "green".to_string() },
                         steering_angle: realistic_steering, // Real precise steering
-                        brake_force: realistic_brake, // Real braking based on distance
-                        acceleration: realistic_acceleration, // Real smooth acceleration
+                        brake_force: vehicle_state.map_or(realistic_brake, |state| state.brake_level * 100.0),
+                        acceleration: vehicle_state.map_or(realistic_acceleration, |state| state.acceleration),
                         weather_condition: "clear".to_string(),
-                        road_condition: "dry".to_string(),
+                        road_condition: road_condition(vehicle_state.map_or(DEFAULT_ROAD_FRICTION, |state| state.road_friction)),
                         timestamp: current_time,
                         is_valid: true,
+                        speed_limit: speed_limit.unwrap_or(0.0),
+                        speed_limit_violations: self.speed_limit_violations,
+                        schema_version: AutonomousCarData::SCHEMA_VERSION,
+                        run_id: crate::runs::current().run_id.clone(),
+                        eco_mode: eco::enabled(),
+                        estimated_energy_savings: if eco::enabled() { eco_energy_savings(speed_limit) } else { 0.0 },
+                        drive_energy: vehicle_state.map_or(0.0, |state| state.drive_energy),
                     };
 
                     // Check subscriber count for better restart detection
@@ -1189,8 +2394,10 @@ impl Activity for AutonomousModePublisher {
     }
 
     fn shutdown(&mut self) {
+        self.events.stopped();
         info!("🔄 AutonomousModePublisher shutting down - cleaning up individual DDS participant");
         self.writer = None;
+        self.qos_monitor.shutdown();
         self.participant = None; // Clean shutdown of individual participant
     }
 }
@@ -1201,10 +2408,13 @@ impl Activity for AutonomousModePublisher {
 /// when the car is in manual mode.
 pub struct ManualModePublisher {
     activity_id: ActivityId,
+    events: EventLogger, // Structured events on the EventLog topic
     input_car_data: Box<dyn ActivityInput<CarData>>,
     input_scene: Box<dyn ActivityInput<Scene>>,
+    input_vehicle_state: Box<dyn ActivityInput<VehicleState>>,
     writer: Option<DataWriter<ManualCarData>>,
     participant: Option<DomainParticipant>, // Own participant for clean restart
+    qos_monitor: QosMonitor, // Reports readers rejected for incompatible QoS
     discovery_counter: u32, // Counter for periodic subscriber re-discovery
 }
 
@@ -1213,14 +2423,18 @@ impl ManualModePublisher {
         activity_id: ActivityId,
         car_data_topic: &str,
         scene_topic: &str,
+        vehicle_state_topic: &str,
         _manual_data_topic: &str,
     ) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("ManualModePublisher"),
             input_car_data: activity_input(car_data_topic),
             input_scene: activity_input(scene_topic),
+            input_vehicle_state: activity_input(vehicle_state_topic),
             writer: None,
             participant: None, // Will be created in startup
+            qos_monitor: QosMonitor::new("ManualModePublisher"),
             discovery_counter: 0, // Initialize discovery counter
         })
     }
@@ -1232,6 +2446,7 @@ impl Activity for ManualModePublisher {
     }
 
     fn startup(&mut self) {
+        self.events.started();
         info!("👤 ManualModePublisher started with INDIVIDUAL participant - publishes IMMEDIATELY when manual mode is active");
         
         // Create individual DDS participant for this component - prevents shared state issues
@@ -1277,6 +2492,7 @@ impl Activity for ManualModePublisher {
             .unwrap();
 
         self.writer = Some(writer);
+        self.qos_monitor.startup(&participant);
         self.participant = Some(participant); // Store for clean shutdown
         
         // Longer initial discovery time to ensure subscribers are detected after restarts
@@ -1286,6 +2502,13 @@ impl Activity for ManualModePublisher {
     }
 
     fn step(&mut self) {
+        // Detect subscribers that never receive data due to QoS mismatches
+        if self.qos_monitor.due() {
+            if let Some(writer) = &self.writer {
+                self.qos_monitor.check("ManualCarData", writer);
+            }
+        }
+
         // Increment discovery counter for periodic subscriber re-discovery
         self.discovery_counter += 1;
         
@@ -1295,9 +2518,10 @@ impl Activity for ManualModePublisher {
             thread::sleep(Duration::from_millis(50)); // Brief pause for discovery refresh
         }
         
-        // Try to read both inputs
+        // Try to read all inputs
         let car_data_result = self.input_car_data.read();
         let scene_result = self.input_scene.read();
+        let vehicle_state = self.input_vehicle_state.read().ok().map(|state| *state);
         
         // Check if we're in manual mode either from car_data or by calculating from scene
         let is_manual_mode = if let Ok(car_data) = &car_data_result {
@@ -1351,17 +2575,23 @@ impl Activity for ManualModePublisher {
                         0.0 // Maintain speed
                     };
 
+                    // Actual values from the vehicle dynamics, scene-based estimates until it is available
                     let manual_data = ManualCarData {
-                        vehicle_speed: realistic_speed, // Real speed based on conditions
+                        vehicle_speed: vehicle_state.map_or(realistic_speed, |state| state.speed),
                         steering_angle: realistic_steering, // Real steering based on traffic
-                        brake_force: realistic_brake, // Real braking based on obstacles
-                        acceleration: realistic_acceleration, // Real acceleration based on distance
+                        brake_force: vehicle_state.map_or(realistic_brake, |state| state.brake_level * 100.0),
+                        acceleration: vehicle_state.map_or(realistic_acceleration, |state| state.acceleration),
                         weather_condition: "clear".to_string(),
-                        road_condition: "dry".to_string(),
+                        road_condition: road_condition(vehicle_state.map_or(DEFAULT_ROAD_FRICTION, |state| state.road_friction)),
                         driver_alertness: true, // assume driver is alert
-                        throttle_position: (realistic_speed / 65.0 * 100.0).clamp(20.0, 80.0), // Realistic throttle
+                        throttle_position: vehicle_state.map_or(
+                            (realistic_speed / 65.0 * 100.0).clamp(20.0, 80.0),
+                            |state| state.throttle_level * 100.0,
+                        ),
                         timestamp: current_time,
                         is_valid: true,
+                        schema_version: ManualCarData::SCHEMA_VERSION,
+                        run_id: crate::runs::current().run_id.clone(),
                     };
 
                     // Check subscriber count for better restart detection
@@ -1394,8 +2624,10 @@ impl Activity for ManualModePublisher {
     }
 
     fn shutdown(&mut self) {
+        self.events.stopped();
         info!("🔄 ManualModePublisher shutting down - cleaning up individual DDS participant");
         self.writer = None;
+        self.qos_monitor.shutdown();
         self.participant = None; // Clean shutdown of individual participant
     }
 }
@@ -1404,13 +2636,19 @@ impl Activity for ManualModePublisher {
 ///
 /// This activity publishes comprehensive emergency driving data with DDS output
 /// when the car is in emergency mode, including seatbelt tightening and safety features.
+/// Seatbelts and airbags react only for the occupants of the [Occupancy].
 pub struct EmergencyModePublisher {
     activity_id: ActivityId,
+    events: EventLogger, // Structured events on the EventLog topic
     input_car_data: Box<dyn ActivityInput<CarData>>,
     input_scene: Box<dyn ActivityInput<Scene>>,
+    input_vehicle_state: Box<dyn ActivityInput<VehicleState>>,
+    input_occupancy: Box<dyn ActivityInput<Occupancy>>,
     writer: Option<DataWriter<EmergencyModeData>>,
     participant: Option<DomainParticipant>, // Own participant for clean restart
+    qos_monitor: QosMonitor, // Reports readers rejected for incompatible QoS
     discovery_counter: u32, // Counter for periodic subscriber re-discovery
+    safe_state: SafeStateMatrix, // Reactions reported for the emergency mode
 }
 
 impl EmergencyModePublisher {
@@ -1418,15 +2656,22 @@ impl EmergencyModePublisher {
         activity_id: ActivityId,
         car_data_topic: &str,
         scene_topic: &str,
+        vehicle_state_topic: &str,
+        occupancy_topic: &str,
         _emergency_data_topic: &str,
     ) -> Box<dyn Activity> {
         Box::new(Self {
             activity_id,
+            events: EventLogger::new("EmergencyModePublisher"),
             input_car_data: activity_input(car_data_topic),
             input_scene: activity_input(scene_topic),
+            input_vehicle_state: activity_input(vehicle_state_topic),
+            input_occupancy: activity_input(occupancy_topic),
             writer: None,
             participant: None, // Will be created in startup
+            qos_monitor: QosMonitor::new("EmergencyModePublisher"),
             discovery_counter: 0, // Initialize discovery counter
+            safe_state: SafeStateMatrix::builtin(), // Loaded from the matrix file in startup
         })
     }
 }
@@ -1437,7 +2682,10 @@ impl Activity for EmergencyModePublisher {
     }
 
     fn startup(&mut self) {
+        self.events.started();
         info!("🚨 EmergencyModePublisher started with INDIVIDUAL participant - publishes IMMEDIATELY when emergency mode is active");
+        self.safe_state = SafeStateMatrix::load();
+        info!("🛟 Safe-state matrix {} active for emergency reactions", self.safe_state.version);
         
         // Create individual DDS participant for this component - prevents shared state issues
         let participant = create_dds_participant();
@@ -1483,6 +2731,7 @@ impl Activity for EmergencyModePublisher {
             .unwrap();
 
         self.writer = Some(writer);
+        self.qos_monitor.startup(&participant);
         self.participant = Some(participant); // Store for clean shutdown
         
         // Longer initial discovery time to ensure subscribers are detected after restarts
@@ -1492,6 +2741,13 @@ impl Activity for EmergencyModePublisher {
     }
 
     fn step(&mut self) {
+        // Detect subscribers that never receive data due to QoS mismatches
+        if self.qos_monitor.due() {
+            if let Some(writer) = &self.writer {
+                self.qos_monitor.check("EmergencyModeData", writer);
+            }
+        }
+
         // Increment discovery counter for periodic subscriber re-discovery
         self.discovery_counter += 1;
         
@@ -1501,9 +2757,12 @@ impl Activity for EmergencyModePublisher {
             thread::sleep(Duration::from_millis(50)); // Brief pause for discovery refresh
         }
         
-        // Try to read both inputs
+        // Try to read all inputs
         let car_data_result = self.input_car_data.read();
         let scene_result = self.input_scene.read();
+        let vehicle_state = self.input_vehicle_state.read().ok().map(|state| *state);
+        // The cabin at startup until the occupancy model publishes
+        let cabin = self.input_occupancy.read().ok().map_or_else(occupancy::initial_cabin, |cabin| *cabin);
         
         // Check if we're in emergency mode either from car_data or by calculating from scene
         let is_emergency_mode = if let Ok(car_data) = &car_data_result {
@@ -1554,11 +2813,16 @@ impl Activity for EmergencyModePublisher {
                         (10.0 - scene.distance_obstacle) * 10.0 
                     };
                     
-                    let emergency_brake_force = if scene.distance_obstacle < 3.0 { 
-                        100.0 
-                    } else { 
-                        (5.0 - scene.distance_obstacle) * 20.0 
-                    };
+                    // Reactions of the safe-state matrix, its brake force is the minimum the CarModeCalculator commands
+                    let safe_state = self.safe_state.evaluate(&scene);
+                    let emergency_brake_force = safe_state.brake_force;
+                    // Only the seatbelts of belted occupants and the airbags of occupied seats react
+                    let restraints = occupancy::restraints(&cabin, safe_state.seatbelt_tightened, safe_state.airbag_ready);
+
+                    // Actual values from the vehicle dynamics, scene-based estimates until it is available
+                    let emergency_speed = vehicle_state.map_or(emergency_speed, |state| state.speed);
+                    let emergency_brake_force = vehicle_state.map_or(emergency_brake_force, |state| state.brake_level * 100.0);
+                    let brake_temperature = vehicle_state.map_or(brake_thermal::AMBIENT_TEMPERATURE, |state| state.brake_temperature);
 
                     let emergency_type = if scene.num_people > 0 && scene.distance_obstacle < 5.0 {
                         "collision_avoidance".to_string()
@@ -1569,7 +2833,7 @@ impl Activity for EmergencyModePublisher {
                     };
 
                     let emergency_data = EmergencyModeData {
-                        vehicle_speed: emergency_speed, // Real emergency speed based on danger level
+                        vehicle_speed: emergency_speed, // Actual speed from the vehicle dynamics
                         steering_angle: emergency_steering, // Real emergency steering based on obstacles
                         brake_force: emergency_brake_force.max(0.0).min(100.0),
                         obstacle_detected: true,
@@ -1577,17 +2841,26 @@ impl Activity for EmergencyModePublisher {
                         collision_risk: collision_risk.max(0.0).min(100.0),
                         stability_control: true, // always active in emergency
                         traffic_signal: "emergency".to_string(), // emergency override
-                        seatbelt_tightened: true, // ⚠️ SEATBELT TIGHTENING ACTIVATED
-                        emergency_lights: true, // hazard lights on
+                        seatbelt_tightened: restraints.seatbelts_tightened > 0, // ⚠️ SEATBELT TIGHTENING of the safe-state matrix, for belted occupants
+                        emergency_lights: safe_state.hazard_lights, // hazard lights
                         emergency_type,
                         emergency_brake_force: emergency_brake_force.max(0.0).min(100.0),
-                        airbag_ready: true, // airbag systems primed
+                        airbag_ready: restraints.airbag_ready, // airbag systems of the occupied seats primed
                         timestamp: current_time,
                         is_valid: true,
+                        schema_version: EmergencyModeData::SCHEMA_VERSION,
+                        safe_state_version: self.safe_state.version.clone(),
+                        run_id: crate::runs::current().run_id.clone(),
+                        brake_temperature,
+                        brake_fade: brake_thermal::fade_factor(brake_temperature) * 100.0,
+                        occupants: cabin.occupants(),
+                        belted_occupants: cabin.belted_occupants(),
+                        passenger_airbag_ready: restraints.passenger_airbag_ready,
                     };
 
-                    debug!("[DDS] 🚨 EMERGENCY MODE: Seatbelts tightened, airbags ready, emergency braking: {:.1}%", 
-                           emergency_data.emergency_brake_force);
+                    debug!("[DDS] 🚨 EMERGENCY MODE ({}): {} occupants, {} seatbelts tightened, airbags ready {} (passenger {}), emergency braking: {:.1}%",
+                           emergency_data.safe_state_version, emergency_data.occupants, restraints.seatbelts_tightened,
+                           emergency_data.airbag_ready, emergency_data.passenger_airbag_ready, emergency_data.emergency_brake_force);
                     
                     // Check subscriber count for better restart detection
                     let subscriber_count = writer.get_publication_matched_status()
@@ -1619,37 +2892,263 @@ impl Activity for EmergencyModePublisher {
     }
 
     fn shutdown(&mut self) {
+        self.events.stopped();
         info!("🔄 EmergencyModePublisher shutting down - cleaning up individual DDS participant");
         self.writer = None;
+        self.qos_monitor.shutdown();
         self.participant = None; // Clean shutdown of individual participant
     }
 }
 
-/// Create an activity input.
+/// Scenario validator activity
+///
+/// This activity checks the driving modes published during each run of a
+/// camera scenario against the expectations of [validation], if enabled, and
+/// reports every result and a summary per run on the event log.
+#[derive(Debug)]
+pub struct ScenarioValidator {
+    /// ID of the activity
+    activity_id: ActivityId,
+    /// Structured event output
+    events: EventLogger,
+    /// Active scenario input
+    input_scenario: Box<dyn ActivityInput<ActiveScenario>>,
+    /// Car data input
+    input_car_data: Box<dyn ActivityInput<CarData>>,
+
+    // Expectations, None while the validation is off
+    expectations: Option<Vec<Expectation>>,
+    // Run being validated with its start and the last published mode
+    run: Option<(ScenarioRun, std::time::Instant)>,
+    mode: String,
+    // Expectations passed and failed since startup
+    passed: usize,
+    failed: usize,
+}
+
+impl ScenarioValidator {
+    pub fn build(activity_id: ActivityId, scenario_topic: &str, car_data_topic: &str) -> Box<dyn Activity> {
+        Box::new(Self {
+            activity_id,
+            events: EventLogger::new("ScenarioValidator"),
+            input_scenario: activity_input(scenario_topic),
+            input_car_data: activity_input(car_data_topic),
+            expectations: None, // Loaded in startup
+            run: None,
+            mode: String::new(),
+            passed: 0,
+            failed: 0,
+        })
+    }
+
+    fn report(&mut self, outcomes: Vec<Outcome>) {
+        for outcome in outcomes {
+            let description = outcome.expectation.describe();
+            let after = format!("{:.1}", outcome.after.as_secs_f64());
+            if outcome.passed {
+                self.passed += 1;
+                info!("✅ Scenario validation PASSED: {} (after {}s)", description, after);
+            } else {
+                self.failed += 1;
+                error!("❌ Scenario validation FAILED: {} (mode {} after {}s)", description, outcome.mode, after);
+            }
+            self.events.emit(
+                if outcome.passed { Severity::Info } else { Severity::Error },
+                if outcome.passed { EventCode::ValidationPassed } else { EventCode::ValidationFailed },
+                description,
+                &[
+                    ("scenario", outcome.expectation.scenario.as_str().to_string()),
+                    ("expected_modes", outcome.expectation.modes.join(",")),
+                    ("within_s", outcome.expectation.within.as_secs_f64().to_string()),
+                    ("mode", outcome.mode.clone()),
+                    ("after_s", after),
+                ],
+            );
+        }
+    }
+
+    /// Fail the open expectations of the current run and report its summary
+    fn finish_run(&mut self) {
+        let Some((mut run, started)) = self.run.take() else {
+            return;
+        };
+        let outcomes = run.finish(&self.mode, started.elapsed());
+        self.report(outcomes);
+        let (passed, failed) = run.tally();
+        if passed + failed == 0 {
+            return;
+        }
+        let message = format!(
+            "{} run {}: {} of {} expectations passed",
+            run.scenario.as_str(), run.run, passed, passed + failed
+        );
+        if failed == 0 {
+            info!("🎬 {}", message);
+        } else {
+            error!("🎬 {}", message);
+        }
+        self.events.emit(
+            if failed == 0 { Severity::Info } else { Severity::Error },
+            EventCode::ValidationSummary,
+            message,
+            &[
+                ("scenario", run.scenario.as_str().to_string()),
+                ("run", run.run.to_string()),
+                ("passed", passed.to_string()),
+                ("failed", failed.to_string()),
+                ("total_passed", self.passed.to_string()),
+                ("total_failed", self.failed.to_string()),
+            ],
+        );
+    }
+}
+
+impl Activity for ScenarioValidator {
+    fn id(&self) -> ActivityId {
+        self.activity_id
+    }
+
+    #[instrument(name = "ScenarioValidator startup")]
+    fn startup(&mut self) {
+        self.events.started();
+        self.expectations = validation::load();
+        if self.expectations.is_none() {
+            debug!("🎬 Scenario validation off, set {} to enable it", validation::SCENARIO_VALIDATION_ENV);
+        }
+    }
+
+    #[instrument(name = "ScenarioValidator")]
+    fn step(&mut self) {
+        let Some(expectations) = &self.expectations else {
+            return;
+        };
+
+        if let Ok(car_data) = self.input_car_data.read() {
+            self.mode = car_data.driving_mode.clone();
+        }
+        let Ok(active) = self.input_scenario.read() else {
+            return;
+        };
+        let (scenario, run) = (active.scenario, active.run);
+
+        let current = self.run.as_ref().map(|(current, _)| (current.scenario, current.run));
+        if current != Some((scenario, run)) {
+            let new_run = ScenarioRun::new(scenario, run, expectations);
+            self.finish_run();
+            debug!("🎬 Validating {} run {}", scenario.as_str(), run);
+            self.run = Some((new_run, std::time::Instant::now()));
+        }
+
+        let outcomes = match &mut self.run {
+            Some((current, started)) if !self.mode.is_empty() => current.observe(&self.mode, started.elapsed()),
+            _ => Vec::new(),
+        };
+        self.report(outcomes);
+    }
+
+    #[instrument(name = "ScenarioValidator shutdown")]
+    fn shutdown(&mut self) {
+        // The last run was cut short, its open expectations are neither passed nor failed
+        self.run = None;
+        if self.expectations.is_some() {
+            info!("🎬 Scenario validation: {} expectations passed, {} failed", self.passed, self.failed);
+        }
+        self.events.stopped();
+    }
+}
+
+/// Autonomous cruising speed for the given speed limit
+///
+/// Cruise slightly below the limit. Without map data, fall back to the
+/// default highway cruising band of 60-75 km/h.
+fn autonomous_target_speed(speed_limit: Option<f64>) -> f64 {
+    let cruise = random_walk_float(0.0, 0.1, 5.0); // Very stable speed
+    match speed_limit {
+        Some(limit) => (limit * 0.95 + cruise).clamp(limit * 0.8, limit),
+        None => (65.0 + cruise).clamp(60.0, 75.0),
+    }
+}
+
+/// Brake torque for a brake level, linearly interpolated in [BRAKE_TORQUE_MAP]
+fn brake_torque(level: f64) -> f64 {
+    let level = level.clamp(0.0, 1.0);
+    BRAKE_TORQUE_MAP
+        .windows(2)
+        .find(|pair| level <= pair[1].0)
+        .map(|pair| {
+            let ((l0, t0), (l1, t1)) = (pair[0], pair[1]);
+            t0 + (t1 - t0) * (level - l0) / (l1 - l0)
+        })
+        .unwrap_or(0.0)
+}
+
+/// Rolling resistance and aerodynamic drag at `speed` m/s (N)
+fn driving_resistance(speed: f64) -> f64 {
+    if speed <= 0.0 {
+        return 0.0;
+    }
+    ROLLING_RESISTANCE * VEHICLE_MASS * GRAVITY + DRAG_FACTOR * speed * speed
+}
+
+/// Drive energy per distance saved by eco cruising over normal cruising (%)
+///
+/// At a constant speed the drive energy per distance equals the driving
+/// resistance, compared at the cruising speeds of both modes without their
+/// random variation.
+fn eco_energy_savings(speed_limit: Option<f64>) -> f64 {
+    let normal = speed_limit.map_or(65.0, |limit| limit * 0.95);
+    let eco = eco::target_speed(normal, speed_limit);
+    (1.0 - driving_resistance(eco / 3.6) / driving_resistance(normal / 3.6)) * 100.0
+}
+
+/// Road condition reported to DDS subscribers for a friction coefficient
+fn road_condition(road_friction: f64) -> String {
+    if road_friction >= 0.75 {
+        "dry".to_string()
+    } else if road_friction >= 0.4 {
+        "wet".to_string()
+    } else {
+        "icy".to_string()
+    }
+}
+
+/// Current wall clock time in milliseconds since the Unix epoch
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+/// Create an activity input on the com backend selected at startup.
 fn activity_input<T>(topic: &str) -> Box<dyn ActivityInput<T>>
 where
     T: fmt::Debug + 'static,
 {
-    #[cfg(feature = "com_iox2")]
-    return Box::new(Iox2Input::new(topic));
-    #[cfg(feature = "com_linux_shm")]
-    return Box::new(LinuxShmInput::new(topic));
+    match com_backend() {
+        #[cfg(feature = "com_iox2")]
+        ComBackend::Iox2 => Box::new(Iox2Input::new(topic)),
+        ComBackend::LinuxShm => Box::new(LinuxShmInput::new(topic)),
+    }
 }
 
-/// Create an activity output.
-fn activity_output<T>(topic: &str) -> Box<dyn ActivityOutput<T>>
+/// Create an activity output on the com backend selected at startup.
+pub(crate) fn activity_output<T>(topic: &str) -> Box<dyn ActivityOutput<T>>
 where
     T: fmt::Debug + 'static,
 {
-    #[cfg(feature = "com_iox2")]
-    return Box::new(Iox2Output::new(topic));
-    #[cfg(feature = "com_linux_shm")]
-    return Box::new(LinuxShmOutput::new(topic));
+    match com_backend() {
+        #[cfg(feature = "com_iox2")]
+        ComBackend::Iox2 => Box::new(Iox2Output::new(topic)),
+        ComBackend::LinuxShm => Box::new(LinuxShmOutput::new(topic)),
+    }
 }
 
 /// Generate a pseudo-random number in the specified range.
+///
+/// Drawn from the deterministic stream of the current step in a seeded run, see [`crate::runs`].
 fn gen_random_in_range(range: Range<i64>) -> i64 {
-    let rand = RandomState::new().build_hasher().finish();
+    let rand = crate::runs::next_u64().unwrap_or_else(|| RandomState::new().build_hasher().finish());
     let rand = (rand % (i64::MAX as u64)) as i64;
     rand % (range.end - range.start + 1) + range.start
 }
@@ -0,0 +1,74 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Energy-aware autonomous driving
+//!
+//! With `MINI_ADAS_ECO_MODE=on` the autonomous mode drives in an eco sub-mode
+//! that trades some travel time for less drive energy of the vehicle
+//! dynamics model:
+//!
+//! - it cruises at [`CRUISE_FACTOR`] of the normal autonomous target speed,
+//!   as the aerodynamic drag grows with the square of the speed, but never
+//!   below 80% of the speed limit
+//! - above the target speed it coasts and lets the driving resistances slow
+//!   the vehicle down, braking only [`COAST_BAND`] km/h above the target
+//! - it accelerates with [`THROTTLE_GAIN`], capped at [`MAX_THROTTLE`]
+//!
+//! Manual and emergency driving are not affected. The variable is read by
+//! the CarModeCalculator and the AutonomousModePublisher, so all agents of a
+//! deployment must be started with the same value.
+
+use feo_log::{info, warn};
+use std::sync::OnceLock;
+
+/// Environment variable enabling the eco sub-mode with `on`
+pub const ECO_MODE_ENV: &str = "MINI_ADAS_ECO_MODE";
+
+/// Share of the normal autonomous target speed cruised at
+pub const CRUISE_FACTOR: f64 = 0.9;
+
+/// Speed above the target that is coasted down before braking (km/h)
+pub const COAST_BAND: f64 = 8.0;
+
+/// Throttle per km/h below the target speed
+pub const THROTTLE_GAIN: f64 = 0.02;
+
+/// Highest throttle level
+pub const MAX_THROTTLE: f64 = 0.5;
+
+/// Whether the autonomous mode drives in the eco sub-mode, read once
+pub fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+
+    *ENABLED.get_or_init(|| match std::env::var(ECO_MODE_ENV).as_deref() {
+        Ok("on") => {
+            info!("🌱 Autonomous mode drives in the eco sub-mode");
+            true
+        }
+        Ok("off") | Err(_) => false,
+        Ok(other) => {
+            warn!("🌱 Unknown {ECO_MODE_ENV} '{other}', expected 'on' or 'off', eco sub-mode off");
+            false
+        }
+    })
+}
+
+/// Eco target speed for the normal autonomous `target` speed (km/h)
+pub fn target_speed(target: f64, speed_limit: Option<f64>) -> f64 {
+    let eco = target * CRUISE_FACTOR;
+    match speed_limit {
+        // Do not hold up the traffic behind
+        Some(limit) => eco.max(limit * 0.8).min(target),
+        None => eco,
+    }
+}
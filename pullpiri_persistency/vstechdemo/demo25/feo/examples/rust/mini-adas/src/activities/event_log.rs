@@ -0,0 +1,308 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Structured event log of the activities
+//!
+//! feo_log messages are text for humans, spread over the logs of several
+//! processes. In addition, activities report notable events on the DDS topic
+//! [`EVENT_LOG_TOPIC`] as [`EventLog`] samples with a stable code and a
+//! context map, so that the console apps can offer a single machine-readable
+//! feed of the whole pipeline.
+//!
+//! Every activity owns an [`EventLogger`] naming it as the component. Events
+//! are queued to a background thread of the process that owns its own DDS
+//! participant, so that emitting an event never blocks a step on DDS. When
+//! the queue is full the event is dropped; the number of dropped events is
+//! added to the context of the next event that fits.
+
+use crate::activities::messages::{EventContext, EventLog, Versioned};
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use dust_dds::{
+    domain::domain_participant_factory::DomainParticipantFactory,
+    infrastructure::qos::{DataWriterQos, QosKind},
+    infrastructure::qos_policy::{
+        DurabilityQosPolicy, DurabilityQosPolicyKind, HistoryQosPolicy, HistoryQosPolicyKind,
+        ReliabilityQosPolicy, ReliabilityQosPolicyKind,
+    },
+};
+use feo_log::{debug, info, warn};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// DDS topic and type name of the events
+pub const EVENT_LOG_TOPIC: &str = "EventLog";
+
+/// DDS domain shared with the console apps
+const DDS_DOMAIN_ID: i32 = 100;
+
+/// Events waiting for the publishing thread
+const QUEUE_LEN: usize = 256;
+
+/// Events kept for console apps started later
+const HISTORY_DEPTH: u32 = 50;
+
+/// Severity of an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Debug => "debug",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// Stable code of an event, for filtering without parsing messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventCode {
+    ActivityStarted,
+    ActivityStopped,
+    ActivityFailed,
+    ActivityRestarted,
+    SafeStateRequested,
+    ScenarioChanged,
+    RouteSegmentEntered,
+    V2xWarningRejected,
+    V2xHazardActive,
+    V2xHazardCleared,
+    OccupancyChanged,
+    ModeChanged,
+    ModeRestored,
+    ModePersistFailed,
+    SpeedLimitChanged,
+    SpeedLimitViolation,
+    BrakeFadeActive,
+    BrakeFadeCleared,
+    QosIncompatible,
+    QosCompatible,
+    ValidationPassed,
+    ValidationFailed,
+    ValidationSummary,
+}
+
+impl EventCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventCode::ActivityStarted => "ACTIVITY_STARTED",
+            EventCode::ActivityStopped => "ACTIVITY_STOPPED",
+            EventCode::ActivityFailed => "ACTIVITY_FAILED",
+            EventCode::ActivityRestarted => "ACTIVITY_RESTARTED",
+            EventCode::SafeStateRequested => "SAFE_STATE_REQUESTED",
+            EventCode::ScenarioChanged => "SCENARIO_CHANGED",
+            EventCode::RouteSegmentEntered => "ROUTE_SEGMENT_ENTERED",
+            EventCode::V2xWarningRejected => "V2X_WARNING_REJECTED",
+            EventCode::V2xHazardActive => "V2X_HAZARD_ACTIVE",
+            EventCode::V2xHazardCleared => "V2X_HAZARD_CLEARED",
+            EventCode::OccupancyChanged => "OCCUPANCY_CHANGED",
+            EventCode::ModeChanged => "MODE_CHANGED",
+            EventCode::ModeRestored => "MODE_RESTORED",
+            EventCode::ModePersistFailed => "MODE_PERSIST_FAILED",
+            EventCode::SpeedLimitChanged => "SPEED_LIMIT_CHANGED",
+            EventCode::SpeedLimitViolation => "SPEED_LIMIT_VIOLATION",
+            EventCode::BrakeFadeActive => "BRAKE_FADE_ACTIVE",
+            EventCode::BrakeFadeCleared => "BRAKE_FADE_CLEARED",
+            EventCode::QosIncompatible => "QOS_INCOMPATIBLE",
+            EventCode::QosCompatible => "QOS_COMPATIBLE",
+            EventCode::ValidationPassed => "VALIDATION_PASSED",
+            EventCode::ValidationFailed => "VALIDATION_FAILED",
+            EventCode::ValidationSummary => "VALIDATION_SUMMARY",
+        }
+    }
+}
+
+/// Queue of the publishing thread
+struct Sink {
+    sender: SyncSender<EventLog>,
+    sequence: AtomicU64,
+    dropped: AtomicU32,
+}
+
+static SINK: OnceLock<Option<Sink>> = OnceLock::new();
+
+/// Queue of the process, starting the publishing thread on first use
+fn sink() -> Option<&'static Sink> {
+    SINK.get_or_init(|| {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_LEN);
+        thread::Builder::new()
+            .name("event-log".to_string())
+            .spawn(move || publish(receiver))
+            .map_err(|e| warn!("📋 Cannot start the {EVENT_LOG_TOPIC} publisher: {e:?}"))
+            .ok()?;
+        Some(Sink {
+            sender,
+            sequence: AtomicU64::new(0),
+            dropped: AtomicU32::new(0),
+        })
+    })
+    .as_ref()
+}
+
+/// Publish the queued events until the process exits
+fn publish(receiver: Receiver<EventLog>) {
+    let participant = match DomainParticipantFactory::get_instance().create_participant(
+        DDS_DOMAIN_ID,
+        QosKind::Default,
+        None,
+        &[],
+    ) {
+        Ok(participant) => participant,
+        Err(e) => {
+            warn!("📋 Cannot create the {EVENT_LOG_TOPIC} participant: {e:?}");
+            return;
+        }
+    };
+    let writer_qos = DataWriterQos {
+        reliability: ReliabilityQosPolicy {
+            kind: ReliabilityQosPolicyKind::BestEffort,
+            max_blocking_time: dust_dds::infrastructure::time::DurationKind::Finite(
+                dust_dds::infrastructure::time::Duration::new(0, 100_000_000),
+            ),
+        },
+        durability: DurabilityQosPolicy {
+            kind: DurabilityQosPolicyKind::TransientLocal, // Console apps started later see the recent events
+        },
+        history: HistoryQosPolicy {
+            kind: HistoryQosPolicyKind::KeepLast(HISTORY_DEPTH),
+        },
+        ..Default::default()
+    };
+    let writer = participant
+        .create_topic::<EventLog>(
+            EVENT_LOG_TOPIC,
+            EVENT_LOG_TOPIC,
+            QosKind::Default,
+            None,
+            &[],
+        )
+        .and_then(|topic| {
+            participant
+                .create_publisher(QosKind::Default, None, &[])?
+                .create_datawriter::<EventLog>(&topic, QosKind::Specific(writer_qos), None, &[])
+        });
+    let writer = match writer {
+        Ok(writer) => writer,
+        Err(e) => {
+            warn!("📋 Cannot create the {EVENT_LOG_TOPIC} writer: {e:?}");
+            return;
+        }
+    };
+    info!("📋 Publishing structured events on DDS topic '{EVENT_LOG_TOPIC}'");
+
+    while let Ok(event) = receiver.recv() {
+        if writer.write(&event, None).is_err() {
+            debug!("📝 [DDS] {EVENT_LOG_TOPIC} cached in TransientLocal (no active subscribers)");
+        }
+    }
+}
+
+/// Emitter of the events of one activity
+#[derive(Debug, Clone, Copy)]
+pub struct EventLogger {
+    component: &'static str,
+}
+
+impl EventLogger {
+    /// ### Parameters
+    /// * `component` - name of the owning activity, as shown in the events
+    pub const fn new(component: &'static str) -> Self {
+        Self { component }
+    }
+
+    /// Queue an event for publishing, without waiting for DDS
+    ///
+    /// ### Parameters
+    /// * `severity` - severity of the event
+    /// * `code` - stable code of the event
+    /// * `message` - human-readable description
+    /// * `context` - inputs and values behind the event as key and value
+    pub fn emit(
+        &self,
+        severity: Severity,
+        code: EventCode,
+        message: impl Into<String>,
+        context: &[(&str, String)],
+    ) {
+        let Some(sink) = sink() else {
+            return;
+        };
+        let mut context: Vec<EventContext> = context
+            .iter()
+            .map(|(key, value)| EventContext {
+                key: key.to_string(),
+                value: value.clone(),
+            })
+            .collect();
+        let dropped = sink.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            context.push(EventContext {
+                key: "dropped_events".to_string(),
+                value: dropped.to_string(),
+            });
+        }
+
+        let event = EventLog {
+            component: self.component.to_string(),
+            severity: severity.as_str().to_string(),
+            code: code.as_str().to_string(),
+            message: message.into(),
+            context,
+            sequence: sink.sequence.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64,
+            schema_version: EventLog::SCHEMA_VERSION,
+        };
+        match sink.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                sink.dropped.fetch_add(dropped + 1, Ordering::Relaxed);
+            }
+            // The publishing thread failed to start DDS and already logged why
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Report the startup of the activity
+    pub fn started(&self) {
+        self.emit(
+            Severity::Info,
+            EventCode::ActivityStarted,
+            format!("{} started", self.component),
+            &[],
+        );
+    }
+
+    /// Report the shutdown of the activity
+    pub fn stopped(&self) {
+        self.emit(
+            Severity::Info,
+            EventCode::ActivityStopped,
+            format!("{} stopped", self.component),
+            &[],
+        );
+    }
+}
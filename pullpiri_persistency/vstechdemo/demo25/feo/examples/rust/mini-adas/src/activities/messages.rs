@@ -24,6 +24,9 @@ use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 use dust_dds::topic_definition::type_support::DdsType;
 
+// Vehicle data shared with the DDS console apps
+pub use vehicle_types::{AutonomousCarData, CarData, EmergencyModeData, ManualCarData, Versioned};
+
 /// Camera image
 ///
 /// A neural network could detect the number of people,
@@ -67,6 +70,56 @@ pub struct Scene {
     pub distance_right_lane: f64,
 }
 
+/// Demo scenario
+///
+/// Traffic pattern the [CameraImage]s are currently generated for.
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum DemoScenario {
+    #[default]
+    Highway,
+    City,
+    Suburban,
+    EmergencyTest,
+}
+
+impl DemoScenario {
+    /// Name used in logs and validation expectations
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DemoScenario::Highway => "highway",
+            DemoScenario::City => "city",
+            DemoScenario::Suburban => "suburban",
+            DemoScenario::EmergencyTest => "emergency_test",
+        }
+    }
+
+    /// Parse the name used in logs and validation expectations
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "highway" => Some(DemoScenario::Highway),
+            "city" => Some(DemoScenario::City),
+            "suburban" => Some(DemoScenario::Suburban),
+            "emergency_test" => Some(DemoScenario::EmergencyTest),
+            _ => None,
+        }
+    }
+}
+
+/// Active scenario
+///
+/// Scenario of the camera, published every step. `run` is incremented
+/// whenever a scenario starts, so that a scenario following itself is
+/// recognized as a new run.
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct ActiveScenario {
+    pub scenario: DemoScenario,
+    pub run: u32,
+}
+
 /// Brake instruction
 ///
 /// This is an instruction whether to engage the brakes and at which level.
@@ -78,6 +131,16 @@ pub struct BrakeInstruction {
     pub level: f64,
 }
 
+/// Throttle instruction
+///
+/// This is an instruction how far to open the throttle (0.0 - 1.0).
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct ThrottleInstruction {
+    pub level: f64,
+}
+
 /// Steering
 ///
 /// This carries the angle of steering.
@@ -88,6 +151,225 @@ pub struct Steering {
     pub angle: f64,
 }
 
+/// Road class
+///
+/// Class of the road segment the vehicle is currently driving on.
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum RoadClass {
+    #[default]
+    Highway,
+    Rural,
+    Suburban,
+    Urban,
+}
+
+/// Map attributes
+///
+/// Attributes of the current road segment as provided by the route simulator.
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct MapAttributes {
+    pub speed_limit: f64, // km/h
+    pub road_class: RoadClass,
+    pub road_friction: f64, // tire-road friction coefficient
+}
+
+/// Vehicle state
+///
+/// Longitudinal state of the vehicle as computed by the dynamics model
+/// from the actuated brake and throttle instructions.
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct VehicleState {
+    pub speed: f64,          // km/h
+    pub acceleration: f64,   // m/s²
+    pub brake_torque: f64,   // Nm, sum over all wheels
+    pub brake_level: f64,    // actuated brake level (0.0 - 1.0)
+    pub throttle_level: f64, // actuated throttle level (0.0 - 1.0)
+    pub road_friction: f64,  // tire-road friction coefficient
+    pub drive_energy: f64,   // Wh of traction work since startup
+    pub brake_temperature: f64, // °C of the brake discs
+}
+
+/// Kind of a hazard announced over V2X
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum HazardKind {
+    #[default]
+    None,
+    RoadWorks,
+    AccidentAhead,
+    EmergencyVehicle,
+}
+
+impl HazardKind {
+    /// Name used in V2X scripts and DDS messages
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HazardKind::None => "none",
+            HazardKind::RoadWorks => "road_works",
+            HazardKind::AccidentAhead => "accident_ahead",
+            HazardKind::EmergencyVehicle => "emergency_vehicle",
+        }
+    }
+
+    /// Parse the name used in V2X scripts and DDS messages
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(HazardKind::None),
+            "road_works" => Some(HazardKind::RoadWorks),
+            "accident_ahead" => Some(HazardKind::AccidentAhead),
+            "emergency_vehicle" => Some(HazardKind::EmergencyVehicle),
+            _ => None,
+        }
+    }
+}
+
+/// V2X hazard
+///
+/// The most relevant hazard warning currently received over V2X,
+/// [HazardKind::None] if there is none.
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct V2xHazard {
+    pub kind: HazardKind,
+    pub distance: f64, // meters ahead
+}
+
+/// Safe-state request
+///
+/// Raised by the activity supervision when a safety-critical activity keeps
+/// failing, see [crate::supervision]. Once active it stays active until the
+/// agents restart.
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct SafeStateRequest {
+    pub active: bool,
+    pub activity: u64, // Id of the failing activity
+    pub failures: u32, // Failures in a row that led to the request
+}
+
+/// Seat of the cabin
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum Seat {
+    #[default]
+    Driver,
+    FrontPassenger,
+    RearLeft,
+    RearCenter,
+    RearRight,
+}
+
+impl Seat {
+    /// All seats, in the order of [Occupancy::seats]
+    pub const ALL: [Seat; 5] = [
+        Seat::Driver,
+        Seat::FrontPassenger,
+        Seat::RearLeft,
+        Seat::RearCenter,
+        Seat::RearRight,
+    ];
+
+    /// Name used in cabin scripts and events
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Seat::Driver => "driver",
+            Seat::FrontPassenger => "front_passenger",
+            Seat::RearLeft => "rear_left",
+            Seat::RearCenter => "rear_center",
+            Seat::RearRight => "rear_right",
+        }
+    }
+
+    /// Parse the name used in cabin scripts and events
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "driver" => Some(Seat::Driver),
+            "front_passenger" => Some(Seat::FrontPassenger),
+            "rear_left" => Some(Seat::RearLeft),
+            "rear_center" => Some(Seat::RearCenter),
+            "rear_right" => Some(Seat::RearRight),
+            _ => None,
+        }
+    }
+}
+
+/// Door of the cabin
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum Door {
+    #[default]
+    FrontLeft,
+    FrontRight,
+    RearLeft,
+    RearRight,
+}
+
+impl Door {
+    /// Name used in events
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Door::FrontLeft => "front_left",
+            Door::FrontRight => "front_right",
+            Door::RearLeft => "rear_left",
+            Door::RearRight => "rear_right",
+        }
+    }
+}
+
+/// State of one seat
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct SeatState {
+    pub occupied: bool,
+    pub belted: bool, // seatbelt fastened, only for an occupied seat
+}
+
+/// Occupancy
+///
+/// Occupants, seatbelts and doors of the cabin as simulated by the
+/// occupancy model, see [crate::activities::occupancy].
+#[cfg_attr(feature = "recording", derive(Serialize, Deserialize, MaxSize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Occupancy {
+    pub seats: [SeatState; 5], // indexed by Seat
+    pub doors_open: [bool; 4], // indexed by Door
+}
+
+impl Occupancy {
+    /// State of `seat`
+    pub fn seat(&self, seat: Seat) -> SeatState {
+        self.seats[seat as usize]
+    }
+
+    /// Number of occupied seats
+    pub fn occupants(&self) -> u32 {
+        self.seats.iter().filter(|seat| seat.occupied).count() as u32
+    }
+
+    /// Number of occupants with fastened seatbelts
+    pub fn belted_occupants(&self) -> u32 {
+        self.seats.iter().filter(|seat| seat.occupied && seat.belted).count() as u32
+    }
+
+    /// Whether `door` is open
+    pub fn is_open(&self, door: Door) -> bool {
+        self.doors_open[door as usize]
+    }
+}
+
 /// ADASObstacleDetectionIsWarning
 ///
 /// DDS message for obstacle detection warning
@@ -97,109 +379,161 @@ pub struct ADASObstacleDetectionIsWarning {
     pub value: bool,
 }
 
-/// CarData
+/// ModeDecisionExplanation
+///
+/// Why the driving mode was last changed: the evaluated conditions,
+/// their inputs and the rule that fired (e.g. "manual: num_cars 7 > 5")
+#[derive(DdsType, Clone, Debug, Default)]
+#[repr(C)]
+pub struct ModeDecisionExplanation {
+    pub decision_id: u64,             // Incremented on every mode change
+    pub driving_mode: String,         // Mode selected by the decision
+    pub previous_mode: String,        // Mode before the decision
+    pub rule: String,                 // Rule that fired
+    pub num_people: u32,              // Scene input
+    pub num_cars: u32,                // Scene input
+    pub distance_obstacle: f64,       // meters, scene input
+    pub emergency_condition: bool,    // distance_obstacle < emergency threshold
+    pub manual_distance_condition: bool, // distance_obstacle < manual threshold
+    pub manual_people_condition: bool,   // num_people above limit
+    pub manual_cars_condition: bool,     // num_cars above limit
+    pub timestamp: i64,               // Unix timestamp in milliseconds
+    pub schema_version: u32,          // ModeDecisionExplanation::SCHEMA_VERSION
+}
+
+impl Versioned for ModeDecisionExplanation {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+/// IntegrationHealth
 ///
-/// Basic car driving mode data for scenario handling
+/// QoS compatibility of one DDS data writer of mini-adas with the readers
+/// discovered for its topic, published periodically by every DDS publisher
 #[derive(DdsType, Clone, Debug, Default)]
 #[repr(C)]
-pub struct CarData {
-    pub driving_mode: String,
+pub struct IntegrationHealth {
+    pub publisher: String,            // Publishing activity, e.g. "AutonomousModePublisher"
+    pub topic: String,                // Topic of the checked data writer
+    pub offered_reliability: String,  // "BestEffort" or "Reliable"
+    pub offered_durability: String,   // "Volatile", "TransientLocal", ...
+    pub matched_readers: u32,         // Readers currently receiving the data
+    pub incompatible_readers: u32,    // Readers rejected for incompatible QoS since startup
+    pub incompatible_policies: String, // e.g. "RELIABILITY, DURABILITY", empty if none
+    pub healthy: bool,                // No reader was rejected
+    pub timestamp: i64,               // Unix timestamp in milliseconds
+    pub schema_version: u32,          // IntegrationHealth::SCHEMA_VERSION
 }
 
-/// AutonomousCarData
+impl Versioned for IntegrationHealth {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+/// V2xHazardWarning
 ///
-/// Autonomous driving mode parameters
+/// Hazard warning injected over DDS, e.g. by a V2X gateway or a test tool
 #[derive(DdsType, Clone, Debug, Default)]
 #[repr(C)]
-pub struct AutonomousCarData {
-    pub vehicle_speed: f64,        // km/h
-    pub lane_position: f64,        // meters (-1.0 to 1.0, 0 = center)
-    pub obstacle_detected: bool,   // true if obstacle detected
-    pub obstacle_distance: f64,    // meters
-    pub traffic_signal: String,    // "green", "yellow", "red", "stop"
-    pub steering_angle: f64,       // degrees (-45 to 45)
-    pub brake_force: f64,         // percentage (0-100)
-    pub acceleration: f64,        // m/s²
-    pub weather_condition: String, // "clear", "rain", "snow", "fog"
-    pub road_condition: String,   // "dry", "wet", "icy", "gravel"
-    pub timestamp: i64,           // Unix timestamp in milliseconds
-    pub is_valid: bool,           // Data validity flag
+pub struct V2xHazardWarning {
+    pub hazard: String,       // "road_works", "accident_ahead", "emergency_vehicle" or "none" to clear
+    pub distance: f64,        // meters ahead
+    pub valid_for_ms: u64,    // Lifetime of the warning, 0 for the default
+    pub timestamp: i64,       // Unix timestamp in milliseconds
+    pub schema_version: u32,  // V2xHazardWarning::SCHEMA_VERSION
+}
+
+impl Versioned for V2xHazardWarning {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
 }
 
-/// ManualCarData
+/// EventContext
 ///
-/// Manual driving mode parameters
+/// One entry of the context map of an [EventLog] event
 #[derive(DdsType, Clone, Debug, Default)]
 #[repr(C)]
-pub struct ManualCarData {
-    pub vehicle_speed: f64,        // km/h
-    pub steering_angle: f64,       // degrees (-45 to 45)
-    pub brake_force: f64,         // percentage (0-100)
-    pub acceleration: f64,        // m/s²
-    pub weather_condition: String, // "clear", "rain", "snow", "fog"
-    pub road_condition: String,   // "dry", "wet", "icy", "gravel"
-    pub driver_alertness: bool,   // true if driver is alert
-    pub throttle_position: f64,   // percentage (0-100)
-    pub timestamp: i64,           // Unix timestamp in milliseconds
-    pub is_valid: bool,           // Data validity flag
+pub struct EventContext {
+    pub key: String,   // e.g. "distance_obstacle"
+    pub value: String, // Formatted value, e.g. "4.2"
 }
 
-/// EmergencyModeData
+/// EventLog
 ///
-/// Emergency driving mode parameters
+/// Structured event of an activity, a machine-readable counterpart of the
+/// notable feo_log messages of the whole pipeline
 #[derive(DdsType, Clone, Debug, Default)]
 #[repr(C)]
-pub struct EmergencyModeData {
-    pub vehicle_speed: f64,         // km/h
-    pub steering_angle: f64,        // degrees (-45 to 45)
-    pub brake_force: f64,          // percentage (0-100)
-    pub obstacle_detected: bool,    // true if immediate threat
-    pub obstacle_distance: f64,     // meters
-    pub collision_risk: f64,        // percentage (0-100)
-    pub stability_control: bool,    // true if stability systems active
-    pub traffic_signal: String,     // "green", "yellow", "red", "stop"
-    pub seatbelt_tightened: bool,   // true if emergency seatbelt tightening
-    pub emergency_lights: bool,     // true if hazard lights on
-    pub emergency_type: String,     // "collision_avoidance", "obstacle", "medical", "system_failure"
-    pub emergency_brake_force: f64, // percentage (0-100)
-    pub airbag_ready: bool,         // true if airbag systems primed
+pub struct EventLog {
+    pub component: String,          // Emitting activity, e.g. "CarModeCalculator"
+    pub severity: String,           // "debug", "info", "warning", "error" or "critical"
+    pub code: String,               // Stable event code, e.g. "MODE_CHANGED"
+    pub message: String,            // Human-readable description
+    pub context: Vec<EventContext>, // Inputs and values behind the event
+    pub sequence: u64,              // Incremented per event of the emitting process
     pub timestamp: i64,             // Unix timestamp in milliseconds
-    pub is_valid: bool,             // Data validity flag
+    pub schema_version: u32,        // EventLog::SCHEMA_VERSION
+}
+
+impl Versioned for EventLog {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
 }
 
 /// Return a type registry containing the types defined in this file
 #[cfg(feature = "recording")]
 pub fn type_registry() -> TypeRegistry {
+    use crate::com::com_backend;
     use core::fmt;
-    use feo_com::interface::ActivityInput;
+    use feo_com::interface::{ActivityInput, ComBackend};
 
     #[cfg(feature = "com_iox2")]
     use feo_com::iox2::Iox2Input;
 
-    #[cfg(feature = "com_linux_shm")]
     use feo_com::linux_shm::LinuxShmInput;
 
     fn activity_input<T>(topic: &str) -> Box<dyn ActivityInput<T>>
     where
         T: fmt::Debug + 'static,
     {
-        #[cfg(feature = "com_iox2")]
-        return Box::new(Iox2Input::new(topic));
+        match com_backend() {
+            #[cfg(feature = "com_iox2")]
+            ComBackend::Iox2 => Box::new(Iox2Input::new(topic)),
 
-        #[cfg(feature = "com_linux_shm")]
-        Box::new(LinuxShmInput::new(topic))
+            ComBackend::LinuxShm => Box::new(LinuxShmInput::new(topic)),
+        }
     }
 
     let mut registry = TypeRegistry::default();
     register_types!(
         registry;
         CameraImage, |topic: &str| activity_input(topic);
+        ActiveScenario, |topic: &str| activity_input(topic);
         RadarScan, |topic: &str| activity_input(topic);
         Scene, |topic: &str| activity_input(topic);
         BrakeInstruction, |topic: &str| activity_input(topic);
+        ThrottleInstruction, |topic: &str| activity_input(topic);
         Steering, |topic: &str| activity_input(topic);
+        MapAttributes, |topic: &str| activity_input(topic);
+        VehicleState, |topic: &str| activity_input(topic);
+        V2xHazard, |topic: &str| activity_input(topic);
+        Occupancy, |topic: &str| activity_input(topic);
+        SafeStateRequest, |topic: &str| activity_input(topic);
         ADASObstacleDetectionIsWarning, |topic: &str| activity_input(topic);
         CarData, |topic: &str| activity_input(topic);
+        ModeDecisionExplanation, |topic: &str| activity_input(topic);
         AutonomousCarData, |topic: &str| activity_input(topic);
         ManualCarData, |topic: &str| activity_input(topic);
         EmergencyModeData, |topic: &str| activity_input(topic)
@@ -11,5 +11,13 @@
  * SPDX-License-Identifier: Apache-2.0
  ********************************************************************************/
 
+pub mod brake_thermal;
 pub mod components;
+pub mod eco;
+pub mod event_log;
 pub mod messages;
+pub mod occupancy;
+pub mod qos_check;
+pub mod safe_state;
+pub mod v2x;
+pub mod validation;
@@ -0,0 +1,199 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Cabin occupancy
+//!
+//! The `OccupancyModel` activity simulates the occupants of the cabin, their
+//! seatbelts and the doors. The cabin starts with a belted driver. A script
+//! named by `MINI_ADAS_CABIN_SCRIPT` changes it, one change per line as
+//! `<at s> <change> <seat>`, with times relative to the startup of the model
+//! and `#` starting a comment. The changes are:
+//!
+//! - `board` and `leave`, only at standstill: a change due while the vehicle
+//!   moves waits for the next stop, and the door of the seat stays open for
+//!   [`DOOR_OPEN_TIME`]. Boarding occupants are unbelted.
+//! - `buckle` and `unbuckle` of the seatbelt of an occupied seat
+//!
+//! The EmergencyModePublisher limits the reactions of the safe-state matrix
+//! to the cabin with [`restraints`]: only the seatbelts of belted occupants
+//! are tightened and only the airbags of occupied seats are primed, e.g. not
+//! the passenger airbag for an empty front passenger seat.
+
+use crate::activities::messages::{Door, Occupancy, Seat, SeatState};
+use core::time::Duration;
+use feo_log::{info, warn};
+
+/// Environment variable naming the cabin script
+pub const CABIN_SCRIPT_ENV: &str = "MINI_ADAS_CABIN_SCRIPT";
+
+/// Time a door stays open for an occupant boarding or leaving
+pub const DOOR_OPEN_TIME: Duration = Duration::from_secs(3);
+
+/// Speed below which the vehicle stands still (km/h)
+pub const STANDSTILL_SPEED: f64 = 1.0;
+
+/// Change of the cabin in a script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CabinChange {
+    Board,
+    Leave,
+    Buckle,
+    Unbuckle,
+}
+
+impl CabinChange {
+    /// Name used in cabin scripts and events
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CabinChange::Board => "board",
+            CabinChange::Leave => "leave",
+            CabinChange::Buckle => "buckle",
+            CabinChange::Unbuckle => "unbuckle",
+        }
+    }
+
+    /// Parse the name used in cabin scripts and events
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "board" => Some(CabinChange::Board),
+            "leave" => Some(CabinChange::Leave),
+            "buckle" => Some(CabinChange::Buckle),
+            "unbuckle" => Some(CabinChange::Unbuckle),
+            _ => None,
+        }
+    }
+
+    /// Whether the change opens a door and therefore waits for standstill
+    pub fn needs_standstill(&self) -> bool {
+        matches!(self, CabinChange::Board | CabinChange::Leave)
+    }
+}
+
+/// Change of a cabin script
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptedChange {
+    pub at: Duration,
+    pub change: CabinChange,
+    pub seat: Seat,
+}
+
+fn parse_line(line: &str) -> Result<ScriptedChange, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [at, change, seat] = fields[..] else {
+        return Err(format!("expected 3 fields, found {}", fields.len()));
+    };
+    let at = at
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("invalid time '{}'", at))?;
+    let change = CabinChange::parse(change).ok_or_else(|| format!("unknown change '{}'", change))?;
+    let seat = Seat::parse(seat).ok_or_else(|| format!("unknown seat '{}'", seat))?;
+    Ok(ScriptedChange { at, change, seat })
+}
+
+/// Parse a cabin script, skipping invalid lines with a warning
+///
+/// The changes are ordered by time, changes at the same time in the order of the script.
+pub fn parse_script(script: &str) -> Vec<ScriptedChange> {
+    let mut changes: Vec<ScriptedChange> = script
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                return None;
+            }
+            parse_line(line)
+                .inspect_err(|error| warn!("🪑 Cabin script line {} ignored: {}", index + 1, error))
+                .ok()
+        })
+        .collect();
+    changes.sort_by_key(|scripted| scripted.at);
+    changes
+}
+
+/// Load the script named by [`CABIN_SCRIPT_ENV`], empty if there is none
+pub fn load_script() -> Vec<ScriptedChange> {
+    let Ok(path) = std::env::var(CABIN_SCRIPT_ENV) else {
+        return Vec::new();
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(script) => {
+            let script = parse_script(&script);
+            info!("🪑 Loaded {} cabin changes from {}", script.len(), path);
+            script
+        }
+        Err(error) => {
+            warn!("🪑 Cabin script {} not readable: {}", path, error);
+            Vec::new()
+        }
+    }
+}
+
+/// Cabin at startup: a belted driver, all doors closed
+pub fn initial_cabin() -> Occupancy {
+    let mut cabin = Occupancy::default();
+    cabin.seats[Seat::Driver as usize] = SeatState {
+        occupied: true,
+        belted: true,
+    };
+    cabin
+}
+
+/// Door of `seat`, the rear right one for the rear center seat
+pub fn door(seat: Seat) -> Door {
+    match seat {
+        Seat::Driver => Door::FrontLeft,
+        Seat::FrontPassenger => Door::FrontRight,
+        Seat::RearLeft => Door::RearLeft,
+        Seat::RearCenter | Seat::RearRight => Door::RearRight,
+    }
+}
+
+/// Apply `change` to `seat` of `cabin`, whether it changed anything
+///
+/// Boarding an occupied seat, leaving an empty one and buckling on an empty
+/// seat change nothing.
+pub fn apply(cabin: &mut Occupancy, change: CabinChange, seat: Seat) -> bool {
+    let state = &mut cabin.seats[seat as usize];
+    let before = *state;
+    match change {
+        CabinChange::Board => state.occupied = true,
+        CabinChange::Leave => *state = SeatState::default(),
+        CabinChange::Buckle => state.belted = state.occupied,
+        CabinChange::Unbuckle => state.belted = false,
+    }
+    *state != before
+}
+
+/// Restraint reactions for the occupants of a cabin
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Restraints {
+    /// Seatbelts tightened, those of the belted occupants
+    pub seatbelts_tightened: u32,
+    /// Airbags of at least one occupied seat primed
+    pub airbag_ready: bool,
+    /// Airbag of the front passenger primed
+    pub passenger_airbag_ready: bool,
+}
+
+/// Restraints for `cabin` when the safe-state matrix asks to `tighten` the seatbelts and to `prime` the airbags
+pub fn restraints(cabin: &Occupancy, tighten: bool, prime: bool) -> Restraints {
+    Restraints {
+        seatbelts_tightened: if tighten { cabin.belted_occupants() } else { 0 },
+        airbag_ready: prime && cabin.occupants() > 0,
+        passenger_airbag_ready: prime && cabin.seat(Seat::FrontPassenger).occupied,
+    }
+}
@@ -0,0 +1,268 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! QoS mismatch detection of the DDS publishers
+//!
+//! A subscriber whose requested QoS is incompatible with the offered QoS,
+//! e.g. a `Reliable` reader of a `BestEffort` writer, is never matched by
+//! DDS: both sides run without an error while no data flows. Such readers
+//! do not show up in the matched subscriptions, so the offered incompatible
+//! QoS status of each writer is inspected instead.
+//!
+//! Every DDS publisher activity owns a [`QosMonitor`]. It checks the writers
+//! at the first step after startup and every [`CHECK_INTERVAL`] afterwards,
+//! logs a warning when a reader was rejected and publishes the result as
+//! [`IntegrationHealth`] for the console apps. Changes are also reported on
+//! the event log.
+
+use crate::activities::event_log::{EventCode, EventLogger, Severity};
+use crate::activities::messages::{IntegrationHealth, Versioned};
+use core::time::Duration;
+use dust_dds::{
+    domain::domain_participant::DomainParticipant,
+    infrastructure::qos::{DataWriterQos, QosKind},
+    infrastructure::qos_policy::{
+        DurabilityQosPolicy, DurabilityQosPolicyKind, HistoryQosPolicy, HistoryQosPolicyKind,
+        ReliabilityQosPolicy, ReliabilityQosPolicyKind,
+    },
+    publication::data_writer::DataWriter,
+};
+use feo_log::{debug, info, warn};
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// DDS topic and type name of the health reports
+pub const INTEGRATION_HEALTH_TOPIC: &str = "IntegrationHealth";
+
+/// Time between two checks of the same publisher
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Name of a QoS policy from its id in the DDS specification
+fn policy_name(policy_id: i32) -> &'static str {
+    match policy_id {
+        2 => "DURABILITY",
+        3 => "PRESENTATION",
+        4 => "DEADLINE",
+        5 => "LATENCY_BUDGET",
+        6 => "OWNERSHIP",
+        8 => "LIVELINESS",
+        11 => "RELIABILITY",
+        12 => "DESTINATION_ORDER",
+        13 => "HISTORY",
+        23 => "DATA_REPRESENTATION",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Periodic QoS check of the data writers of one publisher activity
+pub struct QosMonitor {
+    publisher: &'static str,
+    events: EventLogger,
+    writer: Option<DataWriter<IntegrationHealth>>,
+    last_check: Option<Instant>,
+    /// Last logged (matched, incompatible) readers per topic, to log changes only
+    reported: HashMap<String, (u32, u32)>,
+}
+
+impl QosMonitor {
+    /// ### Parameters
+    /// * `publisher` - name of the owning activity, as shown in the reports
+    pub fn new(publisher: &'static str) -> Self {
+        Self {
+            publisher,
+            events: EventLogger::new(publisher),
+            writer: None,
+            last_check: None,
+            reported: HashMap::new(),
+        }
+    }
+
+    /// Create the `IntegrationHealth` writer on the participant of the activity
+    pub fn startup(&mut self, participant: &DomainParticipant) {
+        let topic = match participant.create_topic::<IntegrationHealth>(
+            INTEGRATION_HEALTH_TOPIC,
+            INTEGRATION_HEALTH_TOPIC,
+            QosKind::Default,
+            None,
+            &[],
+        ) {
+            Ok(topic) => topic,
+            Err(e) => {
+                warn!(
+                    "{}: cannot create {INTEGRATION_HEALTH_TOPIC} topic: {e:?}",
+                    self.publisher
+                );
+                return;
+            }
+        };
+        let writer_qos = DataWriterQos {
+            reliability: ReliabilityQosPolicy {
+                kind: ReliabilityQosPolicyKind::BestEffort,
+                max_blocking_time: dust_dds::infrastructure::time::DurationKind::Finite(
+                    dust_dds::infrastructure::time::Duration::new(0, 100_000_000),
+                ),
+            },
+            durability: DurabilityQosPolicy {
+                kind: DurabilityQosPolicyKind::TransientLocal, // Console apps started later see the last reports
+            },
+            history: HistoryQosPolicy {
+                kind: HistoryQosPolicyKind::KeepLast(5),
+            },
+            ..Default::default()
+        };
+        self.writer = participant
+            .create_publisher(QosKind::Default, None, &[])
+            .and_then(|publisher| {
+                publisher.create_datawriter::<IntegrationHealth>(
+                    &topic,
+                    QosKind::Specific(writer_qos),
+                    None,
+                    &[],
+                )
+            })
+            .map_err(|e| {
+                warn!(
+                    "{}: cannot create {INTEGRATION_HEALTH_TOPIC} writer: {e:?}",
+                    self.publisher
+                )
+            })
+            .ok();
+    }
+
+    /// Whether the writers are due for a check; restarts the interval if so
+    pub fn due(&mut self) -> bool {
+        let now = Instant::now();
+        let due = self
+            .last_check
+            .is_none_or(|last_check| now.duration_since(last_check) >= CHECK_INTERVAL);
+        if due {
+            self.last_check = Some(now);
+        }
+        due
+    }
+
+    /// Check the readers discovered for one writer and publish the result
+    ///
+    /// ### Parameters
+    /// * `topic` - topic of the writer
+    /// * `writer` - data writer of the activity
+    pub fn check<Foo>(&mut self, topic: &str, writer: &DataWriter<Foo>) {
+        let matched_readers = writer
+            .get_publication_matched_status()
+            .map(|status| status.current_count.max(0) as u32)
+            .unwrap_or_default();
+        // Cumulative: a rejected reader is only reported once, but stays broken until fixed
+        let (incompatible_readers, incompatible_policies) =
+            match writer.get_offered_incompatible_qos_status() {
+                Ok(status) => (
+                    status.total_count.max(0) as u32,
+                    status
+                        .policies
+                        .iter()
+                        .filter(|policy| policy.count > 0)
+                        .map(|policy| policy_name(policy.policy_id))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                Err(_) => (0, String::new()),
+            };
+        let (offered_reliability, offered_durability) = writer
+            .get_qos()
+            .map(|qos| {
+                (
+                    format!("{:?}", qos.reliability.kind),
+                    format!("{:?}", qos.durability.kind),
+                )
+            })
+            .unwrap_or_default();
+
+        let health = IntegrationHealth {
+            publisher: self.publisher.to_string(),
+            topic: topic.to_string(),
+            offered_reliability,
+            offered_durability,
+            matched_readers,
+            incompatible_readers,
+            incompatible_policies,
+            healthy: incompatible_readers == 0,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64,
+            schema_version: IntegrationHealth::SCHEMA_VERSION,
+        };
+
+        let state = (health.matched_readers, health.incompatible_readers);
+        if self.reported.insert(topic.to_string(), state) != Some(state) {
+            let context = [
+                ("topic", topic.to_string()),
+                ("matched_readers", health.matched_readers.to_string()),
+                (
+                    "incompatible_readers",
+                    health.incompatible_readers.to_string(),
+                ),
+                (
+                    "incompatible_policies",
+                    health.incompatible_policies.clone(),
+                ),
+            ];
+            if health.healthy {
+                info!(
+                    "🔌 [DDS] {} '{}': {} reader(s) matched, QoS compatible",
+                    self.publisher, topic, health.matched_readers
+                );
+                self.events.emit(
+                    Severity::Info,
+                    EventCode::QosCompatible,
+                    format!(
+                        "{} reader(s) of '{}' matched",
+                        health.matched_readers, topic
+                    ),
+                    &context,
+                );
+            } else {
+                warn!(
+                    "⚠️ [DDS] {} '{}': {} reader(s) rejected for incompatible {} (offered {}/{}), they receive NO data",
+                    self.publisher,
+                    topic,
+                    health.incompatible_readers,
+                    health.incompatible_policies,
+                    health.offered_reliability,
+                    health.offered_durability
+                );
+                self.events.emit(
+                    Severity::Error,
+                    EventCode::QosIncompatible,
+                    format!(
+                        "{} reader(s) of '{}' rejected for incompatible {}",
+                        health.incompatible_readers, topic, health.incompatible_policies
+                    ),
+                    &context,
+                );
+            }
+        }
+
+        if let Some(writer) = &self.writer {
+            if writer.write(&health, None).is_err() {
+                debug!("📝 [DDS] {INTEGRATION_HEALTH_TOPIC} cached in TransientLocal (no active subscribers)");
+            }
+        }
+    }
+
+    /// Drop the writer before the participant of the activity
+    pub fn shutdown(&mut self) {
+        self.writer = None;
+        self.last_check = None;
+        self.reported.clear();
+    }
+}
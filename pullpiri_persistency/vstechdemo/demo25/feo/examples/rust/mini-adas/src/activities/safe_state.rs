@@ -0,0 +1,266 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Configurable safe-state behavior of the emergency mode
+//!
+//! The reactions to the emergency mode are rules of a safe-state matrix, each
+//! a condition on the scene and an action with its parameters. The
+//! `EmergencyModePublisher` reports the reactions of the matching rules and
+//! the brake arbitration of the `CarModeCalculator` brakes at least with
+//! their brake force, so both act on the same matrix.
+//!
+//! A matrix file named by `MINI_ADAS_SAFE_STATE_MATRIX` replaces the
+//! [`BUILTIN_MATRIX`]. It starts with a `version <name>` line, reported in
+//! `EmergencyModeData`, followed by one rule per line as
+//! `<condition> [<m>] <action> [<parameters>]`, `#` starting a comment:
+//!
+//! - conditions: `always`, `obstacle_within <m>`, `pedestrian_within <m>`
+//! - actions: `seatbelt_tighten`, `hazard_lights`, `airbag_prime`,
+//!   `brake <%>` and `brake_curve <far m> <far %> <near m> <near %>`, a
+//!   force interpolated linearly between two obstacle distances
+//!
+//! The brake force is the highest of all matching brake actions. A file with
+//! an invalid line is rejected as a whole and the built-in matrix is used, as
+//! half a matrix could drop a reaction without notice.
+
+use crate::activities::messages::Scene;
+use feo_log::{error, info};
+
+/// Environment variable naming the safe-state matrix file
+pub const SAFE_STATE_MATRIX_ENV: &str = "MINI_ADAS_SAFE_STATE_MATRIX";
+
+/// Matrix used without a matrix file
+pub const BUILTIN_MATRIX: &str = "\
+version builtin-1
+always              seatbelt_tighten
+always              hazard_lights
+always              airbag_prime
+always              brake 80
+obstacle_within 3   brake 100
+";
+
+/// Condition on the scene selecting a rule
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    /// Every emergency
+    Always,
+    /// Obstacle closer than the distance (m)
+    ObstacleWithin(f64),
+    /// People in the scene and the obstacle closer than the distance (m)
+    PedestrianWithin(f64),
+}
+
+impl Condition {
+    fn matches(&self, scene: &Scene) -> bool {
+        match *self {
+            Self::Always => true,
+            Self::ObstacleWithin(distance) => scene.distance_obstacle < distance,
+            Self::PedestrianWithin(distance) => {
+                scene.num_people > 0 && scene.distance_obstacle < distance
+            }
+        }
+    }
+}
+
+/// Reaction of a rule
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    SeatbeltTighten,
+    HazardLights,
+    AirbagPrime,
+    /// Brake force (%)
+    Brake(f64),
+    /// Brake force (%) interpolated between two obstacle distances, constant beyond them
+    BrakeCurve {
+        far: f64,
+        far_force: f64,
+        near: f64,
+        near_force: f64,
+    },
+}
+
+impl Action {
+    fn brake_force(&self, scene: &Scene) -> Option<f64> {
+        match *self {
+            Self::Brake(force) => Some(force),
+            Self::BrakeCurve {
+                far,
+                far_force,
+                near,
+                near_force,
+            } => {
+                let share = ((far - scene.distance_obstacle) / (far - near)).clamp(0.0, 1.0);
+                Some(far_force + (near_force - far_force) * share)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Rule of the matrix
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rule {
+    pub condition: Condition,
+    pub action: Action,
+}
+
+/// Reactions of all rules matching a scene
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SafeState {
+    pub seatbelt_tightened: bool,
+    pub hazard_lights: bool,
+    pub airbag_ready: bool,
+    /// Minimum brake force (%)
+    pub brake_force: f64,
+}
+
+/// Rules of the safe state, with the version identifying them
+#[derive(Debug, Clone, PartialEq)]
+pub struct SafeStateMatrix {
+    pub version: String,
+    pub rules: Vec<Rule>,
+}
+
+impl SafeStateMatrix {
+    /// The [`BUILTIN_MATRIX`]
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_MATRIX).expect("built-in safe-state matrix is valid")
+    }
+
+    /// Parse a matrix, failing on the first invalid line
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut version = None;
+        let mut rules = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields[0] == "version" {
+                let [_, name] = fields[..] else {
+                    return Err(format!("line {}: expected 'version <name>'", index + 1));
+                };
+                version = Some(name.to_string());
+                continue;
+            }
+            let rule =
+                parse_rule(&fields).map_err(|error| format!("line {}: {}", index + 1, error))?;
+            rules.push(rule);
+        }
+        let version = version.ok_or("missing 'version <name>' line")?;
+        Ok(Self { version, rules })
+    }
+
+    /// Load the matrix named by [`SAFE_STATE_MATRIX_ENV`], the built-in one if there is none
+    pub fn load() -> Self {
+        let Ok(path) = std::env::var(SAFE_STATE_MATRIX_ENV) else {
+            return Self::builtin();
+        };
+        let matrix = std::fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| Self::parse(&text));
+        match matrix {
+            Ok(matrix) => {
+                info!(
+                    "🛟 Loaded safe-state matrix {} with {} rules from {}",
+                    matrix.version,
+                    matrix.rules.len(),
+                    path
+                );
+                matrix
+            }
+            Err(err) => {
+                let matrix = Self::builtin();
+                error!(
+                    "🛟 Safe-state matrix {} rejected ({}), using {}",
+                    path, err, matrix.version
+                );
+                matrix
+            }
+        }
+    }
+
+    /// Reactions to an emergency in `scene`
+    pub fn evaluate(&self, scene: &Scene) -> SafeState {
+        let mut state = SafeState::default();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.condition.matches(scene))
+        {
+            match rule.action {
+                Action::SeatbeltTighten => state.seatbelt_tightened = true,
+                Action::HazardLights => state.hazard_lights = true,
+                Action::AirbagPrime => state.airbag_ready = true,
+                action => {
+                    let force = action.brake_force(scene).unwrap_or_default();
+                    state.brake_force = state.brake_force.max(force);
+                }
+            }
+        }
+        state
+    }
+}
+
+fn parse_rule(fields: &[&str]) -> Result<Rule, String> {
+    let number = |value: &str| {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite() && *number >= 0.0)
+            .ok_or_else(|| format!("invalid number '{}'", value))
+    };
+    let percent = |value: &str| {
+        number(value)
+            .ok()
+            .filter(|force| *force <= 100.0)
+            .ok_or_else(|| format!("invalid brake force '{}'", value))
+    };
+
+    let (condition, rest) = match fields {
+        ["always", rest @ ..] => (Condition::Always, rest),
+        ["obstacle_within", distance, rest @ ..] => {
+            (Condition::ObstacleWithin(number(distance)?), rest)
+        }
+        ["pedestrian_within", distance, rest @ ..] => {
+            (Condition::PedestrianWithin(number(distance)?), rest)
+        }
+        [condition, ..] => return Err(format!("unknown condition '{}'", condition)),
+        [] => return Err("empty rule".to_string()),
+    };
+    let action = match rest {
+        ["seatbelt_tighten"] => Action::SeatbeltTighten,
+        ["hazard_lights"] => Action::HazardLights,
+        ["airbag_prime"] => Action::AirbagPrime,
+        ["brake", force] => Action::Brake(percent(force)?),
+        ["brake_curve", far, far_force, near, near_force] => {
+            let (far, near) = (number(far)?, number(near)?);
+            if near >= far {
+                return Err(format!(
+                    "near distance {} not below far distance {}",
+                    near, far
+                ));
+            }
+            Action::BrakeCurve {
+                far,
+                far_force: percent(far_force)?,
+                near,
+                near_force: percent(near_force)?,
+            }
+        }
+        [] => return Err("missing action".to_string()),
+        [action, ..] => return Err(format!("invalid action '{}'", action)),
+    };
+    Ok(Rule { condition, action })
+}
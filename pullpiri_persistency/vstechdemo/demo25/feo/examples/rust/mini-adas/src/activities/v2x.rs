@@ -0,0 +1,148 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Simulated V2X hazard warnings
+//!
+//! The `V2xReceiver` activity injects hazard warnings that on-board sensing
+//! cannot see yet into the mode decision. Warnings come from two sources:
+//!
+//! - a script named by `MINI_ADAS_V2X_SCRIPT`, one warning per line as
+//!   `<start s> <duration s> <hazard> <distance m>`, with times relative to
+//!   the startup of the receiver and `#` starting a comment
+//! - the DDS topic `V2xHazardWarning`, each warning valid for its
+//!   `valid_for_ms` or [`DEFAULT_VALIDITY`]; hazard `none` clears it
+//!
+//! Of all active warnings the closest one is published. Within the
+//! [`manual_range`] of its kind it forces the manual mode.
+
+use crate::activities::messages::{HazardKind, V2xHazard};
+use core::time::Duration;
+use feo_log::{info, warn};
+
+/// Environment variable naming the V2X script
+pub const V2X_SCRIPT_ENV: &str = "MINI_ADAS_V2X_SCRIPT";
+
+/// DDS topic and type name of injected hazard warnings
+pub const V2X_WARNING_TOPIC: &str = "V2xHazardWarning";
+
+/// Lifetime of a DDS warning that does not set its own
+pub const DEFAULT_VALIDITY: Duration = Duration::from_secs(30);
+
+/// Distance ahead within which a hazard forces the manual mode (m)
+pub fn manual_range(kind: HazardKind) -> f64 {
+    match kind {
+        HazardKind::None => 0.0,
+        HazardKind::RoadWorks => 500.0,
+        HazardKind::AccidentAhead => 1000.0,
+        HazardKind::EmergencyVehicle => 300.0,
+    }
+}
+
+/// Whether `hazard` forces the manual mode
+pub fn forces_manual(hazard: &V2xHazard) -> bool {
+    hazard.kind != HazardKind::None && hazard.distance <= manual_range(hazard.kind)
+}
+
+/// The more relevant of two hazards: any hazard over none, the closer one otherwise
+pub fn most_relevant(a: V2xHazard, b: V2xHazard) -> V2xHazard {
+    match (a.kind, b.kind) {
+        (HazardKind::None, _) => b,
+        (_, HazardKind::None) => a,
+        _ if b.distance < a.distance => b,
+        _ => a,
+    }
+}
+
+/// Warning of a V2X script
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptedHazard {
+    pub start: Duration,
+    pub end: Duration,
+    pub hazard: V2xHazard,
+}
+
+fn parse_line(line: &str) -> Result<ScriptedHazard, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [start, duration, kind, distance] = fields[..] else {
+        return Err(format!("expected 4 fields, found {}", fields.len()));
+    };
+    let seconds = |value: &str| {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+            .map(Duration::from_secs_f64)
+            .ok_or_else(|| format!("invalid time '{}'", value))
+    };
+    let start = seconds(start)?;
+    let duration = seconds(duration)?;
+    let kind = HazardKind::parse(kind).ok_or_else(|| format!("unknown hazard '{}'", kind))?;
+    let distance = distance
+        .parse::<f64>()
+        .ok()
+        .filter(|distance| distance.is_finite() && *distance >= 0.0)
+        .ok_or_else(|| format!("invalid distance '{}'", distance))?;
+    Ok(ScriptedHazard {
+        start,
+        end: start + duration,
+        hazard: V2xHazard { kind, distance },
+    })
+}
+
+/// Parse a V2X script, skipping invalid lines with a warning
+pub fn parse_script(script: &str) -> Vec<ScriptedHazard> {
+    script
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                return None;
+            }
+            parse_line(line)
+                .inspect_err(|error| warn!("📡 V2X script line {} ignored: {}", index + 1, error))
+                .ok()
+        })
+        .collect()
+}
+
+/// Load the script named by [`V2X_SCRIPT_ENV`], empty if there is none
+pub fn load_script() -> Vec<ScriptedHazard> {
+    let Ok(path) = std::env::var(V2X_SCRIPT_ENV) else {
+        return Vec::new();
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(script) => {
+            let script = parse_script(&script);
+            info!(
+                "📡 Loaded {} V2X hazard warnings from {}",
+                script.len(),
+                path
+            );
+            script
+        }
+        Err(error) => {
+            warn!("📡 V2X script {} not readable: {}", path, error);
+            Vec::new()
+        }
+    }
+}
+
+/// Most relevant scripted hazard active `elapsed` after startup
+pub fn scripted_hazard(script: &[ScriptedHazard], elapsed: Duration) -> V2xHazard {
+    script
+        .iter()
+        .filter(|scripted| scripted.start <= elapsed && elapsed < scripted.end)
+        .map(|scripted| scripted.hazard)
+        .fold(V2xHazard::default(), most_relevant)
+}
@@ -0,0 +1,225 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Expected driving modes of the demo scenarios
+//!
+//! During a rehearsal the `ScenarioValidator` checks every run of a camera
+//! scenario against expectations of the form "the emergency_test scenario
+//! reaches the emergency mode within 20 s". A threshold change that breaks
+//! the demo then fails an expectation instead of going unnoticed until the
+//! demo is shown live.
+//!
+//! The validation is off unless `MINI_ADAS_SCENARIO_VALIDATION` is set, to
+//! `builtin` for the [`BUILTIN_EXPECTATIONS`] or to the path of a file with
+//! one expectation per line as `<scenario> reaches <mode>[,<mode>...] within
+//! <s>`, `#` starting a comment. An expectation passes as soon as one of the
+//! modes is published within the time after the scenario started, and fails
+//! when the time runs out or the scenario ends first.
+
+use crate::activities::messages::DemoScenario;
+use core::time::Duration;
+use feo_log::{error, info};
+
+/// Environment variable enabling the validation
+pub const SCENARIO_VALIDATION_ENV: &str = "MINI_ADAS_SCENARIO_VALIDATION";
+
+/// Expectations of `MINI_ADAS_SCENARIO_VALIDATION=builtin`
+pub const BUILTIN_EXPECTATIONS: &str = "\
+emergency_test  reaches emergency             within 20
+city            reaches manual,emergency      within 30
+highway         reaches autonomous,manual     within 30
+";
+
+/// Driving modes published by the CarModeCalculator
+const MODES: &[&str] = &["autonomous", "manual", "emergency"];
+
+/// Expected outcome of a scenario
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expectation {
+    pub scenario: DemoScenario,
+    /// Any of these modes passes
+    pub modes: Vec<String>,
+    pub within: Duration,
+}
+
+impl Expectation {
+    /// Human-readable form, e.g. `emergency_test reaches emergency within 20s`
+    pub fn describe(&self) -> String {
+        format!(
+            "{} reaches {} within {}s",
+            self.scenario.as_str(),
+            self.modes.join(" or "),
+            self.within.as_secs_f64()
+        )
+    }
+}
+
+/// Parse expectations, failing on the first invalid line
+pub fn parse(text: &str) -> Result<Vec<Expectation>, String> {
+    let mut expectations = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let expectation =
+            parse_expectation(&fields).map_err(|error| format!("line {}: {}", index + 1, error))?;
+        expectations.push(expectation);
+    }
+    Ok(expectations)
+}
+
+fn parse_expectation(fields: &[&str]) -> Result<Expectation, String> {
+    let [scenario, "reaches", modes, "within", seconds] = fields[..] else {
+        return Err("expected '<scenario> reaches <mode>[,<mode>...] within <s>'".to_string());
+    };
+    let scenario =
+        DemoScenario::parse(scenario).ok_or_else(|| format!("unknown scenario '{}'", scenario))?;
+    let modes = modes
+        .split(',')
+        .map(|mode| {
+            MODES
+                .contains(&mode)
+                .then(|| mode.to_string())
+                .ok_or_else(|| format!("unknown mode '{}'", mode))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let within = seconds
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("invalid time '{}'", seconds))?;
+    Ok(Expectation {
+        scenario,
+        modes,
+        within,
+    })
+}
+
+/// Load the expectations selected by [`SCENARIO_VALIDATION_ENV`], `None` if the validation is off
+pub fn load() -> Option<Vec<Expectation>> {
+    let source = std::env::var(SCENARIO_VALIDATION_ENV).ok()?;
+    let expectations = match source.as_str() {
+        "" | "off" => return None,
+        "builtin" => parse(BUILTIN_EXPECTATIONS),
+        path => std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| parse(&text)),
+    };
+    match expectations {
+        Ok(expectations) => {
+            info!(
+                "🎬 Validating {} scenario expectations from {}",
+                expectations.len(),
+                source
+            );
+            Some(expectations)
+        }
+        Err(err) => {
+            // Validating against a fallback would report results nobody asked for
+            error!(
+                "🎬 Scenario expectations {} rejected ({}), validation is off",
+                source, err
+            );
+            None
+        }
+    }
+}
+
+/// Result of one expectation in one run of its scenario
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub expectation: Expectation,
+    pub passed: bool,
+    /// Time since the scenario started when the outcome was decided
+    pub after: Duration,
+    /// Mode published when the outcome was decided
+    pub mode: String,
+}
+
+/// Expectations of one run of a scenario that are not decided yet
+#[derive(Debug)]
+pub struct ScenarioRun {
+    pub scenario: DemoScenario,
+    pub run: u32,
+    pending: Vec<Expectation>,
+    outcomes: Vec<Outcome>,
+}
+
+impl ScenarioRun {
+    /// Start a run with the expectations of its scenario
+    pub fn new(scenario: DemoScenario, run: u32, expectations: &[Expectation]) -> Self {
+        Self {
+            scenario,
+            run,
+            pending: expectations
+                .iter()
+                .filter(|expectation| expectation.scenario == scenario)
+                .cloned()
+                .collect(),
+            outcomes: Vec::new(),
+        }
+    }
+
+    /// Check the published `mode`, returning the outcomes decided by it
+    ///
+    /// ### Parameters
+    /// * `mode` - driving mode published by the CarModeCalculator
+    /// * `elapsed` - time since the scenario started
+    pub fn observe(&mut self, mode: &str, elapsed: Duration) -> Vec<Outcome> {
+        let mut decided = Vec::new();
+        self.pending.retain(|expectation| {
+            let passed = expectation.modes.iter().any(|expected| expected == mode);
+            if !passed && elapsed <= expectation.within {
+                return true;
+            }
+            decided.push(Outcome {
+                expectation: expectation.clone(),
+                passed: passed && elapsed <= expectation.within,
+                after: elapsed,
+                mode: mode.to_string(),
+            });
+            false
+        });
+        self.outcomes.extend(decided.iter().cloned());
+        decided
+    }
+
+    /// End the run, failing the expectations that were not met
+    pub fn finish(&mut self, mode: &str, elapsed: Duration) -> Vec<Outcome> {
+        let failed: Vec<Outcome> = self
+            .pending
+            .drain(..)
+            .map(|expectation| Outcome {
+                expectation,
+                passed: false,
+                after: elapsed,
+                mode: mode.to_string(),
+            })
+            .collect();
+        self.outcomes.extend(failed.iter().cloned());
+        failed
+    }
+
+    /// Outcomes of the run as (passed, failed)
+    pub fn tally(&self) -> (usize, usize) {
+        let passed = self
+            .outcomes
+            .iter()
+            .filter(|outcome| outcome.passed)
+            .count();
+        (passed, self.outcomes.len() - passed)
+    }
+}
@@ -0,0 +1,173 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Activity assignments loaded at startup
+//!
+//! The built-in [`default_layout`] places every activity in a worker of an
+//! agent. ECU variants differ in the cores available to the QM agents, so a
+//! layout file named by `MINI_ADAS_ASSIGNMENTS` can replace it without
+//! recompiling. It lists one worker per line as
+//! `<agent id> <worker id> <activity id>...`, `#` starting a comment:
+//!
+//! ```text
+//! # Route, V2X and occupancy moved to the decision agent
+//! 100 40 0
+//! 100 41 1
+//! 101 42 2 3 9 10 11 12 13 16
+//! 101 43 14 15 17
+//! 102 44 5 6 7 8
+//! ```
+//!
+//! The file has to keep the agents of the built-in layout, as the binaries
+//! start with fixed agent ids, use every worker id once and assign every
+//! activity exactly once. The ASIL partitioning of [`check_partitioning`] is
+//! verified regardless of the safety profile, so a file can move QM
+//! activities between the QM agents but never next to the safety-critical
+//! ones. A file violating any rule is rejected as a whole and the built-in
+//! layout is used. All agents of a deployment must read the same file, they
+//! then agree on the layout or on the fallback.
+//!
+//! [`default_layout`]: crate::config::default_layout
+
+use crate::config::{default_layout, AssignmentLayout};
+use crate::safety::check_partitioning;
+use feo::ids::{ActivityId, WorkerId};
+use feo_log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// Environment variable naming the layout file
+pub const ASSIGNMENTS_ENV: &str = "MINI_ADAS_ASSIGNMENTS";
+
+/// Layout of this process, loaded on the first call
+///
+/// The layout is read once, so that all parts of an agent use the same
+/// assignments even if the file changes while the agent runs.
+pub fn layout() -> AssignmentLayout {
+    static LAYOUT: OnceLock<AssignmentLayout> = OnceLock::new();
+
+    LAYOUT.get_or_init(load).clone()
+}
+
+fn load() -> AssignmentLayout {
+    let Ok(path) = std::env::var(ASSIGNMENTS_ENV) else {
+        return default_layout();
+    };
+    if cfg!(feature = "signalling_direct_mpsc") {
+        warn!("{ASSIGNMENTS_ENV} is ignored with signalling_direct_mpsc, all activities run in one agent");
+        return default_layout();
+    }
+
+    let default = default_layout();
+    let layout = std::fs::read_to_string(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|text| parse(&text))
+        .and_then(|layout| validate(&layout, &default).map(|()| layout));
+    match layout {
+        Ok(layout) => {
+            let workers: usize = layout.values().map(Vec::len).sum();
+            info!(
+                "Loaded assignments of {workers} workers in {} agents from {path}",
+                layout.len()
+            );
+            layout
+        }
+        Err(err) => {
+            error!("Assignments {path} rejected ({err}), using the built-in assignments");
+            default
+        }
+    }
+}
+
+/// Parse a layout file, failing on the first invalid line
+pub fn parse(text: &str) -> Result<AssignmentLayout, String> {
+    let mut layout: AssignmentLayout = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let ids = line
+            .split_whitespace()
+            .map(|field| {
+                field
+                    .parse::<u64>()
+                    .map_err(|_| format!("line {}: '{field}' is not an id", index + 1))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let [agent, worker, ref activities @ ..] = ids[..] else {
+            return Err(format!(
+                "line {}: expected '<agent id> <worker id> <activity id>...'",
+                index + 1
+            ));
+        };
+        if activities.is_empty() {
+            return Err(format!(
+                "line {}: worker {worker} has no activity",
+                index + 1
+            ));
+        }
+        layout.entry(agent.into()).or_default().push((
+            worker.into(),
+            activities.iter().map(|&id| id.into()).collect(),
+        ));
+    }
+    Ok(layout)
+}
+
+/// Check a layout against the built-in one and the ASIL partitioning
+///
+/// ### Parameters
+/// * `layout` - layout to check
+/// * `default` - built-in layout, defining the agents and activities
+pub fn validate(layout: &AssignmentLayout, default: &AssignmentLayout) -> Result<(), String> {
+    if let Some(agent) = layout.keys().find(|agent| !default.contains_key(agent)) {
+        return Err(format!("unknown agent {agent}"));
+    }
+    if let Some(agent) = default.keys().find(|agent| !layout.contains_key(agent)) {
+        return Err(format!("agent {agent} has no worker"));
+    }
+
+    let mut workers: HashSet<WorkerId> = HashSet::new();
+    let mut activities: HashSet<ActivityId> = HashSet::new();
+    for (worker, ids) in layout.values().flatten() {
+        if !workers.insert(*worker) {
+            return Err(format!("worker {worker} is listed twice"));
+        }
+        if let Some(id) = ids.iter().find(|id| !activities.insert(**id)) {
+            return Err(format!("activity {id} is assigned twice"));
+        }
+    }
+
+    let known: HashSet<ActivityId> = default
+        .values()
+        .flatten()
+        .flat_map(|(_, ids)| ids.iter().copied())
+        .collect();
+    if let Some(id) = activities.difference(&known).min() {
+        return Err(format!("unknown activity {id}"));
+    }
+    if let Some(id) = known.difference(&activities).min() {
+        return Err(format!("activity {id} is not assigned"));
+    }
+
+    let violations = check_partitioning(layout);
+    if !violations.is_empty() {
+        let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        return Err(format!(
+            "ASIL partitioning violated: {}",
+            violations.join("; ")
+        ));
+    }
+    Ok(())
+}
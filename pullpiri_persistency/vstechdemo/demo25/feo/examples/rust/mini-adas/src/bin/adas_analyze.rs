@@ -0,0 +1,474 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Offline analysis of recorded test drives
+//!
+//! Reads a recording written by `adas_recorder` and/or the persisted mode
+//! history of the CarModeCalculator and prints
+//! - per topic: number of samples, duration, rate and min/mean/max of every field
+//! - the mode-transition timeline with the time spent in each mode
+//!
+//! With `--csv <dir>` one CSV file per topic (`time_s` plus the message fields)
+//! and `modes.csv` are written for plotting.
+//!
+//! ```sh
+//! adas-analyze --recording rec.bin --csv plots
+//! adas-analyze --history history.txt
+//! adas-analyze --history-from-service
+//! ```
+
+use feo::recording::recorder::Record;
+use feo_log::{info, warn, LevelFilter};
+use mini_adas::activities::components::MODE_HISTORY_KEY;
+use mini_adas::activities::messages::{
+    BrakeInstruction, CameraImage, RadarScan, Scene, Steering, ThrottleInstruction, VehicleState,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    feo_logger::init(LevelFilter::Info, true, false);
+
+    let params = Params::from_args();
+
+    if let Some(path) = &params.recording {
+        let series = read_recording(path);
+        print_topic_summary(&series);
+        if let Some(dir) = &params.csv_dir {
+            for (topic, series) in &series {
+                write_csv(dir, &csv_name(topic), &series.to_csv());
+            }
+        }
+    }
+
+    let history = match &params.history {
+        Some(HistorySource::File(path)) => Some(
+            std::fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("failed to read mode history {}: {e}", path.display())),
+        ),
+        Some(HistorySource::Service) => {
+            let history = mini_adas::persistency::get(MODE_HISTORY_KEY);
+            if history.is_none() {
+                warn!("No mode history stored under {}", MODE_HISTORY_KEY);
+            }
+            history
+        }
+        None => None,
+    };
+    if let Some(history) = history {
+        let timeline = ModeTimeline::parse(&history);
+        timeline.print();
+        if let Some(dir) = &params.csv_dir {
+            write_csv(dir, "modes.csv", &timeline.to_csv());
+        }
+    }
+}
+
+/// Where the mode history is read from
+enum HistorySource {
+    /// Text file with one `<timestamp_ms>;<mode>` entry per line
+    File(PathBuf),
+    /// The persistency service, under [MODE_HISTORY_KEY]
+    Service,
+}
+
+/// Parameters of the analysis
+struct Params {
+    /// Recording written by `adas_recorder`
+    recording: Option<PathBuf>,
+    /// Persisted mode history
+    history: Option<HistorySource>,
+    /// Directory receiving the CSV exports
+    csv_dir: Option<PathBuf>,
+}
+
+impl Params {
+    fn from_args() -> Self {
+        let mut params = Self {
+            recording: None,
+            history: None,
+            csv_dir: None,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| usage(&format!("missing value of {name}")))
+            };
+            match arg.as_str() {
+                "--recording" => params.recording = Some(value("--recording")),
+                "--history" => params.history = Some(HistorySource::File(value("--history"))),
+                "--history-from-service" => params.history = Some(HistorySource::Service),
+                "--csv" => params.csv_dir = Some(value("--csv")),
+                "-h" | "--help" => usage(""),
+                other => usage(&format!("unknown argument {other}")),
+            }
+        }
+
+        // Without any input analyze the recording at the recorder's default location
+        if params.recording.is_none() && params.history.is_none() {
+            params.recording = Some(PathBuf::from("rec.bin"));
+        }
+        params
+    }
+}
+
+fn usage(error: &str) -> ! {
+    if !error.is_empty() {
+        eprintln!("error: {error}");
+    }
+    eprintln!(
+        "usage: adas-analyze [--recording <rec.bin>] [--history <file> | --history-from-service] [--csv <dir>]"
+    );
+    std::process::exit(if error.is_empty() { 0 } else { 2 })
+}
+
+/// A recorded message type whose numeric fields are analyzed
+trait Sample {
+    /// Names of the fields in the order of [Sample::values]
+    const FIELDS: &'static [&'static str];
+
+    fn values(&self) -> Vec<f64>;
+}
+
+fn flag(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl Sample for CameraImage {
+    const FIELDS: &'static [&'static str] = &["num_people", "num_cars", "distance_obstacle"];
+
+    fn values(&self) -> Vec<f64> {
+        vec![
+            self.num_people as f64,
+            self.num_cars as f64,
+            self.distance_obstacle,
+        ]
+    }
+}
+
+impl Sample for RadarScan {
+    const FIELDS: &'static [&'static str] = &["distance_obstacle", "error_margin"];
+
+    fn values(&self) -> Vec<f64> {
+        vec![self.distance_obstacle, self.error_margin]
+    }
+}
+
+impl Sample for Scene {
+    const FIELDS: &'static [&'static str] = &[
+        "num_people",
+        "num_cars",
+        "distance_obstacle",
+        "distance_left_lane",
+        "distance_right_lane",
+    ];
+
+    fn values(&self) -> Vec<f64> {
+        vec![
+            self.num_people as f64,
+            self.num_cars as f64,
+            self.distance_obstacle,
+            self.distance_left_lane,
+            self.distance_right_lane,
+        ]
+    }
+}
+
+impl Sample for BrakeInstruction {
+    const FIELDS: &'static [&'static str] = &["active", "level"];
+
+    fn values(&self) -> Vec<f64> {
+        vec![flag(self.active), self.level]
+    }
+}
+
+impl Sample for ThrottleInstruction {
+    const FIELDS: &'static [&'static str] = &["level"];
+
+    fn values(&self) -> Vec<f64> {
+        vec![self.level]
+    }
+}
+
+impl Sample for Steering {
+    const FIELDS: &'static [&'static str] = &["angle"];
+
+    fn values(&self) -> Vec<f64> {
+        vec![self.angle]
+    }
+}
+
+impl Sample for VehicleState {
+    const FIELDS: &'static [&'static str] = &[
+        "speed",
+        "acceleration",
+        "brake_torque",
+        "brake_level",
+        "throttle_level",
+        "road_friction",
+        "drive_energy",
+        "brake_temperature",
+    ];
+
+    fn values(&self) -> Vec<f64> {
+        vec![
+            self.speed,
+            self.acceleration,
+            self.brake_torque,
+            self.brake_level,
+            self.throttle_level,
+            self.road_friction,
+            self.drive_energy,
+            self.brake_temperature,
+        ]
+    }
+}
+
+/// Samples of one topic: seconds since startup of the primary and field values
+struct Series {
+    type_name: String,
+    fields: &'static [&'static str],
+    samples: Vec<(f64, Vec<f64>)>,
+}
+
+impl Series {
+    fn new<T: Sample>(type_name: &str) -> Self {
+        Self {
+            type_name: type_name.to_string(),
+            fields: T::FIELDS,
+            samples: Vec::new(),
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = format!("time_s,{}\n", self.fields.join(","));
+        for (time, values) in &self.samples {
+            let _ = write!(csv, "{time:.6}");
+            for value in values {
+                let _ = write!(csv, ",{value}");
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// Decode the payload of a data record if it is a `T`
+///
+/// Returns `None` if the record describes a different type.
+fn decode<'a, T: Sample + Deserialize<'a>>(
+    type_name: &str,
+    bytes: &'a [u8],
+) -> Option<(Vec<f64>, &'a [u8])> {
+    (type_name == std::any::type_name::<T>()).then(|| {
+        let (message, remaining): (T, _) =
+            postcard::take_from_bytes(bytes).expect("failed to deserialize message");
+        (message.values(), remaining)
+    })
+}
+
+/// Read all data records of a recording, grouped by topic
+fn read_recording(path: &Path) -> BTreeMap<String, Series> {
+    let serialized_data = std::fs::read(path)
+        .unwrap_or_else(|e| panic!("failed to read recording {}: {e}", path.display()));
+    info!(
+        "Read recording {} with {} bytes",
+        path.display(),
+        serialized_data.len()
+    );
+
+    let mut series: BTreeMap<String, Series> = BTreeMap::new();
+    let mut skipped = 0usize;
+    let mut remaining_bytes = serialized_data.as_slice();
+    while !remaining_bytes.is_empty() {
+        let (record, remaining) = match postcard::take_from_bytes(remaining_bytes) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                // The recorder may have been stopped in the middle of a record
+                warn!("Stopping at truncated record: {e}");
+                break;
+            }
+        };
+        remaining_bytes = remaining;
+
+        let Record::DataDescription(data_record) = record else {
+            continue;
+        };
+        let time = data_record.timestamp.0.as_secs_f64();
+        let type_name = data_record.type_name;
+
+        macro_rules! decode_as {
+            ($($message:ty),*) => {
+                $(
+                    if let Some((values, remaining)) = decode::<$message>(type_name, remaining_bytes) {
+                        series
+                            .entry(data_record.topic.to_string())
+                            .or_insert_with(|| Series::new::<$message>(type_name))
+                            .samples
+                            .push((time, values));
+                        remaining_bytes = remaining;
+                        continue;
+                    }
+                )*
+            };
+        }
+        decode_as!(
+            CameraImage,
+            RadarScan,
+            Scene,
+            BrakeInstruction,
+            ThrottleInstruction,
+            Steering,
+            VehicleState
+        );
+
+        // Not analyzed, skip the data
+        skipped += 1;
+        remaining_bytes = &remaining_bytes[data_record.data_size.min(remaining_bytes.len())..];
+    }
+    if skipped > 0 {
+        info!("Skipped {} records of types that are not analyzed", skipped);
+    }
+    series
+}
+
+fn print_topic_summary(series: &BTreeMap<String, Series>) {
+    println!("== Topics ==");
+    if series.is_empty() {
+        println!("no recorded messages");
+    }
+    for (topic, series) in series {
+        let (Some((first, _)), Some((last, _))) = (series.samples.first(), series.samples.last())
+        else {
+            continue;
+        };
+        let duration = last - first;
+        let rate = if duration > 0.0 {
+            (series.samples.len() - 1) as f64 / duration
+        } else {
+            0.0
+        };
+        println!(
+            "{topic} ({}): {} samples over {duration:.1} s, {rate:.2} Hz",
+            series.type_name,
+            series.samples.len()
+        );
+        for (index, field) in series.fields.iter().enumerate() {
+            let values = series.samples.iter().map(|(_, values)| values[index]);
+            let min = values.clone().fold(f64::INFINITY, f64::min);
+            let max = values.clone().fold(f64::NEG_INFINITY, f64::max);
+            let mean = values.sum::<f64>() / series.samples.len() as f64;
+            println!("  {field:<20} min {min:>10.3}  mean {mean:>10.3}  max {max:>10.3}");
+        }
+    }
+}
+
+/// Mode changes of the CarModeCalculator, oldest first
+struct ModeTimeline {
+    /// Unix time in milliseconds and the mode selected at that time
+    entries: Vec<(i64, String)>,
+}
+
+impl ModeTimeline {
+    /// Parse the persisted history, skipping malformed lines
+    fn parse(history: &str) -> Self {
+        let mut entries: Vec<(i64, String)> = history
+            .lines()
+            .filter_map(|line| {
+                let (timestamp, mode) = line.trim().split_once(';')?;
+                Some((timestamp.parse().ok()?, mode.to_string()))
+            })
+            .collect();
+        entries.sort_by_key(|(timestamp, _)| *timestamp);
+        Self { entries }
+    }
+
+    /// Time each entry was active in milliseconds, `None` for the current mode
+    fn durations(&self) -> impl Iterator<Item = Option<i64>> + '_ {
+        let next = self.entries.iter().skip(1).map(|(next, _)| Some(*next));
+        self.entries
+            .iter()
+            .zip(next.chain(core::iter::once(None)))
+            .map(|((timestamp, _), next)| next.map(|next| next - timestamp))
+    }
+
+    fn print(&self) {
+        println!("== Mode timeline ==");
+        let Some((start, _)) = self.entries.first() else {
+            println!("no mode changes");
+            return;
+        };
+
+        let mut time_in_mode: BTreeMap<&str, i64> = BTreeMap::new();
+        for ((timestamp, mode), duration) in self.entries.iter().zip(self.durations()) {
+            let offset = (timestamp - start) as f64 / 1000.0;
+            match duration {
+                Some(duration) => {
+                    *time_in_mode.entry(mode).or_default() += duration;
+                    println!(
+                        "+{offset:>9.1} s  {mode:<10} for {:.1} s",
+                        duration as f64 / 1000.0
+                    );
+                }
+                None => println!("+{offset:>9.1} s  {mode:<10} (current)"),
+            }
+        }
+
+        println!("{} transitions", self.entries.len().saturating_sub(1));
+        let total: i64 = time_in_mode.values().sum();
+        for (mode, time) in &time_in_mode {
+            let share = if total > 0 {
+                *time as f64 * 100.0 / total as f64
+            } else {
+                0.0
+            };
+            println!(
+                "  {mode:<10} {:>9.1} s  {share:>5.1} %",
+                *time as f64 / 1000.0
+            );
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("timestamp_ms,mode,duration_ms\n");
+        for ((timestamp, mode), duration) in self.entries.iter().zip(self.durations()) {
+            let duration = duration.map(|duration| duration.to_string());
+            let _ = writeln!(csv, "{timestamp},{mode},{}", duration.unwrap_or_default());
+        }
+        csv
+    }
+}
+
+/// File name of the CSV export of a topic, e.g. `vehicle_state.csv`
+fn csv_name(topic: &str) -> String {
+    let name = topic.strip_prefix("feo/com/vehicle/").unwrap_or(topic);
+    format!("{}.csv", name.replace('/', "_"))
+}
+
+fn write_csv(dir: &Path, name: &str, csv: &str) {
+    std::fs::create_dir_all(dir)
+        .unwrap_or_else(|e| panic!("failed to create {}: {e}", dir.display()));
+    let path = dir.join(name);
+    std::fs::write(&path, csv)
+        .unwrap_or_else(|e| panic!("failed to write {}: {e}", path.display()));
+    info!("Wrote {}", path.display());
+}
@@ -61,6 +61,16 @@ fn main() {
             {
                 remaining_bytes = remaining;
                 println!("{:#?}", steering);
+            } else if let Some((throttle, remaining)) =
+                try_deserialization_as_a::<messages::ThrottleInstruction>(data_record, remaining_bytes)
+            {
+                remaining_bytes = remaining;
+                println!("{:#?}", throttle);
+            } else if let Some((vehicle_state, remaining)) =
+                try_deserialization_as_a::<messages::VehicleState>(data_record, remaining_bytes)
+            {
+                remaining_bytes = remaining;
+                println!("{:#?}", vehicle_state);
             } else {
                 // Skip data record
                 info!("Skipping deserialization of {}", data_record.type_name);
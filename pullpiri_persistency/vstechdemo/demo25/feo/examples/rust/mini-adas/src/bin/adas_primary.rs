@@ -15,9 +15,9 @@ use feo::agent::com_init::initialize_com_primary;
 use feo::ids::AgentId;
 use feo_log::{info, LevelFilter};
 use feo_time::Duration;
-use mini_adas::config::{
-    agent_assignments_ids, topic_dependencies, COM_BACKEND, MAX_ADDITIONAL_SUBSCRIBERS,
-};
+use mini_adas::com::com_backend;
+use mini_adas::config::{agent_assignments_ids, topic_dependencies, MAX_ADDITIONAL_SUBSCRIBERS};
+use mini_adas::safety::{enforce_partitioning, SafetyProfile};
 use std::collections::HashSet;
 
 const AGENT_ID: AgentId = AgentId::new(100);
@@ -31,11 +31,14 @@ fn main() {
 
     info!("Starting primary agent {AGENT_ID}");
 
+    // Refuse to run safety-critical activities without process isolation
+    enforce_partitioning(SafetyProfile::from_env(), &agent_assignments_ids());
+
     let config = cfg::make_config(params);
 
     // Initialize topics. Do not drop.
     let _topic_guards = initialize_com_primary(
-        COM_BACKEND,
+        com_backend(),
         AGENT_ID,
         topic_dependencies(),
         &agent_assignments_ids(),
@@ -15,14 +15,16 @@ use feo::ids::AgentId;
 use feo::recording::recorder::RecordingRules;
 use feo_log::{debug, LevelFilter};
 use mini_adas::activities::messages::{
-    self, BrakeInstruction, CameraImage, RadarScan, Scene, Steering,
+    self, BrakeInstruction, CameraImage, RadarScan, Scene, Steering, ThrottleInstruction,
+    VehicleState,
 };
 
 use feo::agent::com_init::initialize_com_recorder;
 use feo::topicspec::TopicSpecification;
+use mini_adas::com::com_backend;
 use mini_adas::config::{
-    topic_dependencies, COM_BACKEND, TOPIC_CAMERA_FRONT, TOPIC_CONTROL_BRAKES,
-    TOPIC_CONTROL_STEERING, TOPIC_INFERRED_SCENE, TOPIC_RADAR_FRONT,
+    topic_dependencies, TOPIC_CAMERA_FRONT, TOPIC_CONTROL_BRAKES, TOPIC_CONTROL_STEERING,
+    TOPIC_CONTROL_THROTTLE, TOPIC_INFERRED_SCENE, TOPIC_RADAR_FRONT, TOPIC_VEHICLE_STATE,
 };
 use std::collections::HashMap;
 
@@ -39,6 +41,11 @@ fn main() {
             core::any::type_name::<BrakeInstruction>(),
         ),
         (TOPIC_CONTROL_STEERING, core::any::type_name::<Steering>()),
+        (
+            TOPIC_CONTROL_THROTTLE,
+            core::any::type_name::<ThrottleInstruction>(),
+        ),
+        (TOPIC_VEHICLE_STATE, core::any::type_name::<VehicleState>()),
         (TOPIC_INFERRED_SCENE, core::any::type_name::<Scene>()),
         (TOPIC_RADAR_FRONT, core::any::type_name::<RadarScan>()),
     ]);
@@ -52,7 +59,7 @@ fn main() {
         .collect();
 
     // Initialize topics. Do not drop.
-    let _topic_guards = initialize_com_recorder(COM_BACKEND, topic_specs);
+    let _topic_guards = initialize_com_recorder(com_backend(), topic_specs);
 
     debug!("Creating recorder with agent id {}", params.agent_id);
     let mut recorder = cfg::Recorder::new(config);
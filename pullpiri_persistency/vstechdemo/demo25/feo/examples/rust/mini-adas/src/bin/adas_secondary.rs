@@ -23,8 +23,10 @@ fn main() {
     use mini_adas::config::socket_paths;
     #[cfg(feature = "signalling_direct_tcp")]
     use mini_adas::config::BIND_ADDR;
+    use mini_adas::com::com_backend;
     use mini_adas::config::{agent_assignments, topic_dependencies};
-    use mini_adas::config::{agent_assignments_ids, COM_BACKEND};
+    use mini_adas::config::agent_assignments_ids;
+    use mini_adas::safety::{enforce_partitioning, SafetyProfile};
     use params::Params;
     use std::collections::HashSet;
 
@@ -33,6 +35,7 @@ fn main() {
 
     let params = Params::from_args();
     info!("Starting agent {}", params.agent_id);
+    enforce_partitioning(SafetyProfile::from_env(), &agent_assignments_ids());
 
     let config = SecondaryConfig {
         id: params.agent_id,
@@ -55,7 +58,7 @@ fn main() {
 
     // Initialize topics. Do not drop.
     let _topic_guards =
-        initialize_com_secondary(COM_BACKEND, topic_dependencies(), &local_activities);
+        initialize_com_secondary(com_backend(), topic_dependencies(), &local_activities);
 
     let secondary = Secondary::new(config);
     secondary.run();
@@ -69,8 +72,10 @@ fn main() {
     use feo::agent::NodeAddress;
     use feo::ids::ActivityId;
     use feo_log::{info, LevelFilter};
+    use mini_adas::com::com_backend;
     use mini_adas::config::{agent_assignments, topic_dependencies};
-    use mini_adas::config::{agent_assignments_ids, COM_BACKEND};
+    use mini_adas::config::agent_assignments_ids;
+    use mini_adas::safety::{enforce_partitioning, SafetyProfile};
     use mini_adas::config::{BIND_ADDR, BIND_ADDR2};
     use params::Params;
     use std::collections::HashSet;
@@ -80,6 +85,7 @@ fn main() {
 
     let params = Params::from_args();
     info!("Starting agent {}", params.agent_id);
+    enforce_partitioning(SafetyProfile::from_env(), &agent_assignments_ids());
 
     let config = SecondaryConfig {
         id: params.agent_id,
@@ -100,7 +106,7 @@ fn main() {
 
     // Initialize topics. Do not drop.
     let _topic_guards =
-        initialize_com_secondary(COM_BACKEND, topic_dependencies(), &local_activities);
+        initialize_com_secondary(com_backend(), topic_dependencies(), &local_activities);
 
     let secondary = Secondary::new(config);
     secondary.run();
@@ -115,8 +121,10 @@ fn main() {
     use feo::ids::ActivityId;
     use feo_log::{info, LevelFilter};
     use mini_adas::config::socket_paths;
+    use mini_adas::com::com_backend;
     use mini_adas::config::{agent_assignments, topic_dependencies};
-    use mini_adas::config::{agent_assignments_ids, COM_BACKEND};
+    use mini_adas::config::agent_assignments_ids;
+    use mini_adas::safety::{enforce_partitioning, SafetyProfile};
     use params::Params;
     use std::collections::HashSet;
 
@@ -125,6 +133,7 @@ fn main() {
 
     let params = Params::from_args();
     info!("Starting agent {}", params.agent_id);
+    enforce_partitioning(SafetyProfile::from_env(), &agent_assignments_ids());
 
     let config = SecondaryConfig {
         id: params.agent_id,
@@ -145,7 +154,7 @@ fn main() {
 
     // Initialize topics. Do not drop.
     let _topic_guards =
-        initialize_com_secondary(COM_BACKEND, topic_dependencies(), &local_activities);
+        initialize_com_secondary(com_backend(), topic_dependencies(), &local_activities);
 
     let secondary = Secondary::new(config);
     secondary.run();
@@ -0,0 +1,105 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Runtime selection of the com backend
+//!
+//! Both com backends are compiled into the agents and the backend is picked
+//! once per process at startup. iceoryx2 is preferred, but it needs shared
+//! memory and a writable iceoryx2 state directory, which containers and some
+//! test hosts do not provide. Before iceoryx2 is selected, a probe creates a
+//! throwaway service; if that fails the agent falls back to linux_shm instead
+//! of panicking on the first topic.
+//!
+//! `MINI_ADAS_COM_BACKEND` overrides the selection with `iox2` or
+//! `linux_shm`, `auto` being the default. All agents of a deployment have to
+//! use the same backend. The probe gives the same result for agents on one
+//! host, so the variable only needs to be set when the agents run with
+//! different permissions.
+
+use feo_com::interface::ComBackend;
+use feo_log::{error, info, warn};
+use std::sync::OnceLock;
+
+/// Environment variable selecting the com backend, `auto`, `iox2` or `linux_shm`
+pub const COM_BACKEND_ENV: &str = "MINI_ADAS_COM_BACKEND";
+
+/// Backend requested by [`COM_BACKEND_ENV`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendRequest {
+    /// iceoryx2 if the probe succeeds, linux_shm otherwise
+    Auto,
+    Iox2,
+    LinuxShm,
+}
+
+impl BackendRequest {
+    /// Request from `MINI_ADAS_COM_BACKEND`, [`BackendRequest::Auto`] without the variable
+    pub fn from_env() -> Self {
+        match std::env::var(COM_BACKEND_ENV).as_deref() {
+            Ok("iox2") => Self::Iox2,
+            Ok("linux_shm") => Self::LinuxShm,
+            Ok("auto") | Err(_) => Self::Auto,
+            Ok(other) => {
+                warn!(
+                    "Unknown {COM_BACKEND_ENV} '{other}', selecting the com backend automatically"
+                );
+                Self::Auto
+            }
+        }
+    }
+}
+
+/// Com backend of this process, selected on the first call
+///
+/// The topics and the activity inputs and outputs of one process must use
+/// the same backend, so the selection is never repeated.
+pub fn com_backend() -> ComBackend {
+    static BACKEND: OnceLock<ComBackend> = OnceLock::new();
+
+    *BACKEND.get_or_init(|| {
+        let request = BackendRequest::from_env();
+        let backend = select(request);
+        info!("Using com backend {backend:?} (requested {request:?})");
+        backend
+    })
+}
+
+fn select(request: BackendRequest) -> ComBackend {
+    match request {
+        BackendRequest::LinuxShm => ComBackend::LinuxShm,
+        BackendRequest::Auto | BackendRequest::Iox2 => iox2_or_fallback(request),
+    }
+}
+
+#[cfg(feature = "com_iox2")]
+fn iox2_or_fallback(request: BackendRequest) -> ComBackend {
+    match feo_com::iox2::probe() {
+        Ok(()) => ComBackend::Iox2,
+        Err(err) if request == BackendRequest::Iox2 => {
+            error!("iceoryx2 requested by {COM_BACKEND_ENV} is unavailable ({err}), falling back to linux_shm");
+            ComBackend::LinuxShm
+        }
+        Err(err) => {
+            warn!("iceoryx2 is unavailable ({err}), falling back to linux_shm");
+            ComBackend::LinuxShm
+        }
+    }
+}
+
+#[cfg(not(feature = "com_iox2"))]
+fn iox2_or_fallback(request: BackendRequest) -> ComBackend {
+    if request == BackendRequest::Iox2 {
+        error!("iceoryx2 requested by {COM_BACKEND_ENV} is not compiled in, using linux_shm");
+    }
+    ComBackend::LinuxShm
+}
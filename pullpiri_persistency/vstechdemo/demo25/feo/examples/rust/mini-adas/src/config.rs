@@ -12,39 +12,47 @@
  ********************************************************************************/
 
 use crate::activities::components::{
-    Camera, EnvironmentRenderer, NeuralNet, Radar,
+    BrakeController, Camera, EnvironmentRenderer, NeuralNet, Radar,
     SteeringController, CarModeCalculator, CarDataPublisher, AutonomousModePublisher, 
-    ManualModePublisher, EmergencyModePublisher,
+    ManualModePublisher, EmergencyModePublisher, RouteSimulator, ScenarioValidator, V2xReceiver, OccupancyModel,
 };
-use crate::activities::messages::{CameraImage, RadarScan, Scene, Steering, CarData, AutonomousCarData, ManualCarData, EmergencyModeData};
+use crate::activities::messages::{ActiveScenario, BrakeInstruction, CameraImage, RadarScan, Scene, Steering, ThrottleInstruction, VehicleState, CarData, AutonomousCarData, ManualCarData, EmergencyModeData, MapAttributes, ModeDecisionExplanation, SafeStateRequest, V2xHazard, Occupancy};
 use crate::ffi::{lane_assist, trajectory_visualizer};
 use core::net::{IpAddr, Ipv4Addr, SocketAddr};
 use feo::activity::{ActivityBuilder, ActivityIdAndBuilder};
 use feo::ids::{ActivityId, AgentId, WorkerId};
 use feo::topicspec::{Direction, TopicSpecification};
-use feo_com::interface::ComBackend;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 pub type WorkerAssignment = (WorkerId, Vec<(ActivityId, Box<dyn ActivityBuilder>)>);
 
+/// Activity ids of a worker
+pub type WorkerLayout = (WorkerId, Vec<ActivityId>);
+
+/// Activity ids per worker per agent
+pub type AssignmentLayout = HashMap<AgentId, Vec<WorkerLayout>>;
+
 // For each activity, list the activities it needs to wait for
 pub type ActivityDependencies = HashMap<ActivityId, Vec<ActivityId>>;
 
-#[cfg(feature = "com_iox2")]
-pub const COM_BACKEND: ComBackend = ComBackend::Iox2;
-#[cfg(feature = "com_linux_shm")]
-pub const COM_BACKEND: ComBackend = ComBackend::LinuxShm;
-
 pub const BIND_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8081);
 pub const BIND_ADDR2: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8082);
 
 pub const TOPIC_INFERRED_SCENE: &str = "feo/com/vehicle/inferred/scene";
 pub const TOPIC_CONTROL_BRAKES: &str = "feo/com/vehicle/control/brakes";
 pub const TOPIC_CONTROL_STEERING: &str = "feo/com/vehicle/control/steering";
+pub const TOPIC_CONTROL_THROTTLE: &str = "feo/com/vehicle/control/throttle";
+pub const TOPIC_VEHICLE_STATE: &str = "feo/com/vehicle/state";
 pub const TOPIC_CAMERA_FRONT: &str = "feo/com/vehicle/camera/front";
+pub const TOPIC_DEMO_SCENARIO: &str = "feo/com/vehicle/camera/scenario";
 pub const TOPIC_RADAR_FRONT: &str = "feo/com/vehicle/radar/front";
+pub const TOPIC_MAP_ATTRIBUTES: &str = "feo/com/vehicle/map/attributes";
+pub const TOPIC_V2X_HAZARD: &str = "feo/com/vehicle/v2x/hazard";
+pub const TOPIC_OCCUPANCY: &str = "feo/com/vehicle/cabin/occupancy";
+pub const TOPIC_SAFE_STATE_REQUEST: &str = "feo/com/vehicle/supervision/safe_state";
 pub const TOPIC_CAR_DATA: &str = "feo/com/vehicle/car_data";
+pub const TOPIC_MODE_EXPLANATION: &str = "feo/com/vehicle/mode_explanation";
 pub const TOPIC_AUTONOMOUS_DATA: &str = "feo/com/vehicle/autonomous_data";
 pub const TOPIC_MANUAL_DATA: &str = "feo/com/vehicle/manual_data";
 pub const TOPIC_EMERGENCY_DATA: &str = "feo/com/vehicle/emergency_data";
@@ -60,83 +68,57 @@ pub fn socket_paths() -> (PathBuf, PathBuf) {
 }
 
 pub fn agent_assignments() -> HashMap<AgentId, Vec<(WorkerId, Vec<ActivityIdAndBuilder>)>> {
+    let assignment = crate::assignments::layout()
+        .into_iter()
+        .map(|(agent, workers)| {
+            let workers = workers
+                .into_iter()
+                .map(|(worker, activities)| {
+                    let activities = activities
+                        .into_iter()
+                        .map(|id| {
+                            let builder = activity_builder(id)
+                                .unwrap_or_else(|| panic!("No builder for activity {id}"));
+                            (id, builder)
+                        })
+                        .collect();
+                    (worker, activities)
+                })
+                .collect();
+            (agent, workers)
+        })
+        .collect();
+
+    crate::supervision::instrument(crate::step_counters::instrument(crate::runs::instrument(
+        assignment,
+    )))
+}
+
+/// Built-in assignments of the activities, replaced by the file named by
+/// [`ASSIGNMENTS_ENV`](crate::assignments::ASSIGNMENTS_ENV)
+pub fn default_layout() -> AssignmentLayout {
     // ASIL Functional Safety Architecture:
     // - Agent 100 (QM Process): Non-safety sensor simulation
     // - Agent 101 (QM Process): Data fusion and decision making  
     // - Agent 102 (Safety-Critical Process): Vehicle control and safety functions
     
-    // QM Process - Sensor simulation workers
-    let w40: WorkerAssignment = (
-        40.into(),
-        vec![(
-            0.into(),
-            Box::new(|id| Camera::build(id, TOPIC_CAMERA_FRONT)),
-        )],
-    );
-    let w41: WorkerAssignment = (
-        41.into(),
-        vec![(1.into(), Box::new(|id| Radar::build(id, TOPIC_RADAR_FRONT)))],
-    );
+    // QM Process - Sensor simulation workers: Camera, Radar, RouteSimulator, V2xReceiver and
+    // OccupancyModel
+    let w40: WorkerLayout = (40.into(), vec![0.into()]);
+    let w41: WorkerLayout = (41.into(), vec![1.into()]);
+    let w43: WorkerLayout = (43.into(), vec![14.into(), 15.into(), 17.into()]);
 
-    // QM Process - Data fusion and decision making worker
-    let w42: WorkerAssignment = (
+    // QM Process - Data fusion and decision making worker: NeuralNet, EnvironmentRenderer,
+    // CarModeCalculator, the data publishers and the ScenarioValidator
+    let w42: WorkerLayout = (
         42.into(),
-        vec![
-            (
-                2.into(),
-                Box::new(|id| {
-                    NeuralNet::build(
-                        id,
-                        TOPIC_CAMERA_FRONT,
-                        TOPIC_RADAR_FRONT,
-                        TOPIC_INFERRED_SCENE,
-                    )
-                }),
-            ),
-            (
-                3.into(),
-                Box::new(|id| EnvironmentRenderer::build(id, TOPIC_INFERRED_SCENE)),
-            ),
-            (
-                9.into(),
-                Box::new(|id| CarModeCalculator::build(id, TOPIC_INFERRED_SCENE, TOPIC_CAR_DATA)),
-            ),
-            (
-                10.into(),
-                Box::new(|id| CarDataPublisher::build(id, TOPIC_CAR_DATA)),
-            ),
-            (
-                11.into(),
-                Box::new(|id| AutonomousModePublisher::build(id, TOPIC_CAR_DATA, TOPIC_INFERRED_SCENE, TOPIC_AUTONOMOUS_DATA)),
-            ),
-            (
-                12.into(),
-                Box::new(|id| ManualModePublisher::build(id, TOPIC_CAR_DATA, TOPIC_INFERRED_SCENE, TOPIC_MANUAL_DATA)),
-            ),
-            (
-                13.into(),
-                Box::new(|id| EmergencyModePublisher::build(id, TOPIC_CAR_DATA, TOPIC_INFERRED_SCENE, TOPIC_EMERGENCY_DATA)),
-            ),
-        ],
+        vec![2.into(), 3.into(), 9.into(), 10.into(), 11.into(), 12.into(), 13.into(), 16.into()],
     );
 
-    // Safety-Critical Process - Vehicle control worker (ASIL compliant isolation)
-    let w44: WorkerAssignment = (
-        44.into(),
-        vec![
-            // Safety-critical steering control functions
-            (5.into(), Box::new(|id| lane_assist::CppActivity::build(id))),
-            (
-                7.into(),
-                Box::new(|id| SteeringController::build(id, TOPIC_CONTROL_STEERING)),
-            ),
-            // Non-safety trajectory visualization (runs in safety process for data access)
-            (
-                8.into(),
-                Box::new(|id| trajectory_visualizer::CppActivity::build(id)),
-            ),
-        ],
-    );
+    // Safety-Critical Process - Vehicle control worker (ASIL compliant isolation): LaneAssist,
+    // BrakeController, SteeringController and the non-safety TrajectoryVisualizer, which runs
+    // in the safety process for data access
+    let w44: WorkerLayout = (44.into(), vec![5.into(), 6.into(), 7.into(), 8.into()]);
 
     // ASIL-compliant agent assignments ensuring Freedom From Interference (FFI):
     // All configurations must maintain strict process separation between QM and Safety-Critical
@@ -146,9 +128,9 @@ pub fn agent_assignments() -> HashMap<AgentId, Vec<(WorkerId, Vec<ActivityIdAndB
         feature = "signalling_relayed_tcp",
         feature = "signalling_relayed_unix"
     ))]
-    let assignment = [
+    let layout = [
         // Agent 100: QM Process - Sensor simulation (non-safety)
-        (100.into(), vec![w40, w41]),
+        (100.into(), vec![w40, w41, w43]),
         // Agent 101: QM Process - Data processing and decisions (non-safety)  
         (101.into(), vec![w42]),
         // Agent 102: Safety-Critical Process - Vehicle control (ASIL compliant)
@@ -160,11 +142,55 @@ pub fn agent_assignments() -> HashMap<AgentId, Vec<(WorkerId, Vec<ActivityIdAndB
     // Note: direct_mpsc mode violates ASIL FFI requirements by running all agents in one process
     // This configuration is only for development/testing and should NOT be used in production
     #[cfg(feature = "signalling_direct_mpsc")]
-    let assignment = [(100.into(), vec![w40, w41, w42, w44])]
+    let layout = [(100.into(), vec![w40, w41, w43, w42, w44])]
         .into_iter()
         .collect();
 
-    assignment
+    layout
+}
+
+/// Builder of an activity, `None` for an unknown activity id
+pub fn activity_builder(id: ActivityId) -> Option<Box<dyn ActivityBuilder>> {
+    let builder: Box<dyn ActivityBuilder> = match id.id() {
+        0 => Box::new(|id| Camera::build(id, TOPIC_CAMERA_FRONT, TOPIC_DEMO_SCENARIO)),
+        1 => Box::new(|id| Radar::build(id, TOPIC_RADAR_FRONT)),
+        2 => Box::new(|id| {
+            NeuralNet::build(
+                id,
+                TOPIC_CAMERA_FRONT,
+                TOPIC_RADAR_FRONT,
+                TOPIC_INFERRED_SCENE,
+            )
+        }),
+        3 => Box::new(|id| EnvironmentRenderer::build(id, TOPIC_INFERRED_SCENE)),
+        // Safety-critical steering control functions
+        5 => Box::new(|id| lane_assist::CppActivity::build(id)),
+        // Longitudinal vehicle dynamics fed by the brake and throttle instructions
+        6 => Box::new(|id| {
+            BrakeController::build(
+                id,
+                TOPIC_CONTROL_BRAKES,
+                TOPIC_CONTROL_THROTTLE,
+                TOPIC_MAP_ATTRIBUTES,
+                TOPIC_VEHICLE_STATE,
+            )
+        }),
+        7 => Box::new(|id| SteeringController::build(id, TOPIC_CONTROL_STEERING)),
+        // Non-safety trajectory visualization
+        8 => Box::new(|id| trajectory_visualizer::CppActivity::build(id)),
+        9 => Box::new(|id| CarModeCalculator::build(id, TOPIC_INFERRED_SCENE, TOPIC_MAP_ATTRIBUTES, TOPIC_V2X_HAZARD, TOPIC_VEHICLE_STATE, TOPIC_SAFE_STATE_REQUEST, TOPIC_CAR_DATA, TOPIC_MODE_EXPLANATION, TOPIC_CONTROL_BRAKES, TOPIC_CONTROL_THROTTLE)),
+        10 => Box::new(|id| CarDataPublisher::build(id, TOPIC_CAR_DATA, TOPIC_MODE_EXPLANATION)),
+        11 => Box::new(|id| AutonomousModePublisher::build(id, TOPIC_CAR_DATA, TOPIC_INFERRED_SCENE, TOPIC_MAP_ATTRIBUTES, TOPIC_VEHICLE_STATE, TOPIC_AUTONOMOUS_DATA)),
+        12 => Box::new(|id| ManualModePublisher::build(id, TOPIC_CAR_DATA, TOPIC_INFERRED_SCENE, TOPIC_VEHICLE_STATE, TOPIC_MANUAL_DATA)),
+        13 => Box::new(|id| EmergencyModePublisher::build(id, TOPIC_CAR_DATA, TOPIC_INFERRED_SCENE, TOPIC_VEHICLE_STATE, TOPIC_OCCUPANCY, TOPIC_EMERGENCY_DATA)),
+        14 => Box::new(|id| RouteSimulator::build(id, TOPIC_MAP_ATTRIBUTES)),
+        15 => Box::new(|id| V2xReceiver::build(id, TOPIC_V2X_HAZARD)),
+        // Rehearsal checks of the scenario outcomes, idle unless enabled
+        16 => Box::new(|id| ScenarioValidator::build(id, TOPIC_DEMO_SCENARIO, TOPIC_CAR_DATA)),
+        17 => Box::new(|id| OccupancyModel::build(id, TOPIC_VEHICLE_STATE, TOPIC_OCCUPANCY)),
+        _ => return None,
+    };
+    Some(builder)
 }
 
 pub fn activity_dependencies() -> ActivityDependencies {
@@ -179,20 +205,30 @@ pub fn activity_dependencies() -> ActivityDependencies {
         (3.into(), vec![2.into()]),
         // LaneAssist
         (5.into(), vec![2.into()]),
+        // BrakeController
+        (6.into(), vec![9.into(), 14.into()]),
         // SteeringController
         (7.into(), vec![5.into()]),
         // TrajectoryVisualizer
         (8.into(), vec![5.into()]),
         // CarModeCalculator
-        (9.into(), vec![2.into()]),
+        (9.into(), vec![2.into(), 14.into(), 15.into()]),
         // CarDataPublisher
         (10.into(), vec![9.into()]),
         // AutonomousModePublisher
-        (11.into(), vec![10.into()]),
+        (11.into(), vec![10.into(), 6.into()]),
         // ManualModePublisher
-        (12.into(), vec![10.into()]),
+        (12.into(), vec![10.into(), 6.into()]),
         // EmergencyModePublisher
-        (13.into(), vec![10.into()]),
+        (13.into(), vec![10.into(), 6.into(), 17.into()]),
+        // RouteSimulator
+        (14.into(), vec![]),
+        // V2xReceiver
+        (15.into(), vec![]),
+        // ScenarioValidator
+        (16.into(), vec![0.into(), 9.into()]),
+        // OccupancyModel
+        (17.into(), vec![6.into()]),
     ];
 
     dependencies.into()
@@ -206,10 +242,27 @@ pub fn topic_dependencies<'a>() -> Vec<TopicSpecification<'a>> {
             TOPIC_CAMERA_FRONT,
             vec![(0.into(), Outgoing), (2.into(), Incoming)],
         ),
+        TopicSpecification::new::<ActiveScenario>(
+            TOPIC_DEMO_SCENARIO,
+            vec![(0.into(), Outgoing), (16.into(), Incoming)],
+        ),
         TopicSpecification::new::<RadarScan>(
             TOPIC_RADAR_FRONT,
             vec![(1.into(), Outgoing), (2.into(), Incoming)],
         ),
+        TopicSpecification::new::<MapAttributes>(
+            TOPIC_MAP_ATTRIBUTES,
+            vec![(14.into(), Outgoing), (6.into(), Incoming), (9.into(), Incoming), (11.into(), Incoming)],
+        ),
+        TopicSpecification::new::<V2xHazard>(
+            TOPIC_V2X_HAZARD,
+            vec![(15.into(), Outgoing), (9.into(), Incoming)],
+        ),
+        // Published by the supervisor of the BrakeController, see `supervision::SAFE_STATE_REPORTER`
+        TopicSpecification::new::<SafeStateRequest>(
+            TOPIC_SAFE_STATE_REQUEST,
+            vec![(6.into(), Outgoing), (9.into(), Incoming)],
+        ),
         TopicSpecification::new::<Scene>(
             TOPIC_INFERRED_SCENE,
             vec![
@@ -226,6 +279,29 @@ pub fn topic_dependencies<'a>() -> Vec<TopicSpecification<'a>> {
             TOPIC_CONTROL_STEERING,
             vec![(5.into(), Outgoing), (7.into(), Incoming)],
         ),
+        TopicSpecification::new::<BrakeInstruction>(
+            TOPIC_CONTROL_BRAKES,
+            vec![(9.into(), Outgoing), (6.into(), Incoming)],
+        ),
+        TopicSpecification::new::<ThrottleInstruction>(
+            TOPIC_CONTROL_THROTTLE,
+            vec![(9.into(), Outgoing), (6.into(), Incoming)],
+        ),
+        TopicSpecification::new::<VehicleState>(
+            TOPIC_VEHICLE_STATE,
+            vec![
+                (6.into(), Outgoing),
+                (9.into(), Incoming),
+                (11.into(), Incoming),
+                (12.into(), Incoming),
+                (13.into(), Incoming),
+                (17.into(), Incoming),
+            ],
+        ),
+        TopicSpecification::new::<Occupancy>(
+            TOPIC_OCCUPANCY,
+            vec![(17.into(), Outgoing), (13.into(), Incoming)],
+        ),
         TopicSpecification::new::<CarData>(
             TOPIC_CAR_DATA,
             vec![
@@ -234,8 +310,13 @@ pub fn topic_dependencies<'a>() -> Vec<TopicSpecification<'a>> {
                 (11.into(), Incoming),
                 (12.into(), Incoming),
                 (13.into(), Incoming),
+                (16.into(), Incoming),
             ],
         ),
+        TopicSpecification::new::<ModeDecisionExplanation>(
+            TOPIC_MODE_EXPLANATION,
+            vec![(9.into(), Outgoing), (10.into(), Incoming)],
+        ),
         TopicSpecification::new::<AutonomousCarData>(
             TOPIC_AUTONOMOUS_DATA,
             vec![(11.into(), Outgoing)],
@@ -259,15 +340,5 @@ pub fn worker_agent_map() -> HashMap<WorkerId, AgentId> {
 }
 
 pub fn agent_assignments_ids() -> HashMap<AgentId, Vec<(WorkerId, Vec<ActivityId>)>> {
-    agent_assignments()
-        .into_iter()
-        .map(|(k, v)| {
-            (
-                k,
-                v.into_iter()
-                    .map(|(w, a)| (w, a.into_iter().map(|(a, _)| a).collect()))
-                    .collect(),
-            )
-        })
-        .collect()
+    crate::assignments::layout()
 }
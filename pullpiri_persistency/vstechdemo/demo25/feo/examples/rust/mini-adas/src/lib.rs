@@ -16,5 +16,12 @@
 #![deny(clippy::std_instead_of_core)]
 
 pub mod activities;
+pub mod assignments;
+pub mod com;
 pub mod config;
 mod ffi;
+pub mod persistency;
+pub mod runs;
+pub mod safety;
+pub mod step_counters;
+pub mod supervision;
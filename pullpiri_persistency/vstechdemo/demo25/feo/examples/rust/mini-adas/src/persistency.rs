@@ -0,0 +1,72 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Blocking facade over the pullpiri persistency service
+//!
+//! Activities run on plain worker threads, so the async `common::persistency`
+//! client is driven on a dedicated single-threaded tokio runtime. Every call
+//! is bounded by [`TIMEOUT`] so that an unreachable persistency service never
+//! stalls the task chain for long; failures are logged and reported as
+//! `None`/`false`.
+
+use core::future::Future;
+use core::time::Duration;
+use feo_log::warn;
+use std::sync::{Mutex, OnceLock};
+use tokio::runtime::{Builder, Runtime};
+
+/// Upper bound for a single persistency call
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Runtime shared by all callers, `None` if it could not be created
+fn runtime() -> Option<&'static Mutex<Runtime>> {
+    static RUNTIME: OnceLock<Option<Mutex<Runtime>>> = OnceLock::new();
+    RUNTIME
+        .get_or_init(|| match Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => Some(Mutex::new(runtime)),
+            Err(e) => {
+                warn!("Persistency runtime unavailable: {}", e);
+                None
+            }
+        })
+        .as_ref()
+}
+
+fn block_on<T, E: core::fmt::Debug>(
+    operation: &str,
+    key: &str,
+    future: impl Future<Output = Result<T, E>>,
+) -> Option<T> {
+    let runtime = runtime()?.lock().ok()?;
+    match runtime.block_on(async { tokio::time::timeout(TIMEOUT, future).await }) {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(e)) => {
+            warn!("Persistency {} of '{}' failed: {:?}", operation, key, e);
+            None
+        }
+        Err(_) => {
+            warn!("Persistency {} of '{}' timed out after {:?}", operation, key, TIMEOUT);
+            None
+        }
+    }
+}
+
+/// Read a value, `None` if it does not exist or the service is unreachable
+pub fn get(key: &str) -> Option<String> {
+    block_on("get", key, common::persistency::get(key))
+}
+
+/// Write a value, `false` if the service is unreachable
+pub fn put(key: &str, value: &str) -> bool {
+    block_on("put", key, common::persistency::put(key, value)).is_some()
+}
@@ -0,0 +1,338 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Reproducible runs
+//!
+//! Every run has an id, taken from `MINI_ADAS_RUN_ID` or the start time, and
+//! publishes it with the vehicle data on DDS. With `MINI_ADAS_SEED=<u64>` the
+//! run uses a deterministic RNG: every step of an activity draws from its own
+//! stream derived from the seed, the activity id and the step number, so the
+//! generated scenes do not depend on how the workers are scheduled. The
+//! durations of the camera scenarios are randomized from the seed as well.
+//! The seed and the resulting scenario schedule are stored in the
+//! persistency service under `runs/adas/<run id>/seed` as
+//! `<seed>;<scenario>:<steps>,...`.
+//!
+//! `MINI_ADAS_REPLAY_RUN=<run id>` reads that record back and repeats the
+//! stored run under a new run id, even if the built-in schedule changed in
+//! the meantime. All agents of a deployment must be started with the same
+//! variables, they then agree on the run id, the seed and the schedule.
+
+use crate::activities::messages::DemoScenario;
+use core::cell::Cell;
+use core::ops::Range;
+use feo::activity::{Activity, ActivityBuilder, ActivityIdAndBuilder};
+use feo::ids::{ActivityId, AgentId, WorkerId};
+use feo_log::{error, info, warn};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable naming the run
+pub const RUN_ID_ENV: &str = "MINI_ADAS_RUN_ID";
+
+/// Environment variable enabling the deterministic RNG with the given seed
+pub const SEED_ENV: &str = "MINI_ADAS_SEED";
+
+/// Environment variable naming a stored run to repeat
+pub const REPLAY_ENV: &str = "MINI_ADAS_REPLAY_RUN";
+
+/// Key prefix of the run records
+pub const RUN_KEY_PREFIX: &str = "runs/adas/";
+
+/// Built-in camera scenarios as (scenario, duration in steps), repeated in order
+const SCENARIO_CYCLE: &[(DemoScenario, usize)] = &[
+    (DemoScenario::Highway, 40),
+    (DemoScenario::City, 30),
+    (DemoScenario::Suburban, 25),
+    (DemoScenario::EmergencyTest, 15), // Short emergency test
+];
+
+/// Maximum deviation of a randomized scenario duration, in percent
+const DURATION_JITTER_PERCENT: i64 = 25;
+
+/// Key of the seed record of a run
+pub fn seed_key(run_id: &str) -> String {
+    format!("{RUN_KEY_PREFIX}{run_id}/seed")
+}
+
+/// SplitMix64 generator, small and good enough for scenario generation
+#[derive(Debug, Clone, Copy)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Number in `range`, both ends included
+    pub fn in_range(&mut self, range: Range<i64>) -> i64 {
+        let span = (range.end - range.start + 1) as u64;
+        (self.next_u64() % span) as i64 + range.start
+    }
+}
+
+/// Seed and scenario schedule of a deterministic run
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedRecord {
+    pub seed: u64,
+    /// Camera scenarios as (scenario, duration in steps), repeated in order
+    pub schedule: Vec<(DemoScenario, usize)>,
+}
+
+impl SeedRecord {
+    /// Record of a new run, the scenario durations randomized from `seed`
+    pub fn generate(seed: u64) -> Self {
+        let mut rng = SplitMix64::new(seed);
+        let schedule = SCENARIO_CYCLE
+            .iter()
+            .map(|&(scenario, steps)| {
+                let jitter = steps as i64 * DURATION_JITTER_PERCENT / 100;
+                let steps = steps as i64 + rng.in_range(-jitter..jitter);
+                (scenario, steps.max(1) as usize)
+            })
+            .collect();
+        Self { seed, schedule }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (seed, schedule) = value
+            .split_once(';')
+            .ok_or_else(|| "expected '<seed>;<scenario>:<steps>,...'".to_string())?;
+        let seed = seed
+            .parse()
+            .map_err(|_| format!("'{seed}' is not a seed"))?;
+        let schedule = schedule
+            .split(',')
+            .map(|entry| {
+                let (scenario, steps) = entry
+                    .split_once(':')
+                    .ok_or_else(|| format!("'{entry}' is not '<scenario>:<steps>'"))?;
+                let scenario = DemoScenario::parse(scenario)
+                    .ok_or_else(|| format!("unknown scenario '{scenario}'"))?;
+                match steps.parse::<usize>() {
+                    Ok(steps) if steps > 0 => Ok((scenario, steps)),
+                    _ => Err(format!("'{steps}' is not a number of steps")),
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self { seed, schedule })
+    }
+}
+
+impl core::fmt::Display for SeedRecord {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{};", self.seed)?;
+        for (index, (scenario, steps)) in self.schedule.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            write!(f, "{separator}{}:{steps}", scenario.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+/// Identity of the run of this process
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub run_id: String,
+    /// Seed and schedule, `None` without the deterministic RNG
+    pub seeded: Option<SeedRecord>,
+}
+
+impl Run {
+    /// Camera scenarios of the run, the built-in ones without a seed
+    pub fn schedule(&self) -> Vec<(DemoScenario, usize)> {
+        match &self.seeded {
+            Some(record) => record.schedule.clone(),
+            None => SCENARIO_CYCLE.to_vec(),
+        }
+    }
+
+    /// Store the seed record, so that the run can be repeated later
+    ///
+    /// Nothing is stored without the deterministic RNG, such a run cannot
+    /// be repeated anyway.
+    pub fn persist(&self) {
+        let Some(record) = &self.seeded else {
+            return;
+        };
+        let key = seed_key(&self.run_id);
+        if crate::persistency::put(&key, &record.to_string()) {
+            info!(
+                "🎲 Run {} uses seed {}, stored under {key}",
+                self.run_id, record.seed
+            );
+        } else {
+            warn!(
+                "🎲 Seed of run {} not stored, the run cannot be repeated",
+                self.run_id
+            );
+        }
+    }
+}
+
+/// Run of this process, loaded on the first call
+pub fn current() -> &'static Run {
+    static RUN: OnceLock<Run> = OnceLock::new();
+
+    RUN.get_or_init(load)
+}
+
+fn load() -> Run {
+    let run_id = std::env::var(RUN_ID_ENV)
+        .ok()
+        .filter(|run_id| !run_id.is_empty())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis()
+                .to_string()
+        });
+
+    let seeded = match std::env::var(REPLAY_ENV) {
+        Ok(replayed) => match crate::persistency::get(&seed_key(&replayed))
+            .map(|value| SeedRecord::parse(&value))
+        {
+            Some(Ok(record)) => {
+                info!(
+                    "🎲 Run {run_id} repeats run {replayed} with seed {}",
+                    record.seed
+                );
+                Some(record)
+            }
+            Some(Err(err)) => {
+                error!("🎲 Seed record of run {replayed} rejected ({err})");
+                from_seed_env()
+            }
+            None => {
+                error!("🎲 No seed record of run {replayed} found");
+                from_seed_env()
+            }
+        },
+        Err(_) => from_seed_env(),
+    };
+
+    Run { run_id, seeded }
+}
+
+fn from_seed_env() -> Option<SeedRecord> {
+    let seed = std::env::var(SEED_ENV).ok()?;
+    match seed.parse() {
+        Ok(seed) => Some(SeedRecord::generate(seed)),
+        Err(_) => {
+            error!("🎲 {SEED_ENV}='{seed}' is not a number, using the random RNG");
+            None
+        }
+    }
+}
+
+thread_local! {
+    /// Stream of the activity step running on this thread
+    static STREAM: Cell<Option<SplitMix64>> = const { Cell::new(None) };
+}
+
+/// Next number of the current step's stream, `None` without the deterministic RNG
+pub fn next_u64() -> Option<u64> {
+    STREAM.with(|stream| {
+        let mut rng = stream.get()?;
+        let value = rng.next_u64();
+        stream.set(Some(rng));
+        Some(value)
+    })
+}
+
+/// Activity wrapper drawing the random numbers of each step from its own stream
+struct SeededActivity {
+    inner: Box<dyn Activity>,
+    seed: u64,
+    steps: u64,
+}
+
+impl SeededActivity {
+    fn with_stream(&mut self, f: impl FnOnce(&mut Box<dyn Activity>)) {
+        // Mix the seed, activity and step through one generator round each
+        let mut rng = SplitMix64::new(self.seed);
+        let mut rng = SplitMix64::new(rng.next_u64() ^ self.inner.id().id());
+        let rng = SplitMix64::new(rng.next_u64() ^ self.steps);
+        STREAM.with(|stream| stream.set(Some(rng)));
+        f(&mut self.inner);
+        STREAM.with(|stream| stream.set(None));
+    }
+}
+
+impl Activity for SeededActivity {
+    fn id(&self) -> ActivityId {
+        self.inner.id()
+    }
+
+    fn startup(&mut self) {
+        self.with_stream(|inner| inner.startup());
+    }
+
+    fn step(&mut self) {
+        self.steps += 1;
+        self.with_stream(|inner| inner.step());
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown();
+    }
+}
+
+/// Wrap every activity of the assignments if the run is seeded
+///
+/// ### Parameters
+/// * `assignments` - activity builders per worker per agent, see `agent_assignments()`
+pub fn instrument(
+    assignments: HashMap<AgentId, Vec<(WorkerId, Vec<ActivityIdAndBuilder>)>>,
+) -> HashMap<AgentId, Vec<(WorkerId, Vec<ActivityIdAndBuilder>)>> {
+    let Some(record) = &current().seeded else {
+        return assignments;
+    };
+    let seed = record.seed;
+    assignments
+        .into_iter()
+        .map(|(agent, workers)| {
+            let workers = workers
+                .into_iter()
+                .map(|(worker, activities)| {
+                    let activities = activities
+                        .into_iter()
+                        .map(|(id, builder)| {
+                            let builder: Box<dyn ActivityBuilder> =
+                                Box::new(move |id: ActivityId| -> Box<dyn Activity> {
+                                    Box::new(SeededActivity {
+                                        inner: builder(id),
+                                        seed,
+                                        steps: 0,
+                                    })
+                                });
+                            (id, builder)
+                        })
+                        .collect();
+                    (worker, activities)
+                })
+                .collect();
+            (agent, workers)
+        })
+        .collect()
+}
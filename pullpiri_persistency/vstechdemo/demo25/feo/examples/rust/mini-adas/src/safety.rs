@@ -0,0 +1,213 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Startup checks of the ASIL process partitioning
+//!
+//! Freedom from interference requires the safety-critical activities to run
+//! in the safety agent, separated from QM activities. A build or config
+//! mistake, e.g. the `signalling_direct_mpsc` feature that runs every
+//! activity in one process, silently breaks that separation. The agents
+//! therefore verify the assignments of [`agent_assignments_ids`] before
+//! starting. In the production profile a violation refuses the start, in the
+//! development profile it is logged loudly and the agent continues.
+//!
+//! A watchdog activity has to be added to [`SAFETY_CRITICAL_ACTIVITIES`]
+//! once it is part of the assignments.
+//!
+//! [`agent_assignments_ids`]: crate::config::agent_assignments_ids
+
+use core::fmt;
+use feo::ids::{ActivityId, AgentId, WorkerId};
+use feo_log::{error, info};
+use std::collections::HashMap;
+
+/// Environment variable selecting the safety profile, `production` or `development`
+pub const SAFETY_PROFILE_ENV: &str = "MINI_ADAS_SAFETY_PROFILE";
+
+/// Agent hosting the safety-critical process
+pub const SAFETY_AGENT: AgentId = AgentId::new(102);
+
+/// Activities that must run in [`SAFETY_AGENT`], with their names for reports
+pub const SAFETY_CRITICAL_ACTIVITIES: &[(ActivityId, &str)] = &[
+    (ActivityId::new(5), "LaneAssist"),
+    (ActivityId::new(6), "BrakeController"),
+    (ActivityId::new(7), "SteeringController"),
+];
+
+/// QM activities deliberately placed in the safety process
+///
+/// TrajectoryVisualizer runs there for access to the steering data.
+pub const QM_ACTIVITIES_IN_SAFETY_AGENT: &[ActivityId] = &[ActivityId::new(8)];
+
+/// How a partitioning violation is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafetyProfile {
+    /// Refuse to start
+    Production,
+    /// Log the violation and continue
+    Development,
+}
+
+impl SafetyProfile {
+    /// Profile from `MINI_ADAS_SAFETY_PROFILE`
+    ///
+    /// Without the variable, release builds use the production profile and
+    /// debug builds the development profile.
+    pub fn from_env() -> Self {
+        match std::env::var(SAFETY_PROFILE_ENV).as_deref() {
+            Ok("production") => Self::Production,
+            Ok("development") => Self::Development,
+            Ok(other) => {
+                // An unknown value must not weaken the checks
+                error!("Unknown {SAFETY_PROFILE_ENV} '{other}', using the production profile");
+                Self::Production
+            }
+            Err(_) if cfg!(debug_assertions) => Self::Development,
+            Err(_) => Self::Production,
+        }
+    }
+}
+
+/// A safety-critical activity placed where freedom from interference is not given
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionViolation {
+    /// The activity is not assigned to any agent
+    Missing { activity: &'static str },
+    /// The activity runs in another agent than [`SAFETY_AGENT`]
+    WrongAgent {
+        activity: &'static str,
+        agent: AgentId,
+    },
+    /// The activity shares its agent with QM activities
+    CoLocated {
+        activity: &'static str,
+        agent: AgentId,
+        qm_activities: Vec<ActivityId>,
+    },
+}
+
+impl fmt::Display for PartitionViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing { activity } => write!(f, "{activity} is not assigned to any agent"),
+            Self::WrongAgent { activity, agent } => {
+                write!(
+                    f,
+                    "{activity} runs in agent {agent} instead of the safety agent {SAFETY_AGENT}"
+                )
+            }
+            Self::CoLocated {
+                activity,
+                agent,
+                qm_activities,
+            } => {
+                write!(f, "{activity} shares agent {agent} with QM activities")?;
+                for (i, qm_activity) in qm_activities.iter().enumerate() {
+                    let separator = if i == 0 { " " } else { ", " };
+                    write!(f, "{separator}{qm_activity}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Check where the safety-critical activities ended up
+///
+/// ### Parameters
+/// * `assignments` - activity ids per worker per agent, see `agent_assignments_ids()`
+pub fn check_partitioning(
+    assignments: &HashMap<AgentId, Vec<(WorkerId, Vec<ActivityId>)>>,
+) -> Vec<PartitionViolation> {
+    let agent_of: HashMap<ActivityId, AgentId> = assignments
+        .iter()
+        .flat_map(|(agent, workers)| {
+            workers
+                .iter()
+                .flat_map(|(_, activities)| activities.iter())
+                .map(move |activity| (*activity, *agent))
+        })
+        .collect();
+    let is_safety_critical = |id: &ActivityId| {
+        SAFETY_CRITICAL_ACTIVITIES
+            .iter()
+            .any(|(safety_id, _)| safety_id == id)
+    };
+
+    let mut violations = Vec::new();
+    for &(id, name) in SAFETY_CRITICAL_ACTIVITIES {
+        let Some(agent) = agent_of.get(&id).copied() else {
+            violations.push(PartitionViolation::Missing { activity: name });
+            continue;
+        };
+        if agent != SAFETY_AGENT {
+            violations.push(PartitionViolation::WrongAgent {
+                activity: name,
+                agent,
+            });
+        }
+
+        let mut qm_activities: Vec<ActivityId> = agent_of
+            .iter()
+            .filter(|(other, other_agent)| {
+                **other_agent == agent
+                    && !is_safety_critical(other)
+                    && !QM_ACTIVITIES_IN_SAFETY_AGENT.contains(other)
+            })
+            .map(|(other, _)| *other)
+            .collect();
+        if !qm_activities.is_empty() {
+            qm_activities.sort();
+            violations.push(PartitionViolation::CoLocated {
+                activity: name,
+                agent,
+                qm_activities,
+            });
+        }
+    }
+    violations
+}
+
+/// Verify the partitioning and refuse to start on violations in the production profile
+///
+/// ### Parameters
+/// * `profile` - how violations are handled
+/// * `assignments` - activity ids per worker per agent, see `agent_assignments_ids()`
+pub fn enforce_partitioning(
+    profile: SafetyProfile,
+    assignments: &HashMap<AgentId, Vec<(WorkerId, Vec<ActivityId>)>>,
+) {
+    let violations = check_partitioning(assignments);
+    if violations.is_empty() {
+        info!("ASIL partitioning verified: safety-critical activities isolated in agent {SAFETY_AGENT}");
+        return;
+    }
+
+    for violation in &violations {
+        error!("ASIL partitioning violated: {violation}");
+    }
+    match profile {
+        SafetyProfile::Production => {
+            panic!(
+                "Refusing to start: {} ASIL partitioning violation(s) in the production profile",
+                violations.len()
+            );
+        }
+        SafetyProfile::Development => {
+            error!(
+                "!!! Running WITHOUT freedom from interference ({} violation(s)), development profile only !!!",
+                violations.len()
+            );
+        }
+    }
+}
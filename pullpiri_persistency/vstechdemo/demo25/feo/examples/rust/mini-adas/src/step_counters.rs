@@ -0,0 +1,317 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Persistent step counters for long-run soak tests
+//!
+//! With `MINI_ADAS_STEP_COUNTERS=1` every activity is wrapped so that its
+//! step counter and the time of its last step are stored in the persistency
+//! service under `mini-adas/step_counters/<activity id>`. At startup the
+//! counters are reloaded: a stored record means the agent was restarted, and
+//! the time since the last recorded step is accounted as lost. Over weeks of
+//! operation the records show restarts and lost cycles even though every
+//! process start begins counting from scratch.
+//!
+//! Records are written every [`PERSIST_INTERVAL`] and at shutdown, so after
+//! a crash the lost time is overestimated by at most that interval. The
+//! record format is `steps;last_step_ms;restarts;lost_ms`.
+//!
+//! Steps never wait for the persistency service: they queue the record for
+//! a writer thread shared by all activities, and a record that finds the
+//! queue full is skipped until the next interval. Only shutdown waits, for
+//! at most [`FINAL_WRITE_TIMEOUT`], until the last record is written.
+
+use core::fmt;
+use core::time::Duration;
+use feo::activity::{Activity, ActivityBuilder, ActivityIdAndBuilder};
+use feo::ids::{ActivityId, AgentId, WorkerId};
+use feo_log::{info, warn};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Environment variable enabling the step counters
+pub const STEP_COUNTERS_ENV: &str = "MINI_ADAS_STEP_COUNTERS";
+
+/// Key prefix of the step counter records
+pub const STEP_COUNTER_KEY_PREFIX: &str = "mini-adas/step_counters/";
+
+/// Time between two writes of a record
+pub const PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time shutdown waits for the last record to be written
+pub const FINAL_WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Records waiting for the writer thread
+const QUEUE_LEN: usize = 64;
+
+/// Time between two attempts to queue the last record while the queue is full
+const QUEUE_RETRY: Duration = Duration::from_millis(10);
+
+/// Whether the step counters are enabled
+pub fn enabled() -> bool {
+    matches!(
+        std::env::var(STEP_COUNTERS_ENV).as_deref(),
+        Ok("1" | "true")
+    )
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Persisted counters of one activity across all process starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StepCounterRecord {
+    /// Steps executed since the first start
+    pub steps: u64,
+    /// Unix time of the last recorded step in milliseconds, 0 before the first step
+    pub last_step_ms: u64,
+    /// Process restarts detected
+    pub restarts: u64,
+    /// Time without steps between a recorded step and the next start in milliseconds
+    pub lost_ms: u64,
+}
+
+impl StepCounterRecord {
+    fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.split(';').map(|field| field.parse::<u64>().ok());
+        let record = Self {
+            steps: fields.next()??,
+            last_step_ms: fields.next()??,
+            restarts: fields.next()??,
+            lost_ms: fields.next()??,
+        };
+        fields.next().is_none().then_some(record)
+    }
+}
+
+impl fmt::Display for StepCounterRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{};{};{};{}",
+            self.steps, self.last_step_ms, self.restarts, self.lost_ms
+        )
+    }
+}
+
+/// Record queued for the writer thread
+struct Write {
+    key: String,
+    record: StepCounterRecord,
+    /// Told whether the record was written, for the last record of an activity
+    written: Option<Sender<bool>>,
+}
+
+/// Queue of the writer thread, starting the thread on first use
+fn writer() -> Option<&'static SyncSender<Write>> {
+    static WRITER: OnceLock<Option<SyncSender<Write>>> = OnceLock::new();
+    WRITER
+        .get_or_init(|| {
+            let (sender, receiver) = mpsc::sync_channel(QUEUE_LEN);
+            thread::Builder::new()
+                .name("step-counters".to_string())
+                .spawn(move || write_records(receiver))
+                .map_err(|e| warn!("⏱️ Cannot start the step counter writer: {e:?}"))
+                .ok()?;
+            Some(sender)
+        })
+        .as_ref()
+}
+
+/// Write the queued records until the process exits
+fn write_records(receiver: Receiver<Write>) {
+    while let Ok(write) = receiver.recv() {
+        let written = crate::persistency::put(&write.key, &write.record.to_string());
+        if !written {
+            warn!("⏱️ Step counter {} not persisted", write.key);
+        }
+        if let Some(sender) = write.written {
+            let _ = sender.send(written);
+        }
+    }
+}
+
+/// Activity wrapper maintaining the persistent step counter
+struct CountedActivity {
+    inner: Box<dyn Activity>,
+    key: String,
+    record: StepCounterRecord,
+    last_persist: Option<Instant>,
+}
+
+impl CountedActivity {
+    /// Queue the record without waiting for the writer
+    fn persist(&mut self) {
+        self.last_persist = Some(Instant::now());
+        let Some(writer) = writer() else {
+            return;
+        };
+        let write = Write {
+            key: self.key.clone(),
+            record: self.record,
+            written: None,
+        };
+        if let Err(TrySendError::Full(_)) = writer.try_send(write) {
+            warn!(
+                "⏱️ Step counter of activity {} not persisted, the writer is behind",
+                self.inner.id()
+            );
+        }
+    }
+
+    /// Queue the last record and wait until it is written
+    ///
+    /// Queueing and writing share the [`FINAL_WRITE_TIMEOUT`], so a writer
+    /// stuck on an unreachable service cannot hold up the shutdown.
+    fn persist_last(&mut self) {
+        let Some(writer) = writer() else {
+            return;
+        };
+        let deadline = Instant::now() + FINAL_WRITE_TIMEOUT;
+        let (sender, receiver) = mpsc::channel();
+        let mut write = Write {
+            key: self.key.clone(),
+            record: self.record,
+            written: Some(sender),
+        };
+        loop {
+            match writer.try_send(write) {
+                Ok(()) => break,
+                Err(TrySendError::Full(full)) if Instant::now() < deadline => {
+                    write = full;
+                    thread::sleep(QUEUE_RETRY);
+                }
+                Err(TrySendError::Full(_)) => {
+                    warn!(
+                        "⏱️ Last step counter of activity {} not persisted, the writer is behind",
+                        self.inner.id()
+                    );
+                    return;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    warn!(
+                        "⏱️ Last step counter of activity {} not persisted, the writer stopped",
+                        self.inner.id()
+                    );
+                    return;
+                }
+            }
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if receiver.recv_timeout(remaining).is_err() {
+            warn!(
+                "⏱️ Last step counter of activity {} not persisted within {:?}",
+                self.inner.id(),
+                FINAL_WRITE_TIMEOUT
+            );
+        }
+    }
+}
+
+impl Activity for CountedActivity {
+    fn id(&self) -> ActivityId {
+        self.inner.id()
+    }
+
+    fn startup(&mut self) {
+        let stored = crate::persistency::get(&self.key);
+        match stored.as_deref().map(StepCounterRecord::parse) {
+            Some(Some(record)) => {
+                self.record = record;
+                if record.last_step_ms > 0 {
+                    let gap_ms = now_ms().saturating_sub(record.last_step_ms);
+                    self.record.restarts += 1;
+                    self.record.lost_ms += gap_ms;
+                    warn!(
+                        "⏱️ Activity {} restarted after {} steps: no step for {:.1}s (restart #{}, {:.1}s lost in total)",
+                        self.inner.id(),
+                        record.steps,
+                        gap_ms as f64 / 1000.0,
+                        self.record.restarts,
+                        self.record.lost_ms as f64 / 1000.0
+                    );
+                }
+            }
+            Some(None) => warn!(
+                "⏱️ Ignoring malformed step counter of activity {}: '{}'",
+                self.inner.id(),
+                stored.unwrap_or_default()
+            ),
+            None => info!("⏱️ Starting step counter of activity {}", self.inner.id()),
+        }
+        self.inner.startup();
+    }
+
+    fn step(&mut self) {
+        self.inner.step();
+        self.record.steps += 1;
+        self.record.last_step_ms = now_ms();
+        if self
+            .last_persist
+            .is_none_or(|last_persist| last_persist.elapsed() >= PERSIST_INTERVAL)
+        {
+            self.persist();
+        }
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown();
+        self.persist_last();
+    }
+}
+
+/// Wrap an activity builder to maintain the persistent step counter
+pub fn with_step_counter(builder: Box<dyn ActivityBuilder>) -> Box<dyn ActivityBuilder> {
+    Box::new(move |id: ActivityId| -> Box<dyn Activity> {
+        Box::new(CountedActivity {
+            inner: builder(id),
+            key: format!("{STEP_COUNTER_KEY_PREFIX}{id}"),
+            record: StepCounterRecord::default(),
+            last_persist: None,
+        })
+    })
+}
+
+/// Wrap every activity of the assignments if the step counters are enabled
+///
+/// ### Parameters
+/// * `assignments` - activity builders per worker per agent, see `agent_assignments()`
+pub fn instrument(
+    assignments: HashMap<AgentId, Vec<(WorkerId, Vec<ActivityIdAndBuilder>)>>,
+) -> HashMap<AgentId, Vec<(WorkerId, Vec<ActivityIdAndBuilder>)>> {
+    if !enabled() {
+        return assignments;
+    }
+    assignments
+        .into_iter()
+        .map(|(agent, workers)| {
+            let workers = workers
+                .into_iter()
+                .map(|(worker, activities)| {
+                    let activities = activities
+                        .into_iter()
+                        .map(|(id, builder)| (id, with_step_counter(builder)))
+                        .collect();
+                    (worker, activities)
+                })
+                .collect();
+            (agent, workers)
+        })
+        .collect()
+}
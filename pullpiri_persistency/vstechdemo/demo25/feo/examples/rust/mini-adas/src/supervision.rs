@@ -0,0 +1,312 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Restart supervision of the activities
+//!
+//! A panic in a step, e.g. an unwrap of a failed DDS write, unwinds the
+//! worker thread and takes every activity of the worker down with it. Every
+//! activity is therefore wrapped by a supervisor that catches the panics of
+//! its startup, steps and shutdown. A failed activity skips its steps until
+//! its restart is due and is then restarted with shutdown and startup. The
+//! delay doubles with every failure in a row, from [`INITIAL_BACKOFF`] up to
+//! [`MAX_BACKOFF`]; an activity running for [`STABLE_PERIOD`] starts over.
+//!
+//! A safety-critical activity failing [`ESCALATION_FAILURES`] times in a row
+//! requests the safe state: the CarModeCalculator switches to emergency mode
+//! and brakes as the safe-state matrix demands. The request stays active
+//! until the agents restart, even if the activity recovers. It is published
+//! on [`TOPIC_SAFE_STATE_REQUEST`] by the supervisor of
+//! [`SAFE_STATE_REPORTER`], which keeps stepping while the activity it wraps
+//! is down. Only failures in the process of the reporter are escalated; the
+//! partitioning checks keep all safety-critical activities there.
+
+use crate::activities::components::activity_output;
+use crate::activities::event_log::{EventCode, EventLogger, Severity};
+use crate::activities::messages::SafeStateRequest;
+use crate::config::TOPIC_SAFE_STATE_REQUEST;
+use crate::safety::SAFETY_CRITICAL_ACTIVITIES;
+use core::any::Any;
+use core::panic::AssertUnwindSafe;
+use core::time::Duration;
+use feo::activity::{Activity, ActivityBuilder, ActivityIdAndBuilder};
+use feo::ids::{ActivityId, AgentId, WorkerId};
+use feo_com::interface::ActivityOutput;
+use feo_log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Delay of the first restart after a failure
+pub const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest delay of a restart
+pub const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Time an activity has to run without failure to reset its backoff
+pub const STABLE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Failures in a row of a safety-critical activity that request the safe state
+pub const ESCALATION_FAILURES: u32 = 3;
+
+/// Activity whose supervisor publishes the safe-state request, the BrakeController
+pub const SAFE_STATE_REPORTER: ActivityId = ActivityId::new(6);
+
+/// Safe state requested in this process, set once
+static SAFE_STATE: OnceLock<SafeStateRequest> = OnceLock::new();
+
+/// Delay of the restart after `failures` failures in a row
+pub fn backoff(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    INITIAL_BACKOFF
+        .saturating_mul(1 << doublings)
+        .min(MAX_BACKOFF)
+}
+
+/// Message of a caught panic
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("panic without message")
+}
+
+/// Whether the wrapped activity runs or waits for its restart
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Running { since: Instant },
+    Failed { restart_at: Instant },
+}
+
+/// Activity wrapper catching panics and restarting the activity
+struct SupervisedActivity {
+    inner: Box<dyn Activity>,
+    /// Name of a safety-critical activity, `None` for QM activities
+    safety_critical: Option<&'static str>,
+    state: State,
+    /// Failures since the activity last ran for [`STABLE_PERIOD`]
+    failures: u32,
+    /// Restarts since the process started
+    restarts: u32,
+    /// Output of the safe-state request, of the [`SAFE_STATE_REPORTER`] only
+    output_safe_state: Option<Box<dyn ActivityOutput<SafeStateRequest>>>,
+    events: EventLogger,
+}
+
+impl SupervisedActivity {
+    /// Call the wrapped activity, returning the panic message if it panicked
+    fn guarded(&mut self, call: impl FnOnce(&mut dyn Activity)) -> Result<(), String> {
+        let inner = &mut *self.inner;
+        std::panic::catch_unwind(AssertUnwindSafe(|| call(inner)))
+            .map_err(|payload| panic_message(payload.as_ref()).to_string())
+    }
+
+    fn name(&self) -> String {
+        match self.safety_critical {
+            Some(name) => name.to_string(),
+            None => format!("activity {}", self.inner.id()),
+        }
+    }
+
+    /// Schedule the restart after a panic in `phase`
+    fn failed(&mut self, phase: &str, message: &str) {
+        let now = Instant::now();
+        if let State::Running { since } = self.state {
+            if now.duration_since(since) >= STABLE_PERIOD {
+                self.failures = 0;
+            }
+        }
+        self.failures += 1;
+        let delay = backoff(self.failures);
+        self.state = State::Failed {
+            restart_at: now + delay,
+        };
+
+        let name = self.name();
+        error!(
+            "🩺 {name} panicked in {phase} ({} failure(s) in a row), restarting in {:.1}s: {message}",
+            self.failures,
+            delay.as_secs_f64()
+        );
+        self.events.emit(
+            Severity::Error,
+            EventCode::ActivityFailed,
+            format!("{name} panicked in {phase}"),
+            &[
+                ("activity", self.inner.id().to_string()),
+                ("phase", phase.to_string()),
+                ("failures", self.failures.to_string()),
+                ("backoff_ms", delay.as_millis().to_string()),
+                ("panic", message.to_string()),
+            ],
+        );
+
+        if self.safety_critical.is_some() && self.failures >= ESCALATION_FAILURES {
+            self.escalate(&name);
+        }
+    }
+
+    /// Request the safe state, unless it was requested before
+    fn escalate(&self, name: &str) {
+        let request = SafeStateRequest {
+            active: true,
+            activity: self.inner.id().id(),
+            failures: self.failures,
+        };
+        if SAFE_STATE.set(request).is_err() {
+            return;
+        }
+        error!(
+            "🛑 Safety-critical {name} failed {} times in a row, requesting the safe state",
+            self.failures
+        );
+        self.events.emit(
+            Severity::Critical,
+            EventCode::SafeStateRequested,
+            format!(
+                "Safe state requested after {} failures of {name}",
+                self.failures
+            ),
+            &[
+                ("activity", self.inner.id().to_string()),
+                ("failures", self.failures.to_string()),
+            ],
+        );
+    }
+
+    /// Restart the failed activity with shutdown and startup
+    fn restart(&mut self) {
+        self.restarts += 1;
+        let name = self.name();
+        info!("🩺 Restarting {name} (restart #{})", self.restarts);
+
+        // The failed activity may not shut down cleanly, that must not keep it down
+        if let Err(message) = self.guarded(|activity| activity.shutdown()) {
+            warn!("🩺 {name} panicked in shutdown before its restart: {message}");
+        }
+        if let Err(message) = self.guarded(|activity| activity.startup()) {
+            self.failed("startup", &message);
+            return;
+        }
+
+        self.state = State::Running {
+            since: Instant::now(),
+        };
+        self.events.emit(
+            Severity::Warning,
+            EventCode::ActivityRestarted,
+            format!("{name} restarted"),
+            &[
+                ("activity", self.inner.id().to_string()),
+                ("restarts", self.restarts.to_string()),
+                ("failures", self.failures.to_string()),
+            ],
+        );
+    }
+
+    /// Publish the safe-state request of the process, inactive unless requested
+    fn publish_safe_state(&mut self) {
+        let Some(output) = &mut self.output_safe_state else {
+            return;
+        };
+        let request = SAFE_STATE.get().copied().unwrap_or_default();
+        if let Ok(output) = output.write_uninit() {
+            if output.write_payload(request).send().is_err() {
+                debug!("Safe-state request not sent");
+            }
+        }
+    }
+}
+
+impl Activity for SupervisedActivity {
+    fn id(&self) -> ActivityId {
+        self.inner.id()
+    }
+
+    fn startup(&mut self) {
+        match self.guarded(|activity| activity.startup()) {
+            Ok(()) => {
+                self.state = State::Running {
+                    since: Instant::now(),
+                }
+            }
+            Err(message) => self.failed("startup", &message),
+        }
+    }
+
+    fn step(&mut self) {
+        match self.state {
+            State::Running { .. } => {
+                if let Err(message) = self.guarded(|activity| activity.step()) {
+                    self.failed("step", &message);
+                }
+            }
+            State::Failed { restart_at } if Instant::now() >= restart_at => self.restart(),
+            State::Failed { .. } => {}
+        }
+        self.publish_safe_state();
+    }
+
+    fn shutdown(&mut self) {
+        if let Err(message) = self.guarded(|activity| activity.shutdown()) {
+            warn!("🩺 {} panicked in shutdown: {message}", self.name());
+        }
+    }
+}
+
+/// Wrap an activity builder to catch panics and restart the activity
+pub fn supervised(builder: Box<dyn ActivityBuilder>) -> Box<dyn ActivityBuilder> {
+    Box::new(move |id: ActivityId| -> Box<dyn Activity> {
+        let safety_critical = SAFETY_CRITICAL_ACTIVITIES
+            .iter()
+            .find(|(safety_id, _)| *safety_id == id)
+            .map(|(_, name)| *name);
+        Box::new(SupervisedActivity {
+            inner: builder(id),
+            safety_critical,
+            state: State::Running {
+                since: Instant::now(),
+            },
+            failures: 0,
+            restarts: 0,
+            output_safe_state: (id == SAFE_STATE_REPORTER)
+                .then(|| activity_output(TOPIC_SAFE_STATE_REQUEST)),
+            events: EventLogger::new("Supervision"),
+        })
+    })
+}
+
+/// Wrap every activity of the assignments in a supervisor
+///
+/// ### Parameters
+/// * `assignments` - activity builders per worker per agent, see `agent_assignments()`
+pub fn instrument(
+    assignments: HashMap<AgentId, Vec<(WorkerId, Vec<ActivityIdAndBuilder>)>>,
+) -> HashMap<AgentId, Vec<(WorkerId, Vec<ActivityIdAndBuilder>)>> {
+    assignments
+        .into_iter()
+        .map(|(agent, workers)| {
+            let workers = workers
+                .into_iter()
+                .map(|(worker, activities)| {
+                    let activities = activities
+                        .into_iter()
+                        .map(|(id, builder)| (id, supervised(builder)))
+                        .collect();
+                    (worker, activities)
+                })
+                .collect();
+            (agent, workers)
+        })
+        .collect()
+}
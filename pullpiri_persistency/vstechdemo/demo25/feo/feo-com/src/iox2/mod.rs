@@ -253,15 +253,44 @@ impl<T: fmt::Debug> DerefMut for Iox2OutputUninitGuard<T> {
     }
 }
 
+/// Check whether iceoryx2 can be used on this host
+///
+/// Creates and drops a node with a throwaway service, failing with the reason
+/// instead of panicking like the first topic would, e.g. when shared memory
+/// or the iceoryx2 state directory is not accessible.
+pub fn probe() -> Result<(), alloc::string::String> {
+    let config = ipc_config();
+    let name = NodeName::new(&format!("feo_probe_{}", process::id()))
+        .map_err(|e| format!("invalid node name: {e:?}"))?;
+    let node = NodeBuilder::new()
+        .name(&name)
+        .config(&config)
+        .create::<ipc::Service>()
+        .map_err(|e| format!("failed to create ipc node: {e:?}"))?;
+    let service_name = format!("feo/probe/{}", process::id());
+    node.service_builder(
+        &service_name
+            .as_str()
+            .try_into()
+            .map_err(|e| format!("invalid service name: {e:?}"))?,
+    )
+    .publish_subscribe::<u64>()
+    .create()
+    .map_err(|e| format!("failed to create service: {e:?}"))?;
+    Ok(())
+}
+
+fn ipc_config() -> Config {
+    let mut config = Config::default();
+    config.global.prefix = "feo_ipc".try_into().unwrap();
+    config
+}
+
 fn ipc_node() -> &'static Node<ipc::Service> {
     static ICEORYX_NODE: std::sync::OnceLock<Node<ipc::Service>> = std::sync::OnceLock::new();
 
     ICEORYX_NODE.get_or_init(|| {
-        let config = {
-            let mut config = Config::default();
-            config.global.prefix = "feo_ipc".try_into().unwrap();
-            config
-        };
+        let config = ipc_config();
 
         // Ensure there is no left-over state from dead nodes.
         Node::<ipc::Service>::cleanup_dead_nodes(&config);
@@ -0,0 +1,94 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! DDS writer of the change events
+//!
+//! Runs on a thread of its own with its own participant, as dust_dds writes
+//! block. The writer keeps the recent changes for console apps started later.
+
+use dust_dds::{
+    domain::domain_participant_factory::DomainParticipantFactory,
+    infrastructure::qos::{DataWriterQos, QosKind},
+    infrastructure::qos_policy::{
+        DurabilityQosPolicy, DurabilityQosPolicyKind, HistoryQosPolicy, HistoryQosPolicyKind,
+        ReliabilityQosPolicy, ReliabilityQosPolicyKind,
+    },
+};
+use tokio::sync::mpsc::Receiver;
+use vehicle_types::PersistencyChangeEvent;
+
+/// Changes kept for console apps started later
+const HISTORY_DEPTH: u32 = 50;
+
+/// Publish the received changes on `topic` of `domain_id` until every
+/// watcher is gone
+pub fn publish(domain_id: i32, topic: &str, mut receiver: Receiver<PersistencyChangeEvent>) {
+    let participant = match DomainParticipantFactory::get_instance().create_participant(
+        domain_id,
+        QosKind::Default,
+        None,
+        &[],
+    ) {
+        Ok(participant) => participant,
+        Err(e) => {
+            eprintln!("❌ Cannot join DDS domain {}: {:?}", domain_id, e);
+            std::process::exit(1);
+        }
+    };
+    let writer_qos = DataWriterQos {
+        reliability: ReliabilityQosPolicy {
+            kind: ReliabilityQosPolicyKind::BestEffort,
+            max_blocking_time: dust_dds::infrastructure::time::DurationKind::Finite(
+                dust_dds::infrastructure::time::Duration::new(0, 100_000_000),
+            ),
+        },
+        durability: DurabilityQosPolicy {
+            kind: DurabilityQosPolicyKind::TransientLocal,
+        },
+        history: HistoryQosPolicy {
+            kind: HistoryQosPolicyKind::KeepLast(HISTORY_DEPTH),
+        },
+        ..Default::default()
+    };
+    let writer = participant
+        .create_topic::<PersistencyChangeEvent>(topic, topic, QosKind::Default, None, &[])
+        .and_then(|topic| {
+            participant
+                .create_publisher(QosKind::Default, None, &[])?
+                .create_datawriter::<PersistencyChangeEvent>(
+                    &topic,
+                    QosKind::Specific(writer_qos),
+                    None,
+                    &[],
+                )
+        });
+    let writer = match writer {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!(
+                "❌ Cannot create the writer of DDS topic '{}': {:?}",
+                topic, e
+            );
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "📡 Publishing persistency changes on DDS topic '{}' of domain {}",
+        topic, domain_id
+    );
+
+    while let Some(event) = receiver.blocking_recv() {
+        // Without subscribers the sample is only kept in the writer history
+        let _ = writer.write(&event, None);
+    }
+}
@@ -0,0 +1,201 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Change events of the persistency service over DDS
+//!
+//! Watches the keys of the persistency service and publishes every put and
+//! deletion as a [`PersistencyChangeEvent`] sample, so that DDS-native
+//! components like the console apps can react to state changes without a
+//! gRPC client of their own.
+//!
+//! The service stamps each change with its own clock, services older than
+//! API version 17 are stamped on arrival instead. A publisher that falls
+//! behind the service loses the oldest changes; the service reports them as
+//! `LAGGED` and the publisher logs how many were missed. If the service goes
+//! away the watch is connected again, changes in between are not published.
+
+use common::persistency_client::{PersistencyClient, PersistencyError};
+use common::persistency_proto::{watch_request, WatchEventType, WatchResponse};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use vehicle_types::{PersistencyChangeEvent, Versioned};
+
+mod dds;
+
+/// Environment variable overriding the persistency service endpoint
+pub const PERSISTENCY_ENDPOINT_ENV: &str = "PERSISTENCY_ENDPOINT";
+/// Default persistency service endpoint
+pub const DEFAULT_PERSISTENCY_ENDPOINT: &str = "localhost:47007";
+/// Environment variable overriding the DDS domain
+pub const DDS_DOMAIN_ID_ENV: &str = "PERSISTENCY_DDS_DOMAIN_ID";
+/// DDS domain shared with mini-adas and the console apps
+pub const DEFAULT_DDS_DOMAIN_ID: i32 = 100;
+/// Environment variable overriding the DDS topic
+pub const TOPIC_NAME_ENV: &str = "PERSISTENCY_DDS_TOPIC";
+/// Default DDS topic, also the type name
+pub const DEFAULT_TOPIC_NAME: &str = "PersistencyChangeEvent";
+/// Environment variable with the comma-separated key prefixes to publish,
+/// every key if unset
+pub const PREFIXES_ENV: &str = "PERSISTENCY_DDS_PREFIXES";
+
+/// Changes waiting for the DDS writer
+const QUEUE_LEN: usize = 256;
+/// Time between two attempts to watch the service
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Where changes are read from and published to
+#[derive(Debug, Clone)]
+struct Config {
+    endpoint: String,
+    domain_id: i32,
+    topic: String,
+    prefixes: Vec<String>,
+}
+
+impl Config {
+    fn from_env() -> Self {
+        let endpoint = std::env::var(PERSISTENCY_ENDPOINT_ENV)
+            .unwrap_or_else(|_| DEFAULT_PERSISTENCY_ENDPOINT.to_string());
+        let domain_id = std::env::var(DDS_DOMAIN_ID_ENV)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_DDS_DOMAIN_ID);
+        let topic = std::env::var(TOPIC_NAME_ENV)
+            .ok()
+            .filter(|topic| !topic.is_empty())
+            .unwrap_or_else(|| DEFAULT_TOPIC_NAME.to_string());
+        // Overlapping prefixes would publish their common keys twice
+        let mut prefixes: Vec<String> = std::env::var(PREFIXES_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .map(|prefix| prefix.trim().to_string())
+            .filter(|prefix| !prefix.is_empty())
+            .collect();
+        if prefixes.is_empty() {
+            prefixes.push(String::new());
+        }
+
+        Self {
+            endpoint: format!("http://{}", endpoint),
+            domain_id,
+            topic,
+            prefixes,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let config = Config::from_env();
+
+    // dust_dds writes block, keep them off the runtime
+    let (sender, receiver) = mpsc::channel(QUEUE_LEN);
+    let publisher = {
+        let (domain_id, topic) = (config.domain_id, config.topic.clone());
+        std::thread::Builder::new()
+            .name("dds-publisher".to_string())
+            .spawn(move || dds::publish(domain_id, &topic, receiver))
+    };
+    if let Err(e) = publisher {
+        eprintln!("❌ Cannot start the DDS publisher: {}", e);
+        std::process::exit(1);
+    }
+
+    for prefix in &config.prefixes {
+        tokio::spawn(watch(
+            config.endpoint.clone(),
+            prefix.clone(),
+            sender.clone(),
+        ));
+    }
+    drop(sender);
+
+    let _ = tokio::signal::ctrl_c().await;
+    println!("Shutting down persistency DDS publisher");
+}
+
+/// Relay the changes under `prefix` to `events`, watching again whenever
+/// the service goes away
+async fn watch(endpoint: String, prefix: String, events: mpsc::Sender<PersistencyChangeEvent>) {
+    loop {
+        match relay(&endpoint, &prefix, &events).await {
+            Ok(()) if events.is_closed() => return,
+            Ok(()) => eprintln!("⚠️ Watch of '{}' ended, watching again", prefix),
+            Err(e) => eprintln!("⚠️ Cannot watch '{}' at {}: {:?}", prefix, endpoint, e),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Relay the changes under `prefix` until the watch or the DDS writer ends
+async fn relay(
+    endpoint: &str,
+    prefix: &str,
+    events: &mpsc::Sender<PersistencyChangeEvent>,
+) -> Result<(), PersistencyError> {
+    let mut client = PersistencyClient::connect_to(endpoint).await?;
+    let stream = client
+        .watch(
+            watch_request::Target::Prefix(prefix.to_string()),
+            &[],
+            false,
+        )
+        .await?;
+    tokio::pin!(stream);
+    println!(
+        "📡 Publishing the changes of '{}' from {}",
+        prefix, endpoint
+    );
+
+    while let Some(response) = stream.next().await {
+        let response = response?;
+        match change_event(&response) {
+            Some(event) => {
+                if events.send(event).await.is_err() {
+                    return Ok(());
+                }
+            }
+            None => eprintln!(
+                "⚠️ Fell behind the changes of '{}', {} not published",
+                prefix, response.dropped
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// DDS sample of a watched change, `None` for `LAGGED` notifications
+fn change_event(response: &WatchResponse) -> Option<PersistencyChangeEvent> {
+    let operation = match response.r#type() {
+        WatchEventType::Put => "put",
+        WatchEventType::Delete => "delete",
+        WatchEventType::Lagged => return None,
+    };
+    let timestamp = if response.changed_at_ms > 0 {
+        response.changed_at_ms as i64
+    } else {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64
+    };
+
+    Some(PersistencyChangeEvent {
+        key: response.key.clone(),
+        operation: operation.to_string(),
+        timestamp,
+        revision: response.revision,
+        schema_version: PersistencyChangeEvent::SCHEMA_VERSION,
+    })
+}
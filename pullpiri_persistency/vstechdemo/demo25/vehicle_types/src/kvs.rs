@@ -0,0 +1,151 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Conversion to and from the persistency `KvsValue`
+//!
+//! A message is stored as an object value with one entry per field, so that
+//! the persistency admin tools and exports show its fields instead of an
+//! opaque JSON string. The conversion goes through the serde form of the
+//! message: integers become 64-bit values and are narrowed again when the
+//! message is read back, floats stay `f64`.
+
+use common::persistency_proto::kvs_value::Value as ProtoValue;
+use common::persistency_proto::{KvsArray, KvsObject, KvsValue, NullValue};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Error converting a message from or to a `KvsValue`
+#[derive(Debug, Clone, PartialEq)]
+pub struct KvsConversionError(pub String);
+
+impl std::fmt::Display for KvsConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "KvsValue conversion failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for KvsConversionError {}
+
+/// A message that can be stored by the persistency service
+pub trait KvsRecord: Serialize + DeserializeOwned {
+    /// Object value with one entry per field
+    fn to_kvs_value(&self) -> Result<KvsValue, KvsConversionError> {
+        serde_json::to_value(self)
+            .map(|value| json_to_kvs_value(&value))
+            .map_err(|e| KvsConversionError(e.to_string()))
+    }
+
+    /// Message of an object value written by [`KvsRecord::to_kvs_value`]
+    fn from_kvs_value(value: &KvsValue) -> Result<Self, KvsConversionError> {
+        serde_json::from_value(kvs_value_to_json(value))
+            .map_err(|e| KvsConversionError(e.to_string()))
+    }
+}
+
+/// `KvsValue` of a JSON value, integers becoming 64-bit values
+pub fn json_to_kvs_value(value: &Value) -> KvsValue {
+    let value = match value {
+        Value::Null => ProtoValue::NullValue(NullValue {}),
+        Value::Bool(v) => ProtoValue::BooleanValue(*v),
+        Value::Number(v) => match (v.as_i64(), v.as_u64()) {
+            (Some(v), _) => ProtoValue::I64Value(v),
+            (None, Some(v)) => ProtoValue::U64Value(v),
+            _ => ProtoValue::F64Value(v.as_f64().unwrap_or_default()),
+        },
+        Value::String(v) => ProtoValue::StringValue(v.clone()),
+        Value::Array(values) => ProtoValue::ArrayValue(KvsArray {
+            values: values.iter().map(json_to_kvs_value).collect(),
+        }),
+        Value::Object(values) => ProtoValue::ObjectValue(KvsObject {
+            values: values
+                .iter()
+                .map(|(key, value)| (key.clone(), json_to_kvs_value(value)))
+                .collect(),
+        }),
+    };
+    KvsValue { value: Some(value) }
+}
+
+/// JSON value of a `KvsValue`, an unset value becoming `null`
+pub fn kvs_value_to_json(value: &KvsValue) -> Value {
+    match &value.value {
+        None | Some(ProtoValue::NullValue(_)) => Value::Null,
+        Some(ProtoValue::I32Value(v)) => Value::from(*v),
+        Some(ProtoValue::U32Value(v)) => Value::from(*v),
+        Some(ProtoValue::I64Value(v)) => Value::from(*v),
+        Some(ProtoValue::U64Value(v)) => Value::from(*v),
+        Some(ProtoValue::F64Value(v)) => Value::from(*v),
+        Some(ProtoValue::BooleanValue(v)) => Value::from(*v),
+        Some(ProtoValue::StringValue(v)) => Value::from(v.clone()),
+        // serde's form of a byte vector
+        Some(ProtoValue::BytesValue(v)) => Value::from(v.clone()),
+        Some(ProtoValue::ArrayValue(array)) => {
+            Value::Array(array.values.iter().map(kvs_value_to_json).collect())
+        }
+        Some(ProtoValue::ObjectValue(object)) => Value::Object(
+            object
+                .values
+                .iter()
+                .map(|(key, value)| (key.clone(), kvs_value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+//Unit Test Cases
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EmergencyModeData, Versioned};
+
+    #[test]
+    fn test_round_trip() {
+        let data = EmergencyModeData {
+            vehicle_speed: 42.0,
+            brake_force: 87.5,
+            obstacle_detected: true,
+            emergency_type: "collision_avoidance".to_string(),
+            timestamp: 1_700_000_000_000,
+            schema_version: EmergencyModeData::SCHEMA_VERSION,
+            safe_state_version: "3".to_string(),
+            ..Default::default()
+        };
+
+        let value = data.to_kvs_value().unwrap();
+        let Some(ProtoValue::ObjectValue(object)) = &value.value else {
+            panic!("not an object: {:?}", value);
+        };
+        assert_eq!(
+            object.values["vehicle_speed"].value,
+            Some(ProtoValue::F64Value(42.0))
+        );
+        assert_eq!(
+            object.values["schema_version"].value,
+            Some(ProtoValue::I64Value(5))
+        );
+        assert_eq!(EmergencyModeData::from_kvs_value(&value).unwrap(), data);
+    }
+
+    #[test]
+    fn test_missing_field_is_rejected() {
+        let value = json_to_kvs_value(&serde_json::json!({ "vehicle_speed": 42.0 }));
+        assert!(EmergencyModeData::from_kvs_value(&value).is_err());
+
+        // Written before schema version 2
+        let mut json = serde_json::to_value(EmergencyModeData::default()).unwrap();
+        json.as_object_mut().unwrap().remove("safe_state_version");
+        let data = EmergencyModeData::from_kvs_value(&json_to_kvs_value(&json)).unwrap();
+        assert_eq!(data.safe_state_version, "");
+    }
+}
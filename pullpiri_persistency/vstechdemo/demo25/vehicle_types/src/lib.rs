@@ -0,0 +1,222 @@
+/********************************************************************************
+ * Copyright (c) 2025 Contributors to the Eclipse Foundation
+ *
+ * See the NOTICE file(s) distributed with this work for additional
+ * information regarding copyright ownership.
+ *
+ * This program and the accompanying materials are made available under the
+ * terms of the Apache License Version 2.0 which is available at
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ ********************************************************************************/
+
+//! Vehicle data model
+//!
+//! Driving mode data published by mini-adas over DDS and read by the DDS
+//! console apps. Both sides used to define their own copies of these types,
+//! which had to be kept field by field in sync with the DDS wire layout.
+//! Publishers and subscribers now share the definitions of this crate,
+//! including their schema versions. The types also derive serde, as the
+//! console apps serve them as JSON and keep them in their history files,
+//! and convert to and from the persistency [`KvsValue`] with [`kvs`].
+//!
+//! The changes of the persistency service reach the same apps over DDS as
+//! [`PersistencyChangeEvent`] samples.
+//!
+//! [`KvsValue`]: common::persistency_proto::KvsValue
+
+use dust_dds::topic_definition::type_support::DdsType;
+use serde::{Deserialize, Serialize};
+
+pub mod kvs;
+
+pub use kvs::{KvsConversionError, KvsRecord};
+
+/// Schema version of a message published over DDS
+///
+/// Every DDS message carries its version in a trailing `schema_version`
+/// field, so that subscribers can detect publishers built from a different
+/// message layout during rolling updates instead of misinterpreting fields.
+/// Bump the version whenever the layout changes; new fields are appended
+/// after `schema_version` so that older subscribers still read the version.
+pub trait Versioned {
+    /// Schema version of the type as built
+    const SCHEMA_VERSION: u32;
+
+    /// Schema version the message was published with
+    fn schema_version(&self) -> u32;
+}
+
+/// CarData
+///
+/// Basic car driving mode data for scenario handling
+#[derive(DdsType, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[repr(C)]
+pub struct CarData {
+    pub driving_mode: String,
+    pub schema_version: u32,
+    pub active_hazard: String, // V2X hazard behind the mode, e.g. "road_works", empty if none
+    pub hazard_distance: f64,  // meters ahead, 0 if no hazard is active
+    // Absent in history recorded before schema version 3
+    #[serde(default)]
+    pub run_id: String, // run of the publisher, see mini-adas `runs`
+}
+
+impl Versioned for CarData {
+    const SCHEMA_VERSION: u32 = 3;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+/// AutonomousCarData
+///
+/// Autonomous driving mode parameters
+#[derive(DdsType, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[repr(C)]
+pub struct AutonomousCarData {
+    pub vehicle_speed: f64,          // km/h
+    pub lane_position: f64,          // meters (-1.0 to 1.0, 0 = center)
+    pub obstacle_detected: bool,     // true if obstacle detected
+    pub obstacle_distance: f64,      // meters
+    pub traffic_signal: String,      // "green", "yellow", "red", "stop"
+    pub steering_angle: f64,         // degrees (-45 to 45)
+    pub brake_force: f64,            // percentage (0-100)
+    pub acceleration: f64,           // m/s²
+    pub weather_condition: String,   // "clear", "rain", "snow", "fog"
+    pub road_condition: String,      // "dry", "wet", "icy", "gravel"
+    pub timestamp: i64,              // Unix timestamp in milliseconds
+    pub is_valid: bool,              // Data validity flag
+    pub speed_limit: f64,            // km/h, 0 if no map data is available
+    pub speed_limit_violations: u32, // Number of samples published above the speed limit
+    pub schema_version: u32,         // AutonomousCarData::SCHEMA_VERSION
+    // Absent in history recorded before schema version 2
+    #[serde(default)]
+    pub run_id: String, // run of the publisher, see mini-adas `runs`
+    // Absent in history recorded before schema version 3
+    #[serde(default)]
+    pub eco_mode: bool, // true if driving in the eco sub-mode, see mini-adas `eco`
+    #[serde(default)]
+    pub estimated_energy_savings: f64, // percentage of drive energy saved by eco cruising, 0 if off
+    #[serde(default)]
+    pub drive_energy: f64, // Wh of traction work since startup of the vehicle model
+}
+
+impl Versioned for AutonomousCarData {
+    const SCHEMA_VERSION: u32 = 3;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+/// ManualCarData
+///
+/// Manual driving mode parameters
+#[derive(DdsType, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[repr(C)]
+pub struct ManualCarData {
+    pub vehicle_speed: f64,        // km/h
+    pub steering_angle: f64,       // degrees (-45 to 45)
+    pub brake_force: f64,          // percentage (0-100)
+    pub acceleration: f64,         // m/s²
+    pub weather_condition: String, // "clear", "rain", "snow", "fog"
+    pub road_condition: String,    // "dry", "wet", "icy", "gravel"
+    pub driver_alertness: bool,    // true if driver is alert
+    pub throttle_position: f64,    // percentage (0-100)
+    pub timestamp: i64,            // Unix timestamp in milliseconds
+    pub is_valid: bool,            // Data validity flag
+    pub schema_version: u32,       // ManualCarData::SCHEMA_VERSION
+    // Absent in history recorded before schema version 2
+    #[serde(default)]
+    pub run_id: String, // run of the publisher, see mini-adas `runs`
+}
+
+impl Versioned for ManualCarData {
+    const SCHEMA_VERSION: u32 = 2;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+/// EmergencyModeData
+///
+/// Emergency driving mode parameters
+#[derive(DdsType, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[repr(C)]
+pub struct EmergencyModeData {
+    pub vehicle_speed: f64,       // km/h
+    pub steering_angle: f64,      // degrees (-45 to 45)
+    pub brake_force: f64,         // percentage (0-100)
+    pub obstacle_detected: bool,  // true if immediate threat
+    pub obstacle_distance: f64,   // meters
+    pub collision_risk: f64,      // percentage (0-100)
+    pub stability_control: bool,  // true if stability systems active
+    pub traffic_signal: String,   // "green", "yellow", "red", "stop"
+    pub seatbelt_tightened: bool, // true if emergency seatbelt tightening
+    pub emergency_lights: bool,   // true if hazard lights on
+    // "collision_avoidance", "obstacle", "medical" or "system_failure"
+    pub emergency_type: String,
+    pub emergency_brake_force: f64, // percentage (0-100)
+    pub airbag_ready: bool,         // true if airbag systems primed
+    pub timestamp: i64,             // Unix timestamp in milliseconds
+    pub is_valid: bool,             // Data validity flag
+    pub schema_version: u32,        // EmergencyModeData::SCHEMA_VERSION
+    // Absent in history recorded before schema version 2
+    #[serde(default)]
+    pub safe_state_version: String, // version of the safe-state matrix behind the reactions
+    // Absent in history recorded before schema version 3
+    #[serde(default)]
+    pub run_id: String, // run of the publisher, see mini-adas `runs`
+    // Absent in history recorded before schema version 4
+    #[serde(default)]
+    pub brake_temperature: f64, // °C of the brake discs of the vehicle model
+    #[serde(default)]
+    pub brake_fade: f64, // percentage (0-100) of the brake torque still delivered, see mini-adas `brake_thermal`
+    // Absent in history recorded before schema version 5
+    #[serde(default)]
+    pub occupants: u32, // occupied seats of the cabin, see mini-adas `occupancy`
+    #[serde(default)]
+    pub belted_occupants: u32, // occupants with fastened seatbelts, the ones tightened
+    #[serde(default)]
+    pub passenger_airbag_ready: bool, // true if the front passenger airbag is primed, never for an empty seat
+}
+
+impl Versioned for EmergencyModeData {
+    const SCHEMA_VERSION: u32 = 5;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+/// PersistencyChangeEvent
+///
+/// Change of a key of the persistency service, published by
+/// `persistency_dds_publisher` for components that react to state changes
+/// over DDS rather than watching the service over gRPC
+#[derive(DdsType, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[repr(C)]
+pub struct PersistencyChangeEvent {
+    pub key: String,         // changed key, e.g. "vehicle/mode"
+    pub operation: String,   // "put" or "delete"
+    pub timestamp: i64,      // Unix timestamp of the change in milliseconds
+    pub revision: u64,       // increases with every change of the store
+    pub schema_version: u32, // PersistencyChangeEvent::SCHEMA_VERSION
+}
+
+impl Versioned for PersistencyChangeEvent {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+}
+
+impl KvsRecord for CarData {}
+impl KvsRecord for AutonomousCarData {}
+impl KvsRecord for ManualCarData {}
+impl KvsRecord for EmergencyModeData {}